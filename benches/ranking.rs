@@ -0,0 +1,225 @@
+//! Criterion benchmarks for chunking, scoring and end-to-end search over synthetic corpora.
+//!
+//! `cargo bench --bench ranking` runs the `chunking`, `keyword_scoring`, `bm25_scoring` and `search`
+//! groups once per corpus size, then writes each benchmark's sample medians and percentiles as JSON to
+//! `target/bench/ranking.json`. Criterion's own arguments (a name filter, `--save-baseline`) still
+//! apply. Environment variables tune a run:
+//!
+//! - `RAG_BENCH_SIZES`: comma-separated chunk counts [default: 10000,100000,1000000]
+//! - `RAG_BENCH_ITERATIONS`: criterion samples per benchmark [default: 50; 10 at a million chunks]
+//! - `RAG_BENCH_OUT`: where to write the JSON report
+//! - `RAG_BENCH_BASELINE`: an earlier report to compare against; the run fails if any median
+//!   is more than `RAG_BENCH_MAX_SLOWDOWN` (default 0.25) slower and past the baseline's p95
+
+use anyhow::{anyhow, Result};
+use criterion::{BenchmarkId, Criterion, SamplingMode};
+use rag_system::bench::{find_regressions, LatencyStats};
+use rag_system::chunking::{ChunkingEngine, ChunkingStrategy, DocumentChunk};
+use rag_system::processor::ProcessedDocument;
+use rag_system::search::{SearchConfig, SearchEngine, SearchMode};
+use rag_system::testing::DocumentFixture;
+use rag_system::SimpleRagSystem;
+use std::collections::BTreeMap;
+use std::hint::black_box;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+const PARAGRAPHS_PER_DOCUMENT: usize = 10;
+const WORDS_PER_PARAGRAPH: usize = 40;
+const VOCABULARY: usize = 4_096;
+const SYLLABLES: [&str; 16] = [
+    "ka", "lo", "mi", "ren", "tu", "sha", "vo", "ne", "dris", "pa", "quel", "o", "zan", "fi", "ber", "tho",
+];
+const RESULT_LIMIT: usize = 10;
+
+/// Deterministic text with a skewed word distribution, so common terms match many chunks
+struct Corpus {
+    state: u64,
+}
+
+impl Corpus {
+    fn new() -> Self {
+        Self { state: 0x9e37_79b9_7f4a_7c15 }
+    }
+
+    fn next(&mut self) -> u64 {
+        // xorshift64
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    fn word(&mut self) -> String {
+        // Squaring a uniform draw favours low indices, roughly like word frequencies
+        let uniform = (self.next() % 1_000_000) as f64 / 1_000_000.0;
+        let mut index = (uniform * uniform * VOCABULARY as f64) as usize;
+        let mut word = String::new();
+        loop {
+            word.push_str(SYLLABLES[index % SYLLABLES.len()]);
+            index /= SYLLABLES.len();
+            if index == 0 {
+                return word;
+            }
+        }
+    }
+
+    fn paragraph(&mut self) -> String {
+        (0..WORDS_PER_PARAGRAPH).map(|_| self.word()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Enough documents for `chunks` paragraph chunks
+    fn documents(&mut self, chunks: usize) -> Vec<ProcessedDocument> {
+        (0..chunks.div_ceil(PARAGRAPHS_PER_DOCUMENT))
+            .map(|i| {
+                let content = (0..PARAGRAPHS_PER_DOCUMENT).map(|_| self.paragraph()).collect::<Vec<_>>().join("\n\n");
+                DocumentFixture::new(&format!("corpus/{}.txt", i), &content).build()
+            })
+            .collect()
+    }
+
+    fn queries(&mut self, count: usize) -> Vec<String> {
+        (0..count).map(|_| format!("{} {}", self.word(), self.word())).collect()
+    }
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> Result<T> {
+    match std::env::var(name) {
+        Ok(value) => value.trim().parse().map_err(|_| anyhow!("{} is not valid: {}", name, value)),
+        Err(_) => Ok(default),
+    }
+}
+
+/// Per-iteration latency of every criterion sample, keyed by `group/size`
+type Samples = BTreeMap<String, Vec<Duration>>;
+
+/// Benchmarks `run` as `group/size`, recording each sample's mean iteration time in `samples`.
+/// Criterion also calls the routine while warming up, so only the last `sample_size` calls count
+fn bench(
+    criterion: &mut Criterion,
+    samples: &mut Samples,
+    group: &str,
+    size: usize,
+    sample_size: usize,
+    mut run: impl FnMut(usize) -> Result<()>,
+) {
+    let mut durations = Vec::new();
+    let mut iteration = 0;
+    let mut benchmarks = criterion.benchmark_group(group);
+    // Flat sampling runs each sample the same number of times, which keeps the million-chunk tier bounded
+    benchmarks.sample_size(sample_size).sampling_mode(SamplingMode::Flat);
+    benchmarks.bench_function(BenchmarkId::from_parameter(size), |b| {
+        b.iter_custom(|iters| {
+            let started = Instant::now();
+            for _ in 0..iters {
+                run(iteration).expect("benchmark run failed");
+                iteration += 1;
+            }
+            let elapsed = started.elapsed();
+            durations.push(elapsed.div_f64(iters.max(1) as f64));
+            elapsed
+        })
+    });
+    benchmarks.finish();
+    // Empty when a name filter skipped this benchmark
+    if !durations.is_empty() {
+        let warm_up = durations.len().saturating_sub(sample_size);
+        samples.insert(format!("{}/{}", group, size), durations.split_off(warm_up));
+    }
+}
+
+fn bench_size(criterion: &mut Criterion, samples: &mut Samples, chunks: usize, sample_size: usize) -> Result<()> {
+    let mut corpus = Corpus::new();
+    let documents = corpus.documents(chunks);
+    let queries = corpus.queries(64);
+    let query = |i: usize| queries[i % queries.len()].as_str();
+
+    let chunker = ChunkingEngine::with_strategy(ChunkingStrategy::Paragraph);
+    let chunk_all = || -> Result<Vec<DocumentChunk>> {
+        let mut all_chunks = Vec::new();
+        for document in &documents {
+            all_chunks.extend(chunker.chunk_document(document)?);
+        }
+        Ok(all_chunks)
+    };
+    bench(criterion, samples, "chunking", chunks, sample_size, |_| {
+        black_box(chunk_all()?);
+        Ok(())
+    });
+    let all_chunks = chunk_all()?;
+
+    for (group, mode) in [("keyword_scoring", SearchMode::Keyword), ("bm25_scoring", SearchMode::Bm25)] {
+        let searcher = SearchEngine::with_config(SearchConfig { mode, ..Default::default() })?;
+        bench(criterion, samples, group, chunks, sample_size, |i| {
+            black_box(searcher.search(query(i), &all_chunks, RESULT_LIMIT)?);
+            Ok(())
+        });
+    }
+    drop(all_chunks);
+
+    let mut rag = SimpleRagSystem::builder()
+        .chunking(ChunkingStrategy::Paragraph)
+        .search_mode(SearchMode::Bm25)
+        .build()?;
+    for document in &documents {
+        rag.process_bytes(&document.metadata.file_path, document.content.as_bytes())?;
+    }
+    bench(criterion, samples, "search", chunks, sample_size, |i| {
+        black_box(rag.search(query(i), RESULT_LIMIT)?);
+        Ok(())
+    });
+    Ok(())
+}
+
+fn main() -> Result<()> {
+    let sizes: Vec<usize> = env_or("RAG_BENCH_SIZES", "10000,100000,1000000".to_string())?
+        .split(',')
+        .map(|size| size.trim().parse().map_err(|_| anyhow!("Invalid corpus size: {}", size)))
+        .collect::<Result<_>>()?;
+    let sample_size: usize = env_or("RAG_BENCH_ITERATIONS", 50)?;
+    let out: PathBuf = env_or("RAG_BENCH_OUT", PathBuf::from("target/bench/ranking.json"))?;
+
+    let mut criterion = Criterion::default().configure_from_args();
+    let mut samples = Samples::new();
+    for &size in &sizes {
+        // Criterion needs at least 10 samples
+        let sample_size = if size >= 1_000_000 { sample_size.min(10) } else { sample_size }.max(10);
+        bench_size(&mut criterion, &mut samples, size, sample_size)?;
+    }
+    criterion.final_summary();
+    if samples.is_empty() {
+        return Ok(());
+    }
+
+    let report: BTreeMap<String, LatencyStats> =
+        samples.iter().map(|(name, durations)| (name.clone(), LatencyStats::from_durations(durations))).collect();
+    println!("{:<28} {:>10} {:>10} {:>10}", "benchmark", "p50 ms", "p95 ms", "mean ms");
+    for (name, stats) in &report {
+        println!("{:<28} {:>10.3} {:>10.3} {:>10.3}", name, stats.p50_ms, stats.p95_ms, stats.mean_ms);
+    }
+    if let Some(parent) = out.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&out, serde_json::to_string_pretty(&report)?)?;
+    println!("Wrote {}", out.display());
+
+    if let Ok(baseline) = std::env::var("RAG_BENCH_BASELINE") {
+        let baseline: BTreeMap<String, LatencyStats> = serde_json::from_str(&std::fs::read_to_string(&baseline)?)?;
+        let max_slowdown: f64 = env_or("RAG_BENCH_MAX_SLOWDOWN", 0.25)?;
+        let regressions = find_regressions(&baseline, &report, max_slowdown);
+        for regression in &regressions {
+            println!(
+                "REGRESSION {}: p50 {:.3}ms -> {:.3}ms ({:+.1}%)",
+                regression.name,
+                regression.baseline_p50_ms,
+                regression.current_p50_ms,
+                regression.slowdown * 100.0
+            );
+        }
+        if !regressions.is_empty() {
+            return Err(anyhow!("{} benchmarks regressed by more than {:.0}%", regressions.len(), max_slowdown * 100.0));
+        }
+        println!("No benchmark regressed by more than {:.0}%", max_slowdown * 100.0);
+    }
+    Ok(())
+}
@@ -0,0 +1,194 @@
+//! Text analysis shared by indexing and querying: a tokenizer followed by a chain of filters.
+//!
+//! Text is split into runs of letters and digits, then each filter rewrites the token stream in the
+//! order configured. Chunks and queries go through the same chain, so a stemmed query term meets the
+//! stemmed form in the chunk; only `synonyms` is skipped for chunks, since expanding both sides would
+//! count each synonym twice.
+
+use serde::{Deserialize, Serialize};
+use crate::keyphrases::STOPWORDS;
+use crate::synonyms::SynonymDictionary;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenFilter {
+    Lowercase,
+    /// Drop common English words such as `the` and `of`
+    StopWords,
+    /// Strip English suffixes, so `indexing`, `indexed` and `indexes` all become `index`
+    Stem,
+    /// Append the expansions of terms in the query's synonyms dictionary; put it before `stem`, since
+    /// dictionary terms are matched as written
+    Synonyms,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnalyzerConfig {
+    pub filters: Vec<TokenFilter>,
+}
+
+impl Default for AnalyzerConfig {
+    fn default() -> Self {
+        Self {
+            filters: vec![TokenFilter::Lowercase, TokenFilter::Synonyms],
+        }
+    }
+}
+
+impl AnalyzerConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Analyzer {
+    filters: Vec<TokenFilter>,
+}
+
+impl Default for Analyzer {
+    fn default() -> Self {
+        Self::new(&AnalyzerConfig::default())
+    }
+}
+
+impl Analyzer {
+    pub fn new(config: &AnalyzerConfig) -> Self {
+        Self {
+            filters: config.filters.clone(),
+        }
+    }
+
+    /// Tokens of indexed text
+    pub fn analyze(&self, text: &str) -> Vec<String> {
+        self.run(text, None)
+    }
+
+    /// Tokens of a query, expanded with `synonyms` if the chain has the synonyms filter
+    pub fn analyze_query(&self, text: &str, synonyms: &SynonymDictionary) -> Vec<String> {
+        self.run(text, Some(synonyms))
+    }
+
+    pub fn expands_synonyms(&self) -> bool {
+        self.filters.contains(&TokenFilter::Synonyms)
+    }
+
+    fn run(&self, text: &str, synonyms: Option<&SynonymDictionary>) -> Vec<String> {
+        let mut tokens = tokenize(text);
+        for filter in &self.filters {
+            match filter {
+                TokenFilter::Lowercase => tokens.iter_mut().for_each(|t| *t = t.to_lowercase()),
+                TokenFilter::StopWords => tokens.retain(|t| !STOPWORDS.contains(&t.to_lowercase().as_str())),
+                TokenFilter::Stem => tokens.iter_mut().for_each(|t| *t = stem(t)),
+                TokenFilter::Synonyms => {
+                    let Some(synonyms) = synonyms.filter(|s| !s.is_empty()) else {
+                        continue;
+                    };
+                    let lowered: Vec<String> = tokens.iter().map(|t| t.to_lowercase()).collect();
+                    let added: Vec<String> = synonyms.expansions(&lowered).into_iter().flat_map(tokenize).collect();
+                    tokens.extend(added);
+                }
+            }
+        }
+        tokens
+    }
+}
+
+/// Runs of letters and digits, as written
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Light English suffix stripping: plurals, then `-ing`, `-ed` and `-ly`, then a final `e`.
+///
+/// It conflates far fewer forms than a full Porter stemmer, but never reduces a word below three letters.
+pub fn stem(word: &str) -> String {
+    if word.chars().count() <= 3 || !word.is_ascii() {
+        return word.to_string();
+    }
+    let mut stem = word.to_string();
+    if let Some(base) = stem.strip_suffix("sses") {
+        stem = format!("{}ss", base);
+    } else if let Some(base) = stem.strip_suffix("ies").filter(|b| b.len() >= 2) {
+        stem = format!("{}y", base);
+    } else if let Some(base) = ["xes", "ches", "shes"]
+        .iter()
+        .find_map(|suffix| stem.strip_suffix(suffix).map(|b| format!("{}{}", b, &suffix[..suffix.len() - 2])))
+    {
+        stem = base;
+    } else if stem.ends_with('s') && !stem.ends_with("ss") && !stem.ends_with("us") && !stem.ends_with("is") {
+        stem.pop();
+    }
+
+    for suffix in ["ing", "ed", "ly"] {
+        if let Some(base) = stem.strip_suffix(suffix) {
+            if base.len() >= 3 && base.chars().any(is_vowel) {
+                stem = undouble(base);
+                break;
+            }
+        }
+    }
+    if stem.len() > 4 && stem.ends_with('e') {
+        stem.pop();
+    }
+    stem
+}
+
+fn is_vowel(c: char) -> bool {
+    matches!(c, 'a' | 'e' | 'i' | 'o' | 'u' | 'y')
+}
+
+/// `runn` from `running` back to `run`; `ll`, `ss` and `zz` are usually part of the word
+fn undouble(base: &str) -> String {
+    let mut chars = base.chars().rev();
+    match (chars.next(), chars.next()) {
+        (Some(a), Some(b)) if a == b && !is_vowel(a) && !matches!(a, 'l' | 's' | 'z') => {
+            base[..base.len() - 1].to_string()
+        }
+        _ => base.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filters_run_in_order() {
+        let text = "The Indexes were rebuilt while indexing K8s clusters";
+        let plain = Analyzer::new(&AnalyzerConfig { filters: vec![TokenFilter::Lowercase] });
+        assert_eq!(plain.analyze(text)[..3], ["the", "indexes", "were"]);
+
+        let config = AnalyzerConfig {
+            filters: vec![TokenFilter::Lowercase, TokenFilter::Synonyms, TokenFilter::StopWords, TokenFilter::Stem],
+        };
+        let full = Analyzer::new(&config);
+        assert_eq!(full.analyze(text), ["index", "rebuilt", "index", "k8s", "cluster"]);
+
+        let synonyms = SynonymDictionary::parse("k8s => kubernetes").unwrap();
+        assert_eq!(full.analyze_query("k8s pods", &synonyms), ["k8s", "pod", "kubernet"]);
+        assert_eq!(full.analyze("k8s pods"), ["k8s", "pod"]);
+    }
+
+    #[test]
+    fn test_stem_conflates_common_forms() {
+        for (word, expected) in [
+            ("running", "run"),
+            ("runs", "run"),
+            ("libraries", "library"),
+            ("jumped", "jump"),
+            ("quickly", "quick"),
+            ("caching", "cach"),
+            ("cache", "cach"),
+            ("class", "class"),
+            ("bus", "bus"),
+            ("is", "is"),
+        ] {
+            assert_eq!(stem(word), expected, "{}", word);
+        }
+    }
+}
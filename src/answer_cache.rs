@@ -0,0 +1,107 @@
+//! Reusing answers for questions that embed close to ones already answered
+
+use serde::{Deserialize, Serialize};
+use crate::embedding::cosine_similarity;
+use crate::generation::Answer;
+
+/// When a cached answer may stand in for a fresh one
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AnswerCacheConfig {
+    /// Minimum cosine similarity between question embeddings
+    pub similarity_threshold: f32,
+    /// Seconds an answer stays valid; `None` keeps it until the index changes
+    pub ttl_secs: Option<u64>,
+    /// Oldest answers are dropped beyond this
+    pub max_entries: usize,
+}
+
+impl Default for AnswerCacheConfig {
+    fn default() -> Self {
+        Self {
+            similarity_threshold: 0.95,
+            ttl_secs: None,
+            max_entries: 1_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAnswer {
+    pub question: String,
+    /// Embedding model of `embedding`; entries from other models never match
+    pub embedding_model: String,
+    pub embedding: Vec<f32>,
+    pub answer: Answer,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    /// Tenant whose documents the answer came from; other tenants never see it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl CachedAnswer {
+    pub fn is_expired(&self, ttl_secs: Option<u64>, now: u64) -> bool {
+        ttl_secs.is_some_and(|ttl| now.saturating_sub(self.created_at) > ttl)
+    }
+}
+
+/// Index of the most similar live entry at or above the threshold
+pub fn find_cached_answer(
+    entries: &[CachedAnswer],
+    config: &AnswerCacheConfig,
+    tenant: Option<&str>,
+    embedding_model: &str,
+    embedding: &[f32],
+    now: u64,
+) -> Option<usize> {
+    entries
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| entry.tenant.as_deref() == tenant && entry.embedding_model == embedding_model)
+        .filter(|(_, entry)| !entry.is_expired(config.ttl_secs, now))
+        .map(|(i, entry)| (i, cosine_similarity(&entry.embedding, embedding)))
+        .filter(|(_, similarity)| *similarity >= config.similarity_threshold)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(i, _)| i)
+}
+
+pub(crate) fn unix_now() -> u64 {
+    crate::time::SystemTime::now()
+        .duration_since(crate::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(embedding: Vec<f32>, created_at: u64) -> CachedAnswer {
+        CachedAnswer {
+            question: String::new(),
+            embedding_model: "m".to_string(),
+            embedding,
+            answer: Answer::from_response("cached".to_string(), &[]),
+            created_at,
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn test_lookup_respects_threshold_model_and_ttl() {
+        let entries = vec![entry(vec![1.0, 0.0], 100), entry(vec![0.6, 0.8], 100)];
+        let config = AnswerCacheConfig {
+            similarity_threshold: 0.9,
+            ttl_secs: Some(60),
+            ..AnswerCacheConfig::default()
+        };
+
+        assert_eq!(find_cached_answer(&entries, &config, None, "m", &[0.99, 0.1], 120), Some(0));
+        assert_eq!(find_cached_answer(&entries, &config, None, "m", &[0.0, 1.0], 120), None);
+        assert_eq!(find_cached_answer(&entries, &config, None, "other", &[1.0, 0.0], 120), None);
+        assert_eq!(find_cached_answer(&entries, &config, None, "m", &[1.0, 0.0], 200), None);
+        // Another tenant's answers never match
+        assert_eq!(find_cached_answer(&entries, &config, Some("acme"), "m", &[0.99, 0.1], 120), None);
+    }
+}
@@ -0,0 +1,191 @@
+//! Async entry points that await providers instead of blocking a thread on them.
+//!
+//! Only the network calls are awaited. Reading a file, chunking, scoring and storage are shared
+//! with the blocking pipeline and run through `blocking`, so a large index does not stall the
+//! runtime's other tasks.
+
+use anyhow::Result;
+use std::collections::HashMap;
+use std::path::Path;
+use crate::time::Instant;
+use tracing::field::Empty;
+use crate::bench::elapsed_ms;
+use crate::chunking::DocumentChunk;
+use crate::embedding::EmbeddingProvider;
+use crate::generation::Answer;
+use crate::graph::extract_relations_async;
+use crate::hyde::hypothetical_document_async;
+use crate::processor::ProcessedDocument;
+use crate::search::SearchResult;
+use crate::summary::summarize_document_async;
+use crate::tags::DocumentFilter;
+use crate::translate::translate_query_async;
+use crate::{attach_summary, chunk_texts, first_embedding, SimpleRagSystem};
+
+impl SimpleRagSystem {
+    /// Like `process_document`, awaiting the summary, relation and embedding calls
+    #[tracing::instrument(
+        name = "process_document",
+        skip_all,
+        fields(path = %file_path.display(), doc_id = Empty, chunks = Empty, elapsed_ms = Empty)
+    )]
+    pub async fn process_document_async(&mut self, file_path: &Path) -> Result<String> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let doc_id = match blocking(|| self.processor.process_file(file_path)) {
+            Ok(document) => self.ingest_async(document).await,
+            Err(e) => Err(e),
+        };
+        self.record_usage_since("ingest", &before);
+        let span = tracing::Span::current();
+        if let Ok(doc_id) = &doc_id {
+            span.record("doc_id", tracing::field::display(doc_id));
+        }
+        span.record("elapsed_ms", elapsed_ms(started));
+        doc_id
+    }
+
+    pub async fn search_async(&self, query: &str, limit: usize) -> Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let all_chunks = blocking(|| self.storage.get_all_chunks())?;
+        let results = self.search_chunks_async(query, all_chunks, limit).await;
+        self.record_usage_since("search", &before);
+        blocking(|| self.log_query(query, &DocumentFilter::default(), started, &results));
+        results
+    }
+
+    /// Like `ask`, awaiting the embedding and completion calls
+    pub async fn ask_async(&self, question: &str) -> Result<Answer> {
+        let before = self.usage.total();
+        let answer = self.answer_question_async(question).await;
+        let usage = self.record_usage_since("ask", &before);
+        answer.map(|answer| Answer { usage, ..answer })
+    }
+
+    async fn ingest_async(&mut self, mut document: ProcessedDocument) -> Result<String> {
+        let mut chunks = blocking(|| self.chunker.chunk_document(&document))?;
+        if self.summarize {
+            let summary = summarize_document_async(self.completion_provider()?.as_ref(), &document).await?;
+            attach_summary(&mut document, &mut chunks, summary);
+        }
+        if self.extract_graph {
+            document.metadata.relations = extract_relations_async(self.completion_provider()?.as_ref(), &chunks).await?;
+        }
+        self.report_chunked(&document, &chunks);
+        self.index_document_async(document, chunks).await
+    }
+
+    async fn index_document_async(&mut self, document: ProcessedDocument, chunks: Vec<DocumentChunk>) -> Result<String> {
+        if let Some(embedder) = self.embedder.clone() {
+            let vectors = self.embed_cached_async(embedder.as_ref(), &chunk_texts(&chunks)).await?;
+            blocking(|| self.store_chunk_embeddings(embedder.model_name(), &chunks, vectors))?;
+        }
+        blocking(|| self.store_document(document, chunks))
+    }
+
+    #[tracing::instrument(
+        name = "search",
+        skip(self, all_chunks),
+        fields(mode = ?self.searcher.config().mode, chunks = all_chunks.len(), results = Empty, elapsed_ms = Empty)
+    )]
+    async fn search_chunks_async(
+        &self,
+        query: &str,
+        all_chunks: Vec<DocumentChunk>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let all_chunks = blocking(|| self.group_by_shard(all_chunks));
+        let results = self.score_chunks_async(query, all_chunks, limit).await;
+        self.finish_search(started, &results);
+        results
+    }
+
+    async fn score_chunks_async(&self, query: &str, all_chunks: Vec<DocumentChunk>, limit: usize) -> Result<Vec<SearchResult>> {
+        let translated = match self.searcher.translation_language() {
+            Some(language) => Some(translate_query_async(self.completion_provider()?.as_ref(), query, language).await?),
+            None => None,
+        };
+        let query = translated.as_deref().unwrap_or(query);
+        let Some(embedder) = self.query_embedder()? else {
+            return blocking(|| {
+                let context = self.search_context()?;
+                self.search_shards(query, None, &all_chunks, &HashMap::<String, Vec<f32>>::new(), &context, limit)
+            });
+        };
+        let embedded_text = if self.hyde {
+            hypothetical_document_async(self.completion_provider()?.as_ref(), query).await?
+        } else {
+            self.searcher.embedding_query(query)
+        };
+        let query_embedding = first_embedding(embedder, self.embed_cached_async(embedder, &[embedded_text]).await?)?;
+        blocking(|| self.score_with_embedding(query, &query_embedding, &all_chunks, limit, false))
+    }
+
+    async fn embed_cached_async(&self, embedder: &dyn EmbeddingProvider, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let (vectors, uncached) = self.cached_embeddings(embedder.model_name(), texts);
+        let fresh = if uncached.is_empty() { Vec::new() } else { embedder.embed_async(&uncached).await? };
+        self.merge_embeddings(embedder.model_name(), texts, vectors, &uncached, fresh)
+    }
+
+    async fn answer_question_async(&self, question: &str) -> Result<Answer> {
+        let llm = self.completion_provider()?;
+        let cache_embedding = match self.answer_cache_embedder()? {
+            Some(embedder) => {
+                let embedding = first_embedding(embedder, self.embed_cached_async(embedder, &[question.to_string()]).await?)?;
+                if let Some(answer) = self.cached_answer_for(question, &embedding) {
+                    return Ok(answer);
+                }
+                Some(embedding)
+            }
+            None => None,
+        };
+
+        let all_chunks = blocking(|| self.storage.get_all_chunks())?;
+        let context = self.search_chunks_async(question, all_chunks, self.context_chunks).await?;
+        let (context, chunks) = blocking(|| self.pack_context(question, context))?;
+        if let Some(refusal) = self.refuse_weak_context(&context) {
+            return Ok(refusal);
+        }
+        let (text, _) = llm.complete_async(&self.prompt_template.build_request(question, &context)).await?;
+        let answer = Answer::from_response(text, &chunks);
+        if let Some(embedding) = cache_embedding {
+            self.cache_answer_for(question, embedding, &answer);
+        }
+        Ok(answer)
+    }
+}
+
+/// Runs synchronous storage or search work from an async wrapper without starving the runtime.
+///
+/// The wrappers borrow `self`, so `spawn_blocking` (which needs `'static` work) is out; on a
+/// multi-threaded runtime `block_in_place` hands this worker's other tasks to a fresh thread
+/// instead. A current-thread runtime has no other worker to hand them to, so the work runs inline.
+fn blocking<T>(work: impl FnOnce() -> T) -> T {
+    #[cfg(feature = "rig")]
+    if let Ok(handle) = tokio::runtime::Handle::try_current() {
+        if handle.runtime_flavor() == tokio::runtime::RuntimeFlavor::MultiThread {
+            return tokio::task::block_in_place(work);
+        }
+    }
+    work()
+}
+
+#[cfg(all(test, feature = "rig"))]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 1)]
+    async fn test_blocking_work_leaves_the_runtime_free() {
+        let waited = tokio::spawn(async {
+            let (sender, receiver) = mpsc::channel();
+            // Queued behind us on the only worker, so it only runs if `blocking` hands the worker off
+            tokio::spawn(async move { sender.send(()).unwrap() });
+            blocking(|| receiver.recv_timeout(Duration::from_secs(5)))
+        });
+        assert!(waited.await.unwrap().is_ok());
+    }
+}
@@ -0,0 +1,132 @@
+//! Append-only record of what was done to an index's documents: who ingested, updated, deleted,
+//! undeleted or purged which document, or imported which ragpack over them, from which source, when,
+//! and whether it worked
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditAction {
+    Ingest,
+    /// A changed source file reingested in place of its previous version
+    Update,
+    /// Moved to the trash
+    Delete,
+    Undelete,
+    /// Removed for good
+    Purge,
+    /// The whole index replaced with a ragpack's contents, such as a backup
+    Import,
+}
+
+impl std::str::FromStr for AuditAction {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "ingest" => Ok(AuditAction::Ingest),
+            "update" => Ok(AuditAction::Update),
+            "delete" => Ok(AuditAction::Delete),
+            "undelete" => Ok(AuditAction::Undelete),
+            "purge" => Ok(AuditAction::Purge),
+            "import" => Ok(AuditAction::Import),
+            other => Err(anyhow!(
+                "Unknown audit action '{}' (expected ingest, update, delete, undelete, purge or import)",
+                other
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            AuditAction::Ingest => "ingest",
+            AuditAction::Update => "update",
+            AuditAction::Delete => "delete",
+            AuditAction::Undelete => "undelete",
+            AuditAction::Purge => "purge",
+            AuditAction::Import => "import",
+        };
+        f.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix seconds when the operation finished
+    pub timestamp: u64,
+    /// User or API key that asked for it
+    pub actor: String,
+    pub action: AuditAction,
+    /// File path or upload name the document came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Document affected; unset when an ingest failed before one was stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub document_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl AuditEntry {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// Entries `audit_log` returns; every condition set must hold
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AuditFilter {
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub document_id: Option<String>,
+    /// Only entries at or after these Unix seconds
+    pub since: Option<u64>,
+}
+
+impl AuditFilter {
+    pub fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().is_none_or(|actor| &entry.actor == actor)
+            && self.action.is_none_or(|action| entry.action == action)
+            && self.document_id.as_ref().is_none_or(|id| entry.document_id.as_ref() == Some(id))
+            && self.since.is_none_or(|since| entry.timestamp >= since)
+    }
+}
+
+/// Who operations are recorded as when no one else is named: `$RAG_ACTOR`, else `$USER`
+pub fn default_actor() -> String {
+    ["RAG_ACTOR", "USER", "USERNAME"]
+        .iter()
+        .find_map(|var| std::env::var(var).ok().filter(|name| !name.is_empty()))
+        .unwrap_or_else(|| "unknown".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_selects_entries() {
+        let entry = AuditEntry {
+            timestamp: 1_700_000_000,
+            actor: "ci".to_string(),
+            action: AuditAction::Ingest,
+            source: Some("docs/setup.md".to_string()),
+            document_id: Some("doc".to_string()),
+            error: None,
+            tenant: None,
+        };
+        assert!(AuditFilter::default().matches(&entry));
+        let filter = AuditFilter { actor: Some("ci".to_string()), action: Some(AuditAction::Ingest), ..Default::default() };
+        assert!(filter.matches(&entry));
+        assert!(!AuditFilter { action: Some(AuditAction::Purge), ..Default::default() }.matches(&entry));
+        assert!(!AuditFilter { since: Some(1_800_000_000), ..Default::default() }.matches(&entry));
+        assert_eq!("Purge".parse::<AuditAction>().unwrap(), AuditAction::Purge);
+        assert!("rename".parse::<AuditAction>().is_err());
+        assert!(!serde_json::to_string(&entry).unwrap().contains("error"));
+    }
+}
@@ -0,0 +1,182 @@
+//! API keys and per-collection permissions for the HTTP server
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::ratelimit::RateLimits;
+
+/// Collection name in a key's permissions that stands for every collection, and for the index as a whole
+pub const ALL_COLLECTIONS: &str = "*";
+
+/// What a key may do with a collection; `write` covers reading too
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    /// Search and fetch documents
+    Read,
+    /// Also add documents
+    Write,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessKey {
+    /// Who holds the key, for logs
+    pub name: String,
+    /// Sent as `Authorization: Bearer <key>` or `X-Api-Key: <key>`
+    pub key: String,
+    /// Permission per collection name, or `*` for all of them
+    #[serde(default)]
+    pub collections: BTreeMap<String, Permission>,
+    /// Overrides the `[auth]` defaults for this key
+    #[serde(default, flatten)]
+    pub limits: RateLimits,
+}
+
+impl AccessKey {
+    /// Whether the key may act on `collection`; `None` means the whole index, which needs `*`
+    pub fn allows(&self, collection: Option<&str>, needed: Permission) -> bool {
+        let granted = |name: &str| self.collections.get(name).is_some_and(|granted| *granted >= needed);
+        granted(ALL_COLLECTIONS) || collection.is_some_and(granted)
+    }
+}
+
+/// `[[auth.keys]]` in the config file; with no keys the server is open to anyone who can reach it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuthConfig {
+    pub keys: Vec<AccessKey>,
+    /// Limits for keys that don't set their own, and for every request when there are no keys
+    #[serde(flatten)]
+    pub limits: RateLimits,
+}
+
+/// Why a request was turned away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// No key, or one that isn't configured
+    Unauthenticated,
+    /// A valid key without the permission needed
+    Forbidden { key: String },
+}
+
+impl AuthConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.keys.is_empty()
+    }
+
+    /// Key whose secret is `presented`, compared in constant time
+    pub fn key(&self, presented: &str) -> Option<&AccessKey> {
+        self.keys.iter().find(|key| constant_time_eq(key.key.as_bytes(), presented.as_bytes()))
+    }
+
+    /// Limits for the key named `name`, falling back to the defaults
+    pub fn limits_for(&self, name: &str) -> RateLimits {
+        match self.keys.iter().find(|key| key.name == name) {
+            Some(key) => key.limits.or(self.limits),
+            None => self.limits,
+        }
+    }
+
+    /// Check that the key presented, if any, grants `needed` on `collection`.
+    /// Returns the key's name, or `None` when auth is disabled
+    pub fn authorize(
+        &self,
+        presented: Option<&str>,
+        collection: Option<&str>,
+        needed: Permission,
+    ) -> Result<Option<&str>, AuthError> {
+        if !self.is_enabled() {
+            return Ok(None);
+        }
+        let key = presented.and_then(|presented| self.key(presented)).ok_or(AuthError::Unauthenticated)?;
+        if key.allows(collection, needed) {
+            Ok(Some(&key.name))
+        } else {
+            Err(AuthError::Forbidden { key: key.name.clone() })
+        }
+    }
+}
+
+/// Key from an `Authorization: Bearer` or `X-Api-Key` header value pair
+pub fn presented_key<'a>(authorization: Option<&'a str>, api_key: Option<&'a str>) -> Option<&'a str> {
+    let bearer = authorization.and_then(|value| {
+        let (scheme, token) = value.trim().split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then(|| token.trim())
+    });
+    bearer.or(api_key.map(str::trim)).filter(|key| !key.is_empty())
+}
+
+/// Equality whose timing doesn't reveal how much of a guessed key was right
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AuthConfig {
+        AuthConfig {
+            keys: vec![
+                AccessKey {
+                    name: "ingest-bot".to_string(),
+                    key: "w-secret".to_string(),
+                    collections: BTreeMap::from([("handbook".to_string(), Permission::Write)]),
+                    limits: RateLimits::default(),
+                },
+                AccessKey {
+                    name: "frontend".to_string(),
+                    key: "r-secret".to_string(),
+                    collections: BTreeMap::from([(ALL_COLLECTIONS.to_string(), Permission::Read)]),
+                    limits: RateLimits::default(),
+                },
+            ],
+            limits: RateLimits::default(),
+        }
+    }
+
+    #[test]
+    fn test_permissions_per_collection() {
+        let auth = config();
+        assert_eq!(auth.authorize(Some("w-secret"), Some("handbook"), Permission::Write), Ok(Some("ingest-bot")));
+        assert_eq!(auth.authorize(Some("w-secret"), Some("handbook"), Permission::Read), Ok(Some("ingest-bot")));
+        assert_eq!(
+            auth.authorize(Some("w-secret"), None, Permission::Read),
+            Err(AuthError::Forbidden { key: "ingest-bot".to_string() })
+        );
+        assert_eq!(auth.authorize(Some("r-secret"), Some("anything"), Permission::Read), Ok(Some("frontend")));
+        assert!(auth.authorize(Some("r-secret"), Some("handbook"), Permission::Write).is_err());
+        assert_eq!(auth.authorize(Some("guess"), None, Permission::Read), Err(AuthError::Unauthenticated));
+        assert_eq!(auth.authorize(None, None, Permission::Read), Err(AuthError::Unauthenticated));
+        assert_eq!(AuthConfig::default().authorize(None, None, Permission::Write), Ok(None));
+    }
+
+    #[test]
+    fn test_presented_key_headers() {
+        assert_eq!(presented_key(Some("Bearer abc"), None), Some("abc"));
+        assert_eq!(presented_key(Some("bearer  abc "), Some("other")), Some("abc"));
+        assert_eq!(presented_key(Some("Basic abc"), Some("xyz")), Some("xyz"));
+        assert_eq!(presented_key(None, Some("")), None);
+    }
+
+    #[test]
+    fn test_keys_parse_from_toml() {
+        let auth: AuthConfig = toml::from_str(
+            r#"
+            requests_per_minute = 60
+            max_concurrent = 4
+
+            [[keys]]
+            name = "frontend"
+            key = "r-secret"
+            collections = { "*" = "read", drafts = "write" }
+            requests_per_minute = 600
+            "#,
+        )
+        .unwrap();
+        assert_eq!(auth.keys[0].collections["drafts"], Permission::Write);
+        assert!(auth.keys[0].allows(Some("drafts"), Permission::Write));
+        let limits = auth.limits_for("frontend");
+        assert_eq!((limits.requests_per_minute, limits.max_concurrent), (Some(600), Some(4)));
+        assert_eq!(auth.limits_for("anonymous").requests_per_minute, Some(60));
+    }
+}
@@ -0,0 +1,174 @@
+//! Scheduled backups: ragpacks of the whole index written to a directory at an interval, keeping
+//! the newest few, for `rag-system restore` to recover from. There is no object store client; a
+//! bucket is backed up to through a filesystem mount of it
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::SimpleRagSystem;
+
+pub const DEFAULT_BACKUP_INTERVAL_SECS: u64 = 3_600;
+pub const DEFAULT_BACKUP_RETAIN: usize = 24;
+
+/// `[backup]` in the config file; backups are off until `dir` is set
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    /// Directory the backups go to, local or a mounted object store bucket; not a bucket URL
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<PathBuf>,
+    pub interval_secs: u64,
+    /// Backups kept; older ones are deleted after each new one
+    pub retain: usize,
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            interval_secs: DEFAULT_BACKUP_INTERVAL_SECS,
+            retain: DEFAULT_BACKUP_RETAIN,
+        }
+    }
+}
+
+impl BackupConfig {
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some()
+    }
+
+    /// `backup` as given if it is a file, else the backup of that name in `dir`; `latest` is the newest
+    pub fn resolve(&self, backup: &Path) -> Result<PathBuf> {
+        if backup.is_file() {
+            return Ok(backup.to_path_buf());
+        }
+        let Some(dir) = &self.dir else {
+            return Err(anyhow!("No backup at {} and no [backup] dir to look in", backup.display()));
+        };
+        if backup == Path::new("latest") {
+            return list_backups(dir)?
+                .pop()
+                .map(|(_, path)| path)
+                .ok_or_else(|| anyhow!("No backups in {}", dir.display()));
+        }
+        let path = dir.join(backup);
+        if !path.is_file() {
+            return Err(anyhow!("No backup {} in {}", backup.display(), dir.display()));
+        }
+        Ok(path)
+    }
+}
+
+/// Backups in `dir` with the Unix seconds they were taken, oldest first
+pub fn list_backups(dir: &Path) -> Result<Vec<(u64, PathBuf)>> {
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut backups = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let taken = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_prefix("backup-")?.strip_suffix(".ragpack")?.parse().ok());
+        if let Some(taken) = taken {
+            backups.push((taken, path));
+        }
+    }
+    backups.sort();
+    Ok(backups)
+}
+
+/// Decides when the next backup is due, from the newest one already in the directory
+#[derive(Debug)]
+pub struct BackupScheduler {
+    dir: PathBuf,
+    interval_secs: u64,
+    retain: usize,
+    /// Unix seconds of the newest backup
+    last: Option<u64>,
+}
+
+impl BackupScheduler {
+    pub fn new(config: &BackupConfig) -> Result<Self> {
+        let dir = config.dir.clone().ok_or_else(|| anyhow!("Backups need a [backup] dir"))?;
+        if dir.to_str().is_some_and(|dir| dir.contains("://")) {
+            return Err(anyhow!(
+                "Backup dir {} is a URL; object stores are only written through a mount, such as s3fs or gcsfuse",
+                dir.display()
+            ));
+        }
+        std::fs::create_dir_all(&dir).map_err(|e| anyhow!("Cannot create backup dir {}: {}", dir.display(), e))?;
+        let last = list_backups(&dir)?.last().map(|(taken, _)| *taken);
+        Ok(Self {
+            dir,
+            interval_secs: config.interval_secs.max(1),
+            retain: config.retain.max(1),
+            last,
+        })
+    }
+
+    /// Seconds from `now` until a backup is due, zero if one is
+    pub fn due_in(&self, now: u64) -> u64 {
+        self.last.map_or(0, |last| (last + self.interval_secs).saturating_sub(now))
+    }
+
+    /// Back up `rag` if a backup is due, returning where it went
+    pub fn run_if_due(&mut self, rag: &SimpleRagSystem, now: u64) -> Result<Option<PathBuf>> {
+        if self.due_in(now) > 0 {
+            return Ok(None);
+        }
+        self.backup(rag, now).map(Some)
+    }
+
+    /// Write a backup of `rag` now, then delete the oldest past `retain`
+    pub fn backup(&mut self, rag: &SimpleRagSystem, now: u64) -> Result<PathBuf> {
+        let path = self.dir.join(format!("backup-{}.ragpack", now));
+        // Written aside first, so a crash mid-write never leaves a truncated backup to restore
+        let partial = path.with_extension("ragpack.partial");
+        rag.backup(&partial)?;
+        std::fs::rename(&partial, &path)?;
+        self.last = Some(now);
+        let backups = list_backups(&self.dir)?;
+        for (_, old) in &backups[..backups.len().saturating_sub(self.retain)] {
+            std::fs::remove_file(old)?;
+        }
+        tracing::info!("Backed up the index to {}", path.display());
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backups_rotate_and_resolve() {
+        let dir = std::env::temp_dir().join(format!("rag_backups_{}", std::process::id()));
+        let config = BackupConfig { dir: Some(dir.clone()), interval_secs: 60, retain: 2 };
+        let mut rag = SimpleRagSystem::new().unwrap();
+        rag.process_bytes("leave.md", b"Staff get twenty days of paid leave.").unwrap();
+
+        let mut scheduler = BackupScheduler::new(&config).unwrap();
+        assert!(scheduler.run_if_due(&rag, 1_000).unwrap().is_some());
+        assert!(scheduler.run_if_due(&rag, 1_030).unwrap().is_none());
+        assert_eq!(scheduler.due_in(1_030), 30);
+        scheduler.run_if_due(&rag, 1_060).unwrap();
+        scheduler.run_if_due(&rag, 1_120).unwrap();
+        let taken: Vec<u64> = list_backups(&dir).unwrap().into_iter().map(|(taken, _)| taken).collect();
+        assert_eq!(taken, vec![1_060, 1_120]);
+        // A restarted scheduler picks up from the newest backup on disk
+        assert_eq!(BackupScheduler::new(&config).unwrap().due_in(1_150), 30);
+
+        let latest = config.resolve(Path::new("latest")).unwrap();
+        assert_eq!(latest, dir.join("backup-1120.ragpack"));
+        assert_eq!(config.resolve(Path::new("backup-1060.ragpack")).unwrap(), dir.join("backup-1060.ragpack"));
+        assert!(config.resolve(Path::new("backup-1000.ragpack")).is_err());
+        let mut restored = SimpleRagSystem::new().unwrap();
+        assert_eq!(restored.import(&latest).unwrap(), 1);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let bucket = BackupConfig { dir: Some(PathBuf::from("s3://backups/rag")), ..BackupConfig::default() };
+        assert!(BackupScheduler::new(&bucket).unwrap_err().to_string().contains("mount"));
+    }
+}
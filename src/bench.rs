@@ -0,0 +1,193 @@
+//! Ingest throughput and query latency benchmarks
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::time::{Duration, Instant};
+use crate::quantization::EmbeddingQuantization;
+
+/// Latency distribution of a batch of timed operations, in milliseconds
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub samples: usize,
+    pub mean_ms: f64,
+    pub min_ms: f64,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl LatencyStats {
+    pub fn from_durations(durations: &[Duration]) -> Self {
+        if durations.is_empty() {
+            return Self::default();
+        }
+        let mut ms: Vec<f64> = durations.iter().map(|d| d.as_secs_f64() * 1_000.0).collect();
+        ms.sort_by(f64::total_cmp);
+        Self {
+            samples: ms.len(),
+            mean_ms: ms.iter().sum::<f64>() / ms.len() as f64,
+            min_ms: ms[0],
+            p50_ms: percentile(&ms, 50.0),
+            p95_ms: percentile(&ms, 95.0),
+            p99_ms: percentile(&ms, 99.0),
+            max_ms: ms[ms.len() - 1],
+        }
+    }
+}
+
+/// Milliseconds since `started`, as recorded in spans and reports
+pub(crate) fn elapsed_ms(started: Instant) -> f64 {
+    started.elapsed().as_secs_f64() * 1_000.0
+}
+
+/// Nearest-rank percentile of sorted samples
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = (p / 100.0 * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// Time to chunk, embed and store the whole corpus once
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IngestThroughput {
+    pub documents: usize,
+    pub chunks: usize,
+    pub bytes: usize,
+    pub seconds: f64,
+    pub documents_per_sec: f64,
+    pub chunks_per_sec: f64,
+    pub mb_per_sec: f64,
+}
+
+impl IngestThroughput {
+    pub fn new(documents: usize, chunks: usize, bytes: usize, elapsed: Duration) -> Self {
+        // Keep tiny corpora from dividing by zero
+        let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+        Self {
+            documents,
+            chunks,
+            bytes,
+            seconds: elapsed.as_secs_f64(),
+            documents_per_sec: documents as f64 / seconds,
+            chunks_per_sec: chunks as f64 / seconds,
+            mb_per_sec: bytes as f64 / 1_000_000.0 / seconds,
+        }
+    }
+}
+
+/// Configuration the numbers were measured under, so reports from different setups line up
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchSetup {
+    pub search_mode: String,
+    pub search_fingerprint: String,
+    pub embedding_model: Option<String>,
+    pub queries: usize,
+    pub iterations: usize,
+    pub limit: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub setup: BenchSetup,
+    pub ingest: IngestThroughput,
+    pub query: LatencyStats,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantized: Option<QuantizedBench>,
+}
+
+/// The same queries against a quantized copy of the embeddings
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantizedBench {
+    pub quantization: EmbeddingQuantization,
+    pub query: LatencyStats,
+    /// Share of each query's full-precision results the quantized search also returns, on average
+    pub recall: f32,
+    pub full_bytes: usize,
+    pub quantized_bytes: usize,
+}
+
+/// One query per line; blank lines and `#` comments are skipped
+pub fn load_queries(path: &Path) -> Result<Vec<String>> {
+    let queries: Vec<String> = std::fs::read_to_string(path)?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect();
+    if queries.is_empty() {
+        return Err(anyhow!("{} contains no queries", path.display()));
+    }
+    Ok(queries)
+}
+
+/// A benchmark whose median got slower than the baseline allows
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencyRegression {
+    pub name: String,
+    pub baseline_p50_ms: f64,
+    pub current_p50_ms: f64,
+    /// Relative slowdown, 0.25 for 25% slower
+    pub slowdown: f64,
+}
+
+/// Benchmarks in both runs whose median latency grew by more than `max_slowdown` (0.25 for 25%)
+/// and past the baseline's own p95. Medians are compared because the tail is dominated by scheduler
+/// noise on shared machines, and a median still inside the baseline's spread is noise too
+pub fn find_regressions(
+    baseline: &BTreeMap<String, LatencyStats>,
+    current: &BTreeMap<String, LatencyStats>,
+    max_slowdown: f64,
+) -> Vec<LatencyRegression> {
+    current
+        .iter()
+        .filter_map(|(name, stats)| {
+            let before = baseline.get(name)?;
+            let slowdown = stats.p50_ms / before.p50_ms.max(f64::EPSILON) - 1.0;
+            (slowdown > max_slowdown && stats.p50_ms > before.p95_ms).then(|| LatencyRegression {
+                name: name.clone(),
+                baseline_p50_ms: before.p50_ms,
+                current_p50_ms: stats.p50_ms,
+                slowdown,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_latency_percentiles() {
+        let durations: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+
+        let stats = LatencyStats::from_durations(&durations);
+
+        assert_eq!(stats.samples, 100);
+        assert_eq!((stats.min_ms, stats.max_ms), (1.0, 100.0));
+        assert_eq!((stats.p50_ms, stats.p95_ms, stats.p99_ms), (50.0, 95.0, 99.0));
+        assert!((stats.mean_ms - 50.5).abs() < 1e-9);
+        assert_eq!(LatencyStats::from_durations(&[]).samples, 0);
+    }
+
+    #[test]
+    fn test_regressions_compare_medians_of_shared_benchmarks() {
+        let stats = |p50_ms| LatencyStats { p50_ms, ..Default::default() };
+        let baseline = BTreeMap::from([("search/10000".to_string(), stats(2.0)), ("chunk/10000".to_string(), stats(1.0))]);
+        let current = BTreeMap::from([
+            ("search/10000".to_string(), stats(2.1)),
+            ("chunk/10000".to_string(), stats(1.5)),
+            ("search/1000000".to_string(), stats(90.0)),
+        ]);
+
+        let regressions = find_regressions(&baseline, &current, 0.1);
+        assert_eq!(regressions.len(), 1);
+        assert_eq!(regressions[0].name, "chunk/10000");
+        assert!((regressions[0].slowdown - 0.5).abs() < 1e-9);
+
+        let noisy = BTreeMap::from([("chunk/10000".to_string(), LatencyStats { p50_ms: 1.0, p95_ms: 1.6, ..Default::default() })]);
+        assert!(find_regressions(&noisy, &current, 0.1).is_empty(), "within the baseline's spread");
+    }
+}
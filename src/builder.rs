@@ -0,0 +1,275 @@
+//! Step-by-step construction of a `SimpleRagSystem`
+
+use anyhow::Result;
+use std::sync::Arc;
+use crate::answer_cache::AnswerCacheConfig;
+use crate::chunking::{ChunkingEngine, ChunkingStrategy};
+use crate::context::ContextOrder;
+use crate::embedding::EmbeddingProvider;
+use crate::generation::{PromptTemplate, DEFAULT_CONTEXT_CHUNKS};
+use crate::llm::CompletionProvider;
+use crate::metrics::Metrics;
+use crate::processor::IngestProgressCallback;
+use crate::audit::default_actor;
+use crate::webhook::WebhookNotifier;
+use crate::plugins::{PluginConfig, PluginRegistry};
+use crate::processor::DocumentProcessor;
+use crate::query_log::QueryLogConfig;
+use crate::search::{SearchConfig, SearchEngine, SearchMode};
+use crate::storage::StorageManager;
+use crate::synonyms::SynonymDictionary;
+use crate::usage::UsageMeter;
+use crate::SimpleRagSystem;
+
+/// Collects the parts of a system; anything left unset gets the same default as `SimpleRagSystem::new`
+pub struct SimpleRagSystemBuilder {
+    chunking: Option<ChunkingStrategy>,
+    storage: Option<StorageManager>,
+    tenant: Option<String>,
+    search: SearchConfig,
+    completion: Option<Arc<dyn CompletionProvider>>,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    prompt_template: PromptTemplate,
+    context_chunks: usize,
+    context_tokens: Option<usize>,
+    context_order: ContextOrder,
+    min_retrieval_score: f32,
+    hyde: bool,
+    summarize: bool,
+    extract_graph: bool,
+    synonyms: SynonymDictionary,
+    answer_cache: Option<AnswerCacheConfig>,
+    query_log: Option<QueryLogConfig>,
+    actor: Option<String>,
+    webhooks: Option<Arc<WebhookNotifier>>,
+    plugins: Option<(PluginRegistry, PluginConfig)>,
+    ingest_progress: Option<IngestProgressCallback>,
+}
+
+impl Default for SimpleRagSystemBuilder {
+    fn default() -> Self {
+        Self {
+            chunking: None,
+            storage: None,
+            tenant: None,
+            search: SearchConfig::default(),
+            completion: None,
+            embedder: None,
+            prompt_template: PromptTemplate::default(),
+            context_chunks: DEFAULT_CONTEXT_CHUNKS,
+            context_tokens: None,
+            context_order: ContextOrder::Relevance,
+            min_retrieval_score: 0.0,
+            hyde: false,
+            summarize: false,
+            extract_graph: false,
+            synonyms: SynonymDictionary::default(),
+            answer_cache: None,
+            query_log: None,
+            actor: None,
+            webhooks: None,
+            plugins: None,
+            ingest_progress: None,
+        }
+    }
+}
+
+impl SimpleRagSystemBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn chunking(mut self, strategy: ChunkingStrategy) -> Self {
+        self.chunking = Some(strategy);
+        self
+    }
+
+    /// Where documents, chunks and embeddings live, e.g. `StorageManager::open(path)?`; in memory by default
+    pub fn storage(mut self, storage: StorageManager) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Only see and store this tenant's documents, see `StorageManager::tenant`
+    pub fn tenant(mut self, tenant: impl Into<String>) -> Self {
+        self.tenant = Some(tenant.into());
+        self
+    }
+
+    /// Replaces the whole search configuration, including any mode set before
+    pub fn search_config(mut self, config: SearchConfig) -> Self {
+        self.search = config;
+        self
+    }
+
+    pub fn search_mode(mut self, mode: SearchMode) -> Self {
+        self.search.mode = mode;
+        self
+    }
+
+    pub fn completion_provider(mut self, provider: impl CompletionProvider + 'static) -> Self {
+        self.completion = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn embedding_provider(mut self, provider: impl EmbeddingProvider + 'static) -> Self {
+        self.embedder = Some(Arc::new(provider));
+        self
+    }
+
+    pub fn prompt_template(mut self, template: PromptTemplate) -> Self {
+        self.prompt_template = template;
+        self
+    }
+
+    pub fn context_chunks(mut self, chunks: usize) -> Self {
+        self.context_chunks = chunks;
+        self
+    }
+
+    pub fn context_tokens(mut self, tokens: usize) -> Self {
+        self.context_tokens = Some(tokens);
+        self
+    }
+
+    pub fn context_order(mut self, order: ContextOrder) -> Self {
+        self.context_order = order;
+        self
+    }
+
+    pub fn min_retrieval_score(mut self, min_score: f32) -> Self {
+        self.min_retrieval_score = min_score;
+        self
+    }
+
+    pub fn hyde(mut self, enabled: bool) -> Self {
+        self.hyde = enabled;
+        self
+    }
+
+    pub fn summaries(mut self, enabled: bool) -> Self {
+        self.summarize = enabled;
+        self
+    }
+
+    pub fn knowledge_graph(mut self, enabled: bool) -> Self {
+        self.extract_graph = enabled;
+        self
+    }
+
+    pub fn synonyms(mut self, synonyms: SynonymDictionary) -> Self {
+        self.synonyms = synonyms;
+        self
+    }
+
+    pub fn answer_cache(mut self, config: AnswerCacheConfig) -> Self {
+        self.answer_cache = Some(config);
+        self
+    }
+
+    pub fn query_log(mut self, config: QueryLogConfig) -> Self {
+        self.query_log = Some(config);
+        self
+    }
+
+    /// Who the audit log records operations as; `audit::default_actor` when unset
+    pub fn actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = Some(actor.into());
+        self
+    }
+
+    /// Tell webhooks about documents ingested, updated and deleted
+    pub fn webhooks(mut self, notifier: WebhookNotifier) -> Self {
+        self.webhooks = Some(Arc::new(notifier));
+        self
+    }
+
+    /// Use the components `config` names from `registry`; `build` fails on a name nothing is registered under
+    pub fn plugins(mut self, registry: PluginRegistry, config: PluginConfig) -> Self {
+        self.plugins = Some((registry, config));
+        self
+    }
+
+    pub fn ingest_progress(mut self, callback: IngestProgressCallback) -> Self {
+        self.ingest_progress = Some(callback);
+        self
+    }
+
+    /// Fails if the search configuration is invalid
+    pub fn build(self) -> Result<SimpleRagSystem> {
+        let chunker = match self.chunking {
+            Some(strategy) => ChunkingEngine::with_strategy(strategy),
+            None => ChunkingEngine::new()?,
+        };
+        let mut storage = match self.storage {
+            Some(storage) => storage,
+            None => StorageManager::new()?,
+        };
+        if let Some(tenant) = &self.tenant {
+            storage = storage.tenant(tenant);
+        }
+        let usage = Arc::new(UsageMeter::default());
+        let mut rag = SimpleRagSystem {
+            processor: DocumentProcessor::new(),
+            chunker,
+            searcher: SearchEngine::with_config(self.search)?.with_synonyms(self.synonyms),
+            storage,
+            completion: None,
+            embedder: None,
+            prompt_template: self.prompt_template,
+            context_chunks: self.context_chunks,
+            context_tokens: self.context_tokens,
+            context_order: self.context_order,
+            min_retrieval_score: self.min_retrieval_score,
+            hyde: self.hyde,
+            summarize: self.summarize,
+            extract_graph: self.extract_graph,
+            answer_cache: self.answer_cache,
+            query_log: self.query_log,
+            actor: self.actor.unwrap_or_else(default_actor),
+            webhooks: self.webhooks,
+            metrics: Arc::new(Metrics::new(usage.clone())),
+            usage,
+            ingest_progress: self.ingest_progress,
+        };
+        // Wrapped by the system so their calls are metered
+        if let Some(provider) = self.completion {
+            rag = rag.with_completion_provider(provider);
+        }
+        if let Some(provider) = self.embedder {
+            rag = rag.with_embedding_provider(provider);
+        }
+        if let Some((registry, config)) = &self.plugins {
+            rag = rag.with_plugins(registry, config)?;
+        }
+        Ok(rag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_builder_applies_every_part() {
+        let test_file = "/tmp/test_builder.txt";
+        std::fs::write(test_file, "Ownership moves values.\n\nBorrowing lends them out.").unwrap();
+
+        let mut rag = SimpleRagSystem::builder()
+            .chunking(ChunkingStrategy::Paragraph)
+            .storage(StorageManager::new().unwrap())
+            .search_mode(SearchMode::Bm25)
+            .context_chunks(2)
+            .build()
+            .unwrap();
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+
+        assert_eq!(rag.document_chunks(&doc_id).unwrap().len(), 2);
+        assert_eq!(rag.searcher.config().mode, SearchMode::Bm25);
+        assert_eq!(rag.context_chunks, 2);
+        assert!(rag.completion_provider().is_err());
+
+        std::fs::remove_file(test_file).unwrap();
+    }
+}
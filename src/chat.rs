@@ -0,0 +1,174 @@
+//! Multi-turn conversations over the document store
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::generation::Answer;
+use crate::llm::CompletionRequest;
+use crate::usage::Usage;
+use crate::SimpleRagSystem;
+
+/// Prior turns included in prompts by default
+pub const DEFAULT_CHAT_HISTORY: usize = 6;
+
+const CONDENSE_PREAMBLE: &str = "Rewrite the user's follow-up question as a standalone search query \
+that can be understood without the conversation. Respond with the query only.";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatTurn {
+    pub question: String,
+    /// The standalone query actually used for retrieval
+    pub retrieval_query: String,
+    pub answer: Answer,
+}
+
+/// Conversation state on top of a RAG system: keeps history, condenses follow-ups, feeds prior turns to the LLM
+pub struct ChatSession<'a> {
+    rag: &'a SimpleRagSystem,
+    history: Vec<ChatTurn>,
+    max_history: usize,
+}
+
+impl<'a> ChatSession<'a> {
+    pub fn new(rag: &'a SimpleRagSystem) -> Self {
+        Self {
+            rag,
+            history: Vec::new(),
+            max_history: DEFAULT_CHAT_HISTORY,
+        }
+    }
+
+    /// How many prior turns are condensed into and shown to the prompt
+    pub fn with_max_history(mut self, max_history: usize) -> Self {
+        self.max_history = max_history;
+        self
+    }
+
+    pub fn history(&self) -> &[ChatTurn] {
+        &self.history
+    }
+
+    pub fn last_turn(&self) -> Option<&ChatTurn> {
+        self.history.last()
+    }
+
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// Tokens and estimated cost of every turn so far
+    pub fn usage(&self) -> Usage {
+        let mut total = Usage::default();
+        for turn in &self.history {
+            total.add(&turn.answer.usage);
+        }
+        total
+    }
+
+    pub fn send(&mut self, question: &str) -> Result<&ChatTurn> {
+        let before = self.rag.usage();
+        let turn = self.answer_turn(question);
+        let usage = self.rag.record_usage_since("chat", &before);
+        let mut turn = turn?;
+        turn.answer.usage = usage;
+        self.history.push(turn);
+        Ok(self.history.last().unwrap())
+    }
+
+    fn answer_turn(&self, question: &str) -> Result<ChatTurn> {
+        let llm = self.rag.completion_provider()?;
+
+        let retrieval_query = if self.history.is_empty() {
+            question.to_string()
+        } else {
+            let request = CompletionRequest::new(format!(
+                "Conversation:\n{}\n\nFollow-up question: {}\n\nStandalone query:",
+                self.transcript(),
+                question
+            ))
+            .with_preamble(CONDENSE_PREAMBLE)
+            .with_temperature(0.0);
+            let condensed = llm.complete(&request)?.trim().to_string();
+            tracing::debug!("Condensed follow-up '{}' to '{}'", question, condensed);
+            condensed
+        };
+
+        let (results, chunks) = self.rag.retrieve_context(&retrieval_query)?;
+        let answer = match self.rag.refuse_weak_context(&results) {
+            Some(refusal) => refusal,
+            None => {
+                let mut request = self.rag.prompt_template().build_request(question, &results);
+                if !self.history.is_empty() {
+                    request.prompt = format!("Conversation so far:\n{}\n\n{}", self.transcript(), request.prompt);
+                }
+                Answer::from_response(llm.complete(&request)?, &chunks)
+            }
+        };
+
+        Ok(ChatTurn {
+            question: question.to_string(),
+            retrieval_query,
+            answer,
+        })
+    }
+
+    fn transcript(&self) -> String {
+        let start = self.history.len().saturating_sub(self.max_history);
+        self.history[start..]
+            .iter()
+            .map(|turn| format!("User: {}\nAssistant: {}", turn.question, turn.answer.text.trim()))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::CompletionProvider;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    /// Condenses follow-ups to a fixed query and records every prompt it sees
+    struct RecordingLlm {
+        prompts: Mutex<Vec<String>>,
+    }
+
+    impl CompletionProvider for RecordingLlm {
+        fn model_name(&self) -> &str {
+            "recording"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> Result<String> {
+            self.prompts.lock().unwrap().push(request.prompt.clone());
+            if request.prompt.ends_with("Standalone query:") {
+                return Ok("tokio scheduler".to_string());
+            }
+            Ok("It is a runtime [1].".to_string())
+        }
+    }
+
+    #[test]
+    fn test_follow_up_is_condensed_and_history_is_prompted() {
+        let test_file = "/tmp/test_chat.txt";
+        std::fs::write(test_file, "Tokio is an async runtime with a work-stealing scheduler.").unwrap();
+
+        let llm = Arc::new(RecordingLlm { prompts: Mutex::new(Vec::new()) });
+        let mut rag = SimpleRagSystem::new().unwrap().with_completion_provider(llm.clone());
+        rag.process_document(Path::new(test_file)).unwrap();
+
+        let mut session = ChatSession::new(&rag);
+        session.send("What is tokio?").unwrap();
+        let turn = session.send("How does its scheduler work?").unwrap();
+
+        assert_eq!(turn.retrieval_query, "tokio scheduler");
+        assert_eq!(turn.answer.citations.len(), 1);
+        let prompts = llm.prompts.lock().unwrap();
+        assert!(prompts.last().unwrap().contains("User: What is tokio?\nAssistant: It is a runtime [1]."));
+
+        drop(prompts);
+        session.reset();
+        assert!(session.history().is_empty());
+
+        std::fs::remove_file(test_file).unwrap();
+    }
+}
@@ -2,40 +2,109 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::sync::Arc;
+use crate::time::Instant;
+use tracing::field::Empty;
+use crate::bench::elapsed_ms;
+use crate::entities::{extract_entities, Entity};
+use crate::keyphrases::{extract_keyphrases, KEYPHRASES_PER_CHUNK};
+use crate::minhash::minhash;
+use crate::plugins::Chunker;
 use crate::processor::ProcessedDocument;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentChunk {
     pub id: String,
-    pub content: String,
+    /// Shared so that copying chunks out of storage for a search doesn't copy their text
+    pub content: Arc<str>,
     pub start_pos: usize,
     pub end_pos: usize,
     pub word_count: usize,
     pub document_id: String,
+    /// Byte range of the chunk within the document content
+    #[serde(default)]
+    pub byte_start: usize,
+    #[serde(default)]
+    pub byte_end: usize,
+    /// People, organizations and places the chunk names, found at ingest; shared like `content`
+    #[serde(default, skip_serializing_if = "<[Entity]>::is_empty")]
+    pub entities: Arc<[Entity]>,
+    /// Top RAKE keyphrases of the chunk, lowercased, best first
+    #[serde(default, skip_serializing_if = "<[String]>::is_empty")]
+    pub keyphrases: Arc<[String]>,
+    /// MinHash signature of the chunk's shingles, for spotting near duplicates; see `signature`
+    #[serde(default, skip_serializing_if = "<[u32]>::is_empty")]
+    pub minhash: Arc<[u32]>,
+}
+
+impl DocumentChunk {
+    /// The stored MinHash signature, or one computed from the content for chunks indexed before them
+    pub fn signature(&self) -> Cow<'_, [u32]> {
+        if self.minhash.is_empty() {
+            Cow::Owned(minhash(&self.content))
+        } else {
+            Cow::Borrowed(&self.minhash)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ChunkingStrategy {
     FixedSize { size: usize },
     Paragraph,
+    /// A `Chunker` registered under `name`; see `plugins`
+    Custom { name: String },
 }
 
 pub struct ChunkingEngine {
     strategy: ChunkingStrategy,
+    /// What a `Custom` strategy splits with
+    custom: Option<Arc<dyn Chunker>>,
 }
 
 impl ChunkingEngine {
     pub fn new() -> Result<Self> {
-        Ok(Self {
-            strategy: ChunkingStrategy::FixedSize { size: 500 },
-        })
+        Ok(Self::with_strategy(ChunkingStrategy::FixedSize { size: 500 }))
+    }
+
+    /// A `Custom` strategy fails to chunk anything until given its chunker with `with_chunker`
+    pub fn with_strategy(strategy: ChunkingStrategy) -> Self {
+        Self { strategy, custom: None }
+    }
+
+    /// Split with `chunker`, reported as the custom strategy `name`
+    pub fn with_chunker(name: &str, chunker: Arc<dyn Chunker>) -> Self {
+        Self {
+            strategy: ChunkingStrategy::Custom { name: name.to_string() },
+            custom: Some(chunker),
+        }
     }
 
+    pub fn strategy(&self) -> &ChunkingStrategy {
+        &self.strategy
+    }
+
+    #[tracing::instrument(
+        skip(self, document),
+        fields(doc_id = %document.id, strategy = ?self.strategy, chunks = Empty, elapsed_ms = Empty)
+    )]
     pub fn chunk_document(&self, document: &ProcessedDocument) -> Result<Vec<DocumentChunk>> {
-        match &self.strategy {
+        let started = Instant::now();
+        let chunks = match &self.strategy {
             ChunkingStrategy::FixedSize { size } => self.fixed_size_chunking(document, *size),
             ChunkingStrategy::Paragraph => self.paragraph_chunking(document),
+            ChunkingStrategy::Custom { name } => match &self.custom {
+                Some(chunker) => custom_chunking(document, chunker.as_ref()),
+                None => Err(anyhow::anyhow!("No chunker registered as '{}'", name)),
+            },
+        };
+        let span = tracing::Span::current();
+        if let Ok(chunks) = &chunks {
+            span.record("chunks", chunks.len());
         }
+        span.record("elapsed_ms", elapsed_ms(started));
+        chunks
     }
 
     fn fixed_size_chunking(&self, document: &ProcessedDocument, chunk_size: usize) -> Result<Vec<DocumentChunk>> {
@@ -48,13 +117,25 @@ impl ChunkingEngine {
             let chunk_words = &words[start..end];
             let chunk_content = chunk_words.join(" ");
 
+            let byte_start = byte_offset(&document.content, chunk_words[0]);
+            let last_word = chunk_words[chunk_words.len() - 1];
+            let byte_end = byte_offset(&document.content, last_word) + last_word.len();
+            let entities = extract_entities(&chunk_content).into();
+            let keyphrases = extract_keyphrases(&chunk_content, KEYPHRASES_PER_CHUNK).into();
+            let minhash = minhash(&chunk_content).into();
+
             let chunk = DocumentChunk {
                 id: format!("{}_{}", document.id, chunks.len()),
-                content: chunk_content,
+                content: chunk_content.into(),
                 start_pos: start,
                 end_pos: end,
                 word_count: chunk_words.len(),
                 document_id: document.id.clone(),
+                byte_start,
+                byte_end,
+                entities,
+                keyphrases,
+                minhash,
             };
 
             chunks.push(chunk);
@@ -72,13 +153,20 @@ impl ChunkingEngine {
         for (i, paragraph) in paragraphs.iter().enumerate() {
             let word_count = paragraph.split_whitespace().count();
 
+            let byte_start = byte_offset(&document.content, paragraph);
+
             let chunk = DocumentChunk {
                 id: format!("{}_{}", document.id, i),
-                content: paragraph.to_string(),
+                content: (*paragraph).into(),
                 start_pos: word_pos,
                 end_pos: word_pos + word_count,
                 word_count,
                 document_id: document.id.clone(),
+                byte_start,
+                byte_end: byte_start + paragraph.len(),
+                entities: extract_entities(paragraph).into(),
+                keyphrases: extract_keyphrases(paragraph, KEYPHRASES_PER_CHUNK).into(),
+                minhash: minhash(paragraph).into(),
             };
 
             chunks.push(chunk);
@@ -89,6 +177,41 @@ impl ChunkingEngine {
     }
 }
 
+fn custom_chunking(document: &ProcessedDocument, chunker: &dyn Chunker) -> Result<Vec<DocumentChunk>> {
+    let content = &document.content;
+    let mut chunks = Vec::new();
+    let mut word_pos = 0;
+    for range in chunker.split(content) {
+        let text = content
+            .get(range.clone())
+            .ok_or_else(|| anyhow::anyhow!("Chunker returned {:?}, outside the text or inside a character", range))?;
+        if text.trim().is_empty() {
+            continue;
+        }
+        let word_count = text.split_whitespace().count();
+        chunks.push(DocumentChunk {
+            id: format!("{}_{}", document.id, chunks.len()),
+            content: text.into(),
+            start_pos: word_pos,
+            end_pos: word_pos + word_count,
+            word_count,
+            document_id: document.id.clone(),
+            byte_start: range.start,
+            byte_end: range.end,
+            entities: extract_entities(text).into(),
+            keyphrases: extract_keyphrases(text, KEYPHRASES_PER_CHUNK).into(),
+            minhash: minhash(text).into(),
+        });
+        word_pos += word_count;
+    }
+    Ok(chunks)
+}
+
+/// Offset of `part` within `source`; `part` must be a subslice of `source`
+fn byte_offset(source: &str, part: &str) -> usize {
+    part.as_ptr() as usize - source.as_ptr() as usize
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -110,6 +233,13 @@ mod tests {
                 file_type: "txt".to_string(),
                 file_size: 100,
                 word_count: 15,
+                summary: None,
+                relations: Vec::new(),
+                tags: Default::default(),
+                tenant: None,
+                language: None,
+                ingested_at: None,
+                modified_at: None,
             },
         };
 
@@ -117,4 +247,30 @@ mod tests {
         assert!(!chunks.is_empty());
         assert!(chunks[0].word_count <= 500);
     }
+
+    #[test]
+    fn test_chunk_byte_spans() {
+        let engine = ChunkingEngine::with_strategy(ChunkingStrategy::FixedSize { size: 3 });
+        let document = ProcessedDocument {
+            id: "test".to_string(),
+            content: "  one two\tthree\n\nfour  five ".to_string(),
+            metadata: crate::processor::DocumentMetadata {
+                file_path: "/test.txt".to_string(),
+                file_type: "txt".to_string(),
+                file_size: 30,
+                word_count: 5,
+                summary: None,
+                relations: Vec::new(),
+                tags: Default::default(),
+                tenant: None,
+                language: None,
+                ingested_at: None,
+                modified_at: None,
+            },
+        };
+
+        let chunks = engine.chunk_document(&document).unwrap();
+        assert_eq!(&document.content[chunks[0].byte_start..chunks[0].byte_end], "one two\tthree");
+        assert_eq!(&document.content[chunks[1].byte_start..chunks[1].byte_end], "four  five");
+    }
 }
\ No newline at end of file
@@ -0,0 +1,313 @@
+//! Typed configuration loaded from `~/.config/rag-system/config.toml`
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::auth::AuthConfig;
+use crate::backup::BackupConfig;
+use crate::chunking::ChunkingStrategy;
+#[cfg(feature = "openai")]
+use crate::embedding::RigEmbeddingProvider;
+use crate::embedding::{EmbeddingConfig, EmbeddingProvider};
+#[cfg(any(feature = "openai", feature = "anthropic"))]
+use crate::llm::RigCompletionProvider;
+use crate::llm::{CompletionProvider, ProviderKind};
+use crate::quantization::EmbeddingQuantization;
+use crate::query_log::QueryLogConfig;
+use crate::search::SearchConfig;
+use crate::webhook::WebhookConfig;
+use crate::plugins::PluginConfig;
+
+/// Everything `SimpleRagSystem::from_config` needs; every section is optional in the file
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RagConfig {
+    pub storage: StorageConfig,
+    pub chunking: ChunkingConfig,
+    pub search: SearchConfig,
+    /// LLM used for answers; without it `ask` and chat are unavailable
+    pub generation: Option<GenerationConfig>,
+    /// Embeddings for vector and hybrid search; without it only lexical modes work
+    pub embedding: Option<EmbeddingSettings>,
+    pub api_keys: ApiKeys,
+    /// Keys `serve` accepts; with none the server is open
+    pub auth: AuthConfig,
+    pub query_log: QueryLogConfig,
+    /// Scheduled backups in `serve` and `watch`
+    pub backup: BackupConfig,
+    /// Endpoints `serve` and `watch` tell about documents ingested, updated and deleted
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub webhooks: Vec<WebhookConfig>,
+    /// Registered chunker, extractors and scorers to use; see `SimpleRagSystem::from_config_with_plugins`
+    #[serde(skip_serializing_if = "PluginConfig::is_empty")]
+    pub plugins: PluginConfig,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Snapshot file at `path`, written on persist
+    #[default]
+    Json,
+    /// Nothing is written; the index lasts as long as the process
+    Memory,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StorageConfig {
+    pub backend: StorageBackend,
+    /// Index file for the json backend; defaults to `$RAG_SYSTEM_INDEX` or `~/.rag_system/index.json`
+    pub path: Option<PathBuf>,
+    /// Document content kept in memory, in megabytes; past it the least recently used is dropped
+    /// to a spill file, read back on demand. Chunks and embeddings always stay loaded
+    pub memory_budget_mb: Option<usize>,
+    /// `int8` keeps chunk embeddings at one byte per dimension instead of four and `pq` goes further;
+    /// `binary` adds sign bits to prefilter with, keeping the full vectors
+    pub embedding_quantization: EmbeddingQuantization,
+    /// Leading dimensions of each embedding to keep, for Matryoshka-trained models; all when unset
+    pub embedding_dimensions: Option<usize>,
+    /// Disk index file searched in place of the embeddings held in memory; `build-index --disk` writes it
+    pub disk_index: Option<PathBuf>,
+    /// Files, and search threads, to partition documents with their chunks and embeddings across
+    pub shards: Option<usize>,
+}
+
+impl StorageConfig {
+    pub fn memory_budget_bytes(&self) -> Option<usize> {
+        self.memory_budget_mb.map(|mb| mb * 1024 * 1024)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChunkingKind {
+    /// Runs of `size` words
+    #[default]
+    Fixed,
+    /// One chunk per blank-line separated paragraph
+    Paragraph,
+}
+
+impl std::str::FromStr for ChunkingKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "fixed" => Ok(ChunkingKind::Fixed),
+            "paragraph" => Ok(ChunkingKind::Paragraph),
+            other => Err(anyhow!("Unknown chunking strategy '{}' (expected fixed or paragraph)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ChunkingConfig {
+    pub strategy: ChunkingKind,
+    /// Words per chunk with the fixed strategy
+    pub size: usize,
+}
+
+impl Default for ChunkingConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ChunkingKind::Fixed,
+            size: 500,
+        }
+    }
+}
+
+impl ChunkingConfig {
+    pub fn strategy(&self) -> ChunkingStrategy {
+        match self.strategy {
+            ChunkingKind::Fixed => ChunkingStrategy::FixedSize { size: self.size },
+            ChunkingKind::Paragraph => ChunkingStrategy::Paragraph,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GenerationConfig {
+    pub provider: ProviderKind,
+    /// Defaults to the provider's default completion model
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl GenerationConfig {
+    pub fn model(&self) -> &str {
+        self.model.as_deref().unwrap_or_else(|| self.provider.default_completion_model())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EmbeddingSettings {
+    pub provider: ProviderKind,
+    /// Defaults to the provider's default embedding model
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub config: EmbeddingConfig,
+}
+
+impl EmbeddingSettings {
+    pub fn model(&self) -> Result<&str> {
+        match &self.model {
+            Some(model) => Ok(model),
+            None => self
+                .provider
+                .default_embedding_model()
+                .ok_or_else(|| anyhow!("{:?} has no embedding models", self.provider)),
+        }
+    }
+}
+
+/// Keys used instead of the `OPENAI_API_KEY` and `ANTHROPIC_API_KEY` environment variables
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ApiKeys {
+    pub openai: Option<String>,
+    pub anthropic: Option<String>,
+}
+
+impl RagConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        tracing::debug!("Loading config from {}", path.display());
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| anyhow!("Invalid config {}: {}", path.display(), e))
+    }
+
+    /// The file at `default_config_path`, or defaults when there is none
+    pub fn load_default() -> Result<Self> {
+        match default_config_path() {
+            Some(path) if path.exists() => Self::from_file(&path),
+            _ => Ok(Self::default()),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Index file for the json backend
+    pub fn index_path(&self) -> PathBuf {
+        self.storage.path.clone().unwrap_or_else(default_index_path)
+    }
+
+    pub fn completion_provider(&self) -> Result<Option<Arc<dyn CompletionProvider>>> {
+        let Some(generation) = &self.generation else {
+            return Ok(None);
+        };
+        let model = generation.model();
+        Ok(Some(match (generation.provider, &self.api_keys) {
+            #[cfg(feature = "openai")]
+            (ProviderKind::OpenAi, ApiKeys { openai: Some(key), .. }) => {
+                Arc::new(RigCompletionProvider::openai_with_key(model, key))
+            }
+            #[cfg(feature = "anthropic")]
+            (ProviderKind::Anthropic, ApiKeys { anthropic: Some(key), .. }) => {
+                Arc::new(RigCompletionProvider::anthropic_with_key(model, key))
+            }
+            (kind, _) => crate::llm::completion_provider(kind, model)?,
+        }))
+    }
+
+    pub fn embedding_provider(&self) -> Result<Option<Arc<dyn EmbeddingProvider>>> {
+        let Some(embedding) = &self.embedding else {
+            return Ok(None);
+        };
+        let model = embedding.model()?;
+        Ok(Some(match (embedding.provider, &self.api_keys.openai) {
+            #[cfg(feature = "openai")]
+            (ProviderKind::OpenAi, Some(key)) => {
+                Arc::new(RigEmbeddingProvider::openai_with_key(model, key).with_config(embedding.config.clone()))
+            }
+            (kind, _) => crate::embedding::embedding_provider(kind, model, &embedding.config, None)?,
+        }))
+    }
+}
+
+/// `$XDG_CONFIG_HOME/rag-system/config.toml`, falling back to `~/.config`
+pub fn default_config_path() -> Option<PathBuf> {
+    let base = match std::env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var("HOME").ok()?).join(".config"),
+    };
+    Some(base.join("rag-system").join("config.toml"))
+}
+
+/// `$RAG_SYSTEM_INDEX`, or `~/.rag_system/index.json`
+pub fn default_index_path() -> PathBuf {
+    if let Ok(path) = std::env::var("RAG_SYSTEM_INDEX") {
+        return PathBuf::from(path);
+    }
+    let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
+    Path::new(&home).join(".rag_system").join("index.json")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::search::SearchMode;
+
+    #[test]
+    fn test_parses_partial_config() {
+        let config: RagConfig = toml::from_str(
+            r#"
+            [storage]
+            path = "/tmp/index.json"
+
+            [chunking]
+            strategy = "paragraph"
+
+            [search]
+            mode = "hybrid"
+            keyword_weight = 0.5
+
+            [embedding]
+            provider = "ollama"
+            batch_size = 32
+
+            [api_keys]
+            openai = "sk-test"
+
+            [[webhooks]]
+            url = "http://cache.internal/invalidate"
+            events = ["updated", "deleted"]
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.index_path(), PathBuf::from("/tmp/index.json"));
+        assert!(matches!(config.chunking.strategy(), ChunkingStrategy::Paragraph));
+        assert_eq!((config.search.mode, config.search.bm25_k1), (SearchMode::Hybrid, 1.2));
+        let embedding = config.embedding.as_ref().unwrap();
+        assert_eq!(embedding.model().unwrap(), "nomic-embed-text");
+        assert_eq!((embedding.config.batch_size, embedding.config.concurrency), (32, 4));
+        assert!(config.generation.is_none());
+        assert_eq!(config.api_keys.openai.as_deref(), Some("sk-test"));
+        assert_eq!((config.webhooks[0].events.len(), config.webhooks[0].retries), (2, 2));
+    }
+
+    #[test]
+    fn test_config_round_trips() {
+        let config_file = "/tmp/test_rag_config.toml";
+        let config = RagConfig {
+            generation: Some(GenerationConfig { provider: ProviderKind::Anthropic, model: None }),
+            ..RagConfig::default()
+        };
+        config.save(Path::new(config_file)).unwrap();
+
+        let loaded = RagConfig::from_file(Path::new(config_file)).unwrap();
+        assert_eq!(loaded, config);
+        assert_eq!(loaded.generation.unwrap().model(), "claude-sonnet-4-0");
+
+        std::fs::remove_file(config_file).unwrap();
+    }
+}
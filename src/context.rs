@@ -0,0 +1,190 @@
+//! Selecting retrieved chunks for the prompt under a token budget
+
+use serde::{Deserialize, Serialize};
+use tiktoken_rs::CoreBPE;
+use crate::chunking::DocumentChunk;
+use crate::search::SearchResult;
+
+/// Tokens spent on each item's `[n]` marker and separator
+const ITEM_OVERHEAD_TOKENS: usize = 4;
+
+/// How selected chunks are laid out in the prompt
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum ContextOrder {
+    /// Best match first
+    Relevance,
+    /// Grouped by document, in reading order, so neighbouring passages stay together
+    Document,
+}
+
+/// Chunks chosen for the prompt, with their search results in the same order
+#[derive(Debug, Clone)]
+pub struct PackedContext {
+    pub results: Vec<SearchResult>,
+    pub chunks: Vec<DocumentChunk>,
+    pub tokens: usize,
+    /// Candidates left out as duplicates or for lack of budget
+    pub dropped: usize,
+}
+
+/// Picks chunks by rank until the token budget is spent, skipping ones that overlap a better match
+pub struct ContextBuilder {
+    token_budget: usize,
+    overlap_threshold: f32,
+    order: ContextOrder,
+    bpe: &'static CoreBPE,
+}
+
+impl ContextBuilder {
+    /// Budget counted with the cl100k tokenizer
+    pub fn new(token_budget: usize) -> Self {
+        Self {
+            token_budget,
+            overlap_threshold: 0.5,
+            order: ContextOrder::Relevance,
+            bpe: tiktoken_rs::cl100k_base_singleton(),
+        }
+    }
+
+    /// Budget counted with the tokenizer of `model`, or cl100k for models tiktoken doesn't know
+    pub fn for_model(model: &str, token_budget: usize) -> Self {
+        let mut builder = Self::new(token_budget);
+        if let Ok(bpe) = tiktoken_rs::bpe_for_model(model) {
+            builder.bpe = bpe;
+        }
+        builder
+    }
+
+    pub fn with_order(mut self, order: ContextOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Share of the shorter chunk's bytes two chunks must share to count as duplicates
+    pub fn with_overlap_threshold(mut self, threshold: f32) -> Self {
+        self.overlap_threshold = threshold;
+        self
+    }
+
+    pub fn token_budget(&self) -> usize {
+        self.token_budget
+    }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    /// `results` and `chunks` are parallel, best match first
+    pub fn build(&self, results: Vec<SearchResult>, chunks: Vec<DocumentChunk>) -> PackedContext {
+        let candidates = results.len();
+        let mut selected: Vec<(SearchResult, DocumentChunk)> = Vec::new();
+        let mut tokens = 0;
+
+        for (result, chunk) in results.into_iter().zip(chunks) {
+            if selected.iter().any(|(_, kept)| self.is_duplicate(kept, &chunk)) {
+                continue;
+            }
+            let cost = self.count_tokens(&chunk.content) + ITEM_OVERHEAD_TOKENS;
+            if tokens + cost > self.token_budget {
+                continue;
+            }
+            tokens += cost;
+            selected.push((result, chunk));
+        }
+
+        if self.order == ContextOrder::Document {
+            selected.sort_by(|(_, a), (_, b)| (&a.document_id, a.byte_start).cmp(&(&b.document_id, b.byte_start)));
+        }
+
+        let dropped = candidates - selected.len();
+        let (results, chunks) = selected.into_iter().unzip();
+        PackedContext {
+            results,
+            chunks,
+            tokens,
+            dropped,
+        }
+    }
+
+    fn is_duplicate(&self, a: &DocumentChunk, b: &DocumentChunk) -> bool {
+        if a.content == b.content {
+            return true;
+        }
+        if a.document_id != b.document_id {
+            return false;
+        }
+        let overlap = a.byte_end.min(b.byte_end).saturating_sub(a.byte_start.max(b.byte_start));
+        let shorter = (a.byte_end - a.byte_start).min(b.byte_end - b.byte_start);
+        shorter > 0 && overlap as f32 / shorter as f32 >= self.overlap_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(id: &str, document_id: &str, content: &str, span: (usize, usize)) -> (SearchResult, DocumentChunk) {
+        (
+            SearchResult {
+                chunk_id: id.to_string(),
+                document_id: document_id.to_string(),
+                content: content.to_string(),
+                score: 1.0,
+                rank: 0,
+                pinned: false,
+            },
+            DocumentChunk {
+                id: id.to_string(),
+                content: content.into(),
+                start_pos: 0,
+                end_pos: 0,
+                word_count: 0,
+                document_id: document_id.to_string(),
+                byte_start: span.0,
+                byte_end: span.1,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
+            },
+        )
+    }
+
+    #[test]
+    fn test_overlapping_chunks_are_deduplicated() {
+        let (results, chunks): (Vec<_>, Vec<_>) = vec![
+            candidate("a", "d1", "alpha beta gamma", (0, 100)),
+            candidate("b", "d1", "beta gamma delta", (40, 140)),
+            candidate("c", "d2", "alpha beta gamma", (0, 100)),
+            candidate("d", "d1", "epsilon", (200, 260)),
+        ]
+        .into_iter()
+        .unzip();
+
+        let packed = ContextBuilder::new(1_000)
+            .with_order(ContextOrder::Document)
+            .build(results, chunks);
+
+        // b overlaps a by 60%, c repeats a's text in another document
+        let ids: Vec<&str> = packed.chunks.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "d"]);
+        assert_eq!(packed.dropped, 2);
+    }
+
+    #[test]
+    fn test_budget_counts_real_tokens() {
+        let builder = ContextBuilder::new(30);
+        assert_eq!(builder.count_tokens("hello world"), 2);
+
+        let (results, chunks): (Vec<_>, Vec<_>) = vec![
+            candidate("long", "d1", &"tokens ".repeat(40), (0, 280)),
+            candidate("short", "d2", "a short passage", (0, 15)),
+        ]
+        .into_iter()
+        .unzip();
+        let packed = builder.build(results, chunks);
+
+        // The oversized chunk is skipped, but a smaller one further down still fits
+        assert_eq!(packed.results.iter().map(|r| r.chunk_id.as_str()).collect::<Vec<_>>(), vec!["short"]);
+        assert!(packed.tokens <= 30);
+    }
+}
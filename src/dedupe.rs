@@ -0,0 +1,269 @@
+//! Duplicate documents: exact copies have the same normalized text, near copies share most of their
+//! word shingles. Only pairs whose MinHash signatures share an LSH band are compared, for documents
+//! and for duplicate chunks across documents, since there are too many to compare every pair
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use crate::chunking::DocumentChunk;
+use crate::minhash::{candidate_pairs, minhash, similarity};
+use crate::processor::ProcessedDocument;
+use crate::search::tokenize;
+use crate::storage::fnv1a;
+
+/// Words per shingle
+pub const SHINGLE_WORDS: usize = 5;
+
+/// Jaccard similarity of shingles above which two documents are near duplicates
+pub const DEFAULT_NEAR_DUPLICATE_THRESHOLD: f64 = 0.8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicateKind {
+    /// Same text once case, punctuation and spacing are ignored
+    Exact,
+    Near,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateGroup {
+    pub kind: DuplicateKind,
+    /// The document to keep first, then its duplicates
+    pub documents: Vec<String>,
+    /// Lowest similarity among the pairs that joined the group; 1.0 for exact copies
+    pub similarity: f64,
+}
+
+impl DuplicateGroup {
+    pub fn keep(&self) -> &str {
+        &self.documents[0]
+    }
+
+    pub fn duplicates(&self) -> &[String] {
+        &self.documents[1..]
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DuplicateReport {
+    pub documents: usize,
+    pub groups: Vec<DuplicateGroup>,
+}
+
+impl DuplicateReport {
+    /// Every document but the one each group keeps
+    pub fn removable(&self) -> Vec<&str> {
+        self.groups.iter().flat_map(|group| group.duplicates()).map(String::as_str).collect()
+    }
+}
+
+/// Chunks of different documents that nearly repeat one another
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkDuplicateGroup {
+    pub chunks: Vec<String>,
+    /// Documents the chunks come from, in the same order
+    pub documents: Vec<String>,
+    /// Lowest estimated similarity among the pairs that joined the group
+    pub similarity: f64,
+}
+
+/// Hashes of each run of `SHINGLE_WORDS` words; shorter texts are one shingle
+pub fn shingles(text: &str) -> HashSet<u64> {
+    let words = tokenize(text);
+    if words.len() <= SHINGLE_WORDS {
+        return HashSet::from([fnv1a(&words.join(" "))]);
+    }
+    words.windows(SHINGLE_WORDS).map(|window| fnv1a(&window.join(" "))).collect()
+}
+
+pub fn jaccard(a: &HashSet<u64>, b: &HashSet<u64>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// Group `documents` into exact and near duplicates. A group keeps its longest document, ties
+/// going to the first by path, and near duplicates are linked transitively. Near duplicates are
+/// only looked for among pairs sharing an LSH band, so pairs well under 0.5 similar may be missed
+pub fn find_duplicates(documents: &[ProcessedDocument], threshold: f64) -> DuplicateReport {
+    let mut order: Vec<&ProcessedDocument> = documents.iter().collect();
+    order.sort_by(|a, b| b.content.len().cmp(&a.content.len()).then(a.metadata.file_path.cmp(&b.metadata.file_path)));
+
+    // Copies share a hash, but a hash shared by different texts must not make them copies
+    let mut exact: BTreeMap<u64, Vec<Vec<&ProcessedDocument>>> = BTreeMap::new();
+    for document in &order {
+        let normalized = tokenize(&document.content).join(" ");
+        let bucket = exact.entry(fnv1a(&normalized)).or_default();
+        match bucket.iter_mut().find(|copies| tokenize(&copies[0].content).join(" ") == normalized) {
+            Some(copies) => copies.push(document),
+            None => bucket.push(vec![document]),
+        }
+    }
+    let mut groups: Vec<DuplicateGroup> = exact
+        .values()
+        .flatten()
+        .filter(|copies| copies.len() > 1)
+        .map(|copies| DuplicateGroup {
+            kind: DuplicateKind::Exact,
+            documents: copies.iter().map(|doc| doc.id.clone()).collect(),
+            similarity: 1.0,
+        })
+        .collect();
+
+    // One representative per exact group, longest first, compared when their signatures collide
+    let mut firsts: Vec<&ProcessedDocument> = exact.values().flatten().map(|copies| copies[0]).collect();
+    firsts.sort_by_key(|doc| order.iter().position(|d| d.id == doc.id));
+    let signatures: Vec<Vec<u32>> = firsts.iter().map(|doc| minhash(&doc.content)).collect();
+    let refs: Vec<&[u32]> = signatures.iter().map(Vec::as_slice).collect();
+    let sets: Vec<HashSet<u64>> = firsts.iter().map(|doc| shingles(&doc.content)).collect();
+    let mut parent: Vec<usize> = (0..firsts.len()).collect();
+    let mut weakest = vec![1.0f64; firsts.len()];
+    for (i, j) in candidate_pairs(&refs) {
+        let similarity = jaccard(&sets[i], &sets[j]);
+        if similarity >= threshold {
+            let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+            let joined = similarity.min(weakest[a]).min(weakest[b]);
+            // The earlier, longer document stays the root and so the one kept
+            let (keep, merged) = (a.min(b), a.max(b));
+            parent[merged] = keep;
+            weakest[keep] = joined;
+        }
+    }
+    let mut near: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..firsts.len() {
+        let r = root(&mut parent, i);
+        near.entry(r).or_default().push(i);
+    }
+    groups.extend(near.into_iter().filter(|(_, members)| members.len() > 1).map(|(r, members)| DuplicateGroup {
+        kind: DuplicateKind::Near,
+        documents: members.into_iter().map(|i| firsts[i].id.clone()).collect(),
+        similarity: weakest[r],
+    }));
+
+    DuplicateReport { documents: documents.len(), groups }
+}
+
+/// Group chunks of different documents whose MinHash signatures are at least `threshold` similar.
+/// Only pairs sharing an LSH band are compared, so pairs well under 0.5 may be missed
+pub fn find_duplicate_chunks(chunks: &[DocumentChunk], threshold: f64) -> Vec<ChunkDuplicateGroup> {
+    let mut chunks: Vec<&DocumentChunk> = chunks.iter().collect();
+    chunks.sort_by(|a, b| a.id.cmp(&b.id));
+    let signatures: Vec<_> = chunks.iter().map(|chunk| chunk.signature()).collect();
+    let refs: Vec<&[u32]> = signatures.iter().map(|s| s.as_ref()).collect();
+
+    let mut parent: Vec<usize> = (0..chunks.len()).collect();
+    let mut weakest = vec![1.0f64; chunks.len()];
+    for (i, j) in candidate_pairs(&refs) {
+        if chunks[i].document_id == chunks[j].document_id {
+            continue;
+        }
+        let similarity = similarity(refs[i], refs[j]);
+        if similarity >= threshold {
+            let (a, b) = (root(&mut parent, i), root(&mut parent, j));
+            if a != b {
+                let (keep, merged) = (a.min(b), a.max(b));
+                weakest[keep] = similarity.min(weakest[a]).min(weakest[b]);
+                parent[merged] = keep;
+            } else {
+                weakest[a] = weakest[a].min(similarity);
+            }
+        }
+    }
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for i in 0..chunks.len() {
+        let r = root(&mut parent, i);
+        groups.entry(r).or_default().push(i);
+    }
+    groups
+        .into_iter()
+        .filter(|(_, members)| members.len() > 1)
+        .map(|(r, members)| ChunkDuplicateGroup {
+            chunks: members.iter().map(|&i| chunks[i].id.clone()).collect(),
+            documents: members.iter().map(|&i| chunks[i].document_id.clone()).collect(),
+            similarity: weakest[r],
+        })
+        .collect()
+}
+
+fn root(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::DocumentMetadata;
+
+    fn document(id: &str, content: &str) -> ProcessedDocument {
+        ProcessedDocument {
+            id: id.to_string(),
+            content: content.to_string(),
+            metadata: DocumentMetadata {
+                file_path: format!("{}.md", id),
+                file_type: "md".to_string(),
+                file_size: content.len(),
+                word_count: content.split_whitespace().count(),
+                summary: None,
+                relations: Vec::new(),
+                tags: Default::default(),
+                tenant: None,
+                language: None,
+                ingested_at: None,
+                modified_at: None,
+            },
+        }
+    }
+
+    #[test]
+    fn test_exact_and_near_copies_are_grouped() {
+        let leave = "Staff get twenty days of paid leave each year, booked through the portal with a week of notice.";
+        let documents = vec![
+            document("a", leave),
+            document("b", &leave.to_uppercase()),
+            document("c", &format!("{} Unused days carry over.", leave)),
+            document("d", "Sourdough needs a starter fed every day and a long, cold rise overnight."),
+        ];
+        let report = find_duplicates(&documents, 0.7);
+        assert_eq!(report.groups.len(), 2);
+        assert_eq!((report.groups[0].kind, report.groups[0].documents.clone()), (DuplicateKind::Exact, vec!["a".into(), "b".into()]));
+        let near = &report.groups[1];
+        assert_eq!((near.kind, near.keep()), (DuplicateKind::Near, "c"));
+        assert_eq!(near.duplicates(), ["a".to_string()]);
+        assert!(near.similarity >= 0.7 && near.similarity < 1.0);
+        assert_eq!(report.removable(), vec!["b", "a"]);
+
+        assert!(find_duplicates(&documents, 0.99).groups.iter().all(|g| g.kind == DuplicateKind::Exact));
+
+        // Among many unrelated documents only the copies are grouped
+        let mut many: Vec<ProcessedDocument> = (0..200)
+            .map(|i| document(&format!("n{:03}", i), &format!("Note {} on topic {} filed in week {} by team {}.", i, i * 7, i * 13, i * 31)))
+            .collect();
+        many.push(document("copy", &many[42].content.clone()));
+        let report = find_duplicates(&many, 0.8);
+        assert_eq!(report.groups.len(), 1);
+        assert_eq!(report.groups[0].documents, ["copy", "n042"]);
+    }
+
+    #[test]
+    fn test_repeated_chunks_are_grouped_across_documents() {
+        let clause = "All expenses over fifty pounds need a receipt and the approval of a budget holder before they are \
+                      paid. Claims are filed through the finance portal within thirty days of the purchase, and late \
+                      claims are only paid once the head of department has signed them off.";
+        let chunks = vec![
+            crate::testing::fake_chunk("policy", 0, clause),
+            crate::testing::fake_chunk("policy", 1, "Travel is booked through the finance team's agency."),
+            crate::testing::fake_chunk("faq", 0, &clause.replace("fifty", "sixty")),
+            crate::testing::fake_chunk("faq", 1, clause),
+        ];
+        let groups = find_duplicate_chunks(&chunks, 0.5);
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].chunks, ["faq_0", "faq_1", "policy_0"]);
+        assert_eq!(groups[0].documents, ["faq", "faq", "policy"]);
+        assert!(groups[0].similarity >= 0.5 && groups[0].similarity < 1.0);
+    }
+}
@@ -0,0 +1,455 @@
+//! Disk-resident vector index in the style of DiskANN, for corpora whose embeddings don't fit in memory.
+//!
+//! Building links the vectors into a Vamana graph: each node keeps at most `degree` neighbours, which
+//! robust pruning spreads out in direction so a greedy walk reaches any part of the space in few hops.
+//! The index file holds one fixed-size record per node, the full-precision vector followed by its
+//! neighbours, so a node is a single read. Only sign bits of each vector and the chunk ids stay in
+//! memory. A search walks the graph from the medoid: it reads the closest unvisited node on its list,
+//! scores it exactly, and ranks the node's neighbours by their sign bits to decide where to go next.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use crate::quantization::BinaryVector;
+
+const MAGIC: &[u8; 8] = b"RAGDANN1";
+const HEADER_BYTES: u64 = 24;
+
+/// How the graph is built and searched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DiskIndexConfig {
+    /// Most neighbours a node keeps; more means better recall and a bigger file
+    pub degree: usize,
+    /// Candidates considered for a node's neighbours while building
+    pub build_list: usize,
+    /// Candidates kept while searching, and so nodes read from disk per query
+    pub search_list: usize,
+    /// Pruning slack above 1, which keeps some longer edges for faster navigation
+    pub alpha: f32,
+}
+
+impl Default for DiskIndexConfig {
+    fn default() -> Self {
+        Self {
+            degree: 32,
+            build_list: 64,
+            search_list: 64,
+            alpha: 1.2,
+        }
+    }
+}
+
+impl DiskIndexConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// A built index, with each node's vector and neighbours left on disk
+pub struct DiskIndex {
+    path: PathBuf,
+    file: Mutex<File>,
+    dimensions: usize,
+    degree: usize,
+    entry: u32,
+    ids: Vec<String>,
+    positions: HashMap<String, u32>,
+    codes: Vec<BinaryVector>,
+}
+
+impl std::fmt::Debug for DiskIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiskIndex")
+            .field("path", &self.path)
+            .field("vectors", &self.ids.len())
+            .field("dimensions", &self.dimensions)
+            .finish()
+    }
+}
+
+impl DiskIndex {
+    /// Build the graph over `vectors` in memory and write it to `path`, replacing any index there
+    pub fn build(path: &Path, vectors: &HashMap<String, Vec<f32>>, config: &DiskIndexConfig) -> Result<Self> {
+        let mut entries: Vec<(&String, &Vec<f32>)> = vectors.iter().collect();
+        entries.sort_by(|a, b| a.0.cmp(b.0));
+        let dimensions = entries.first().map_or(0, |(_, v)| v.len());
+        if let Some((id, vector)) = entries.iter().find(|(_, v)| v.len() != dimensions) {
+            return Err(anyhow!("Embedding of {} has {} dimensions, expected {}", id, vector.len(), dimensions));
+        }
+        let normalized: Vec<Vec<f32>> = entries.iter().map(|(_, v)| normalize(v)).collect();
+        let (graph, entry) = vamana(&normalized, config);
+
+        let temporary = path.with_extension("tmp");
+        let mut out = BufWriter::new(
+            File::create(&temporary).with_context(|| format!("Failed to create {}", temporary.display()))?,
+        );
+        out.write_all(MAGIC)?;
+        for value in [dimensions, config.degree, normalized.len(), entry as usize] {
+            out.write_all(&(value as u32).to_le_bytes())?;
+        }
+        for (vector, neighbours) in normalized.iter().zip(&graph) {
+            for x in vector {
+                out.write_all(&x.to_le_bytes())?;
+            }
+            out.write_all(&(neighbours.len() as u32).to_le_bytes())?;
+            for slot in 0..config.degree {
+                out.write_all(&neighbours.get(slot).copied().unwrap_or(0).to_le_bytes())?;
+            }
+        }
+        for (id, _) in &entries {
+            out.write_all(&(id.len() as u32).to_le_bytes())?;
+            out.write_all(id.as_bytes())?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        std::fs::rename(&temporary, path)?;
+        Self::open(path)
+    }
+
+    /// Load an index written by `build`, reading every vector once to keep its sign bits
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path).with_context(|| format!("Failed to open disk index {}", path.display()))?;
+        let mut reader = BufReader::new(file.try_clone()?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(anyhow!("{} is not a disk vector index", path.display()));
+        }
+        let mut header = [0u32; 4];
+        for value in &mut header {
+            *value = read_u32(&mut reader)?;
+        }
+        let [dimensions, degree, count, entry] = header.map(|v| v as usize);
+
+        let mut codes = Vec::with_capacity(count);
+        let mut vector = vec![0.0f32; dimensions];
+        for _ in 0..count {
+            for x in &mut vector {
+                *x = f32::from_le_bytes(read_array(&mut reader)?);
+            }
+            codes.push(BinaryVector::quantize(&vector));
+            reader.seek_relative(4 * (1 + degree as i64))?;
+        }
+        let mut ids = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut id = vec![0u8; read_u32(&mut reader)? as usize];
+            reader.read_exact(&mut id)?;
+            ids.push(String::from_utf8(id)?);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            file: Mutex::new(file),
+            dimensions,
+            degree,
+            entry: entry as u32,
+            positions: ids.iter().enumerate().map(|(i, id)| (id.clone(), i as u32)).collect(),
+            ids,
+            codes,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.positions.contains_key(id)
+    }
+
+    /// Bytes the index keeps in memory: sign bits and ids
+    pub fn memory_bytes(&self) -> usize {
+        self.codes.iter().map(|c| c.bits.len() * 8).sum::<usize>() + self.ids.iter().map(String::len).sum::<usize>()
+    }
+
+    /// The full-precision (normalized) vector stored for `id`
+    pub fn vector(&self, id: &str) -> Result<Option<Vec<f32>>> {
+        match self.positions.get(id) {
+            Some(&node) => Ok(Some(self.read_node(node)?.0)),
+            None => Ok(None),
+        }
+    }
+
+    /// Up to `search_list` ids nearest `query`, with their exact cosine similarity, best first
+    pub fn search(&self, query: &[f32], search_list: usize) -> Result<Vec<(String, f32)>> {
+        if self.is_empty() {
+            return Ok(Vec::new());
+        }
+        let query = self.prepare(query)?;
+        self.walk(&query, search_list)
+    }
+
+    /// Cosine similarity of `query` to each of `ids`, 0 for ids the index doesn't hold. Up to
+    /// `search_list` ids are each read and scored exactly, as a search restricted to them would
+    /// otherwise miss them; more go through a graph search, and those it doesn't reach get the
+    /// estimate their sign bits give
+    pub fn similarities(&self, query: &[f32], ids: &[&str], search_list: usize) -> Result<Vec<f32>> {
+        let nodes: Vec<Option<u32>> = ids.iter().map(|id| self.positions.get(*id).copied()).collect();
+        if nodes.iter().all(Option::is_none) {
+            return Ok(vec![0.0; ids.len()]);
+        }
+        let query = self.prepare(query)?;
+        if nodes.iter().flatten().count() <= search_list {
+            return nodes
+                .into_iter()
+                .map(|node| node.map_or(Ok(0.0), |node| self.read_node(node).map(|(v, _)| dot(&query, &v))))
+                .collect();
+        }
+        let found: HashMap<String, f32> = self.walk(&query, search_list)?.into_iter().collect();
+        Ok(ids
+            .iter()
+            .zip(nodes)
+            .map(|(id, node)| match (found.get(*id), node) {
+                (Some(&similarity), _) => similarity,
+                (None, Some(node)) => self.codes[node as usize].cosine(&query),
+                (None, None) => 0.0,
+            })
+            .collect())
+    }
+
+    /// `query` cut to the index's dimensions and normalized; a longer query is a full-dimension
+    /// embedding of a truncated index
+    fn prepare(&self, query: &[f32]) -> Result<Vec<f32>> {
+        if query.len() < self.dimensions {
+            return Err(anyhow!("Query has {} dimensions, the disk index {}", query.len(), self.dimensions));
+        }
+        Ok(normalize(&query[..self.dimensions]))
+    }
+
+    /// Greedy walk from the medoid towards a prepared `query`
+    fn walk(&self, query: &[f32], search_list: usize) -> Result<Vec<(String, f32)>> {
+        let approximate = |node: u32| self.codes[node as usize].cosine(query);
+
+        let mut candidates = vec![(approximate(self.entry), self.entry)];
+        let mut seen = HashSet::from([self.entry]);
+        let mut visited = HashSet::new();
+        let mut scored = Vec::new();
+        while let Some(&(_, node)) = candidates.iter().find(|(_, node)| !visited.contains(node)) {
+            visited.insert(node);
+            let (vector, neighbours) = self.read_node(node)?;
+            scored.push((dot(query, &vector), node));
+            for neighbour in neighbours {
+                if seen.insert(neighbour) {
+                    candidates.push((approximate(neighbour), neighbour));
+                }
+            }
+            candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+            candidates.truncate(search_list.max(1));
+        }
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(search_list);
+        Ok(scored.into_iter().map(|(score, node)| (self.ids[node as usize].clone(), score)).collect())
+    }
+
+    fn read_node(&self, node: u32) -> Result<(Vec<f32>, Vec<u32>)> {
+        let record_bytes = 4 * (self.dimensions + 1 + self.degree);
+        let mut record = vec![0u8; record_bytes];
+        {
+            let mut file = self.file.lock().unwrap();
+            file.seek(SeekFrom::Start(HEADER_BYTES + node as u64 * record_bytes as u64))?;
+            file.read_exact(&mut record)?;
+        }
+        let words: Vec<[u8; 4]> = record.chunks_exact(4).map(|w| [w[0], w[1], w[2], w[3]]).collect();
+        let vector = words[..self.dimensions].iter().map(|w| f32::from_le_bytes(*w)).collect();
+        let count = u32::from_le_bytes(words[self.dimensions]) as usize;
+        let neighbours = words[self.dimensions + 1..][..count.min(self.degree)]
+            .iter()
+            .map(|w| u32::from_le_bytes(*w))
+            .collect();
+        Ok((vector, neighbours))
+    }
+}
+
+/// Out-neighbours of every node, and the node searches start from
+fn vamana(vectors: &[Vec<f32>], config: &DiskIndexConfig) -> (Vec<Vec<u32>>, u32) {
+    let n = vectors.len();
+    if n == 0 {
+        return (Vec::new(), 0);
+    }
+    let degree = config.degree.min(n - 1);
+    let mut random = Xorshift(0x9e37_79b9_7f4a_7c15);
+    let mut graph: Vec<Vec<u32>> = (0..n)
+        .map(|p| {
+            let mut neighbours = Vec::with_capacity(degree);
+            while neighbours.len() < degree {
+                let q = random.below(n) as u32;
+                if q as usize != p && !neighbours.contains(&q) {
+                    neighbours.push(q);
+                }
+            }
+            neighbours
+        })
+        .collect();
+    let entry = medoid(vectors);
+    let mut order: Vec<u32> = (0..n as u32).collect();
+    for i in (1..n).rev() {
+        order.swap(i, random.below(i + 1));
+    }
+
+    let distance = |a: u32, b: u32| 1.0 - dot(&vectors[a as usize], &vectors[b as usize]);
+    let prune = |p: u32, mut candidates: Vec<u32>, alpha: f32| {
+        candidates.sort_unstable();
+        candidates.dedup();
+        candidates.retain(|&c| c != p);
+        candidates.sort_by(|&a, &b| distance(p, a).total_cmp(&distance(p, b)));
+        let mut kept = Vec::with_capacity(config.degree);
+        while let Some(&best) = candidates.first() {
+            kept.push(best);
+            if kept.len() == config.degree {
+                break;
+            }
+            candidates.retain(|&c| alpha * distance(best, c) > distance(p, c));
+        }
+        kept
+    };
+
+    // A first pass without slack, then one with it, as in the Vamana paper
+    for alpha in [1.0, config.alpha] {
+        for &p in &order {
+            let mut candidates = greedy_visit(vectors, &graph, entry, &vectors[p as usize], config.build_list);
+            candidates.extend(&graph[p as usize]);
+            graph[p as usize] = prune(p, candidates, alpha);
+            for q in graph[p as usize].clone() {
+                let back = &mut graph[q as usize];
+                if !back.contains(&p) {
+                    back.push(p);
+                    if back.len() > config.degree {
+                        let candidates = std::mem::take(back);
+                        graph[q as usize] = prune(q, candidates, alpha);
+                    }
+                }
+            }
+        }
+    }
+    (graph, entry)
+}
+
+/// Nodes a greedy walk towards `target` reads, keeping the `list_size` closest candidates
+fn greedy_visit(vectors: &[Vec<f32>], graph: &[Vec<u32>], entry: u32, target: &[f32], list_size: usize) -> Vec<u32> {
+    let similarity = |node: u32| dot(target, &vectors[node as usize]);
+    let mut candidates = vec![(similarity(entry), entry)];
+    let mut seen = HashSet::from([entry]);
+    let mut visited = Vec::new();
+    while let Some(&(_, node)) = candidates.iter().find(|(_, node)| !visited.contains(node)) {
+        visited.push(node);
+        for &neighbour in &graph[node as usize] {
+            if seen.insert(neighbour) {
+                candidates.push((similarity(neighbour), neighbour));
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates.truncate(list_size.max(1));
+    }
+    visited
+}
+
+/// Node closest to the mean of all vectors
+fn medoid(vectors: &[Vec<f32>]) -> u32 {
+    let mut mean = vec![0.0f32; vectors[0].len()];
+    for vector in vectors {
+        mean.iter_mut().zip(vector).for_each(|(m, x)| *m += x);
+    }
+    (0..vectors.len())
+        .max_by(|&a, &b| dot(&mean, &vectors[a]).total_cmp(&dot(&mean, &vectors[b])))
+        .unwrap_or(0) as u32
+}
+
+pub(crate) fn normalize(vector: &[f32]) -> Vec<f32> {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        vector.to_vec()
+    } else {
+        vector.iter().map(|x| x / norm).collect()
+    }
+}
+
+pub(crate) fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn read_array(reader: &mut impl Read) -> Result<[u8; 4]> {
+    let mut bytes = [0u8; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u32(reader: &mut impl Read) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+/// Deterministic generator for random choices while building indexes, so builds are reproducible
+pub(crate) struct Xorshift(pub(crate) u64);
+
+impl Xorshift {
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::cosine_similarity;
+
+    #[test]
+    fn test_disk_index_finds_nearest_neighbours() {
+        let mut random = Xorshift(42);
+        let vectors: HashMap<String, Vec<f32>> = (0..300)
+            .map(|i| (format!("c{}", i), (0..16).map(|_| random.below(2001) as f32 / 1000.0 - 1.0).collect()))
+            .collect();
+        let path = std::env::temp_dir().join(format!("rag_disk_index_{}.dann", std::process::id()));
+        let config = DiskIndexConfig { degree: 12, build_list: 32, search_list: 32, alpha: 1.2 };
+        let index = DiskIndex::build(&path, &vectors, &config).unwrap();
+        assert_eq!((index.len(), index.dimensions()), (300, 16));
+        assert!(index.memory_bytes() < 300 * 16 * 4 / 4);
+
+        let mut hits = 0;
+        for q in 0..20 {
+            let query: Vec<f32> = (0..16).map(|_| random.below(2001) as f32 / 1000.0 - 1.0).collect();
+            let mut exact: Vec<(&String, f32)> =
+                vectors.iter().map(|(id, v)| (id, cosine_similarity(&query, v))).collect();
+            exact.sort_by(|a, b| b.1.total_cmp(&a.1));
+            let found = index.search(&query, 32).unwrap();
+            assert!((found[0].1 - cosine_similarity(&query, &vectors[&found[0].0])).abs() < 1e-5, "query {}", q);
+            hits += exact.iter().take(5).filter(|(id, _)| found.iter().take(10).any(|(f, _)| f == *id)).count();
+        }
+        assert!(hits >= 90, "recall@10 of the true top 5 was {} of 100", hits);
+
+        let reopened = DiskIndex::open(&path).unwrap();
+        let stored = reopened.vector("c7").unwrap().unwrap();
+        assert!((cosine_similarity(&stored, &vectors["c7"]) - 1.0).abs() < 1e-5);
+        assert!(reopened.vector("missing").unwrap().is_none());
+
+        // A few ids are scored exactly wherever they rank; many fall back to sign estimates past the walk
+        let query: Vec<f32> = (0..16).map(|_| random.below(2001) as f32 / 1000.0 - 1.0).collect();
+        let few = reopened.similarities(&query, &["c7", "missing", "c250"], 32).unwrap();
+        assert!((few[0] - cosine_similarity(&query, &vectors["c7"])).abs() < 1e-5);
+        assert!(few[1] == 0.0 && (few[2] - cosine_similarity(&query, &vectors["c250"])).abs() < 1e-5);
+        let ids: Vec<String> = (0..300).map(|i| format!("c{}", i)).collect();
+        let many = reopened.similarities(&query, &ids.iter().map(String::as_str).collect::<Vec<_>>(), 8).unwrap();
+        let walked = reopened.search(&query, 8).unwrap();
+        assert!(walked.iter().all(|(id, similarity)| (many[ids.iter().position(|i| i == id).unwrap()] - similarity).abs() < 1e-6));
+        let missed = (0..300).find(|&i| !walked.iter().any(|(id, _)| *id == ids[i])).unwrap();
+        let code = &reopened.codes[reopened.positions[&ids[missed]] as usize];
+        assert_eq!(many[missed], code.cosine(&normalize(&query)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
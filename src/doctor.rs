@@ -0,0 +1,287 @@
+//! Health checks behind `rag-system doctor`
+
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::config::{ApiKeys, ChunkingKind, RagConfig};
+use crate::embedding::EmbeddingProvider;
+use crate::llm::ProviderKind;
+use crate::storage::{GcReport, IntegrityReport};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+}
+
+/// Outcome of one check, with what to do about it when it did not pass
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub check: String,
+    pub status: CheckStatus,
+    pub message: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn ok(check: &str, message: impl Into<String>) -> Self {
+        Self::new(check, CheckStatus::Ok, message.into(), None)
+    }
+
+    pub fn warning(check: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(check, CheckStatus::Warning, message.into(), Some(hint.into()))
+    }
+
+    pub fn error(check: &str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Self::new(check, CheckStatus::Error, message.into(), Some(hint.into()))
+    }
+
+    fn new(check: &str, status: CheckStatus, message: String, hint: Option<String>) -> Self {
+        Self {
+            check: check.to_string(),
+            status,
+            message,
+            hint,
+        }
+    }
+}
+
+/// Whether a key for `kind` is in the config or the environment; Ollama needs none
+fn has_api_key(kind: ProviderKind, keys: &ApiKeys) -> bool {
+    match kind {
+        ProviderKind::OpenAi => keys.openai.is_some() || std::env::var("OPENAI_API_KEY").is_ok(),
+        ProviderKind::Anthropic => keys.anthropic.is_some() || std::env::var("ANTHROPIC_API_KEY").is_ok(),
+        ProviderKind::Ollama => true,
+    }
+}
+
+fn missing_key_hint(kind: ProviderKind) -> String {
+    let (var, field) = match kind {
+        ProviderKind::Anthropic => ("ANTHROPIC_API_KEY", "anthropic"),
+        _ => ("OPENAI_API_KEY", "openai"),
+    };
+    format!("Set {} or `{}` under [api_keys] in the config file", var, field)
+}
+
+/// Settings that cannot work together, or credentials the configured providers will ask for
+pub fn check_config(config: &RagConfig) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+
+    if config.chunking.strategy == ChunkingKind::Fixed && config.chunking.size == 0 {
+        diagnostics.push(Diagnostic::error(
+            "config",
+            "chunking.size is 0, so fixed-size chunking produces no chunks",
+            "Set chunking.size to a positive number of words, e.g. 500",
+        ));
+    }
+
+    if let Some(generation) = &config.generation {
+        if !has_api_key(generation.provider, &config.api_keys) {
+            diagnostics.push(Diagnostic::error(
+                "config",
+                format!("generation uses {:?} but no API key is available", generation.provider),
+                missing_key_hint(generation.provider),
+            ));
+        }
+    }
+
+    match &config.embedding {
+        Some(embedding) if embedding.provider.default_embedding_model().is_none() => {
+            diagnostics.push(Diagnostic::error(
+                "config",
+                format!("{:?} has no embedding API", embedding.provider),
+                "Set embedding.provider to openai or ollama",
+            ));
+        }
+        Some(embedding) if !has_api_key(embedding.provider, &config.api_keys) => {
+            diagnostics.push(Diagnostic::error(
+                "config",
+                format!("embedding uses {:?} but no API key is available", embedding.provider),
+                missing_key_hint(embedding.provider),
+            ));
+        }
+        None if config.search.mode.uses_embeddings() => {
+            diagnostics.push(Diagnostic::warning(
+                "config",
+                format!("search.mode is {:?} but no [embedding] section is configured", config.search.mode),
+                "Add an [embedding] section, or pass --embedding-model when the index isn't embedded yet",
+            ));
+        }
+        _ => {}
+    }
+
+    if diagnostics.is_empty() {
+        diagnostics.push(Diagnostic::ok("config", "settings are consistent"));
+    }
+    diagnostics
+}
+
+/// Whether the index file can be read now and written back later
+pub fn check_index_file(path: &Path) -> Diagnostic {
+    if !path.exists() {
+        let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        return match std::fs::create_dir_all(parent) {
+            Ok(()) if !is_readonly(parent) => Diagnostic::ok(
+                "storage",
+                format!("{} does not exist yet and will be created on first write", path.display()),
+            ),
+            _ => Diagnostic::error(
+                "storage",
+                format!("cannot create {}", parent.display()),
+                "Check the directory permissions, or point --index or storage.path somewhere writable",
+            ),
+        };
+    }
+    if is_readonly(path) {
+        return Diagnostic::error(
+            "storage",
+            format!("{} is read-only, so changes cannot be saved", path.display()),
+            "Check the file permissions, or point --index or storage.path somewhere writable",
+        );
+    }
+    Diagnostic::ok("storage", format!("{} is readable and writable", path.display()))
+}
+
+fn is_readonly(path: &Path) -> bool {
+    std::fs::metadata(path).map(|m| m.permissions().readonly()).unwrap_or(true)
+}
+
+pub fn check_integrity(report: &IntegrityReport, documents: usize, chunks: usize) -> Vec<Diagnostic> {
+    if report.is_clean() {
+        return vec![Diagnostic::ok(
+            "integrity",
+            format!("{} documents and {} chunks are consistent", documents, chunks),
+        )];
+    }
+
+    let rebuild = "Run `rag-system reindex` to rebuild chunks and embeddings from the stored documents";
+    let mut diagnostics = Vec::new();
+    for (ids, problem, hint) in [
+        (&report.orphan_chunks, "chunks belong to missing documents", rebuild),
+        (
+            &report.documents_without_chunks,
+            "documents have no chunks and can never be retrieved",
+            rebuild,
+        ),
+        (&report.orphan_embeddings, "embeddings belong to missing chunks", rebuild),
+        (
+            &report.unembedded_chunks,
+            "chunks have no embedding and are invisible to vector search",
+            "Run `rag-system reindex` with the index's embedding model to embed them",
+        ),
+    ] {
+        if !ids.is_empty() {
+            diagnostics.push(Diagnostic::warning(
+                "integrity",
+                format!("{} {} (e.g. {})", ids.len(), problem, ids[0]),
+                hint,
+            ));
+        }
+    }
+    diagnostics
+}
+
+/// What garbage collection removed, and whether the smaller index could be saved
+pub fn check_gc(report: &GcReport, saved: anyhow::Result<()>) -> Diagnostic {
+    if report.is_empty() {
+        return Diagnostic::ok("gc", "no orphan chunks or embeddings");
+    }
+    let removed = format!(
+        "removed {} orphan chunks and {} orphan embeddings ({} bytes)",
+        report.chunks, report.embeddings, report.bytes
+    );
+    match saved {
+        Ok(()) => Diagnostic::ok("gc", removed),
+        Err(e) => Diagnostic::warning(
+            "gc",
+            format!("{}, but the index could not be saved: {}", removed, e),
+            "Check that the index file is writable; they will be collected again next time",
+        ),
+    }
+}
+
+/// Embed a probe text to confirm the provider is reachable and returns vectors of the expected size
+pub fn check_embedding_provider(embedder: &dyn EmbeddingProvider, index_model: Option<&str>) -> Diagnostic {
+    let model = embedder.model_name();
+    if let Some(index_model) = index_model.filter(|m| *m != model) {
+        return Diagnostic::error(
+            "embedding",
+            format!("the index is embedded with {}, but {} is configured", index_model, model),
+            format!(
+                "Pass --embedding-model {}, or run `rag-system reindex --embed-model {}`",
+                index_model, model
+            ),
+        );
+    }
+    match embedder.embed(&["rag-system doctor".to_string()]) {
+        Ok(vectors) => match vectors.first() {
+            Some(vector) if embedder.dimensions() == 0 || vector.len() == embedder.dimensions() => {
+                Diagnostic::ok("embedding", format!("{} returned a {}-dimensional embedding", model, vector.len()))
+            }
+            Some(vector) => Diagnostic::warning(
+                "embedding",
+                format!("{} returned {} dimensions, expected {}", model, vector.len(), embedder.dimensions()),
+                "Check that the model name matches the model the provider actually serves",
+            ),
+            None => Diagnostic::error(
+                "embedding",
+                format!("{} returned no embedding", model),
+                "Check the provider's status and the model name",
+            ),
+        },
+        Err(e) => Diagnostic::error(
+            "embedding",
+            format!("{} is unreachable: {}", model, e),
+            "Check the API key and network, or for Ollama that `ollama serve` is running and the model is pulled",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EmbeddingSettings;
+
+    #[test]
+    fn test_config_checks_flag_unworkable_settings() {
+        let mut config = RagConfig::default();
+        assert_eq!(check_config(&config)[0].status, CheckStatus::Ok);
+
+        config.chunking.size = 0;
+        config.embedding = Some(EmbeddingSettings {
+            provider: ProviderKind::Anthropic,
+            model: None,
+            config: Default::default(),
+        });
+        let diagnostics = check_config(&config);
+
+        assert_eq!(diagnostics.len(), 2);
+        assert!(diagnostics.iter().all(|d| d.status == CheckStatus::Error && d.hint.is_some()));
+        assert!(diagnostics[1].message.contains("no embedding API"));
+    }
+
+    #[test]
+    fn test_integrity_problems_point_to_reindex() {
+        let clean = check_integrity(&IntegrityReport::default(), 2, 5);
+        assert_eq!(clean[0].message, "2 documents and 5 chunks are consistent");
+
+        let report = IntegrityReport {
+            orphan_chunks: vec!["doc_0".to_string(), "doc_1".to_string()],
+            ..Default::default()
+        };
+        let diagnostics = check_integrity(&report, 0, 2);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].message, "2 chunks belong to missing documents (e.g. doc_0)");
+        assert!(diagnostics[0].hint.as_deref().unwrap().contains("reindex"));
+    }
+
+    #[test]
+    fn test_gc_reports_what_it_reclaimed() {
+        assert_eq!(check_gc(&GcReport::default(), Ok(())).message, "no orphan chunks or embeddings");
+        let report = GcReport { chunks: 2, embeddings: 3, bytes: 40 };
+        assert_eq!(check_gc(&report, Ok(())).message, "removed 2 orphan chunks and 3 orphan embeddings (40 bytes)");
+        assert_eq!(check_gc(&report, Err(anyhow::anyhow!("read-only"))).status, CheckStatus::Warning);
+    }
+}
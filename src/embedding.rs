@@ -0,0 +1,604 @@
+//! Text embedding abstraction for vector search
+
+use anyhow::{anyhow, Result};
+#[cfg(any(feature = "openai", feature = "ollama"))]
+use rig::client::EmbeddingsClient;
+#[cfg(feature = "rig")]
+use rig::embeddings::{EmbeddingError, EmbeddingModel};
+#[cfg(feature = "ollama")]
+use rig::providers::ollama;
+#[cfg(feature = "openai")]
+use rig::providers::openai;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+#[cfg(feature = "rig")]
+use std::time::Duration;
+#[cfg(feature = "rig")]
+use tokio::sync::{Mutex, Semaphore};
+#[cfg(feature = "rig")]
+use tokio::task::JoinSet;
+#[cfg(feature = "rig")]
+use tokio::time::Instant;
+#[cfg(feature = "rig")]
+use crate::llm::block_on;
+#[cfg(feature = "ollama")]
+use crate::llm::ollama_base_url;
+#[cfg(feature = "ollama")]
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::llm::{BoxFuture, ProviderKind};
+
+/// Anything that can turn texts into fixed-size vectors
+pub trait EmbeddingProvider: Send + Sync {
+    fn model_name(&self) -> &str;
+
+    fn dimensions(&self) -> usize;
+
+    /// One vector per input text, in input order
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>>;
+
+    /// `embed` without blocking a thread. The default runs the blocking call in place,
+    /// so providers that wait on the network should override it
+    fn embed_async<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move { self.embed(texts) })
+    }
+}
+
+impl<P: EmbeddingProvider + ?Sized> EmbeddingProvider for Arc<P> {
+    fn model_name(&self) -> &str {
+        (**self).model_name()
+    }
+
+    fn dimensions(&self) -> usize {
+        (**self).dimensions()
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        (**self).embed(texts)
+    }
+
+    fn embed_async<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        (**self).embed_async(texts)
+    }
+}
+
+/// Throughput knobs for embedding large batches of chunks
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingConfig {
+    /// Texts sent per request, capped at what the model accepts
+    pub batch_size: usize,
+    /// Requests in flight at once
+    pub concurrency: usize,
+    /// Retries on rate limits and transient failures; the delay doubles after each attempt
+    pub max_retries: u32,
+    pub retry_delay_ms: u64,
+    /// Cap on requests started per minute, for accounts with tight provider limits
+    pub requests_per_minute: Option<u32>,
+    /// Where a local model runs; only Ollama can choose
+    pub device: EmbeddingDevice,
+}
+
+/// Hardware a local embedding model runs on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingDevice {
+    /// Whatever the model server picks
+    #[default]
+    Auto,
+    Cpu,
+    /// Every layer on the GPU, falling back to the CPU for good once the GPU fails a request
+    Gpu,
+}
+
+impl EmbeddingDevice {
+    /// Ollama's `num_gpu` option, the number of layers offloaded to the GPU; 0 keeps the model on the CPU
+    #[cfg(feature = "ollama")]
+    fn ollama_gpu_layers(self) -> Option<u32> {
+        match self {
+            EmbeddingDevice::Auto => None,
+            EmbeddingDevice::Cpu => Some(0),
+            EmbeddingDevice::Gpu => Some(OLLAMA_ALL_LAYERS),
+        }
+    }
+}
+
+/// More layers than any model has, which Ollama reads as all of them
+#[cfg(feature = "ollama")]
+const OLLAMA_ALL_LAYERS: u32 = 999;
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            batch_size: 256,
+            concurrency: 4,
+            max_retries: 5,
+            retry_delay_ms: 500,
+            requests_per_minute: None,
+            device: EmbeddingDevice::Auto,
+        }
+    }
+}
+
+/// Called with (texts embedded so far, total texts) as batches complete
+pub type ProgressCallback = Arc<dyn Fn(usize, usize) + Send + Sync>;
+
+/// Embedding provider backed by any rig embedding model
+#[cfg(feature = "rig")]
+pub struct RigEmbeddingProvider<M> {
+    model: M,
+    model_name: String,
+    config: EmbeddingConfig,
+    progress: Option<ProgressCallback>,
+}
+
+#[cfg(feature = "rig")]
+impl<M: EmbeddingModel + 'static> RigEmbeddingProvider<M> {
+    pub fn new(model: M, model_name: impl Into<String>) -> Self {
+        Self {
+            model,
+            model_name: model_name.into(),
+            config: EmbeddingConfig::default(),
+            progress: None,
+        }
+    }
+
+    pub fn with_config(mut self, config: EmbeddingConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.config.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_retries(mut self, max_retries: u32, initial_delay: Duration) -> Self {
+        self.config.max_retries = max_retries;
+        self.config.retry_delay_ms = initial_delay.as_millis() as u64;
+        self
+    }
+
+    pub fn with_progress(mut self, progress: ProgressCallback) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    async fn embed_batches(&self, batches: Vec<Vec<String>>, total: usize) -> Result<Vec<Vec<f32>>> {
+        let semaphore = Arc::new(Semaphore::new(self.config.concurrency.max(1)));
+        let limiter = Arc::new(RateLimiter::new(self.config.requests_per_minute));
+        let mut tasks = JoinSet::new();
+        let batch_count = batches.len();
+
+        for (index, batch) in batches.into_iter().enumerate() {
+            let model = self.model.clone();
+            let model_name = self.model_name.clone();
+            let config = self.config.clone();
+            let semaphore = semaphore.clone();
+            let limiter = limiter.clone();
+            tasks.spawn(async move {
+                let _permit = semaphore.acquire_owned().await?;
+                let vectors = embed_with_retry(&model, &model_name, batch, &config, &limiter).await?;
+                Ok::<_, anyhow::Error>((index, vectors))
+            });
+        }
+
+        let mut results: Vec<Option<Vec<Vec<f32>>>> = vec![None; batch_count];
+        let mut embedded = 0;
+        while let Some(joined) = tasks.join_next().await {
+            let (index, vectors) = joined??;
+            embedded += vectors.len();
+            if let Some(progress) = &self.progress {
+                progress(embedded, total);
+            }
+            results[index] = Some(vectors);
+        }
+        Ok(results.into_iter().flatten().flatten().collect())
+    }
+}
+
+#[cfg(feature = "rig")]
+async fn embed_with_retry<M: EmbeddingModel>(
+    model: &M,
+    model_name: &str,
+    batch: Vec<String>,
+    config: &EmbeddingConfig,
+    limiter: &RateLimiter,
+) -> Result<Vec<Vec<f32>>> {
+    let mut delay = Duration::from_millis(config.retry_delay_ms);
+    let mut attempt = 0;
+    loop {
+        limiter.wait().await;
+        match model.embed_texts(batch.clone()).await {
+            Ok(embeddings) => {
+                return Ok(embeddings
+                    .into_iter()
+                    .map(|e| e.vec.into_iter().map(|v| v as f32).collect())
+                    .collect())
+            }
+            Err(err) if attempt < config.max_retries && is_retryable(&err) => {
+                tracing::warn!("{} embedding request failed ({}), retrying in {:?}", model_name, err, delay);
+                tokio::time::sleep(delay).await;
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(err) => return Err(anyhow!("{} embedding failed: {}", model_name, err)),
+        }
+    }
+}
+
+/// Spaces request starts evenly to stay under a requests-per-minute limit
+#[cfg(feature = "rig")]
+struct RateLimiter {
+    interval: Option<Duration>,
+    next_slot: Mutex<Instant>,
+}
+
+#[cfg(feature = "rig")]
+impl RateLimiter {
+    fn new(requests_per_minute: Option<u32>) -> Self {
+        Self {
+            interval: requests_per_minute
+                .filter(|&rpm| rpm > 0)
+                .map(|rpm| Duration::from_secs(60) / rpm),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    async fn wait(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        // Holding the lock while sleeping queues callers in order
+        let mut next_slot = self.next_slot.lock().await;
+        let now = Instant::now();
+        if *next_slot > now {
+            tokio::time::sleep_until(*next_slot).await;
+        }
+        *next_slot = (*next_slot).max(now) + interval;
+    }
+}
+
+#[cfg(feature = "openai")]
+impl RigEmbeddingProvider<openai::EmbeddingModel> {
+    /// OpenAI embedding model such as `text-embedding-3-small`, with the API key read from `OPENAI_API_KEY`
+    pub fn openai(model_name: &str) -> Result<Self> {
+        let api_key = std::env::var("OPENAI_API_KEY").map_err(|_| anyhow!("OPENAI_API_KEY is not set"))?;
+        Ok(Self::openai_with_key(model_name, &api_key))
+    }
+
+    pub fn openai_with_key(model_name: &str, api_key: &str) -> Self {
+        let client = openai::Client::new(api_key);
+        Self::new(client.embedding_model(model_name), model_name)
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl RigEmbeddingProvider<OllamaEmbeddingModel> {
+    /// Embedding model served by a local Ollama instance, e.g. `nomic-embed-text`
+    pub fn ollama(model_name: &str) -> Result<Self> {
+        Self::ollama_on(model_name, EmbeddingDevice::Auto)
+    }
+
+    /// `ollama` with the model run on `device`
+    pub fn ollama_on(model_name: &str, device: EmbeddingDevice) -> Result<Self> {
+        let model = OllamaEmbeddingModel::new(&ollama_base_url(), model_name, device)?;
+        Ok(Self::new(model, model_name))
+    }
+}
+
+/// Ollama's `/api/embed`, asking for the model on a device; rig's own Ollama model sends no options
+#[cfg(feature = "ollama")]
+#[derive(Clone)]
+pub struct OllamaEmbeddingModel {
+    http: reqwest::Client,
+    url: String,
+    model: String,
+    ndims: usize,
+    device: EmbeddingDevice,
+    /// Set once the GPU has failed, so later requests go straight to the CPU
+    on_cpu: Arc<AtomicBool>,
+}
+
+#[cfg(feature = "ollama")]
+impl OllamaEmbeddingModel {
+    pub fn new(base_url: &str, model: &str, device: EmbeddingDevice) -> Result<Self> {
+        let ndims = match model.split(':').next().unwrap_or(model) {
+            ollama::NOMIC_EMBED_TEXT => 768,
+            ollama::ALL_MINILM => 384,
+            "mxbai-embed-large" => 1024,
+            // Unknown models report 0 until the first vector comes back
+            _ => 0,
+        };
+        Ok(Self {
+            http: reqwest::Client::builder().build()?,
+            url: format!("{}/api/embed", base_url.trim_end_matches('/')),
+            model: model.to_string(),
+            ndims,
+            device,
+            on_cpu: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// Device requests run on now: the configured one, until the GPU has failed
+    pub fn device(&self) -> EmbeddingDevice {
+        if self.on_cpu.load(Ordering::SeqCst) {
+            EmbeddingDevice::Cpu
+        } else {
+            self.device
+        }
+    }
+
+    async fn request(&self, texts: &[String], device: EmbeddingDevice) -> Result<Vec<Vec<f64>>, EmbeddingError> {
+        #[derive(Deserialize)]
+        struct EmbedResponse {
+            embeddings: Vec<Vec<f64>>,
+        }
+        let mut payload = serde_json::json!({ "model": self.model, "input": texts });
+        if let Some(layers) = device.ollama_gpu_layers() {
+            payload["options"] = serde_json::json!({ "num_gpu": layers });
+        }
+        let response = self.http.post(&self.url).json(&payload).send().await?;
+        if !response.status().is_success() {
+            return Err(EmbeddingError::ProviderError(response.text().await?));
+        }
+        let embeddings = response.json::<EmbedResponse>().await?.embeddings;
+        if embeddings.len() != texts.len() {
+            return Err(EmbeddingError::ResponseError("Number of returned embeddings does not match input".into()));
+        }
+        Ok(embeddings)
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl EmbeddingModel for OllamaEmbeddingModel {
+    const MAX_DOCUMENTS: usize = 1024;
+
+    fn ndims(&self) -> usize {
+        self.ndims
+    }
+
+    async fn embed_texts(
+        &self,
+        texts: impl IntoIterator<Item = String> + Send,
+    ) -> Result<Vec<rig::embeddings::Embedding>, EmbeddingError> {
+        let texts: Vec<String> = texts.into_iter().collect();
+        let device = self.device();
+        let vectors = match self.request(&texts, device).await {
+            // A model too big for the GPU, or a server without one, is an error from the server itself
+            Err(EmbeddingError::ProviderError(message)) if device == EmbeddingDevice::Gpu => {
+                tracing::warn!("{} failed on the GPU ({}), embedding on the CPU from now on", self.model, message);
+                self.on_cpu.store(true, Ordering::SeqCst);
+                self.request(&texts, EmbeddingDevice::Cpu).await?
+            }
+            vectors => vectors?,
+        };
+        Ok(texts.into_iter().zip(vectors).map(|(document, vec)| rig::embeddings::Embedding { document, vec }).collect())
+    }
+}
+
+/// Embedding provider for `model` on the given host
+#[cfg_attr(not(any(feature = "openai", feature = "ollama")), allow(unused_variables))]
+pub fn embedding_provider(
+    kind: ProviderKind,
+    model: &str,
+    config: &EmbeddingConfig,
+    progress: Option<ProgressCallback>,
+) -> Result<Arc<dyn EmbeddingProvider>> {
+    #[cfg(any(feature = "openai", feature = "ollama"))]
+    fn configure<M: EmbeddingModel + 'static>(
+        provider: RigEmbeddingProvider<M>,
+        config: &EmbeddingConfig,
+        progress: Option<ProgressCallback>,
+    ) -> Arc<dyn EmbeddingProvider> {
+        let provider = provider.with_config(config.clone());
+        Arc::new(match progress {
+            Some(progress) => provider.with_progress(progress),
+            None => provider,
+        })
+    }
+
+    match kind {
+        #[cfg(feature = "openai")]
+        ProviderKind::OpenAi => {
+            if config.device != EmbeddingDevice::Auto {
+                tracing::warn!("Ignoring embedding device {:?}: OpenAI runs its models itself", config.device);
+            }
+            Ok(configure(RigEmbeddingProvider::openai(model)?, config, progress))
+        }
+        #[cfg(feature = "ollama")]
+        ProviderKind::Ollama => Ok(configure(RigEmbeddingProvider::ollama_on(model, config.device)?, config, progress)),
+        ProviderKind::Anthropic => Err(anyhow!("Anthropic has no embedding API; embed with openai or ollama")),
+        #[allow(unreachable_patterns)]
+        kind => Err(kind.missing_feature()),
+    }
+}
+
+#[cfg(feature = "rig")]
+impl<M: EmbeddingModel + 'static> EmbeddingProvider for RigEmbeddingProvider<M> {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn dimensions(&self) -> usize {
+        self.model.ndims()
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        block_on(self.embed_async(texts))?
+    }
+
+    fn embed_async<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        let batch_size = self.config.batch_size.clamp(1, M::MAX_DOCUMENTS);
+        let batches: Vec<Vec<String>> = texts.chunks(batch_size).map(<[String]>::to_vec).collect();
+        tracing::debug!("Embedding {} texts in {} requests with {}", texts.len(), batches.len(), self.model_name);
+        Box::pin(self.embed_batches(batches, texts.len()))
+    }
+}
+
+/// Rate limits and network hiccups are worth retrying; bad input or auth is not
+#[cfg(feature = "rig")]
+fn is_retryable(err: &EmbeddingError) -> bool {
+    match err {
+        EmbeddingError::HttpError(err) => err.is_timeout() || err.is_connect(),
+        EmbeddingError::ProviderError(message) => {
+            let message = message.to_lowercase();
+            message.contains("rate limit") || message.contains("rate_limit") || message.contains("overloaded")
+        }
+        _ => false,
+    }
+}
+
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_similarity() {
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 3.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 1.0]), 0.0);
+    }
+
+    #[cfg(feature = "rig")]
+    #[test]
+    fn test_rate_limit_errors_are_retried() {
+        assert!(is_retryable(&EmbeddingError::ProviderError("Rate limit reached for text-embedding-3-small".to_string())));
+        assert!(!is_retryable(&EmbeddingError::ProviderError("Incorrect API key provided".to_string())));
+    }
+
+    /// Embeds each text as its length, failing the first request with a rate limit
+    #[cfg(feature = "rig")]
+    #[derive(Clone, Default)]
+    struct FlakyModel {
+        requests: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    #[cfg(feature = "rig")]
+    impl EmbeddingModel for FlakyModel {
+        const MAX_DOCUMENTS: usize = 2;
+
+        fn ndims(&self) -> usize {
+            1
+        }
+
+        fn embed_texts(
+            &self,
+            texts: impl IntoIterator<Item = String> + Send,
+        ) -> impl std::future::Future<Output = Result<Vec<rig::embeddings::Embedding>, EmbeddingError>> + Send {
+            let texts: Vec<String> = texts.into_iter().collect();
+            let request = self.requests.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            async move {
+                if request == 0 {
+                    return Err(EmbeddingError::ProviderError("Rate limit reached".to_string()));
+                }
+                Ok(texts
+                    .into_iter()
+                    .map(|text| rig::embeddings::Embedding { vec: vec![text.len() as f64], document: text })
+                    .collect())
+            }
+        }
+    }
+
+    #[cfg(feature = "rig")]
+    #[test]
+    fn test_batches_run_concurrently_in_order_with_retry() {
+        let model = FlakyModel::default();
+        let progress = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = progress.clone();
+        let provider = RigEmbeddingProvider::new(model.clone(), "flaky")
+            .with_config(EmbeddingConfig {
+                batch_size: 10,
+                concurrency: 3,
+                retry_delay_ms: 1,
+                ..EmbeddingConfig::default()
+            })
+            .with_progress(Arc::new(move |done, total| recorder.lock().unwrap().push((done, total))));
+
+        let texts: Vec<String> = (1..=5).map(|n| "x".repeat(n)).collect();
+        let vectors = provider.embed(&texts).unwrap();
+
+        assert_eq!(vectors, vec![vec![1.0], vec![2.0], vec![3.0], vec![4.0], vec![5.0]]);
+        // Batch size is capped at the model's two documents: three batches plus one retry
+        assert_eq!(model.requests.load(std::sync::atomic::Ordering::SeqCst), 4);
+        assert_eq!(progress.lock().unwrap().last(), Some(&(5, 5)));
+    }
+
+    #[cfg(feature = "rig")]
+    #[tokio::test]
+    async fn test_embed_async_awaits_inside_a_current_thread_runtime() {
+        // The blocking `embed` cannot run here, since block_in_place needs a multi-threaded runtime
+        let model = FlakyModel::default();
+        let provider = RigEmbeddingProvider::new(model.clone(), "flaky").with_config(EmbeddingConfig {
+            retry_delay_ms: 1,
+            ..EmbeddingConfig::default()
+        });
+
+        let texts: Vec<String> = (1..=3).map(|n| "x".repeat(n)).collect();
+        let vectors = provider.embed_async(&texts).await.unwrap();
+
+        assert_eq!(vectors, vec![vec![1.0], vec![2.0], vec![3.0]]);
+    }
+
+    /// Answer one request on `listener` with `status` and `body`, returning the request's JSON body
+    #[cfg(feature = "ollama")]
+    fn respond(listener: &std::net::TcpListener, status: &str, body: &str) -> serde_json::Value {
+        use std::io::{BufRead, BufReader, Read, Write};
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+        }
+        let mut request = vec![0; length];
+        reader.read_exact(&mut request).unwrap();
+        let response = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}", status, body.len(), body);
+        stream.write_all(response.as_bytes()).unwrap();
+        serde_json::from_slice(&request).unwrap()
+    }
+
+    #[cfg(feature = "ollama")]
+    #[test]
+    fn test_ollama_falls_back_to_cpu_when_the_gpu_fails() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let embedded = r#"{"embeddings": [[0.5, 0.25]]}"#;
+            vec![
+                respond(&listener, "500 Internal Server Error", r#"{"error": "CUDA out of memory"}"#),
+                respond(&listener, "200 OK", embedded),
+                respond(&listener, "200 OK", embedded),
+            ]
+        });
+        let model = OllamaEmbeddingModel::new(&base_url, "nomic-embed-text", EmbeddingDevice::Gpu).unwrap();
+        let provider = RigEmbeddingProvider::new(model.clone(), "nomic-embed-text");
+
+        assert_eq!(provider.embed(&["leave".to_string()]).unwrap(), vec![vec![0.5, 0.25]]);
+        assert_eq!(model.device(), EmbeddingDevice::Cpu);
+        provider.embed(&["pay".to_string()]).unwrap();
+        let layers: Vec<_> = server.join().unwrap().iter().map(|request| request["options"]["num_gpu"].as_u64()).collect();
+        assert_eq!(layers, [Some(999), Some(0), Some(0)]);
+
+        let auto = OllamaEmbeddingModel::new(&base_url, "nomic-embed-text", EmbeddingDevice::Auto).unwrap();
+        assert_eq!((auto.device(), EmbeddingDevice::Auto.ollama_gpu_layers()), (EmbeddingDevice::Auto, None));
+        let config: EmbeddingConfig = toml::from_str("device = \"gpu\"").unwrap();
+        assert_eq!(config.device, EmbeddingDevice::Gpu);
+    }
+}
@@ -0,0 +1,207 @@
+//! Lightweight named entity recognition run on each chunk at ingest.
+//!
+//! Runs of capitalized words are classified by the words around them: an honorific marks a person,
+//! a suffix such as `Inc` or `University` an organization, a preposition such as `in` a location.
+//! It is rule-based so it costs nothing per chunk, and it misses plenty; it is meant for filtering
+//! and boosting, not for building a complete catalogue.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+const HONORIFICS: &[&str] = &["mr", "mrs", "ms", "dr", "prof", "professor", "sir", "dame", "lord", "lady"];
+
+const ORGANIZATION_SUFFIXES: &[&str] = &[
+    "inc", "ltd", "llc", "corp", "corporation", "company", "co", "group", "university", "institute",
+    "foundation", "labs", "laboratory", "agency", "bank", "association", "society", "council", "college",
+];
+
+const LOCATION_SUFFIXES: &[&str] = &[
+    "park", "street", "road", "city", "river", "mountain", "lake", "island", "county", "valley", "bay",
+];
+
+const LOCATION_PREPOSITIONS: &[&str] = &["in", "near", "from", "across", "around"];
+
+/// Capitalized words that start a sentence without naming anything
+const LEADING_STOPWORDS: &[&str] = &[
+    "the", "a", "an", "this", "that", "these", "those", "it", "in", "on", "at", "when", "while", "if", "but",
+    "and", "or", "so", "we", "i", "our", "their", "his", "her", "its", "after", "before", "during", "later",
+    "then", "however", "today",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum EntityKind {
+    Person,
+    Organization,
+    Location,
+}
+
+impl std::str::FromStr for EntityKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "person" => Ok(EntityKind::Person),
+            "organization" | "organisation" | "org" => Ok(EntityKind::Organization),
+            "location" | "place" => Ok(EntityKind::Location),
+            other => Err(anyhow!("Unknown entity type '{}' (expected person, organization or location)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Entity {
+    pub kind: EntityKind,
+    pub text: String,
+}
+
+impl Entity {
+    /// Whether `name` appears in the entity as whole words, ignoring case, so `Turing` matches `Alan Turing`
+    pub fn matches_name(&self, name: &str) -> bool {
+        contains_phrase(&words(&self.text), &words(name))
+    }
+}
+
+/// Entities named in `text`, each once, in order of first mention
+pub fn extract_entities(text: &str) -> Vec<Entity> {
+    let tokens = tokenize(text);
+    let mut entities: Vec<Entity> = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        if !tokens[i].capitalized {
+            i += 1;
+            continue;
+        }
+        // A run of capitalized words, which may be joined by `of` as in `Bank of England`
+        let start = i;
+        let mut end = i + 1;
+        while end < tokens.len() {
+            if tokens[end].capitalized && !tokens[end - 1].ends_sentence {
+                end += 1;
+            } else if tokens[end].word == "of"
+                && end + 1 < tokens.len()
+                && tokens[end + 1].capitalized
+                && !tokens[end - 1].ends_sentence
+            {
+                end += 2;
+            } else {
+                break;
+            }
+        }
+        i = end;
+        if let Some(entity) = classify(&tokens, start, end) {
+            if !entities.contains(&entity) {
+                entities.push(entity);
+            }
+        }
+    }
+    entities
+}
+
+fn classify(tokens: &[Token], mut start: usize, end: usize) -> Option<Entity> {
+    let lower = |i: usize| tokens[i].word.to_lowercase();
+    // Whatever precedes an honorific, such as `Later` in `Later Dr. Clarke`, is not part of the name
+    let honorific = (start..end).rev().find(|&i| HONORIFICS.contains(&lower(i).as_str()));
+    if let Some(i) = honorific {
+        start = i + 1;
+    }
+    while start < end && LEADING_STOPWORDS.contains(&lower(start).as_str()) {
+        start += 1;
+    }
+    if start == end {
+        return None;
+    }
+    let text = tokens[start..end].iter().map(|t| t.word.as_str()).collect::<Vec<_>>().join(" ");
+    let previous = (start > 0).then(|| lower(start - 1));
+    let first = lower(start);
+    let last = lower(end - 1);
+    let run = end - start;
+
+    let kind = if honorific.is_some() {
+        EntityKind::Person
+    } else if ORGANIZATION_SUFFIXES.contains(&last.as_str()) || ORGANIZATION_SUFFIXES.contains(&first.as_str()) {
+        EntityKind::Organization
+    } else if LOCATION_SUFFIXES.contains(&last.as_str())
+        || previous.is_some_and(|p| LOCATION_PREPOSITIONS.contains(&p.as_str()))
+    {
+        EntityKind::Location
+    } else if (2..=3).contains(&run) {
+        EntityKind::Person
+    } else {
+        // A lone capitalized word is too often just the start of a sentence
+        return None;
+    };
+    Some(Entity { kind, text })
+}
+
+struct Token {
+    word: String,
+    capitalized: bool,
+    ends_sentence: bool,
+}
+
+fn tokenize(text: &str) -> Vec<Token> {
+    text.split_whitespace()
+        .filter_map(|raw| {
+            let ends_sentence = raw.ends_with(['.', '!', '?', ':', ';']) && !is_abbreviation(raw);
+            let word = raw.trim_matches(|c: char| !c.is_alphanumeric());
+            let word = word.strip_suffix("'s").or_else(|| word.strip_suffix("’s")).unwrap_or(word);
+            if word.is_empty() {
+                return None;
+            }
+            Some(Token {
+                capitalized: word.chars().next().is_some_and(char::is_uppercase),
+                word: word.to_string(),
+                ends_sentence,
+            })
+        })
+        .collect()
+}
+
+/// `Dr.` and `Inc.` end with a period without ending the sentence
+fn is_abbreviation(raw: &str) -> bool {
+    let word = raw.trim_end_matches('.').to_lowercase();
+    HONORIFICS.contains(&word.as_str()) || matches!(word.as_str(), "inc" | "ltd" | "corp" | "co" | "st")
+}
+
+/// Lowercased alphanumeric words, the form names are compared in
+pub(crate) fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+pub(crate) fn contains_phrase(haystack: &[String], needle: &[String]) -> bool {
+    !needle.is_empty() && haystack.windows(needle.len()).any(|window| window == needle)
+}
+
+/// Whether text, given as its `words`, names the entity
+pub(crate) fn mentioned_in(entity: &Entity, text_words: &[String]) -> bool {
+    contains_phrase(text_words, &words(&entity.text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extracts_people_organizations_and_locations() {
+        let entities = extract_entities(
+            "During the war Alan Turing worked at Bletchley Park. Later Dr. Joan Clarke joined Acme Labs \
+             in London, and the Bank of England printed money.",
+        );
+        let found: Vec<(EntityKind, &str)> = entities.iter().map(|e| (e.kind, e.text.as_str())).collect();
+        assert!(found.contains(&(EntityKind::Person, "Alan Turing")));
+        assert!(found.contains(&(EntityKind::Location, "Bletchley Park")));
+        assert!(found.contains(&(EntityKind::Person, "Joan Clarke")));
+        assert!(found.contains(&(EntityKind::Organization, "Acme Labs")));
+        assert!(found.contains(&(EntityKind::Location, "London")));
+        assert!(found.contains(&(EntityKind::Organization, "Bank of England")));
+        assert!(!found.iter().any(|(_, text)| *text == "During" || *text == "Later"));
+
+        assert!(entities[0].matches_name("turing"));
+        assert!(!entities[0].matches_name("Turin"));
+        assert_eq!("org".parse::<EntityKind>().unwrap(), EntityKind::Organization);
+        assert!("animal".parse::<EntityKind>().is_err());
+    }
+}
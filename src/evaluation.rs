@@ -1,8 +1,15 @@
 //! Simple evaluation for MVP
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use crate::search::SearchResult;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::path::Path;
+use crate::chunking::DocumentChunk;
+use crate::llm::{CompletionProvider, CompletionRequest};
+use crate::quantization::{quantized_copy, EmbeddingQuantization, StoredEmbedding};
+use crate::search::{SearchConfig, SearchEngine, SearchResult};
+use crate::statistics::{paired_t_test, wilcoxon_signed_rank};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EvaluationMetrics {
@@ -10,10 +17,311 @@ pub struct EvaluationMetrics {
     pub precision: f32,
     pub recall: f32,
     pub f1_score: f32,
+    /// 1.0 when any relevant document is in the top-k; averaged, the share of queries with a hit
+    #[serde(default)]
+    pub hit_rate: f32,
+}
+
+impl EvaluationMetrics {
+    /// Metric names paired with their values, in a stable order for reports
+    pub fn values(&self) -> Vec<(&'static str, f32)> {
+        vec![
+            ("relevance", self.relevance),
+            ("precision", self.precision),
+            ("recall", self.recall),
+            ("f1_score", self.f1_score),
+            ("hit_rate", self.hit_rate),
+        ]
+    }
+
+    pub fn mean(metrics: &[EvaluationMetrics]) -> EvaluationMetrics {
+        let n = metrics.len().max(1) as f32;
+        EvaluationMetrics {
+            relevance: metrics.iter().map(|m| m.relevance).sum::<f32>() / n,
+            precision: metrics.iter().map(|m| m.precision).sum::<f32>() / n,
+            recall: metrics.iter().map(|m| m.recall).sum::<f32>() / n,
+            f1_score: metrics.iter().map(|m| m.f1_score).sum::<f32>() / n,
+            hit_rate: metrics.iter().map(|m| m.hit_rate).sum::<f32>() / n,
+        }
+    }
+}
+
+/// A query with the documents a good search should return for it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluationQuery {
+    pub query: String,
+    pub relevant_doc_ids: Vec<String>,
+    /// Chunk the query was generated from, for synthetic datasets
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_chunk_id: Option<String>,
+}
+
+/// Labelled queries shared by batch evaluation, comparisons and regression checks
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvaluationDataset {
+    pub queries: Vec<EvaluationQuery>,
+}
+
+impl EvaluationDataset {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryEvaluation {
+    pub query: String,
+    pub metrics: EvaluationMetrics,
+}
+
+/// Per-query and mean metrics for one search configuration over a dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatasetEvaluation {
+    pub k: usize,
+    pub per_query: Vec<QueryEvaluation>,
+    pub mean: EvaluationMetrics,
+}
+
+/// How configuration B did against configuration A on a single metric
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub metric: String,
+    pub mean_a: f32,
+    pub mean_b: f32,
+    pub delta: f32,
+    pub wins_a: usize,
+    pub wins_b: usize,
+    pub ties: usize,
+    /// Two-sided p-value of a paired t-test over per-query values; None with fewer than two queries
+    pub t_test_p_value: Option<f64>,
+    /// Two-sided p-value of a Wilcoxon signed-rank test over per-query values
+    pub wilcoxon_p_value: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonReport {
+    pub config_a: SearchConfig,
+    pub config_b: SearchConfig,
+    pub evaluation_a: DatasetEvaluation,
+    pub evaluation_b: DatasetEvaluation,
+    pub metrics: Vec<MetricComparison>,
+}
+
+/// How searching quantized embeddings compares to searching the full-precision ones
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuantizationReport {
+    pub quantization: EmbeddingQuantization,
+    pub k: usize,
+    pub full: DatasetEvaluation,
+    pub quantized: DatasetEvaluation,
+    /// Mean share of each query's full-precision top-k that the quantized search also returned
+    pub result_overlap: f32,
+    pub full_bytes: usize,
+    pub quantized_bytes: usize,
+}
+
+/// Metrics computed from LLM relevance judgments instead of labelled ground truth
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JudgedMetrics {
+    pub grades: Vec<u8>,
+    pub mean_grade: f32,
+    pub precision: f32,
+    pub ndcg: f32,
+}
+
+/// Highest grade on the judge's relevance scale
+pub const MAX_RELEVANCE_GRADE: u8 = 3;
+
+/// Grades at or above this count as relevant for judged precision
+pub const RELEVANT_GRADE_THRESHOLD: u8 = 2;
+
+const JUDGE_PREAMBLE: &str = "You are a search quality rater. Grade how relevant a passage is to a search query \
+on a scale from 0 to 3: 0 = irrelevant, 1 = related but does not help answer the query, \
+2 = partially answers the query, 3 = fully answers the query. Respond with the single digit only.";
+
+/// Uses an LLM to grade each retrieved chunk's relevance to the query on a 0-3 scale
+pub struct RelevanceJudge {
+    llm: Arc<dyn CompletionProvider>,
+    cache: Mutex<HashMap<(String, String), u8>>,
+}
+
+impl RelevanceJudge {
+    pub fn new(llm: Arc<dyn CompletionProvider>) -> Self {
+        Self {
+            llm,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Grade a single result, reusing a cached judgment for the same query and chunk
+    pub fn judge(&self, query: &str, result: &SearchResult) -> Result<u8> {
+        let key = (query.to_string(), result.chunk_id.clone());
+        if let Some(grade) = self.cache.lock().unwrap().get(&key) {
+            return Ok(*grade);
+        }
+
+        let request = CompletionRequest::new(format!("Query: {}\n\nPassage:\n{}\n\nGrade:", query, result.content))
+            .with_preamble(JUDGE_PREAMBLE)
+            .with_temperature(0.0);
+        let response = self.llm.complete(&request)?;
+        let grade = Self::parse_grade(&response)?;
+
+        self.cache.lock().unwrap().insert(key, grade);
+        Ok(grade)
+    }
+
+    pub fn cached_judgments(&self) -> usize {
+        self.cache.lock().unwrap().len()
+    }
+
+    fn parse_grade(response: &str) -> Result<u8> {
+        response
+            .chars()
+            .filter_map(|c| c.to_digit(10))
+            .find(|d| *d <= MAX_RELEVANCE_GRADE as u32)
+            .map(|d| d as u8)
+            .ok_or_else(|| anyhow!("Judge returned no grade in '{}'", response.trim()))
+    }
+}
+
+/// Whether a single claim from a generated answer is supported by the retrieved context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClaimVerdict {
+    pub claim: String,
+    pub supported: bool,
+}
+
+/// How well a generated answer is backed by the retrieved chunks
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaithfulnessMetrics {
+    pub claims: Vec<ClaimVerdict>,
+    /// Share of extracted claims the LLM found entailed by the context
+    pub faithfulness: f32,
+    /// Share of the answer's content words that also appear in the context
+    pub groundedness: f32,
+}
+
+impl FaithfulnessMetrics {
+    pub fn unsupported_claims(&self) -> Vec<&str> {
+        self.claims
+            .iter()
+            .filter(|v| !v.supported)
+            .map(|v| v.claim.as_str())
+            .collect()
+    }
+}
+
+/// Retrieval and answer quality for one query, reported together
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagEvaluationReport {
+    pub query: String,
+    pub retrieval: EvaluationMetrics,
+    pub answer: FaithfulnessMetrics,
+}
+
+const CLAIM_EXTRACTION_PREAMBLE: &str = "Break the given answer into the individual factual claims it makes. \
+Write one short, self-contained claim per line with no numbering or commentary.";
+
+const ENTAILMENT_PREAMBLE: &str = "You verify facts. Given a context and a claim, decide whether the claim \
+is fully supported by the context alone. Respond with YES or NO only.";
+
+/// Checks generated answers against retrieved chunks via claim extraction and entailment
+pub struct FaithfulnessEvaluator {
+    llm: Arc<dyn CompletionProvider>,
+}
+
+impl FaithfulnessEvaluator {
+    pub fn new(llm: Arc<dyn CompletionProvider>) -> Self {
+        Self { llm }
+    }
+
+    pub fn evaluate(&self, answer: &str, context: &[SearchResult]) -> Result<FaithfulnessMetrics> {
+        let contexts: Vec<&str> = context.iter().map(|r| r.content.as_str()).collect();
+        self.evaluate_texts(answer, &contexts)
+    }
+
+    /// Same as `evaluate`, for contexts that are plain text rather than search results
+    pub fn evaluate_texts(&self, answer: &str, contexts: &[&str]) -> Result<FaithfulnessMetrics> {
+        let context_text = contexts.join("\n\n");
+
+        let claims = self.extract_claims(answer)?;
+        let verdicts = claims
+            .into_iter()
+            .map(|claim| {
+                let supported = self.is_entailed(&context_text, &claim)?;
+                Ok(ClaimVerdict { claim, supported })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let faithfulness = if verdicts.is_empty() {
+            0.0
+        } else {
+            verdicts.iter().filter(|v| v.supported).count() as f32 / verdicts.len() as f32
+        };
+
+        Ok(FaithfulnessMetrics {
+            claims: verdicts,
+            faithfulness,
+            groundedness: Self::groundedness(answer, &context_text),
+        })
+    }
+
+    fn extract_claims(&self, answer: &str) -> Result<Vec<String>> {
+        let request = CompletionRequest::new(format!("Answer:\n{}\n\nClaims:", answer))
+            .with_preamble(CLAIM_EXTRACTION_PREAMBLE)
+            .with_temperature(0.0);
+        let response = self.llm.complete(&request)?;
+
+        Ok(response
+            .lines()
+            .map(|line| line.trim().trim_start_matches(|c: char| c == '-' || c == '*' || c == '.' || c.is_ascii_digit()).trim())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    fn is_entailed(&self, context: &str, claim: &str) -> Result<bool> {
+        let request = CompletionRequest::new(format!("Context:\n{}\n\nClaim: {}\n\nSupported:", context, claim))
+            .with_preamble(ENTAILMENT_PREAMBLE)
+            .with_temperature(0.0);
+        let response = self.llm.complete(&request)?;
+        Ok(response.trim().to_lowercase().starts_with("yes"))
+    }
+
+    fn groundedness(answer: &str, context: &str) -> f32 {
+        let context_lower = context.to_lowercase();
+        let context_words: std::collections::HashSet<&str> = context_lower
+            .split(|c: char| !c.is_alphanumeric())
+            .collect();
+        let answer_lower = answer.to_lowercase();
+        let answer_words: Vec<&str> = answer_lower
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| w.len() > 3)
+            .collect();
+
+        if answer_words.is_empty() {
+            return 0.0;
+        }
+
+        let grounded = answer_words.iter().filter(|w| context_words.contains(*w)).count();
+        grounded as f32 / answer_words.len() as f32
+    }
 }
 
 pub struct Evaluator;
 
+impl Default for Evaluator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Evaluator {
     pub fn new() -> Self {
         Self
@@ -26,6 +334,7 @@ impl Evaluator {
                 precision: 0.0,
                 recall: 0.0,
                 f1_score: 0.0,
+                hit_rate: 0.0,
             });
         }
 
@@ -42,14 +351,199 @@ impl Evaluator {
             0.0
         };
 
+        let hit_rate = if expected_doc_ids.is_empty() || results.iter().any(|r| expected_doc_ids.contains(&r.document_id)) {
+            1.0
+        } else {
+            0.0
+        };
+
         Ok(EvaluationMetrics {
             relevance,
             precision,
             recall,
             f1_score,
+            hit_rate,
+        })
+    }
+
+    /// Run every dataset query against the chunks and collect per-query and mean metrics
+    pub fn evaluate_dataset(
+        &self,
+        engine: &SearchEngine,
+        chunks: &[DocumentChunk],
+        dataset: &EvaluationDataset,
+        k: usize,
+    ) -> Result<DatasetEvaluation> {
+        let per_query = dataset
+            .queries
+            .iter()
+            .map(|q| {
+                let results = engine.search(&q.query, chunks, k)?;
+                Ok(QueryEvaluation {
+                    query: q.query.clone(),
+                    metrics: self.evaluate(&results, &q.relevant_doc_ids)?,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let all: Vec<EvaluationMetrics> = per_query.iter().map(|q| q.metrics.clone()).collect();
+        Ok(DatasetEvaluation {
+            k,
+            mean: EvaluationMetrics::mean(&all),
+            per_query,
+        })
+    }
+
+    /// Run every dataset query against full-precision `embeddings` and a copy quantized to `quantization`,
+    /// given each query's embedding in dataset order
+    #[allow(clippy::too_many_arguments)]
+    pub fn evaluate_quantization(
+        &self,
+        engine: &SearchEngine,
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, Vec<f32>>,
+        dataset: &EvaluationDataset,
+        query_embeddings: &[Vec<f32>],
+        quantization: EmbeddingQuantization,
+        k: usize,
+    ) -> Result<QuantizationReport> {
+        if quantization == EmbeddingQuantization::None {
+            return Err(anyhow!("Pick a quantization to compare full precision with"));
+        }
+        if query_embeddings.len() != dataset.queries.len() {
+            return Err(anyhow!("Expected {} query embeddings, got {}", dataset.queries.len(), query_embeddings.len()));
+        }
+        let quantized = quantized_copy(embeddings, quantization)?;
+
+        let (mut full_queries, mut quantized_queries, mut overlaps) = (Vec::new(), Vec::new(), Vec::new());
+        for (q, query_embedding) in dataset.queries.iter().zip(query_embeddings) {
+            let full_results = engine.search_with_embeddings(&q.query, query_embedding, chunks, embeddings, k)?;
+            let quantized_results = engine.search_with_embeddings(&q.query, query_embedding, chunks, &quantized, k)?;
+            let shared = full_results
+                .iter()
+                .filter(|r| quantized_results.iter().any(|other| other.chunk_id == r.chunk_id))
+                .count();
+            overlaps.push(if full_results.is_empty() { 1.0 } else { shared as f32 / full_results.len() as f32 });
+            full_queries.push(QueryEvaluation {
+                query: q.query.clone(),
+                metrics: self.evaluate(&full_results, &q.relevant_doc_ids)?,
+            });
+            quantized_queries.push(QueryEvaluation {
+                query: q.query.clone(),
+                metrics: self.evaluate(&quantized_results, &q.relevant_doc_ids)?,
+            });
+        }
+
+        let evaluation = |per_query: Vec<QueryEvaluation>| {
+            let all: Vec<EvaluationMetrics> = per_query.iter().map(|q| q.metrics.clone()).collect();
+            DatasetEvaluation { k, mean: EvaluationMetrics::mean(&all), per_query }
+        };
+        Ok(QuantizationReport {
+            quantization,
+            k,
+            full: evaluation(full_queries),
+            quantized: evaluation(quantized_queries),
+            result_overlap: overlaps.iter().sum::<f32>() / overlaps.len().max(1) as f32,
+            full_bytes: embeddings.values().map(|v| v.len() * std::mem::size_of::<f32>()).sum(),
+            quantized_bytes: quantized.values().map(StoredEmbedding::memory_bytes).sum(),
+        })
+    }
+
+    /// Evaluate two search configurations on the same dataset and report deltas and per-query wins
+    pub fn compare(
+        &self,
+        config_a: &SearchConfig,
+        config_b: &SearchConfig,
+        dataset: &EvaluationDataset,
+        chunks: &[DocumentChunk],
+        k: usize,
+    ) -> Result<ComparisonReport> {
+        let evaluation_a = self.evaluate_dataset(&SearchEngine::with_config(config_a.clone())?, chunks, dataset, k)?;
+        let evaluation_b = self.evaluate_dataset(&SearchEngine::with_config(config_b.clone())?, chunks, dataset, k)?;
+
+        let metrics = evaluation_a
+            .mean
+            .values()
+            .into_iter()
+            .zip(evaluation_b.mean.values())
+            .enumerate()
+            .map(|(i, ((name, mean_a), (_, mean_b)))| {
+                let values_a: Vec<f64> = evaluation_a.per_query.iter().map(|q| q.metrics.values()[i].1 as f64).collect();
+                let values_b: Vec<f64> = evaluation_b.per_query.iter().map(|q| q.metrics.values()[i].1 as f64).collect();
+                let mut comparison = MetricComparison {
+                    metric: name.to_string(),
+                    mean_a,
+                    mean_b,
+                    delta: mean_b - mean_a,
+                    wins_a: 0,
+                    wins_b: 0,
+                    ties: 0,
+                    t_test_p_value: paired_t_test(&values_a, &values_b).map(|r| r.p_value),
+                    wilcoxon_p_value: wilcoxon_signed_rank(&values_a, &values_b).map(|r| r.p_value),
+                };
+                for (a, b) in values_a.iter().zip(&values_b) {
+                    if (a - b).abs() < f32::EPSILON as f64 {
+                        comparison.ties += 1;
+                    } else if a > b {
+                        comparison.wins_a += 1;
+                    } else {
+                        comparison.wins_b += 1;
+                    }
+                }
+                comparison
+            })
+            .collect();
+
+        Ok(ComparisonReport {
+            config_a: config_a.clone(),
+            config_b: config_b.clone(),
+            evaluation_a,
+            evaluation_b,
+            metrics,
         })
     }
 
+    /// Evaluate results using LLM judgments in place of expected document IDs
+    pub fn evaluate_with_judge(&self, query: &str, results: &[SearchResult], judge: &RelevanceJudge) -> Result<JudgedMetrics> {
+        let grades = results
+            .iter()
+            .map(|result| judge.judge(query, result))
+            .collect::<Result<Vec<u8>>>()?;
+
+        if grades.is_empty() {
+            return Ok(JudgedMetrics {
+                grades,
+                mean_grade: 0.0,
+                precision: 0.0,
+                ndcg: 0.0,
+            });
+        }
+
+        let mean_grade = grades.iter().map(|g| *g as f32).sum::<f32>() / grades.len() as f32;
+        let relevant = grades.iter().filter(|g| **g >= RELEVANT_GRADE_THRESHOLD).count();
+        let precision = relevant as f32 / grades.len() as f32;
+
+        let mut ideal = grades.clone();
+        ideal.sort_unstable_by(|a, b| b.cmp(a));
+        let ideal_dcg = Self::dcg(&ideal);
+        let ndcg = if ideal_dcg > 0.0 { Self::dcg(&grades) / ideal_dcg } else { 0.0 };
+
+        Ok(JudgedMetrics {
+            grades,
+            mean_grade,
+            precision,
+            ndcg,
+        })
+    }
+
+    fn dcg(grades: &[u8]) -> f32 {
+        grades
+            .iter()
+            .enumerate()
+            .map(|(i, g)| (2f32.powi(*g as i32) - 1.0) / ((i + 2) as f32).log2())
+            .sum()
+    }
+
     fn calculate_relevance(&self, results: &[SearchResult]) -> f32 {
         if results.is_empty() {
             return 0.0;
@@ -97,10 +591,158 @@ mod tests {
     use super::*;
     use crate::search::SearchResult;
 
+    struct FixedGradeLlm {
+        grade: &'static str,
+        calls: Mutex<usize>,
+    }
+
+    impl CompletionProvider for FixedGradeLlm {
+        fn model_name(&self) -> &str {
+            "fixed"
+        }
+
+        fn complete(&self, _request: &CompletionRequest) -> Result<String> {
+            *self.calls.lock().unwrap() += 1;
+            Ok(self.grade.to_string())
+        }
+    }
+
+    fn result(chunk_id: &str) -> SearchResult {
+        SearchResult {
+            chunk_id: chunk_id.to_string(),
+            document_id: "doc1".to_string(),
+            content: "Some content".to_string(),
+            score: 0.5,
+            rank: 1,
+            pinned: false,
+        }
+    }
+
     #[test]
     fn test_evaluator_creation() {
+        let _evaluator = Evaluator::new();
+    }
+
+    #[test]
+    fn test_judged_evaluation_caches_grades() {
+        let llm = Arc::new(FixedGradeLlm { grade: "Grade: 3", calls: Mutex::new(0) });
+        let judge = RelevanceJudge::new(llm.clone());
         let evaluator = Evaluator::new();
-        assert!(true);
+        let results = vec![result("chunk1"), result("chunk2")];
+
+        let metrics = evaluator.evaluate_with_judge("query", &results, &judge).unwrap();
+        assert_eq!(metrics.grades, vec![3, 3]);
+        assert_eq!(metrics.precision, 1.0);
+        assert!((metrics.ndcg - 1.0).abs() < 1e-6);
+
+        evaluator.evaluate_with_judge("query", &results, &judge).unwrap();
+        assert_eq!(*llm.calls.lock().unwrap(), 2);
+        assert_eq!(judge.cached_judgments(), 2);
+    }
+
+    struct ScriptedLlm;
+
+    impl CompletionProvider for ScriptedLlm {
+        fn model_name(&self) -> &str {
+            "scripted"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> Result<String> {
+            if request.prompt.starts_with("Answer:") {
+                return Ok("- Rust is a programming language\n- Rust was created on Mars".to_string());
+            }
+            Ok(if request.prompt.contains("Mars") { "NO" } else { "YES" }.to_string())
+        }
+    }
+
+    #[test]
+    fn test_faithfulness_flags_unsupported_claims() {
+        let evaluator = FaithfulnessEvaluator::new(Arc::new(ScriptedLlm));
+        let context = vec![SearchResult {
+            content: "Rust is a systems programming language".to_string(),
+            ..result("chunk1")
+        }];
+
+        let metrics = evaluator
+            .evaluate("Rust is a programming language created on Mars.", &context)
+            .unwrap();
+
+        assert_eq!(metrics.claims.len(), 2);
+        assert_eq!(metrics.faithfulness, 0.5);
+        assert_eq!(metrics.unsupported_claims(), vec!["Rust was created on Mars"]);
+        assert!(metrics.groundedness > 0.0 && metrics.groundedness < 1.0);
+    }
+
+    fn chunk(id: &str, document_id: &str, content: &str) -> DocumentChunk {
+        DocumentChunk {
+            id: id.to_string(),
+            content: content.into(),
+            start_pos: 0,
+            end_pos: content.split_whitespace().count(),
+            word_count: content.split_whitespace().count(),
+            document_id: document_id.to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_compare_search_configs() {
+        let chunks = vec![
+            chunk("c1", "rust", "Rust guarantees memory safety through ownership and borrowing without garbage collection"),
+            chunk("c2", "python", "Python uses a garbage collector and reference counting to manage memory automatically"),
+        ];
+        let dataset = EvaluationDataset {
+            queries: vec![
+                EvaluationQuery { query: "ownership borrowing".to_string(), relevant_doc_ids: vec!["rust".to_string()], source_chunk_id: None },
+                EvaluationQuery { query: "reference counting".to_string(), relevant_doc_ids: vec!["python".to_string()], source_chunk_id: None },
+            ],
+        };
+        let keyword = SearchConfig::default();
+        let bm25 = SearchConfig { mode: crate::search::SearchMode::Bm25, ..SearchConfig::default() };
+
+        let report = Evaluator::new().compare(&keyword, &bm25, &dataset, &chunks, 1).unwrap();
+
+        assert_eq!(report.evaluation_a.per_query.len(), 2);
+        assert_eq!(report.metrics.len(), 5);
+        let precision = report.metrics.iter().find(|m| m.metric == "precision").unwrap();
+        assert_eq!(precision.mean_b, 1.0);
+        assert_eq!(precision.wins_a + precision.wins_b + precision.ties, 2);
+        assert!((precision.delta - (precision.mean_b - precision.mean_a)).abs() < 1e-6);
+        assert!(precision.t_test_p_value.is_some());
+        assert!(precision.wilcoxon_p_value.unwrap() <= 1.0);
+    }
+
+    #[test]
+    fn test_hit_rate_over_dataset() {
+        let chunks = vec![
+            chunk("c1", "rust", "Rust ownership and borrowing"),
+            chunk("c2", "go", "Go goroutines and channels"),
+        ];
+        let dataset = EvaluationDataset {
+            queries: vec![
+                EvaluationQuery { query: "ownership".to_string(), relevant_doc_ids: vec!["rust".to_string()], source_chunk_id: None },
+                EvaluationQuery { query: "goroutines".to_string(), relevant_doc_ids: vec!["haskell".to_string()], source_chunk_id: None },
+            ],
+        };
+
+        let evaluation = Evaluator::new()
+            .evaluate_dataset(&SearchEngine::new().unwrap(), &chunks, &dataset, 1)
+            .unwrap();
+
+        assert_eq!(evaluation.per_query[0].metrics.hit_rate, 1.0);
+        assert_eq!(evaluation.per_query[1].metrics.hit_rate, 0.0);
+        assert_eq!(evaluation.mean.hit_rate, 0.5);
+    }
+
+    #[test]
+    fn test_judge_rejects_ungraded_response() {
+        let llm = Arc::new(FixedGradeLlm { grade: "not sure", calls: Mutex::new(0) });
+        let judge = RelevanceJudge::new(llm);
+        assert!(judge.judge("query", &result("chunk1")).is_err());
     }
 
     #[test]
@@ -115,6 +757,7 @@ mod tests {
         assert_eq!(metrics.precision, 0.0);
         assert_eq!(metrics.recall, 0.0);
         assert_eq!(metrics.f1_score, 0.0);
+        assert_eq!(metrics.hit_rate, 0.0);
     }
 
     #[test]
@@ -127,6 +770,7 @@ mod tests {
             content: "Perfect match".to_string(),
             score: 1.0,
             rank: 1,
+            pinned: false,
         }];
 
         let expected = vec!["doc1".to_string()];
@@ -136,5 +780,6 @@ mod tests {
         assert_eq!(metrics.precision, 1.0);
         assert_eq!(metrics.recall, 1.0);
         assert_eq!(metrics.f1_score, 1.0);
+        assert_eq!(metrics.hit_rate, 1.0);
     }
 }
\ No newline at end of file
@@ -0,0 +1,89 @@
+//! Facet counts over a search's candidates, so a UI can offer drill-down filters such as
+//! "Markdown (12)" next to the results
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use crate::language::LANGUAGE_TAG;
+use crate::processor::DocumentMetadata;
+use crate::search::SearchResult;
+use crate::tags::{BOOST_TAG, COLLECTION_TAG, PINNED_TAG};
+
+/// Candidate documents per value, each counted once however many of its chunks matched. Every value
+/// is also a query condition, e.g. `type:md`, `collection:handbook`, `language:de` or `project:alpha`
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Facets {
+    /// Documents counted: those with a chunk that matched the query
+    pub documents: usize,
+    pub file_type: BTreeMap<String, usize>,
+    pub collection: BTreeMap<String, usize>,
+    pub language: BTreeMap<String, usize>,
+    /// Every other tag, leaving out the ranking tags `boost` and `pinned`
+    pub tags: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+impl Facets {
+    /// Count the documents of `candidates` whose metadata `documents` holds
+    pub fn count(candidates: &[SearchResult], documents: &HashMap<String, DocumentMetadata>) -> Self {
+        let mut facets = Self::default();
+        let mut seen = HashSet::new();
+        let matched = candidates.iter().filter(|r| r.score > 0.0 && seen.insert(r.document_id.as_str()));
+        for metadata in matched.filter_map(|r| documents.get(&r.document_id)) {
+            facets.documents += 1;
+            *facets.file_type.entry(metadata.file_type.to_lowercase()).or_default() += 1;
+            if let Some(language) = metadata.language() {
+                *facets.language.entry(language.to_string()).or_default() += 1;
+            }
+            for (key, value) in &metadata.tags {
+                let counts = match key.as_str() {
+                    COLLECTION_TAG => &mut facets.collection,
+                    LANGUAGE_TAG | BOOST_TAG | PINNED_TAG => continue,
+                    key => facets.tags.entry(key.to_string()).or_default(),
+                };
+                *counts.entry(value.clone()).or_default() += 1;
+            }
+        }
+        facets
+    }
+}
+
+/// The top results of a search and the facets of all its candidates
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FacetedResults {
+    pub results: Vec<SearchResult>,
+    pub facets: Facets,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+    use crate::SimpleRagSystem;
+
+    #[test]
+    fn test_facets_count_every_candidate_before_truncation() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let handbook = rag.process_bytes("leave.md", b"The leave policy is that staff get twenty days of leave.").unwrap();
+        let urlaub = rag.process_bytes("urlaub.md", b"Die leave Regel ist, dass das Team nicht mehr als zwanzig Tage hat.").unwrap();
+        let form = rag.process_bytes("form.txt", b"Leave requests go to HR with the form.").unwrap();
+        rag.process_bytes("pay.txt", b"Payroll runs monthly.").unwrap();
+        rag.set_tag(&handbook, "collection", "handbook").unwrap();
+        rag.set_tag(&urlaub, "collection", "handbook").unwrap();
+        rag.set_tag(&form, "project", "alpha").unwrap();
+        rag.set_tag(&form, "boost", "2").unwrap();
+
+        let faceted = rag.search_faceted(&Query::parse("leave").unwrap(), 1).unwrap();
+        assert_eq!(faceted.results.len(), 1);
+        let facets = faceted.facets;
+        assert_eq!(facets.documents, 3);
+        assert_eq!(facets.file_type, BTreeMap::from([("md".to_string(), 2), ("txt".to_string(), 1)]));
+        assert_eq!(facets.collection, BTreeMap::from([("handbook".to_string(), 2)]));
+        assert_eq!(facets.language, BTreeMap::from([("de".to_string(), 1), ("en".to_string(), 2)]));
+        assert_eq!(facets.tags.keys().collect::<Vec<_>>(), ["project"]);
+
+        // Each value drills down as a query condition
+        let german = rag.search_faceted(&Query::parse("leave language:de").unwrap(), 5).unwrap();
+        assert_eq!(german.results[0].document_id, urlaub);
+        assert_eq!(german.facets.documents, 1);
+        assert_eq!(rag.facets(&Query::parse("type:txt").unwrap()).unwrap().documents, 2);
+    }
+}
@@ -0,0 +1,116 @@
+//! Relevance judgements on search results, used to adjust the scores of later similar searches.
+//!
+//! The adjustment is Rocchio-style, applied to chunk scores rather than to a query vector: each
+//! judgement on a chunk pulls that chunk up, or pushes it down, in proportion to how similar its query
+//! is to the one being searched.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::related::term_similarity;
+use crate::search::tokenize;
+
+/// Judgements on queries less similar than this to the current one are ignored
+pub const MIN_QUERY_SIMILARITY: f32 = 0.5;
+
+/// Judgements kept per tenant; past it the oldest are dropped
+pub const MAX_FEEDBACK_ENTRIES: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedbackEntry {
+    /// Unix seconds when the judgement was recorded
+    pub timestamp: u64,
+    pub query: String,
+    pub chunk_id: String,
+    pub relevant: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl FeedbackEntry {
+    /// Whether `other` judges the same chunk for the same query, ignoring case and punctuation, so the
+    /// later judgement replaces the earlier one
+    pub fn supersedes(&self, other: &FeedbackEntry) -> bool {
+        self.tenant == other.tenant && self.chunk_id == other.chunk_id && tokenize(&self.query) == tokenize(&other.query)
+    }
+}
+
+/// Adjustment per chunk id, from -1 (judged irrelevant for this very query) to 1 (judged relevant)
+pub fn feedback_adjustments(query: &str, feedback: &[FeedbackEntry]) -> HashMap<String, f32> {
+    let profile = query_profile(query);
+    let mut adjustments: HashMap<String, f32> = HashMap::new();
+    for entry in feedback {
+        let similarity = term_similarity(&profile, &query_profile(&entry.query));
+        if similarity < MIN_QUERY_SIMILARITY {
+            continue;
+        }
+        let sign = if entry.relevant { 1.0 } else { -1.0 };
+        *adjustments.entry(entry.chunk_id.clone()).or_insert(0.0) += sign * similarity;
+    }
+    adjustments.values_mut().for_each(|a| *a = a.clamp(-1.0, 1.0));
+    adjustments
+}
+
+/// Move each chunk's score by `weight` times its adjustment, scaled to the best score so the effect is
+/// the same in every search mode; scores never go below zero
+pub fn apply_feedback(
+    chunk_ids: impl Iterator<Item = impl AsRef<str>>,
+    scores: &mut [f32],
+    adjustments: &HashMap<String, f32>,
+    weight: f32,
+) {
+    if adjustments.is_empty() || weight == 0.0 {
+        return;
+    }
+    let best = scores.iter().copied().fold(0.0, f32::max);
+    let scale = if best > 0.0 { best } else { 1.0 };
+    for (chunk_id, score) in chunk_ids.zip(scores.iter_mut()) {
+        if let Some(adjustment) = adjustments.get(chunk_id.as_ref()) {
+            *score = (*score + weight * scale * adjustment).max(0.0);
+        }
+    }
+}
+
+fn query_profile(query: &str) -> HashMap<String, f32> {
+    let mut terms = HashMap::new();
+    for token in tokenize(query) {
+        *terms.entry(token).or_insert(0.0) += 1.0;
+    }
+    terms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn judged(query: &str, chunk_id: &str, relevant: bool) -> FeedbackEntry {
+        FeedbackEntry {
+            timestamp: 0,
+            query: query.to_string(),
+            chunk_id: chunk_id.to_string(),
+            relevant,
+            tenant: None,
+        }
+    }
+
+    #[test]
+    fn test_similar_queries_adjust_judged_chunks() {
+        let feedback = [
+            judged("paid leave policy", "a_0", true),
+            judged("paid leave", "b_0", false),
+            judged("office parking", "c_0", true),
+        ];
+        let adjustments = feedback_adjustments("Paid leave policy?", &feedback);
+        assert_eq!(adjustments["a_0"], 1.0);
+        assert!(adjustments["b_0"] < 0.0);
+        assert!(!adjustments.contains_key("c_0"));
+
+        let mut scores = [2.0, 2.0, 1.0];
+        apply_feedback(["a_0", "b_0", "c_0"].iter(), &mut scores, &adjustments, 0.5);
+        assert_eq!(scores[0], 3.0);
+        assert!(scores[1] < 2.0);
+        assert_eq!(scores[2], 1.0);
+
+        assert!(!judged("Paid leave?", "a_0", false).supersedes(&feedback[0]));
+        assert!(judged("paid LEAVE policy?", "a_0", false).supersedes(&feedback[0]));
+    }
+}
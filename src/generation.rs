@@ -0,0 +1,303 @@
+//! Prompt construction for answer generation
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::chunking::DocumentChunk;
+use crate::llm::CompletionRequest;
+use crate::search::SearchResult;
+use crate::usage::Usage;
+
+pub const DEFAULT_ANSWER_PREAMBLE: &str = "You answer questions using only the provided context. \
+If the context does not contain the answer, say that you don't know instead of guessing.";
+
+pub const DEFAULT_CITATION_INSTRUCTIONS: &str = "Cite the passages you rely on with their bracketed number, for example [1].";
+
+/// Number of retrieved chunks placed in the prompt by default
+pub const DEFAULT_CONTEXT_CHUNKS: usize = 5;
+
+/// Part of the context window kept free for instructions, history and the answer
+pub const PROMPT_TOKEN_RESERVE: usize = 2_048;
+
+/// A passage the answer relied on, pointing back into the source document
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Citation {
+    /// The bracketed number used in the answer text
+    pub marker: usize,
+    pub chunk_id: String,
+    pub document_id: String,
+    pub byte_start: usize,
+    pub byte_end: usize,
+}
+
+pub const INSUFFICIENT_CONTEXT_ANSWER: &str = "I don't have enough information in the indexed documents to answer that.";
+
+/// Why an answer was declined instead of generated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InsufficientContext {
+    /// Best retrieval score, `None` when nothing matched at all
+    pub best_score: Option<f32>,
+    pub min_score: f32,
+}
+
+/// Generated answer text with the citations it references
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Answer {
+    pub text: String,
+    pub citations: Vec<Citation>,
+    /// Set when retrieval was too weak to answer from, in which case the LLM was not called
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub insufficient_context: Option<InsufficientContext>,
+    /// Tokens and estimated cost spent producing this answer
+    #[serde(default)]
+    pub usage: Usage,
+}
+
+impl Answer {
+    pub fn insufficient_context(best_score: Option<f32>, min_score: f32) -> Self {
+        Self {
+            text: INSUFFICIENT_CONTEXT_ANSWER.to_string(),
+            citations: Vec::new(),
+            insufficient_context: Some(InsufficientContext { best_score, min_score }),
+            usage: Usage::default(),
+        }
+    }
+
+    pub fn is_refusal(&self) -> bool {
+        self.insufficient_context.is_some()
+    }
+
+    /// Pair the answer with the context chunks whose `[n]` markers appear in it
+    pub fn from_response(text: String, context: &[DocumentChunk]) -> Self {
+        let citations = cited_markers(&text)
+            .into_iter()
+            .filter_map(|marker| {
+                let chunk = context.get(marker.checked_sub(1)?)?;
+                Some(Citation {
+                    marker,
+                    chunk_id: chunk.id.clone(),
+                    document_id: chunk.document_id.clone(),
+                    byte_start: chunk.byte_start,
+                    byte_end: chunk.byte_end,
+                })
+            })
+            .collect();
+
+        Self {
+            text,
+            citations,
+            insufficient_context: None,
+            usage: Usage::default(),
+        }
+    }
+}
+
+/// Distinct `[n]` markers in order of first appearance
+fn cited_markers(text: &str) -> Vec<usize> {
+    let mut markers = Vec::new();
+    for part in text.split('[').skip(1) {
+        let Some((number, _)) = part.split_once(']') else {
+            continue;
+        };
+        for n in number.split(',').filter_map(|n| n.trim().parse::<usize>().ok()) {
+            if !markers.contains(&n) {
+                markers.push(n);
+            }
+        }
+    }
+    markers
+}
+
+/// The RAG prompt, adjustable without recompiling.
+///
+/// `context_item` may use `{index}`, `{content}`, `{chunk_id}`, `{document_id}` and `{score}`;
+/// `user` may use `{context}` and `{question}`. Keep `{index}` visible to the model if answers
+/// should carry citations, since they are resolved from `[n]` markers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PromptTemplate {
+    pub system: String,
+    pub citation_instructions: String,
+    pub context_item: String,
+    pub context_separator: String,
+    pub user: String,
+}
+
+impl Default for PromptTemplate {
+    fn default() -> Self {
+        Self {
+            system: DEFAULT_ANSWER_PREAMBLE.to_string(),
+            citation_instructions: DEFAULT_CITATION_INSTRUCTIONS.to_string(),
+            context_item: "[{index}] {content}".to_string(),
+            context_separator: "\n\n".to_string(),
+            user: "Context:\n{context}\n\nQuestion: {question}\n\nAnswer:".to_string(),
+        }
+    }
+}
+
+impl PromptTemplate {
+    /// Load a JSON template; fields left out keep their defaults
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn preamble(&self) -> String {
+        [self.system.trim(), self.citation_instructions.trim()]
+            .iter()
+            .filter(|part| !part.is_empty())
+            .copied()
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    pub fn format_context(&self, context: &[SearchResult]) -> String {
+        context
+            .iter()
+            .enumerate()
+            .map(|(i, result)| {
+                render(
+                    &self.context_item,
+                    &[
+                        ("index", &(i + 1).to_string()),
+                        ("content", &result.content),
+                        ("chunk_id", &result.chunk_id),
+                        ("document_id", &result.document_id),
+                        ("score", &format!("{:.3}", result.score)),
+                    ],
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(&self.context_separator)
+    }
+
+    /// Build the completion request that answers `question` from the retrieved chunks
+    pub fn build_request(&self, question: &str, context: &[SearchResult]) -> CompletionRequest {
+        let prompt = render(&self.user, &[("context", &self.format_context(context)), ("question", question)]);
+        CompletionRequest::new(prompt).with_preamble(self.preamble())
+    }
+}
+
+/// Build the answer request with the default template
+pub fn build_answer_request(question: &str, context: &[SearchResult]) -> CompletionRequest {
+    PromptTemplate::default().build_request(question, context)
+}
+
+/// Substitute `{name}` placeholders in a single pass, so values are never re-expanded
+fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut output = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find('{') {
+        output.push_str(&rest[..open]);
+        let after = &rest[open + 1..];
+        let replacement = after.find('}').and_then(|close| {
+            let name = &after[..close];
+            values.iter().find(|(key, _)| *key == name).map(|(_, value)| (*value, close))
+        });
+        match replacement {
+            Some((value, close)) => {
+                output.push_str(value);
+                rest = &after[close + 1..];
+            }
+            None => {
+                output.push('{');
+                rest = after;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_answer_request_numbers_context() {
+        let context = vec![
+            SearchResult {
+                chunk_id: "c1".to_string(),
+                document_id: "d1".to_string(),
+                content: "First passage".to_string(),
+                score: 1.0,
+                rank: 1,
+                pinned: false,
+            },
+            SearchResult {
+                chunk_id: "c2".to_string(),
+                document_id: "d1".to_string(),
+                content: "Second passage".to_string(),
+                score: 0.5,
+                rank: 2,
+                pinned: false,
+            },
+        ];
+
+        let request = build_answer_request("What?", &context);
+
+        assert!(request.prompt.contains("[1] First passage\n\n[2] Second passage"));
+        assert!(request.prompt.ends_with("Question: What?\n\nAnswer:"));
+        assert!(request.preamble.unwrap().starts_with(DEFAULT_ANSWER_PREAMBLE));
+    }
+
+    #[test]
+    fn test_custom_template_from_file() {
+        let template_file = "/tmp/test_prompt_template.json";
+        std::fs::write(
+            template_file,
+            r#"{"system": "Answer like a pirate.", "citation_instructions": "", "context_item": "({index}) {document_id}: {content}"}"#,
+        )
+        .unwrap();
+        let template = PromptTemplate::from_file(Path::new(template_file)).unwrap();
+        let context = vec![SearchResult {
+            chunk_id: "c1".to_string(),
+            document_id: "d1".to_string(),
+            content: "Treasure is {question}".to_string(),
+            score: 1.0,
+            rank: 1,
+            pinned: false,
+        }];
+
+        let request = template.build_request("Where?", &context);
+
+        assert_eq!(request.preamble.as_deref(), Some("Answer like a pirate."));
+        // User template falls back to the default; chunk content is not re-expanded
+        assert_eq!(request.prompt, "Context:\n(1) d1: Treasure is {question}\n\nQuestion: Where?\n\nAnswer:");
+
+        std::fs::remove_file(template_file).unwrap();
+    }
+
+    #[test]
+    fn test_answer_citations_follow_markers() {
+        let context: Vec<DocumentChunk> = (0..3)
+            .map(|i| DocumentChunk {
+                id: format!("c{}", i),
+                content: "".into(),
+                start_pos: 0,
+                end_pos: 0,
+                word_count: 0,
+                document_id: "d1".to_string(),
+                byte_start: i * 10,
+                byte_end: i * 10 + 9,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
+            })
+            .collect();
+
+        let answer = Answer::from_response("Yes [3], because [1, 3] and [7].".to_string(), &context);
+
+        let cited: Vec<(usize, &str, usize)> = answer
+            .citations
+            .iter()
+            .map(|c| (c.marker, c.chunk_id.as_str(), c.byte_start))
+            .collect();
+        assert_eq!(cited, vec![(3, "c2", 20), (1, "c0", 0)]);
+    }
+}
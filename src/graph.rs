@@ -0,0 +1,288 @@
+//! Entity relations extracted from chunks at ingest, and search expanded along them
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use crate::chunking::DocumentChunk;
+use crate::llm::{CompletionProvider, CompletionRequest};
+
+/// Each hop away from the entities a search found halves the score of the chunks it reaches
+pub const GRAPH_HOP_DECAY: f32 = 0.5;
+
+const GRAPH_PREAMBLE: &str = "List the relations the text states between named entities such as \
+people, organizations, places, products and concepts, one per line as `subject | relation | object`. \
+Respond with the lines only, or `none` if there are none.";
+
+/// One edge of the graph, as stated by some chunk of the document it is stored with
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Relation {
+    pub subject: String,
+    pub predicate: String,
+    pub object: String,
+}
+
+/// Relations stated across `chunks`, without duplicates; summary chunks are skipped
+pub fn extract_relations(llm: &dyn CompletionProvider, chunks: &[DocumentChunk]) -> Result<Vec<Relation>> {
+    let mut relations = BTreeSet::new();
+    for chunk in chunks.iter().filter(|c| !c.is_summary()) {
+        relations.extend(parse_relations(&llm.complete(&relations_request(chunk))?));
+    }
+    Ok(relations.into_iter().collect())
+}
+
+pub async fn extract_relations_async(llm: &dyn CompletionProvider, chunks: &[DocumentChunk]) -> Result<Vec<Relation>> {
+    let mut relations = BTreeSet::new();
+    for chunk in chunks.iter().filter(|c| !c.is_summary()) {
+        let (response, _) = llm.complete_async(&relations_request(chunk)).await?;
+        relations.extend(parse_relations(&response));
+    }
+    Ok(relations.into_iter().collect())
+}
+
+fn relations_request(chunk: &DocumentChunk) -> CompletionRequest {
+    CompletionRequest::new(format!("Text:\n{}\n\nRelations:", chunk.content))
+        .with_preamble(GRAPH_PREAMBLE)
+        .with_temperature(0.0)
+}
+
+/// Lines of `subject | relation | object`; anything else in the response is ignored
+pub fn parse_relations(response: &str) -> Vec<Relation> {
+    response
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim().trim_start_matches(['-', '*']).trim().trim_matches('`');
+            let parts: Vec<&str> = line.split('|').map(str::trim).collect();
+            match parts[..] {
+                [subject, predicate, object] if !subject.is_empty() && !object.is_empty() => Some(Relation {
+                    subject: subject.to_string(),
+                    predicate: predicate.to_string(),
+                    object: object.to_string(),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Undirected graph of entities, matched case-insensitively on whole words
+#[derive(Debug, Clone, Default)]
+pub struct KnowledgeGraph {
+    edges: BTreeMap<String, BTreeSet<String>>,
+    relations: usize,
+}
+
+impl KnowledgeGraph {
+    pub fn from_relations<'a>(relations: impl IntoIterator<Item = &'a Relation>) -> Self {
+        let mut graph = Self::default();
+        for relation in relations {
+            let (subject, object) = (normalize(&relation.subject), normalize(&relation.object));
+            if subject.is_empty() || object.is_empty() || subject == object {
+                continue;
+            }
+            graph.edges.entry(subject.clone()).or_default().insert(object.clone());
+            graph.edges.entry(object).or_default().insert(subject);
+            graph.relations += 1;
+        }
+        graph
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.edges.len()
+    }
+
+    pub fn relation_count(&self) -> usize {
+        self.relations
+    }
+
+    /// Entities of the graph named in `text`
+    pub fn entities_in(&self, text: &str) -> BTreeSet<String> {
+        named_in(text, self.edges.keys())
+    }
+
+    /// Entities within `hops` edges of `seeds`, with how many edges away they are; seeds are left out
+    pub fn neighbourhood(&self, seeds: &BTreeSet<String>, hops: usize) -> BTreeMap<String, usize> {
+        let mut reached: BTreeMap<String, usize> = BTreeMap::new();
+        let mut frontier: Vec<&String> = seeds.iter().collect();
+        for hop in 1..=hops {
+            let mut next = Vec::new();
+            for entity in frontier {
+                for neighbour in self.edges.get(entity).into_iter().flatten() {
+                    if !seeds.contains(neighbour) && !reached.contains_key(neighbour) {
+                        reached.insert(neighbour.clone(), hop);
+                        next.push(neighbour);
+                    }
+                }
+            }
+            frontier = next;
+        }
+        reached
+    }
+}
+
+/// Which chunks name which graph entities, worked out when documents are stored and removed, so a
+/// graph search looks up the chunks an entity reaches instead of scanning every chunk for it
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EntityLinks {
+    /// Relations each document states, by document id
+    relations: BTreeMap<String, Vec<Relation>>,
+    /// Entities each chunk names, by chunk id
+    chunks: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl EntityLinks {
+    /// Links for documents stating `relations`, by document id, and every chunk in `chunks`
+    pub fn build<'a>(
+        relations: impl IntoIterator<Item = (&'a str, &'a [Relation])>,
+        chunks: &HashMap<String, DocumentChunk>,
+    ) -> Self {
+        let mut links = Self::default();
+        for (doc_id, stated) in relations.into_iter().filter(|(_, stated)| !stated.is_empty()) {
+            links.relations.insert(doc_id.to_string(), stated.to_vec());
+        }
+        let graph = links.graph(|_| true);
+        if graph.edges.is_empty() {
+            return links;
+        }
+        for chunk in chunks.values().filter(|c| !c.is_summary()) {
+            links.link(chunk, graph.entities_in(&chunk.content));
+        }
+        links
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relations.is_empty()
+    }
+
+    /// Graph of the relations stated by the documents `include` accepts
+    pub fn graph(&self, include: impl Fn(&str) -> bool) -> KnowledgeGraph {
+        KnowledgeGraph::from_relations(self.relations.iter().filter(|(id, _)| include(id)).flat_map(|(_, r)| r))
+    }
+
+    /// Entities the chunk names, empty if it names none
+    pub fn entities_of(&self, chunk_id: &str) -> Option<&BTreeSet<String>> {
+        self.chunks.get(chunk_id)
+    }
+
+    /// Each linked chunk with the entities it names
+    pub fn linked_chunks(&self) -> impl Iterator<Item = (&String, &BTreeSet<String>)> {
+        self.chunks.iter()
+    }
+
+    /// Record the relations `doc_id` states, linking its chunks `own` to every entity of the graph, and
+    /// the rest of `chunks` to the entities it adds; only then are all chunks scanned
+    pub fn add_document(
+        &mut self,
+        doc_id: &str,
+        relations: Vec<Relation>,
+        own: &[String],
+        chunks: &HashMap<String, DocumentChunk>,
+    ) {
+        // Stored again, its relations may have changed
+        self.remove_document(doc_id, []);
+        let before = self.graph(|_| true);
+        if !relations.is_empty() {
+            self.relations.insert(doc_id.to_string(), relations);
+        }
+        let after = self.graph(|_| true);
+        if after.edges.is_empty() {
+            return;
+        }
+        for chunk in own.iter().filter_map(|id| chunks.get(id)).filter(|c| !c.is_summary()) {
+            self.link(chunk, after.entities_in(&chunk.content));
+        }
+        let added: Vec<&String> = after.edges.keys().filter(|entity| !before.edges.contains_key(*entity)).collect();
+        if added.is_empty() {
+            return;
+        }
+        for chunk in chunks.values().filter(|c| c.document_id != doc_id && !c.is_summary()) {
+            self.link(chunk, named_in(&chunk.content, added.iter().copied()));
+        }
+    }
+
+    fn link(&mut self, chunk: &DocumentChunk, entities: BTreeSet<String>) {
+        if !entities.is_empty() {
+            self.chunks.entry(chunk.id.clone()).or_default().extend(entities);
+        }
+    }
+
+    /// Forget `doc_id`'s relations and `chunk_ids`, and links to entities no other document states
+    pub fn remove_document<'a>(&mut self, doc_id: &str, chunk_ids: impl IntoIterator<Item = &'a str>) {
+        for chunk_id in chunk_ids {
+            self.chunks.remove(chunk_id);
+        }
+        if self.relations.remove(doc_id).is_some() {
+            let graph = self.graph(|_| true);
+            for entities in self.chunks.values_mut() {
+                entities.retain(|entity| graph.edges.contains_key(entity));
+            }
+            self.chunks.retain(|_, entities| !entities.is_empty());
+        }
+    }
+
+    /// Drop links of chunks `keep` rejects
+    pub fn retain_chunks(&mut self, keep: impl Fn(&str) -> bool) {
+        self.chunks.retain(|chunk_id, _| keep(chunk_id));
+    }
+}
+
+/// Which of `entities` `text` names, on word boundaries
+fn named_in<'a>(text: &str, entities: impl IntoIterator<Item = &'a String>) -> BTreeSet<String> {
+    let words = format!(" {} ", normalize(text));
+    entities.into_iter().filter(|entity| words.contains(&format!(" {} ", entity))).cloned().collect()
+}
+
+/// Lowercase words separated by single spaces, so phrases match on word boundaries
+fn normalize(text: &str) -> String {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_relations_and_walk_neighbourhood() {
+        let relations = parse_relations(
+            "- Alan Turing | worked at | Bletchley Park\nBletchley Park | located in | Buckinghamshire\nnone\nnot | a relation",
+        );
+        assert_eq!(relations.len(), 2);
+        assert_eq!(relations[0].subject, "Alan Turing");
+
+        let graph = KnowledgeGraph::from_relations(&relations);
+        assert_eq!((graph.entity_count(), graph.relation_count()), (3, 2));
+        let seeds = graph.entities_in("Where did alan turing work?");
+        assert_eq!(seeds, BTreeSet::from(["alan turing".to_string()]));
+        assert!(graph.entities_in("Turingmachines").is_empty());
+
+        let reached = graph.neighbourhood(&seeds, 2);
+        assert_eq!(reached.get("bletchley park"), Some(&1));
+        assert_eq!(reached.get("buckinghamshire"), Some(&2));
+        assert_eq!(graph.neighbourhood(&seeds, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_entity_links_follow_documents_in_and_out() {
+        use crate::testing::fake_chunk;
+        let mut chunks: HashMap<String, DocumentChunk> = HashMap::new();
+        let mut add = |chunk: DocumentChunk| chunks.insert(chunk.id.clone(), chunk);
+        add(fake_chunk("history", 0, "Bletchley Park hosted the codebreakers."));
+        add(fake_chunk("turing", 0, "Alan Turing worked at Bletchley Park."));
+
+        let mut links = EntityLinks::default();
+        links.add_document("history", Vec::new(), &["history_0".to_string()], &chunks);
+        assert!(links.entities_of("history_0").is_none());
+        let relations = parse_relations("Alan Turing | worked at | Bletchley Park");
+        links.add_document("turing", relations, &["turing_0".to_string()], &chunks);
+        // The earlier document's chunk now names an entity the later one added
+        assert_eq!(links.entities_of("history_0"), Some(&BTreeSet::from(["bletchley park".to_string()])));
+        assert_eq!(links.entities_of("turing_0").unwrap().len(), 2);
+
+        links.remove_document("turing", ["turing_0"]);
+        assert!(links.is_empty());
+        assert_eq!(links.linked_chunks().count(), 0);
+    }
+}
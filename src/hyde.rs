@@ -0,0 +1,28 @@
+//! HyDE: retrieve with the embedding of a hypothetical answer instead of the bare query
+
+use anyhow::Result;
+use crate::llm::{CompletionProvider, CompletionRequest};
+
+const HYDE_PREAMBLE: &str = "Write a short passage that answers the question, in the style of \
+the documentation it would appear in. It is fine to guess details; it is only used for search. \
+Respond with the passage only.";
+
+/// A plausible answer to `query`, whose embedding sits closer to relevant chunks than a terse query does
+pub fn hypothetical_document(llm: &dyn CompletionProvider, query: &str) -> Result<String> {
+    let passage = llm.complete(&hyde_request(query))?.trim().to_string();
+    tracing::debug!("HyDE passage for '{}': {}", query, passage);
+    Ok(passage)
+}
+
+pub async fn hypothetical_document_async(llm: &dyn CompletionProvider, query: &str) -> Result<String> {
+    let (passage, _) = llm.complete_async(&hyde_request(query)).await?;
+    let passage = passage.trim().to_string();
+    tracing::debug!("HyDE passage for '{}': {}", query, passage);
+    Ok(passage)
+}
+
+fn hyde_request(query: &str) -> CompletionRequest {
+    CompletionRequest::new(format!("Question: {}\n\nPassage:", query))
+        .with_preamble(HYDE_PREAMBLE)
+        .with_max_tokens(256)
+}
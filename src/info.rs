@@ -0,0 +1,75 @@
+//! What an index is made of and how current it is, as reported by `rag-system info`
+
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use crate::chunking::ChunkingStrategy;
+use crate::config::StorageBackend;
+use crate::processor::ProcessedDocument;
+use crate::search::SearchMode;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub backend: StorageBackend,
+    /// Index file, `None` for the in-memory backend
+    pub data_path: Option<PathBuf>,
+    /// Strategy applied to documents processed from now on
+    pub chunking: ChunkingStrategy,
+    pub search_mode: SearchMode,
+    /// Model the stored embeddings came from
+    pub embedding_model: Option<String>,
+    pub embedding_dimensions: Option<usize>,
+    /// Tenant the counts are scoped to, `None` for the default tenant
+    pub tenant: Option<String>,
+    /// Every tenant with documents in the index
+    pub tenants: Vec<String>,
+    pub documents: usize,
+    pub chunks: usize,
+    pub freshness: IndexFreshness,
+}
+
+/// What `SimpleRagSystem::warm_up` touched before the first query
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct WarmUpReport {
+    pub documents: usize,
+    pub chunks: usize,
+    pub embeddings: usize,
+    /// Logged queries whose embeddings are now cached, most frequent first
+    pub queries_primed: usize,
+    pub elapsed_ms: f64,
+}
+
+/// How far the index has drifted from its source files
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IndexFreshness {
+    /// When the index file was last written, in seconds since the Unix epoch
+    pub last_written: Option<u64>,
+    /// Documents whose source file changed since it was processed
+    pub stale_documents: usize,
+    /// Documents whose source file no longer exists
+    pub missing_sources: usize,
+    /// Chunks with no embedding although the index has an embedding model
+    pub unembedded_chunks: usize,
+}
+
+impl IndexFreshness {
+    pub fn is_current(&self) -> bool {
+        self.stale_documents == 0 && self.missing_sources == 0 && self.unembedded_chunks == 0
+    }
+
+    /// Compare each document against its file on disk
+    pub(crate) fn check_sources(&mut self, documents: &[ProcessedDocument]) {
+        for doc in documents {
+            match std::fs::read_to_string(&doc.metadata.file_path) {
+                Ok(current) if current == doc.content => {}
+                Ok(_) => self.stale_documents += 1,
+                Err(_) => self.missing_sources += 1,
+            }
+        }
+    }
+}
+
+/// Modification time of `path` in seconds since the Unix epoch
+pub(crate) fn modified_secs(path: &Path) -> Option<u64> {
+    let modified = std::fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(std::time::UNIX_EPOCH).ok()?.as_secs())
+}
@@ -0,0 +1,239 @@
+//! Ingesting whole directories and glob patterns, and rebuilding the index from stored documents
+
+use anyhow::{anyhow, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Extensions ingested when no `include` pattern is given
+pub const DEFAULT_EXTENSIONS: &[&str] = &["txt", "md", "markdown", "rst", "text"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IngestOptions {
+    /// Globs a file must match, relative to the ingested directory; empty means the default extensions
+    pub include: Vec<String>,
+    /// Globs excluding files even when included
+    pub exclude: Vec<String>,
+    /// Process and chunk files but store nothing, reporting `WouldIngest` outcomes instead
+    pub dry_run: bool,
+    /// Threads per stage of the ingest pipeline, so reading and chunking overlap with embedding;
+    /// 0 or 1 processes one file at a time
+    pub workers: usize,
+}
+
+/// What processing a file would store, without storing it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IngestPreview {
+    /// ID the document was assigned in this run; a real ingest assigns a fresh one
+    pub document_id: String,
+    pub file_type: String,
+    pub file_size: usize,
+    pub word_count: usize,
+    pub chunks: usize,
+    /// Word count of each chunk, in document order
+    pub chunk_words: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum IngestOutcome {
+    Ingested { document_id: String, chunks: usize },
+    /// Dry run: the file would have been ingested like this
+    WouldIngest(IngestPreview),
+    Skipped { reason: String },
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IngestedFile {
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub outcome: IngestOutcome,
+}
+
+/// What happened to every file found under the ingested path
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub files: Vec<IngestedFile>,
+}
+
+impl IngestReport {
+    pub fn ingested(&self) -> usize {
+        self.count(|o| matches!(o, IngestOutcome::Ingested { .. }))
+    }
+
+    pub fn would_ingest(&self) -> usize {
+        self.count(|o| matches!(o, IngestOutcome::WouldIngest(_)))
+    }
+
+    pub fn skipped(&self) -> usize {
+        self.count(|o| matches!(o, IngestOutcome::Skipped { .. }))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, IngestOutcome::Failed { .. }))
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.files
+            .iter()
+            .map(|f| match &f.outcome {
+                IngestOutcome::Ingested { chunks, .. } => *chunks,
+                IngestOutcome::WouldIngest(preview) => preview.chunks,
+                _ => 0,
+            })
+            .sum()
+    }
+
+    fn count(&self, predicate: impl Fn(&IngestOutcome) -> bool) -> usize {
+        self.files.iter().filter(|f| predicate(&f.outcome)).count()
+    }
+}
+
+/// Index shape before and after `SimpleRagSystem::reindex`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReindexReport {
+    pub documents: usize,
+    pub chunks_before: usize,
+    pub chunks_after: usize,
+    pub embeddings_before: usize,
+    pub embeddings_after: usize,
+    pub embedding_model_before: Option<String>,
+    pub embedding_model_after: Option<String>,
+}
+
+/// Files under `path` to ingest, and ones found but skipped with the reason.
+///
+/// `path` may be a file, a directory searched recursively, or a glob such as `docs/**/*.md`.
+/// Hidden files and directories are ignored altogether.
+pub fn discover_files(path: &Path, options: &IngestOptions) -> Result<(Vec<PathBuf>, Vec<IngestedFile>)> {
+    let mut options = options.clone();
+    let root = if path.exists() {
+        path.to_path_buf()
+    } else {
+        let (root, pattern) = split_glob(path).ok_or_else(|| anyhow!("{} does not exist", path.display()))?;
+        options.include.push(pattern);
+        root
+    };
+
+    if root.is_file() {
+        return Ok((vec![root], Vec::new()));
+    }
+
+    let include = build_globs(&options.include)?;
+    let exclude = build_globs(&options.exclude)?;
+    let mut files = Vec::new();
+    walk(&root, &mut files)?;
+    files.sort();
+
+    let mut selected = Vec::new();
+    let mut skipped = Vec::new();
+    for file in files {
+        let relative = file.strip_prefix(&root).unwrap_or(&file);
+        let reason = if exclude.as_ref().is_some_and(|set| set.is_match(relative)) {
+            Some("excluded")
+        } else {
+            match &include {
+                Some(set) if !set.is_match(relative) => Some("not matched by --include"),
+                Some(_) => None,
+                None if !has_default_extension(&file) => Some("unsupported file type"),
+                None => None,
+            }
+        };
+        match reason {
+            Some(reason) => {
+                tracing::debug!("Skipping {}: {}", file.display(), reason);
+                skipped.push(IngestedFile {
+                    path: file,
+                    outcome: IngestOutcome::Skipped { reason: reason.to_string() },
+                })
+            }
+            None => selected.push(file),
+        }
+    }
+    Ok((selected, skipped))
+}
+
+fn walk(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            walk(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn build_globs(patterns: &[String]) -> Result<Option<GlobSet>> {
+    if patterns.is_empty() {
+        return Ok(None);
+    }
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(Glob::new(pattern).map_err(|e| anyhow!("Invalid glob '{}': {}", pattern, e))?);
+    }
+    Ok(Some(builder.build()?))
+}
+
+fn has_default_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| DEFAULT_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+}
+
+/// Directory before the first component with glob characters, and the rest as a pattern
+fn split_glob(path: &Path) -> Option<(PathBuf, String)> {
+    let mut root = PathBuf::new();
+    let mut components = path.components();
+    for component in components.by_ref() {
+        let part = component.as_os_str().to_string_lossy();
+        if part.contains(['*', '?', '[', '{']) {
+            let rest: PathBuf = std::iter::once(component).chain(components).collect();
+            let root = if root.as_os_str().is_empty() { PathBuf::from(".") } else { root };
+            return Some((root, rest.to_string_lossy().to_string()));
+        }
+        root.push(component);
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_discovers_files_by_extension_and_glob() {
+        let root = Path::new("/tmp/test_rag_ingest_dir");
+        let _ = std::fs::remove_dir_all(root);
+        std::fs::create_dir_all(root.join("guides/.git")).unwrap();
+        for file in ["a.md", "b.txt", "image.png", "guides/c.md", "guides/.git/config.txt"] {
+            std::fs::write(root.join(file), "text").unwrap();
+        }
+
+        let (selected, skipped) = discover_files(root, &IngestOptions::default()).unwrap();
+        assert_eq!(selected, vec![root.join("a.md"), root.join("b.txt"), root.join("guides/c.md")]);
+        assert_eq!(skipped.len(), 1);
+        assert_eq!(skipped[0].outcome, IngestOutcome::Skipped { reason: "unsupported file type".to_string() });
+
+        let (selected, _) = discover_files(&root.join("**/*.md"), &IngestOptions::default()).unwrap();
+        assert_eq!(selected, vec![root.join("a.md"), root.join("guides/c.md")]);
+
+        let options = IngestOptions {
+            include: vec!["*.md".to_string()],
+            exclude: vec!["guides/**".to_string()],
+            ..Default::default()
+        };
+        let (selected, skipped) = discover_files(root, &options).unwrap();
+        assert_eq!(selected, vec![root.join("a.md")]);
+        assert_eq!(skipped.len(), 3);
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,262 @@
+//! Inverted file (IVF) vector index: k-means splits the embeddings into lists around centroids, and a
+//! search only scores the chunks in the `nprobe` lists whose centroids are nearest the query.
+//!
+//! Clustering is spherical, on unit vectors by dot product, since chunks are ranked by cosine. The
+//! index keeps only the centroids and the chunk ids in each list; vectors stay in storage. Chunks
+//! embedded after training join the list of their nearest centroid.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use crate::disk_index::{dot, normalize, Xorshift};
+
+/// Rounds of reassigning vectors and moving centroids; training stops early once no vector moves
+const MAX_ITERATIONS: usize = 25;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IvfConfig {
+    /// Lists to train; the square root of the chunk count when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lists: Option<usize>,
+    /// Nearest lists a search scores; more means better recall and slower searches
+    pub nprobe: usize,
+}
+
+impl Default for IvfConfig {
+    fn default() -> Self {
+        Self { lists: None, nprobe: 8 }
+    }
+}
+
+impl IvfConfig {
+    pub fn is_default(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "StoredIvfIndex")]
+pub struct IvfIndex {
+    /// Unit-length centre of each list
+    pub centroids: Vec<Vec<f32>>,
+    /// Ids of the chunks in each list
+    pub postings: Vec<Vec<String>>,
+    /// Unix seconds when it was trained
+    pub trained_at: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+/// An index as snapshots hold it; those written before posting lists were kept have each chunk's
+/// list instead
+#[derive(Deserialize)]
+struct StoredIvfIndex {
+    centroids: Vec<Vec<f32>>,
+    #[serde(default)]
+    postings: Vec<Vec<String>>,
+    #[serde(default)]
+    assignments: HashMap<String, usize>,
+    trained_at: u64,
+    #[serde(default)]
+    tenant: Option<String>,
+}
+
+impl From<StoredIvfIndex> for IvfIndex {
+    fn from(stored: StoredIvfIndex) -> Self {
+        let mut postings = stored.postings;
+        postings.resize(stored.centroids.len(), Vec::new());
+        let mut assignments: Vec<(String, usize)> = stored.assignments.into_iter().collect();
+        assignments.sort();
+        for (id, list) in assignments {
+            if let Some(posting) = postings.get_mut(list) {
+                posting.push(id);
+            }
+        }
+        Self { centroids: stored.centroids, postings, trained_at: stored.trained_at, tenant: stored.tenant }
+    }
+}
+
+impl IvfIndex {
+    /// Cluster `vectors` into `lists` lists, seeded k-means++ style
+    pub fn train(vectors: &HashMap<String, Vec<f32>>, lists: usize, now: u64) -> Result<Self> {
+        if vectors.is_empty() {
+            return Err(anyhow!("No embeddings to train an IVF index on"));
+        }
+        if lists == 0 {
+            return Err(anyhow!("An IVF index needs at least one list"));
+        }
+        let mut ids: Vec<&String> = vectors.keys().collect();
+        ids.sort();
+        let points: Vec<Vec<f32>> = ids.iter().map(|id| normalize(&vectors[*id])).collect();
+        let lists = lists.min(points.len());
+
+        let mut random = Xorshift(0x2545_f491_4f6c_dd1d);
+        let mut centroids = vec![points[random.below(points.len())].clone()];
+        while centroids.len() < lists {
+            let distances: Vec<f32> = points.iter().map(|p| 1.0 - nearest(&centroids, p).1).collect();
+            centroids.push(points[weighted_pick(&distances, &mut random)].clone());
+        }
+
+        let mut assignments = vec![usize::MAX; points.len()];
+        for _ in 0..MAX_ITERATIONS {
+            let mut moved = false;
+            for (point, assignment) in points.iter().zip(&mut assignments) {
+                let list = nearest(&centroids, point).0;
+                moved |= *assignment != list;
+                *assignment = list;
+            }
+            if !moved {
+                break;
+            }
+            let mut sums = vec![vec![0.0f32; points[0].len()]; centroids.len()];
+            for (point, &list) in points.iter().zip(&assignments) {
+                sums[list].iter_mut().zip(point).for_each(|(s, x)| *s += x);
+            }
+            for (centroid, sum) in centroids.iter_mut().zip(sums) {
+                // A list left empty keeps its centroid and may win vectors back next round
+                if sum.iter().any(|x| *x != 0.0) {
+                    *centroid = normalize(&sum);
+                }
+            }
+        }
+
+        let mut postings = vec![Vec::new(); centroids.len()];
+        for (id, list) in ids.into_iter().zip(assignments) {
+            postings[list].push(id.clone());
+        }
+        Ok(Self { centroids, postings, trained_at: now, tenant: None })
+    }
+
+    pub fn lists(&self) -> usize {
+        self.centroids.len()
+    }
+
+    /// Chunks in the lists
+    pub fn len(&self) -> usize {
+        self.postings.iter().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.postings.iter().all(Vec::is_empty)
+    }
+
+    /// The `nprobe` lists whose centroids are nearest `query`
+    pub fn probe(&self, query: &[f32], nprobe: usize) -> Vec<usize> {
+        let query = self.prepare(query);
+        let mut lists: Vec<(usize, f32)> = self.centroids.iter().map(|c| dot(c, &query)).enumerate().collect();
+        lists.sort_by(|a, b| b.1.total_cmp(&a.1));
+        lists.into_iter().take(nprobe).map(|(list, _)| list).collect()
+    }
+
+    /// Chunks in `lists`, read from their posting lists alone
+    pub fn chunks_in(&self, lists: &[usize]) -> HashSet<&str> {
+        lists.iter().filter_map(|&list| self.postings.get(list)).flatten().map(String::as_str).collect()
+    }
+
+    /// Put newly embedded chunks in the lists of their nearest centroids, moving any that were in others
+    pub fn add(&mut self, vectors: &HashMap<String, Vec<f32>>) {
+        if self.centroids.is_empty() {
+            return;
+        }
+        self.remove(|id| vectors.contains_key(id));
+        let mut ids: Vec<&String> = vectors.keys().collect();
+        ids.sort();
+        for id in ids {
+            let list = nearest(&self.centroids, &self.prepare(&vectors[id])).0;
+            self.postings[list].push(id.clone());
+        }
+    }
+
+    /// Drop the chunks `removed` picks from every list
+    pub fn remove(&mut self, mut removed: impl FnMut(&str) -> bool) {
+        for posting in &mut self.postings {
+            posting.retain(|id| !removed(id));
+        }
+    }
+
+    /// `vector` cut to the centroids' dimensions and normalized
+    fn prepare(&self, vector: &[f32]) -> Vec<f32> {
+        normalize(&vector[..vector.len().min(self.dimensions())])
+    }
+
+    fn dimensions(&self) -> usize {
+        self.centroids.first().map_or(0, Vec::len)
+    }
+}
+
+/// List count used when `IvfConfig::lists` is unset
+pub fn default_lists(vectors: usize) -> usize {
+    ((vectors as f64).sqrt().round() as usize).max(1)
+}
+
+/// Index and similarity of the centroid nearest `point`
+fn nearest(centroids: &[Vec<f32>], point: &[f32]) -> (usize, f32) {
+    centroids
+        .iter()
+        .map(|c| dot(c, point))
+        .enumerate()
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .unwrap_or((0, 0.0))
+}
+
+/// Position picked with probability proportional to its weight squared, or uniformly if all are zero
+fn weighted_pick(weights: &[f32], random: &mut Xorshift) -> usize {
+    let total: f64 = weights.iter().map(|w| (*w as f64).powi(2)).sum();
+    if total <= 0.0 {
+        return random.below(weights.len());
+    }
+    let mut target = random.below(1 << 24) as f64 / (1 << 24) as f64 * total;
+    for (i, w) in weights.iter().enumerate() {
+        target -= (*w as f64).powi(2);
+        if target <= 0.0 {
+            return i;
+        }
+    }
+    weights.len() - 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probing_finds_the_query_cluster() {
+        // Three well separated clusters of ten vectors each
+        let mut random = Xorshift(7);
+        let mut vectors = HashMap::new();
+        for (cluster, centre) in [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]].iter().enumerate() {
+            for i in 0..10 {
+                let vector = centre.iter().map(|x| x + random.below(100) as f32 / 1000.0).collect();
+                vectors.insert(format!("c{}_{}", cluster, i), vector);
+            }
+        }
+        let index = IvfIndex::train(&vectors, 3, 0).unwrap();
+        assert_eq!((index.lists(), index.len()), (3, 30));
+        let lists = index.probe(&[0.0, 0.9, 0.1], 1);
+        let probed = index.chunks_in(&lists);
+        assert_eq!(probed.len(), 10);
+        assert!((0..10).all(|i| probed.contains(format!("c1_{}", i).as_str())));
+
+        // Chunks embedded later join their nearest list, and move with a new embedding
+        let mut index = index;
+        index.add(&HashMap::from([("added_later".to_string(), vec![0.1, 1.0, 0.0])]));
+        assert!(index.chunks_in(&lists).contains("added_later"));
+        index.add(&HashMap::from([("added_later".to_string(), vec![1.0, 0.1, 0.0])]));
+        assert!(!index.chunks_in(&lists).contains("added_later"));
+        assert_eq!(index.len(), 31);
+        index.remove(|id| id.starts_with("c1_"));
+        assert!(index.chunks_in(&lists).is_empty());
+
+        // Snapshots written before posting lists were kept
+        let legacy = r#"{"centroids": [[1.0, 0.0], [0.0, 1.0]], "assignments": {"a": 1, "b": 0}, "trained_at": 5}"#;
+        let legacy: IvfIndex = serde_json::from_str(legacy).unwrap();
+        assert_eq!(legacy.postings, [vec!["b".to_string()], vec!["a".to_string()]]);
+        let round_trip: IvfIndex = serde_json::from_str(&serde_json::to_string(&index).unwrap()).unwrap();
+        assert_eq!(round_trip, index);
+
+        assert_eq!(IvfIndex::train(&vectors, 100, 0).unwrap().lists(), 30);
+        assert!(IvfIndex::train(&HashMap::new(), 3, 0).is_err());
+        assert_eq!(default_lists(10_000), 100);
+    }
+}
@@ -0,0 +1,137 @@
+//! RAKE-style keyphrase extraction run on each chunk at ingest.
+//!
+//! Stopwords and punctuation split the text into candidate phrases. A word scores its degree, the
+//! total length of the phrases it appears in, over its frequency, and a phrase the sum of its words,
+//! so longer phrases built from words that rarely stand alone come first.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::chunking::DocumentChunk;
+
+/// Keyphrases kept per chunk
+pub const KEYPHRASES_PER_CHUNK: usize = 5;
+
+/// Longer candidates are usually run-on text rather than a phrase
+const MAX_PHRASE_WORDS: usize = 4;
+
+pub(crate) const STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "against", "all", "also", "am", "an", "and", "any", "are", "as", "at",
+    "be", "because", "been", "before", "being", "below", "between", "both", "but", "by", "can", "could", "did",
+    "do", "does", "doing", "down", "during", "each", "few", "for", "from", "further", "had", "has", "have",
+    "having", "he", "her", "here", "hers", "him", "his", "how", "i", "if", "in", "into", "is", "it", "its",
+    "itself", "just", "may", "me", "might", "more", "most", "must", "my", "no", "nor", "not", "now", "of", "off",
+    "on", "once", "only", "or", "other", "our", "ours", "out", "over", "own", "same", "she", "should", "so",
+    "some", "such", "than", "that", "the", "their", "them", "then", "there", "these", "they", "this", "those",
+    "through", "to", "too", "under", "until", "up", "use", "used", "using", "very", "was", "we", "were", "what",
+    "when", "where", "which", "while", "who", "whom", "why", "will", "with", "would", "you", "your",
+];
+
+/// The top `limit` keyphrases of `text`, lowercased, best first; ties keep the order of first mention
+pub fn extract_keyphrases(text: &str, limit: usize) -> Vec<String> {
+    let phrases = candidate_phrases(text);
+    let mut frequency: HashMap<&str, f32> = HashMap::new();
+    let mut degree: HashMap<&str, f32> = HashMap::new();
+    for phrase in &phrases {
+        for word in phrase {
+            *frequency.entry(word).or_insert(0.0) += 1.0;
+            *degree.entry(word).or_insert(0.0) += phrase.len() as f32;
+        }
+    }
+
+    let mut scored: Vec<(String, f32)> = Vec::new();
+    for phrase in &phrases {
+        let text = phrase.join(" ");
+        if scored.iter().any(|(seen, _)| *seen == text) {
+            continue;
+        }
+        let score = phrase.iter().map(|w| degree[w.as_str()] / frequency[w.as_str()]).sum();
+        scored.push((text, score));
+    }
+    scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+    scored.into_iter().take(limit).map(|(phrase, _)| phrase).collect()
+}
+
+/// A keyphrase of a document, with how many of its chunks it is a top phrase of
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentKeyphrase {
+    pub phrase: String,
+    pub chunks: usize,
+}
+
+/// What a document covers: its chunks' keyphrases, most widespread first, then in document order
+pub fn document_keyphrases(chunks: &[DocumentChunk], limit: usize) -> Vec<DocumentKeyphrase> {
+    let mut ordered: Vec<&DocumentChunk> = chunks.iter().filter(|c| !c.is_summary()).collect();
+    ordered.sort_by_key(|c| c.start_pos);
+    let mut phrases: Vec<DocumentKeyphrase> = Vec::new();
+    for phrase in ordered.iter().flat_map(|c| c.keyphrases.iter()) {
+        match phrases.iter_mut().find(|p| p.phrase == *phrase) {
+            Some(existing) => existing.chunks += 1,
+            None => phrases.push(DocumentKeyphrase { phrase: phrase.clone(), chunks: 1 }),
+        }
+    }
+    phrases.sort_by_key(|p| std::cmp::Reverse(p.chunks));
+    phrases.truncate(limit);
+    phrases
+}
+
+/// Whether text, given as its lowercased words, contains the keyphrase
+pub(crate) fn mentioned_in(phrase: &str, text_words: &[String]) -> bool {
+    let phrase: Vec<&str> = phrase.split_whitespace().collect();
+    !phrase.is_empty() && text_words.windows(phrase.len()).any(|window| window.iter().eq(phrase.iter()))
+}
+
+/// Runs of content words between stopwords and punctuation; numbers and single letters are dropped
+fn candidate_phrases(text: &str) -> Vec<Vec<String>> {
+    let mut phrases = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    let mut flush = |current: &mut Vec<String>| {
+        if !current.is_empty() && current.len() <= MAX_PHRASE_WORDS {
+            phrases.push(std::mem::take(current));
+        }
+        current.clear();
+    };
+    for raw in text.split_whitespace() {
+        let breaks_after = raw.ends_with([',', '.', ';', ':', '!', '?', ')', '"']);
+        let word = raw.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase();
+        let content = word.chars().count() > 1 && !word.chars().all(|c| c.is_numeric());
+        if !content || STOPWORDS.contains(&word.as_str()) || raw.starts_with(['(', '"']) {
+            flush(&mut current);
+        }
+        if content && !STOPWORDS.contains(&word.as_str()) {
+            current.push(word);
+        }
+        if breaks_after {
+            flush(&mut current);
+        }
+    }
+    flush(&mut current);
+    phrases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyphrases_prefer_multiword_phrases() {
+        let text = "Rust has a borrow checker. The borrow checker runs at compile time, and memory safety \
+                    follows from it (mostly).";
+        assert_eq!(
+            extract_keyphrases(text, 4),
+            ["memory safety follows", "borrow checker runs", "borrow checker", "compile time"]
+        );
+        assert!(extract_keyphrases("the and of 42", 3).is_empty());
+    }
+
+    #[test]
+    fn test_document_keyphrases_count_chunks() {
+        let chunks = [
+            crate::testing::fake_chunk("doc", 0, "The borrow checker, explained."),
+            crate::testing::fake_chunk("doc", 1, "Lifetimes and the borrow checker."),
+        ];
+        let phrases = document_keyphrases(&chunks, 2);
+        assert_eq!(phrases[0], DocumentKeyphrase { phrase: "borrow checker".to_string(), chunks: 2 });
+        assert_eq!(phrases.len(), 2);
+        assert!(mentioned_in("borrow checker", &["the".into(), "borrow".into(), "checker".into()]));
+    }
+}
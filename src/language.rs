@@ -0,0 +1,47 @@
+//! Guessing the language a document is written in from its most common function words
+
+use crate::entities::words;
+
+/// Tag that states a document's language, in place of the detected one
+pub const LANGUAGE_TAG: &str = "language";
+
+/// Words looked at; the opening of a document is enough to tell its language
+const SAMPLE_WORDS: usize = 2000;
+/// Function words a sample must hold before it is judged at all
+const MIN_HITS: usize = 3;
+
+/// ISO 639-1 code of each language and words common in it but rare in the others
+const FUNCTION_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "of", "to", "is", "in", "that", "it", "with", "for", "are", "this", "be", "on", "was"]),
+    ("de", &["der", "die", "und", "das", "ist", "nicht", "mit", "ein", "eine", "den", "auf", "sich", "von", "zu", "ich"]),
+    ("fr", &["le", "les", "et", "est", "une", "dans", "des", "du", "pour", "qui", "pas", "sur", "au", "avec", "ce"]),
+    ("es", &["el", "los", "las", "y", "es", "una", "del", "por", "con", "para", "que", "se", "al", "como", "su"]),
+    ("it", &["il", "gli", "della", "che", "di", "e", "è", "una", "per", "non", "con", "sono", "nel", "alla", "questo"]),
+    ("nl", &["de", "het", "een", "en", "van", "is", "niet", "dat", "op", "voor", "met", "zijn", "ook", "er", "maar"]),
+    ("pt", &["o", "os", "as", "e", "uma", "do", "da", "em", "para", "não", "com", "que", "por", "dos", "mais"]),
+];
+
+/// The language most of `text`'s function words belong to, or `None` when too few are known
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let sample: Vec<String> = words(text).into_iter().take(SAMPLE_WORDS).collect();
+    let hits = |common: &[&str]| sample.iter().filter(|word| common.contains(&word.as_str())).count();
+    let (language, best) = FUNCTION_WORDS
+        .iter()
+        .map(|(language, common)| (*language, hits(common)))
+        .max_by_key(|(_, hits)| *hits)?;
+    (best >= MIN_HITS).then_some(language)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detects_common_languages() {
+        assert_eq!(detect_language("The policy is clear: staff are entitled to leave with pay for this year."), Some("en"));
+        assert_eq!(detect_language("Die Mitarbeiter haben Anspruch auf Urlaub, und das ist nicht verhandelbar."), Some("de"));
+        assert_eq!(detect_language("Les employés ont droit à des congés payés pour une année dans le contrat."), Some("fr"));
+        assert_eq!(detect_language("Los empleados tienen derecho a vacaciones pagadas por el año, como es normal."), Some("es"));
+        assert_eq!(detect_language("Kubernetes Helm Terraform"), None);
+    }
+}
@@ -1,118 +1,3726 @@
 //! Minimal Working RAG System MVP
 
-use std::path::Path;
-use std::collections::HashMap;
+use anyhow::anyhow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::time::{Duration, Instant};
+use tracing::field::Empty;
+use crate::answer_cache::{AnswerCacheConfig, CachedAnswer};
+use crate::bench::{elapsed_ms, BenchReport, BenchSetup, IngestThroughput, LatencyStats, QuantizedBench};
+use crate::builder::SimpleRagSystemBuilder;
+use crate::chat::ChatSession;
+use crate::chunking::{ChunkingEngine, ChunkingStrategy, DocumentChunk};
+use crate::config::{RagConfig, StorageBackend};
+use crate::context::{ContextBuilder, ContextOrder};
+use crate::disk_index::DiskIndex;
+use crate::embedding::EmbeddingProvider;
+use crate::facets::{FacetedResults, Facets};
+use crate::feedback::FeedbackEntry;
+use crate::evaluation::{
+    ComparisonReport, DatasetEvaluation, EvaluationDataset, EvaluationMetrics, Evaluator, FaithfulnessEvaluator,
+    JudgedMetrics, QuantizationReport, RagEvaluationReport, RelevanceJudge,
+};
+use crate::graph::{extract_relations, KnowledgeGraph, GRAPH_HOP_DECAY};
+use crate::generation::{Answer, PROMPT_TOKEN_RESERVE, PromptTemplate};
+use crate::hyde::hypothetical_document;
+use crate::info::{modified_secs, IndexFreshness, SystemInfo, WarmUpReport};
+use crate::keyphrases::{document_keyphrases, DocumentKeyphrase};
+use crate::language::detect_language;
+use crate::ivf::IvfIndex;
+use crate::ingest::{
+    discover_files, IngestOptions, IngestOutcome, IngestPreview, IngestReport, IngestedFile, ReindexReport,
+};
+use crate::llm::{CompletionProvider, DEFAULT_CONTEXT_WINDOW};
+use crate::ltr::{RankingModel, TrainingExample, TrainingReport};
+use crate::metrics::Metrics;
+use crate::multihop::{MultiHopAnswer, MultiHopRetriever};
+use crate::processor::{DocumentMetadata, DocumentProcessor, IngestProgress, IngestProgressCallback, ProcessedDocument};
+use crate::quantization::{quantized_copy, EmbeddingQuantization, EmbeddingVector, StoredEmbedding};
+use crate::audit::{AuditAction, AuditEntry, AuditFilter};
+use crate::webhook::{IndexEvent, WebhookNotifier, WebhookPayload};
+use crate::plugins::{PluginConfig, PluginRegistry};
+use crate::query::{Query, TypedQuery};
+use crate::saved::{SavedSearch, DEFAULT_SAVED_SEARCH_LIMIT};
+use crate::session::SearchSession;
+use crate::sort::ResultSort;
+use crate::query_log::{export_jsonl, QueryLogConfig, QueryLogEntry};
+use crate::dedupe::{find_duplicate_chunks, find_duplicates, ChunkDuplicateGroup, DuplicateReport};
+use crate::ragpack::Ragpack;
+use crate::regression::{RegressionHarness, RegressionReport};
+use crate::related::{
+    centroid_similarity, embedding_centroid, term_profile, term_similarity, weight_by_idf, RelatedDocument, Similarity,
+};
+use crate::search::{
+    ChunkSignals, QueryBatchResult, SearchConfig, SearchContext, SearchEngine, SearchMode, SearchResult, TermStats,
+    VectorIndex,
+};
+use crate::storage::{
+    shard_of, CorpusStats, EvaluationRun, GcReport, IntegrityReport, StorageManager, StorageSnapshot, StorageStats,
+    TrashedDocument,
+};
+use crate::structured::{complete_structured, structured_request, StructuredAnswer};
+use crate::summary::{document_representatives, summarize_document, summary_chunk};
+use crate::synonyms::SynonymDictionary;
+use crate::synthetic::QaGenerator;
+use crate::tags::{DocumentBoost, DocumentFilter};
+use crate::translate::translate_query;
+use crate::usage::{MeteredCompletionProvider, MeteredEmbeddingProvider, Usage, UsageMeter};
 
 pub mod chunking;
 pub mod processor;
 pub mod search;
+pub mod feedback;
+pub mod facets;
+pub mod ltr;
+pub mod quantization;
+pub mod disk_index;
+pub mod ivf;
+pub mod pq;
+pub mod analysis;
 pub mod storage;
 pub mod evaluation;
+pub mod llm;
+pub mod regression;
+pub mod synthetic;
+pub mod statistics;
+pub mod ragas;
+pub mod generation;
+pub mod chat;
+pub mod embedding;
+pub mod hyde;
+pub mod context;
+pub mod multihop;
+pub mod summary;
+pub mod graph;
+pub mod entities;
+pub mod language;
+pub mod keyphrases;
+pub mod related;
+pub mod dedupe;
+pub mod minhash;
+pub mod translate;
+pub mod synonyms;
+#[cfg(feature = "rig")]
+pub mod tool;
+pub mod structured;
+pub mod answer_cache;
+pub mod usage;
+pub mod mcp;
+pub mod config;
+pub mod ragpack;
+pub mod backup;
+pub mod ingest;
+pub mod bench;
+pub mod watch;
+pub mod doctor;
+pub mod tags;
+pub mod query;
+pub mod saved;
+pub mod session;
+pub mod sort;
+pub mod info;
+pub mod builder;
+pub mod metrics;
+pub mod query_log;
+pub mod audit;
+pub mod webhook;
+pub mod plugins;
+pub mod testing;
+pub mod auth;
+pub mod ratelimit;
+pub mod server;
+pub mod prelude;
+mod async_rag;
+mod pipeline;
+
+/// std's clock, except in a browser, where reading it panics and `web-time` asks JavaScript instead
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+pub(crate) use std::time;
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+pub(crate) use web_time as time;
 
-pub use chunking::*;
-pub use processor::*;
-pub use search::*;
-pub use storage::*;
-pub use evaluation::*;
 
 /// Simple RAG system that ties everything together
 pub struct SimpleRagSystem {
+    processor: DocumentProcessor,
     chunker: ChunkingEngine,
     searcher: SearchEngine,
     storage: StorageManager,
+    completion: Option<Arc<dyn CompletionProvider>>,
+    embedder: Option<Arc<dyn EmbeddingProvider>>,
+    prompt_template: PromptTemplate,
+    context_chunks: usize,
+    context_tokens: Option<usize>,
+    context_order: ContextOrder,
+    min_retrieval_score: f32,
+    hyde: bool,
+    summarize: bool,
+    extract_graph: bool,
+    answer_cache: Option<AnswerCacheConfig>,
+    query_log: Option<QueryLogConfig>,
+    /// Who the audit log records operations as
+    actor: String,
+    webhooks: Option<Arc<WebhookNotifier>>,
+    usage: Arc<UsageMeter>,
+    metrics: Arc<Metrics>,
+    ingest_progress: Option<IngestProgressCallback>,
 }
 
 impl SimpleRagSystem {
     pub fn new() -> anyhow::Result<Self> {
-        Ok(Self {
-            chunker: ChunkingEngine::new()?,
-            searcher: SearchEngine::new()?,
-            storage: StorageManager::new()?,
+        Self::builder().build()
+    }
+
+    /// Compose a system part by part instead of relying on each module's defaults
+    pub fn builder() -> SimpleRagSystemBuilder {
+        SimpleRagSystemBuilder::new()
+    }
+
+    /// System whose storage is loaded from and persisted to a snapshot file
+    pub fn open(index_path: &Path) -> anyhow::Result<Self> {
+        Self::builder().storage(StorageManager::open(index_path)?).build()
+    }
+
+    /// System set up from a config file: storage, chunking, search and any configured providers
+    pub fn from_config(config: &RagConfig) -> anyhow::Result<Self> {
+        Self::from_config_with_plugins(config, &PluginRegistry::default())
+    }
+
+    /// Like `from_config`, with the components its `[plugins]` section names taken from `registry`
+    pub fn from_config_with_plugins(config: &RagConfig, registry: &PluginRegistry) -> anyhow::Result<Self> {
+        let storage = match config.storage.backend {
+            StorageBackend::Json => StorageManager::open(&config.index_path())?,
+            StorageBackend::Memory => StorageManager::new()?,
+        };
+        let storage = match config.storage.memory_budget_bytes() {
+            Some(max_bytes) => storage.with_memory_budget(max_bytes),
+            None => storage,
+        }
+        .with_embedding_quantization(config.storage.embedding_quantization);
+        let storage = match config.storage.embedding_dimensions {
+            Some(dimensions) => storage.with_embedding_dimensions(dimensions),
+            None => storage,
+        };
+        let storage = match config.storage.shards {
+            Some(shards) => storage.with_shards(shards),
+            None => storage,
+        };
+        let mut builder = Self::builder()
+            .storage(storage)
+            .chunking(config.chunking.strategy())
+            .search_config(config.search.clone())
+            .plugins(registry.clone(), config.plugins.clone());
+        if let Some(provider) = config.completion_provider()? {
+            builder = builder.completion_provider(provider);
+        }
+        if let Some(provider) = config.embedding_provider()? {
+            builder = builder.embedding_provider(provider);
+        }
+        if config.query_log.enabled {
+            builder = builder.query_log(config.query_log.clone());
+        }
+        if let Some(path) = &config.search.synonyms {
+            builder = builder.synonyms(SynonymDictionary::load(path)?);
+        }
+        match &config.storage.disk_index {
+            Some(path) if path.exists() => builder.build()?.with_disk_index(path),
+            _ => builder.build(),
+        }
+    }
+
+    /// Keep at most `max_bytes` of document content in memory; see `StorageManager::with_memory_budget`
+    pub fn with_memory_budget(mut self, max_bytes: usize) -> Self {
+        self.storage = self.storage.with_memory_budget(max_bytes);
+        self
+    }
+
+    /// Keep chunk embeddings in `quantization`'s form; see `StorageManager::with_embedding_quantization`
+    pub fn with_embedding_quantization(mut self, quantization: EmbeddingQuantization) -> Self {
+        self.storage = self.storage.with_embedding_quantization(quantization);
+        self
+    }
+
+    /// Partition the index across `shards`; see `StorageManager::with_shards`
+    pub fn with_shards(mut self, shards: usize) -> Self {
+        self.storage = self.storage.with_shards(shards);
+        self
+    }
+
+    /// Keep only the first `dimensions` of each embedding; see `StorageManager::with_embedding_dimensions`
+    pub fn with_embedding_dimensions(mut self, dimensions: usize) -> Self {
+        self.storage = self.storage.with_embedding_dimensions(dimensions);
+        self
+    }
+
+    /// Scope the system to `tenant`: it only sees, searches and stores that tenant's documents
+    pub fn with_tenant(mut self, tenant: &str) -> Self {
+        self.storage = self.storage.tenant(tenant);
+        self
+    }
+
+    pub fn tenant(&self) -> Option<&str> {
+        self.storage.tenant_id()
+    }
+
+    /// Record ingests, updates and deletes in the audit log as `actor` rather than `audit::default_actor`
+    pub fn with_actor(mut self, actor: impl Into<String>) -> Self {
+        self.actor = actor.into();
+        self
+    }
+
+    pub fn actor(&self) -> &str {
+        &self.actor
+    }
+
+    /// Run `operation`, auditing what it does as `actor`, such as the API key behind a request
+    pub fn as_actor<T>(&mut self, actor: &str, operation: impl FnOnce(&mut Self) -> T) -> T {
+        let previous = std::mem::replace(&mut self.actor, actor.to_string());
+        let result = operation(self);
+        self.actor = previous;
+        result
+    }
+
+    /// This tenant's audited operations that pass `filter`, oldest first
+    pub fn audit_log(&self, filter: &AuditFilter) -> Vec<AuditEntry> {
+        self.storage.audit_log(filter)
+    }
+
+    /// Tell webhooks about documents ingested, updated and deleted from now on
+    pub fn with_webhooks(mut self, notifier: WebhookNotifier) -> Self {
+        self.webhooks = Some(Arc::new(notifier));
+        self
+    }
+
+    /// Record an operation in the audit log and, when it changed the index, tell the webhooks
+    fn audit<T>(&self, action: AuditAction, source: Option<&str>, document_id: Option<&str>, result: &anyhow::Result<T>) {
+        let timestamp = answer_cache::unix_now();
+        self.storage.record_audit(AuditEntry {
+            timestamp,
+            actor: self.actor.clone(),
+            action,
+            source: source.map(str::to_string),
+            document_id: document_id.map(str::to_string),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            tenant: None,
+        });
+        let (Some(webhooks), Some(document_id), Ok(_)) = (&self.webhooks, document_id, result) else {
+            return;
+        };
+        let event = match action {
+            AuditAction::Ingest | AuditAction::Undelete => IndexEvent::Ingested,
+            AuditAction::Update => IndexEvent::Updated,
+            AuditAction::Delete | AuditAction::Purge => IndexEvent::Deleted,
+            AuditAction::Import => return,
+        };
+        let chunks = match event {
+            IndexEvent::Deleted => None,
+            _ => self.storage.get_document_chunks(document_id).ok().map(|chunks| chunks.len()),
+        };
+        webhooks.notify(WebhookPayload {
+            event,
+            document_id: document_id.to_string(),
+            source: source.map(str::to_string),
+            chunks,
+            tenant: self.tenant().map(str::to_string),
+            timestamp,
+        });
+    }
+
+    /// Called as each processed document is chunked, embedded and stored
+    pub fn with_ingest_progress(mut self, callback: IngestProgressCallback) -> Self {
+        self.ingest_progress = Some(callback);
+        self
+    }
+
+    fn report(&self, progress: IngestProgress) {
+        if let Some(callback) = &self.ingest_progress {
+            callback(&progress);
+        }
+    }
+
+    /// How documents processed from now on are split into chunks
+    pub fn with_chunking_strategy(mut self, strategy: ChunkingStrategy) -> Self {
+        self.chunker = ChunkingEngine::with_strategy(strategy);
+        self
+    }
+
+    /// Use the chunker, extractors and scorers `config` names, taken from `registry`; fails on a
+    /// name nothing is registered under
+    pub fn with_plugins(mut self, registry: &PluginRegistry, config: &PluginConfig) -> anyhow::Result<Self> {
+        if let Some(name) = &config.chunker {
+            self.chunker = ChunkingEngine::with_chunker(name, registry.chunker(name)?);
+        }
+        for (extension, name) in &config.extractors {
+            self.processor = self.processor.with_extractor(extension, registry.extractor(name)?);
+        }
+        if !config.scorers.is_empty() {
+            let scorers = config.scorers.iter().map(|name| registry.scorer(name)).collect::<anyhow::Result<_>>()?;
+            self.searcher = self.searcher.with_scorers(scorers);
+        }
+        Ok(self)
+    }
+
+    /// LLM used by `ask` to generate answers: a built-in rig provider or any `CompletionProvider` of your own
+    pub fn with_completion_provider(mut self, provider: impl CompletionProvider + 'static) -> Self {
+        let provider: Arc<dyn CompletionProvider> = Arc::new(provider);
+        self.completion = Some(Arc::new(MeteredCompletionProvider::new(provider, self.usage.clone())));
+        self
+    }
+
+    /// Embeds chunks at ingest and queries in vector and hybrid search modes
+    pub fn with_embedding_provider(mut self, provider: impl EmbeddingProvider + 'static) -> Self {
+        let provider: Arc<dyn EmbeddingProvider> = Arc::new(provider);
+        self.embedder = Some(Arc::new(MeteredEmbeddingProvider::new(provider, self.usage.clone())));
+        self
+    }
+
+    /// Counters and search latencies since the system was created, e.g. for `serve_metrics`
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// Tokens and estimated cost of every call this system has made
+    pub fn usage(&self) -> Usage {
+        self.usage.total()
+    }
+
+    /// Add what was used since `before` to the stored totals of `operation`
+    pub(crate) fn record_usage_since(&self, operation: &str, before: &Usage) -> Usage {
+        let used = self.usage.total().since(before);
+        self.storage.record_usage(operation, &used);
+        used
+    }
+
+    /// Model the stored chunk embeddings came from, if any
+    pub fn embedding_model(&self) -> Option<String> {
+        self.storage.embedding_model()
+    }
+
+    pub fn with_search_config(mut self, config: SearchConfig) -> anyhow::Result<Self> {
+        self.set_search_config(config)?;
+        Ok(self)
+    }
+
+    pub fn set_search_config(&mut self, config: SearchConfig) -> anyhow::Result<()> {
+        let synonyms = std::mem::take(self.searcher.synonyms_mut());
+        let disk_index = self.searcher.disk_index().cloned();
+        let scorers = self.searcher.scorers().to_vec();
+        self.searcher = SearchEngine::with_config(config)?
+            .with_synonyms(synonyms)
+            .with_disk_index(disk_index)
+            .with_scorers(scorers);
+        Ok(())
+    }
+
+    /// Chunks retrieved for each answer; fewer are used if they overflow the token budget
+    pub fn with_context_chunks(mut self, chunks: usize) -> Self {
+        self.context_chunks = chunks;
+        self
+    }
+
+    /// Token budget for retrieved chunks; defaults to the model's context window minus a reserve
+    pub fn with_context_tokens(mut self, tokens: usize) -> Self {
+        self.context_tokens = Some(tokens);
+        self
+    }
+
+    pub fn with_context_order(mut self, order: ContextOrder) -> Self {
+        self.context_order = order;
+        self
+    }
+
+    /// Decline to answer when no retrieved chunk scores above this; the scale depends on the search mode
+    pub fn with_min_retrieval_score(mut self, min_score: f32) -> Self {
+        self.min_retrieval_score = min_score;
+        self
+    }
+
+    /// Embed an LLM-written hypothetical answer instead of the query in vector and hybrid search
+    pub fn with_hyde(mut self, enabled: bool) -> Self {
+        self.hyde = enabled;
+        self
+    }
+
+    /// Have the LLM summarize each processed document; the summary is kept in metadata and indexed
+    pub fn with_summaries(mut self, enabled: bool) -> Self {
+        self.summarize = enabled;
+        self
+    }
+
+    /// Have the LLM extract entity relations from each chunk; kept in document metadata for `search_graph`
+    pub fn with_knowledge_graph(mut self, enabled: bool) -> Self {
+        self.extract_graph = enabled;
+        self
+    }
+
+    /// Expand each query with the synonyms of the terms it mentions before it is scored, wherever
+    /// the analyzer has the synonyms filter, as the default one does
+    pub fn with_synonyms(mut self, synonyms: SynonymDictionary) -> Self {
+        self.searcher = self.searcher.with_synonyms(synonyms);
+        self
+    }
+
+    pub fn synonyms(&self) -> &SynonymDictionary {
+        self.searcher.synonyms()
+    }
+
+    /// Expand `term` in queries from now on, on top of whatever it already expanded to
+    pub fn add_synonyms<S: AsRef<str>>(&mut self, term: &str, expansions: &[S]) {
+        self.searcher.synonyms_mut().add(term, expansions);
+    }
+
+    /// Stop expanding `term`, returning whether it had any synonyms
+    pub fn remove_synonyms(&mut self, term: &str) -> bool {
+        self.searcher.synonyms_mut().remove(term).is_some()
+    }
+
+    /// Reuse answers from `ask` for questions that embed close to one already answered.
+    ///
+    /// Needs an embedding provider. Entries are kept in the index and dropped whenever
+    /// a document is processed, since answers may no longer reflect the corpus.
+    pub fn with_answer_cache(mut self, config: AnswerCacheConfig) -> Self {
+        self.answer_cache = Some(config);
+        self
+    }
+
+    /// Record every search with its filter, results and latency, kept in the index; see `query_log`
+    pub fn with_query_log(mut self, config: QueryLogConfig) -> Self {
+        self.storage.trim_query_log(config.max_entries);
+        self.query_log = Some(config);
+        self
+    }
+
+    pub fn logs_queries(&self) -> bool {
+        self.query_log.is_some()
+    }
+
+    /// Searches logged so far, oldest first
+    pub fn query_log(&self) -> anyhow::Result<Vec<QueryLogEntry>> {
+        self.storage.query_log()
+    }
+
+    /// Write the query log as JSON lines, returning the number of entries
+    pub fn export_query_log(&self, out: impl std::io::Write) -> anyhow::Result<usize> {
+        let entries = self.storage.query_log()?;
+        export_jsonl(&entries, out)?;
+        Ok(entries.len())
+    }
+
+    pub fn clear_query_log(&mut self) -> anyhow::Result<usize> {
+        self.storage.clear_query_log()
+    }
+
+    /// Judge a search result relevant or not to `query`; later searches for similar queries rank the
+    /// chunk higher or lower, see `SearchConfig::feedback_weight`
+    pub fn record_feedback(&self, query: &str, chunk_id: &str, relevant: bool) -> anyhow::Result<()> {
+        if self.storage.get_chunk(chunk_id)?.is_none() {
+            return Err(anyhow!("No chunk {}", chunk_id));
+        }
+        self.storage.record_feedback(FeedbackEntry {
+            timestamp: answer_cache::unix_now(),
+            query: query.to_string(),
+            chunk_id: chunk_id.to_string(),
+            relevant,
+            tenant: None,
+        });
+        Ok(())
+    }
+
+    /// Relevance judgements recorded so far, oldest first
+    pub fn feedback(&self) -> Vec<FeedbackEntry> {
+        self.storage.feedback()
+    }
+
+    pub fn clear_feedback(&mut self) -> usize {
+        self.storage.clear_feedback()
+    }
+
+    /// Fit a ranking model to the recorded feedback in the current search mode and keep it in the index;
+    /// searches use it once `SearchConfig::learned_ranking` is on
+    pub fn train_ranking_model(&mut self) -> anyhow::Result<TrainingReport> {
+        let feedback = self.storage.feedback();
+        if feedback.is_empty() {
+            return Err(anyhow!("No relevance feedback to train on; record some with record_feedback"));
+        }
+        let chunks = self.storage.get_all_chunks()?;
+        let embeddings = self.storage.get_stored_embeddings()?;
+        let mut context = SearchContext::default();
+        self.add_document_signals(&mut context, true)?;
+        let positions: HashMap<&str, usize> = chunks.iter().enumerate().map(|(i, c)| (c.id.as_str(), i)).collect();
+        let mut by_query: BTreeMap<&str, Vec<&FeedbackEntry>> = BTreeMap::new();
+        for entry in &feedback {
+            by_query.entry(entry.query.as_str()).or_default().push(entry);
+        }
+
+        let mut examples = Vec::new();
+        for (query, entries) in by_query {
+            let query_embedding = match self.query_embedder()? {
+                Some(embedder) => {
+                    let text = self.searcher.embedding_query(query);
+                    Some(first_embedding(embedder, self.embed_cached(embedder, &[text])?)?)
+                }
+                None => None,
+            };
+            let features =
+                self.searcher.ranking_features(query, query_embedding.as_deref(), &chunks, &embeddings, &context)?;
+            for entry in entries {
+                if let Some(&i) = positions.get(entry.chunk_id.as_str()) {
+                    examples.push(TrainingExample { features: features[i], relevant: entry.relevant });
+                }
+            }
+        }
+        let report = ltr::train(&examples, self.searcher.config().mode, answer_cache::unix_now())?;
+        tracing::info!("Trained a ranking model on {} judgements, {:.0}% fit", report.examples, report.accuracy * 100.0);
+        self.storage.set_ranking_model(report.model.clone());
+        Ok(report)
+    }
+
+    /// The model `train_ranking_model` last fitted, if any
+    pub fn ranking_model(&self) -> Option<RankingModel> {
+        self.storage.ranking_model()
+    }
+
+    /// Write a disk index of every stored embedding to `path` and search with it from now on. Needs
+    /// full-precision vectors, either stored or in the disk index being replaced
+    pub fn build_disk_index(&mut self, path: &Path) -> anyhow::Result<Arc<DiskIndex>> {
+        let previous = self.searcher.disk_index().cloned();
+        let mut vectors = HashMap::new();
+        for (id, embedding) in self.storage.get_stored_embeddings()? {
+            let vector = match (&embedding, &previous) {
+                (StoredEmbedding::Full(vector), _) => vector.clone(),
+                (_, Some(index)) if index.contains(&id) => index.vector(&id)?.unwrap_or_default(),
+                _ => {
+                    return Err(anyhow!(
+                        "Embedding of {} is quantized; build the disk index before quantizing, or re-embed the index",
+                        id
+                    ))
+                }
+            };
+            vectors.insert(id, vector);
+        }
+        if vectors.is_empty() {
+            return Err(anyhow!("No embeddings to index; ingest documents with an embedding provider first"));
+        }
+        let index = Arc::new(self.searcher.build_disk_index(path, &vectors)?);
+        tracing::info!("Built a disk index of {} vectors at {}", index.len(), path.display());
+        self.storage.reset_stale_vectors();
+        self.searcher.set_disk_index(Some(index.clone()));
+        Ok(index)
+    }
+
+    /// Cluster the stored embeddings into `lists` lists for searches with `VectorIndex::Ivf`, replacing
+    /// any IVF index trained before; unset, `search.ivf.lists` or the square root of the chunk count
+    pub fn train_ivf_index(&mut self, lists: Option<usize>) -> anyhow::Result<Arc<IvfIndex>> {
+        let vectors: HashMap<String, Vec<f32>> =
+            self.storage.get_stored_embeddings()?.into_iter().map(|(id, e)| (id, e.to_vec())).collect();
+        let lists = lists.or(self.searcher.config().ivf.lists).unwrap_or_else(|| ivf::default_lists(vectors.len()));
+        let index = IvfIndex::train(&vectors, lists, answer_cache::unix_now())?;
+        tracing::info!("Trained an IVF index of {} lists over {} vectors", index.lists(), index.len());
+        Ok(self.storage.set_ivf_index(index))
+    }
+
+    pub fn ivf_index(&self) -> Option<Arc<IvfIndex>> {
+        self.storage.ivf_index()
+    }
+
+    /// Search with the disk index at `path` built by `build_disk_index`. Chunks embedded or removed
+    /// since it was built are searched exactly until it is rebuilt
+    pub fn with_disk_index(mut self, path: &Path) -> anyhow::Result<Self> {
+        self.searcher.set_disk_index(Some(Arc::new(DiskIndex::open(path)?)));
+        self.storage.track_stale_vectors();
+        Ok(self)
+    }
+
+    pub fn disk_index(&self) -> Option<&Arc<DiskIndex>> {
+        self.searcher.disk_index()
+    }
+
+    pub fn cached_answers(&self) -> Vec<CachedAnswer> {
+        self.storage.cached_answers()
+    }
+
+    /// Drop cached answers older than `ttl_secs`, returning how many were removed
+    pub fn evict_expired_answers(&self, ttl_secs: u64) -> usize {
+        self.storage.evict_expired_answers(ttl_secs)
+    }
+
+    pub fn clear_answer_cache(&self) -> usize {
+        self.storage.clear_answer_cache()
+    }
+
+    /// Prompt used by `ask` and chat sessions
+    pub fn with_prompt_template(mut self, template: PromptTemplate) -> Self {
+        self.prompt_template = template;
+        self
+    }
+
+    pub fn set_prompt_template(&mut self, template: PromptTemplate) {
+        self.prompt_template = template;
+    }
+
+    pub fn prompt_template(&self) -> &PromptTemplate {
+        &self.prompt_template
+    }
+
+    /// Answer a question from the stored documents: retrieve, build a context prompt, generate
+    pub fn ask(&self, question: &str) -> anyhow::Result<Answer> {
+        let before = self.usage.total();
+        let answer = self.answer_question(question);
+        let usage = self.record_usage_since("ask", &before);
+        answer.map(|answer| Answer { usage, ..answer })
+    }
+
+    fn answer_question(&self, question: &str) -> anyhow::Result<Answer> {
+        let llm = self.completion_provider()?;
+        let cache_embedding = match self.answer_cache_embedder()? {
+            Some(embedder) => {
+                let embedding = first_embedding(embedder, self.embed_cached(embedder, &[question.to_string()])?)?;
+                if let Some(answer) = self.cached_answer_for(question, &embedding) {
+                    return Ok(answer);
+                }
+                Some(embedding)
+            }
+            None => None,
+        };
+
+        let (context, chunks) = self.retrieve_context(question)?;
+        if let Some(refusal) = self.refuse_weak_context(&context) {
+            return Ok(refusal);
+        }
+        let text = llm.complete(&self.prompt_template.build_request(question, &context))?;
+        let answer = Answer::from_response(text, &chunks);
+        if let Some(embedding) = cache_embedding {
+            self.cache_answer_for(question, embedding, &answer);
+        }
+        Ok(answer)
+    }
+
+    /// Embedder for answer cache lookups, `None` when the cache is off
+    fn answer_cache_embedder(&self) -> anyhow::Result<Option<&dyn EmbeddingProvider>> {
+        if self.answer_cache.is_none() {
+            return Ok(None);
+        }
+        let embedder = self
+            .embedder
+            .as_ref()
+            .ok_or_else(|| anyhow!("The answer cache needs an embedding provider"))?;
+        Ok(Some(embedder.as_ref()))
+    }
+
+    fn cached_answer_for(&self, question: &str, embedding: &[f32]) -> Option<Answer> {
+        let (config, embedder) = (self.answer_cache.as_ref()?, self.embedder.as_ref()?);
+        let cached = self.storage.cached_answer(config, embedder.model_name(), embedding);
+        self.metrics.record_answer_lookup(cached.is_some());
+        if cached.is_some() {
+            tracing::debug!("Answer cache hit for '{}'", question);
+        }
+        cached
+    }
+
+    fn cache_answer_for(&self, question: &str, embedding: Vec<f32>, answer: &Answer) {
+        if let (Some(config), Some(embedder)) = (&self.answer_cache, &self.embedder) {
+            self.storage.cache_answer(
+                config,
+                CachedAnswer {
+                    question: question.to_string(),
+                    embedding_model: embedder.model_name().to_string(),
+                    embedding,
+                    answer: answer.clone(),
+                    created_at: answer_cache::unix_now(),
+                    // Stamped by the storage view
+                    tenant: None,
+                },
+            );
+        }
+    }
+
+    /// Answer as JSON conforming to `schema`, deserialized into `T`.
+    ///
+    /// Fails rather than returning prose when retrieval is too weak to answer from.
+    pub fn ask_structured<T: serde::de::DeserializeOwned>(
+        &self,
+        question: &str,
+        schema: &serde_json::Value,
+    ) -> anyhow::Result<StructuredAnswer<T>> {
+        let before = self.usage.total();
+        let answer = self.structured_answer(question, schema);
+        let usage = self.record_usage_since("ask", &before);
+        answer.map(|answer| StructuredAnswer { usage, ..answer })
+    }
+
+    fn structured_answer<T: serde::de::DeserializeOwned>(
+        &self,
+        question: &str,
+        schema: &serde_json::Value,
+    ) -> anyhow::Result<StructuredAnswer<T>> {
+        let llm = self.completion_provider()?;
+        let (context, chunks) = self.retrieve_context(question)?;
+        if let Some(refusal) = self.refuse_weak_context(&context) {
+            return Err(anyhow!(refusal.text));
+        }
+        let request = structured_request(self.prompt_template.build_request(question, &context), schema)?;
+        complete_structured(llm.as_ref(), &request, schema, &chunks)
+    }
+
+    /// The insufficient-context answer, if the best result falls short of the minimum score
+    pub(crate) fn refuse_weak_context(&self, context: &[SearchResult]) -> Option<Answer> {
+        let best_score = context.iter().map(|r| r.score).reduce(f32::max);
+        match best_score {
+            Some(score) if score > self.min_retrieval_score => None,
+            _ => {
+                tracing::info!(
+                    "Declining to answer: best retrieval score {:?} is not above {}",
+                    best_score,
+                    self.min_retrieval_score
+                );
+                Some(Answer::insufficient_context(best_score, self.min_retrieval_score))
+            }
+        }
+    }
+
+    /// Answer with up to `max_hops` follow-up searches requested by the LLM
+    pub fn ask_multi_hop(&self, question: &str, max_hops: usize) -> anyhow::Result<MultiHopAnswer> {
+        let before = self.usage.total();
+        let result = MultiHopRetriever::new(self).with_max_hops(max_hops).answer(question);
+        let usage = self.record_usage_since("ask", &before);
+        result.map(|mut result| {
+            result.answer.usage = usage;
+            result
         })
     }
 
-    pub fn process_document(&mut self, file_path: &Path) -> anyhow::Result<String> {
-        // Process the document
-        let processor = DocumentProcessor::new();
-        let document = processor.process_file(file_path)?;
+    /// Start a multi-turn conversation over the stored documents
+    pub fn start_chat(&self) -> ChatSession<'_> {
+        ChatSession::new(self)
+    }
+
+    pub(crate) fn completion_provider(&self) -> anyhow::Result<&Arc<dyn CompletionProvider>> {
+        self.completion
+            .as_ref()
+            .ok_or_else(|| anyhow!("No completion provider configured"))
+    }
+
+    /// Matching results for the prompt, plus their stored chunks for citations
+    pub(crate) fn retrieve_context(&self, query: &str) -> anyhow::Result<(Vec<SearchResult>, Vec<DocumentChunk>)> {
+        let context = self.search_chunks(query, self.storage.get_all_chunks()?, self.context_chunks)?;
+        self.pack_context(query, context)
+    }
+
+    /// Keep the results that matched and fit them into the prompt's token budget
+    fn pack_context(
+        &self,
+        query: &str,
+        mut context: Vec<SearchResult>,
+    ) -> anyhow::Result<(Vec<SearchResult>, Vec<DocumentChunk>)> {
+        context.retain(|r| r.score > 0.0);
+
+        let mut results = Vec::with_capacity(context.len());
+        let mut chunks = Vec::with_capacity(context.len());
+        for result in context {
+            if let Some(chunk) = self.storage.get_chunk(&result.chunk_id)? {
+                results.push(result);
+                chunks.push(chunk);
+            }
+        }
+
+        let packed = self.context_builder().build(results, chunks);
+        tracing::debug!("Packed {} chunks into the context for '{}'", packed.chunks.len(), query);
+        Ok((packed.results, packed.chunks))
+    }
+
+    pub(crate) fn context_builder(&self) -> ContextBuilder {
+        let (model, window) = match &self.completion {
+            Some(llm) => (llm.model_name(), llm.context_window()),
+            None => ("", DEFAULT_CONTEXT_WINDOW),
+        };
+        let budget = self
+            .context_tokens
+            .unwrap_or_else(|| window.saturating_sub(PROMPT_TOKEN_RESERVE));
+        ContextBuilder::for_model(model, budget).with_order(self.context_order)
+    }
+
+    /// Write storage back to its snapshot file, if it has one
+    pub fn persist(&self) -> anyhow::Result<()> {
+        self.storage.persist()
+    }
+
+    /// Rebuild every chunk, and embedding when an embedder is set, from the stored document content.
+    ///
+    /// Uses the current chunking strategy and embedding provider, so switching either only needs a
+    /// reindex rather than re-processing the source files. Existing summaries are kept, not regenerated.
+    pub fn reindex(&mut self) -> anyhow::Result<ReindexReport> {
+        let before = self.usage.total();
+        let report = self.rebuild_index();
+        self.record_usage_since("ingest", &before);
+        report
+    }
+
+    fn rebuild_index(&mut self) -> anyhow::Result<ReindexReport> {
+        let mut documents = Vec::new();
+        for doc_id in self.storage.list_documents()? {
+            documents.extend(self.storage.get_document(&doc_id)?);
+        }
+        documents.sort_by(|a, b| a.metadata.file_path.cmp(&b.metadata.file_path));
 
-        // Chunk the document
+        let chunks_before = self.storage.get_stats()?.total_chunks;
+        let embeddings_before = self.storage.get_all_embeddings()?.len();
+        let embedding_model_before = self.storage.embedding_model();
+
+        // Chunk everything up front so a chunking error leaves the index untouched
+        let mut rebuilt = Vec::new();
+        for mut document in documents {
+            // Documents indexed before languages were detected
+            if document.metadata.language.is_none() {
+                document.metadata.language = detect_language(&document.content).map(str::to_string);
+            }
+            let mut chunks = self.chunker.chunk_document(&document)?;
+            if let Some(summary) = &document.metadata.summary {
+                chunks.push(summary_chunk(&document, summary));
+            }
+            rebuilt.push((document, chunks));
+        }
+
+        tracing::info!("Reindexing {} documents", rebuilt.len());
+        self.report(IngestProgress::Discovered { files: rebuilt.len() });
+        self.storage.clear_chunks()?;
+        for (document, chunks) in rebuilt {
+            self.report(IngestProgress::Chunked {
+                file_path: document.metadata.file_path.clone(),
+                chunks: chunks.len(),
+            });
+            self.index_document(document, chunks)?;
+        }
+
+        Ok(ReindexReport {
+            documents: self.storage.list_documents()?.len(),
+            chunks_before,
+            chunks_after: self.storage.get_stats()?.total_chunks,
+            embeddings_before,
+            embeddings_after: self.storage.get_all_embeddings()?.len(),
+            embedding_model_before,
+            embedding_model_after: self.storage.embedding_model(),
+        })
+    }
+
+    /// Time a cold rebuild of the index, then every query `iterations` times.
+    ///
+    /// The rebuild runs with the embedding cache emptied, so it pays for every embedding; the
+    /// whole index, trash and other tenants included, is restored afterwards, leaving storage as it was.
+    /// A tenant view can't restore the whole index, so it is refused before anything changes.
+    pub fn benchmark(&mut self, queries: &[String], iterations: usize, limit: usize) -> anyhow::Result<BenchReport> {
+        if let Some(tenant) = self.tenant() {
+            return Err(anyhow!("Benchmarking rebuilds and restores the whole index, which tenant {} cannot do", tenant));
+        }
+        let snapshot = self.storage.full_snapshot()?;
+        let mut cold = snapshot.clone();
+        cold.embedding_cache.clear();
+        self.storage.restore(cold)?;
+
+        let started = Instant::now();
+        let rebuilt = self.rebuild_index();
+        let elapsed = started.elapsed();
+        let ingest = rebuilt.and_then(|report| {
+            let bytes = self.storage.get_stats()?.total_size_bytes;
+            Ok(IngestThroughput::new(report.documents, report.chunks_after, bytes, elapsed))
+        });
+        let embedding_model = self.storage.embedding_model();
+        self.storage.restore(snapshot.clone())?;
+        let ingest = ingest?;
+
+        let mut durations = Vec::with_capacity(queries.len() * iterations);
+        for _ in 0..iterations {
+            for query in queries {
+                let started = Instant::now();
+                self.search(query, limit)?;
+                durations.push(started.elapsed());
+            }
+        }
+        self.storage.restore(snapshot)?;
+
+        let config = self.searcher.config();
+        Ok(BenchReport {
+            setup: BenchSetup {
+                search_mode: format!("{:?}", config.mode),
+                search_fingerprint: config.fingerprint(),
+                embedding_model,
+                queries: queries.len(),
+                iterations,
+                limit,
+            },
+            ingest,
+            query: LatencyStats::from_durations(&durations),
+            quantized: None,
+        })
+    }
+
+    /// Time the benchmark queries against a copy of the embeddings quantized to `quantization`, as
+    /// `evaluate_quantization` builds one, and measure how many of the full-precision results it keeps.
+    /// Each timed query is embedded, from the cache after the first round, and scored. The stored
+    /// embeddings are left alone; needs an embedder and an index that isn't quantized yet
+    pub fn benchmark_quantization(
+        &self,
+        queries: &[String],
+        iterations: usize,
+        limit: usize,
+        quantization: EmbeddingQuantization,
+    ) -> anyhow::Result<QuantizedBench> {
+        if !self.searcher.config().mode.uses_embeddings() {
+            return Err(anyhow!("Quantization only affects vector and hybrid search"));
+        }
+        let embedder = self
+            .query_embedder()?
+            .ok_or_else(|| anyhow!("Measuring quantization needs an embedding provider to embed the queries"))?;
+        let stored = self.storage.get_stored_embeddings()?;
+        if stored.values().any(StoredEmbedding::is_quantized) {
+            return Err(anyhow!("The index is already quantized; re-embed it at full precision to measure again"));
+        }
+        let full: HashMap<String, Vec<f32>> = stored.into_iter().map(|(id, e)| (id, e.to_vec())).collect();
+        let quantized = quantized_copy(&full, quantization)?;
+        let chunks = self.storage.get_all_chunks()?;
+        let context = self.search_context()?;
+        let embedded = |query: &String| {
+            first_embedding(embedder, self.embed_cached(embedder, &[self.searcher.embedding_query(query)])?)
+        };
+        let mut expected: Vec<HashSet<String>> = Vec::with_capacity(queries.len());
+        for query in queries {
+            let results = self.searcher.search_with_embeddings_in(query, &embedded(query)?, &chunks, &full, &context, limit)?;
+            expected.push(results.into_iter().map(|r| r.chunk_id).collect());
+        }
+
+        let mut durations = Vec::with_capacity(queries.len() * iterations);
+        let mut recalls = Vec::with_capacity(queries.len());
+        for round in 0..iterations.max(1) {
+            for (query, expected) in queries.iter().zip(&expected) {
+                let started = Instant::now();
+                let query_embedding = embedded(query)?;
+                let results =
+                    self.searcher.search_with_embeddings_in(query, &query_embedding, &chunks, &quantized, &context, limit)?;
+                durations.push(started.elapsed());
+                if round == 0 {
+                    let kept = results.iter().filter(|r| expected.contains(&r.chunk_id)).count();
+                    recalls.push(if expected.is_empty() { 1.0 } else { kept as f32 / expected.len() as f32 });
+                }
+            }
+        }
+        Ok(QuantizedBench {
+            quantization,
+            query: LatencyStats::from_durations(&durations),
+            recall: recalls.iter().sum::<f32>() / recalls.len().max(1) as f32,
+            full_bytes: full.values().map(|v| v.len() * std::mem::size_of::<f32>()).sum(),
+            quantized_bytes: quantized.values().map(StoredEmbedding::memory_bytes).sum(),
+        })
+    }
+
+    /// Read and chunk a file exactly as `process_document` would, without storing anything
+    pub fn preview_document(&self, file_path: &Path) -> anyhow::Result<IngestPreview> {
+        let document = self.processor.process_file(file_path)?;
         let chunks = self.chunker.chunk_document(&document)?;
+        Ok(IngestPreview {
+            document_id: document.id,
+            file_type: document.metadata.file_type,
+            file_size: document.metadata.file_size,
+            word_count: document.metadata.word_count,
+            chunks: chunks.len(),
+            chunk_words: chunks.iter().map(|c| c.word_count).collect(),
+        })
+    }
 
-        // Store the document and chunks
-        let doc_id = self.storage.store_document(document)?;
-        self.storage.store_chunks(doc_id.clone(), chunks)?;
+    /// Process every file matched by a directory, glob or file path, carrying on past failures
+    pub fn ingest_path(&mut self, path: &Path, options: &IngestOptions) -> anyhow::Result<IngestReport> {
+        let (selected, skipped) = discover_files(path, options)?;
+        self.report(IngestProgress::Discovered { files: selected.len() });
 
-        Ok(doc_id)
+        let processed: Vec<(PathBuf, anyhow::Result<IngestOutcome>)> = if options.dry_run {
+            let preview = |file: &Path| self.preview_document(file).map(IngestOutcome::WouldIngest);
+            selected.into_iter().map(|file| (file.clone(), preview(&file))).collect()
+        } else {
+            let stored = if options.workers > 1 {
+                let stored = self.ingest_files_pipelined(selected, options.workers);
+                for (file, doc_id) in &stored {
+                    self.audit(AuditAction::Ingest, Some(&file.display().to_string()), doc_id.as_deref().ok(), doc_id);
+                }
+                stored
+            } else {
+                selected.into_iter().map(|file| (file.clone(), self.process_document(&file))).collect()
+            };
+            let outcome = |document_id: String| {
+                let chunks = self.storage.get_document_chunks(&document_id)?.len();
+                Ok(IngestOutcome::Ingested { document_id, chunks })
+            };
+            stored.into_iter().map(|(file, doc_id)| (file, doc_id.and_then(outcome))).collect()
+        };
+
+        let mut report = IngestReport { files: skipped };
+        for (file, processed) in processed {
+            let outcome = match processed {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    tracing::warn!("Failed to ingest {}: {}", file.display(), e);
+                    self.report(IngestProgress::Failed {
+                        file_path: file.display().to_string(),
+                        error: e.to_string(),
+                    });
+                    IngestOutcome::Failed { error: e.to_string() }
+                }
+            };
+            report.files.push(IngestedFile { path: file, outcome });
+        }
+        report.files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(report)
     }
 
-    pub fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
-        let all_chunks = self.storage.get_all_chunks()?;
-        let results = self.searcher.search(query, &all_chunks, limit)?;
-        Ok(results)
+    /// Write the whole index to a portable `.ragpack` file
+    pub fn export(&self, path: &Path) -> anyhow::Result<()> {
+        Ragpack::new(self.storage.snapshot()?).save(path)
     }
 
-    pub fn evaluate_search(&self, query: &str, expected_doc_ids: &[String]) -> anyhow::Result<EvaluationMetrics> {
-        let results = self.search(query, 5)?;
-        let evaluator = Evaluator::new();
-        evaluator.evaluate(&results, expected_doc_ids)
+    /// Write every tenant's data to a `.ragpack` file, which `import` restores
+    pub fn backup(&self, path: &Path) -> anyhow::Result<()> {
+        Ragpack::new(self.storage.full_snapshot()?).save(path)
     }
 
-    pub fn list_documents(&self) -> anyhow::Result<Vec<String>> {
-        self.storage.list_documents()
+    /// Replace the index with `snapshot`, as a read replica does each time its primary persists;
+    /// returns how many documents it held
+    pub fn restore_snapshot(&mut self, snapshot: StorageSnapshot) -> anyhow::Result<usize> {
+        let documents = snapshot.documents.len();
+        self.storage.restore(snapshot)?;
+        Ok(documents)
     }
 
-    pub fn get_document(&self, doc_id: &str) -> anyhow::Result<Option<ProcessedDocument>> {
-        self.storage.get_document(doc_id)
+    /// Replace the index with the contents of a `.ragpack` file, returning how many documents it held.
+    /// The audit log is kept, with the import added to it
+    pub fn import(&mut self, path: &Path) -> anyhow::Result<usize> {
+        let imported = Ragpack::load(path).and_then(|pack| {
+            let documents = pack.snapshot.documents.len();
+            self.storage.restore(pack.snapshot)?;
+            Ok(documents)
+        });
+        self.audit(AuditAction::Import, Some(&path.display().to_string()), None, &imported);
+        imported
     }
 
-    pub fn get_stats(&self) -> anyhow::Result<StorageStats> {
-        self.storage.get_stats()
+    pub fn process_document(&mut self, file_path: &Path) -> anyhow::Result<String> {
+        let doc_id = self.process_file(file_path);
+        self.audit(AuditAction::Ingest, Some(&file_path.display().to_string()), doc_id.as_deref().ok(), &doc_id);
+        doc_id
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+    /// Reingest a changed source file in place of its previous version `old_id`, keeping its tags. The
+    /// old version is removed
+    /// once the new version is stored; if reading or indexing it fails, the old version stays as it was
+    pub fn update_document(&mut self, old_id: &str, file_path: &Path) -> anyhow::Result<String> {
+        let tags = self.tags(old_id)?;
+        let doc_id = self.process_file(file_path).and_then(|doc_id| {
+            let replaced = if tags.is_empty() { Ok(()) } else { self.set_tags(&doc_id, tags) };
+            match replaced.and_then(|()| self.storage.delete_document(old_id)) {
+                Ok(_) => Ok(doc_id),
+                Err(e) => {
+                    self.storage.delete_document(&doc_id)?;
+                    Err(e)
+                }
+            }
+        });
+        self.audit(AuditAction::Update, Some(&file_path.display().to_string()), doc_id.as_deref().ok(), &doc_id);
+        doc_id
+    }
 
-    #[test]
-    fn test_simple_rag_workflow() {
-        // Create test content
-        let test_content = r#"
-# Machine Learning Basics
+    #[tracing::instrument(
+        name = "process_document",
+        skip_all,
+        fields(path = %file_path.display(), doc_id = Empty, chunks = Empty, elapsed_ms = Empty)
+    )]
+    fn process_file(&mut self, file_path: &Path) -> anyhow::Result<String> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let doc_id = self.processor.process_file(file_path).and_then(|document| self.ingest(document));
+        self.record_usage_since("ingest", &before);
+        let span = tracing::Span::current();
+        if let Ok(doc_id) = &doc_id {
+            span.record("doc_id", tracing::field::display(doc_id));
+        }
+        span.record("elapsed_ms", elapsed_ms(started));
+        doc_id
+    }
 
-Machine learning is a subset of artificial intelligence that enables computers to learn from data without being explicitly programmed.
+    /// Like `process_document` for a document already in memory, such as an upload;
+    /// `name` stands in for the file path in citations and metadata
+    #[tracing::instrument(name = "process_document", skip_all, fields(path = name, doc_id = Empty, chunks = Empty))]
+    pub fn process_bytes(&mut self, name: &str, bytes: &[u8]) -> anyhow::Result<String> {
+        let before = self.usage.total();
+        let doc_id = self.processor.process_bytes(name, bytes).and_then(|document| self.ingest(document));
+        self.record_usage_since("ingest", &before);
+        if let Ok(doc_id) = &doc_id {
+            tracing::Span::current().record("doc_id", tracing::field::display(doc_id));
+        }
+        self.audit(AuditAction::Ingest, Some(name), doc_id.as_deref().ok(), &doc_id);
+        doc_id
+    }
 
-## Key Concepts
+    fn ingest(&self, document: ProcessedDocument) -> anyhow::Result<String> {
+        let (document, chunks) = self.chunk_for_index(document)?;
+        self.index_document(document, chunks)
+    }
 
-### Supervised Learning
-In supervised learning, we train models on labeled data.
+    /// Split a document into chunks, with its summary chunk when summaries are on and its relations
+    /// when graph extraction is
+    fn chunk_for_index(&self, mut document: ProcessedDocument) -> anyhow::Result<(ProcessedDocument, Vec<DocumentChunk>)> {
+        let mut chunks = self.chunker.chunk_document(&document)?;
+        if self.summarize {
+            let summary = summarize_document(self.completion_provider()?.as_ref(), &document)?;
+            attach_summary(&mut document, &mut chunks, summary);
+        }
+        if self.extract_graph {
+            document.metadata.relations = extract_relations(self.completion_provider()?.as_ref(), &chunks)?;
+        }
+        self.report_chunked(&document, &chunks);
+        Ok((document, chunks))
+    }
 
-### Unsupervised Learning
-Unsupervised learning finds patterns in unlabeled data.
+    fn report_chunked(&self, document: &ProcessedDocument, chunks: &[DocumentChunk]) {
+        tracing::debug!("Split {} into {} chunks", document.metadata.file_path, chunks.len());
+        self.report(IngestProgress::Chunked {
+            file_path: document.metadata.file_path.clone(),
+            chunks: chunks.len(),
+        });
+    }
 
-Applications include natural language processing, computer vision, and recommendation systems.
-"#;
+    /// Embed `chunks` if there is an embedder, then store them with their document
+    fn index_document(&self, document: ProcessedDocument, chunks: Vec<DocumentChunk>) -> anyhow::Result<String> {
+        self.embed_for_index(&chunks)?;
+        self.store_document(document, chunks)
+    }
 
-        // Create temporary file
-        let test_file = "/tmp/test_rag.md";
-        fs::write(test_file, test_content).unwrap();
+    /// Embed and store the vectors of `chunks`, when there is an embedder
+    fn embed_for_index(&self, chunks: &[DocumentChunk]) -> anyhow::Result<()> {
+        if let Some(embedder) = &self.embedder {
+            let vectors = self.embed_cached(embedder.as_ref(), &chunk_texts(chunks))?;
+            self.store_chunk_embeddings(embedder.model_name(), chunks, vectors)?;
+        }
+        Ok(())
+    }
 
-        // Test the workflow
-        let mut rag = SimpleRagSystem::new().unwrap();
-        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+    // Storing takes `&self` so the ingest pipeline's store stage can run beside the other stages;
+    // a storage handle writes to the same maps as `self.storage`
+    fn store_chunk_embeddings(&self, model: &str, chunks: &[DocumentChunk], vectors: Vec<Vec<f32>>) -> anyhow::Result<()> {
+        self.report(IngestProgress::Embedded {
+            done: vectors.len(),
+            total: chunks.len(),
+        });
+        let embeddings = chunks.iter().map(|c| c.id.clone()).zip(vectors).collect();
+        self.storage.handle().store_embeddings(model, embeddings)
+    }
 
-        // Test search
-        let results = rag.search("machine learning", 3).unwrap();
-        assert!(!results.is_empty());
+    fn store_document(&self, document: ProcessedDocument, chunks: Vec<DocumentChunk>) -> anyhow::Result<String> {
+        let mut storage = self.storage.handle();
+        storage.clear_answer_cache();
+        tracing::info!(
+            "Storing {} as document {} ({} chunks)",
+            document.metadata.file_path,
+            document.id,
+            chunks.len()
+        );
+        let doc_id = storage.store_document(document)?;
+        let chunk_count = chunks.len();
+        tracing::Span::current().record("chunks", chunk_count);
+        self.metrics.record_ingest(chunk_count);
+        storage.store_chunks(doc_id.clone(), chunks)?;
+        self.report(IngestProgress::Stored {
+            document_id: doc_id.clone(),
+            chunks: chunk_count,
+        });
 
-        // Test evaluation
-        let metrics = rag.evaluate_search("machine learning", &[doc_id]).unwrap();
-        assert!(metrics.relevance >= 0.0);
-        assert!(metrics.precision >= 0.0);
+        Ok(doc_id)
+    }
 
-        // Clean up
-        fs::remove_file(test_file).unwrap();
+    pub fn search(&self, query: &str, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.search_chunks(query, self.storage.get_all_chunks()?, limit);
+        self.record_usage_since("search", &before);
+        self.log_query(query, &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    /// Like `search`, over only the documents, and chunks, the filter selects
+    pub fn search_filtered(&self, query: &str, limit: usize, filter: &DocumentFilter) -> anyhow::Result<Vec<SearchResult>> {
+        if filter.is_empty() {
+            return self.search(query, limit);
+        }
+        let selected: HashSet<String> = self.documents_matching(filter)?.into_iter().collect();
+        let mut chunks = self.storage.get_all_chunks()?;
+        chunks.retain(|c| selected.contains(&c.document_id) && filter.matches_chunk(c));
+
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.search_chunks(query, chunks, limit);
+        self.record_usage_since("search", &before);
+        self.log_query(query, filter, started, &results);
+        results
+    }
+
+    /// Search with a parsed query: its terms rank the chunks its field conditions, phrases, groups and
+    /// negations select, see `query::Query`. A query with nothing to rank by, such as `type:md`, lists
+    /// the chunks it selects in index order, each scored 1. The query log records `query` as it
+    /// displays, so a `TypedQuery` is logged as typed
+    pub fn search_query<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        query: &Q,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.query_candidates(query.as_ref(), Some(limit)).map(|(results, _)| results);
+        self.record_usage_since("search", &before);
+        self.log_query(&query.to_string(), &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    /// `search_query` with facet counts over every candidate, not just the top `limit`
+    pub fn search_faceted<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        query: &Q,
+        limit: usize,
+    ) -> anyhow::Result<FacetedResults> {
+        self.search_faceted_with(query, limit, ResultSort::default(), None)
+    }
+
+    /// `search_faceted` with its results ordered as `search_sorted` orders them, or reranked as
+    /// `search_with_session` reranks them, from the same pass over the candidates as the facets
+    pub fn search_faceted_with<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        query: &Q,
+        limit: usize,
+        sort: ResultSort,
+        session: Option<&SearchSession>,
+    ) -> anyhow::Result<FacetedResults> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let searched = self.query_candidates(query.as_ref(), None).map(|(mut results, documents)| {
+            let facets = Facets::count(&results, &documents);
+            match session {
+                Some(session) if !session.is_empty() => {
+                    results.truncate(limit.saturating_mul(2));
+                    results = self.rerank_in_session(session, results, limit);
+                }
+                _ if !sort.is_relevance() => sort.sort(&mut results, &documents),
+                _ => {}
+            }
+            results.truncate(limit);
+            (results, facets)
+        });
+        self.record_usage_since("search", &before);
+        let (results, facets) = match searched {
+            Ok((results, facets)) => (Ok(results), Some(facets)),
+            Err(e) => (Err(e), None),
+        };
+        self.log_query(&query.to_string(), &DocumentFilter::default(), started, &results);
+        Ok(FacetedResults { results: results?, facets: facets.unwrap_or_default() })
+    }
+
+    /// `search_query` ordered by `sort`: every chunk that matched, as `matching_chunks` decides, is
+    /// sorted before the top `limit` are taken, so `date` returns the newest matches rather than the
+    /// newest of the best ones, one per document. An explicit sort takes precedence over pinned documents
+    pub fn search_sorted<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        query: &Q,
+        limit: usize,
+        sort: ResultSort,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        if sort.is_relevance() {
+            return self.search_query(query, limit);
+        }
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.query_candidates(query.as_ref(), None).map(|(mut results, documents)| {
+            sort.sort(&mut results, &documents);
+            results.truncate(limit);
+            results
+        });
+        self.record_usage_since("search", &before);
+        self.log_query(&query.to_string(), &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    /// Facet counts of every candidate of `query`, for results found some other way
+    pub fn facets(&self, query: &Query) -> anyhow::Result<Facets> {
+        let before = self.usage.total();
+        let candidates = self.query_candidates(query, None);
+        self.record_usage_since("search", &before);
+        let (results, documents) = candidates?;
+        Ok(Facets::count(&results, &documents))
+    }
+
+    /// The best `limit` chunks for `query`, or every chunk that matched it, as `matching_chunks`
+    /// decides, with the metadata of the documents when they were needed to select chunks or count facets
+    fn query_candidates(
+        &self,
+        query: &Query,
+        limit: Option<usize>,
+    ) -> anyhow::Result<(Vec<SearchResult>, HashMap<String, DocumentMetadata>)> {
+        let mut chunks = self.storage.get_all_chunks()?;
+        let mut documents = HashMap::new();
+        if query.has_constraints() || limit.is_none() {
+            for doc_id in self.storage.list_documents()? {
+                if let Some(doc) = self.storage.get_document(&doc_id)? {
+                    documents.insert(doc_id, doc.metadata);
+                }
+            }
+        }
+        if query.has_constraints() {
+            chunks.retain(|c| documents.get(&c.document_id).is_some_and(|metadata| query.matches(c, metadata)));
+        }
+
+        let text = query.text();
+        if !text.trim().is_empty() {
+            let results = match limit {
+                Some(limit) => self.search_chunks(&text, chunks, limit)?,
+                None => self.matching_chunks(&text, chunks)?,
+            };
+            return Ok((results, documents));
+        }
+        let limit = limit.unwrap_or(chunks.len());
+        let listed = chunks.into_iter().filter(|c| !c.is_summary()).take(limit).enumerate();
+        let results = listed
+            .map(|(i, chunk)| SearchResult {
+                chunk_id: chunk.id,
+                document_id: chunk.document_id,
+                content: chunk.content.to_string(),
+                score: 1.0,
+                rank: i + 1,
+                pinned: false,
+            })
+            .collect();
+        Ok((results, documents))
+    }
+
+    /// `search_query` within a session: documents its recent searches returned score `session_boost`
+    /// higher and ones rejected in it `session_demotion` lower, and this search's results are
+    /// remembered for the next. Twice `limit` candidates are reranked, so a demoted result can give
+    /// way to one just below the cut
+    pub fn search_in_session<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        session: &mut SearchSession,
+        query: &Q,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let results = self.search_with_session(session, query, limit)?;
+        session.record(&results);
+        Ok(results)
     }
-}
\ No newline at end of file
+
+    /// `search_in_session` without remembering the results, for a caller that records them with
+    /// `SearchSession::record` into a session others may have changed meanwhile
+    pub fn search_with_session<Q: AsRef<Query> + std::fmt::Display + ?Sized>(
+        &self,
+        session: &SearchSession,
+        query: &Q,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        if session.is_empty() {
+            return self.search_query(query, limit);
+        }
+        let results = self.search_query(query, limit.saturating_mul(2))?;
+        Ok(self.rerank_in_session(session, results, limit))
+    }
+
+    /// The best `limit` of `results` once `session`'s boosts and demotions are applied, pinned first
+    fn rerank_in_session(&self, session: &SearchSession, mut results: Vec<SearchResult>, limit: usize) -> Vec<SearchResult> {
+        let config = self.searcher.config();
+        for result in &mut results {
+            result.score *= session.factor(&result.document_id, config);
+        }
+        results.sort_by(|a, b| b.pinned.cmp(&a.pinned).then(b.score.total_cmp(&a.score)));
+        results.truncate(limit);
+        for (rank, result) in results.iter_mut().enumerate() {
+            result.rank = rank + 1;
+        }
+        results
+    }
+
+    /// Save a search to re-run by name, replacing and returning any saved under that name before.
+    /// Fails on a name or query `SavedSearch::parsed` rejects
+    pub fn save_search(&mut self, mut search: SavedSearch) -> anyhow::Result<Option<SavedSearch>> {
+        search.parsed()?;
+        search.saved_at = answer_cache::unix_now();
+        Ok(self.storage.save_search(search))
+    }
+
+    pub fn saved_search(&self, name: &str) -> Option<SavedSearch> {
+        self.storage.saved_search(name)
+    }
+
+    /// Saved searches, by name
+    pub fn saved_searches(&self) -> Vec<SavedSearch> {
+        self.storage.saved_searches()
+    }
+
+    pub fn delete_saved_search(&mut self, name: &str) -> anyhow::Result<SavedSearch> {
+        self.storage
+            .delete_saved_search(name)
+            .ok_or_else(|| anyhow!("No saved search named '{}'", name))
+    }
+
+    /// Run the search saved as `name`, for `limit` results if given, otherwise the number it was saved with
+    pub fn run_saved_search(&self, name: &str, limit: Option<usize>) -> anyhow::Result<Vec<SearchResult>> {
+        let saved = self.saved_search(name).ok_or_else(|| anyhow!("No saved search named '{}'", name))?;
+        let limit = limit.or(saved.limit).unwrap_or(DEFAULT_SAVED_SEARCH_LIMIT);
+        self.search_query(&TypedQuery { input: saved.query.clone(), query: saved.parsed()? }, limit)
+    }
+
+    /// Run every query, timing each and recording failures alongside the results
+    pub fn batch_search(&self, queries: &[String], limit: usize) -> Vec<QueryBatchResult> {
+        queries
+            .iter()
+            .map(|query| {
+                let started = Instant::now();
+                let outcome = self.search(query, limit);
+                let latency_ms = elapsed_ms(started);
+                let (results, error) = match outcome {
+                    Ok(results) => (results, None),
+                    Err(e) => (Vec::new(), Some(e.to_string())),
+                };
+                QueryBatchResult {
+                    query: query.clone(),
+                    results,
+                    error,
+                    latency_ms,
+                }
+            })
+            .collect()
+    }
+
+    /// Document-level search over the summaries generated at ingest, one result per document
+    pub fn search_documents(&self, query: &str, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let mut summaries = self.storage.get_all_chunks()?;
+        summaries.retain(DocumentChunk::is_summary);
+        let results = self.search_chunks(query, summaries, limit);
+        self.record_usage_since("search", &before);
+        self.log_query(query, &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    /// Find the `documents` best-matching documents by their summaries (or opening chunks, when
+    /// summaries are off), then search only their chunks. On large mixed corpora this skips scoring
+    /// most chunks and keeps results from drifting into off-topic documents.
+    pub fn search_two_stage(&self, query: &str, documents: usize, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.two_stage_chunks(query, documents, limit);
+        self.record_usage_since("search", &before);
+        self.log_query(query, &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    /// Falls back to searching every chunk when fewer than `documents` representatives match at all,
+    /// since the rest were picked by storage order rather than relevance
+    fn two_stage_chunks(&self, query: &str, documents: usize, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let mut chunks = self.storage.get_all_chunks()?;
+        let candidates = self.score_chunks(query, document_representatives(&chunks), documents, false)?;
+        let selected: HashSet<&str> =
+            candidates.iter().filter(|r| r.score > 0.0).map(|r| r.document_id.as_str()).collect();
+        if selected.len() < documents {
+            tracing::debug!("Only {} documents matched in the first stage, searching them all", selected.len());
+            chunks.retain(|c| !c.is_summary());
+            return self.search_chunks(query, chunks, limit);
+        }
+        tracing::debug!("Two-stage search narrowed to {} documents", selected.len());
+        chunks.retain(|c| !c.is_summary() && selected.contains(c.document_id.as_str()));
+        self.search_chunks(query, chunks, limit)
+    }
+
+    /// Graph of the entity relations extracted at ingest, across every document
+    pub fn knowledge_graph(&self) -> anyhow::Result<KnowledgeGraph> {
+        Ok(self.storage.knowledge_graph())
+    }
+
+    /// Like `search`, then follow the knowledge graph up to `hops` edges out from the entities in the
+    /// query and results, adding chunks that name any entity on the way. Questions spanning several
+    /// entities find passages that share no words with the question itself.
+    pub fn search_graph(&self, query: &str, limit: usize, hops: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let before = self.usage.total();
+        let results = self.graph_expanded_chunks(query, limit, hops);
+        self.record_usage_since("search", &before);
+        self.log_query(query, &DocumentFilter::default(), started, &results);
+        results
+    }
+
+    fn graph_expanded_chunks(&self, query: &str, limit: usize, hops: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let chunks = self.storage.get_all_chunks()?;
+        let mut results = self.search_chunks(query, chunks.clone(), limit)?;
+        let graph = self.knowledge_graph()?;
+        let mut seeds = graph.entities_in(query);
+        for result in results.iter().filter(|r| r.score > 0.0) {
+            seeds.extend(graph.entities_in(&result.content));
+        }
+        let mut reached = graph.neighbourhood(&seeds, hops);
+        tracing::debug!("Graph expansion from {} entities reached {}", seeds.len(), reached.len());
+        if seeds.is_empty() {
+            return Ok(results);
+        }
+        reached.extend(seeds.into_iter().map(|entity| (entity, 0)));
+
+        // A chunk naming an entity is one hop past it; each hop discounts the best result's score
+        // Only chunks linked to an entity at ingest can be reached, so only they are looked at
+        let anchor = results.first().map_or(1.0, |r| r.score);
+        let found: HashMap<String, usize> = results.iter().enumerate().map(|(i, r)| (r.chunk_id.clone(), i)).collect();
+        let visible: HashMap<&str, &DocumentChunk> = chunks.iter().map(|c| (c.id.as_str(), c)).collect();
+        let links = self.storage.entity_links();
+        for (chunk_id, entities) in links.linked_chunks() {
+            let Some(chunk) = visible.get(chunk_id.as_str()) else {
+                continue;
+            };
+            let Some(hop) = entities.iter().filter_map(|e| reached.get(e)).min().copied() else {
+                continue;
+            };
+            let score = anchor * GRAPH_HOP_DECAY.powi(hop as i32 + 1);
+            match found.get(&chunk.id) {
+                Some(&i) => results[i].score = results[i].score.max(score),
+                None => results.push(SearchResult {
+                    chunk_id: chunk.id.clone(),
+                    document_id: chunk.document_id.clone(),
+                    content: chunk.content.to_string(),
+                    score,
+                    rank: 0,
+                    pinned: false,
+                }),
+            }
+        }
+        // Stable, so ties keep search results ahead of expansions
+        results.sort_by(|a, b| b.score.total_cmp(&a.score));
+        results.truncate(limit);
+        for (rank, result) in results.iter_mut().enumerate() {
+            result.rank = rank + 1;
+        }
+        Ok(results)
+    }
+
+    #[tracing::instrument(
+        name = "search",
+        skip(self, all_chunks),
+        fields(mode = ?self.searcher.config().mode, chunks = all_chunks.len(), results = Empty, elapsed_ms = Empty)
+    )]
+    fn search_chunks(&self, query: &str, all_chunks: Vec<DocumentChunk>, limit: usize) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let results = self.score_chunks(query, self.group_by_shard(all_chunks), limit, false);
+        self.finish_search(started, &results);
+        results
+    }
+
+    /// Every chunk that matched `query`, best first: one holding a query term or scoring at least
+    /// `MIN_RELATIVE_SCORE` of the best, not every chunk a vector search scores above 0
+    #[tracing::instrument(
+        name = "search",
+        skip(self, all_chunks),
+        fields(mode = ?self.searcher.config().mode, chunks = all_chunks.len(), results = Empty, elapsed_ms = Empty)
+    )]
+    fn matching_chunks(&self, query: &str, all_chunks: Vec<DocumentChunk>) -> anyhow::Result<Vec<SearchResult>> {
+        let started = Instant::now();
+        let limit = all_chunks.len();
+        let results = self.score_chunks(query, self.group_by_shard(all_chunks), limit, true);
+        self.finish_search(started, &results);
+        results
+    }
+
+    /// `chunks` ordered so each shard's form one run, as `search_shards` expects
+    fn group_by_shard(&self, mut chunks: Vec<DocumentChunk>) -> Vec<DocumentChunk> {
+        let shards = self.storage.shard_count();
+        if shards > 1 {
+            chunks.sort_by_cached_key(|chunk| shard_of(&chunk.document_id, shards));
+        }
+        chunks
+    }
+
+    /// Score each shard's run of `chunks` on a thread of its own, with BM25 weighing terms by counts
+    /// of all the shards together, then rank the joined scores once as one search over `chunks` would
+    fn search_shards<E: EmbeddingVector + Sync>(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f32]>,
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        context: &SearchContext,
+        limit: usize,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let shards = self.storage.shard_count();
+        if shards <= 1 {
+            let signals = self.searcher.signals(query, query_embedding, chunks, embeddings, context)?;
+            return Ok(self.searcher.rank_signals(query, chunks, signals, context, limit));
+        }
+        let runs: Vec<&[DocumentChunk]> = chunks
+            .chunk_by(|a, b| shard_of(&a.document_id, shards) == shard_of(&b.document_id, shards))
+            .collect();
+        let mut sharded = None;
+        if self.searcher.config().mode == SearchMode::Bm25 {
+            let stats = on_each_run(&runs, |run| Ok(self.searcher.term_stats(query, run, &context.collections)))?;
+            let mut context = context.clone();
+            context.term_stats = Some(stats.into_iter().fold(TermStats::default(), TermStats::merge));
+            sharded = Some(context);
+        }
+        let context = sharded.as_ref().unwrap_or(context);
+        let signals = on_each_run(&runs, |run| self.searcher.signals(query, query_embedding, run, embeddings, context))?;
+        Ok(self.searcher.rank_signals(query, chunks, ChunkSignals::concat(signals), context, limit))
+    }
+
+    /// Record the latency and result count of a search on its span and in the metrics
+    fn finish_search(&self, started: Instant, results: &anyhow::Result<Vec<SearchResult>>) {
+        self.metrics.record_search(started.elapsed());
+        let span = tracing::Span::current();
+        if let Ok(results) = results {
+            span.record("results", results.len());
+        }
+        span.record("elapsed_ms", elapsed_ms(started));
+    }
+
+    /// Add a search to the query log, when it's enabled
+    fn log_query(
+        &self,
+        query: &str,
+        filter: &DocumentFilter,
+        started: Instant,
+        results: &anyhow::Result<Vec<SearchResult>>,
+    ) {
+        let Some(config) = &self.query_log else {
+            return;
+        };
+        let entry = QueryLogEntry {
+            timestamp: answer_cache::unix_now(),
+            query: query.to_string(),
+            mode: self.searcher.config().mode,
+            filter: filter.clone(),
+            result_ids: results.iter().flatten().map(|r| r.chunk_id.clone()).collect(),
+            latency_ms: elapsed_ms(started),
+            error: results.as_ref().err().map(|e| e.to_string()),
+            tenant: None,
+        };
+        self.storage.record_query(entry, config.max_entries);
+    }
+
+    fn score_chunks(
+        &self,
+        query: &str,
+        all_chunks: Vec<DocumentChunk>,
+        limit: usize,
+        matched_only: bool,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        tracing::debug!("{:?} search for '{}' over {} chunks", self.searcher.config().mode, query, all_chunks.len());
+        let translated = match self.searcher.translation_language() {
+            Some(language) => Some(translate_query(self.completion_provider()?.as_ref(), query, language)?),
+            None => None,
+        };
+        let query = translated.as_deref().unwrap_or(query);
+        let Some(embedder) = self.query_embedder()? else {
+            let context = SearchContext { matched_only, ..self.search_context()? };
+            return self.search_shards(query, None, &all_chunks, &HashMap::<String, Vec<f32>>::new(), &context, limit);
+        };
+        let embedded_text = if self.hyde {
+            hypothetical_document(self.completion_provider()?.as_ref(), query)?
+        } else {
+            self.searcher.embedding_query(query)
+        };
+        let query_embedding = first_embedding(embedder, self.embed_cached(embedder, &[embedded_text])?)?;
+        self.score_with_embedding(query, &query_embedding, &all_chunks, limit, matched_only)
+    }
+
+    /// Document collections, when some collection has an analyzer or vector index of its own, relevance
+    /// feedback, unless it is turned off, the IVF index, if some chunks are searched with it, and the
+    /// chunks the disk index is stale for
+    fn search_context(&self) -> anyhow::Result<SearchContext> {
+        let config = self.searcher.config();
+        let mut context = SearchContext::default();
+        if config.feedback_weight > 0.0 {
+            context.feedback = self.storage.feedback();
+        }
+        if config.learned_ranking {
+            context.ranking = self.storage.ranking_model().filter(|model| model.mode == config.mode);
+            if context.ranking.is_none() {
+                tracing::debug!("No ranking model trained in {:?} mode; using its own scores", config.mode);
+            }
+        }
+        if config.may_use(VectorIndex::Ivf) {
+            context.ivf = self.storage.ivf_index();
+        }
+        if self.searcher.disk_index().is_some() {
+            context.stale_vectors = self.storage.stale_vectors();
+        }
+        context.boosts = self.storage.document_boosts();
+        let recency = context.ranking.is_some();
+        if recency || !config.collection_analyzers.is_empty() || !config.collection_vector_indexes.is_empty() {
+            self.add_document_signals(&mut context, recency)?;
+        }
+        Ok(context)
+    }
+
+    /// Fill in each document's collection and, with `recency`, how recently its file changed, from
+    /// what was recorded at ingest rather than by loading documents or checking their files
+    fn add_document_signals(&self, context: &mut SearchContext, recency: bool) -> anyhow::Result<()> {
+        context.collections = self.storage.document_collections();
+        if recency {
+            let now = answer_cache::unix_now();
+            context.recency = self
+                .storage
+                .source_modified_times()
+                .into_iter()
+                .map(|(doc_id, modified)| (doc_id, ltr::recency(modified, now)))
+                .collect();
+        }
+        Ok(())
+    }
+
+    /// Embedder for the query in vector and hybrid modes; `None` in lexical modes
+    fn query_embedder(&self) -> anyhow::Result<Option<&dyn EmbeddingProvider>> {
+        let mode = self.searcher.config().mode;
+        if !mode.uses_embeddings() {
+            return Ok(None);
+        }
+        let embedder = self
+            .embedder
+            .as_ref()
+            .ok_or_else(|| anyhow!("{:?} search needs an embedding provider", mode))?;
+        if let Some(model) = self.storage.embedding_model() {
+            if model != embedder.model_name() {
+                return Err(anyhow!("Index is embedded with {}, but queries would use {}", model, embedder.model_name()));
+            }
+        }
+        Ok(Some(embedder.as_ref()))
+    }
+
+    fn score_with_embedding(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        chunks: &[DocumentChunk],
+        limit: usize,
+        matched_only: bool,
+    ) -> anyhow::Result<Vec<SearchResult>> {
+        let embeddings = self.storage.get_stored_embeddings()?;
+        let context = SearchContext { matched_only, ..self.search_context()? };
+        let truncated = self.storage.embedding_dimension_limit().is_some_and(|d| d < query_embedding.len());
+        let (Some(candidates), true, Some(embedder)) =
+            (self.searcher.config().full_dimension_rescoring, truncated, self.query_embedder()?)
+        else {
+            return self.search_shards(query, Some(query_embedding), chunks, &embeddings, &context, limit);
+        };
+
+        let first_pass =
+            self.search_shards(query, Some(query_embedding), chunks, &embeddings, &context, candidates.max(limit))?;
+        let selected: HashSet<&str> = first_pass.iter().map(|r| r.chunk_id.as_str()).collect();
+        let candidates: Vec<DocumentChunk> = chunks.iter().filter(|c| selected.contains(c.id.as_str())).cloned().collect();
+        tracing::debug!("Rescoring {} chunks with full-dimension embeddings", candidates.len());
+        let vectors = self.embed_cached(embedder, &chunk_texts(&candidates))?;
+        let full: HashMap<String, Vec<f32>> = candidates.iter().map(|c| c.id.clone()).zip(vectors).collect();
+        self.searcher
+            .search_with_embeddings_in(query, query_embedding, &candidates, &full, &context, limit)
+    }
+
+    /// Embed texts, only calling the provider for ones not already in the storage cache
+    fn embed_cached(&self, embedder: &dyn EmbeddingProvider, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+        let (vectors, uncached) = self.cached_embeddings(embedder.model_name(), texts);
+        let fresh = if uncached.is_empty() { Vec::new() } else { embedder.embed(&uncached)? };
+        self.merge_embeddings(embedder.model_name(), texts, vectors, &uncached, fresh)
+    }
+
+    /// Vectors already cached for `texts`, and the texts the provider still has to embed
+    fn cached_embeddings(&self, model: &str, texts: &[String]) -> (Vec<Option<Vec<f32>>>, Vec<String>) {
+        let vectors: Vec<Option<Vec<f32>>> = texts.iter().map(|t| self.storage.cached_embedding(model, t)).collect();
+        let uncached: Vec<String> = texts
+            .iter()
+            .zip(&vectors)
+            .filter(|(_, vector)| vector.is_none())
+            .map(|(text, _)| text.clone())
+            .collect();
+        tracing::debug!("{} of {} embeddings served from the cache", texts.len() - uncached.len(), texts.len());
+        self.metrics.record_embedding_lookups(texts.len() - uncached.len(), uncached.len());
+        (vectors, uncached)
+    }
+
+    /// Fill the gaps left by `cached_embeddings` with freshly embedded vectors, caching them
+    fn merge_embeddings(
+        &self,
+        model: &str,
+        texts: &[String],
+        mut vectors: Vec<Option<Vec<f32>>>,
+        uncached: &[String],
+        fresh: Vec<Vec<f32>>,
+    ) -> anyhow::Result<Vec<Vec<f32>>> {
+        if fresh.len() != uncached.len() {
+            return Err(anyhow!("{} returned {} embeddings for {} texts", model, fresh.len(), uncached.len()));
+        }
+        let missing = (0..texts.len()).filter(|&i| vectors[i].is_none()).collect::<Vec<_>>();
+        for (i, vector) in missing.into_iter().zip(fresh) {
+            self.storage.cache_embedding(model, &texts[i], vector.clone());
+            vectors[i] = Some(vector);
+        }
+        Ok(vectors.into_iter().flatten().collect())
+    }
+
+    pub fn evaluate_search(&mut self, query: &str, expected_doc_ids: &[String]) -> anyhow::Result<EvaluationMetrics> {
+        let results = self.search(query, 5)?;
+        let evaluator = Evaluator::new();
+        let metrics = evaluator.evaluate(&results, expected_doc_ids)?;
+        self.record_evaluation(1, 5, &metrics)?;
+        Ok(metrics)
+    }
+
+    /// Evaluate every query in the dataset at `k` and record the run in the history
+    pub fn evaluate_dataset(&mut self, dataset: &EvaluationDataset, k: usize) -> anyhow::Result<DatasetEvaluation> {
+        let all_chunks = self.storage.get_all_chunks()?;
+        let evaluation = Evaluator::new().evaluate_dataset(&self.searcher, &all_chunks, dataset, k)?;
+        self.record_evaluation(dataset.queries.len(), k, &evaluation.mean)?;
+        Ok(evaluation)
+    }
+
+    /// Past evaluation runs, oldest first
+    pub fn evaluation_history(&self) -> anyhow::Result<Vec<EvaluationRun>> {
+        self.storage.list_evaluation_runs()
+    }
+
+    fn record_evaluation(&mut self, query_count: usize, k: usize, metrics: &EvaluationMetrics) -> anyhow::Result<()> {
+        let timestamp = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)?
+            .as_secs();
+        self.storage.record_evaluation_run(EvaluationRun {
+            id: uuid::Uuid::new_v4().to_string(),
+            timestamp,
+            config_fingerprint: self.searcher.config().fingerprint(),
+            query_count,
+            k,
+            metrics: metrics.clone(),
+        })
+    }
+
+    /// Evaluate two search configurations against the stored corpus
+    pub fn compare_search_configs(
+        &self,
+        config_a: &SearchConfig,
+        config_b: &SearchConfig,
+        dataset: &EvaluationDataset,
+        k: usize,
+    ) -> anyhow::Result<ComparisonReport> {
+        let all_chunks = self.storage.get_all_chunks()?;
+        Evaluator::new().compare(config_a, config_b, dataset, &all_chunks, k)
+    }
+
+    /// Measure what embedding quantization would cost on `dataset`: the same searches against the
+    /// stored full-precision vectors and a quantized copy of them. Needs an embedder and an index that
+    /// isn't quantized yet
+    pub fn evaluate_quantization(
+        &self,
+        dataset: &EvaluationDataset,
+        quantization: EmbeddingQuantization,
+        k: usize,
+    ) -> anyhow::Result<QuantizationReport> {
+        if !self.searcher.config().mode.uses_embeddings() {
+            return Err(anyhow!("Quantization only affects vector and hybrid search"));
+        }
+        let embedder = self
+            .query_embedder()?
+            .ok_or_else(|| anyhow!("Measuring quantization needs an embedding provider to embed the queries"))?;
+        let stored = self.storage.get_stored_embeddings()?;
+        if stored.values().any(StoredEmbedding::is_quantized) {
+            return Err(anyhow!("The index is already quantized; re-embed it at full precision to measure again"));
+        }
+        let embeddings = stored.into_iter().map(|(id, e)| (id, e.to_vec())).collect();
+        let texts: Vec<String> = dataset.queries.iter().map(|q| self.searcher.embedding_query(&q.query)).collect();
+        let query_embeddings = self.embed_cached(embedder, &texts)?;
+        let all_chunks = self.storage.get_all_chunks()?;
+        Evaluator::new().evaluate_quantization(
+            &self.searcher,
+            &all_chunks,
+            &embeddings,
+            dataset,
+            &query_embeddings,
+            quantization,
+            k,
+        )
+    }
+
+    /// Run the regression harness over the stored corpus with the system's search engine
+    pub fn check_regressions(&self, harness: &RegressionHarness, dataset: &EvaluationDataset) -> anyhow::Result<RegressionReport> {
+        let all_chunks = self.storage.get_all_chunks()?;
+        harness.run(&self.searcher, &all_chunks, dataset)
+    }
+
+    /// Bootstrap an evaluation dataset from the stored corpus
+    pub fn generate_eval_dataset(&self, generator: &QaGenerator, count: usize) -> anyhow::Result<EvaluationDataset> {
+        let all_chunks = self.storage.get_all_chunks()?;
+        generator.generate(&all_chunks, count)
+    }
+
+    /// Evaluate a query without ground truth by letting an LLM grade each result
+    pub fn judge_search(&self, query: &str, limit: usize, judge: &RelevanceJudge) -> anyhow::Result<JudgedMetrics> {
+        let results = self.search(query, limit)?;
+        let evaluator = Evaluator::new();
+        evaluator.evaluate_with_judge(query, &results, judge)
+    }
+
+    /// Score a generated answer for faithfulness next to the retrieval metrics of its query
+    pub fn evaluate_answer(
+        &self,
+        query: &str,
+        answer: &str,
+        expected_doc_ids: &[String],
+        faithfulness: &FaithfulnessEvaluator,
+    ) -> anyhow::Result<RagEvaluationReport> {
+        let results = self.search(query, 5)?;
+        let evaluator = Evaluator::new();
+        Ok(RagEvaluationReport {
+            query: query.to_string(),
+            retrieval: evaluator.evaluate(&results, expected_doc_ids)?,
+            answer: faithfulness.evaluate(answer, &results)?,
+        })
+    }
+
+    /// Move a document to the trash, returning how many chunks went with it. Searches no longer see it,
+    /// but `undelete_document` brings it back until `purge` removes it for good
+    pub fn delete_document(&mut self, doc_id: &str) -> anyhow::Result<usize> {
+        let source = self.storage.document_source(doc_id);
+        let chunks = self
+            .storage
+            .trash_document(doc_id, answer_cache::unix_now())
+            .and_then(|chunks| chunks.ok_or_else(|| anyhow!("No document with id '{}'", doc_id)));
+        self.audit(AuditAction::Delete, source.as_deref(), Some(doc_id), &chunks);
+        chunks
+    }
+
+    /// Take a document back out of the trash
+    pub fn undelete_document(&mut self, doc_id: &str) -> anyhow::Result<()> {
+        let restored = self.storage.undelete_document(doc_id).and_then(|restored| match restored {
+            true => Ok(()),
+            false => Err(anyhow!("No deleted document with id '{}'", doc_id)),
+        });
+        self.audit(AuditAction::Undelete, self.storage.document_source(doc_id).as_deref(), Some(doc_id), &restored);
+        restored
+    }
+
+    /// Documents in the trash, oldest deletion first
+    pub fn trashed_documents(&self) -> Vec<TrashedDocument> {
+        self.storage.trashed_documents()
+    }
+
+    /// Remove a document and everything derived from it for good, trashed or not
+    pub fn purge_document(&mut self, doc_id: &str) -> anyhow::Result<usize> {
+        let source = self.storage.document_source(doc_id);
+        let chunks = self
+            .storage
+            .delete_document(doc_id)
+            .and_then(|chunks| chunks.ok_or_else(|| anyhow!("No document with id '{}'", doc_id)));
+        self.audit(AuditAction::Purge, source.as_deref(), Some(doc_id), &chunks);
+        chunks
+    }
+
+    /// Permanently remove the documents deleted at least `older_than` ago, returning them
+    pub fn purge(&mut self, older_than: Duration) -> anyhow::Result<Vec<TrashedDocument>> {
+        let cutoff = answer_cache::unix_now().saturating_sub(older_than.as_secs());
+        let expired: Vec<TrashedDocument> =
+            self.trashed_documents().into_iter().filter(|doc| doc.deleted_at <= cutoff).collect();
+        for doc in &expired {
+            self.purge_document(&doc.id)?;
+        }
+        Ok(expired)
+    }
+
+    /// Move every document to the trash, as `delete_document` does, returning how many documents and
+    /// chunks were deleted. `purge` removes them for good
+    pub fn delete_all_documents(&mut self) -> anyhow::Result<(usize, usize)> {
+        let doc_ids = self.list_documents()?;
+        let mut chunks = 0;
+        for doc_id in &doc_ids {
+            chunks += self.delete_document(doc_id)?;
+        }
+        Ok((doc_ids.len(), chunks))
+    }
+
+    /// Move the documents tagged `collection=<name>` to the trash, returning how many documents and
+    /// chunks were deleted
+    pub fn delete_collection(&mut self, name: &str) -> anyhow::Result<(usize, usize)> {
+        let doc_ids = self.documents_matching(&DocumentFilter::collection(name))?;
+        let mut chunks = 0;
+        for doc_id in &doc_ids {
+            chunks += self.delete_document(doc_id)?;
+        }
+        Ok((doc_ids.len(), chunks))
+    }
+
+    pub fn list_documents(&self) -> anyhow::Result<Vec<String>> {
+        self.storage.list_documents()
+    }
+
+    /// Exact and near-duplicate documents, near meaning shingle similarity of at least `threshold`
+    pub fn duplicate_report(&self, threshold: f64) -> anyhow::Result<DuplicateReport> {
+        let mut documents = Vec::new();
+        for doc_id in self.storage.list_documents()? {
+            documents.extend(self.storage.get_document(&doc_id)?);
+        }
+        Ok(find_duplicates(&documents, threshold))
+    }
+
+    /// Chunks of different documents that nearly repeat each other, by MinHash similarity
+    pub fn duplicate_chunks(&self, threshold: f64) -> anyhow::Result<Vec<ChunkDuplicateGroup>> {
+        Ok(find_duplicate_chunks(&self.storage.get_all_chunks()?, threshold))
+    }
+
+    /// IDs of the documents whose metadata passes `filter`
+    pub fn documents_matching(&self, filter: &DocumentFilter) -> anyhow::Result<Vec<String>> {
+        let mut selected = Vec::new();
+        for doc_id in self.storage.list_documents()? {
+            if let Some(doc) = self.storage.get_document(&doc_id)? {
+                if filter.matches(&doc.metadata) {
+                    selected.push(doc_id);
+                }
+            }
+        }
+        Ok(selected)
+    }
+
+    pub fn get_document(&self, doc_id: &str) -> anyhow::Result<Option<ProcessedDocument>> {
+        self.storage.get_document(doc_id)
+    }
+
+    /// The `limit` keyphrases that best describe what a document covers, from its chunks' keyphrases
+    pub fn keyphrases(&self, doc_id: &str, limit: usize) -> anyhow::Result<Vec<DocumentKeyphrase>> {
+        self.require_document(doc_id)?;
+        Ok(document_keyphrases(&self.storage.get_document_chunks(doc_id)?, limit))
+    }
+
+    /// Documents most like `doc_id`, best first, leaving out ones with nothing in common. Compares the
+    /// means of their chunk embeddings when the document has embeddings, otherwise their term counts
+    /// weighted by inverse document frequency across the index
+    pub fn related_documents(&self, doc_id: &str, limit: usize) -> anyhow::Result<Vec<RelatedDocument>> {
+        self.require_document(doc_id)?;
+        let chunks = self.storage.get_all_chunks()?;
+        let mut by_document: BTreeMap<&str, Vec<&DocumentChunk>> = BTreeMap::new();
+        for chunk in chunks.iter().filter(|c| !c.is_summary()) {
+            by_document.entry(&chunk.document_id).or_default().push(chunk);
+        }
+        let target = by_document.remove(doc_id).unwrap_or_default();
+        let embeddings = self.storage.get_all_embeddings()?;
+
+        let mut scored: Vec<(&str, f32, Similarity)> = match embedding_centroid(&target, &embeddings) {
+            Some(centroid) => by_document
+                .iter()
+                .filter_map(|(id, chunks)| {
+                    let other = embedding_centroid(chunks, &embeddings)?;
+                    Some((*id, centroid_similarity(&centroid, &other), Similarity::EmbeddingCentroid))
+                })
+                .collect(),
+            None => {
+                let mut profile = term_profile(&target);
+                let mut others: Vec<(&str, HashMap<String, f32>)> =
+                    by_document.iter().map(|(id, chunks)| (*id, term_profile(chunks))).collect();
+                weight_by_idf(others.iter_mut().map(|(_, other)| other).chain([&mut profile]));
+                others
+                    .iter()
+                    .map(|(id, other)| (*id, term_similarity(&profile, other), Similarity::TermOverlap))
+                    .collect()
+            }
+        };
+        scored.retain(|(_, score, _)| *score > 0.0);
+        scored.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let mut related = Vec::new();
+        for (id, score, method) in scored.into_iter().take(limit) {
+            let file_path = self.storage.get_document(id)?.map(|d| d.metadata.file_path).unwrap_or_default();
+            related.push(RelatedDocument { document_id: id.to_string(), file_path, score, method });
+        }
+        Ok(related)
+    }
+
+    pub fn tags(&self, doc_id: &str) -> anyhow::Result<BTreeMap<String, String>> {
+        Ok(self.require_document(doc_id)?.metadata.tags)
+    }
+
+    /// Set `key` on a document, returning the value it replaced
+    pub fn set_tag(&mut self, doc_id: &str, key: &str, value: &str) -> anyhow::Result<Option<String>> {
+        let mut tags = self.tags(doc_id)?;
+        let previous = tags.insert(key.to_string(), value.to_string());
+        DocumentBoost::from_tags(&tags)?;
+        self.storage.set_document_tags(doc_id, tags)?;
+        Ok(previous)
+    }
+
+    /// Remove `key` from a document, returning its value if it was set
+    pub fn remove_tag(&mut self, doc_id: &str, key: &str) -> anyhow::Result<Option<String>> {
+        let mut tags = self.tags(doc_id)?;
+        let removed = tags.remove(key);
+        self.storage.set_document_tags(doc_id, tags)?;
+        Ok(removed)
+    }
+
+    pub fn set_tags(&mut self, doc_id: &str, tags: BTreeMap<String, String>) -> anyhow::Result<()> {
+        self.require_document(doc_id)?;
+        DocumentBoost::from_tags(&tags)?;
+        self.storage.set_document_tags(doc_id, tags)?;
+        Ok(())
+    }
+
+    fn require_document(&self, doc_id: &str) -> anyhow::Result<ProcessedDocument> {
+        self.storage
+            .get_document(doc_id)?
+            .ok_or_else(|| anyhow!("No document with id '{}'", doc_id))
+    }
+
+    /// Chunks stored for a document, in document order
+    pub fn document_chunks(&self, doc_id: &str) -> anyhow::Result<Vec<DocumentChunk>> {
+        self.storage.get_document_chunks(doc_id)
+    }
+
+    pub fn get_chunk(&self, chunk_id: &str) -> anyhow::Result<Option<DocumentChunk>> {
+        self.storage.get_chunk(chunk_id)
+    }
+
+    /// Backend, configuration and embedding details of the index, and how current it is
+    pub fn info(&self) -> anyhow::Result<SystemInfo> {
+        let stats = self.storage.get_stats()?;
+        let data_path = self.storage.path().map(Path::to_path_buf);
+        let mut documents = Vec::new();
+        for doc_id in self.storage.list_documents()? {
+            documents.extend(self.storage.get_document(&doc_id)?);
+        }
+        let mut freshness = IndexFreshness {
+            last_written: data_path.as_deref().and_then(modified_secs),
+            unembedded_chunks: self.storage.check_integrity().unembedded_chunks.len(),
+            ..Default::default()
+        };
+        freshness.check_sources(&documents);
+
+        Ok(SystemInfo {
+            backend: if data_path.is_some() { StorageBackend::Json } else { StorageBackend::Memory },
+            data_path,
+            chunking: self.chunker.strategy().clone(),
+            search_mode: self.searcher.config().mode,
+            embedding_model: self.storage.embedding_model(),
+            embedding_dimensions: self.storage.embedding_dimensions(),
+            tenant: self.storage.tenant_id().map(str::to_string),
+            tenants: self.storage.tenants(),
+            documents: stats.total_documents,
+            chunks: stats.total_chunks,
+            freshness,
+        })
+    }
+
+    /// Do the embedding work a cold first query would otherwise pay for: page in the stored embeddings,
+    /// check the embedder matches the index, and embed the most frequent logged queries (or a probe,
+    /// to open the provider connection) so they are served from the cache. Keyword scoring keeps no
+    /// index between queries, so there is nothing to warm for it
+    pub fn warm_up(&self) -> anyhow::Result<WarmUpReport> {
+        const PRIMED_QUERIES: usize = 100;
+        let started = Instant::now();
+        let mut report = WarmUpReport {
+            documents: self.storage.list_documents()?.len(),
+            chunks: self.storage.get_all_chunks()?.len(),
+            embeddings: self.storage.get_all_embeddings()?.len(),
+            ..Default::default()
+        };
+
+        if let Some(embedder) = self.query_embedder()? {
+            let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+            if !self.hyde {
+                for entry in self.storage.query_log()? {
+                    *counts.entry(entry.query).or_insert(0) += 1;
+                }
+            }
+            let mut queries: Vec<(String, usize)> = counts.into_iter().collect();
+            queries.sort_by_key(|(_, count)| std::cmp::Reverse(*count));
+            let texts: Vec<String> = queries.into_iter().take(PRIMED_QUERIES).map(|(query, _)| query).collect();
+            report.queries_primed = texts.len();
+            if texts.is_empty() {
+                // Bypasses the cache, which would otherwise answer the probe after the first run
+                embedder.embed(&["warm up".to_string()])?;
+            } else {
+                self.embed_cached(embedder, &texts)?;
+            }
+        }
+        report.elapsed_ms = elapsed_ms(started);
+        tracing::info!("Warmed up {} embeddings in {:.1}ms", report.embeddings, report.elapsed_ms);
+        Ok(report)
+    }
+
+    /// Remove chunks of deleted documents and embeddings of deleted chunks, reporting what was freed
+    pub fn gc(&mut self) -> anyhow::Result<GcReport> {
+        self.storage.gc()
+    }
+
+    /// Chunks, documents and embeddings that have lost their counterpart
+    pub fn check_integrity(&self) -> IntegrityReport {
+        self.storage.check_integrity()
+    }
+
+    /// Vocabulary, term document frequencies and chunk lengths, for tuning BM25
+    pub fn corpus_stats(&self) -> anyhow::Result<CorpusStats> {
+        self.storage.corpus_stats()
+    }
+
+    pub fn get_stats(&self) -> anyhow::Result<StorageStats> {
+        self.storage.get_stats()
+    }
+}
+
+fn chunk_texts(chunks: &[DocumentChunk]) -> Vec<String> {
+    chunks.iter().map(|c| c.content.to_string()).collect()
+}
+
+fn first_embedding(embedder: &dyn EmbeddingProvider, mut vectors: Vec<Vec<f32>>) -> anyhow::Result<Vec<f32>> {
+    vectors
+        .pop()
+        .ok_or_else(|| anyhow!("{} returned no embedding", embedder.model_name()))
+}
+
+/// `work` on each run of chunks on a thread of its own, results in run order
+fn on_each_run<T: Send>(
+    runs: &[&[DocumentChunk]],
+    work: impl Fn(&[DocumentChunk]) -> anyhow::Result<T> + Sync,
+) -> anyhow::Result<Vec<T>> {
+    let work = &work;
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = runs.iter().map(|run| scope.spawn(move || work(run))).collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap_or_else(|_| Err(anyhow!("Shard search panicked"))))
+            .collect()
+    })
+}
+
+/// Index the summary as an extra chunk and keep it in the metadata
+fn attach_summary(document: &mut ProcessedDocument, chunks: &mut Vec<DocumentChunk>, summary: String) {
+    chunks.push(summary_chunk(document, &summary));
+    document.metadata.summary = Some(summary);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use crate::generation::InsufficientContext;
+    use crate::llm::CompletionRequest;
+    use crate::ivf::IvfConfig;
+    use crate::search::CrossLingual;
+    use crate::summary::SUMMARY_CHUNK_SUFFIX;
+    use crate::tags::{BOOST_TAG, COLLECTION_TAG, PINNED_TAG};
+
+    #[test]
+    fn test_simple_rag_workflow() {
+        // Create test content
+        let test_content = r#"
+# Machine Learning Basics
+
+Machine learning is a subset of artificial intelligence that enables computers to learn from data without being explicitly programmed.
+
+## Key Concepts
+
+### Supervised Learning
+In supervised learning, we train models on labeled data.
+
+### Unsupervised Learning
+Unsupervised learning finds patterns in unlabeled data.
+
+Applications include natural language processing, computer vision, and recommendation systems.
+"#;
+
+        // Create temporary file
+        let test_file = "/tmp/test_rag.md";
+        fs::write(test_file, test_content).unwrap();
+
+        // Test the workflow
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+
+        // Test search
+        let results = rag.search("machine learning", 3).unwrap();
+        assert!(!results.is_empty());
+
+        // Test evaluation
+        let metrics = rag.evaluate_search("machine learning", &[doc_id]).unwrap();
+        assert!(metrics.relevance >= 0.0);
+        assert!(metrics.precision >= 0.0);
+        assert_eq!(rag.evaluation_history().unwrap().len(), 1);
+
+        // Clean up
+        fs::remove_file(test_file).unwrap();
+    }
+
+    struct ContextEchoLlm;
+
+    impl CompletionProvider for ContextEchoLlm {
+        fn model_name(&self) -> &str {
+            "context-echo"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> anyhow::Result<String> {
+            Ok(request.prompt.lines().nth(1).unwrap_or_default().to_string())
+        }
+    }
+
+    #[test]
+    fn test_ask_generates_from_retrieved_context() {
+        let test_file = "/tmp/test_rag_ask.txt";
+        fs::write(test_file, "Ferris is the unofficial mascot of the Rust programming language.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap();
+        assert!(rag.ask("Who is Ferris?").is_err());
+
+        rag = rag.with_completion_provider(ContextEchoLlm);
+        rag.process_document(Path::new(test_file)).unwrap();
+
+        let answer = rag.ask("Who is Ferris?").unwrap();
+        assert!(answer.text.starts_with("[1] Ferris is the unofficial mascot"));
+        assert_eq!(answer.citations.len(), 1);
+        assert_eq!(answer.citations[0].byte_start, 0);
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_usage_is_tracked_per_operation() {
+        let test_file = "/tmp/test_rag_usage.txt";
+        fs::write(test_file, "Ferris is a crab.").unwrap();
+
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_completion_provider(Arc::new(ContextEchoLlm))
+            .with_embedding_provider(Arc::new(CatEmbedder::default()));
+        rag.process_document(Path::new(test_file)).unwrap();
+        let answer = rag.ask("Who is Ferris?").unwrap();
+
+        assert_eq!(answer.usage.completion_calls, 1);
+        assert!(answer.usage.input_tokens > 0 && answer.usage.output_tokens > 0);
+        let usage = rag.get_stats().unwrap().usage;
+        assert_eq!(usage["ingest"].embedding_calls, 1);
+        assert_eq!(usage["ask"], answer.usage);
+        assert_eq!(rag.usage().completion_calls, 1);
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    /// Two-dimensional embedding: how much the text is about cats versus everything else
+    #[derive(Default)]
+    struct CatEmbedder {
+        texts_embedded: std::sync::atomic::AtomicUsize,
+    }
+
+    impl EmbeddingProvider for CatEmbedder {
+        fn model_name(&self) -> &str {
+            "cat-embedder"
+        }
+
+        fn dimensions(&self) -> usize {
+            2
+        }
+
+        fn embed(&self, texts: &[String]) -> anyhow::Result<Vec<Vec<f32>>> {
+            self.texts_embedded.fetch_add(texts.len(), std::sync::atomic::Ordering::SeqCst);
+            Ok(texts
+                .iter()
+                .map(|t| {
+                    let t = t.to_lowercase();
+                    if t.contains("cat") || t.contains("feline") { vec![1.0, 0.1] } else { vec![0.1, 1.0] }
+                })
+                .collect())
+        }
+    }
+
+    #[test]
+    fn test_similar_questions_reuse_cached_answer() {
+        let test_file = "/tmp/test_rag_answer_cache.txt";
+        fs::write(test_file, "Felines sleep most of the day.").unwrap();
+
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_completion_provider(Arc::new(ContextEchoLlm))
+            .with_embedding_provider(Arc::new(CatEmbedder::default()))
+            .with_answer_cache(AnswerCacheConfig::default());
+        rag.process_document(Path::new(test_file)).unwrap();
+
+        let first = rag.ask("When do felines sleep?").unwrap();
+        let second = rag.ask("When do cats sleep?").unwrap();
+        assert_eq!(second.text, first.text);
+        assert_eq!(rag.cached_answers().len(), 1);
+        assert_eq!(rag.get_stats().unwrap().answer_cache_hits, 1);
+
+        // New documents can change answers, so ingest empties the cache
+        rag.process_document(Path::new(test_file)).unwrap();
+        assert!(rag.cached_answers().is_empty());
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_delete_document_cascades() {
+        let cat_file = "/tmp/test_rag_delete_cat.txt";
+        let dog_file = "/tmp/test_rag_delete_dog.txt";
+        fs::write(cat_file, "Cats purr.").unwrap();
+        fs::write(dog_file, "Dogs bark.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap().with_embedding_provider(CatEmbedder::default());
+        let cat_doc = rag.process_document(Path::new(cat_file)).unwrap();
+        rag.process_document(Path::new(dog_file)).unwrap();
+
+        assert_eq!(rag.delete_document(&cat_doc).unwrap(), 1);
+        assert!(rag.delete_document(&cat_doc).is_err());
+        assert!(rag.search("purr", 5).unwrap().iter().all(|r| r.document_id != cat_doc));
+        assert_eq!(rag.storage.get_all_embeddings().unwrap().len(), 1);
+        assert_eq!(rag.delete_all_documents().unwrap(), (1, 1));
+        assert!(rag.list_documents().unwrap().is_empty());
+        // Everything went to the trash, where it can still be recovered
+        assert_eq!(rag.trashed_documents().len(), 2);
+        rag.undelete_document(&cat_doc).unwrap();
+        assert_eq!(rag.list_documents().unwrap(), vec![cat_doc.clone()]);
+
+        fs::remove_file(cat_file).unwrap();
+        fs::remove_file(dog_file).unwrap();
+    }
+
+    #[test]
+    fn test_reindex_rebuilds_chunks_and_embeddings() {
+        let test_file = "/tmp/test_rag_reindex.txt";
+        fs::write(test_file, "Cats purr loudly.\n\nDogs bark at night.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+        fs::remove_file(test_file).unwrap();
+
+        // The source file is gone; reindexing works from the stored content
+        let mut rag = rag
+            .with_chunking_strategy(ChunkingStrategy::Paragraph)
+            .with_embedding_provider(CatEmbedder::default());
+        let report = rag.reindex().unwrap();
+
+        assert_eq!(report.documents, 1);
+        assert_eq!((report.chunks_before, report.chunks_after), (1, 2));
+        assert_eq!((report.embeddings_before, report.embeddings_after), (0, 2));
+        assert_eq!(report.embedding_model_after.as_deref(), Some("cat-embedder"));
+        assert_eq!(rag.document_chunks(&doc_id).unwrap()[1].content.as_ref(), "Dogs bark at night.");
+    }
+
+    #[test]
+    fn test_benchmark_leaves_index_unchanged() {
+        let test_file = "/tmp/test_rag_bench.txt";
+        fs::write(test_file, "Cats purr loudly.").unwrap();
+        let embedder = Arc::new(CatEmbedder::default());
+        let mut rag = SimpleRagSystem::new().unwrap().with_embedding_provider(embedder.clone());
+        rag.process_document(Path::new(test_file)).unwrap();
+        let trashed = rag.process_bytes("dogs.txt", b"Dogs bark.").unwrap();
+        rag.delete_document(&trashed).unwrap();
+        let before = rag.get_stats().unwrap();
+        let embedded = embedder.texts_embedded.load(std::sync::atomic::Ordering::SeqCst);
+
+        let report = rag.benchmark(&["purr".to_string()], 3, 5).unwrap();
+
+        assert_eq!(report.ingest.chunks, 1);
+        assert_eq!(report.query.samples, 3);
+        // The rebuild embeds cold, even though the chunk is in the cache
+        assert_eq!(embedder.texts_embedded.load(std::sync::atomic::Ordering::SeqCst), embedded + 1);
+        let after = rag.get_stats().unwrap();
+        assert_eq!(after.total_chunks, before.total_chunks);
+        assert_eq!(after.embedding_cache_entries, before.embedding_cache_entries);
+        // The trash, which the timed runs don't see, is kept too
+        rag.undelete_document(&trashed).unwrap();
+        assert_eq!(rag.list_documents().unwrap().len(), 2);
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_ingest_stores_nothing() {
+        let root = Path::new("/tmp/test_rag_dry_run");
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root).unwrap();
+        fs::write(root.join("notes.md"), "Cats purr.\n\nDogs bark.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+        let options = IngestOptions { dry_run: true, ..Default::default() };
+        let report = rag.ingest_path(root, &options).unwrap();
+
+        assert_eq!((report.would_ingest(), report.total_chunks()), (1, 2));
+        let IngestOutcome::WouldIngest(preview) = &report.files[0].outcome else {
+            panic!("expected a dry-run outcome");
+        };
+        assert_eq!((preview.file_type.as_str(), preview.chunk_words.clone()), ("md", vec![2, 2]));
+        assert!(rag.list_documents().unwrap().is_empty());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_pipelined_ingest_embeds_and_stores_every_file() {
+        let root = Path::new("/tmp/test_rag_pipeline");
+        let _ = fs::remove_dir_all(root);
+        fs::create_dir_all(root).unwrap();
+        for i in 0..12 {
+            fs::write(root.join(format!("cat{}.txt", i)), format!("Cat number {} purrs.\n\nIt naps.", i)).unwrap();
+        }
+        fs::write(root.join("broken.txt"), [0xff, 0xfe]).unwrap();
+
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_chunking_strategy(ChunkingStrategy::Paragraph)
+            .with_embedding_provider(CatEmbedder::default());
+        let report = rag.ingest_path(root, &IngestOptions { workers: 3, ..Default::default() }).unwrap();
+
+        assert_eq!((report.ingested(), report.failed(), report.total_chunks()), (12, 1, 24));
+        assert_eq!(report.files[0].path, root.join("broken.txt"));
+        let stats = rag.get_stats().unwrap();
+        assert_eq!((stats.total_documents, stats.total_chunks), (12, 24));
+        // Clean includes every chunk having an embedding
+        assert!(rag.check_integrity().is_clean());
+
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn test_batch_search_keeps_going_past_failures() {
+        let test_file = "/tmp/test_rag_batch.txt";
+        fs::write(test_file, "Cats purr loudly.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        rag.process_document(Path::new(test_file)).unwrap();
+        let queries = vec!["purr".to_string(), "cats".to_string()];
+
+        let batch = rag.batch_search(&queries, 3);
+        assert_eq!(batch.len(), 2);
+        assert!(batch.iter().all(|r| r.error.is_none() && r.results.len() == 1));
+
+        // Vector search without an embedder fails every query, but still reports each one
+        rag.set_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() }).unwrap();
+        let batch = rag.batch_search(&queries, 3);
+        assert!(batch.iter().all(|r| r.results.is_empty() && r.error.as_deref().unwrap().contains("embedding")));
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_tags_restrict_search() {
+        let alpha_file = "/tmp/test_rag_tags_alpha.txt";
+        let beta_file = "/tmp/test_rag_tags_beta.txt";
+        fs::write(alpha_file, "Release checklist for the launch.").unwrap();
+        fs::write(beta_file, "Launch retrospective notes.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let alpha = rag.process_document(Path::new(alpha_file)).unwrap();
+        let beta = rag.process_document(Path::new(beta_file)).unwrap();
+
+        assert_eq!(rag.set_tag(&alpha, "project", "alpha").unwrap(), None);
+        rag.set_tag(&beta, "project", "beta").unwrap();
+        let filter = DocumentFilter::default().with_tag("project", "alpha");
+        let results = rag.search_filtered("launch", 5, &filter).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, alpha);
+
+        assert_eq!(rag.remove_tag(&alpha, "project").unwrap().as_deref(), Some("alpha"));
+        assert!(rag.search_filtered("launch", 5, &filter).unwrap().is_empty());
+        assert!(rag.set_tag("missing", "project", "alpha").is_err());
+
+        fs::remove_file(alpha_file).unwrap();
+        fs::remove_file(beta_file).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_document_is_recoverable_until_purged() {
+        let notes_file = "/tmp/test_rag_trash_notes.txt";
+        let index_file = "/tmp/test_rag_trash_index.json";
+        fs::write(notes_file, "Quarterly roadmap for the parser rewrite.").unwrap();
+        let _ = fs::remove_file(index_file);
+        let mut rag = SimpleRagSystem::open(Path::new(index_file)).unwrap();
+        let notes = rag.process_document(Path::new(notes_file)).unwrap();
+
+        assert_eq!(rag.delete_document(&notes).unwrap(), 1);
+        assert!(rag.search("roadmap", 5).unwrap().is_empty());
+        assert!(rag.list_documents().unwrap().is_empty());
+        rag.persist().unwrap();
+
+        let mut rag = SimpleRagSystem::open(Path::new(index_file)).unwrap();
+        let trashed = rag.trashed_documents();
+        assert_eq!((trashed.len(), trashed[0].id.as_str(), trashed[0].chunks), (1, notes.as_str(), 1));
+        rag.undelete_document(&notes).unwrap();
+        assert_eq!(rag.search("roadmap", 5).unwrap()[0].document_id, notes);
+        assert!(rag.undelete_document(&notes).is_err());
+
+        rag.delete_document(&notes).unwrap();
+        assert!(rag.purge(Duration::from_secs(3600)).unwrap().is_empty());
+        assert_eq!(rag.purge(Duration::ZERO).unwrap().len(), 1);
+        assert!(rag.trashed_documents().is_empty());
+        assert!(rag.undelete_document(&notes).is_err());
+        assert!(rag.storage.full_snapshot().unwrap().chunks.is_empty());
+        assert!(rag.check_integrity().is_clean());
+
+        fs::remove_file(notes_file).unwrap();
+        let _ = fs::remove_file(index_file);
+    }
+
+    #[test]
+    fn test_failed_update_keeps_the_old_version() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let old = rag.process_bytes("agenda.txt", b"Draft agenda for the offsite.").unwrap();
+        rag.set_tag(&old, "team", "events").unwrap();
+
+        assert!(rag.update_document(&old, Path::new("/tmp/test_rag_update_missing.txt")).is_err());
+        assert_eq!(rag.list_documents().unwrap(), vec![old.clone()]);
+        assert_eq!(rag.tags(&old).unwrap().get("team").map(String::as_str), Some("events"));
+        assert_eq!(rag.search("agenda", 5).unwrap()[0].document_id, old);
+    }
+
+    #[test]
+    fn test_audit_log_records_operations_and_persists() {
+        let notes_file = "/tmp/test_rag_audit_notes.txt";
+        let index_file = "/tmp/test_rag_audit_index.json";
+        fs::write(notes_file, "Draft agenda for the offsite.").unwrap();
+        let _ = fs::remove_file(index_file);
+        let mut rag = SimpleRagSystem::open(Path::new(index_file)).unwrap().with_actor("alice");
+        let first = rag.process_document(Path::new(notes_file)).unwrap();
+        fs::write(notes_file, "Final agenda for the offsite.").unwrap();
+        let second = rag.update_document(&first, Path::new(notes_file)).unwrap();
+        rag.as_actor("ci", |rag| rag.delete_document(&second)).unwrap();
+        assert!(rag.delete_document("missing").is_err());
+        rag.persist().unwrap();
+
+        let rag = SimpleRagSystem::open(Path::new(index_file)).unwrap();
+        let log = rag.audit_log(&AuditFilter::default());
+        let actions: Vec<_> = log.iter().map(|entry| (entry.action, entry.actor.as_str(), entry.succeeded())).collect();
+        assert_eq!(
+            actions,
+            vec![
+                (AuditAction::Ingest, "alice", true),
+                (AuditAction::Update, "alice", true),
+                (AuditAction::Delete, "ci", true),
+                (AuditAction::Delete, "alice", false),
+            ]
+        );
+        assert_eq!(log[1].source.as_deref(), Some(notes_file));
+        let by_ci = rag.audit_log(&AuditFilter { actor: Some("ci".to_string()), ..Default::default() });
+        assert_eq!(by_ci[0].document_id.as_deref(), Some(second.as_str()));
+
+        fs::remove_file(notes_file).unwrap();
+        let _ = fs::remove_file(index_file);
+    }
+
+    #[test]
+    fn test_pinned_document_leads_results() {
+        let mirror_file = "/tmp/test_rag_boost_mirror.txt";
+        let official_file = "/tmp/test_rag_boost_official.txt";
+        fs::write(mirror_file, "Configure the proxy: set the proxy host, then the proxy port.").unwrap();
+        fs::write(official_file, "Proxy settings live in the config file.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let mirror = rag.process_document(Path::new(mirror_file)).unwrap();
+        let official = rag.process_document(Path::new(official_file)).unwrap();
+        assert_eq!(rag.search("proxy", 2).unwrap()[0].document_id, mirror);
+
+        rag.set_tag(&official, PINNED_TAG, "true").unwrap();
+        let results = rag.search("proxy", 2).unwrap();
+        assert_eq!((results[0].document_id.as_str(), results[0].pinned, results[1].pinned), (official.as_str(), true, false));
+        assert!(rag.set_tag(&mirror, BOOST_TAG, "-1").is_err());
+        assert!(rag.tags(&mirror).unwrap().is_empty());
+
+        fs::remove_file(mirror_file).unwrap();
+        fs::remove_file(official_file).unwrap();
+    }
+
+    #[test]
+    fn test_pinned_document_must_match_the_query() {
+        // Vector search scores every chunk above 0, however unrelated
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_embedding_provider(CatEmbedder::default())
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+            .unwrap();
+        let cats = rag.process_bytes("cats.md", b"Cats sleep most of the day.").unwrap();
+        let bread = rag.process_bytes("bread.md", b"Sourdough needs a starter.").unwrap();
+        rag.set_tag(&bread, PINNED_TAG, "true").unwrap();
+        let results = rag.search("cats", 2).unwrap();
+        assert!(results[1].score > 0.0);
+        assert_eq!((results[0].document_id.as_str(), results[1].pinned), (cats.as_str(), false));
+
+        let mut session = SearchSession::new();
+        rag.search_in_session(&mut session, &Query::parse("cats").unwrap(), 2).unwrap();
+        assert_eq!(rag.search_in_session(&mut session, &Query::parse("cats").unwrap(), 2).unwrap()[0].document_id, cats);
+    }
+
+    #[test]
+    fn test_facets_and_sorts_take_only_chunks_that_match() {
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_embedding_provider(CatEmbedder::default())
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+            .unwrap();
+        let cats = rag.process_bytes("cats.md", b"Cats sleep most of the day.").unwrap();
+        rag.process_bytes("bread.txt", b"Sourdough needs a starter.").unwrap();
+        let cats_query = Query::parse("cats").unwrap();
+        assert_eq!(rag.search_query(&cats_query, 5).unwrap().len(), 2);
+
+        let faceted = rag.search_faceted(&cats_query, 5).unwrap();
+        assert_eq!(faceted.facets.documents, 1);
+        assert_eq!(faceted.facets.file_type.keys().collect::<Vec<_>>(), ["md"]);
+        assert!(faceted.results.iter().map(|r| r.document_id.as_str()).eq([cats.as_str()]));
+        let by_path = rag.search_sorted(&cats_query, 5, "path".parse().unwrap()).unwrap();
+        assert!(by_path.iter().map(|r| r.document_id.as_str()).eq([cats.as_str()]));
+
+        // A session reranks the same candidates the facets were counted from
+        let mut session = SearchSession::new();
+        session.reject(&cats);
+        let in_session = rag.search_faceted_with(&cats_query, 5, ResultSort::default(), Some(&session)).unwrap();
+        assert_eq!(in_session.facets, faceted.facets);
+        assert_eq!(in_session.results.len(), 1);
+        assert!(in_session.results[0].score < faceted.results[0].score);
+    }
+
+    #[test]
+    fn test_delete_collection_keeps_other_documents() {
+        let notes_file = "/tmp/test_rag_collection_notes.txt";
+        let specs_file = "/tmp/test_rag_collection_specs.txt";
+        fs::write(notes_file, "Meeting notes about the parser.").unwrap();
+        fs::write(specs_file, "Parser specification draft.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let notes = rag.process_document(Path::new(notes_file)).unwrap();
+        let specs = rag.process_document(Path::new(specs_file)).unwrap();
+        rag.set_tag(&notes, COLLECTION_TAG, "notes").unwrap();
+
+        assert_eq!(rag.delete_collection("specs").unwrap(), (0, 0));
+        assert_eq!(rag.delete_collection("notes").unwrap(), (1, 1));
+        assert_eq!(rag.list_documents().unwrap(), vec![specs]);
+
+        fs::remove_file(notes_file).unwrap();
+        fs::remove_file(specs_file).unwrap();
+    }
+
+    #[test]
+    fn test_info_reports_stale_and_missing_sources() {
+        let kept_file = "/tmp/test_rag_info_kept.txt";
+        let edited_file = "/tmp/test_rag_info_edited.txt";
+        fs::write(kept_file, "Unchanged notes.").unwrap();
+        fs::write(edited_file, "First draft.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+        rag.process_document(Path::new(kept_file)).unwrap();
+        rag.process_document(Path::new(edited_file)).unwrap();
+
+        let info = rag.info().unwrap();
+        assert_eq!(info.backend, StorageBackend::Memory);
+        assert!(matches!(info.chunking, ChunkingStrategy::Paragraph));
+        assert_eq!((info.documents, info.embedding_dimensions), (2, None));
+        assert!(info.freshness.is_current());
+
+        fs::write(edited_file, "Second draft.").unwrap();
+        fs::remove_file(kept_file).unwrap();
+        let freshness = rag.info().unwrap().freshness;
+        assert_eq!((freshness.stale_documents, freshness.missing_sources), (1, 1));
+
+        fs::remove_file(edited_file).unwrap();
+    }
+
+    /// Collects formatted log output so tests can look at spans
+    #[derive(Clone, Default)]
+    struct LogBuffer(Arc<std::sync::Mutex<Vec<u8>>>);
+
+    impl std::io::Write for LogBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_pipeline_spans_carry_structured_fields() {
+        let test_file = "/tmp/test_rag_spans.txt";
+        fs::write(test_file, "Spans wrap the pipeline.\n\nEach stage records its fields.").unwrap();
+        let logs = LogBuffer::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+            let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+            rag.search("pipeline", 1).unwrap();
+            let output = String::from_utf8(logs.0.lock().unwrap().clone()).unwrap();
+            assert!(output.contains(&format!("process_document{{path={} chunks=2 doc_id={}", test_file, doc_id)));
+            assert!(output.contains(&format!("chunk_document{{doc_id={} strategy=Paragraph chunks=2", doc_id)));
+            assert!(output.contains(&format!("store_chunks{{doc_id={} chunks=2", doc_id)));
+            assert!(output.contains("search{query=\"pipeline\" limit=1 mode=Keyword chunks=2 results=1"));
+        });
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_metrics_count_ingest_search_and_cache_lookups() {
+        let test_file = "/tmp/test_rag_metrics.txt";
+        fs::write(test_file, "Cats nap in the sun.").unwrap();
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_embedding_provider(Arc::new(CatEmbedder::default()))
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..Default::default() })
+            .unwrap();
+        rag.process_document(Path::new(test_file)).unwrap();
+        rag.search("cats", 1).unwrap();
+        rag.search("cats", 1).unwrap();
+
+        let rendered = rag.metrics().render();
+        assert!(rendered.contains("rag_documents_ingested_total 1\n"));
+        assert!(rendered.contains("rag_chunks_stored_total 1\n"));
+        assert!(rendered.contains("rag_embedding_cache_hits_total 1\n"));
+        assert!(rendered.contains("rag_embedding_cache_misses_total 2\n"));
+        assert_eq!(rag.metrics().search_latency().count, 2);
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_tenants_sharing_storage_only_search_their_own_documents() {
+        let shared = StorageManager::new().unwrap();
+        let mut acme = SimpleRagSystem::builder().storage(shared.tenant("acme")).build().unwrap();
+        let mut globex = SimpleRagSystem::builder().storage(shared.tenant("globex")).build().unwrap();
+        acme.process_bytes("acme.txt", b"Acme rockets reach the moon.").unwrap();
+        globex.process_bytes("globex.txt", b"Globex builds bases on the moon.").unwrap();
+
+        let hits = acme.search("moon", 5).unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].content.starts_with("Acme"));
+        assert_eq!(globex.get_stats().unwrap().total_documents, 1);
+        assert_eq!(acme.tenant(), Some("acme"));
+
+        let refused = acme.benchmark(&["moon".to_string()], 1, 5).unwrap_err();
+        assert!(refused.to_string().contains("tenant acme"));
+        assert_eq!(acme.search("moon", 5).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_query_log_records_searches_once_enabled() {
+        let index_file = "/tmp/test_rag_query_log.json";
+        let log_file = storage::query_log_path(Path::new(index_file));
+        let _ = fs::remove_file(index_file);
+        let _ = fs::remove_file(&log_file);
+        let mut rag = SimpleRagSystem::open(Path::new(index_file)).unwrap();
+        let doc_id = rag.process_bytes("leave.md", b"Staff get twenty days of paid leave.").unwrap();
+        rag.search("leave", 3).unwrap();
+        assert!(rag.query_log().unwrap().is_empty());
+
+        let mut rag = rag.with_query_log(QueryLogConfig { enabled: true, max_entries: 2 });
+        rag.set_tag(&doc_id, "team", "hr").unwrap();
+        let filter = DocumentFilter::parse(&["tag:team=hr".to_string()]).unwrap();
+        rag.search("bonus", 3).unwrap();
+        rag.search("leave", 3).unwrap();
+        rag.search_filtered("paid leave", 3, &filter).unwrap();
+
+        // Logged to its own file as searches run, without persisting the index
+        let config = QueryLogConfig { enabled: true, max_entries: 2 };
+        let reopen = || SimpleRagSystem::open(Path::new(index_file)).unwrap().with_query_log(config.clone());
+        let log = reopen().query_log().unwrap();
+        assert_eq!(log.iter().map(|e| e.query.as_str()).collect::<Vec<_>>(), ["leave", "paid leave"]);
+        assert_eq!(log[1].filter, filter);
+        assert_eq!(log[1].result_ids, [format!("{}_0", doc_id)]);
+
+        for _ in 0..5 {
+            rag.search("leave", 3).unwrap();
+        }
+        assert!(fs::read_to_string(&log_file).unwrap().lines().count() <= 4, "compacted as it grows");
+        rag.persist().unwrap();
+        assert!(StorageManager::read_snapshot(Path::new(index_file)).unwrap().query_log.is_empty());
+        assert_eq!(reopen().query_log().unwrap().len(), 2);
+
+        // Parsed queries are logged as typed, not as they print back
+        rag.search_query(&TypedQuery::parse("leave -bonus words:>=3").unwrap(), 3).unwrap();
+        rag.search_query(&Query::parse("leave -bonus").unwrap(), 3).unwrap();
+        let logged: Vec<String> = rag.query_log().unwrap().into_iter().map(|e| e.query).collect();
+        assert_eq!(logged, ["leave -bonus words:>=3", "leave NOT bonus"]);
+        assert_eq!(rag.clear_query_log().unwrap(), 2);
+        assert!(reopen().query_log().unwrap().is_empty());
+
+        fs::remove_file(index_file).unwrap();
+        fs::remove_file(log_file).unwrap();
+    }
+
+    #[test]
+    fn test_process_bytes_indexes_without_touching_disk() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let doc_id = rag.process_bytes("upload/rust.txt", b"Rust guarantees memory safety without a GC.").unwrap();
+
+        let results = rag.search("memory safety", 1).unwrap();
+        assert_eq!(results[0].document_id, doc_id);
+        assert_eq!(rag.get_document(&doc_id).unwrap().unwrap().metadata.file_path, "upload/rust.txt");
+    }
+
+    #[tokio::test]
+    async fn test_async_pipeline_matches_blocking_one() {
+        let test_file = "/tmp/test_rag_async.txt";
+        fs::write(test_file, "Felines sleep most of the day.").unwrap();
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_completion_provider(Arc::new(ContextEchoLlm))
+            .with_embedding_provider(Arc::new(CatEmbedder::default()))
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..Default::default() })
+            .unwrap();
+
+        let doc_id = rag.process_document_async(Path::new(test_file)).await.unwrap();
+        let results = rag.search_async("cats", 1).await.unwrap();
+        assert_eq!(results[0].document_id, doc_id);
+        assert_eq!(results[0].score, rag.search("cats", 1).unwrap()[0].score);
+
+        let answer = rag.ask_async("Do cats sleep?").await.unwrap();
+        assert_eq!(answer.text, "[1] Felines sleep most of the day.");
+        assert_eq!((answer.citations.len(), answer.usage.completion_calls), (1, 1));
+
+        // Servers hand these futures to tokio::spawn
+        fn assert_send<T: Send>(_: &T) {}
+        assert_send(&rag.ask_async("Do cats sleep?"));
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_export_and_import_ragpack() {
+        let test_file = "/tmp/test_rag_export.txt";
+        let pack_file = "/tmp/test_rag_export.ragpack";
+        fs::write(test_file, "Cargo builds Rust packages.").unwrap();
+
+        let mut source = SimpleRagSystem::new().unwrap();
+        let doc_id = source.process_document(Path::new(test_file)).unwrap();
+        source.export(Path::new(pack_file)).unwrap();
+
+        let mut target = SimpleRagSystem::new().unwrap().with_actor("restorer");
+        target.process_bytes("old.md", b"Replaced by the import.").unwrap();
+        assert_eq!(target.import(Path::new(pack_file)).unwrap(), 1);
+        assert_eq!(target.search("cargo", 1).unwrap()[0].document_id, doc_id);
+        // The target's own history survives, and the pack's joins it, with the import last
+        let log = target.audit_log(&AuditFilter::default());
+        let actions: Vec<_> = log.iter().map(|entry| (entry.action, entry.actor.as_str())).collect();
+        assert_eq!(actions.len(), 3);
+        assert!(actions.contains(&(AuditAction::Ingest, "restorer")));
+        assert_eq!(actions[2], (AuditAction::Import, "restorer"));
+        assert_eq!(log[2].source.as_deref(), Some(pack_file));
+        assert!(target.import(Path::new("/tmp/test_rag_missing.ragpack")).is_err());
+        assert!(!target.audit_log(&AuditFilter::default())[3].succeeded());
+
+        fs::remove_file(test_file).unwrap();
+        fs::remove_file(pack_file).unwrap();
+    }
+
+    #[test]
+    fn test_ingest_reports_progress() {
+        let test_file = "/tmp/test_rag_progress.txt";
+        fs::write(test_file, "Progress is reported per stage.").unwrap();
+
+        let events = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_embedding_provider(CatEmbedder::default())
+            .with_ingest_progress(Arc::new(move |progress: &IngestProgress| recorded.lock().unwrap().push(progress.clone())));
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                IngestProgress::Chunked { file_path: test_file.to_string(), chunks: 1 },
+                IngestProgress::Embedded { done: 1, total: 1 },
+                IngestProgress::Stored { document_id: doc_id, chunks: 1 },
+            ]
+        );
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_vector_search_uses_ingest_embeddings() {
+        let cat_file = "/tmp/test_rag_vector_cat.txt";
+        let dog_file = "/tmp/test_rag_vector_dog.txt";
+        fs::write(cat_file, "Felines sleep most of the day.").unwrap();
+        fs::write(dog_file, "Dogs enjoy long walks.").unwrap();
+
+        let vector_config = SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() };
+        let mut rag = SimpleRagSystem::new().unwrap().with_search_config(vector_config).unwrap();
+        assert!(rag.search("cat", 1).is_err());
+
+        rag = rag.with_embedding_provider(Arc::new(CatEmbedder::default()));
+        let cat_doc = rag.process_document(Path::new(cat_file)).unwrap();
+        rag.process_document(Path::new(dog_file)).unwrap();
+
+        // No term overlap with "cat", so only the embedding can find it
+        let results = rag.search("cat", 1).unwrap();
+        assert_eq!(results[0].document_id, cat_doc);
+
+        fs::remove_file(cat_file).unwrap();
+        fs::remove_file(dog_file).unwrap();
+    }
+
+    #[test]
+    fn test_embedding_cache_skips_repeat_work() {
+        let test_file = "/tmp/test_rag_embedding_cache.txt";
+        fs::write(test_file, "Cats have retractable claws.").unwrap();
+
+        let embedder = Arc::new(CatEmbedder::default());
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+            .unwrap()
+            .with_embedding_provider(embedder.clone());
+
+        rag.process_document(Path::new(test_file)).unwrap();
+        rag.process_document(Path::new(test_file)).unwrap();
+        rag.search("claws", 1).unwrap();
+        rag.search("claws", 1).unwrap();
+
+        // One chunk and one query, each embedded once
+        assert_eq!(embedder.texts_embedded.load(std::sync::atomic::Ordering::SeqCst), 2);
+        let stats = rag.get_stats().unwrap();
+        assert_eq!((stats.embedding_cache_hits, stats.embedding_cache_misses), (2, 2));
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    #[test]
+    fn test_warm_up_primes_logged_queries() {
+        let embedder = Arc::new(CatEmbedder::default());
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+            .unwrap()
+            .with_embedding_provider(embedder.clone())
+            .with_query_log(QueryLogConfig { enabled: true, max_entries: 10 });
+        rag.process_bytes("cats.txt", b"Cats have retractable claws.").unwrap();
+
+        // Nothing logged yet, so only the probe reaches the provider
+        let report = rag.warm_up().unwrap();
+        assert_eq!((report.documents, report.chunks, report.embeddings, report.queries_primed), (1, 1, 1, 0));
+        assert_eq!(embedder.texts_embedded.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+        rag.search("claws", 1).unwrap();
+        rag.search("claws", 1).unwrap();
+        assert_eq!(rag.warm_up().unwrap().queries_primed, 1);
+        assert_eq!(embedder.texts_embedded.load(std::sync::atomic::Ordering::SeqCst), 3);
+    }
+
+    /// Answers every question as if it were about cats
+    struct CatLoverLlm;
+
+    impl CompletionProvider for CatLoverLlm {
+        fn model_name(&self) -> &str {
+            "cat-lover"
+        }
+
+        fn complete(&self, _request: &CompletionRequest) -> anyhow::Result<String> {
+            Ok("A cat uses its whiskers to sense its surroundings.".to_string())
+        }
+    }
+
+    #[test]
+    fn test_hyde_embeds_hypothetical_answer() {
+        let cat_file = "/tmp/test_rag_hyde_cat.txt";
+        let dog_file = "/tmp/test_rag_hyde_dog.txt";
+        fs::write(cat_file, "Felines have sensitive vibrissae.").unwrap();
+        fs::write(dog_file, "Dogs enjoy long walks.").unwrap();
+
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+            .unwrap()
+            .with_embedding_provider(Arc::new(CatEmbedder::default()))
+            .with_hyde(true);
+        let cat_doc = rag.process_document(Path::new(cat_file)).unwrap();
+        rag.process_document(Path::new(dog_file)).unwrap();
+        assert!(rag.search("what are whiskers for?", 1).is_err());
+
+        let rag = rag.with_completion_provider(Arc::new(CatLoverLlm));
+        let results = rag.search("what are whiskers for?", 1).unwrap();
+        assert_eq!(results[0].document_id, cat_doc);
+
+        fs::remove_file(cat_file).unwrap();
+        fs::remove_file(dog_file).unwrap();
+    }
+
+    #[test]
+    fn test_ask_refuses_without_confident_context() {
+        let test_file = "/tmp/test_rag_refusal.txt";
+        fs::write(test_file, "Ferris is the unofficial mascot of the Rust programming language.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap().with_completion_provider(Arc::new(ContextEchoLlm));
+        rag.process_document(Path::new(test_file)).unwrap();
+
+        let answer = rag.ask("quantum chromodynamics").unwrap();
+        assert_eq!(answer.insufficient_context, Some(InsufficientContext { best_score: None, min_score: 0.0 }));
+
+        let strict = rag.with_min_retrieval_score(0.99);
+        let answer = strict.ask("Who is Ferris?").unwrap();
+        assert!(answer.is_refusal());
+        assert!(answer.insufficient_context.unwrap().best_score.unwrap() > 0.0);
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    /// Summarizes every document as "About <first word>."
+    struct FirstWordSummarizer;
+
+    impl CompletionProvider for FirstWordSummarizer {
+        fn model_name(&self) -> &str {
+            "first-word"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> anyhow::Result<String> {
+            let first_word = request.prompt.lines().nth(1).unwrap_or_default().split_whitespace().next().unwrap_or_default();
+            Ok(format!("About {}.", first_word))
+        }
+    }
+
+    #[test]
+    fn test_summaries_are_stored_and_searchable() {
+        let test_file = "/tmp/test_rag_summary.txt";
+        fs::write(test_file, "Lighthouses guide ships along dangerous coasts at night.").unwrap();
+
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_completion_provider(Arc::new(FirstWordSummarizer))
+            .with_summaries(true);
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+
+        let document = rag.get_document(&doc_id).unwrap().unwrap();
+        assert_eq!(document.metadata.summary.as_deref(), Some("About Lighthouses."));
+        assert_eq!(rag.get_stats().unwrap().total_chunks, 2);
+
+        let results = rag.search_documents("lighthouses", 5).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].chunk_id, format!("{}{}", doc_id, SUMMARY_CHUNK_SUFFIX));
+
+        fs::remove_file(test_file).unwrap();
+    }
+
+    /// Knows where Turing worked and where that is
+    struct TuringRelations;
+
+    impl CompletionProvider for TuringRelations {
+        fn model_name(&self) -> &str {
+            "turing-relations"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> anyhow::Result<String> {
+            if request.prompt.contains("Turing") {
+                Ok("Alan Turing | worked at | Bletchley Park".to_string())
+            } else if request.prompt.contains("lies in") {
+                Ok("Bletchley Park | located in | Buckinghamshire".to_string())
+            } else {
+                Ok("none".to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_graph_search_follows_relations_to_other_documents() {
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Bm25, ..SearchConfig::default() })
+            .unwrap()
+            .with_completion_provider(Arc::new(TuringRelations))
+            .with_knowledge_graph(true);
+        let turing = rag.process_bytes("turing.md", b"Alan Turing worked at Bletchley Park.").unwrap();
+        let park = rag.process_bytes("park.md", b"Bletchley Park lies in Buckinghamshire.").unwrap();
+        let county = rag.process_bytes("county.md", b"Buckinghamshire has chalk hills.").unwrap();
+        rag.process_bytes("bread.md", b"Sourdough needs a starter.").unwrap();
+
+        assert_eq!(rag.get_document(&turing).unwrap().unwrap().metadata.relations.len(), 1);
+        assert_eq!(rag.knowledge_graph().unwrap().entity_count(), 3);
+
+        let plain = rag.search("alan turing", 5).unwrap();
+        assert_eq!(plain.iter().filter(|r| r.score > 0.0).count(), 1);
+        // The park shares an entity with the result; the county is one edge further
+        let expanded = rag.search_graph("alan turing", 5, 0).unwrap();
+        let found = |results: &[SearchResult]| -> Vec<String> {
+            results.iter().filter(|r| r.score > 0.0).map(|r| r.document_id.clone()).collect()
+        };
+        assert_eq!(found(&expanded), [turing.clone(), park.clone()]);
+        let expanded = rag.search_graph("alan turing", 5, 1).unwrap();
+        assert_eq!(found(&expanded), [turing.clone(), park.clone(), county]);
+
+        // Links are persisted with the index, and linking from scratch gives the same ones
+        let snapshot = rag.storage.full_snapshot().unwrap();
+        let relinked = StorageManager::from_snapshot(StorageSnapshot { entity_links: Default::default(), ..snapshot.clone() });
+        assert_eq!(*relinked.unwrap().entity_links(), snapshot.entity_links);
+        // Deleting the park takes its relation, and the county it led to, out of the graph
+        rag.purge_document(&park).unwrap();
+        assert_eq!(rag.knowledge_graph().unwrap().entity_count(), 2);
+        assert_eq!(found(&rag.search_graph("alan turing", 5, 1).unwrap()), [turing]);
+    }
+
+    #[test]
+    fn test_entity_filter_selects_chunks_naming_the_entity() {
+        let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+        let doc_id = rag
+            .process_bytes(
+                "history.md",
+                b"Alan Turing designed the bombe.\n\nThe bombe was built by the British Tabulating Machine Company.",
+            )
+            .unwrap();
+        let first = rag.document_chunks(&doc_id).unwrap().into_iter().find(|c| c.start_pos == 0).unwrap();
+        assert_eq!(first.entities[0].text, "Alan Turing");
+
+        let filter = DocumentFilter::parse(&["entity:Person=Turing".to_string()]).unwrap();
+        let results = rag.search_filtered("bombe", 5, &filter).unwrap();
+        assert_eq!(results.iter().map(|r| r.chunk_id.as_str()).collect::<Vec<_>>(), [format!("{}_0", doc_id)]);
+        let filter = DocumentFilter::parse(&["entity:org=Tabulating".to_string()]).unwrap();
+        assert_eq!(rag.search_filtered("bombe", 5, &filter).unwrap()[0].chunk_id, format!("{}_1", doc_id));
+    }
+
+    #[test]
+    fn test_related_documents_share_terms() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let ownership = rag.process_bytes("ownership.md", b"Rust ownership moves values between owners.").unwrap();
+        let borrowing = rag.process_bytes("borrowing.md", b"Borrowing lends values without moving ownership.").unwrap();
+        rag.process_bytes("bread.md", b"Sourdough bread needs a starter.").unwrap();
+
+        let related = rag.related_documents(&ownership, 5).unwrap();
+        assert_eq!(related.len(), 1);
+        assert_eq!((related[0].document_id.as_str(), related[0].method), (borrowing.as_str(), Similarity::TermOverlap));
+        assert_eq!(related[0].file_path, "borrowing.md");
+        assert!(rag.related_documents("missing", 5).is_err());
+    }
+
+    /// Translates German cat words into English
+    struct GermanToEnglish;
+
+    impl CompletionProvider for GermanToEnglish {
+        fn model_name(&self) -> &str {
+            "german-to-english"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> anyhow::Result<String> {
+            assert!(request.preamble.as_deref().unwrap_or_default().contains("English"));
+            let query = request.prompt.lines().next().unwrap_or_default().trim_start_matches("Query: ");
+            Ok(query.replace("Katzen", "cats").replace("Krallen", "claws"))
+        }
+    }
+
+    #[test]
+    fn test_translated_queries_match_documents_in_another_language() {
+        let config = SearchConfig {
+            mode: SearchMode::Bm25,
+            cross_lingual: CrossLingual::Translate,
+            document_language: Some("English".to_string()),
+            ..SearchConfig::default()
+        };
+        let mut rag = SimpleRagSystem::new().unwrap().with_search_config(config.clone()).unwrap();
+        rag.process_bytes("cats.md", b"Cats have retractable claws.").unwrap();
+        assert!(rag.search("Katzen Krallen", 1).is_err());
+
+        let rag = rag.with_completion_provider(Arc::new(GermanToEnglish));
+        assert!(rag.search("Katzen Krallen", 1).unwrap()[0].score > 0.0);
+
+        let untranslated = SearchConfig { document_language: None, ..config };
+        assert!(SimpleRagSystem::new().unwrap().with_search_config(untranslated).is_err());
+    }
+
+    #[test]
+    fn test_synonyms_expand_queries_until_removed() {
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Bm25, ..SearchConfig::default() })
+            .unwrap()
+            .with_synonyms(SynonymDictionary::parse("k8s => kubernetes").unwrap());
+        rag.process_bytes("cluster.md", b"Kubernetes schedules pods onto nodes.").unwrap();
+        assert!(rag.search("k8s", 1).unwrap()[0].score > 0.0);
+
+        assert!(rag.remove_synonyms("k8s"));
+        assert_eq!(rag.search("k8s", 1).unwrap()[0].score, 0.0);
+        rag.add_synonyms("pods", &["containers"]);
+        assert_eq!(rag.synonyms().len(), 1);
+    }
+
+    #[test]
+    fn test_collection_analyzers_apply_to_their_chunks() {
+        use crate::analysis::{AnalyzerConfig, TokenFilter};
+        let stemming = AnalyzerConfig {
+            filters: vec![TokenFilter::Lowercase, TokenFilter::Synonyms, TokenFilter::Stem],
+        };
+        let config = SearchConfig {
+            mode: SearchMode::Bm25,
+            collection_analyzers: BTreeMap::from([("manuals".to_string(), stemming)]),
+            ..SearchConfig::default()
+        };
+        let mut rag = SimpleRagSystem::new().unwrap().with_search_config(config).unwrap();
+        let manual = rag.process_bytes("manual.md", b"Rebuilding indexes after upgrades.").unwrap();
+        rag.set_tag(&manual, COLLECTION_TAG, "manuals").unwrap();
+        let notes = rag.process_bytes("notes.md", b"Rebuilding indexes after upgrades.").unwrap();
+
+        let results = rag.search("rebuild index", 2).unwrap();
+        assert_eq!(results[0].document_id, manual);
+        assert!(results[0].score > 0.0);
+        assert_eq!((results[1].document_id.as_str(), results[1].score), (notes.as_str(), 0.0));
+    }
+
+    #[test]
+    fn test_feedback_reorders_similar_queries() {
+        let mut rag = SimpleRagSystem::new()
+            .unwrap()
+            .with_search_config(SearchConfig { mode: SearchMode::Bm25, ..SearchConfig::default() })
+            .unwrap();
+        let policy = rag.process_bytes("policy.md", b"Paid leave accrues monthly. Paid leave carries over.").unwrap();
+        let form = rag.process_bytes("form.md", b"Request leave with the HR form.").unwrap();
+        let top = |rag: &SimpleRagSystem, query: &str| rag.search(query, 1).unwrap()[0].document_id.clone();
+        assert_eq!(top(&rag, "paid leave"), policy);
+
+        let policy_chunk = rag.search("paid leave", 1).unwrap()[0].chunk_id.clone();
+        let form_chunk = rag.document_chunks(&form).unwrap()[0].id.clone();
+        rag.record_feedback("paid leave", &policy_chunk, false).unwrap();
+        rag.record_feedback("paid leave", &form_chunk, true).unwrap();
+        assert!(rag.record_feedback("paid leave", "missing_0", true).is_err());
+
+        assert_eq!(top(&rag, "paid leave?"), form);
+        assert_eq!(top(&rag, "monthly accrual"), policy);
+        assert_eq!(rag.feedback().len(), 2);
+        // Kept while the document can still be undeleted
+        rag.delete_document(&form).unwrap();
+        assert_eq!(rag.feedback().len(), 2);
+        rag.purge_document(&form).unwrap();
+        assert_eq!(rag.feedback().len(), 1);
+    }
+
+    #[test]
+    fn test_learned_ranking_follows_trained_feedback() {
+        let config = SearchConfig { mode: SearchMode::Bm25, feedback_weight: 0.0, ..SearchConfig::default() };
+        let mut rag = SimpleRagSystem::new().unwrap().with_search_config(config.clone()).unwrap();
+        let terse_leave = rag.process_bytes("a.md", b"Paid leave.").unwrap();
+        let full_leave = rag
+            .process_bytes("b.md", b"Paid leave accrues at two days per month, and unused days carry over into next year.")
+            .unwrap();
+        let terse_parking = rag.process_bytes("c.md", b"Parking permits.").unwrap();
+        let full_parking = rag
+            .process_bytes("d.md", b"Parking permits are issued at the front desk and shown on the dashboard of each car.")
+            .unwrap();
+        assert!(rag.train_ranking_model().is_err());
+
+        let chunk = |rag: &SimpleRagSystem, doc_id: &str| rag.document_chunks(doc_id).unwrap()[0].id.clone();
+        for (query, relevant, irrelevant) in
+            [("paid leave", &full_leave, &terse_leave), ("parking permits", &full_parking, &terse_parking)]
+        {
+            rag.record_feedback(query, &chunk(&rag, relevant), true).unwrap();
+            rag.record_feedback(query, &chunk(&rag, irrelevant), false).unwrap();
+        }
+        assert_eq!(rag.search("paid leave", 1).unwrap()[0].document_id, terse_leave);
+
+        let report = rag.train_ranking_model().unwrap();
+        assert_eq!((report.examples, report.relevant, report.accuracy), (4, 2, 1.0));
+        assert_eq!(rag.search("paid leave", 1).unwrap()[0].document_id, terse_leave);
+        rag.set_search_config(SearchConfig { learned_ranking: true, ..config }).unwrap();
+        assert_eq!(rag.search("paid leave", 1).unwrap()[0].document_id, full_leave);
+    }
+
+    #[test]
+    fn test_int8_quantization_keeps_vector_search_working() {
+        use crate::evaluation::EvaluationQuery;
+        use crate::testing::in_memory_builder;
+        let mut rag = in_memory_builder().build().unwrap();
+        let leave = rag.process_bytes("leave.md", b"Paid leave accrues at two days per month.").unwrap();
+        rag.process_bytes("parking.md", b"Parking permits are issued at the front desk.").unwrap();
+        let dataset = EvaluationDataset {
+            queries: vec![EvaluationQuery {
+                query: "paid leave".to_string(),
+                relevant_doc_ids: vec![leave.clone()],
+                source_chunk_id: None,
+            }],
+        };
+
+        let report = rag.evaluate_quantization(&dataset, EmbeddingQuantization::Int8, 1).unwrap();
+        assert_eq!(report.result_overlap, 1.0);
+        assert_eq!(report.quantized.mean.recall, report.full.mean.recall);
+        assert!(report.quantized_bytes * 3 < report.full_bytes);
+
+        let full_bytes = rag.get_stats().unwrap().embedding_bytes;
+        let mut rag = rag.with_embedding_quantization(EmbeddingQuantization::Int8);
+        assert_eq!(rag.get_stats().unwrap().embedding_bytes, report.quantized_bytes);
+        assert!(rag.get_stats().unwrap().embedding_bytes * 3 < full_bytes);
+        assert_eq!(rag.search("paid leave", 1).unwrap()[0].document_id, leave);
+        rag.process_bytes("pto.md", b"Paid time off requests go to your manager.").unwrap();
+        assert!(rag.storage.get_stored_embeddings().unwrap().values().all(StoredEmbedding::is_quantized));
+        assert!(rag.evaluate_quantization(&dataset, EmbeddingQuantization::Int8, 1).is_err());
+    }
+
+    #[test]
+    fn test_truncated_embeddings_rescore_at_full_dimension() {
+        use crate::testing::in_memory_builder;
+        let texts: [&[u8]; 3] = [
+            b"Paid leave accrues at two days per month.",
+            b"Parking permits are issued at the front desk.",
+            b"Leave requests for paid time off go to your manager.",
+        ];
+        let config = SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() };
+        let mut full = in_memory_builder().search_config(config.clone()).build().unwrap();
+        let mut truncated = in_memory_builder().search_config(config.clone()).build().unwrap().with_embedding_dimensions(8);
+        for (i, text) in texts.iter().enumerate() {
+            full.process_bytes(&format!("{}.md", i), text).unwrap();
+            truncated.process_bytes(&format!("{}.md", i), text).unwrap();
+        }
+        assert_eq!(truncated.storage.embedding_dimensions(), Some(8));
+        assert_eq!(truncated.get_stats().unwrap().embedding_bytes * 8, full.get_stats().unwrap().embedding_bytes);
+
+        let scores = |rag: &SimpleRagSystem| -> Vec<(String, f32)> {
+            rag.search("paid leave", 3).unwrap().into_iter().map(|r| (r.content, r.score)).collect()
+        };
+        let expected = scores(&full);
+        truncated.set_search_config(SearchConfig { full_dimension_rescoring: Some(3), ..config }).unwrap();
+        let rescored = scores(&truncated);
+        assert_eq!(rescored.len(), expected.len());
+        for ((content, score), (expected_content, expected_score)) in rescored.iter().zip(&expected) {
+            assert_eq!(content, expected_content);
+            assert!((score - expected_score).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn test_disk_index_serves_vector_search_after_quantizing() {
+        use crate::testing::in_memory_builder;
+        let config = SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() };
+        let mut rag = in_memory_builder().search_config(config).build().unwrap();
+        rag.process_bytes("leave.md", b"Paid leave accrues at two days per month.").unwrap();
+        rag.process_bytes("parking.md", b"Parking permits are issued at the front desk.").unwrap();
+        let expected = rag.search("paid leave", 1).unwrap()[0].clone();
+
+        let path = std::env::temp_dir().join(format!("rag_lib_disk_index_{}.dann", std::process::id()));
+        assert_eq!(rag.build_disk_index(&path).unwrap().len(), 2);
+        let mut rag = rag.with_embedding_quantization(EmbeddingQuantization::Binary);
+        let found = rag.search("paid leave", 1).unwrap();
+        assert_eq!(found[0].chunk_id, expected.chunk_id);
+        assert!((found[0].score - expected.score).abs() < 1e-5);
+
+        // Rebuilding takes the full vectors back from the old index
+        assert_eq!(rag.build_disk_index(&path).unwrap().len(), 2);
+        let reopened = SimpleRagSystem::new().unwrap().with_disk_index(&path).unwrap();
+        assert_eq!(reopened.disk_index().unwrap().len(), 2);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_disk_index_is_bypassed_for_chunks_changed_since_it_was_built() {
+        use crate::testing::in_memory_builder;
+        // Without boosts, so equal embeddings score equally
+        let config = SearchConfig { mode: SearchMode::Vector, entity_boost: 0.0, keyphrase_boost: 0.0, ..SearchConfig::default() };
+        let mut rag = in_memory_builder().search_config(config).build().unwrap();
+        let leave = rag.process_bytes("leave.md", b"Paid leave accrues at two days per month.").unwrap();
+        let parking = rag.process_bytes("parking.md", b"Parking permits are issued at the front desk.").unwrap();
+        let path = std::env::temp_dir().join(format!("rag_lib_stale_disk_index_{}.dann", std::process::id()));
+        rag.build_disk_index(&path).unwrap();
+        assert!(rag.storage.stale_vectors().is_empty());
+
+        // Re-embedding a chunk, as a reindex does, leaves the index holding its old vector
+        let (leave_chunk, parking_chunk) = (format!("{}_0", leave), format!("{}_0", parking));
+        let model = rag.storage.embedding_model().unwrap();
+        let vector = rag.storage.get_all_embeddings().unwrap().remove(&parking_chunk).unwrap();
+        rag.storage.store_embeddings(&model, HashMap::from([(leave_chunk.clone(), vector)])).unwrap();
+        assert_eq!(rag.storage.stale_vectors(), HashSet::from([leave_chunk.clone()]));
+        let found = rag.search("parking permits", 2).unwrap();
+        assert!(rag.disk_index().unwrap().contains(&leave_chunk));
+        assert!((found[0].score - found[1].score).abs() < 1e-5, "scored from the new embedding, not the index");
+
+        rag.build_disk_index(&path).unwrap();
+        assert!(rag.storage.stale_vectors().is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_ivf_index_applies_to_its_collections() {
+        use crate::testing::in_memory_builder;
+        let config = SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() };
+        let mut rag = in_memory_builder().search_config(config.clone()).build().unwrap();
+        let texts = [
+            "Paid leave accrues at two days per month.",
+            "Paid parking permits are issued at the front desk.",
+            "Sourdough needs a starter fed every day.",
+            "Lighthouses guide ships along the coast.",
+        ];
+        let mut ids = Vec::new();
+        for (i, text) in texts.iter().enumerate() {
+            let id = rag.process_bytes(&format!("{}.md", i), text.as_bytes()).unwrap();
+            if i < 2 {
+                rag.set_tag(&id, COLLECTION_TAG, "archive").unwrap();
+            }
+            ids.push(id);
+        }
+        let scores = |rag: &SimpleRagSystem| -> HashMap<String, f32> {
+            rag.search("paid leave days", 4).unwrap().into_iter().map(|r| (r.document_id, r.score)).collect()
+        };
+        let exact = scores(&rag);
+        assert!(exact[&ids[1]] > 0.0);
+
+        // One document per list, and only the nearest list probed
+        assert_eq!(rag.train_ivf_index(Some(4)).unwrap().lists(), 4);
+        let ivf = IvfConfig { nprobe: 1, ..IvfConfig::default() };
+        let collections = BTreeMap::from([("archive".to_string(), VectorIndex::Ivf)]);
+        rag.set_search_config(SearchConfig { ivf, collection_vector_indexes: collections, ..config }).unwrap();
+        let probed = scores(&rag);
+        assert_eq!(probed[&ids[0]], exact[&ids[0]]);
+        assert_eq!(probed.get(&ids[1]).copied().unwrap_or(0.0), 0.0);
+        assert_eq!(probed[&ids[3]], exact[&ids[3]]);
+
+        // Chunks embedded after training join their nearest list, and deleted ones leave it
+        let added = rag.process_bytes("4.md", texts[0].as_bytes()).unwrap();
+        rag.set_tag(&added, COLLECTION_TAG, "archive").unwrap();
+        assert_eq!(rag.ivf_index().unwrap().len(), 5);
+        assert_eq!(scores(&rag)[&added], exact[&ids[0]]);
+        rag.purge_document(&added).unwrap();
+        assert_eq!(rag.ivf_index().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_product_quantization_keeps_vector_search_working() {
+        use crate::testing::{in_memory_builder, HashEmbedder};
+        let builder = || {
+            in_memory_builder()
+                .embedding_provider(HashEmbedder::new(64).unwrap())
+                .search_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() })
+        };
+        let mut rag = builder().build().unwrap();
+        let words = ["leave", "parking", "sourdough", "lighthouse", "invoice", "badge", "laptop", "garden"];
+        for i in 0..pq::PQ_MIN_TRAINING_VECTORS {
+            let text = format!("{} {} {} note {}", words[i % 8], words[i / 8 % 8], words[i / 64 % 8], i);
+            rag.process_bytes(&format!("{}.md", i), text.as_bytes()).unwrap();
+        }
+        let queries = vec!["leave parking".to_string(), "sourdough garden".to_string()];
+        let before = rag.storage.get_stored_embeddings().unwrap();
+        let bench = rag.benchmark_quantization(&queries, 2, 5, EmbeddingQuantization::Pq).unwrap();
+        assert_eq!(bench.query.samples, 4);
+        assert!(bench.recall > 0.0);
+        assert!(bench.quantized_bytes * 10 < bench.full_bytes);
+        assert!(rag.storage.pq_codebook().is_none());
+        assert_eq!(rag.storage.get_stored_embeddings().unwrap(), before, "measured on a copy");
+
+        // Too few embeddings only stop product quantization
+        let mut small = builder().build().unwrap();
+        small.process_bytes("leave.md", b"leave parking note").unwrap();
+        let error = small.benchmark_quantization(&queries, 1, 5, EmbeddingQuantization::Pq).unwrap_err();
+        assert!(error.to_string().contains("at least"));
+        let int8 = small.benchmark_quantization(&queries, 1, 5, EmbeddingQuantization::Int8).unwrap();
+        assert!(int8.quantized_bytes < int8.full_bytes);
+
+        let top = rag.search("leave parking", 1).unwrap()[0].chunk_id.clone();
+        let rag = rag.with_embedding_quantization(EmbeddingQuantization::Pq);
+        assert!(rag.storage.get_stored_embeddings().unwrap().values().all(StoredEmbedding::is_quantized));
+        let reloaded = builder()
+            .storage(StorageManager::from_snapshot(rag.storage.snapshot().unwrap()).unwrap())
+            .build()
+            .unwrap();
+        let scores = |rag: &SimpleRagSystem| -> Vec<f32> {
+            rag.search("leave parking", 5).unwrap().into_iter().map(|r| r.score).collect()
+        };
+        assert_eq!(scores(&reloaded), scores(&rag));
+        assert_eq!(rag.search("leave parking", 1).unwrap()[0].chunk_id, top);
+    }
+
+    #[test]
+    fn test_corpus_stats_count_terms_per_chunk() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        rag.process_bytes("a.md", b"Paid leave, paid leave.").unwrap();
+        rag.process_bytes("b.md", b"Unpaid leave needs approval").unwrap();
+        rag.process_bytes("c.md", b"Sourdough").unwrap();
+
+        let stats = rag.corpus_stats().unwrap();
+        assert_eq!((stats.chunks, stats.total_terms, stats.vocabulary_size), (3, 9, 6));
+        assert_eq!(stats.document_frequencies["paid"], 1);
+        assert_eq!(stats.most_common(1), vec![("leave", 2)]);
+        assert!(stats.idf("sourdough") > stats.idf("leave"));
+        assert_eq!((stats.chunk_lengths.min, stats.chunk_lengths.p50, stats.chunk_lengths.max), (1, 4, 4));
+        assert!((stats.chunk_lengths.mean - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_sharded_index_persists_and_searches_like_one() {
+        let path = std::env::temp_dir().join(format!("rag_sharded_{}.json", std::process::id()));
+        let texts = [
+            "Paid leave accrues at two days per month.",
+            "Parking permits for paid spaces are issued at the front desk.",
+            "Sourdough needs a starter fed every day.",
+            "Leave the lighthouse lamp on at night.",
+            "Paid overtime needs a manager's approval.",
+        ];
+        let mut single = SimpleRagSystem::new().unwrap();
+        let mut sharded = SimpleRagSystem::open(&path).unwrap().with_shards(3);
+        for (i, text) in texts.iter().enumerate() {
+            single.process_bytes(&format!("{}.md", i), text.as_bytes()).unwrap();
+            sharded.process_bytes(&format!("{}.md", i), text.as_bytes()).unwrap();
+        }
+        sharded.persist().unwrap();
+        assert!((0..3).all(|i| crate::storage::shard_path(&path, i).exists()));
+
+        let reopened = SimpleRagSystem::open(&path).unwrap();
+        assert_eq!(reopened.storage.shard_count(), 3);
+        assert_eq!(reopened.list_documents().unwrap().len(), texts.len());
+        // Document ids differ between the two systems and ties may come back in either order
+        let ranked = |rag: &SimpleRagSystem| -> Vec<(String, f32)> {
+            let mut results: Vec<_> = rag.search("paid", 5).unwrap().into_iter().map(|r| (r.content, r.score)).collect();
+            results.sort_by(|a, b| a.0.cmp(&b.0));
+            results
+        };
+        assert_eq!(ranked(&reopened), ranked(&single));
+
+        for i in 0..3 {
+            std::fs::remove_file(crate::storage::shard_path(&path, i)).unwrap();
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_sharded_search_scores_and_ranks_like_one_index() {
+        let texts = [
+            "Paid leave accrues at two days per month.",
+            "Parking permits for paid spaces are issued at the front desk. Paid parking is near.",
+            "Sourdough needs a starter fed every day.",
+            "Leave the lighthouse lamp on at night.",
+            "Paid overtime needs a manager's approval.",
+            "Cats sleep most of the day, and paid cat sitters feed them.",
+        ];
+        for mode in [SearchMode::Bm25, SearchMode::Hybrid] {
+            let build = |shards: usize| {
+                let mut rag = SimpleRagSystem::new()
+                    .unwrap()
+                    .with_shards(shards)
+                    .with_embedding_provider(CatEmbedder::default())
+                    .with_search_config(SearchConfig { mode, ..SearchConfig::default() })
+                    .unwrap();
+                for (i, text) in texts.iter().enumerate() {
+                    rag.process_bytes(&format!("{}.md", i), text.as_bytes()).unwrap();
+                }
+                rag
+            };
+            // Ties come back in chunk order, which sharding changes
+            let ranked = |rag: &SimpleRagSystem| -> (Vec<usize>, Vec<(f32, String)>) {
+                let results = rag.search("paid leave cats", 4).unwrap();
+                let mut scored: Vec<_> = results.iter().map(|r| (r.score, r.content.clone())).collect();
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+                (results.iter().map(|r| r.rank).collect(), scored)
+            };
+            let sharded = ranked(&build(3));
+            assert_eq!(sharded, ranked(&build(1)), "{:?}", mode);
+            assert_eq!(sharded.0, [1, 2, 3, 4]);
+        }
+    }
+
+    #[test]
+    fn test_two_stage_search_only_scores_top_documents() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let lighthouse = rag
+            .process_bytes("lighthouse.md", b"Lighthouses guide ships along dangerous coasts at night.")
+            .unwrap();
+        rag.process_bytes("bread.md", b"Sourdough bread needs a starter. Bakers ship loaves to ships in port.")
+            .unwrap();
+
+        let results = rag.search_two_stage("lighthouse ships coasts", 1, 5).unwrap();
+        assert!(!results.is_empty());
+        assert!(results.iter().all(|r| r.document_id == lighthouse));
+        assert_eq!(rag.search("lighthouse ships coasts", 5).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_two_stage_search_falls_back_when_too_few_documents_match() {
+        let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+        rag.process_bytes("lighthouse.md", b"Lighthouses guide ships along coasts.").unwrap();
+        // Only this document's second paragraph matches, so its opening chunk scores nothing
+        let history = rag
+            .process_bytes("history.md", b"A history of the harbour town.\n\nIts old lighthouse still stands.")
+            .unwrap();
+        rag.process_bytes("bread.md", b"Sourdough bread needs a starter.").unwrap();
+
+        let results = rag.search_two_stage("lighthouse", 2, 5).unwrap();
+        assert!(results.iter().any(|r| r.document_id == history && r.score > 0.0));
+    }
+}
@@ -0,0 +1,363 @@
+//! LLM completion abstraction
+
+use anyhow::{anyhow, Result};
+#[cfg(any(feature = "openai", feature = "anthropic", feature = "ollama"))]
+use rig::client::CompletionClient;
+#[cfg(feature = "rig")]
+use rig::completion::{AssistantContent, CompletionModel};
+#[cfg(feature = "anthropic")]
+use rig::providers::anthropic;
+#[cfg(feature = "ollama")]
+use rig::providers::ollama;
+#[cfg(feature = "openai")]
+use rig::providers::openai;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use crate::usage::TokenUsage;
+
+/// A single prompt sent to a completion model
+#[derive(Debug, Clone, Default)]
+pub struct CompletionRequest {
+    pub preamble: Option<String>,
+    pub prompt: String,
+    pub temperature: Option<f64>,
+    /// Upper bound on generated tokens; providers that require one fall back to a model default
+    pub max_tokens: Option<u64>,
+}
+
+/// Context window assumed for models that don't declare one
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8_192;
+
+impl CompletionRequest {
+    pub fn new(prompt: impl Into<String>) -> Self {
+        Self {
+            prompt: prompt.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_preamble(mut self, preamble: impl Into<String>) -> Self {
+        self.preamble = Some(preamble.into());
+        self
+    }
+
+    pub fn with_temperature(mut self, temperature: f64) -> Self {
+        self.temperature = Some(temperature);
+        self
+    }
+
+    pub fn with_max_tokens(mut self, max_tokens: u64) -> Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+}
+
+/// Model hosts the system can generate and embed with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProviderKind {
+    OpenAi,
+    /// Local Ollama server, so nothing leaves the machine
+    Ollama,
+    /// Claude models; generation only
+    #[serde(alias = "claude")]
+    Anthropic,
+}
+
+impl ProviderKind {
+    pub fn default_completion_model(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "gpt-4o-mini",
+            ProviderKind::Ollama => "llama3.2",
+            ProviderKind::Anthropic => "claude-sonnet-4-0",
+        }
+    }
+
+    /// `None` for hosts without an embedding API
+    pub fn default_embedding_model(&self) -> Option<&'static str> {
+        match self {
+            ProviderKind::OpenAi => Some("text-embedding-3-small"),
+            ProviderKind::Ollama => Some("nomic-embed-text"),
+            ProviderKind::Anthropic => None,
+        }
+    }
+
+    /// Cargo feature that compiles in support for this host
+    pub fn feature(&self) -> &'static str {
+        match self {
+            ProviderKind::OpenAi => "openai",
+            ProviderKind::Ollama => "ollama",
+            ProviderKind::Anthropic => "anthropic",
+        }
+    }
+
+    /// Error for a host whose feature was left out of this build
+    pub(crate) fn missing_feature(&self) -> anyhow::Error {
+        anyhow!("{} support is not compiled in; rebuild with `--features {}`", self.feature(), self.feature())
+    }
+}
+
+impl std::str::FromStr for ProviderKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "openai" => Ok(ProviderKind::OpenAi),
+            "ollama" => Ok(ProviderKind::Ollama),
+            "anthropic" | "claude" => Ok(ProviderKind::Anthropic),
+            other => Err(anyhow!("Unknown provider '{}' (expected openai, ollama or anthropic)", other)),
+        }
+    }
+}
+
+/// Completion provider for `model` on the given host
+#[cfg_attr(not(any(feature = "openai", feature = "anthropic", feature = "ollama")), allow(unused_variables))]
+pub fn completion_provider(kind: ProviderKind, model: &str) -> Result<Arc<dyn CompletionProvider>> {
+    match kind {
+        #[cfg(feature = "openai")]
+        ProviderKind::OpenAi => Ok(Arc::new(RigCompletionProvider::openai(model)?)),
+        #[cfg(feature = "ollama")]
+        ProviderKind::Ollama => Ok(Arc::new(RigCompletionProvider::ollama(model)?)),
+        #[cfg(feature = "anthropic")]
+        ProviderKind::Anthropic => Ok(Arc::new(RigCompletionProvider::anthropic(model)?)),
+        #[allow(unreachable_patterns)]
+        kind => Err(kind.missing_feature()),
+    }
+}
+
+/// Ollama client for `$OLLAMA_API_BASE_URL`, defaulting to the local server
+#[cfg(feature = "ollama")]
+pub(crate) fn ollama_client() -> Result<ollama::Client> {
+    Ok(ollama::ClientBuilder::new().base_url(&ollama_base_url()).build()?)
+}
+
+/// `$OLLAMA_API_BASE_URL`, or the local server's default address
+#[cfg(feature = "ollama")]
+pub(crate) fn ollama_base_url() -> String {
+    std::env::var("OLLAMA_API_BASE_URL").unwrap_or_else(|_| "http://localhost:11434".to_string())
+}
+
+/// Future returned by the async provider methods, boxed so providers stay usable as trait objects
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Anything that can turn a prompt into text
+pub trait CompletionProvider: Send + Sync {
+    fn model_name(&self) -> &str;
+
+    fn complete(&self, request: &CompletionRequest) -> Result<String>;
+
+    /// Like `complete`, also reporting the tokens used; estimated unless the provider reports them
+    fn complete_with_usage(&self, request: &CompletionRequest) -> Result<(String, TokenUsage)> {
+        let text = self.complete(request)?;
+        let usage = TokenUsage::estimate(request, &text);
+        Ok((text, usage))
+    }
+
+    /// `complete_with_usage` without blocking a thread. The default runs the blocking call in place,
+    /// so providers that wait on the network should override it
+    fn complete_async<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<(String, TokenUsage)>> {
+        Box::pin(async move { self.complete_with_usage(request) })
+    }
+
+    /// Tokens the model accepts per request, prompt and answer together
+    fn context_window(&self) -> usize {
+        DEFAULT_CONTEXT_WINDOW
+    }
+}
+
+impl<P: CompletionProvider + ?Sized> CompletionProvider for Arc<P> {
+    fn model_name(&self) -> &str {
+        (**self).model_name()
+    }
+
+    fn complete(&self, request: &CompletionRequest) -> Result<String> {
+        (**self).complete(request)
+    }
+
+    fn complete_with_usage(&self, request: &CompletionRequest) -> Result<(String, TokenUsage)> {
+        (**self).complete_with_usage(request)
+    }
+
+    fn complete_async<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<(String, TokenUsage)>> {
+        (**self).complete_async(request)
+    }
+
+    fn context_window(&self) -> usize {
+        (**self).context_window()
+    }
+}
+
+/// Completion provider backed by any rig completion model
+#[cfg(feature = "rig")]
+pub struct RigCompletionProvider<M> {
+    model: M,
+    model_name: String,
+    context_window: usize,
+}
+
+#[cfg(feature = "rig")]
+impl<M: CompletionModel> RigCompletionProvider<M> {
+    pub fn new(model: M, model_name: impl Into<String>) -> Self {
+        Self {
+            model,
+            model_name: model_name.into(),
+            context_window: DEFAULT_CONTEXT_WINDOW,
+        }
+    }
+
+    pub fn with_context_window(mut self, tokens: usize) -> Self {
+        self.context_window = tokens;
+        self
+    }
+}
+
+#[cfg(feature = "openai")]
+impl RigCompletionProvider<openai::responses_api::ResponsesCompletionModel> {
+    /// OpenAI model, with the API key read from `OPENAI_API_KEY`
+    pub fn openai(model_name: &str) -> Result<Self> {
+        let api_key = std::env::var("OPENAI_API_KEY").map_err(|_| anyhow!("OPENAI_API_KEY is not set"))?;
+        Ok(Self::openai_with_key(model_name, &api_key))
+    }
+
+    pub fn openai_with_key(model_name: &str, api_key: &str) -> Self {
+        let client = openai::Client::new(api_key);
+        Self::new(client.completion_model(model_name), model_name).with_context_window(128_000)
+    }
+}
+
+#[cfg(feature = "anthropic")]
+impl RigCompletionProvider<anthropic::completion::CompletionModel> {
+    /// Claude model, with the API key read from `ANTHROPIC_API_KEY`
+    pub fn anthropic(model_name: &str) -> Result<Self> {
+        let api_key = std::env::var("ANTHROPIC_API_KEY").map_err(|_| anyhow!("ANTHROPIC_API_KEY is not set"))?;
+        Ok(Self::anthropic_with_key(model_name, &api_key))
+    }
+
+    pub fn anthropic_with_key(model_name: &str, api_key: &str) -> Self {
+        let client = anthropic::Client::new(api_key);
+        let mut model = client.completion_model(model_name);
+        // Anthropic requires max_tokens; rig only knows defaults for the models it was built against
+        model.default_max_tokens.get_or_insert(4_096);
+        Self::new(model, model_name).with_context_window(200_000)
+    }
+}
+
+#[cfg(feature = "ollama")]
+impl RigCompletionProvider<ollama::CompletionModel> {
+    /// Model served by a local Ollama instance, e.g. `llama3.2`
+    pub fn ollama(model_name: &str) -> Result<Self> {
+        let client = ollama_client()?;
+        Ok(Self::new(client.completion_model(model_name), model_name))
+    }
+}
+
+#[cfg(feature = "rig")]
+impl<M: CompletionModel + 'static> CompletionProvider for RigCompletionProvider<M> {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn context_window(&self) -> usize {
+        self.context_window
+    }
+
+    fn complete(&self, request: &CompletionRequest) -> Result<String> {
+        self.complete_with_usage(request).map(|(text, _)| text)
+    }
+
+    fn complete_with_usage(&self, request: &CompletionRequest) -> Result<(String, TokenUsage)> {
+        block_on(self.send(request))?
+    }
+
+    fn complete_async<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<(String, TokenUsage)>> {
+        Box::pin(self.send(request))
+    }
+}
+
+#[cfg(feature = "rig")]
+impl<M: CompletionModel + 'static> RigCompletionProvider<M> {
+    async fn send(&self, request: &CompletionRequest) -> Result<(String, TokenUsage)> {
+        let mut builder = self
+            .model
+            .completion_request(request.prompt.as_str())
+            .temperature_opt(request.temperature)
+            .max_tokens_opt(request.max_tokens);
+        if let Some(preamble) = &request.preamble {
+            builder = builder.preamble(preamble.clone());
+        }
+
+        let response = builder.send().await?;
+        let usage = response.usage;
+        let text: Vec<String> = response
+            .choice
+            .into_iter()
+            .filter_map(|content| match content {
+                AssistantContent::Text(text) => Some(text.text),
+                _ => None,
+            })
+            .collect();
+
+        if text.is_empty() {
+            return Err(anyhow!("{} returned no text", self.model_name));
+        }
+        let text = text.join("");
+        // Some providers leave usage empty, in which case count it ourselves
+        let usage = if usage.input_tokens + usage.output_tokens == 0 {
+            TokenUsage::estimate(request, &text)
+        } else {
+            TokenUsage {
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+            }
+        };
+        Ok((text, usage))
+    }
+}
+
+/// Drive a future to completion from synchronous code.
+///
+/// Inside a multi-threaded tokio runtime this blocks the current worker in place;
+/// outside of one a single-threaded runtime is created for the call.
+#[cfg(feature = "rig")]
+pub(crate) fn block_on<F: Future>(future: F) -> Result<F::Output> {
+    match tokio::runtime::Handle::try_current() {
+        Ok(handle) => Ok(tokio::task::block_in_place(|| handle.block_on(future))),
+        Err(_) => {
+            let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build()?;
+            Ok(runtime.block_on(future))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_request_builder() {
+        let request = CompletionRequest::new("What is RAG?")
+            .with_preamble("You are terse.")
+            .with_temperature(0.0);
+
+        assert_eq!(request.prompt, "What is RAG?");
+        assert_eq!(request.preamble.as_deref(), Some("You are terse."));
+        assert_eq!(request.temperature, Some(0.0));
+    }
+
+    #[test]
+    fn test_provider_kind_parsing() {
+        assert_eq!("Ollama".parse::<ProviderKind>().unwrap(), ProviderKind::Ollama);
+        assert_eq!("openai".parse::<ProviderKind>().unwrap().default_completion_model(), "gpt-4o-mini");
+        assert_eq!("claude".parse::<ProviderKind>().unwrap().default_embedding_model(), None);
+        assert!("mystery".parse::<ProviderKind>().is_err());
+    }
+
+    #[cfg(not(feature = "ollama"))]
+    #[test]
+    fn test_hosts_left_out_of_the_build_name_their_feature() {
+        let err = completion_provider(ProviderKind::Ollama, "llama3.2").err().unwrap();
+        assert!(err.to_string().contains("--features ollama"), "{}", err);
+    }
+}
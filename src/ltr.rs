@@ -0,0 +1,174 @@
+//! Learning to rank: a logistic regression over per-chunk ranking signals, fitted offline to the
+//! relevance feedback kept in the index and applied at query time in place of the mode's own score
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use crate::search::SearchMode;
+
+/// Passes of gradient descent over the examples when training
+const EPOCHS: usize = 500;
+const LEARNING_RATE: f32 = 0.5;
+/// L2 penalty, which keeps weights finite when the examples are perfectly separable
+const REGULARIZATION: f32 = 0.01;
+
+/// A document this many days old scores half the recency of one modified today
+const RECENCY_HALF_LIFE_DAYS: f32 = 90.0;
+/// Chunks of this many words score half the length signal of an endless one
+const LENGTH_SCALE_WORDS: f32 = 100.0;
+
+/// The signals the model weighs, each from 0 to 1
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct RankingFeatures {
+    /// Keyword or BM25 score, relative to the best chunk of the search
+    pub keyword: f32,
+    /// Cosine similarity to the query embedding; 0 in lexical modes
+    pub vector: f32,
+    /// How recently the document's file changed; 0 when unknown
+    pub recency: f32,
+    /// Chunk length, saturating for long chunks
+    pub length: f32,
+}
+
+impl RankingFeatures {
+    fn values(&self) -> [f32; 4] {
+        [self.keyword, self.vector, self.recency, self.length]
+    }
+}
+
+/// Recency signal of a document last modified at `modified` (Unix seconds)
+pub fn recency(modified: Option<u64>, now: u64) -> f32 {
+    let Some(modified) = modified else {
+        return 0.0;
+    };
+    let age_days = now.saturating_sub(modified) as f32 / 86_400.0;
+    0.5f32.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+}
+
+pub fn length(word_count: usize) -> f32 {
+    let words = word_count as f32;
+    words / (words + LENGTH_SCALE_WORDS)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RankingModel {
+    /// Mode the model was trained in; searches in another mode ignore it
+    pub mode: SearchMode,
+    /// One per feature, in the order keyword, vector, recency, length
+    pub weights: [f32; 4],
+    pub bias: f32,
+    /// Judgements it was fitted to
+    pub examples: usize,
+    /// Unix seconds when it was trained
+    pub trained_at: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl RankingModel {
+    /// Probability that a chunk with these features is relevant
+    pub fn score(&self, features: &RankingFeatures) -> f32 {
+        let z: f32 = self.bias + self.weights.iter().zip(features.values()).map(|(w, x)| w * x).sum::<f32>();
+        sigmoid(z)
+    }
+}
+
+/// A judged chunk's features
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrainingExample {
+    pub features: RankingFeatures,
+    pub relevant: bool,
+}
+
+/// How a freshly trained model fits its examples
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrainingReport {
+    pub examples: usize,
+    pub relevant: usize,
+    /// Share of examples the model classifies correctly at a 0.5 threshold
+    pub accuracy: f32,
+    pub model: RankingModel,
+}
+
+/// Fit a model by gradient descent, weighting the classes equally however unbalanced the examples are
+pub fn train(examples: &[TrainingExample], mode: SearchMode, now: u64) -> Result<TrainingReport> {
+    let relevant = examples.iter().filter(|e| e.relevant).count();
+    if relevant == 0 || relevant == examples.len() {
+        return Err(anyhow!(
+            "Training needs chunks judged both relevant and irrelevant ({} of {} judged relevant)",
+            relevant,
+            examples.len()
+        ));
+    }
+    let class_weight = |is_relevant: bool| {
+        let count = if is_relevant { relevant } else { examples.len() - relevant };
+        examples.len() as f32 / (2.0 * count as f32)
+    };
+
+    let mut model = RankingModel {
+        mode,
+        weights: [0.0; 4],
+        bias: 0.0,
+        examples: examples.len(),
+        trained_at: now,
+        tenant: None,
+    };
+    for _ in 0..EPOCHS {
+        let mut weight_gradient = [0.0f32; 4];
+        let mut bias_gradient = 0.0;
+        for example in examples {
+            let target = if example.relevant { 1.0 } else { 0.0 };
+            let error = (model.score(&example.features) - target) * class_weight(example.relevant);
+            for (gradient, x) in weight_gradient.iter_mut().zip(example.features.values()) {
+                *gradient += error * x;
+            }
+            bias_gradient += error;
+        }
+        let n = examples.len() as f32;
+        for (weight, gradient) in model.weights.iter_mut().zip(weight_gradient) {
+            *weight -= LEARNING_RATE * (gradient / n + REGULARIZATION * *weight);
+        }
+        model.bias -= LEARNING_RATE * bias_gradient / n;
+    }
+
+    let correct = examples.iter().filter(|e| (model.score(&e.features) >= 0.5) == e.relevant).count();
+    Ok(TrainingReport {
+        examples: examples.len(),
+        relevant,
+        accuracy: correct as f32 / examples.len() as f32,
+        model,
+    })
+}
+
+fn sigmoid(z: f32) -> f32 {
+    1.0 / (1.0 + (-z).exp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_training_learns_which_signal_matters() {
+        let example = |keyword: f32, length: f32, relevant: bool| TrainingExample {
+            features: RankingFeatures { keyword, vector: 0.0, recency: 0.0, length },
+            relevant,
+        };
+        // Relevance follows the keyword score; length is noise
+        let examples = [
+            example(0.9, 0.2, true),
+            example(0.8, 0.9, true),
+            example(0.7, 0.5, true),
+            example(0.2, 0.9, false),
+            example(0.1, 0.1, false),
+            example(0.3, 0.6, false),
+        ];
+        let report = train(&examples, SearchMode::Bm25, 0).unwrap();
+        assert_eq!(report.accuracy, 1.0);
+        assert!(report.model.weights[0] > report.model.weights[3].abs());
+
+        assert!(train(&examples[..3], SearchMode::Bm25, 0).is_err());
+        assert_eq!(recency(Some(0), 90 * 86_400), 0.5);
+        assert_eq!(recency(None, 0), 0.0);
+        assert_eq!(length(100), 0.5);
+    }
+}
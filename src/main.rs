@@ -1,53 +1,1025 @@
 //! Simple CLI for the RAG System
 
 use clap::{Parser, Subcommand};
-use std::path::Path;
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
 
-use rag_system::SimpleRagSystem;
+use rag_system::answer_cache::AnswerCacheConfig;
+use rag_system::audit::{AuditAction, AuditFilter};
+use rag_system::backup::BackupScheduler;
+use rag_system::bench::load_queries;
+use rag_system::config::{ApiKeys, ChunkingConfig, ChunkingKind, StorageBackend};
+use rag_system::dedupe::{DuplicateKind, DEFAULT_NEAR_DUPLICATE_THRESHOLD};
+use rag_system::doctor::{
+    check_config, check_embedding_provider, check_gc, check_index_file, check_integrity, CheckStatus, Diagnostic,
+};
+use rag_system::embedding::{embedding_provider, EmbeddingConfig, ProgressCallback};
+use rag_system::evaluation::EvaluationDataset;
+use rag_system::facets::{FacetedResults, Facets};
+use rag_system::generation::PromptTemplate;
+use rag_system::ingest::{IngestOptions, IngestOutcome, IngestPreview};
+use rag_system::llm::{completion_provider, ProviderKind};
+use rag_system::mcp::McpServer;
+use rag_system::metrics::serve_metrics;
+use rag_system::multihop::MultiHopAnswer;
+use rag_system::prelude::*;
+use rag_system::quantization::EmbeddingQuantization;
+use rag_system::processor::{IngestProgress, IngestProgressCallback};
+use rag_system::saved::SavedSearch;
+use rag_system::search::{CrossLingual, VectorIndex};
+use rag_system::server::{RagServer, Replica};
+use rag_system::sort::ResultSort;
+use rag_system::synonyms::SynonymDictionary;
+use rag_system::tags::parse_tag;
+use rag_system::watch::{DirectoryWatcher, WatchEvent};
+use rag_system::webhook::WebhookNotifier;
+use std::io::{BufRead, Write};
+use std::net::TcpListener;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tracing_subscriber::fmt::format::FmtSpan;
+use tracing_subscriber::EnvFilter;
 
 #[derive(Parser)]
 #[command(name = "rag-system")]
 #[command(about = "Simple RAG System")]
 struct Cli {
+    /// Config file (defaults to ~/.config/rag-system/config.toml when it exists); flags override it
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// Index file holding processed documents (defaults to $RAG_SYSTEM_INDEX or ~/.rag_system/index.json)
+    #[arg(long, global = true)]
+    index: Option<PathBuf>,
+
+    /// Work with one tenant's documents in a shared index; other tenants' documents stay invisible
+    #[arg(long, global = true)]
+    tenant: Option<String>,
+
+    /// Model host for generation: openai (OPENAI_API_KEY), anthropic (ANTHROPIC_API_KEY) or ollama [default: openai]
+    #[arg(long, global = true)]
+    provider: Option<ProviderKind>,
+
+    /// Model host for embeddings, when it should differ from --provider
+    #[arg(long, global = true)]
+    embedding_provider: Option<ProviderKind>,
+
+    /// Retrieval mode: keyword, bm25, vector or hybrid (vector modes need an embedding model) [default: keyword]
+    #[arg(long, global = true)]
+    search_mode: Option<SearchMode>,
+
+    /// Most chunks of one document in search results, so one long document can't fill them all
+    #[arg(long, global = true, value_name = "N")]
+    max_chunks_per_document: Option<usize>,
+
+    /// Rank matching chunks with the model `train-ranker` fitted, as `learned_ranking = true` does
+    #[arg(long, global = true)]
+    learned_ranking: bool,
+
+    /// Synonyms file queries are expanded with, one `term => expansion, ...` per line; overrides search.synonyms
+    #[arg(long, global = true, value_name = "FILE")]
+    synonyms: Option<PathBuf>,
+
+    /// Embedding model; when set, processed chunks are embedded (defaults to the index's model)
+    #[arg(long, global = true)]
+    embedding_model: Option<String>,
+
+    /// Embed an LLM-written hypothetical answer instead of the query (vector and hybrid modes)
+    #[arg(long, global = true)]
+    hyde: bool,
+
+    /// Record searches in the index's query log, as `[query_log] enabled = true` does
+    #[arg(long, global = true)]
+    log_queries: bool,
+
+    /// Who the audit log records ingests, updates and deletes as [default: $RAG_ACTOR, else $USER]
+    #[arg(long, global = true)]
+    actor: Option<String>,
+
+    /// Chunks sent per embedding request [default: 256]
+    #[arg(long, global = true)]
+    embed_batch_size: Option<usize>,
+
+    /// Embedding requests in flight at once [default: 4]
+    #[arg(long, global = true)]
+    embed_concurrency: Option<usize>,
+
+    /// Cap on embedding requests per minute
+    #[arg(long, global = true)]
+    embed_rpm: Option<u32>,
+
+    /// Log more about what the library is doing: -v info, -vv debug, -vvv trace; RUST_LOG overrides
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log errors, and hide progress bars
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Output format: text, json, or ndjson (one JSON value per line)
+    #[arg(long, global = true, value_enum, default_value = "text")]
+    format: OutputFormat,
+
     #[command(subcommand)]
     command: Commands,
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+    Text,
+    Json,
+    Ndjson,
+}
+
+/// Print `value` in a machine-readable format; arrays become one line per element with ndjson
+fn emit_json<T: Serialize>(format: OutputFormat, value: &T) -> anyhow::Result<()> {
+    let value = serde_json::to_value(value)?;
+    match (format, value) {
+        (OutputFormat::Ndjson, serde_json::Value::Array(items)) => {
+            for item in items {
+                println!("{}", item);
+            }
+        }
+        (OutputFormat::Ndjson, value) => println!("{}", value),
+        (_, value) => println!("{}", serde_json::to_string_pretty(&value)?),
+    }
+    Ok(())
+}
+
+/// Log to stderr at the level picked by -v/-q, unless RUST_LOG says otherwise
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => "error",
+        (false, 0) => "warn",
+        (false, 1) => "info",
+        (false, 2) => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| EnvFilter::new(format!("warn,rag_system={}", level)));
+    // At trace level, also log each pipeline span as it closes, with its timings
+    let span_events = if level == "trace" { FmtSpan::CLOSE } else { FmtSpan::NONE };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_span_events(span_events)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+/// Bars for files processed, chunks created and embeddings computed, fed by the library's callbacks
+struct IngestBars {
+    files: ProgressBar,
+    embeddings: Option<ProgressBar>,
+    chunks: Arc<AtomicUsize>,
+}
+
+impl IngestBars {
+    fn new(files: usize, embeds: bool) -> Self {
+        let multi = MultiProgress::new();
+        let style = ProgressStyle::with_template("{prefix:>10} [{bar:30}] {pos}/{len} {msg}")
+            .expect("valid progress template")
+            .progress_chars("=> ");
+        let files_bar = multi.add(ProgressBar::new(files as u64).with_style(style.clone()).with_prefix("Files"));
+        let embeddings = embeds.then(|| multi.add(ProgressBar::new(0).with_style(style).with_prefix("Embeddings")));
+        Self {
+            files: files_bar,
+            embeddings,
+            chunks: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    fn ingest_callback(&self) -> IngestProgressCallback {
+        let files = self.files.clone();
+        let chunks = self.chunks.clone();
+        Arc::new(move |progress: &IngestProgress| match progress {
+            IngestProgress::Discovered { files: count } => files.set_length(*count as u64),
+            IngestProgress::Chunked { file_path, .. } => files.set_message(file_path.clone()),
+            IngestProgress::Embedded { .. } => {}
+            IngestProgress::Stored { chunks: stored, .. } => {
+                let total = chunks.fetch_add(*stored, Ordering::SeqCst) + stored;
+                files.inc(1);
+                files.set_message(format!("{} chunks created", total));
+            }
+            IngestProgress::Failed { .. } => files.inc(1),
+        })
+    }
+
+    fn embedding_callback(&self) -> ProgressCallback {
+        let bar = self.embeddings.clone().unwrap_or_else(ProgressBar::hidden);
+        Arc::new(move |done: usize, total: usize| {
+            bar.set_length(total as u64);
+            bar.set_position(done as u64);
+        })
+    }
+
+    fn finish(&self) {
+        self.files.finish();
+        if let Some(bar) = &self.embeddings {
+            bar.finish();
+        }
+    }
+}
+
+/// Make config file keys visible to providers, unless the environment already sets them
+fn apply_api_keys(keys: &ApiKeys) {
+    for (var, key) in [("OPENAI_API_KEY", &keys.openai), ("ANTHROPIC_API_KEY", &keys.anthropic)] {
+        if let (Some(key), Err(_)) = (key, std::env::var(var)) {
+            std::env::set_var(var, key);
+        }
+    }
+}
+
+/// Render seconds since the Unix epoch as a UTC date and time
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil-from-days conversion (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", year, month, day, rem / 3600, rem % 3600 / 60, rem % 60)
+}
+
 #[derive(Subcommand)]
 enum Commands {
     /// Process a document
     Process {
         /// Path to the document file
         file: String,
+        /// Have the LLM write a document summary, shown by `list` and searchable with `search --documents`
+        #[arg(long)]
+        summarize: bool,
+        /// Have the LLM extract entity relations from each chunk, for `search --graph-hops`
+        #[arg(long)]
+        graph: bool,
+        /// Process and chunk the file, printing what would be stored, without changing the index
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Process every text file in a directory, or the files matching a glob
+    Ingest {
+        /// Directory, glob such as `docs/**/*.md`, or single file
+        path: PathBuf,
+        /// Only ingest files matching this glob, relative to the directory; repeatable
+        #[arg(long)]
+        include: Vec<String>,
+        /// Skip files matching this glob, relative to the directory; repeatable
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Have the LLM write a summary of each document
+        #[arg(long)]
+        summarize: bool,
+        /// Have the LLM extract entity relations from each chunk, for `search --graph-hops`
+        #[arg(long)]
+        graph: bool,
+        /// Process and chunk the files, printing what would be stored, without changing the index
+        #[arg(long)]
+        dry_run: bool,
+        /// Threads for each ingest stage (read, chunk, embed); 1 processes one file at a time
+        #[arg(long, default_value = "4")]
+        workers: usize,
+    },
+    /// Keep the index in step with a directory, printing files as they are added, changed or removed
+    Watch {
+        dir: PathBuf,
+        /// Only watch files matching this glob, relative to the directory; repeatable
+        #[arg(long)]
+        include: Vec<String>,
+        /// Ignore files matching this glob, relative to the directory; repeatable
+        #[arg(long)]
+        exclude: Vec<String>,
+        /// Seconds between scans
+        #[arg(long, default_value = "2")]
+        interval: u64,
+    },
+    /// Run every query in a file and write the results as NDJSON, one line per query
+    QueryBatch {
+        /// File with one query per line; blank lines and `#` comments are skipped
+        queries: PathBuf,
+        /// Maximum number of results per query
+        #[arg(short, long, default_value = "5")]
+        limit: usize,
+        /// NDJSON file to write; defaults to stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Show, set or remove a document's tags
+    Tag {
+        doc_id: String,
+        /// Tags to set, as key=value
+        tags: Vec<String>,
+        /// Tag keys to remove; repeatable
+        #[arg(long)]
+        remove: Vec<String>,
     },
     /// Search for documents
     Search {
-        /// Search query
+        /// Search query; supports field:value, ranges, "phrases", AND/OR/NOT and (groups), see the README
         query: String,
         /// Maximum number of results
         #[arg(short, long, default_value = "5")]
         limit: usize,
+        /// Match document summaries instead of chunks
+        #[arg(long)]
+        documents: bool,
+        /// Only search documents or chunks matching this filter, e.g. tag:project=alpha or entity:Person=Turing; repeatable
+        #[arg(long, conflicts_with = "documents")]
+        filter: Vec<String>,
+        /// First pick this many documents by summary (or opening chunk), then search only their chunks
+        #[arg(long, value_name = "DOCUMENTS", conflicts_with_all = ["documents", "filter"])]
+        two_stage: Option<usize>,
+        /// Add chunks naming entities up to this many knowledge graph edges from the results
+        #[arg(long, value_name = "HOPS", conflicts_with_all = ["documents", "filter", "two_stage"])]
+        graph_hops: Option<usize>,
+        /// Also count matching documents by file type, collection, language and tag
+        #[arg(long, conflicts_with_all = ["documents", "two_stage", "graph_hops"])]
+        facets: bool,
+        /// Order results by score, date, size or path, optionally with :asc or :desc; ties go by score
+        #[arg(long, conflicts_with_all = ["documents", "two_stage", "graph_hops"])]
+        sort: Option<ResultSort>,
+        /// Search the query as plain text, ignoring its field:value, quote and operator syntax
+        #[arg(long)]
+        raw: bool,
     },
-    /// Evaluate search quality
-    Evaluate {
-        /// Search query
+    /// Save searches under a name and re-run them
+    Saved {
+        #[command(subcommand)]
+        action: SavedAction,
+    },
+    /// Judge a search result relevant to a query, so similar searches rank it higher (or lower)
+    Feedback {
+        /// Query the result was returned for
         query: String,
+        /// Chunk id of the result, as `search --format json` shows it
+        chunk_id: String,
+        /// The result did not answer the query
+        #[arg(long)]
+        irrelevant: bool,
+    },
+    /// Fit a ranking model to the recorded feedback in the current search mode; use it with --learned-ranking
+    TrainRanker,
+    /// (Re)train the IVF index over the stored embeddings, or build the disk index with --disk
+    BuildIndex {
+        /// Write a disk-resident graph index to this file instead; without PATH, to storage.disk_index
+        #[arg(long, value_name = "PATH", num_args = 0..=1)]
+        disk: Option<Option<PathBuf>>,
+        /// IVF lists to train, in place of search.ivf.lists or the square root of the chunk count
+        #[arg(long, conflicts_with = "disk")]
+        lists: Option<usize>,
+    },
+    /// Answer a question using the processed documents and an LLM
+    Ask {
+        /// Question to answer
+        question: String,
+        /// Model used for generation (defaults to gpt-4o-mini, claude-sonnet-4-0 or llama3.2)
+        #[arg(long)]
+        model: Option<String>,
+        /// Chunks retrieved per answer; long-context models such as Claude can take many
+        #[arg(long, default_value = "5")]
+        context_chunks: usize,
+        /// Token budget for retrieved context (defaults to the model's window minus a reserve)
+        #[arg(long)]
+        context_tokens: Option<usize>,
+        /// Decline to answer unless a retrieved chunk scores above this
+        #[arg(long, default_value = "0")]
+        min_score: f32,
+        /// Follow-up searches the LLM may request before answering
+        #[arg(long, default_value = "0")]
+        hops: usize,
+        /// Reuse a cached answer when the question embeds at least this close to a previous one
+        #[arg(long)]
+        cache_threshold: Option<f32>,
+        /// Seconds a cached answer stays valid
+        #[arg(long, requires = "cache_threshold")]
+        cache_ttl: Option<u64>,
+        /// JSON prompt template overriding the default RAG prompt
+        #[arg(long)]
+        prompt_template: Option<PathBuf>,
+    },
+    /// Start an interactive conversation about the processed documents
+    Chat {
+        /// Model used for generation (defaults to gpt-4o-mini, claude-sonnet-4-0 or llama3.2)
+        #[arg(long)]
+        model: Option<String>,
+        /// Chunks retrieved per answer; long-context models such as Claude can take many
+        #[arg(long, default_value = "5")]
+        context_chunks: usize,
+        /// Token budget for retrieved context (defaults to the model's window minus a reserve)
+        #[arg(long)]
+        context_tokens: Option<usize>,
+        /// Decline to answer unless a retrieved chunk scores above this
+        #[arg(long, default_value = "0")]
+        min_score: f32,
+        /// JSON prompt template overriding the default RAG prompt
+        #[arg(long)]
+        prompt_template: Option<PathBuf>,
+    },
+    /// Move a document to the trash, where `undelete` can recover it until `purge` removes it
+    Delete {
+        /// Document ID, as shown by `list`
+        #[arg(required_unless_present = "all")]
+        doc_id: Option<String>,
+        /// Move every document in the index to the trash
+        #[arg(long, conflicts_with = "doc_id")]
+        all: bool,
+        /// Skip the confirmation prompt for --all
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Recover a deleted document from the trash
+    Undelete {
+        doc_id: String,
+    },
+    /// Permanently remove deleted documents, along with their chunks and embeddings
+    Purge {
+        /// Only purge documents deleted at least this many days ago
+        #[arg(long, default_value_t = 0)]
+        older_than_days: u64,
+        /// List what is in the trash instead of purging it
+        #[arg(long)]
+        list: bool,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Report exact and near-duplicate documents, and optionally remove all but one of each group
+    Dedupe {
+        /// Only report the duplicates; this is the default
+        #[arg(long, conflicts_with = "apply")]
+        report: bool,
+        /// Delete every duplicate, keeping the longest document of each group
+        #[arg(long)]
+        apply: bool,
+        /// Report chunks that repeat across documents instead of whole documents
+        #[arg(long, conflicts_with = "apply")]
+        chunks: bool,
+        /// Shingle similarity from which documents count as near duplicates
+        #[arg(long, default_value_t = DEFAULT_NEAR_DUPLICATE_THRESHOLD)]
+        threshold: f64,
+        /// Skip the confirmation prompt for --apply
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Move every document, or just one collection's, to the trash, where `purge` removes them for good
+    Clear {
+        /// Only remove documents tagged collection=NAME
+        #[arg(long, value_name = "NAME")]
+        collection: Option<String>,
+        /// Skip the confirmation prompt
+        #[arg(short, long)]
+        yes: bool,
+    },
+    /// Write the whole index to a portable .ragpack file
+    Export {
+        /// Destination file, e.g. index.ragpack
+        file: PathBuf,
+    },
+    /// Replace the index with the contents of a .ragpack file
+    Import {
+        /// Ragpack written by `export`
+        file: PathBuf,
+        /// Overwrite an index that already has documents
+        #[arg(long)]
+        force: bool,
+    },
+    /// Replace the index with a backup written by `serve` or `watch`
+    Restore {
+        /// Backup file, the name of one in the `[backup]` dir, or `latest`
+        backup: PathBuf,
+    },
+    /// Evaluate search quality for a single query or a whole dataset
+    Evaluate {
+        /// Search query (single-query mode)
+        #[arg(required_unless_present = "dataset")]
+        query: Option<String>,
         /// Expected document IDs (comma-separated)
-        expected: String,
+        #[arg(required_unless_present = "dataset")]
+        expected: Option<String>,
+        /// Evaluation dataset JSON file
+        #[arg(long, conflicts_with_all = ["query", "expected"])]
+        dataset: Option<PathBuf>,
+        /// Cutoffs to evaluate the dataset at (comma-separated)
+        #[arg(long = "k", value_delimiter = ',', default_value = "5")]
+        k: Vec<usize>,
+        /// Write the dataset report as JSON to this file
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Compare the dataset's results on full-precision embeddings and ones quantized to this
+        /// (int8, binary or pq) instead
+        #[arg(long, requires = "dataset")]
+        quantization: Option<EmbeddingQuantization>,
+    },
+    /// List past evaluation runs and how metrics trended
+    EvalHistory,
+    /// Show recorded searches from the query log, newest last
+    Logs {
+        /// Entries to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+        /// Write the whole log to this file as JSON lines instead
+        #[arg(long)]
+        export: Option<PathBuf>,
+        /// Delete the log
+        #[arg(long)]
+        clear: bool,
     },
+    /// Show who ingested, updated and deleted which documents, newest last
+    Audit {
+        /// Entries to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+        /// Only operations by this user or API key
+        #[arg(long = "by", value_name = "ACTOR")]
+        by: Option<String>,
+        /// Only this kind of operation: ingest, update, delete, undelete, purge or import
+        #[arg(long)]
+        action: Option<AuditAction>,
+        /// Only operations on this document
+        #[arg(long)]
+        document: Option<String>,
+        /// Only operations from the last this many days
+        #[arg(long)]
+        days: Option<u64>,
+    },
+    /// Check the configuration, index and embedding provider, and suggest fixes for problems
+    Doctor,
     /// List all processed documents
     List,
+    /// Rebuild chunks and embeddings of every stored document, e.g. after changing strategy or model
+    Reindex {
+        /// Chunking strategy: fixed or paragraph; defaults to the configured one
+        #[arg(long)]
+        chunking: Option<ChunkingKind>,
+        /// Words per chunk for the fixed strategy
+        #[arg(long)]
+        chunk_size: Option<usize>,
+        /// Embedding model to re-embed with, replacing the index's current one
+        #[arg(long)]
+        embed_model: Option<String>,
+    },
+    /// Measure ingest throughput and query latency under the current configuration
+    Bench {
+        /// File with one query per line
+        #[arg(long)]
+        queries: PathBuf,
+        /// Times each query is run
+        #[arg(long, default_value = "10")]
+        iterations: usize,
+        /// Results retrieved per query
+        #[arg(short, long, default_value = "5")]
+        limit: usize,
+        /// Also time the queries against embeddings quantized this way (int8, binary or pq), with the
+        /// share of full-precision results they keep
+        #[arg(long)]
+        quantization: Option<EmbeddingQuantization>,
+    },
+    /// List the keyphrases that describe what a document covers
+    Keywords {
+        /// Document ID, as printed by `process` and `list`
+        doc_id: String,
+        /// Keyphrases to show
+        #[arg(long, default_value = "20")]
+        limit: usize,
+    },
+    /// List the documents most similar to one, for "see also" links
+    Related {
+        /// Document ID, as printed by `process` and `list`
+        doc_id: String,
+        /// Documents to show
+        #[arg(short, long, default_value = "5")]
+        limit: usize,
+    },
+    /// Show a stored document's metadata and content
+    Show {
+        /// Document ID, as printed by `process` and `list`
+        doc_id: String,
+        /// Also list the document's chunks with their IDs and sizes
+        #[arg(long)]
+        chunks: bool,
+        /// Characters of content to preview
+        #[arg(long, default_value = "500")]
+        preview: usize,
+    },
+    /// Serve search and document fetch as Model Context Protocol tools over stdio
+    Mcp {
+        /// Also serve Prometheus metrics at http://ADDR/metrics, e.g. 127.0.0.1:9464
+        #[arg(long, value_name = "ADDR")]
+        metrics_addr: Option<String>,
+    },
+    /// Serve search and document upload over HTTP, guarded by the `[[auth.keys]]` in the config file
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+        /// Load the index and prime the embedding cache before accepting connections
+        #[arg(long)]
+        warm: bool,
+        /// Serve read-only from the index a primary persists at PATH, reloading it when it changes
+        #[arg(long, value_name = "PATH")]
+        replica_of: Option<PathBuf>,
+        /// Seconds between checks of the primary's index
+        #[arg(long, default_value_t = 5, requires = "replica_of")]
+        replica_poll: u64,
+    },
+    /// Show the storage backend, chunking, search and embedding setup, and whether the index is current
+    Info,
     /// Show storage statistics
-    Stats,
+    Stats {
+        /// Break down tokens and estimated cost by operation
+        #[arg(long)]
+        usage: bool,
+        /// Add vocabulary, chunk length and most common term statistics
+        #[arg(long)]
+        corpus: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum SavedAction {
+    /// Save a query, replacing any saved under the same name
+    Add {
+        /// One word, e.g. onboarding
+        name: String,
+        /// Search query, in the same syntax as `search`
+        query: String,
+        /// Only search documents or chunks matching this filter, as for `search`; repeatable
+        #[arg(long)]
+        filter: Vec<String>,
+        /// Results to return when run without --limit
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+    /// List saved searches
+    List,
+    /// Run a saved search
+    Run {
+        name: String,
+        /// Maximum number of results, instead of the saved one
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
+    /// Delete a saved search
+    Remove {
+        name: String,
+    },
+}
+
+/// Print each facet's values, most documents first
+fn print_facets(facets: &Facets) {
+    println!("Facets over {} matching documents:", facets.documents);
+    let tags = facets.tags.iter().map(|(key, counts)| (key.as_str(), counts));
+    let named = [("type", &facets.file_type), ("collection", &facets.collection), ("language", &facets.language)];
+    for (name, counts) in named.into_iter().chain(tags).filter(|(_, counts)| !counts.is_empty()) {
+        let mut counts: Vec<_> = counts.iter().collect();
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+        let values: Vec<String> = counts.iter().map(|(value, count)| format!("{} ({})", value, count)).collect();
+        println!("  {}: {}", name, values.join(", "));
+    }
+}
+
+/// Print the answer followed by its citations as footnotes
+fn print_answer(rag: &SimpleRagSystem, answer: &Answer) -> anyhow::Result<()> {
+    println!("{}", answer.text.trim());
+    if let Some(refusal) = &answer.insufficient_context {
+        match refusal.best_score {
+            Some(score) => println!("  (best retrieval score {:.3}, needed more than {:.3})", score, refusal.min_score),
+            None => println!("  (no indexed chunk matched the question)"),
+        }
+    }
+    if !answer.citations.is_empty() {
+        println!();
+        for citation in &answer.citations {
+            let source = rag
+                .get_document(&citation.document_id)?
+                .map(|doc| doc.metadata.file_path)
+                .unwrap_or_else(|| citation.document_id.clone());
+            println!("  [{}] {} (bytes {}..{}, chunk {})", citation.marker, source, citation.byte_start, citation.byte_end, citation.chunk_id);
+        }
+    }
+    Ok(())
+}
+
+/// Cited passages of an answer with the text they point to
+fn print_sources(rag: &SimpleRagSystem, answer: &Answer) -> anyhow::Result<()> {
+    for citation in &answer.citations {
+        let source = rag
+            .get_document(&citation.document_id)?
+            .map(|doc| doc.metadata.file_path)
+            .unwrap_or_else(|| citation.document_id.clone());
+        println!("  [{}] {} (bytes {}..{})", citation.marker, source, citation.byte_start, citation.byte_end);
+        if let Some(chunk) = rag.get_chunk(&citation.chunk_id)? {
+            let preview: String = chunk.content.chars().take(300).collect();
+            let ellipsis = if preview.len() < chunk.content.len() { "..." } else { "" };
+            println!("      {}{}", preview, ellipsis);
+        }
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stderr; anything but yes declines
+fn confirm(question: &str) -> anyhow::Result<bool> {
+    eprint!("{} [y/N] ", question);
+    let mut reply = String::new();
+    std::io::stdin().lock().read_line(&mut reply)?;
+    Ok(matches!(reply.trim(), "y" | "Y" | "yes"))
+}
+
+/// Smallest, mean and largest chunk, in words
+fn chunk_sizes(chunk_words: &[usize]) -> String {
+    match (chunk_words.iter().min(), chunk_words.iter().max()) {
+        (Some(min), Some(max)) => {
+            let mean = chunk_words.iter().sum::<usize>() / chunk_words.len();
+            format!("{}/{}/{} words min/mean/max", min, mean, max)
+        }
+        _ => "no chunks".to_string(),
+    }
+}
+
+fn print_preview(preview: &IngestPreview) {
+    println!("  Document ID: {} (a real run assigns a new one)", preview.document_id);
+    println!("  Type:        {}", preview.file_type);
+    println!("  Size:        {} bytes, {} words", preview.file_size, preview.word_count);
+    println!("  Chunks:      {} ({})", preview.chunks, chunk_sizes(&preview.chunk_words));
+}
+
+fn print_usage_row(operation: &str, usage: &Usage) {
+    println!(
+        "  {:<8} {:>6} {:>10} {:>10} {:>6} {:>10} {:>10}",
+        operation,
+        usage.completion_calls,
+        usage.input_tokens,
+        usage.output_tokens,
+        usage.embedding_calls,
+        usage.embedding_tokens,
+        format!("${:.4}", usage.cost_usd)
+    );
+}
+
+/// Run every health check, print the results and report whether none failed.
+///
+/// Runs before the normal setup so that a broken config or index is diagnosed rather than fatal.
+fn run_doctor(cli: &Cli) -> anyhow::Result<bool> {
+    let mut diagnostics = Vec::new();
+    let config = match &cli.config {
+        Some(path) => RagConfig::from_file(path),
+        None => RagConfig::load_default(),
+    };
+    let config = match config {
+        Ok(config) => {
+            diagnostics.extend(check_config(&config));
+            config
+        }
+        Err(e) => {
+            diagnostics.push(Diagnostic::error(
+                "config",
+                e.to_string(),
+                "Fix the file, or pass --config with a working one",
+            ));
+            RagConfig::default()
+        }
+    };
+    apply_api_keys(&config.api_keys);
+
+    let rag = match (&cli.index, config.storage.backend) {
+        (None, StorageBackend::Memory) => {
+            diagnostics.push(Diagnostic::ok("storage", "in-memory backend, nothing is persisted"));
+            Some(SimpleRagSystem::new()?)
+        }
+        (index, _) => {
+            let path = index.clone().unwrap_or_else(|| config.index_path());
+            match SimpleRagSystem::open(&path) {
+                Ok(rag) => {
+                    diagnostics.push(check_index_file(&path));
+                    Some(rag)
+                }
+                Err(e) => {
+                    diagnostics.push(Diagnostic::error(
+                        "storage",
+                        format!("{} cannot be loaded: {}", path.display(), e),
+                        "Restore it with `rag-system import` from an export, or move it aside to start fresh",
+                    ));
+                    None
+                }
+            }
+        }
+    };
+
+    let mut rag = match &cli.tenant {
+        Some(tenant) => rag.map(|rag| rag.with_tenant(tenant)),
+        None => rag,
+    };
+    if let Some(rag) = &mut rag {
+        let collected = rag.gc()?;
+        let saved = if collected.is_empty() { Ok(()) } else { rag.persist() };
+        diagnostics.push(check_gc(&collected, saved));
+        let stats = rag.get_stats()?;
+        diagnostics.extend(check_integrity(&rag.check_integrity(), stats.total_documents, stats.total_chunks));
+    }
+
+    // Resolved the way every other command picks its embedding model
+    let index_model = rag.as_ref().and_then(|rag| rag.embedding_model());
+    let model = cli
+        .embedding_model
+        .clone()
+        .or_else(|| config.embedding.as_ref().and_then(|e| e.model.clone()))
+        .or_else(|| index_model.clone())
+        .or_else(|| config.embedding.as_ref().and_then(|e| e.model().ok().map(str::to_string)));
+    let host = cli
+        .embedding_provider
+        .or(config.embedding.as_ref().map(|e| e.provider))
+        .or(cli.provider)
+        .or(config.generation.as_ref().map(|g| g.provider))
+        .unwrap_or(ProviderKind::OpenAi);
+    match model {
+        None => diagnostics.push(Diagnostic::ok("embedding", "no embedding model configured; keyword and BM25 only")),
+        Some(model) => {
+            let settings = config.embedding.as_ref().map(|e| e.config.clone()).unwrap_or_default();
+            match embedding_provider(host, &model, &settings, None) {
+                Ok(embedder) => diagnostics.push(check_embedding_provider(embedder.as_ref(), index_model.as_deref())),
+                Err(e) => diagnostics.push(Diagnostic::error(
+                    "embedding",
+                    format!("cannot set up {} on {:?}: {}", model, host, e),
+                    "Check the API key, or choose another host with --embedding-provider",
+                )),
+            }
+        }
+    }
+
+    let healthy = diagnostics.iter().all(|d| d.status != CheckStatus::Error);
+    if cli.format != OutputFormat::Text {
+        emit_json(cli.format, &diagnostics)?;
+        return Ok(healthy);
+    }
+    for diagnostic in &diagnostics {
+        let mark = match diagnostic.status {
+            CheckStatus::Ok => "✓",
+            CheckStatus::Warning => "!",
+            CheckStatus::Error => "✗",
+        };
+        println!("{} {:<10} {}", mark, diagnostic.check, diagnostic.message);
+        if let Some(hint) = &diagnostic.hint {
+            println!("  {:<10} → {}", "", hint);
+        }
+    }
+    let problems = diagnostics.iter().filter(|d| d.status != CheckStatus::Ok).count();
+    match problems {
+        0 => println!("\nEverything looks good."),
+        n => println!("\n{} problem{} found.", n, if n == 1 { "" } else { "s" }),
+    }
+    Ok(healthy)
 }
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
-    let mut rag = SimpleRagSystem::new()?;
+    init_logging(cli.verbose, cli.quiet);
+    if let Commands::Doctor = cli.command {
+        let healthy = run_doctor(&cli)?;
+        std::process::exit(if healthy { 0 } else { 1 });
+    }
+    let config = match &cli.config {
+        Some(path) => RagConfig::from_file(path)?,
+        None => RagConfig::load_default()?,
+    };
+    apply_api_keys(&config.api_keys);
+
+    let provider = cli
+        .provider
+        .or(config.generation.as_ref().map(|g| g.provider))
+        .unwrap_or(ProviderKind::OpenAi);
+    let completion_model = match &config.generation {
+        Some(generation) if generation.provider == provider => generation.model().to_string(),
+        _ => provider.default_completion_model().to_string(),
+    };
+    let search_mode = cli.search_mode.unwrap_or(config.search.mode);
+
+    let mut rag = match (&cli.index, config.storage.backend) {
+        (None, StorageBackend::Memory) => SimpleRagSystem::new()?,
+        (index, _) => SimpleRagSystem::open(&index.clone().unwrap_or_else(|| config.index_path()))?,
+    }
+    .with_chunking_strategy(config.chunking.strategy());
+    if !config.plugins.is_empty() {
+        eprintln!("Warning: [plugins] names components only programs registering them can use; the CLI ignores it");
+    }
+    if let Some(max_bytes) = config.storage.memory_budget_bytes() {
+        rag = rag.with_memory_budget(max_bytes);
+    }
+    rag = rag.with_embedding_quantization(config.storage.embedding_quantization);
+    if let Some(dimensions) = config.storage.embedding_dimensions {
+        rag = rag.with_embedding_dimensions(dimensions);
+    }
+    if let Some(shards) = config.storage.shards {
+        rag = rag.with_shards(shards);
+    }
+    if let Some(path) = config.storage.disk_index.as_ref().filter(|path| path.exists()) {
+        rag = rag.with_disk_index(path)?;
+    }
+    if let Some(tenant) = &cli.tenant {
+        rag = rag.with_tenant(tenant);
+    }
+    rag.set_search_config(SearchConfig {
+        mode: search_mode,
+        max_chunks_per_document: cli.max_chunks_per_document.or(config.search.max_chunks_per_document),
+        learned_ranking: cli.learned_ranking || config.search.learned_ranking,
+        ..config.search.clone()
+    })?;
+    if let Some(path) = cli.synonyms.as_ref().or(config.search.synonyms.as_ref()) {
+        rag = rag.with_synonyms(SynonymDictionary::load(path)?);
+    }
+    if cli.log_queries || config.query_log.enabled {
+        rag = rag.with_query_log(config.query_log.clone());
+    }
+    if let Some(actor) = &cli.actor {
+        rag = rag.with_actor(actor);
+    }
+    // Only the long-running modes announce their changes
+    if matches!(cli.command, Commands::Serve { .. } | Commands::Watch { .. }) && !config.webhooks.is_empty() {
+        rag = rag.with_webhooks(WebhookNotifier::new(config.webhooks.clone())?);
+    }
+
+    // Keep embedding new chunks once an index has them, but only call the API when needed
+    let reindex_model = match &cli.command {
+        Commands::Reindex { embed_model, .. } => embed_model.clone(),
+        _ => None,
+    };
+    let embedding_model = reindex_model
+        .or_else(|| cli.embedding_model.clone())
+        .or_else(|| config.embedding.as_ref().and_then(|e| e.model.clone()))
+        .or_else(|| rag.embedding_model());
+    let ingests = matches!(
+        cli.command,
+        Commands::Process { dry_run: false, .. } | Commands::Ingest { dry_run: false, .. } | Commands::Reindex { .. }
+    );
+    let embeds_on_ingest = (ingests
+        || matches!(cli.command, Commands::Bench { .. } | Commands::Watch { .. } | Commands::Serve { .. }))
+        && embedding_model.is_some();
+    let caches_answers = matches!(cli.command, Commands::Ask { cache_threshold: Some(_), .. });
+    // Bars would be torn up by interleaved log lines, and -q asks for silence
+    let shows_bars = cli.format == OutputFormat::Text && cli.verbose == 0 && !cli.quiet;
+    let ingest_bars = (ingests && shows_bars)
+        .then(|| IngestBars::new(1, embeds_on_ingest));
+    if embeds_on_ingest || caches_answers || search_mode.uses_embeddings() {
+        let host = cli
+            .embedding_provider
+            .or(config.embedding.as_ref().map(|e| e.provider))
+            .unwrap_or(provider);
+        let model = match embedding_model {
+            Some(model) => model,
+            None => host
+                .default_embedding_model()
+                .ok_or_else(|| anyhow::anyhow!("{:?} has no embedding models; pass --embedding-provider", host))?
+                .to_string(),
+        };
+        let configured = config.embedding.as_ref().map(|e| e.config.clone()).unwrap_or_default();
+        let embedding_config = EmbeddingConfig {
+            batch_size: cli.embed_batch_size.unwrap_or(configured.batch_size),
+            concurrency: cli.embed_concurrency.unwrap_or(configured.concurrency),
+            requests_per_minute: cli.embed_rpm.or(configured.requests_per_minute),
+            ..configured
+        };
+        let progress = match &ingest_bars {
+            Some(bars) if embeds_on_ingest => Some(bars.embedding_callback()),
+            _ => None,
+        };
+        rag = rag.with_embedding_provider(embedding_provider(host, &model, &embedding_config, progress)?);
+    }
+    if cli.hyde || config.search.cross_lingual == CrossLingual::Translate {
+        rag = rag
+            .with_completion_provider(completion_provider(provider, &completion_model)?)
+            .with_hyde(cli.hyde);
+    }
+    // Searches update the embedding cache, so they need persisting; the query log appends to its own file
+    let caches_queries = search_mode.uses_embeddings();
+    let text_output = cli.format == OutputFormat::Text;
+    if let Some(bars) = &ingest_bars {
+        rag = rag.with_ingest_progress(bars.ingest_callback());
+    }
 
     match cli.command {
-        Commands::Process { file } => {
-            println!("Processing document: {}", file);
+        Commands::Process { file, dry_run: true, .. } => {
+            let preview = rag.preview_document(Path::new(&file))?;
+            if !text_output {
+                return emit_json(cli.format, &preview);
+            }
+            println!("Dry run: {} would be stored as", file);
+            print_preview(&preview);
+        }
+        Commands::Process { file, summarize, graph, .. } => {
+            if summarize || graph {
+                rag = rag
+                    .with_completion_provider(completion_provider(provider, &completion_model)?)
+                    .with_summaries(summarize)
+                    .with_knowledge_graph(graph);
+            }
+            if text_output {
+                println!("Processing document: {}", file);
+            }
             let path = Path::new(&file);
 
             if !path.exists() {
@@ -55,65 +1027,1142 @@ fn main() -> anyhow::Result<()> {
                 return Ok(());
             }
 
-            match rag.process_document(path) {
+            let processed = rag.process_document(path);
+            if let Some(bars) = &ingest_bars {
+                bars.finish();
+            }
+            match processed {
                 Ok(doc_id) => {
-                    println!("✓ Document processed successfully");
-                    println!("  Document ID: {}", doc_id);
+                    rag.persist()?;
+                    if text_output {
+                        println!("✓ Document processed successfully");
+                        println!("  Document ID: {}", doc_id);
+                    } else {
+                        emit_json(cli.format, &serde_json::json!({ "document_id": doc_id, "file": file }))?;
+                    }
                 }
                 Err(e) => {
                     eprintln!("Error processing document: {}", e);
                 }
             }
         }
-        Commands::Search { query, limit } => {
-            println!("Searching for: {}", query);
-            match rag.search(&query, limit) {
+        Commands::Ingest { path, include, exclude, summarize, graph, dry_run, workers } => {
+            if (summarize || graph) && !dry_run {
+                rag = rag
+                    .with_completion_provider(completion_provider(provider, &completion_model)?)
+                    .with_summaries(summarize)
+                    .with_knowledge_graph(graph);
+            }
+            let report = rag.ingest_path(&path, &IngestOptions { include, exclude, dry_run, workers });
+            if let Some(bars) = &ingest_bars {
+                bars.finish();
+            }
+            let report = report?;
+            if report.ingested() > 0 {
+                rag.persist()?;
+            }
+            if !text_output {
+                emit_json(cli.format, &report)?;
+            } else {
+                println!("{:<9} {:>7}  FILE", "STATUS", "CHUNKS");
+                for file in &report.files {
+                    let (status, chunks, note) = match &file.outcome {
+                        IngestOutcome::Ingested { chunks, .. } => ("ingested", chunks.to_string(), String::new()),
+                        IngestOutcome::WouldIngest(preview) => {
+                            let sizes = chunk_sizes(&preview.chunk_words);
+                            let note = format!(" ({}, {} bytes, {})", preview.file_type, preview.file_size, sizes);
+                            ("would", preview.chunks.to_string(), note)
+                        }
+                        IngestOutcome::Skipped { reason } => ("skipped", "-".to_string(), format!(" ({})", reason)),
+                        IngestOutcome::Failed { error } => ("failed", "-".to_string(), format!(" ({})", error)),
+                    };
+                    println!("{:<9} {:>7}  {}{}", status, chunks, file.path.display(), note);
+                }
+                if dry_run {
+                    println!(
+                        "\nDry run: {} would be ingested, {} skipped, {} failed, {} chunks would be created",
+                        report.would_ingest(),
+                        report.skipped(),
+                        report.failed(),
+                        report.total_chunks()
+                    );
+                } else {
+                    println!(
+                        "\n{} ingested, {} skipped, {} failed, {} chunks created",
+                        report.ingested(),
+                        report.skipped(),
+                        report.failed(),
+                        report.total_chunks()
+                    );
+                }
+            }
+        }
+        Commands::Watch { dir, include, exclude, interval } => {
+            let options = IngestOptions { include, exclude, dry_run: false, workers: 1 };
+            let mut watcher = DirectoryWatcher::new(&dir, options)?;
+            let mut backups = config.backup.is_enabled().then(|| BackupScheduler::new(&config.backup)).transpose()?;
+            if text_output {
+                println!("Watching {} (Ctrl+C to stop)", watcher.root().display());
+            }
+            loop {
+                let events = watcher.poll(&mut rag)?;
+                if !events.is_empty() {
+                    rag.persist()?;
+                }
+                if let Some(scheduler) = &mut backups {
+                    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+                    match scheduler.run_if_due(&rag, now) {
+                        Ok(Some(path)) if text_output => println!("Backed up to {}", path.display()),
+                        Ok(_) => {}
+                        Err(e) => eprintln!("Warning: backup failed: {}", e),
+                    }
+                }
+                for event in &events {
+                    if !text_output {
+                        // One event per line in both JSON modes, since the stream never ends
+                        println!("{}", serde_json::to_string(event)?);
+                        continue;
+                    }
+                    match event {
+                        WatchEvent::Added { path, document_id, chunks } => {
+                            println!("+ {} ({}, {} chunks)", path.display(), document_id, chunks)
+                        }
+                        WatchEvent::Updated { path, document_id, chunks } => {
+                            println!("~ {} ({}, {} chunks)", path.display(), document_id, chunks)
+                        }
+                        WatchEvent::Removed { path, document_id } => println!("- {} ({})", path.display(), document_id),
+                        WatchEvent::Failed { path, error } => println!("! {} ({})", path.display(), error),
+                    }
+                }
+                std::thread::sleep(std::time::Duration::from_secs(interval.max(1)));
+            }
+        }
+        Commands::QueryBatch { queries, limit, output } => {
+            let queries = load_queries(&queries)?;
+            let batch = rag.batch_search(&queries, limit);
+            let mut lines = String::new();
+            for result in &batch {
+                lines.push_str(&serde_json::to_string(result)?);
+                lines.push('\n');
+            }
+            match &output {
+                Some(output) => std::fs::write(output, lines)?,
+                None => print!("{}", lines),
+            }
+            let failed = batch.iter().filter(|r| r.error.is_some()).count();
+            if failed > 0 {
+                eprintln!("{} of {} queries failed; see their \"error\" field", failed, batch.len());
+            }
+            if let (Some(output), true) = (&output, text_output) {
+                println!("✓ Wrote results for {} queries to {}", batch.len(), output.display());
+            }
+            if caches_queries {
+                rag.persist()?;
+            }
+        }
+        Commands::Tag { doc_id, tags, remove } => {
+            let changes = tags.iter().map(|tag| parse_tag(tag)).collect::<anyhow::Result<Vec<_>>>()?;
+            for (key, value) in &changes {
+                rag.set_tag(&doc_id, key, value)?;
+            }
+            for key in &remove {
+                rag.remove_tag(&doc_id, key)?;
+            }
+            let current = rag.tags(&doc_id)?;
+            if !changes.is_empty() || !remove.is_empty() {
+                rag.persist()?;
+            }
+            if !text_output {
+                return emit_json(cli.format, &current);
+            }
+            if current.is_empty() {
+                println!("{} has no tags", doc_id);
+            }
+            for (key, value) in &current {
+                println!("{}={}", key, value);
+            }
+        }
+        Commands::Search { query, limit, documents, filter, two_stage, graph_hops, facets, sort, raw } => {
+            if text_output {
+                println!("Searching for: {}", query);
+            }
+            let filter = DocumentFilter::parse(&filter)?;
+            let mut facet_counts = None;
+            let results = if documents {
+                rag.search_documents(&query, limit)
+            } else if let Some(top_documents) = two_stage {
+                rag.search_two_stage(&query, top_documents, limit)
+            } else if let Some(hops) = graph_hops {
+                rag.search_graph(&query, limit, hops)
+            } else {
+                let mut parsed = if raw { TypedQuery::raw(&query) } else { TypedQuery::parse(&query)? };
+                if !filter.is_empty() {
+                    parsed = parsed.and(Query::from(&filter));
+                }
+                let sort = sort.unwrap_or_default();
+                if facets {
+                    rag.search_faceted_with(&parsed, limit, sort, None).map(|faceted| {
+                        facet_counts = Some(faceted.facets);
+                        faceted.results
+                    })
+                } else {
+                    rag.search_sorted(&parsed, limit, sort)
+                }
+            };
+            match results {
+                Ok(results) if !text_output => match facet_counts {
+                    Some(facets) => emit_json(cli.format, &FacetedResults { results, facets })?,
+                    None => emit_json(cli.format, &results)?,
+                },
                 Ok(results) => {
                     println!("Found {} results:", results.len());
                     for (i, result) in results.iter().enumerate() {
                         println!("  {}. [Score: {:.3}] {}", i + 1, result.score, result.content);
                     }
+                    if let Some(facets) = facet_counts {
+                        print_facets(&facets);
+                    }
                 }
                 Err(e) => {
                     eprintln!("Error searching: {}", e);
                 }
             }
+            if caches_queries {
+                rag.persist()?;
+            }
+        }
+        Commands::Saved { action: SavedAction::Add { name, query, filter, limit } } => {
+            let mut search = SavedSearch::new(&name, &query).with_filter(DocumentFilter::parse(&filter)?);
+            if let Some(limit) = limit {
+                search = search.with_limit(limit);
+            }
+            let replaced = rag.save_search(search)?;
+            rag.persist()?;
+            if text_output {
+                match replaced {
+                    Some(old) => println!("Replaced saved search {} (was \"{}\")", name, old.query),
+                    None => println!("Saved search {}", name),
+                }
+            }
+        }
+        Commands::Saved { action: SavedAction::List } => {
+            let saved = rag.saved_searches();
+            if !text_output {
+                return emit_json(cli.format, &saved);
+            }
+            if saved.is_empty() {
+                println!("No saved searches");
+            }
+            for search in saved {
+                // With its filters written in the query syntax
+                let query = search.parsed().map_or_else(|_| search.query.clone(), |query| query.to_string());
+                let limit = search.limit.map_or(String::new(), |limit| format!("  (limit {})", limit));
+                println!("  {:<20} {}{}", search.name, query, limit);
+            }
+        }
+        Commands::Saved { action: SavedAction::Run { name, limit } } => {
+            let results = rag.run_saved_search(&name, limit)?;
+            if !text_output {
+                emit_json(cli.format, &results)?;
+            } else {
+                println!("Found {} results:", results.len());
+                for (i, result) in results.iter().enumerate() {
+                    println!("  {}. [Score: {:.3}] {}", i + 1, result.score, result.content);
+                }
+            }
+            if caches_queries {
+                rag.persist()?;
+            }
+        }
+        Commands::Saved { action: SavedAction::Remove { name } } => {
+            rag.delete_saved_search(&name)?;
+            rag.persist()?;
+            if text_output {
+                println!("Deleted saved search {}", name);
+            }
+        }
+        Commands::Feedback { query, chunk_id, irrelevant } => {
+            rag.record_feedback(&query, &chunk_id, !irrelevant)?;
+            rag.persist()?;
+            if text_output {
+                let judgement = if irrelevant { "irrelevant" } else { "relevant" };
+                println!("Recorded {} as {} to '{}'", chunk_id, judgement, query);
+            }
+        }
+        Commands::BuildIndex { disk: None, lists } => {
+            let index = rag.train_ivf_index(lists)?;
+            rag.persist()?;
+            if text_output {
+                println!("Trained {} IVF lists over {} vectors", index.lists(), index.len());
+                if !config.search.may_use(VectorIndex::Ivf) {
+                    println!("Searches only use it with search.vector_index = \"ivf\", or for collections set to ivf");
+                }
+            }
+        }
+        Commands::BuildIndex { disk: Some(disk), .. } => {
+            let path = disk
+                .or_else(|| config.storage.disk_index.clone())
+                .ok_or_else(|| anyhow::anyhow!("Pass --disk PATH or set storage.disk_index in the config"))?;
+            let index = rag.build_disk_index(&path)?;
+            rag.persist()?;
+            if text_output {
+                println!(
+                    "Indexed {} vectors of {} dimensions to {} ({} bytes kept in memory)",
+                    index.len(),
+                    index.dimensions(),
+                    path.display(),
+                    index.memory_bytes()
+                );
+            }
+        }
+        Commands::TrainRanker => {
+            let report = rag.train_ranking_model()?;
+            rag.persist()?;
+            if !text_output {
+                return emit_json(cli.format, &report);
+            }
+            println!(
+                "Trained on {} judgements ({} relevant), {:.0}% classified correctly",
+                report.examples,
+                report.relevant,
+                report.accuracy * 100.0
+            );
+            for (name, weight) in ["keyword", "vector", "recency", "length"].iter().zip(report.model.weights) {
+                println!("  {:<8} {:>7.3}", name, weight);
+            }
+            println!("  {:<8} {:>7.3}", "bias", report.model.bias);
+        }
+        Commands::Ask { question, model, prompt_template, context_chunks, context_tokens, min_score, hops, cache_threshold, cache_ttl } => {
+            let model = model.unwrap_or_else(|| completion_model.clone());
+            let mut rag = rag
+                .with_completion_provider(completion_provider(provider, &model)?)
+                .with_context_chunks(context_chunks)
+                .with_min_retrieval_score(min_score);
+            if let Some(tokens) = context_tokens {
+                rag = rag.with_context_tokens(tokens);
+            }
+            if let Some(threshold) = cache_threshold {
+                rag = rag.with_answer_cache(AnswerCacheConfig {
+                    similarity_threshold: threshold,
+                    ttl_secs: cache_ttl,
+                    ..AnswerCacheConfig::default()
+                });
+            }
+            if let Some(path) = prompt_template {
+                rag.set_prompt_template(PromptTemplate::from_file(&path)?);
+            }
+            let answer = if hops > 0 {
+                rag.ask_multi_hop(&question, hops)
+            } else {
+                rag.ask(&question).map(|answer| MultiHopAnswer { answer, hops: Vec::new() })
+            };
+            match answer {
+                Ok(result) if !text_output => emit_json(cli.format, &result)?,
+                Ok(result) => {
+                    for hop in &result.hops {
+                        println!("  searched: {} (+{} chunks)", hop.query, hop.chunk_ids.len());
+                    }
+                    print_answer(&rag, &result.answer)?
+                }
+                Err(e) => eprintln!("Error answering question: {}", e),
+            }
+            // Keeps cached answers and embeddings along with the usage totals
+            rag.persist()?;
         }
-        Commands::Evaluate { query, expected } => {
-            let expected_docs: Vec<String> = expected.split(',').map(|s| s.trim().to_string()).collect();
-            println!("Evaluating search for: {}", query);
-            println!("Expected documents: {:?}", expected_docs);
+        Commands::Chat { model, prompt_template, context_chunks, context_tokens, min_score } => {
+            let model = model.unwrap_or_else(|| completion_model.clone());
+            let mut rag = rag
+                .with_completion_provider(completion_provider(provider, &model)?)
+                .with_context_chunks(context_chunks)
+                .with_min_retrieval_score(min_score);
+            if let Some(tokens) = context_tokens {
+                rag = rag.with_context_tokens(tokens);
+            }
+            if let Some(path) = prompt_template {
+                rag.set_prompt_template(PromptTemplate::from_file(&path)?);
+            }
+            let mut session = rag.start_chat();
+            if text_output {
+                println!("Chatting about {} documents. Type /help for commands, /exit to quit.", rag.list_documents()?.len());
+            }
+
+            let stdin = std::io::stdin();
+            loop {
+                if text_output {
+                    print!("> ");
+                    std::io::stdout().flush()?;
+                }
+                let mut line = String::new();
+                if stdin.lock().read_line(&mut line)? == 0 {
+                    break;
+                }
+                let question = line.trim();
+                if question.is_empty() {
+                    continue;
+                }
+                if question == "exit" || question == "quit" {
+                    break;
+                }
+                if let Some(command) = question.strip_prefix('/') {
+                    match command.trim() {
+                        "exit" | "quit" => break,
+                        "reset" => {
+                            session.reset();
+                            println!("Conversation cleared.");
+                        }
+                        "sources" => match session.last_turn() {
+                            Some(turn) if !turn.answer.citations.is_empty() => print_sources(&rag, &turn.answer)?,
+                            Some(_) => println!("The last answer cited no sources."),
+                            None => println!("Nothing asked yet."),
+                        },
+                        "history" => {
+                            for (i, turn) in session.history().iter().enumerate() {
+                                println!("  {}. {}", i + 1, turn.question);
+                                if turn.retrieval_query != turn.question {
+                                    println!("     searched: {}", turn.retrieval_query);
+                                }
+                            }
+                        }
+                        "usage" => {
+                            let usage = session.usage();
+                            println!(
+                                "  {} LLM calls, {} tokens in, {} out, ~${:.4}",
+                                usage.completion_calls, usage.input_tokens, usage.output_tokens, usage.cost_usd
+                            );
+                        }
+                        "help" => {
+                            println!("  /sources  passages cited by the last answer");
+                            println!("  /history  questions asked so far");
+                            println!("  /usage    tokens and estimated cost of this conversation");
+                            println!("  /reset    forget the conversation");
+                            println!("  /exit     quit");
+                        }
+                        other => println!("Unknown command /{}; try /help", other),
+                    }
+                    continue;
+                }
+
+                match session.send(question) {
+                    // Each turn is its own value, so pretty JSON would not stream; always one line
+                    Ok(turn) if !text_output => emit_json(OutputFormat::Ndjson, turn)?,
+                    Ok(turn) => {
+                        print_answer(&rag, &turn.answer)?;
+                        println!();
+                    }
+                    Err(e) => eprintln!("Error answering question: {}", e),
+                }
+            }
+            rag.persist()?;
+        }
+        Commands::Delete { doc_id, all, yes } => {
+            let (documents, chunks) = if all {
+                let count = rag.list_documents()?.len();
+                if !yes && !confirm(&format!("Delete all {} documents?", count))? {
+                    eprintln!("Aborted");
+                    return Ok(());
+                }
+                rag.delete_all_documents()?
+            } else {
+                let doc_id = doc_id.as_deref().unwrap_or_default();
+                match rag.delete_document(doc_id) {
+                    Ok(chunks) => (1, chunks),
+                    Err(e) => {
+                        eprintln!("Error deleting document: {}", e);
+                        return Ok(());
+                    }
+                }
+            };
+            rag.persist()?;
+            if text_output {
+                println!("✓ Deleted {} documents and {} chunks", documents, chunks);
+                if let Some(doc_id) = doc_id {
+                    println!("  `undelete {}` recovers it until the trash is purged", doc_id);
+                }
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": documents, "chunks": chunks }))?;
+            }
+        }
+        Commands::Undelete { doc_id } => {
+            rag.undelete_document(&doc_id)?;
+            rag.persist()?;
+            if text_output {
+                println!("✓ Recovered {}", doc_id);
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "document": doc_id }))?;
+            }
+        }
+        Commands::Purge { list: true, .. } => {
+            let trashed = rag.trashed_documents();
+            if !text_output {
+                return emit_json(cli.format, &trashed);
+            }
+            if trashed.is_empty() {
+                println!("The trash is empty");
+            }
+            for doc in &trashed {
+                println!("{}  {}  deleted {} UTC, {} chunks", doc.id, doc.file_path, format_timestamp(doc.deleted_at), doc.chunks);
+            }
+        }
+        Commands::Purge { older_than_days, yes, .. } => {
+            let older_than = std::time::Duration::from_secs(older_than_days * 24 * 60 * 60);
+            let count = rag.trashed_documents().len();
+            if count > 0 && !yes && !confirm(&format!("Permanently delete up to {} trashed documents?", count))? {
+                eprintln!("Aborted");
+                return Ok(());
+            }
+            let purged = rag.purge(older_than)?;
+            rag.persist()?;
+            let chunks: usize = purged.iter().map(|doc| doc.chunks).sum();
+            if text_output {
+                println!("✓ Purged {} documents and {} chunks", purged.len(), chunks);
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": purged.len(), "chunks": chunks }))?;
+            }
+        }
+        Commands::Dedupe { chunks: true, threshold, .. } => {
+            let groups = rag.duplicate_chunks(threshold)?;
+            if !text_output {
+                return emit_json(cli.format, &groups);
+            }
+            for group in &groups {
+                println!("similarity {:.2}", group.similarity);
+                for (chunk_id, doc_id) in group.chunks.iter().zip(&group.documents) {
+                    let source = rag.get_document(doc_id)?.map_or(doc_id.clone(), |doc| doc.metadata.file_path);
+                    println!("  {} ({})", source, chunk_id);
+                }
+            }
+            println!("{} groups of repeated chunks", groups.len());
+        }
+        Commands::Dedupe { report: _, apply, threshold, yes, .. } => {
+            let report = rag.duplicate_report(threshold)?;
+            let removable = report.removable();
+            if text_output {
+                for group in &report.groups {
+                    let kind = match group.kind {
+                        DuplicateKind::Exact => "exact".to_string(),
+                        DuplicateKind::Near => format!("near, similarity {:.2}", group.similarity),
+                    };
+                    let source = |doc_id: &str| -> anyhow::Result<String> {
+                        Ok(rag.get_document(doc_id)?.map_or(doc_id.to_string(), |doc| doc.metadata.file_path))
+                    };
+                    println!("{} ({})", source(group.keep())?, kind);
+                    for duplicate in group.duplicates() {
+                        println!("  = {}", source(duplicate)?);
+                    }
+                }
+                println!("{} duplicates of {} documents", removable.len(), report.documents);
+            } else if !apply {
+                return emit_json(cli.format, &report);
+            }
+            if !apply || removable.is_empty() {
+                return Ok(());
+            }
+            if !yes && !confirm(&format!("Delete {} duplicate documents?", removable.len()))? {
+                eprintln!("Aborted");
+                return Ok(());
+            }
+            let mut chunks = 0;
+            for doc_id in &removable {
+                chunks += rag.delete_document(doc_id)?;
+            }
+            rag.persist()?;
+            if text_output {
+                println!("✓ Deleted {} documents and {} chunks", removable.len(), chunks);
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": removable.len(), "chunks": chunks, "report": report }))?;
+            }
+        }
+        Commands::Clear { collection, yes } => {
+            let (count, scope) = match &collection {
+                Some(name) => (
+                    rag.documents_matching(&DocumentFilter::collection(name.as_str()))?.len(),
+                    format!("collection '{}'", name),
+                ),
+                None => (rag.list_documents()?.len(), "the index".to_string()),
+            };
+            if count > 0 && !yes && !confirm(&format!("Remove {} documents from {}?", count, scope))? {
+                eprintln!("Aborted");
+                return Ok(());
+            }
+            let (documents, chunks) = match &collection {
+                Some(name) => rag.delete_collection(name)?,
+                None => rag.delete_all_documents()?,
+            };
+            rag.persist()?;
+            if text_output {
+                println!("✓ Cleared {}: removed {} documents and {} chunks", scope, documents, chunks);
+            } else {
+                emit_json(
+                    cli.format,
+                    &serde_json::json!({ "collection": collection, "documents": documents, "chunks": chunks }),
+                )?;
+            }
+        }
+        Commands::Export { file } => {
+            rag.export(&file)?;
+            let documents = rag.list_documents()?.len();
+            if text_output {
+                println!("✓ Exported {} documents to {}", documents, file.display());
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": documents, "file": file }))?;
+            }
+        }
+        Commands::Import { file, force } => {
+            let existing = rag.list_documents()?.len();
+            if existing > 0 && !force {
+                eprintln!("Error: the index already has {} documents; pass --force to replace them", existing);
+                return Ok(());
+            }
+            let documents = rag.import(&file)?;
+            rag.persist()?;
+            if text_output {
+                println!("✓ Imported {} documents from {}", documents, file.display());
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": documents, "file": file }))?;
+            }
+        }
+        Commands::Restore { backup } => {
+            let path = config.backup.resolve(&backup)?;
+            let documents = rag.import(&path)?;
+            rag.persist()?;
+            if text_output {
+                println!("✓ Restored {} documents from {}", documents, path.display());
+            } else {
+                emit_json(cli.format, &serde_json::json!({ "documents": documents, "file": path }))?;
+            }
+        }
+        Commands::Evaluate { dataset: Some(dataset_path), k, output, quantization: Some(quantization), .. } => {
+            let dataset = EvaluationDataset::from_file(&dataset_path)?;
+            let reports = k
+                .into_iter()
+                .map(|k| rag.evaluate_quantization(&dataset, quantization, k))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            rag.persist()?;
+            if let Some(output) = output {
+                std::fs::write(&output, serde_json::to_string_pretty(&reports)?)?;
+                if text_output {
+                    println!("✓ Report written to {}", output.display());
+                }
+            }
+            if !text_output {
+                emit_json(cli.format, &reports)?;
+                return Ok(());
+            }
+
+            if let Some(report) = reports.first() {
+                println!(
+                    "Embeddings: {} KiB at full precision, {} KiB quantized",
+                    report.full_bytes / 1024,
+                    report.quantized_bytes / 1024
+                );
+            }
+            println!("{:>4}  {:>12}  {:>12}  {:>12}", "k", "Recall full", "Recall quant", "Overlap");
+            for report in &reports {
+                println!(
+                    "{:>4}  {:>12.3}  {:>12.3}  {:>12.3}",
+                    report.k, report.full.mean.recall, report.quantized.mean.recall, report.result_overlap
+                );
+            }
+        }
+        Commands::Evaluate { dataset: Some(dataset_path), k, output, .. } => {
+            let dataset = EvaluationDataset::from_file(&dataset_path)?;
+            if text_output {
+                println!("Evaluating {} queries from {}", dataset.queries.len(), dataset_path.display());
+            }
+
+            let mut evaluations = Vec::new();
+            for k in k {
+                evaluations.push(rag.evaluate_dataset(&dataset, k)?);
+            }
+            rag.persist()?;
+            if let Some(output) = output {
+                std::fs::write(&output, serde_json::to_string_pretty(&evaluations)?)?;
+                if text_output {
+                    println!("✓ Report written to {}", output.display());
+                }
+            }
+            if !text_output {
+                emit_json(cli.format, &evaluations)?;
+                return Ok(());
+            }
+
+            println!("{:>4}  {:>9}  {:>9}  {:>9}  {:>9}  {:>9}", "k", "Relevance", "Precision", "Recall", "F1", "Hit Rate");
+            for evaluation in &evaluations {
+                let m = &evaluation.mean;
+                println!(
+                    "{:>4}  {:>9.3}  {:>9.3}  {:>9.3}  {:>9.3}  {:>9.3}",
+                    evaluation.k, m.relevance, m.precision, m.recall, m.f1_score, m.hit_rate
+                );
+            }
+        }
+        Commands::Evaluate { query, expected, .. } => {
+            let query = query.unwrap_or_default();
+            let expected_docs: Vec<String> = expected.unwrap_or_default().split(',').map(|s| s.trim().to_string()).collect();
+            if text_output {
+                println!("Evaluating search for: {}", query);
+                println!("Expected documents: {:?}", expected_docs);
+            }
 
             match rag.evaluate_search(&query, &expected_docs) {
+                Ok(metrics) if !text_output => {
+                    rag.persist()?;
+                    emit_json(cli.format, &metrics)?;
+                }
                 Ok(metrics) => {
+                    rag.persist()?;
                     println!("Evaluation Results:");
                     println!("  Relevance: {:.3}", metrics.relevance);
                     println!("  Precision: {:.3}", metrics.precision);
                     println!("  Recall: {:.3}", metrics.recall);
                     println!("  F1 Score: {:.3}", metrics.f1_score);
+                    println!("  Hit Rate: {:.3}", metrics.hit_rate);
                 }
                 Err(e) => {
                     eprintln!("Error evaluating: {}", e);
                 }
             }
         }
+        Commands::Logs { limit, export, clear } => {
+            if clear {
+                let removed = rag.clear_query_log()?;
+                rag.persist()?;
+                println!("✓ Removed {} query log entries", removed);
+                return Ok(());
+            }
+            if let Some(path) = export {
+                let count = rag.export_query_log(std::io::BufWriter::new(std::fs::File::create(&path)?))?;
+                println!("✓ Exported {} queries to {}", count, path.display());
+                return Ok(());
+            }
+            let entries = rag.query_log()?;
+            let shown = &entries[entries.len().saturating_sub(limit)..];
+            if !text_output {
+                return emit_json(cli.format, &shown);
+            }
+            if entries.is_empty() {
+                println!("The query log is empty; enable it with --log-queries or [query_log] enabled = true");
+            }
+            for entry in shown {
+                let outcome = match &entry.error {
+                    Some(error) => format!("error: {}", error),
+                    None => format!("{} results", entry.result_ids.len()),
+                };
+                println!(
+                    "{} {:>8.1}ms {:?} {} {:?}",
+                    format_timestamp(entry.timestamp),
+                    entry.latency_ms,
+                    entry.mode,
+                    outcome,
+                    entry.query
+                );
+            }
+        }
+        Commands::Audit { limit, by, action, document, days } => {
+            let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+            let filter = AuditFilter {
+                actor: by,
+                action,
+                document_id: document,
+                since: days.map(|days| now.saturating_sub(days * 24 * 60 * 60)),
+            };
+            let entries = rag.audit_log(&filter);
+            let shown = &entries[entries.len().saturating_sub(limit)..];
+            if !text_output {
+                return emit_json(cli.format, &shown);
+            }
+            if entries.is_empty() {
+                println!("No audited operations match");
+            }
+            for entry in shown {
+                let outcome = match &entry.error {
+                    Some(error) => format!("failed: {}", error),
+                    None => "ok".to_string(),
+                };
+                println!(
+                    "{} UTC  {:<8} {:<8} {}  {}  {}",
+                    format_timestamp(entry.timestamp),
+                    entry.action,
+                    entry.actor,
+                    entry.document_id.as_deref().unwrap_or("-"),
+                    entry.source.as_deref().unwrap_or("-"),
+                    outcome
+                );
+            }
+        }
+        Commands::EvalHistory => {
+            let runs = rag.evaluation_history()?;
+            if !text_output {
+                return emit_json(cli.format, &runs);
+            }
+            println!("Evaluation History ({} runs):", runs.len());
+            let mut previous_f1: Option<f32> = None;
+            for run in &runs {
+                let trend = match previous_f1 {
+                    Some(prev) if run.metrics.f1_score > prev + f32::EPSILON => "↑",
+                    Some(prev) if run.metrics.f1_score < prev - f32::EPSILON => "↓",
+                    Some(_) => "=",
+                    None => " ",
+                };
+                println!(
+                    "  {} [{}] queries={} k={} P={:.3} R={:.3} F1={:.3} Hit@k={:.3} {}",
+                    format_timestamp(run.timestamp),
+                    run.config_fingerprint,
+                    run.query_count,
+                    run.k,
+                    run.metrics.precision,
+                    run.metrics.recall,
+                    run.metrics.f1_score,
+                    run.metrics.hit_rate,
+                    trend
+                );
+                previous_f1 = Some(run.metrics.f1_score);
+            }
+            if let (Some(first), Some(last)) = (runs.first(), runs.last()) {
+                println!("  F1 trend: {:.3} -> {:.3} ({:+.3})", first.metrics.f1_score, last.metrics.f1_score, last.metrics.f1_score - first.metrics.f1_score);
+            }
+        }
+        Commands::Doctor => unreachable!("doctor runs before the index is opened"),
         Commands::List => {
             let docs = rag.list_documents()?;
+            if !text_output {
+                let mut listed = Vec::new();
+                for doc_id in &docs {
+                    if let Some(doc) = rag.get_document(doc_id)? {
+                        listed.push(serde_json::json!({ "id": doc.id, "metadata": doc.metadata }));
+                    }
+                }
+                return emit_json(cli.format, &listed);
+            }
             println!("Processed Documents ({}):", docs.len());
             for doc_id in docs {
                 if let Some(doc) = rag.get_document(&doc_id)? {
                     println!("  - {} ({}, {} words)", doc_id, doc.metadata.file_type, doc.metadata.word_count);
+                    if !doc.metadata.tags.is_empty() {
+                        let tags: Vec<String> = doc.metadata.tags.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+                        println!("      tags: {}", tags.join(", "));
+                    }
+                    if let Some(summary) = &doc.metadata.summary {
+                        println!("      {}", summary);
+                    }
                 }
             }
         }
-        Commands::Stats => {
+        Commands::Reindex { chunking, chunk_size, .. } => {
+            let chunking = ChunkingConfig {
+                strategy: chunking.unwrap_or(config.chunking.strategy),
+                size: chunk_size.unwrap_or(config.chunking.size),
+            };
+            rag = rag.with_chunking_strategy(chunking.strategy());
+            let report = rag.reindex();
+            if let Some(bars) = &ingest_bars {
+                bars.finish();
+            }
+            let report = report?;
+            rag.persist()?;
+            if !text_output {
+                return emit_json(cli.format, &report);
+            }
+            let model = |m: &Option<String>| m.clone().unwrap_or_else(|| "-".to_string());
+            println!("Reindexed {} documents", report.documents);
+            println!("  {:<16} {:>12} {:>12}", "", "BEFORE", "AFTER");
+            println!("  {:<16} {:>12} {:>12}", "Chunks", report.chunks_before, report.chunks_after);
+            println!("  {:<16} {:>12} {:>12}", "Embeddings", report.embeddings_before, report.embeddings_after);
+            println!(
+                "  {:<16} {:>12} {:>12}",
+                "Embedding model",
+                model(&report.embedding_model_before),
+                model(&report.embedding_model_after)
+            );
+        }
+        Commands::Bench { queries, iterations, limit, quantization } => {
+            let queries = load_queries(&queries)?;
+            if text_output {
+                println!("Benchmarking {} queries x {} iterations...", queries.len(), iterations);
+            }
+            let mut report = rag.benchmark(&queries, iterations, limit)?;
+            if let Some(quantization) = quantization {
+                report.quantized = Some(rag.benchmark_quantization(&queries, iterations, limit, quantization)?);
+            }
+            if !text_output {
+                return emit_json(cli.format, &report);
+            }
+            let setup = &report.setup;
+            println!("setup.search_mode          {}", setup.search_mode);
+            println!("setup.search_fingerprint   {}", setup.search_fingerprint);
+            println!("setup.embedding_model      {}", setup.embedding_model.as_deref().unwrap_or("-"));
+            println!("setup.limit                {}", setup.limit);
+            let ingest = &report.ingest;
+            println!("ingest.documents           {}", ingest.documents);
+            println!("ingest.chunks              {}", ingest.chunks);
+            println!("ingest.seconds             {:.3}", ingest.seconds);
+            println!("ingest.documents_per_sec   {:.1}", ingest.documents_per_sec);
+            println!("ingest.chunks_per_sec      {:.1}", ingest.chunks_per_sec);
+            println!("ingest.mb_per_sec          {:.3}", ingest.mb_per_sec);
+            let query = &report.query;
+            println!("query.samples              {}", query.samples);
+            println!("query.mean_ms              {:.3}", query.mean_ms);
+            println!("query.min_ms               {:.3}", query.min_ms);
+            println!("query.p50_ms               {:.3}", query.p50_ms);
+            println!("query.p95_ms               {:.3}", query.p95_ms);
+            println!("query.p99_ms               {:.3}", query.p99_ms);
+            println!("query.max_ms               {:.3}", query.max_ms);
+            if let Some(quantized) = &report.quantized {
+                println!("quantized.quantization     {:?}", quantized.quantization);
+                println!("quantized.recall           {:.3}", quantized.recall);
+                println!("quantized.p50_ms           {:.3}", quantized.query.p50_ms);
+                println!("quantized.p95_ms           {:.3}", quantized.query.p95_ms);
+                println!("quantized.full_bytes       {}", quantized.full_bytes);
+                println!("quantized.bytes            {}", quantized.quantized_bytes);
+            }
+        }
+        Commands::Keywords { doc_id, limit } => {
+            let phrases = rag.keyphrases(&doc_id, limit)?;
+            if !text_output {
+                return emit_json(cli.format, &phrases);
+            }
+            if phrases.is_empty() {
+                println!("{} has no keyphrases; reindex to extract them", doc_id);
+            }
+            for phrase in &phrases {
+                println!("{:>4}  {}", phrase.chunks, phrase.phrase);
+            }
+        }
+        Commands::Related { doc_id, limit } => {
+            let related = rag.related_documents(&doc_id, limit)?;
+            if !text_output {
+                return emit_json(cli.format, &related);
+            }
+            if related.is_empty() {
+                println!("No documents share content with {}", doc_id);
+            }
+            for doc in &related {
+                println!("  [Score: {:.3}] {}  {}", doc.score, doc.document_id, doc.file_path);
+            }
+        }
+        Commands::Show { doc_id, chunks, preview } => {
+            let doc = rag
+                .get_document(&doc_id)?
+                .ok_or_else(|| anyhow::anyhow!("No document with ID '{}'", doc_id))?;
+            let doc_chunks = rag.document_chunks(&doc_id)?;
+            if !text_output {
+                let mut shown = serde_json::json!({
+                    "id": doc.id,
+                    "metadata": doc.metadata,
+                    "content": doc.content,
+                    "chunk_count": doc_chunks.len(),
+                });
+                if chunks {
+                    shown["chunks"] = serde_json::to_value(&doc_chunks)?;
+                }
+                return emit_json(cli.format, &shown);
+            }
+            println!("Document {}", doc.id);
+            println!("  File:    {}", doc.metadata.file_path);
+            println!("  Type:    {}", doc.metadata.file_type);
+            println!("  Size:    {} bytes", doc.metadata.file_size);
+            println!("  Words:   {}", doc.metadata.word_count);
+            println!("  Chunks:  {}", doc_chunks.len());
+            if let Some(summary) = &doc.metadata.summary {
+                println!("  Summary: {}", summary);
+            }
+            for (key, value) in &doc.metadata.tags {
+                println!("  Tag:     {}={}", key, value);
+            }
+            let shown: String = doc.content.chars().take(preview).collect();
+            println!("\n{}", shown.trim_end());
+            if shown.len() < doc.content.len() {
+                println!("... ({} more characters)", doc.content[shown.len()..].chars().count());
+            }
+            if chunks {
+                println!("\n{:<40} {:>6} {:>13}", "CHUNK", "WORDS", "BYTES");
+                for chunk in &doc_chunks {
+                    println!(
+                        "{:<40} {:>6} {:>13}",
+                        chunk.id,
+                        chunk.word_count,
+                        format!("{}..{}", chunk.byte_start, chunk.byte_end)
+                    );
+                }
+            }
+        }
+        Commands::Mcp { metrics_addr } => {
+            // stdout carries the protocol, so only report on stderr
+            if let Some(addr) = metrics_addr {
+                let listener = TcpListener::bind(&addr)
+                    .map_err(|e| anyhow::anyhow!("Cannot serve metrics on {}: {}", addr, e))?;
+                eprintln!("Serving metrics at http://{}/metrics", listener.local_addr()?);
+                let metrics = rag.metrics();
+                std::thread::spawn(move || serve_metrics(listener, metrics));
+            }
+            eprintln!("Serving {} documents over MCP on stdio", rag.list_documents()?.len());
+            McpServer::new(&rag).serve(std::io::stdin().lock(), std::io::stdout().lock())?;
+            if caches_queries {
+                rag.persist()?;
+            }
+        }
+        Commands::Serve { addr, warm, replica_of, replica_poll } => {
+            let listener = TcpListener::bind(&addr).map_err(|e| anyhow::anyhow!("Cannot serve on {}: {}", addr, e))?;
+            let replica = replica_of.map(|primary| Replica::new(primary, std::time::Duration::from_secs(replica_poll.max(1))));
+            if let Some(snapshot) = replica.as_ref().map(Replica::poll).transpose()?.flatten() {
+                rag.restore_snapshot(snapshot)?;
+            }
+            if warm {
+                let report = rag.warm_up()?;
+                println!(
+                    "Warmed up {} embeddings and {} cached queries in {:.1}ms",
+                    report.embeddings, report.queries_primed, report.elapsed_ms
+                );
+            }
+            if !config.auth.is_enabled() {
+                let access = if replica.is_some() { "search" } else { "search and add documents" };
+                eprintln!("Warning: no [[auth.keys]] configured, anyone who can reach {} may {}", addr, access);
+            }
+            println!(
+                "Serving {} documents at http://{}",
+                rag.list_documents()?.len(),
+                listener.local_addr()?
+            );
+            let mut server = RagServer::new(rag, config.auth.clone());
+            if config.backup.is_enabled() {
+                server = server.with_backups(BackupScheduler::new(&config.backup)?);
+            }
+            if let Some(replica) = replica {
+                println!("Read-only replica of {}", replica.primary().display());
+                server = server.with_replica(replica);
+            }
+            Arc::new(server).serve(listener)?;
+        }
+        Commands::Info => {
+            let info = rag.info()?;
+            if !text_output {
+                return emit_json(cli.format, &info);
+            }
+            let data_path = match &info.data_path {
+                Some(path) => path.display().to_string(),
+                None => "(in memory, nothing is persisted)".to_string(),
+            };
+            let chunking = match &info.chunking {
+                ChunkingStrategy::FixedSize { size } => format!("fixed, {} words", size),
+                ChunkingStrategy::Paragraph => "paragraph".to_string(),
+                ChunkingStrategy::Custom { name } => format!("custom ({})", name),
+            };
+            let embedding = match (&info.embedding_model, info.embedding_dimensions) {
+                (Some(model), Some(dimensions)) => format!("{} ({} dimensions)", model, dimensions),
+                (Some(model), None) => model.clone(),
+                (None, _) => "none".to_string(),
+            };
+            let last_written = match info.freshness.last_written {
+                Some(secs) => format!("{} UTC", format_timestamp(secs)),
+                None => "never".to_string(),
+            };
+            println!("Backend:          {:?}", info.backend);
+            println!("Data path:        {}", data_path);
+            println!("Chunking:         {}", chunking);
+            println!("Search mode:      {:?}", info.search_mode);
+            println!("Embedding model:  {}", embedding);
+            if !info.tenants.is_empty() {
+                let scope = info.tenant.as_deref().unwrap_or("the default tenant");
+                println!("Tenants:          {} (counts below are for {})", info.tenants.join(", "), scope);
+            }
+            println!("Documents:        {} ({} chunks)", info.documents, info.chunks);
+            println!("Last written:     {}", last_written);
+            let freshness = &info.freshness;
+            if freshness.is_current() {
+                println!("Freshness:        up to date with the source files");
+            } else {
+                println!(
+                    "Freshness:        {} changed, {} missing source files, {} unembedded chunks",
+                    freshness.stale_documents, freshness.missing_sources, freshness.unembedded_chunks
+                );
+                if freshness.stale_documents + freshness.missing_sources > 0 {
+                    println!("                  keep the files in step with `watch`, or delete and re-process them");
+                }
+                if freshness.unembedded_chunks > 0 {
+                    println!("                  run `reindex` to embed every chunk");
+                }
+            }
+        }
+        Commands::Stats { usage, corpus } => {
             let stats = rag.get_stats()?;
+            let corpus = corpus.then(|| rag.corpus_stats()).transpose()?;
+            if !text_output {
+                let mut json = serde_json::to_value(&stats)?;
+                if let Some(corpus) = &corpus {
+                    json["corpus"] = serde_json::to_value(corpus)?;
+                }
+                return emit_json(cli.format, &json);
+            }
             println!("Storage Statistics:");
             println!("  Total Documents: {}", stats.total_documents);
             println!("  Total Chunks: {}", stats.total_chunks);
             println!("  Total Size: {} bytes", stats.total_size_bytes);
+            if stats.evicted_documents > 0 {
+                println!("  Evicted From Memory: {} documents", stats.evicted_documents);
+            }
+            if stats.embedding_bytes > 0 {
+                println!("  Embeddings: {} bytes", stats.embedding_bytes);
+            }
+            let lookups = stats.embedding_cache_hits + stats.embedding_cache_misses;
+            if lookups > 0 {
+                println!(
+                    "  Embedding Cache: {} entries, {} hits / {} lookups ({:.1}%)",
+                    stats.embedding_cache_entries,
+                    stats.embedding_cache_hits,
+                    lookups,
+                    100.0 * stats.embedding_cache_hits as f64 / lookups as f64
+                );
+            }
+            let answer_lookups = stats.answer_cache_hits + stats.answer_cache_misses;
+            if answer_lookups > 0 {
+                println!(
+                    "  Answer Cache: {} entries, {} hits / {} lookups ({:.1}%)",
+                    stats.answer_cache_entries,
+                    stats.answer_cache_hits,
+                    answer_lookups,
+                    100.0 * stats.answer_cache_hits as f64 / answer_lookups as f64
+                );
+            }
+            if usage {
+                println!();
+                println!("Usage (estimated cost at list prices):");
+                println!("  {:<8} {:>6} {:>10} {:>10} {:>6} {:>10} {:>10}", "", "LLM", "In", "Out", "Embed", "Tokens", "Cost");
+                let mut total = Usage::default();
+                for (operation, used) in &stats.usage {
+                    print_usage_row(operation, used);
+                    total.add(used);
+                }
+                print_usage_row("total", &total);
+            }
+            if let Some(corpus) = corpus {
+                let lengths = &corpus.chunk_lengths;
+                println!();
+                println!("Corpus:");
+                println!("  Vocabulary: {} terms, {} in total", corpus.vocabulary_size, corpus.total_terms);
+                println!(
+                    "  Chunk Length (terms): min {}, mean {:.1}, p50 {}, p90 {}, p99 {}, max {}",
+                    lengths.min, lengths.mean, lengths.p50, lengths.p90, lengths.p99, lengths.max
+                );
+                println!("  Most Common Terms:");
+                for (term, chunks) in corpus.most_common(10) {
+                    println!("    {:<20} {:>6} chunks  idf {:.3}", term, chunks, corpus.idf(term));
+                }
+            }
         }
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_cli_flags_do_not_collide() {
+        // Clap only notices a global flag clashing with a subcommand's when that subcommand is parsed
+        Cli::command().debug_assert();
+        let cli = Cli::try_parse_from(["rag-system", "--format", "json", "query-batch", "q.txt", "--output", "r.jsonl"]).unwrap();
+        assert!(cli.format == OutputFormat::Json);
+        assert!(matches!(cli.command, Commands::QueryBatch { output: Some(_), .. }));
+    }
+}
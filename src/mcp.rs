@@ -0,0 +1,199 @@
+//! Model Context Protocol server over stdio, so MCP clients can query the index
+
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::io::{BufRead, Write};
+use crate::SimpleRagSystem;
+
+/// Protocol revision answered when the client doesn't ask for one
+pub const MCP_PROTOCOL_VERSION: &str = "2024-11-05";
+
+const PARSE_ERROR: i64 = -32700;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves `search` and `fetch_document` tools as newline-delimited JSON-RPC
+pub struct McpServer<'a> {
+    rag: &'a SimpleRagSystem,
+}
+
+impl<'a> McpServer<'a> {
+    pub fn new(rag: &'a SimpleRagSystem) -> Self {
+        Self { rag }
+    }
+
+    /// Answer requests from `input` until it closes
+    pub fn serve(&self, input: impl BufRead, mut output: impl Write) -> Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let response = match serde_json::from_str::<Value>(&line) {
+                Ok(message) => self.handle(&message),
+                Err(e) => Some(error_response(Value::Null, PARSE_ERROR, &e.to_string())),
+            };
+            if let Some(response) = response {
+                writeln!(output, "{}", response)?;
+                output.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The response to one JSON-RPC message; `None` for notifications
+    pub fn handle(&self, message: &Value) -> Option<Value> {
+        let id = message.get("id")?.clone();
+        let method = message.get("method").and_then(Value::as_str).unwrap_or_default();
+        let params = message.get("params").cloned().unwrap_or(Value::Null);
+        tracing::debug!("MCP request {}: {}", id, method);
+
+        let result = match method {
+            "initialize" => {
+                let version = params
+                    .get("protocolVersion")
+                    .and_then(Value::as_str)
+                    .unwrap_or(MCP_PROTOCOL_VERSION);
+                json!({
+                    "protocolVersion": version,
+                    "capabilities": { "tools": {} },
+                    "serverInfo": { "name": "rag-system", "version": env!("CARGO_PKG_VERSION") }
+                })
+            }
+            "ping" => json!({}),
+            "tools/list" => json!({ "tools": tool_definitions() }),
+            "tools/call" => match self.call_tool(&params) {
+                Ok(text) => json!({ "content": [{ "type": "text", "text": text }], "isError": false }),
+                Err(ToolCallError::InvalidParams(message)) => return Some(error_response(id, INVALID_PARAMS, &message)),
+                // Tool failures are reported to the model rather than as protocol errors
+                Err(ToolCallError::Failed(e)) => {
+                    json!({ "content": [{ "type": "text", "text": e.to_string() }], "isError": true })
+                }
+            },
+            other => return Some(error_response(id, METHOD_NOT_FOUND, &format!("Unknown method '{}'", other))),
+        };
+
+        Some(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+    }
+
+    fn call_tool(&self, params: &Value) -> Result<String, ToolCallError> {
+        let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        let string_arg = |name: &str| {
+            arguments
+                .get(name)
+                .and_then(Value::as_str)
+                .ok_or_else(|| ToolCallError::InvalidParams(format!("Missing string argument '{}'", name)))
+        };
+
+        match params.get("name").and_then(Value::as_str) {
+            Some("search") => {
+                let query = string_arg("query")?;
+                let limit = arguments.get("limit").and_then(Value::as_u64).unwrap_or(5) as usize;
+                let mut hits = Vec::new();
+                for result in self.rag.search(query, limit)?.into_iter().filter(|r| r.score > 0.0) {
+                    let source = self.rag.get_document(&result.document_id)?.map(|doc| doc.metadata.file_path);
+                    hits.push(json!({
+                        "document_id": result.document_id,
+                        "chunk_id": result.chunk_id,
+                        "source": source,
+                        "score": result.score,
+                        "content": result.content,
+                    }));
+                }
+                Ok(serde_json::to_string_pretty(&hits).map_err(anyhow::Error::from)?)
+            }
+            Some("fetch_document") => {
+                let document_id = string_arg("document_id")?;
+                match self.rag.get_document(document_id)? {
+                    Some(document) => Ok(serde_json::to_string_pretty(&document).map_err(anyhow::Error::from)?),
+                    None => Err(ToolCallError::Failed(anyhow::anyhow!("No document with id '{}'", document_id))),
+                }
+            }
+            Some(other) => Err(ToolCallError::InvalidParams(format!("Unknown tool '{}'", other))),
+            None => Err(ToolCallError::InvalidParams("Missing tool name".to_string())),
+        }
+    }
+}
+
+enum ToolCallError {
+    InvalidParams(String),
+    Failed(anyhow::Error),
+}
+
+impl From<anyhow::Error> for ToolCallError {
+    fn from(error: anyhow::Error) -> Self {
+        ToolCallError::Failed(error)
+    }
+}
+
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "name": "search",
+            "description": "Search the indexed documents for passages relevant to a query.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look for" },
+                    "limit": { "type": "integer", "description": "Maximum number of passages (default 5)" }
+                },
+                "required": ["query"]
+            }
+        },
+        {
+            "name": "fetch_document",
+            "description": "Fetch the full text and metadata of an indexed document by the id returned from search.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "document_id": { "type": "string" }
+                },
+                "required": ["document_id"]
+            }
+        }
+    ])
+}
+
+fn error_response(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_serves_search_and_fetch() {
+        let test_file = "/tmp/test_mcp.txt";
+        std::fs::write(test_file, "Lifetimes describe how long references stay valid.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let doc_id = rag.process_document(Path::new(test_file)).unwrap();
+
+        let requests = [
+            json!({"jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {"protocolVersion": "2025-03-26"}}),
+            json!({"jsonrpc": "2.0", "method": "notifications/initialized"}),
+            json!({"jsonrpc": "2.0", "id": 2, "method": "tools/call", "params": {"name": "search", "arguments": {"query": "lifetimes"}}}),
+            json!({"jsonrpc": "2.0", "id": 3, "method": "tools/call", "params": {"name": "fetch_document", "arguments": {"document_id": doc_id}}}),
+            json!({"jsonrpc": "2.0", "id": 4, "method": "tools/call", "params": {"name": "search", "arguments": {}}}),
+        ];
+        let input: String = requests.iter().map(|r| format!("{}\n", r)).collect();
+        let mut output = Vec::new();
+        McpServer::new(&rag).serve(input.as_bytes(), &mut output).unwrap();
+
+        let responses: Vec<Value> = String::from_utf8(output)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(responses.len(), 4);
+        assert_eq!(responses[0]["result"]["protocolVersion"], "2025-03-26");
+        let hits = responses[1]["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(hits.contains(&doc_id) && hits.contains(test_file));
+        let document = responses[2]["result"]["content"][0]["text"].as_str().unwrap();
+        assert!(document.contains("stay valid"));
+        assert_eq!(responses[3]["error"]["code"], INVALID_PARAMS);
+
+        std::fs::remove_file(test_file).unwrap();
+    }
+}
@@ -0,0 +1,185 @@
+//! Process-lifetime counters in the Prometheus text format, served over HTTP while a server runs
+
+use anyhow::Result;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use crate::usage::UsageMeter;
+
+/// Upper bounds of the search latency buckets, in seconds
+pub const LATENCY_BUCKETS: [f64; 12] = [0.001, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Cumulative histogram with the classic Prometheus layout
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    /// Observations at or below each of `LATENCY_BUCKETS`
+    pub buckets: [u64; LATENCY_BUCKETS.len()],
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Histogram {
+    pub fn observe(&mut self, seconds: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// What one system has done since it was created; shared with the metrics endpoint
+#[derive(Debug, Default)]
+pub struct Metrics {
+    documents_ingested: AtomicU64,
+    chunks_stored: AtomicU64,
+    embedding_cache_hits: AtomicU64,
+    embedding_cache_misses: AtomicU64,
+    answer_cache_hits: AtomicU64,
+    answer_cache_misses: AtomicU64,
+    search_latency: Mutex<Histogram>,
+    /// Provider calls are counted by the usage meter already
+    usage: Arc<UsageMeter>,
+}
+
+impl Metrics {
+    pub fn new(usage: Arc<UsageMeter>) -> Self {
+        Self {
+            usage,
+            ..Default::default()
+        }
+    }
+
+    pub fn record_ingest(&self, chunks: usize) {
+        self.documents_ingested.fetch_add(1, Ordering::Relaxed);
+        self.chunks_stored.fetch_add(chunks as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_embedding_lookups(&self, hits: usize, misses: usize) {
+        self.embedding_cache_hits.fetch_add(hits as u64, Ordering::Relaxed);
+        self.embedding_cache_misses.fetch_add(misses as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_answer_lookup(&self, hit: bool) {
+        let counter = if hit { &self.answer_cache_hits } else { &self.answer_cache_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_search(&self, elapsed: Duration) {
+        self.search_latency.lock().unwrap().observe(elapsed.as_secs_f64());
+    }
+
+    pub fn search_latency(&self) -> Histogram {
+        self.search_latency.lock().unwrap().clone()
+    }
+
+    /// Every metric in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let usage = self.usage.total();
+        let mut out = String::new();
+        let load = |counter: &AtomicU64| counter.load(Ordering::Relaxed);
+        let counters = [
+            ("rag_documents_ingested_total", "Documents processed and stored", load(&self.documents_ingested)),
+            ("rag_chunks_stored_total", "Chunks stored by ingestion", load(&self.chunks_stored)),
+            ("rag_embedding_calls_total", "Requests made to the embedding provider", usage.embedding_calls),
+            ("rag_completion_calls_total", "Requests made to the completion provider", usage.completion_calls),
+            ("rag_embedding_cache_hits_total", "Texts served from the embedding cache", load(&self.embedding_cache_hits)),
+            ("rag_embedding_cache_misses_total", "Texts that had to be embedded", load(&self.embedding_cache_misses)),
+            ("rag_answer_cache_hits_total", "Questions answered from the answer cache", load(&self.answer_cache_hits)),
+            ("rag_answer_cache_misses_total", "Questions not found in the answer cache", load(&self.answer_cache_misses)),
+        ];
+        for (name, help, value) in counters {
+            let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, value);
+        }
+
+        let latency = self.search_latency();
+        let name = "rag_search_latency_seconds";
+        let _ = writeln!(out, "# HELP {} Time to score chunks for a query\n# TYPE {} histogram", name, name);
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(latency.buckets) {
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, latency.count);
+        let _ = writeln!(out, "{}_sum {}\n{}_count {}", name, latency.sum, name, latency.count);
+        out
+    }
+}
+
+/// Answer `GET /metrics` on `listener` until it fails; every other path is a 404
+pub fn serve_metrics(listener: TcpListener, metrics: Arc<Metrics>) -> Result<()> {
+    for stream in listener.incoming() {
+        if let Err(e) = respond(stream?, &metrics) {
+            tracing::warn!("Metrics request failed: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn respond(mut stream: TcpStream, metrics: &Metrics) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; the request has no body worth reading
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, content_type, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => ("200 OK", "text/plain; version=0.0.4", metrics.render()),
+        _ => ("404 Not Found", "text/plain", "Not found\n".to_string()),
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_metrics_endpoint_renders_counters_and_histogram() {
+        let metrics = Arc::new(Metrics::default());
+        metrics.record_ingest(3);
+        metrics.record_embedding_lookups(2, 1);
+        metrics.record_search(Duration::from_millis(20));
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let served = metrics.clone();
+        std::thread::spawn(move || serve_metrics(listener, served));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("rag_documents_ingested_total 1\n"));
+        assert!(response.contains("rag_chunks_stored_total 3\n"));
+        assert!(response.contains("rag_embedding_cache_hits_total 2\n"));
+        assert!(response.contains("rag_search_latency_seconds_bucket{le=\"0.01\"} 0\n"));
+        assert!(response.contains("rag_search_latency_seconds_bucket{le=\"0.025\"} 1\n"));
+        assert!(response.contains("rag_search_latency_seconds_count 1\n"));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(b"GET /other HTTP/1.1\r\n\r\n").unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+    }
+}
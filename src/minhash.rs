@@ -0,0 +1,83 @@
+//! MinHash signatures of chunks' word shingles, and LSH banding over them, for finding near-duplicate
+//! text across documents without comparing every pair of chunks
+
+use std::collections::{BTreeSet, HashMap};
+use crate::dedupe::shingles;
+
+/// Hash functions per signature
+pub const MINHASH_PERMUTATIONS: usize = 64;
+
+/// Bands the signature is split into for LSH. With four rows a band, pairs more similar than about
+/// (1/16)^(1/4) = 0.5 are likely to share one and so be compared
+pub const LSH_BANDS: usize = 16;
+
+const ROWS: usize = MINHASH_PERMUTATIONS / LSH_BANDS;
+
+/// Lowest hash of `text`'s shingles under each of `MINHASH_PERMUTATIONS` hash functions
+pub fn minhash(text: &str) -> Vec<u32> {
+    let shingles = shingles(text);
+    (0..MINHASH_PERMUTATIONS as u64)
+        .map(|i| {
+            let seed = mix(i);
+            shingles.iter().map(|s| mix(s ^ seed) as u32).min().unwrap_or(u32::MAX)
+        })
+        .collect()
+}
+
+/// Estimated Jaccard similarity of the shingles behind two signatures
+pub fn similarity(a: &[u32], b: &[u32]) -> f64 {
+    if a.is_empty() || a.len() != b.len() {
+        return 0.0;
+    }
+    a.iter().zip(b).filter(|(x, y)| x == y).count() as f64 / a.len() as f64
+}
+
+/// Pairs of positions in `signatures`, lower first, that share at least one LSH band
+pub fn candidate_pairs(signatures: &[&[u32]]) -> BTreeSet<(usize, usize)> {
+    let mut buckets: HashMap<(usize, &[u32]), Vec<usize>> = HashMap::new();
+    for (i, signature) in signatures.iter().enumerate() {
+        if signature.len() != MINHASH_PERMUTATIONS {
+            continue;
+        }
+        for (band, rows) in signature.chunks(ROWS).enumerate() {
+            buckets.entry((band, rows)).or_default().push(i);
+        }
+    }
+    let mut pairs = BTreeSet::new();
+    for members in buckets.values().filter(|members| members.len() > 1) {
+        for (n, &a) in members.iter().enumerate() {
+            pairs.extend(members[n + 1..].iter().map(|&b| (a, b)));
+        }
+    }
+    pairs
+}
+
+/// SplitMix64 finalizer, a cheap hash with good avalanche
+fn mix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_similar_texts_share_a_band() {
+        let base = "Staff get twenty days of paid leave each year, booked through the HR portal with one week of notice, \
+                    and unused days carry over to the next year up to a limit of five.";
+        let edited = base.replace("five", "ten");
+        let other = "Sourdough needs a starter fed every day and a long, cold rise in the fridge overnight before baking.";
+        let signatures = [minhash(base), minhash(&edited), minhash(other)];
+        assert!(similarity(&signatures[0], &signatures[1]) > 0.7);
+        assert!(similarity(&signatures[0], &signatures[2]) < 0.2);
+        assert_eq!(similarity(&signatures[0], &minhash(base)), 1.0);
+
+        let refs: Vec<&[u32]> = signatures.iter().map(Vec::as_slice).collect();
+        let pairs = candidate_pairs(&refs);
+        assert!(pairs.contains(&(0, 1)));
+        assert!(!pairs.contains(&(0, 2)) && !pairs.contains(&(1, 2)));
+    }
+}
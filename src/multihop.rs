@@ -0,0 +1,163 @@
+//! Iterative retrieval: the LLM may ask for follow-up searches before answering
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use crate::chunking::DocumentChunk;
+use crate::generation::Answer;
+use crate::search::SearchResult;
+use crate::SimpleRagSystem;
+
+pub const DEFAULT_MAX_HOPS: usize = 3;
+
+const MULTI_HOP_INSTRUCTIONS: &str = "If the context is missing something you need, reply with a single line \
+`SEARCH: <query>` to look it up. Otherwise reply with `ANSWER:` followed by your answer.";
+
+/// One retrieval round: the query that was run and the chunks it added to the context
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Hop {
+    pub query: String,
+    pub chunk_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MultiHopAnswer {
+    pub answer: Answer,
+    pub hops: Vec<Hop>,
+}
+
+/// Answers questions spanning several documents by letting the LLM request more searches
+pub struct MultiHopRetriever<'a> {
+    rag: &'a SimpleRagSystem,
+    max_hops: usize,
+}
+
+impl<'a> MultiHopRetriever<'a> {
+    pub fn new(rag: &'a SimpleRagSystem) -> Self {
+        Self {
+            rag,
+            max_hops: DEFAULT_MAX_HOPS,
+        }
+    }
+
+    /// Follow-up searches allowed after the initial one
+    pub fn with_max_hops(mut self, max_hops: usize) -> Self {
+        self.max_hops = max_hops;
+        self
+    }
+
+    pub fn answer(&self, question: &str) -> Result<MultiHopAnswer> {
+        let llm = self.rag.completion_provider()?;
+        let mut results: Vec<SearchResult> = Vec::new();
+        let mut chunks: Vec<DocumentChunk> = Vec::new();
+        let mut hops: Vec<Hop> = Vec::new();
+        let mut query = question.to_string();
+
+        loop {
+            let (found, found_chunks) = self.rag.retrieve_context(&query)?;
+            let mut added = Vec::new();
+            for (result, chunk) in found.into_iter().zip(found_chunks) {
+                if !chunks.iter().any(|c| c.id == chunk.id) {
+                    added.push(chunk.id.clone());
+                    results.push(result);
+                    chunks.push(chunk);
+                }
+            }
+            hops.push(Hop { query, chunk_ids: added });
+
+            // Accumulated context is re-packed so later hops can't overflow the budget
+            let packed = self.rag.context_builder().build(results.clone(), chunks.clone());
+            let template = self.rag.prompt_template();
+            let last_hop = hops.len() > self.max_hops;
+            let mut request = template.build_request(question, &packed.results);
+            if !last_hop {
+                request.preamble = Some(format!("{} {}", template.preamble(), MULTI_HOP_INSTRUCTIONS));
+            }
+
+            let response = llm.complete(&request)?;
+            let next_query = parse_search(&response).filter(|next| !hops.iter().any(|h| &h.query == next));
+            match next_query {
+                Some(next) if !last_hop => {
+                    tracing::debug!("Hop {} searches for '{}'", hops.len() + 1, next);
+                    query = next
+                }
+                _ => {
+                    let answer = match self.rag.refuse_weak_context(&packed.results) {
+                        Some(refusal) => refusal,
+                        None => {
+                            // Out of hops, or repeating a search: ask once more without the option to search
+                            let response = if parse_search(&response).is_some() && !last_hop {
+                                llm.complete(&template.build_request(question, &packed.results))?
+                            } else {
+                                response
+                            };
+                            Answer::from_response(strip_answer_prefix(&response), &packed.chunks)
+                        }
+                    };
+                    return Ok(MultiHopAnswer { answer, hops });
+                }
+            }
+        }
+    }
+}
+
+fn parse_search(response: &str) -> Option<String> {
+    let line = response.trim().lines().next()?.trim();
+    let query = line.strip_prefix("SEARCH:")?.trim();
+    (!query.is_empty()).then(|| query.to_string())
+}
+
+fn strip_answer_prefix(response: &str) -> String {
+    let response = response.trim();
+    response.strip_prefix("ANSWER:").unwrap_or(response).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::{CompletionProvider, CompletionRequest};
+    use std::path::Path;
+    use std::sync::Arc;
+
+    /// Looks up Ferris until the context mentions it, then answers citing both passages
+    struct ComparingLlm;
+
+    impl CompletionProvider for ComparingLlm {
+        fn model_name(&self) -> &str {
+            "comparing"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> Result<String> {
+            if request.prompt.contains("Ferris") {
+                Ok("ANSWER: Tokio is a runtime [1] while Ferris is a crab [2].".to_string())
+            } else {
+                Ok("SEARCH: Ferris crab".to_string())
+            }
+        }
+    }
+
+    #[test]
+    fn test_follow_up_search_adds_second_document() {
+        let tokio_file = "/tmp/test_multihop_tokio.txt";
+        let ferris_file = "/tmp/test_multihop_ferris.txt";
+        std::fs::write(tokio_file, "Tokio is an asynchronous runtime.").unwrap();
+        std::fs::write(ferris_file, "Ferris is a friendly crab.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap().with_completion_provider(Arc::new(ComparingLlm));
+        rag.process_document(Path::new(tokio_file)).unwrap();
+        let ferris_doc = rag.process_document(Path::new(ferris_file)).unwrap();
+
+        let result = rag.ask_multi_hop("Describe tokio", 2).unwrap();
+
+        assert_eq!(result.hops.len(), 2);
+        assert_eq!(result.hops[1].query, "Ferris crab");
+        assert!(result.answer.text.starts_with("Tokio is a runtime"));
+        assert_eq!(result.answer.citations[1].document_id, ferris_doc);
+
+        // Without follow-up hops the first answer stands
+        let single = rag.ask_multi_hop("Describe tokio", 0).unwrap();
+        assert_eq!(single.hops.len(), 1);
+
+        std::fs::remove_file(tokio_file).unwrap();
+        std::fs::remove_file(ferris_file).unwrap();
+    }
+}
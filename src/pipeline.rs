@@ -0,0 +1,90 @@
+//! Bulk ingestion as a pipeline of stages (read and extract, chunk, embed, store) joined by bounded
+//! queues, so CPU-bound chunking overlaps with waiting on the embedding provider.
+//!
+//! Each stage has its own worker threads, and a full queue holds back the stage feeding it, so a
+//! slow embedder doesn't leave every document of a large directory waiting in memory.
+
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::mpsc::{sync_channel, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::Scope;
+use crate::SimpleRagSystem;
+
+/// A file and how its processing has gone so far; failures pass through to be reported
+type Item<T> = (PathBuf, Result<T>);
+
+/// Run `work` on `workers` threads over everything from `input`, queueing up to `capacity` results
+fn spawn_stage<'scope, I, O>(
+    scope: &'scope Scope<'scope, '_>,
+    input: Receiver<I>,
+    workers: usize,
+    capacity: usize,
+    work: impl Fn(I) -> O + Send + Sync + 'scope,
+) -> Receiver<O>
+where
+    I: Send + 'scope,
+    O: Send + 'scope,
+{
+    let (output, results) = sync_channel(capacity);
+    let input = Arc::new(Mutex::new(input));
+    let work = Arc::new(work);
+    for _ in 0..workers.max(1) {
+        let (input, output, work) = (input.clone(), output.clone(), work.clone());
+        scope.spawn(move || loop {
+            // The lock is only held while taking the next item, not while working on it
+            let next = input.lock().unwrap().recv();
+            let Ok(item) = next else {
+                break;
+            };
+            if output.send(work(item)).is_err() {
+                break;
+            }
+        });
+    }
+    results
+}
+
+impl SimpleRagSystem {
+    /// Ingest `files` with `workers` threads per stage, returning each file's document id or error
+    pub(crate) fn ingest_files_pipelined(&self, files: Vec<PathBuf>, workers: usize) -> Vec<Item<String>> {
+        let capacity = workers * 2;
+        let before = self.usage.total();
+        let stored = std::thread::scope(|scope| {
+            let (feed, paths) = sync_channel(capacity);
+            scope.spawn(move || {
+                for file in files {
+                    if feed.send(file).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let processor = self.processor.clone();
+            let read = spawn_stage(scope, paths, workers, capacity, move |path: PathBuf| {
+                let document = processor.process_file(&path);
+                (path, document)
+            });
+            let chunked = spawn_stage(scope, read, workers, capacity, |(path, document): Item<_>| {
+                (path, document.and_then(|document| self.chunk_for_index(document)))
+            });
+            let embedded = spawn_stage(scope, chunked, workers, capacity, |(path, chunked): Item<_>| {
+                let embedded = chunked.and_then(|(document, chunks)| {
+                    self.embed_for_index(&chunks)?;
+                    Ok((document, chunks))
+                });
+                (path, embedded)
+            });
+            // Stored one at a time, in the order documents finish embedding
+            embedded
+                .into_iter()
+                .map(|(path, embedded)| {
+                    let stored = embedded.and_then(|(document, chunks)| self.store_document(document, chunks));
+                    (path, stored)
+                })
+                .collect()
+        });
+        self.record_usage_since("ingest", &before);
+        stored
+    }
+}
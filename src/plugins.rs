@@ -0,0 +1,202 @@
+//! Components library users supply at runtime: chunkers, content extractors and search scorers,
+//! registered by name so a config file's `[plugins]` section can refer to them
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::sync::Arc;
+use crate::chunking::DocumentChunk;
+
+/// Splits a document's text into chunks, in place of the built-in strategies
+pub trait Chunker: Send + Sync {
+    /// Byte ranges of `content`, in order, each becoming one chunk; they must fall on char boundaries
+    fn split(&self, content: &str) -> Vec<Range<usize>>;
+}
+
+/// Turns the raw bytes of a file into the text that is chunked and indexed
+pub trait ContentExtractor: Send + Sync {
+    /// `name` is the file path or upload name, for error messages and format sniffing
+    fn extract(&self, name: &str, bytes: &[u8]) -> Result<String>;
+}
+
+/// Adjusts each candidate chunk's score after the built-in scoring, boosts and feedback
+pub trait SearchScorer: Send + Sync {
+    /// New score of `chunk`, given its score so far
+    fn score(&self, query: &str, chunk: &DocumentChunk, score: f32) -> f32;
+}
+
+/// `[plugins]` in the config file: registered components to use, by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PluginConfig {
+    /// Chunker documents are split with, in place of the `[chunking]` strategy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub chunker: Option<String>,
+    /// Extractor for each file extension, such as `pdf = "pdf-text"`; other files are read as UTF-8
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub extractors: BTreeMap<String, String>,
+    /// Scorers applied to every search, in order
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scorers: Vec<String>,
+}
+
+impl PluginConfig {
+    pub fn is_empty(&self) -> bool {
+        self == &Self::default()
+    }
+}
+
+/// Named chunkers, extractors and scorers; registering a name again replaces the earlier one
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    chunkers: BTreeMap<String, Arc<dyn Chunker>>,
+    extractors: BTreeMap<String, Arc<dyn ContentExtractor>>,
+    scorers: BTreeMap<String, Arc<dyn SearchScorer>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register_chunker(&mut self, name: &str, chunker: impl Chunker + 'static) -> &mut Self {
+        self.chunkers.insert(name.to_string(), Arc::new(chunker));
+        self
+    }
+
+    pub fn register_extractor(&mut self, name: &str, extractor: impl ContentExtractor + 'static) -> &mut Self {
+        self.extractors.insert(name.to_string(), Arc::new(extractor));
+        self
+    }
+
+    pub fn register_scorer(&mut self, name: &str, scorer: impl SearchScorer + 'static) -> &mut Self {
+        self.scorers.insert(name.to_string(), Arc::new(scorer));
+        self
+    }
+
+    pub fn chunker(&self, name: &str) -> Result<Arc<dyn Chunker>> {
+        lookup(&self.chunkers, "chunker", name)
+    }
+
+    pub fn extractor(&self, name: &str) -> Result<Arc<dyn ContentExtractor>> {
+        lookup(&self.extractors, "content extractor", name)
+    }
+
+    pub fn scorer(&self, name: &str) -> Result<Arc<dyn SearchScorer>> {
+        lookup(&self.scorers, "search scorer", name)
+    }
+
+    /// Names of the registered chunkers, extractors and scorers
+    pub fn names(&self) -> (Vec<&str>, Vec<&str>, Vec<&str>) {
+        (names(&self.chunkers), names(&self.extractors), names(&self.scorers))
+    }
+}
+
+impl std::fmt::Debug for PluginRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (chunkers, extractors, scorers) = self.names();
+        f.debug_struct("PluginRegistry")
+            .field("chunkers", &chunkers)
+            .field("extractors", &extractors)
+            .field("scorers", &scorers)
+            .finish()
+    }
+}
+
+fn names<T: ?Sized>(map: &BTreeMap<String, Arc<T>>) -> Vec<&str> {
+    map.keys().map(String::as_str).collect()
+}
+
+fn lookup<T: ?Sized>(map: &BTreeMap<String, Arc<T>>, kind: &str, name: &str) -> Result<Arc<T>> {
+    map.get(name).cloned().ok_or_else(|| {
+        let known = names(map);
+        match known.is_empty() {
+            true => anyhow!("No {} registered as '{}'; none are registered", kind, name),
+            false => anyhow!("No {} registered as '{}' (registered: {})", kind, name, known.join(", ")),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::processor::DocumentProcessor;
+    use crate::SimpleRagSystem;
+
+    /// One chunk per line
+    struct Lines;
+
+    impl Chunker for Lines {
+        fn split(&self, content: &str) -> Vec<Range<usize>> {
+            let mut start = 0;
+            let mut ranges = Vec::new();
+            for line in content.split_inclusive('\n') {
+                let trimmed = line.trim_end().len();
+                if trimmed > 0 {
+                    ranges.push(start..start + trimmed);
+                }
+                start += line.len();
+            }
+            ranges
+        }
+    }
+
+    /// Reads files whose text was written backwards
+    struct Reversed;
+
+    impl ContentExtractor for Reversed {
+        fn extract(&self, _name: &str, bytes: &[u8]) -> Result<String> {
+            Ok(String::from_utf8_lossy(bytes).chars().rev().collect())
+        }
+    }
+
+    /// Zeroes the score of chunks that mention drafts
+    struct NoDrafts;
+
+    impl SearchScorer for NoDrafts {
+        fn score(&self, _query: &str, chunk: &DocumentChunk, score: f32) -> f32 {
+            if chunk.content.contains("draft") { 0.0 } else { score }
+        }
+    }
+
+    fn registry() -> PluginRegistry {
+        let mut registry = PluginRegistry::new();
+        registry.register_chunker("lines", Lines).register_extractor("reversed", Reversed).register_scorer("no-drafts", NoDrafts);
+        registry
+    }
+
+    #[test]
+    fn test_config_selects_registered_components() {
+        let config: PluginConfig = toml::from_str(
+            r#"
+            chunker = "lines"
+            scorers = ["no-drafts"]
+            extractors = { rev = "reversed" }
+            "#,
+        )
+        .unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap().with_plugins(&registry(), &config).unwrap();
+
+        let doc_id = rag.process_bytes("notes.txt", b"Leave policy\ndraft leave policy\nLeave requests").unwrap();
+        assert_eq!(rag.document_chunks(&doc_id).unwrap().len(), 3);
+        let results = rag.search("leave policy", 5).unwrap();
+        assert_eq!(results[0].content, "Leave policy");
+        assert!(results.iter().filter(|r| r.content.contains("draft")).all(|r| r.score == 0.0));
+
+        let reversed = rag.process_bytes("secret.rev", b"sdrawkcab nettirw").unwrap();
+        assert_eq!(rag.get_document(&reversed).unwrap().unwrap().content, "written backwards");
+        assert_eq!(DocumentProcessor::new().process_bytes("plain.txt", b"as is").unwrap().content, "as is");
+    }
+
+    #[test]
+    fn test_unknown_names_list_what_is_registered() {
+        let config = PluginConfig { scorers: vec!["freshness".to_string()], ..PluginConfig::default() };
+        let Err(error) = SimpleRagSystem::new().unwrap().with_plugins(&registry(), &config) else {
+            panic!("an unregistered scorer was accepted");
+        };
+        assert!(error.to_string().contains("'freshness'") && error.to_string().contains("no-drafts"));
+        let Err(error) = PluginRegistry::new().chunker("lines") else { unreachable!() };
+        assert!(error.to_string().contains("none are registered"));
+    }
+}
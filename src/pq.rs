@@ -0,0 +1,209 @@
+//! Product quantization of stored embeddings.
+//!
+//! A trained codebook splits each vector into subvectors of `PQ_SUBVECTOR_DIMENSIONS` and replaces each
+//! one by the nearest of up to 256 centroids learned for its slice, so a vector becomes one byte per
+//! slice: a thirty-second of its `f32`s, plus its reconstructed length. Searches use asymmetric
+//! distance computation: the full-precision query is compared once with every centroid, after which
+//! scoring a chunk is a table lookup per byte.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use crate::disk_index::{dot, normalize, Xorshift};
+
+/// Dimensions each code byte stands for
+pub const PQ_SUBVECTOR_DIMENSIONS: usize = 8;
+/// Fewer vectors than this are left at full precision, too few to learn 256 centroids a slice from
+pub const PQ_MIN_TRAINING_VECTORS: usize = 256;
+/// Vectors sampled to train on, sixteen per centroid, which keeps training time flat for large corpora
+const TRAINING_SAMPLE: usize = 4_096;
+const ITERATIONS: usize = 10;
+
+/// Centroids of every slice, learned from unit-length vectors since chunks are ranked by cosine
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PqCodebook {
+    pub dimensions: usize,
+    /// Per slice, its centroids
+    pub centroids: Vec<Vec<Vec<f32>>>,
+}
+
+impl PqCodebook {
+    /// Learn centroids for each slice of `vectors` by k-means
+    pub fn train<'a>(vectors: impl IntoIterator<Item = &'a Vec<f32>>) -> Result<Self> {
+        let mut points: Vec<Vec<f32>> = vectors.into_iter().map(|v| normalize(v)).collect();
+        let dimensions = points.first().map_or(0, Vec::len);
+        if points.is_empty() || dimensions == 0 {
+            return Err(anyhow!("No embeddings to train a product quantizer on"));
+        }
+        if points.iter().any(|p| p.len() != dimensions) {
+            return Err(anyhow!("Embeddings of different lengths can't share a product quantizer"));
+        }
+        let mut random = Xorshift(0x853c_49e6_748f_ea9b);
+        while points.len() > TRAINING_SAMPLE {
+            let i = random.below(points.len());
+            points.swap_remove(i);
+        }
+        let centroids = slices(dimensions)
+            .map(|range| {
+                let slice: Vec<&[f32]> = points.iter().map(|p| &p[range.clone()]).collect();
+                kmeans(&slice, 256.min(slice.len()), &mut random)
+            })
+            .collect();
+        Ok(Self { dimensions, centroids })
+    }
+
+    pub fn encode(self: &Arc<Self>, vector: &[f32]) -> PqVector {
+        let vector = normalize(vector);
+        let codes: Vec<u8> = slices(self.dimensions)
+            .zip(&self.centroids)
+            .map(|(range, centroids)| nearest(centroids, &vector[range]) as u8)
+            .collect();
+        let norm = self.reconstruct(&codes).iter().map(|x| x * x).sum::<f32>().sqrt();
+        PqVector { codes, norm, codebook: self.clone() }
+    }
+
+    fn reconstruct(&self, codes: &[u8]) -> Vec<f32> {
+        codes.iter().zip(&self.centroids).flat_map(|(&code, centroids)| centroids[code as usize].clone()).collect()
+    }
+
+    /// Dot products of `query`'s slices with every centroid, for scoring many codes against it
+    pub fn distance_table(&self, query: &[f32]) -> Option<DistanceTable> {
+        if query.len() < self.dimensions {
+            return None;
+        }
+        let query = &query[..self.dimensions];
+        let dots = slices(self.dimensions)
+            .zip(&self.centroids)
+            .map(|(range, centroids)| centroids.iter().map(|c| dot(c, &query[range.clone()])).collect())
+            .collect();
+        Some(DistanceTable { dots, query_norm: query.iter().map(|x| x * x).sum::<f32>().sqrt() })
+    }
+
+    /// Bytes of one code
+    pub fn code_bytes(&self) -> usize {
+        self.centroids.len()
+    }
+}
+
+/// A vector as one centroid per slice of its codebook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PqVector {
+    pub codes: Vec<u8>,
+    /// Length of the vector the codes reconstruct
+    pub norm: f32,
+    /// Shared by every code; snapshots store it once and reattach it on load
+    #[serde(skip)]
+    pub codebook: Arc<PqCodebook>,
+}
+
+impl PqVector {
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.codebook.reconstruct(&self.codes)
+    }
+
+    /// Cosine similarity of a full-precision `query` with the reconstructed vector
+    pub fn cosine(&self, query: &[f32]) -> f32 {
+        match self.codebook.distance_table(query) {
+            Some(table) => table.cosine(self),
+            None => 0.0,
+        }
+    }
+}
+
+/// A query's dot product with every centroid of a codebook
+pub struct DistanceTable {
+    dots: Vec<Vec<f32>>,
+    query_norm: f32,
+}
+
+impl DistanceTable {
+    pub fn cosine(&self, vector: &PqVector) -> f32 {
+        if self.query_norm == 0.0 || vector.norm == 0.0 {
+            return 0.0;
+        }
+        let dot: f32 = vector.codes.iter().zip(&self.dots).map(|(&code, dots)| dots[code as usize]).sum();
+        dot / (self.query_norm * vector.norm)
+    }
+}
+
+/// Codebook over `vectors`, unless there are too few to learn it from
+pub fn train_if_enough(vectors: &HashMap<String, Vec<f32>>) -> Result<Option<Arc<PqCodebook>>> {
+    if vectors.len() < PQ_MIN_TRAINING_VECTORS {
+        return Ok(None);
+    }
+    // Sorted, so the same vectors always train the same codebook
+    let mut sorted: Vec<(&String, &Vec<f32>)> = vectors.iter().collect();
+    sorted.sort_by_key(|(id, _)| *id);
+    Ok(Some(Arc::new(PqCodebook::train(sorted.into_iter().map(|(_, v)| v))?)))
+}
+
+/// Index ranges of the slices of a vector of `dimensions`; the last takes any remainder
+fn slices(dimensions: usize) -> impl Iterator<Item = Range<usize>> {
+    let count = (dimensions / PQ_SUBVECTOR_DIMENSIONS).max(1);
+    (0..count).map(move |i| {
+        let start = i * PQ_SUBVECTOR_DIMENSIONS;
+        start..if i + 1 == count { dimensions } else { start + PQ_SUBVECTOR_DIMENSIONS }
+    })
+}
+
+fn kmeans(points: &[&[f32]], k: usize, random: &mut Xorshift) -> Vec<Vec<f32>> {
+    let mut centroids: Vec<Vec<f32>> = (0..k).map(|_| points[random.below(points.len())].to_vec()).collect();
+    for _ in 0..ITERATIONS {
+        let mut sums = vec![vec![0.0f32; points[0].len()]; k];
+        let mut counts = vec![0usize; k];
+        for point in points {
+            let nearest = nearest(&centroids, point);
+            sums[nearest].iter_mut().zip(point.iter()).for_each(|(s, x)| *s += x);
+            counts[nearest] += 1;
+        }
+        for ((centroid, sum), count) in centroids.iter_mut().zip(sums).zip(counts) {
+            if count > 0 {
+                *centroid = sum.into_iter().map(|s| s / count as f32).collect();
+            } else {
+                // Move a centroid nobody chose onto some point, where it can win a share next round
+                *centroid = points[random.below(points.len())].to_vec();
+            }
+        }
+    }
+    centroids
+}
+
+/// Index of the centroid closest to `point` by Euclidean distance
+fn nearest(centroids: &[Vec<f32>], point: &[f32]) -> usize {
+    centroids
+        .iter()
+        .map(|c| c.iter().zip(point).map(|(a, b)| (a - b) * (a - b)).sum::<f32>())
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map_or(0, |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::embedding::cosine_similarity;
+
+    #[test]
+    fn test_codes_score_close_to_full_precision() {
+        let mut random = Xorshift(11);
+        let mut component = || random.below(2001) as f32 / 1000.0 - 1.0;
+        let vectors: HashMap<String, Vec<f32>> =
+            (0..400).map(|i| (format!("c{}", i), (0..32).map(|_| component()).collect())).collect();
+        let codebook = train_if_enough(&vectors).unwrap().unwrap();
+        assert_eq!(codebook.code_bytes(), 4);
+        assert!(train_if_enough(&HashMap::new()).unwrap().is_none());
+
+        let query: Vec<f32> = (0..32).map(|_| component()).collect();
+        let table = codebook.distance_table(&query).unwrap();
+        let mut error = 0.0;
+        for vector in vectors.values() {
+            let code = codebook.encode(vector);
+            assert!((table.cosine(&code) - code.cosine(&query)).abs() < 1e-5);
+            error += (table.cosine(&code) - cosine_similarity(&query, vector)).abs();
+        }
+        assert!(error / 400.0 < 0.1, "mean cosine error {}", error / 400.0);
+        assert!(codebook.distance_table(&query[..16]).is_none());
+    }
+}
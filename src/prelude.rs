@@ -0,0 +1,29 @@
+//! The types most programs need, for `use rag_system::prelude::*;`
+//!
+//! This list only grows; everything else is reached through its module, e.g. `rag_system::evaluation::Evaluator`.
+
+pub use anyhow::{Error, Result};
+pub use crate::builder::SimpleRagSystemBuilder;
+pub use crate::chunking::{ChunkingStrategy, DocumentChunk};
+pub use crate::config::RagConfig;
+pub use crate::embedding::EmbeddingProvider;
+pub use crate::generation::{Answer, Citation};
+pub use crate::llm::{CompletionProvider, CompletionRequest};
+pub use crate::processor::ProcessedDocument;
+pub use crate::query::{Query, TypedQuery};
+pub use crate::search::{SearchConfig, SearchMode, SearchResult};
+pub use crate::tags::DocumentFilter;
+pub use crate::usage::{TokenUsage, Usage};
+pub use crate::SimpleRagSystem;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prelude_is_enough_to_build_and_search() {
+        let rag: SimpleRagSystem = SimpleRagSystemBuilder::new().search_mode(SearchMode::Bm25).build().unwrap();
+        let results: Vec<SearchResult> = rag.search("ownership", 3).unwrap();
+        assert!(results.is_empty());
+    }
+}
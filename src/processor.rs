@@ -1,9 +1,16 @@
 //! Simple document processor for MVP
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::fs;
+use std::sync::Arc;
+use crate::answer_cache::unix_now;
+use crate::graph::Relation;
+use crate::info::modified_secs;
+use crate::language::{detect_language, LANGUAGE_TAG};
+use crate::plugins::ContentExtractor;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DocumentMetadata {
@@ -11,6 +18,35 @@ pub struct DocumentMetadata {
     pub file_type: String,
     pub file_size: usize,
     pub word_count: usize,
+    /// LLM-written overview, when summarization at ingest is enabled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Entity relations the LLM found in the chunks, when graph extraction at ingest is enabled
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relations: Vec<Relation>,
+    /// User-assigned labels, such as `project=alpha`, that searches can filter on
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub tags: BTreeMap<String, String>,
+    /// Owner in a multi-tenant index, set by the storage view that stored the document
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// ISO 639-1 code of the language detected at ingest, such as `en`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Unix seconds the document was read for indexing; unset for documents indexed before it was kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ingested_at: Option<u64>,
+    /// Unix seconds the source file was last modified when it was read; unset for uploads and for
+    /// documents indexed before it was kept
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub modified_at: Option<u64>,
+}
+
+impl DocumentMetadata {
+    /// The language the `language` tag states, otherwise the detected one
+    pub fn language(&self) -> Option<&str> {
+        self.tags.get(LANGUAGE_TAG).or(self.language.as_ref()).map(String::as_str)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,31 +56,80 @@ pub struct ProcessedDocument {
     pub metadata: DocumentMetadata,
 }
 
-pub struct DocumentProcessor;
+/// Milestones reported while a document is ingested
+#[derive(Debug, Clone, PartialEq)]
+pub enum IngestProgress {
+    /// A directory or glob matched `files` files to ingest
+    Discovered { files: usize },
+    /// The file was read and split into `chunks`
+    Chunked { file_path: String, chunks: usize },
+    /// `done` of `total` chunks have embeddings, counting ones served from the cache
+    Embedded { done: usize, total: usize },
+    /// The document and its chunks are in storage
+    Stored { document_id: String, chunks: usize },
+    /// A file of a multi-file ingest could not be processed
+    Failed { file_path: String, error: String },
+}
+
+pub type IngestProgressCallback = std::sync::Arc<dyn Fn(&IngestProgress) + Send + Sync>;
+
+/// Reads files as UTF-8 text, or through the extractor registered for their extension
+#[derive(Clone, Default)]
+pub struct DocumentProcessor {
+    /// By lowercased file extension
+    extractors: BTreeMap<String, Arc<dyn ContentExtractor>>,
+}
 
 impl DocumentProcessor {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Read files ending in `.extension` with `extractor`
+    pub fn with_extractor(mut self, extension: &str, extractor: Arc<dyn ContentExtractor>) -> Self {
+        self.extractors.insert(extension.trim_start_matches('.').to_lowercase(), extractor);
+        self
     }
 
     pub fn process_file(&self, file_path: &Path) -> Result<ProcessedDocument> {
-        let content = fs::read_to_string(file_path)?;
-        let metadata = file_path.metadata()?;
+        let bytes = fs::read(file_path)?;
+        let mut document = self.process_bytes(&file_path.to_string_lossy(), &bytes)?;
+        document.metadata.modified_at = modified_secs(file_path);
+        Ok(document)
+    }
 
-        let word_count = content.split_whitespace().count();
-        let file_type = file_path.extension()
+    /// Document from an in-memory buffer, so no filesystem is needed; `name` is recorded as its path
+    pub fn process_bytes(&self, name: &str, bytes: &[u8]) -> Result<ProcessedDocument> {
+        let file_type = Path::new(name)
+            .extension()
             .and_then(|ext| ext.to_str())
             .unwrap_or("txt")
             .to_string();
+        let content = match self.extractors.get(&file_type.to_lowercase()) {
+            Some(extractor) => extractor.extract(name, bytes)?,
+            None => std::str::from_utf8(bytes)
+                .map_err(|e| anyhow!("{} is not valid UTF-8 text: {}", name, e))?
+                .to_string(),
+        };
+
+        let word_count = content.split_whitespace().count();
+        let language = detect_language(&content).map(str::to_string);
 
         let doc = ProcessedDocument {
             id: uuid::Uuid::new_v4().to_string(),
             content,
             metadata: DocumentMetadata {
-                file_path: file_path.to_string_lossy().to_string(),
+                file_path: name.to_string(),
                 file_type,
-                file_size: metadata.len() as usize,
+                file_size: bytes.len(),
                 word_count,
+                summary: None,
+                relations: Vec::new(),
+                tags: BTreeMap::new(),
+                tenant: None,
+                language,
+                ingested_at: Some(unix_now()),
+                modified_at: None,
             },
         };
 
@@ -59,8 +144,7 @@ mod tests {
 
     #[test]
     fn test_processor_creation() {
-        let processor = DocumentProcessor::new();
-        assert!(true); // Just test that it doesn't panic
+        let _processor = DocumentProcessor::new(); // Just test that it doesn't panic
     }
 
     #[test]
@@ -78,4 +162,18 @@ mod tests {
 
         fs::remove_file(test_file).unwrap();
     }
+
+    #[test]
+    fn test_bytes_processing_needs_no_file() {
+        let processor = DocumentProcessor::new();
+        let content = "# Intro\n\nHello there.";
+
+        let document = processor.process_bytes("notes/intro.md", content.as_bytes()).unwrap();
+        assert_eq!(document.content, content);
+        assert_eq!(document.metadata.file_path, "notes/intro.md");
+        assert_eq!(document.metadata.file_type, "md");
+        assert_eq!(document.metadata.file_size, content.len());
+
+        assert!(processor.process_bytes("blob.bin", &[0xff, 0xfe]).is_err());
+    }
 }
\ No newline at end of file
@@ -0,0 +1,409 @@
+//! Quantization of stored embeddings, trading accuracy for memory.
+//!
+//! Int8 keeps one signed byte per dimension plus the vector's scale, a quarter of the memory of `f32`s.
+//! It is symmetric, mapping the vector's largest magnitude to 127. Binary adds the sign of each
+//! dimension to the full vector; searches first pick candidates by Hamming distance between sign
+//! bits, then rescore only those against their full vectors, saving scoring time rather than
+//! memory. Product quantization sits between int8 and `f32`s; see `pq`.
+//!
+//! Queries always stay full precision and are scored against the stored codes directly, so nothing
+//! is dequantized on the search path.
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use crate::embedding::cosine_similarity;
+use crate::pq::{self, PqCodebook, PqVector};
+
+/// How chunk embeddings are kept in the index
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EmbeddingQuantization {
+    /// Four bytes per dimension, exactly as the provider returned them
+    #[default]
+    None,
+    /// One byte per dimension
+    Int8,
+    /// Sign bits to prefilter with by Hamming distance, beside the full vectors candidates are rescored with
+    Binary,
+    /// One byte per eight dimensions from a codebook trained on the stored vectors
+    Pq,
+}
+
+impl std::str::FromStr for EmbeddingQuantization {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(EmbeddingQuantization::None),
+            "int8" => Ok(EmbeddingQuantization::Int8),
+            "binary" => Ok(EmbeddingQuantization::Binary),
+            "pq" => Ok(EmbeddingQuantization::Pq),
+            other => Err(anyhow!("Unknown embedding quantization '{}' (expected none, int8, binary or pq)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantizedVector {
+    /// Value of one step; the original component is about `scale * value`
+    pub scale: f32,
+    pub values: Vec<i8>,
+}
+
+impl QuantizedVector {
+    pub fn quantize(vector: &[f32]) -> Self {
+        let max = vector.iter().fold(0.0f32, |max, x| max.max(x.abs()));
+        let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+        Self {
+            scale,
+            values: vector.iter().map(|x| (x / scale).round().clamp(-127.0, 127.0) as i8).collect(),
+        }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&v| v as f32 * self.scale).collect()
+    }
+
+    /// Cosine similarity of a full-precision `query` with this vector
+    pub fn cosine(&self, query: &[f32]) -> f32 {
+        let (mut dot, mut norm_query, mut norm_values) = (0.0f32, 0.0f32, 0.0f32);
+        for (q, &v) in query.iter().zip(&self.values) {
+            let v = v as f32;
+            dot += q * v;
+            norm_query += q * q;
+            norm_values += v * v;
+        }
+        if norm_query == 0.0 || norm_values == 0.0 {
+            0.0
+        } else {
+            dot / (norm_query.sqrt() * norm_values.sqrt())
+        }
+    }
+}
+
+/// Signs of a vector's components, packed 64 to a word
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BinaryVector {
+    pub dimensions: usize,
+    pub bits: Vec<u64>,
+}
+
+impl BinaryVector {
+    /// Set bits for the positive components
+    pub fn quantize(vector: &[f32]) -> Self {
+        let mut bits = vec![0u64; vector.len().div_ceil(64)];
+        for (i, _) in vector.iter().enumerate().filter(|(_, x)| **x > 0.0) {
+            bits[i / 64] |= 1 << (i % 64);
+        }
+        Self { dimensions: vector.len(), bits }
+    }
+
+    /// Unit vector of +1s and -1s along the stored signs
+    pub fn dequantize(&self) -> Vec<f32> {
+        let magnitude = 1.0 / (self.dimensions.max(1) as f32).sqrt();
+        (0..self.dimensions).map(|i| if self.bit(i) { magnitude } else { -magnitude }).collect()
+    }
+
+    /// Dimensions whose signs differ between the two codes
+    pub fn hamming(&self, other: &BinaryVector) -> u32 {
+        self.bits.iter().zip(&other.bits).map(|(a, b)| (a ^ b).count_ones()).sum()
+    }
+
+    /// Cosine similarity of a full-precision `query` with the signs
+    pub fn cosine(&self, query: &[f32]) -> f32 {
+        let (mut dot, mut norm_query) = (0.0f32, 0.0f32);
+        for (i, q) in query.iter().enumerate().take(self.dimensions) {
+            dot += if self.bit(i) { *q } else { -q };
+            norm_query += q * q;
+        }
+        if norm_query == 0.0 || self.dimensions == 0 {
+            0.0
+        } else {
+            dot / (norm_query.sqrt() * (self.dimensions as f32).sqrt())
+        }
+    }
+
+    fn bit(&self, i: usize) -> bool {
+        self.bits[i / 64] & (1 << (i % 64)) != 0
+    }
+}
+
+/// Sign bits of a vector kept with the vector itself, so the candidates the bits pick are rescored at
+/// full precision
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BinaryEmbedding {
+    #[serde(flatten)]
+    pub code: BinaryVector,
+    /// Empty in indexes saved before the full vectors were kept, which score by the signs alone
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub full: Vec<f32>,
+}
+
+impl BinaryEmbedding {
+    pub fn new(vector: Vec<f32>) -> Self {
+        Self { code: BinaryVector::quantize(&vector), full: vector }
+    }
+
+    /// Cosine similarity of a full-precision `query` with the full vector, or the signs without one
+    pub fn cosine(&self, query: &[f32]) -> f32 {
+        if self.full.is_empty() {
+            self.code.cosine(query)
+        } else {
+            cosine_similarity(query, &self.full)
+        }
+    }
+}
+
+/// Positions of the `keep` codes nearest `query` by Hamming distance; `None` when there are no more
+/// codes than that, so every one is scored. Positions without a code are left out
+pub fn hamming_prefilter(query: &[f32], codes: &[Option<&BinaryVector>], keep: usize) -> Option<HashSet<usize>> {
+    if codes.iter().flatten().count() <= keep {
+        return None;
+    }
+    let query = BinaryVector::quantize(query);
+    let mut distances: Vec<(u32, usize)> =
+        codes.iter().enumerate().filter_map(|(i, code)| code.map(|c| (c.hamming(&query), i))).collect();
+    distances.sort_unstable();
+    Some(distances.into_iter().take(keep).map(|(_, i)| i).collect())
+}
+
+/// A chunk embedding as the index keeps it. Full vectors serialize as plain arrays, as they always have
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum StoredEmbedding {
+    Full(Vec<f32>),
+    Int8(QuantizedVector),
+    Binary(BinaryEmbedding),
+    Pq(PqVector),
+}
+
+impl StoredEmbedding {
+    /// `vector` in `quantization`'s form; product quantization also needs a codebook, and without one
+    /// the vector stays full precision
+    pub fn new(vector: Vec<f32>, quantization: EmbeddingQuantization, codebook: Option<&Arc<PqCodebook>>) -> Self {
+        match quantization {
+            EmbeddingQuantization::None => StoredEmbedding::Full(vector),
+            EmbeddingQuantization::Pq => match codebook {
+                Some(codebook) => StoredEmbedding::Pq(codebook.encode(&vector)),
+                None => StoredEmbedding::Full(vector),
+            },
+            EmbeddingQuantization::Int8 => StoredEmbedding::Int8(QuantizedVector::quantize(&vector)),
+            EmbeddingQuantization::Binary => StoredEmbedding::Binary(BinaryEmbedding::new(vector)),
+        }
+    }
+
+    /// This embedding in `quantization`'s form if that's coarser than its own; precision that was
+    /// quantized away can't be brought back
+    pub fn quantized(self, quantization: EmbeddingQuantization, codebook: Option<&Arc<PqCodebook>>) -> Self {
+        match (self, quantization) {
+            (StoredEmbedding::Full(vector), EmbeddingQuantization::Int8) => {
+                StoredEmbedding::Int8(QuantizedVector::quantize(&vector))
+            }
+            (embedding @ (StoredEmbedding::Full(_) | StoredEmbedding::Int8(_)), EmbeddingQuantization::Pq) => {
+                match codebook {
+                    Some(codebook) => StoredEmbedding::Pq(codebook.encode(&embedding.to_vec())),
+                    None => embedding,
+                }
+            }
+            (
+                embedding @ (StoredEmbedding::Full(_) | StoredEmbedding::Int8(_) | StoredEmbedding::Pq(_)),
+                EmbeddingQuantization::Binary,
+            ) => StoredEmbedding::Binary(BinaryEmbedding::new(embedding.to_vec())),
+            (embedding, _) => embedding,
+        }
+    }
+
+    /// The first `dimensions` components, as Matryoshka-trained models allow searching with
+    pub fn truncated(self, dimensions: usize) -> Self {
+        match self {
+            StoredEmbedding::Full(mut vector) => {
+                vector.truncate(dimensions);
+                StoredEmbedding::Full(vector)
+            }
+            StoredEmbedding::Int8(mut quantized) => {
+                quantized.values.truncate(dimensions);
+                StoredEmbedding::Int8(quantized)
+            }
+            StoredEmbedding::Binary(mut binary) if dimensions < binary.code.dimensions => {
+                let code = &mut binary.code;
+                code.bits.truncate(dimensions.div_ceil(64));
+                if let Some(last) = code.bits.last_mut().filter(|_| !dimensions.is_multiple_of(64)) {
+                    *last &= (1 << (dimensions % 64)) - 1;
+                }
+                code.dimensions = dimensions;
+                binary.full.truncate(dimensions);
+                StoredEmbedding::Binary(binary)
+            }
+            embedding => embedding,
+        }
+    }
+
+    pub fn is_quantized(&self) -> bool {
+        !matches!(self, StoredEmbedding::Full(_))
+    }
+
+    pub fn to_vec(&self) -> Vec<f32> {
+        match self {
+            StoredEmbedding::Full(vector) => vector.clone(),
+            StoredEmbedding::Int8(quantized) => quantized.dequantize(),
+            StoredEmbedding::Binary(binary) if binary.full.is_empty() => binary.code.dequantize(),
+            StoredEmbedding::Binary(binary) => binary.full.clone(),
+            StoredEmbedding::Pq(pq) => pq.dequantize(),
+        }
+    }
+
+    /// Bytes the vector's components take in memory
+    pub fn memory_bytes(&self) -> usize {
+        match self {
+            StoredEmbedding::Full(vector) => vector.len() * std::mem::size_of::<f32>(),
+            StoredEmbedding::Int8(quantized) => quantized.values.len() + std::mem::size_of::<f32>(),
+            StoredEmbedding::Binary(binary) => {
+                binary.code.bits.len() * std::mem::size_of::<u64>() + binary.full.len() * std::mem::size_of::<f32>()
+            }
+            StoredEmbedding::Pq(pq) => pq.codes.len() + std::mem::size_of::<f32>(),
+        }
+    }
+}
+
+/// A copy of full-precision `embeddings` in `quantization`'s form, for measuring what quantizing
+/// would cost while the stored ones stay as they are
+pub fn quantized_copy(
+    embeddings: &HashMap<String, Vec<f32>>,
+    quantization: EmbeddingQuantization,
+) -> Result<HashMap<String, StoredEmbedding>> {
+    let codebook = match quantization {
+        EmbeddingQuantization::Pq => Some(pq::train_if_enough(embeddings)?.ok_or_else(|| {
+            anyhow!("Product quantization needs at least {} embeddings to train on", pq::PQ_MIN_TRAINING_VECTORS)
+        })?),
+        _ => None,
+    };
+    Ok(embeddings
+        .iter()
+        .map(|(id, vector)| (id.clone(), StoredEmbedding::new(vector.clone(), quantization, codebook.as_ref())))
+        .collect())
+}
+
+/// A stored vector a full-precision query embedding can be scored against
+pub trait EmbeddingVector {
+    fn cosine(&self, query: &[f32]) -> f32;
+    fn dimensions(&self) -> usize;
+
+    /// Sign bits to prefilter by, for binary embeddings
+    fn binary_code(&self) -> Option<&BinaryVector> {
+        None
+    }
+
+    /// Codes to score through a query's distance table, for product-quantized embeddings
+    fn pq_code(&self) -> Option<&PqVector> {
+        None
+    }
+}
+
+impl EmbeddingVector for Vec<f32> {
+    fn cosine(&self, query: &[f32]) -> f32 {
+        cosine_similarity(query, self)
+    }
+
+    fn dimensions(&self) -> usize {
+        self.len()
+    }
+}
+
+impl EmbeddingVector for StoredEmbedding {
+    fn cosine(&self, query: &[f32]) -> f32 {
+        match self {
+            StoredEmbedding::Full(vector) => cosine_similarity(query, vector),
+            StoredEmbedding::Int8(quantized) => quantized.cosine(query),
+            StoredEmbedding::Binary(binary) => binary.cosine(query),
+            StoredEmbedding::Pq(pq) => pq.cosine(query),
+        }
+    }
+
+    fn dimensions(&self) -> usize {
+        match self {
+            StoredEmbedding::Full(vector) => vector.len(),
+            StoredEmbedding::Int8(quantized) => quantized.values.len(),
+            StoredEmbedding::Binary(binary) => binary.code.dimensions,
+            StoredEmbedding::Pq(pq) => pq.codebook.dimensions,
+        }
+    }
+
+    fn binary_code(&self) -> Option<&BinaryVector> {
+        match self {
+            StoredEmbedding::Binary(binary) => Some(&binary.code),
+            _ => None,
+        }
+    }
+
+    fn pq_code(&self) -> Option<&PqVector> {
+        match self {
+            StoredEmbedding::Pq(pq) => Some(pq),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_int8_vectors_score_close_to_full_precision() {
+        let vector: Vec<f32> = (0..64).map(|i| ((i * 37 % 23) as f32 - 11.0) / 13.0).collect();
+        let query: Vec<f32> = (0..64).map(|i| ((i * 11 % 17) as f32 - 8.0) / 9.0).collect();
+        let full = StoredEmbedding::new(vector.clone(), EmbeddingQuantization::None, None);
+        let int8 = full.clone().quantized(EmbeddingQuantization::Int8, None);
+
+        assert!(int8.is_quantized());
+        assert!((full.cosine(&query) - int8.cosine(&query)).abs() < 0.01);
+        assert!(int8.to_vec().iter().zip(&vector).all(|(a, b)| (a - b).abs() < 0.01));
+        assert_eq!(full.memory_bytes(), 256);
+        assert_eq!(int8.memory_bytes(), 68);
+        assert_eq!(QuantizedVector::quantize(&[0.0, 0.0]).cosine(&[1.0, 0.0]), 0.0);
+
+        // Old snapshots hold plain arrays
+        let parsed: StoredEmbedding = serde_json::from_str("[0.5, 1.0]").unwrap();
+        assert_eq!(parsed, StoredEmbedding::Full(vec![0.5, 1.0]));
+        let round_trip: StoredEmbedding = serde_json::from_str(&serde_json::to_string(&int8).unwrap()).unwrap();
+        assert_eq!(round_trip, int8);
+    }
+
+    #[test]
+    fn test_hamming_prefilter_keeps_nearest_codes() {
+        let query = [0.9, -0.2, 0.4, -0.7];
+        let near = BinaryVector::quantize(&[0.5, -0.1, 0.3, -0.2]);
+        let middle = BinaryVector::quantize(&[0.5, 0.1, 0.3, -0.2]);
+        let far = BinaryVector::quantize(&[-0.5, 0.1, -0.3, 0.2]);
+        assert_eq!((near.hamming(&far), near.hamming(&middle)), (4, 1));
+        assert!((near.cosine(&query) - 2.2 / (1.5f32.sqrt() * 2.0)).abs() < 1e-6);
+        assert_eq!(near.dequantize(), [0.5, -0.5, 0.5, -0.5]);
+
+        let codes = [Some(&far), None, Some(&near), Some(&middle)];
+        assert_eq!(hamming_prefilter(&query, &codes, 2), Some(HashSet::from([2, 3])));
+        assert_eq!(hamming_prefilter(&query, &codes, 3), None);
+
+        let binary = StoredEmbedding::new(vec![0.2; 100], EmbeddingQuantization::Binary, None);
+        assert_eq!((binary.memory_bytes(), binary.dimensions()), (16 + 400, 100));
+        assert_eq!(binary.to_vec(), vec![0.2; 100]);
+        let parsed: StoredEmbedding = serde_json::from_str(&serde_json::to_string(&binary).unwrap()).unwrap();
+        assert_eq!(parsed, binary);
+
+        // Indexes saved before the full vectors were kept score by the signs
+        let legacy: StoredEmbedding = serde_json::from_str(&serde_json::to_string(&near).unwrap()).unwrap();
+        assert_eq!(legacy.cosine(&query), near.cosine(&query));
+    }
+
+    #[test]
+    fn test_truncation_keeps_leading_dimensions() {
+        let vector: Vec<f32> = (0..70).map(|i| if i % 3 == 0 { -1.0 } else { 1.0 }).collect();
+        for quantization in [EmbeddingQuantization::None, EmbeddingQuantization::Int8, EmbeddingQuantization::Binary] {
+            let truncated = StoredEmbedding::new(vector.clone(), quantization, None).truncated(65);
+            assert_eq!(truncated.dimensions(), 65);
+            assert_eq!(truncated, StoredEmbedding::new(vector[..65].to_vec(), quantization, None));
+        }
+        let short = StoredEmbedding::new(vec![1.0, 2.0], EmbeddingQuantization::None, None);
+        assert_eq!(short.clone().truncated(8), short);
+    }
+}
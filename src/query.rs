@@ -0,0 +1,606 @@
+//! A small query language for power users: `field:value` conditions, ranges, `"quoted phrases"`,
+//! `AND`/`OR`/`NOT` (or `-`) and parenthesised groups, parsed into a typed `Query`.
+//!
+//! As in most search engines, bare words at the top level only rank results, so a plain question
+//! searches exactly as it always did. Field conditions, phrases, groups and negations restrict which
+//! chunks can be found; their words count towards the ranking too, except under a negation.
+//!
+//! A field without a meaning of its own is a tag; `tag.<key>:value` always is, even for a key such as
+//! `type` that names a built-in field.
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+use crate::chunking::DocumentChunk;
+use crate::entities::{contains_phrase, words, EntityKind};
+use crate::processor::DocumentMetadata;
+use crate::tags::{parse_tag, DocumentFilter};
+
+/// Prefix of a field that names a tag, whatever the key
+pub const TAG_FIELD_PREFIX: &str = "tag.";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// A bare word: required only inside a group or under a negation
+    Term(String),
+    /// Words that must appear together, in order, ignoring case and punctuation
+    Phrase(String),
+    Field { field: String, condition: Condition },
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+}
+
+/// What a field's value must be
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Equals(String),
+    /// Numbers compare as numbers and anything else, such as ISO dates, as text; an unset end is open
+    Range { lower: Option<Bound>, upper: Option<Bound> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bound {
+    pub value: String,
+    pub inclusive: bool,
+}
+
+impl Query {
+    /// Parse `input`, such as `leave type:md (policy OR handbook) -draft words:[100 TO *]`
+    pub fn parse(input: &str) -> Result<Self> {
+        let tokens = lex(input)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let query = parser.or()?;
+        match parser.tokens.get(parser.position) {
+            None => Ok(query.unwrap_or_else(|| Query::And(Vec::new()))),
+            Some(Token::Close) => Err(anyhow!("Unbalanced ')' in query")),
+            Some(token) => Err(anyhow!("Unexpected {} in query", token)),
+        }
+    }
+
+    /// `field:value`, as in `type:md` or `project:alpha`
+    pub fn field(field: impl Into<String>, value: impl Into<String>) -> Self {
+        Query::Field { field: field.into(), condition: Condition::Equals(value.into()) }
+    }
+
+    /// `tag.key:value`, which only ever matches the document's tag `key`
+    pub fn tag(key: &str, value: impl Into<String>) -> Self {
+        Self::field(format!("{}{}", TAG_FIELD_PREFIX, key), value)
+    }
+
+    /// The whole of `text` as one term, which ranks and restricts nothing, as a search before the
+    /// query language did
+    pub fn raw(text: impl Into<String>) -> Self {
+        Query::Term(text.into())
+    }
+
+    /// Both this query and `other`
+    pub fn and(self, other: Query) -> Self {
+        let mut clauses = match self {
+            Query::And(clauses) => clauses,
+            query => vec![query],
+        };
+        match other {
+            Query::And(more) => clauses.extend(more),
+            other => clauses.push(other),
+        }
+        Query::And(clauses)
+    }
+
+    /// Text the results are ranked by: every term and phrase outside a negation
+    pub fn text(&self) -> String {
+        let mut text = Vec::new();
+        self.collect_text(&mut text);
+        text.join(" ")
+    }
+
+    fn collect_text<'a>(&'a self, text: &mut Vec<&'a str>) {
+        match self {
+            Query::Term(word) | Query::Phrase(word) => text.push(word),
+            Query::And(clauses) | Query::Or(clauses) => clauses.iter().for_each(|clause| clause.collect_text(text)),
+            Query::Field { .. } | Query::Not(_) => {}
+        }
+    }
+
+    /// Whether anything besides top-level terms, which only rank, limits what can be found
+    pub fn has_constraints(&self) -> bool {
+        match self {
+            Query::Term(_) => false,
+            Query::And(clauses) => clauses.iter().any(|clause| !matches!(clause, Query::Term(_))),
+            _ => true,
+        }
+    }
+
+    /// Whether a chunk of the document `metadata` describes passes the query's constraints
+    pub fn matches(&self, chunk: &DocumentChunk, metadata: &DocumentMetadata) -> bool {
+        let content = words(&chunk.content);
+        match self {
+            Query::Term(_) => true,
+            Query::And(clauses) => clauses
+                .iter()
+                .filter(|clause| !matches!(clause, Query::Term(_)))
+                .all(|clause| clause.holds(chunk, &content, metadata)),
+            query => query.holds(chunk, &content, metadata),
+        }
+    }
+
+    fn holds(&self, chunk: &DocumentChunk, content: &[String], metadata: &DocumentMetadata) -> bool {
+        match self {
+            Query::Term(text) | Query::Phrase(text) => contains_phrase(content, &words(text)),
+            Query::Field { field, condition } => field_holds(field, condition, chunk, metadata),
+            Query::And(clauses) => clauses.iter().all(|clause| clause.holds(chunk, content, metadata)),
+            Query::Or(clauses) => clauses.iter().any(|clause| clause.holds(chunk, content, metadata)),
+            Query::Not(query) => !query.holds(chunk, content, metadata),
+        }
+    }
+}
+
+impl std::str::FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Query::parse(s)
+    }
+}
+
+impl AsRef<Query> for Query {
+    fn as_ref(&self) -> &Query {
+        self
+    }
+}
+
+/// A query with the text it was typed as, which the query log records instead of the parsed form
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypedQuery {
+    pub input: String,
+    pub query: Query,
+}
+
+impl TypedQuery {
+    pub fn parse(input: &str) -> Result<Self> {
+        Ok(Self { input: input.to_string(), query: Query::parse(input)? })
+    }
+
+    /// `input` searched as plain text, its syntax ignored; see `Query::raw`
+    pub fn raw(input: &str) -> Self {
+        Self { input: input.to_string(), query: Query::raw(input) }
+    }
+
+    /// Also require `other`, which the logged text leaves out
+    pub fn and(self, other: Query) -> Self {
+        Self { input: self.input, query: self.query.and(other) }
+    }
+}
+
+impl AsRef<Query> for TypedQuery {
+    fn as_ref(&self) -> &Query {
+        &self.query
+    }
+}
+
+impl fmt::Display for TypedQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.input)
+    }
+}
+
+/// The same conditions as `filter`, each required
+impl From<&DocumentFilter> for Query {
+    fn from(filter: &DocumentFilter) -> Self {
+        let tags = filter.tags.iter().map(|(key, value)| Query::tag(key, value));
+        let entities = filter.entities.iter().map(|(kind, name)| Query::field("entity", format!("{:?}={}", kind, name)));
+        Query::And(tags.chain(entities).collect())
+    }
+}
+
+/// Fields with a meaning of their own; any other field is a tag
+fn field_holds(field: &str, condition: &Condition, chunk: &DocumentChunk, metadata: &DocumentMetadata) -> bool {
+    if let Some(tag) = field.strip_prefix(TAG_FIELD_PREFIX) {
+        return metadata.tags.get(tag).is_some_and(|value| condition.holds(value));
+    }
+    match (field, condition) {
+        ("type", Condition::Equals(value)) => metadata.file_type.eq_ignore_ascii_case(value),
+        ("path", Condition::Equals(value)) => metadata.file_path.to_lowercase().contains(&value.to_lowercase()),
+        ("language", Condition::Equals(value)) => metadata.language().is_some_and(|language| language.eq_ignore_ascii_case(value)),
+        ("entity", Condition::Equals(value)) => {
+            let Ok((kind, name)) = parse_tag(value) else {
+                return chunk.entities.iter().any(|entity| entity.matches_name(value));
+            };
+            let Ok(kind) = kind.parse::<EntityKind>() else {
+                return false;
+            };
+            chunk.entities.iter().any(|entity| entity.kind == kind && entity.matches_name(&name))
+        }
+        ("size", condition) => condition.holds(&metadata.file_size.to_string()),
+        ("words", condition) => condition.holds(&metadata.word_count.to_string()),
+        (tag, condition) => metadata.tags.get(tag).is_some_and(|value| condition.holds(value)),
+    }
+}
+
+impl Condition {
+    fn holds(&self, value: &str) -> bool {
+        match self {
+            Condition::Equals(expected) => value == expected,
+            Condition::Range { lower, upper } => {
+                let above = lower.as_ref().is_none_or(|bound| match compare(value, &bound.value) {
+                    std::cmp::Ordering::Greater => true,
+                    std::cmp::Ordering::Equal => bound.inclusive,
+                    std::cmp::Ordering::Less => false,
+                });
+                let below = upper.as_ref().is_none_or(|bound| match compare(value, &bound.value) {
+                    std::cmp::Ordering::Less => true,
+                    std::cmp::Ordering::Equal => bound.inclusive,
+                    std::cmp::Ordering::Greater => false,
+                });
+                above && below
+            }
+        }
+    }
+}
+
+fn compare(a: &str, b: &str) -> std::cmp::Ordering {
+    match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.total_cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Leaf(Query),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::And => f.write_str("AND"),
+            Token::Or => f.write_str("OR"),
+            Token::Not => f.write_str("NOT"),
+            Token::Leaf(query) => write!(f, "'{}'", query),
+        }
+    }
+}
+
+fn lex(input: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = input.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            '"' => {
+                chars.next();
+                tokens.push(Token::Leaf(Query::Phrase(quoted(input, &mut chars)?)));
+            }
+            // A leading dash negates, but one inside a word such as `e-mail` is just part of it
+            '-' if tokens_can_follow(input, start) => {
+                chars.next();
+                tokens.push(Token::Not);
+            }
+            _ => {
+                let mut end = start;
+                while let Some(&(i, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '"' {
+                        break;
+                    }
+                    end = i + c.len_utf8();
+                    chars.next();
+                    let value_follows = chars.peek().is_some_and(|&(_, next)| !next.is_whitespace() && !"/:)".contains(next));
+                    if c == ':' && value_follows && is_field_name(&input[start..i]) {
+                        tokens.push(Token::Leaf(field(&input[start..i], input, &mut chars)?));
+                        end = start;
+                        break;
+                    }
+                }
+                match &input[start..end] {
+                    "" => {}
+                    "AND" => tokens.push(Token::And),
+                    "OR" => tokens.push(Token::Or),
+                    "NOT" => tokens.push(Token::Not),
+                    word => tokens.push(Token::Leaf(Query::Term(word.to_string()))),
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Whether a dash at `at` starts a negation: it must begin a word and be followed by something to negate
+fn tokens_can_follow(input: &str, at: usize) -> bool {
+    input[at + 1..].chars().next().is_some_and(|next| !next.is_whitespace())
+}
+
+/// Field names start with a letter, so times like `10:30` and words like `note:` stay plain text
+fn is_field_name(name: &str) -> bool {
+    name.starts_with(char::is_alphabetic) && name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '.' || c == '-')
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+/// Text up to the closing quote, the opening one already consumed
+fn quoted(input: &str, chars: &mut Chars) -> Result<String> {
+    let start = chars.peek().map_or(input.len(), |&(i, _)| i);
+    for (i, c) in chars.by_ref() {
+        if c == '"' {
+            return Ok(input[start..i].to_string());
+        }
+    }
+    Err(anyhow!("Unclosed quote in query"))
+}
+
+/// The condition after `name:`: a quoted value, a `[a TO b]` range, a comparison or a plain value
+fn field(name: &str, input: &str, chars: &mut Chars) -> Result<Query> {
+    let condition = match chars.peek().map(|&(_, c)| c) {
+        Some('"') => {
+            chars.next();
+            Condition::Equals(quoted(input, chars)?)
+        }
+        Some(open @ ('[' | '{')) => {
+            chars.next();
+            let start = chars.peek().map_or(input.len(), |&(i, _)| i);
+            let Some((end, close)) = chars.by_ref().find(|&(_, c)| c == ']' || c == '}') else {
+                return Err(anyhow!("Unclosed range for field '{}'", name));
+            };
+            let parts: Vec<&str> = input[start..end].split_whitespace().collect();
+            let [lower, "TO", upper] = parts[..] else {
+                return Err(anyhow!("Expected a range like {}:[low TO high], got '{}'", name, &input[start..end]));
+            };
+            let bound = |value: &str, inclusive: bool| (value != "*").then(|| Bound { value: value.to_string(), inclusive });
+            Condition::Range { lower: bound(lower, open == '['), upper: bound(upper, close == ']') }
+        }
+        _ => {
+            let mut value = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c.is_whitespace() || c == '(' || c == ')' {
+                    break;
+                }
+                value.push(c);
+                chars.next();
+            }
+            comparison(&value).unwrap_or(Condition::Equals(value))
+        }
+    };
+    if matches!(&condition, Condition::Equals(value) if value.is_empty()) {
+        return Err(anyhow!("Field '{}' has no value", name));
+    }
+    Ok(Query::Field { field: name.to_string(), condition })
+}
+
+/// `>=10`, `<2024-01-01` and the like
+fn comparison(value: &str) -> Option<Condition> {
+    let (operator, rest) = [">=", "<=", ">", "<"].iter().find_map(|op| Some((*op, value.strip_prefix(op)?)))?;
+    if rest.is_empty() {
+        return None;
+    }
+    let bound = Some(Bound { value: rest.to_string(), inclusive: operator.ends_with('=') });
+    Some(match operator {
+        ">=" | ">" => Condition::Range { lower: bound, upper: None },
+        _ => Condition::Range { lower: None, upper: bound },
+    })
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    /// Clauses joined by `OR`; `None` for an empty query or group
+    fn or(&mut self) -> Result<Option<Query>> {
+        let Some(first) = self.and()? else {
+            return Ok(None);
+        };
+        let mut clauses = vec![first];
+        while self.peek() == Some(&Token::Or) {
+            self.position += 1;
+            clauses.push(self.and()?.ok_or_else(|| anyhow!("Expected a term after OR"))?);
+        }
+        Ok(Some(if clauses.len() == 1 { clauses.remove(0) } else { Query::Or(clauses) }))
+    }
+
+    /// Clauses side by side, or joined by `AND`, all of which must hold
+    fn and(&mut self) -> Result<Option<Query>> {
+        let mut clauses = Vec::new();
+        loop {
+            match self.peek() {
+                None | Some(Token::Or) | Some(Token::Close) => break,
+                Some(Token::And) if clauses.is_empty() => return Err(anyhow!("Expected a term before AND")),
+                Some(Token::And) => {
+                    self.position += 1;
+                    clauses.push(self.unary()?.ok_or_else(|| anyhow!("Expected a term after AND"))?);
+                }
+                Some(_) => clauses.extend(self.unary()?),
+            }
+        }
+        Ok(match clauses.len() {
+            0 => None,
+            1 => clauses.pop(),
+            _ => Some(Query::And(clauses)),
+        })
+    }
+
+    fn unary(&mut self) -> Result<Option<Query>> {
+        let Some(token) = self.tokens.get(self.position).cloned() else {
+            return Ok(None);
+        };
+        self.position += 1;
+        match token {
+            Token::Not => {
+                let negated = self.unary()?.ok_or_else(|| anyhow!("Expected a term after NOT"))?;
+                Ok(Some(Query::Not(Box::new(negated))))
+            }
+            Token::Open => {
+                let group = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(anyhow!("Unbalanced '(' in query"));
+                }
+                self.position += 1;
+                // A group of one term still requires it, unlike a bare top-level term
+                Ok(group.map(|group| match group {
+                    Query::Term(word) => Query::And(vec![Query::Phrase(word)]),
+                    group => group,
+                }))
+            }
+            Token::Leaf(query) => Ok(Some(query)),
+            token => Err(anyhow!("Unexpected {} in query", token)),
+        }
+    }
+}
+
+impl fmt::Display for Query {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |f: &mut fmt::Formatter<'_>, clauses: &[Query], separator: &str| -> fmt::Result {
+            for (i, clause) in clauses.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(separator)?;
+                }
+                match clause {
+                    Query::And(_) | Query::Or(_) => write!(f, "({})", clause)?,
+                    clause => write!(f, "{}", clause)?,
+                }
+            }
+            Ok(())
+        };
+        match self {
+            Query::Term(word) => f.write_str(word),
+            Query::Phrase(phrase) => write!(f, "\"{}\"", phrase),
+            Query::Field { field, condition: Condition::Equals(value) } if value.contains(char::is_whitespace) => {
+                write!(f, "{}:\"{}\"", field, value)
+            }
+            Query::Field { field, condition: Condition::Equals(value) } => write!(f, "{}:{}", field, value),
+            Query::Field { field, condition: Condition::Range { lower, upper } } => {
+                let open = if lower.as_ref().is_none_or(|b| b.inclusive) { '[' } else { '{' };
+                let close = if upper.as_ref().is_none_or(|b| b.inclusive) { ']' } else { '}' };
+                let end = |bound: &Option<Bound>| bound.as_ref().map_or("*".to_string(), |b| b.value.clone());
+                write!(f, "{}:{}{} TO {}{}", field, open, end(lower), end(upper), close)
+            }
+            Query::And(clauses) => join(f, clauses, " "),
+            Query::Or(clauses) => join(f, clauses, " OR "),
+            Query::Not(query) => match query.as_ref() {
+                Query::And(_) | Query::Or(_) => write!(f, "NOT ({})", query),
+                query => write!(f, "NOT {}", query),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+
+    fn chunk(content: &str) -> DocumentChunk {
+        DocumentChunk {
+            id: "doc_0".to_string(),
+            content: content.into(),
+            start_pos: 0,
+            end_pos: 0,
+            word_count: 0,
+            document_id: "doc".to_string(),
+            byte_start: 0,
+            byte_end: content.len(),
+            entities: crate::entities::extract_entities(content).into(),
+            keyphrases: Arc::from([]),
+            minhash: Arc::from([]),
+        }
+    }
+
+    fn metadata(file_path: &str, word_count: usize, tags: &[(&str, &str)]) -> DocumentMetadata {
+        DocumentMetadata {
+            file_path: file_path.to_string(),
+            file_type: file_path.rsplit('.').next().unwrap_or("txt").to_string(),
+            file_size: 0,
+            word_count,
+            summary: None,
+            relations: Vec::new(),
+            tags: tags.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<BTreeMap<_, _>>(),
+            tenant: None,
+            language: None,
+            ingested_at: None,
+            modified_at: None,
+        }
+    }
+
+    #[test]
+    fn test_parses_fields_ranges_phrases_and_groups() {
+        let query = Query::parse(r#"leave type:md (policy OR "paid time") -draft words:[100 TO *] year:>=2020"#).unwrap();
+        let Query::And(clauses) = &query else { panic!("expected a conjunction, got {:?}", query) };
+        assert_eq!(clauses[0], Query::Term("leave".to_string()));
+        assert_eq!(clauses[1], Query::field("type", "md"));
+        assert_eq!(
+            clauses[2],
+            Query::Or(vec![Query::Term("policy".to_string()), Query::Phrase("paid time".to_string())])
+        );
+        assert_eq!(clauses[3], Query::Not(Box::new(Query::Term("draft".to_string()))));
+        assert_eq!(query.text(), "leave policy paid time");
+        assert_eq!(
+            query.to_string(),
+            r#"leave type:md (policy OR "paid time") NOT draft words:[100 TO *] year:[2020 TO *]"#
+        );
+        assert_eq!(Query::parse(&query.to_string()).unwrap(), query);
+
+        // Plain questions parse to bare terms and search as before
+        let plain = Query::parse("Error: how do I use std::fs at 10:30 - see http://wiki/e-mail?").unwrap();
+        assert!(!plain.has_constraints());
+        assert_eq!(plain.text(), "Error: how do I use std::fs at 10:30 - see http://wiki/e-mail?");
+
+        for broken in ["(policy", "policy)", "\"paid", "type:\"\"", "words:[1 2]", "OR leave", "leave NOT"] {
+            assert!(Query::parse(broken).is_err(), "{} should not parse", broken);
+        }
+    }
+
+    #[test]
+    fn test_constraints_select_chunks() {
+        let handbook = metadata("handbook/leave.md", 300, &[("collection", "handbook"), ("year", "2023")]);
+        let leave = chunk("Staff get twenty days of paid time off. Ask Alan Turing.");
+        let holds = |query: &str, metadata: &DocumentMetadata| Query::parse(query).unwrap().matches(&leave, metadata);
+
+        assert!(holds("vacation", &handbook), "top-level words only rank");
+        assert!(holds(r#"collection:handbook "paid time""#, &handbook));
+        assert!(!holds(r#""time paid""#, &handbook));
+        assert!(holds("type:MD path:handbook/ words:{100 TO 300]", &handbook));
+        assert!(!holds("words:<300", &handbook));
+        assert!(holds("year:[2020 TO 2023] (twenty OR thirty)", &handbook));
+        assert!(!holds("(vacation OR sabbatical)", &handbook));
+        assert!(!holds("-twenty", &handbook));
+        assert!(holds("entity:person=Turing NOT collection:payroll", &handbook));
+        assert!(!holds("entity:location=Turing", &handbook));
+        assert!(!holds("(vacation)", &handbook));
+
+        let filter = DocumentFilter::default().with_tag("collection", "payroll");
+        assert!(!Query::from(&filter).matches(&leave, &handbook));
+
+        // A tag whose key is also a built-in field
+        let policy = metadata("handbook/leave.md", 300, &[("type", "policy")]);
+        assert!(holds("tag.type:policy", &policy));
+        assert!(!holds("type:policy", &policy));
+        assert!(!holds("tag.type:md", &policy));
+        let filter = DocumentFilter::default().with_tag("type", "policy");
+        assert_eq!(Query::from(&filter).to_string(), "tag.type:policy");
+        assert!(Query::from(&filter).matches(&leave, &policy));
+
+        let raw = TypedQuery::raw("type:md -draft (leave");
+        assert!(!raw.query.has_constraints());
+        assert_eq!(raw.query.text(), "type:md -draft (leave");
+    }
+}
@@ -0,0 +1,78 @@
+//! Opt-in record of the searches run against an index, kept with it for relevance tuning and analytics
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use crate::search::SearchMode;
+use crate::tags::DocumentFilter;
+
+/// Entries kept by default; the oldest are dropped past this
+pub const DEFAULT_MAX_QUERY_LOG_ENTRIES: usize = 10_000;
+
+/// `[query_log]` in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct QueryLogConfig {
+    pub enabled: bool,
+    pub max_entries: usize,
+}
+
+impl Default for QueryLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_entries: DEFAULT_MAX_QUERY_LOG_ENTRIES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueryLogEntry {
+    /// Unix seconds when the search ran
+    pub timestamp: u64,
+    pub query: String,
+    pub mode: SearchMode,
+    #[serde(default, skip_serializing_if = "DocumentFilter::is_empty")]
+    pub filter: DocumentFilter,
+    /// Chunk ids returned, best first
+    pub result_ids: Vec<String>,
+    pub latency_ms: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+/// Write `entries` as JSON lines, the format `logs --export` produces
+pub fn export_jsonl(entries: &[QueryLogEntry], mut out: impl Write) -> Result<()> {
+    for entry in entries {
+        writeln!(out, "{}", serde_json::to_string(entry)?)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export_writes_one_entry_per_line() {
+        let entry = QueryLogEntry {
+            timestamp: 1_700_000_000,
+            query: "paid leave".to_string(),
+            mode: SearchMode::Bm25,
+            filter: DocumentFilter::default(),
+            result_ids: vec!["doc_0".to_string()],
+            latency_ms: 1.5,
+            error: None,
+            tenant: None,
+        };
+        let mut out = Vec::new();
+        export_jsonl(&[entry.clone(), entry.clone()], &mut out).unwrap();
+
+        let lines: Vec<&str> = std::str::from_utf8(&out).unwrap().lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(!lines[0].contains("filter") && !lines[0].contains("error"));
+        assert_eq!(serde_json::from_str::<QueryLogEntry>(lines[1]).unwrap(), entry);
+    }
+}
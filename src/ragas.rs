@@ -0,0 +1,241 @@
+//! RAGAS-style end-to-end RAG quality metrics
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use crate::evaluation::FaithfulnessEvaluator;
+use crate::llm::{CompletionProvider, CompletionRequest};
+use crate::search::tokenize;
+
+/// One question answered by the pipeline, with the contexts it was given
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagasSample {
+    pub question: String,
+    pub answer: String,
+    pub contexts: Vec<String>,
+    /// Reference answer; required for context recall
+    #[serde(default)]
+    pub ground_truth: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RagasScores {
+    /// Rank-weighted share of useful contexts, rewarding useful ones ranked first
+    pub context_precision: f32,
+    /// Share of ground-truth sentences attributable to the contexts
+    pub context_recall: Option<f32>,
+    /// How closely questions regenerated from the answer match the original question
+    pub answer_relevancy: f32,
+    /// Share of answer claims entailed by the contexts
+    pub faithfulness: f32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RagasReport {
+    pub per_sample: Vec<RagasScores>,
+    pub mean: RagasScores,
+}
+
+const CONTEXT_USEFUL_PREAMBLE: &str = "Given a question, a reference answer and a context passage, \
+decide whether the passage was useful in arriving at the reference answer. Respond with YES or NO only.";
+
+const ATTRIBUTION_PREAMBLE: &str = "Given a context and a sentence, decide whether the sentence can be \
+attributed to the context. Respond with YES or NO only.";
+
+const QUESTION_GENERATION_PREAMBLE: &str = "Write a question that the given answer responds to. \
+Respond with the question only.";
+
+/// Scores samples with context precision, context recall, answer relevancy and faithfulness
+pub struct RagasEvaluator {
+    llm: Arc<dyn CompletionProvider>,
+    faithfulness: FaithfulnessEvaluator,
+    generated_questions: usize,
+}
+
+impl RagasEvaluator {
+    pub fn new(llm: Arc<dyn CompletionProvider>) -> Self {
+        Self {
+            faithfulness: FaithfulnessEvaluator::new(llm.clone()),
+            llm,
+            generated_questions: 3,
+        }
+    }
+
+    /// Number of questions regenerated from each answer for answer relevancy
+    pub fn with_generated_questions(mut self, count: usize) -> Self {
+        self.generated_questions = count.max(1);
+        self
+    }
+
+    pub fn evaluate(&self, sample: &RagasSample) -> Result<RagasScores> {
+        let contexts: Vec<&str> = sample.contexts.iter().map(String::as_str).collect();
+        Ok(RagasScores {
+            context_precision: self.context_precision(sample)?,
+            context_recall: self.context_recall(sample)?,
+            answer_relevancy: self.answer_relevancy(sample)?,
+            faithfulness: self.faithfulness.evaluate_texts(&sample.answer, &contexts)?.faithfulness,
+        })
+    }
+
+    pub fn evaluate_all(&self, samples: &[RagasSample]) -> Result<RagasReport> {
+        let per_sample = samples.iter().map(|s| self.evaluate(s)).collect::<Result<Vec<_>>>()?;
+
+        let n = per_sample.len().max(1) as f32;
+        let recalls: Vec<f32> = per_sample.iter().filter_map(|s| s.context_recall).collect();
+        let mean = RagasScores {
+            context_precision: per_sample.iter().map(|s| s.context_precision).sum::<f32>() / n,
+            context_recall: if recalls.is_empty() {
+                None
+            } else {
+                Some(recalls.iter().sum::<f32>() / recalls.len() as f32)
+            },
+            answer_relevancy: per_sample.iter().map(|s| s.answer_relevancy).sum::<f32>() / n,
+            faithfulness: per_sample.iter().map(|s| s.faithfulness).sum::<f32>() / n,
+        };
+
+        Ok(RagasReport { per_sample, mean })
+    }
+
+    fn context_precision(&self, sample: &RagasSample) -> Result<f32> {
+        // Without a reference answer, the generated answer stands in for it
+        let reference = sample.ground_truth.as_deref().unwrap_or(&sample.answer);
+
+        let mut useful_so_far = 0;
+        let mut weighted = 0.0;
+        for (i, context) in sample.contexts.iter().enumerate() {
+            let prompt = format!("Question: {}\n\nReference answer: {}\n\nContext:\n{}\n\nUseful:", sample.question, reference, context);
+            if self.ask_yes_no(prompt, CONTEXT_USEFUL_PREAMBLE)? {
+                useful_so_far += 1;
+                weighted += useful_so_far as f32 / (i + 1) as f32;
+            }
+        }
+
+        Ok(if useful_so_far == 0 { 0.0 } else { weighted / useful_so_far as f32 })
+    }
+
+    fn context_recall(&self, sample: &RagasSample) -> Result<Option<f32>> {
+        let Some(ground_truth) = &sample.ground_truth else {
+            return Ok(None);
+        };
+
+        let sentences: Vec<&str> = ground_truth
+            .split(['.', '!', '?'])
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .collect();
+        if sentences.is_empty() {
+            return Ok(Some(0.0));
+        }
+
+        let context = sample.contexts.join("\n\n");
+        let mut attributed = 0;
+        for sentence in &sentences {
+            let prompt = format!("Context:\n{}\n\nSentence: {}\n\nAttributable:", context, sentence);
+            if self.ask_yes_no(prompt, ATTRIBUTION_PREAMBLE)? {
+                attributed += 1;
+            }
+        }
+
+        Ok(Some(attributed as f32 / sentences.len() as f32))
+    }
+
+    fn answer_relevancy(&self, sample: &RagasSample) -> Result<f32> {
+        // RAGAS compares embeddings of the regenerated questions; term-vector cosine stands in here
+        let original = term_vector(&sample.question);
+        let mut total = 0.0;
+        for _ in 0..self.generated_questions {
+            let request = CompletionRequest::new(format!("Answer:\n{}\n\nQuestion:", sample.answer))
+                .with_preamble(QUESTION_GENERATION_PREAMBLE);
+            let generated = self.llm.complete(&request)?;
+            total += cosine(&original, &term_vector(&generated));
+        }
+        Ok(total / self.generated_questions as f32)
+    }
+
+    fn ask_yes_no(&self, prompt: String, preamble: &str) -> Result<bool> {
+        let request = CompletionRequest::new(prompt).with_preamble(preamble).with_temperature(0.0);
+        let response = self.llm.complete(&request)?;
+        Ok(response.trim().to_lowercase().starts_with("yes"))
+    }
+}
+
+fn term_vector(text: &str) -> HashMap<String, f32> {
+    let mut vector = HashMap::new();
+    for token in tokenize(text) {
+        *vector.entry(token).or_insert(0.0) += 1.0;
+    }
+    vector
+}
+
+fn cosine(a: &HashMap<String, f32>, b: &HashMap<String, f32>) -> f32 {
+    let dot: f32 = a.iter().filter_map(|(t, w)| b.get(t).map(|v| w * v)).sum();
+    let norm_a = a.values().map(|w| w * w).sum::<f32>().sqrt();
+    let norm_b = b.values().map(|w| w * w).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Says YES only for passages mentioning Paris, and echoes a fixed question
+    struct ParisLlm;
+
+    impl CompletionProvider for ParisLlm {
+        fn model_name(&self) -> &str {
+            "paris"
+        }
+
+        fn complete(&self, request: &CompletionRequest) -> Result<String> {
+            if request.prompt.starts_with("Answer:") && request.prompt.ends_with("Question:") {
+                return Ok("What is the capital of France?".to_string());
+            }
+            if request.prompt.starts_with("Answer:") {
+                return Ok("Paris is the capital of France".to_string());
+            }
+            let last_section = request.prompt.rsplit("\n\n").nth(1).unwrap_or("");
+            Ok(if last_section.contains("Paris") { "YES" } else { "NO" }.to_string())
+        }
+    }
+
+    fn sample() -> RagasSample {
+        RagasSample {
+            question: "What is the capital of France?".to_string(),
+            answer: "Paris is the capital of France.".to_string(),
+            contexts: vec![
+                "Berlin is the capital of Germany.".to_string(),
+                "Paris is the capital and largest city of France.".to_string(),
+            ],
+            ground_truth: Some("Paris is the capital of France. Paris lies on the Seine.".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_ragas_scores() {
+        let evaluator = RagasEvaluator::new(Arc::new(ParisLlm)).with_generated_questions(2);
+
+        let scores = evaluator.evaluate(&sample()).unwrap();
+
+        // The only useful context is ranked second
+        assert!((scores.context_precision - 0.5).abs() < 1e-6);
+        assert_eq!(scores.context_recall, Some(1.0));
+        assert!((scores.answer_relevancy - 1.0).abs() < 1e-6);
+        assert_eq!(scores.faithfulness, 1.0);
+    }
+
+    #[test]
+    fn test_context_recall_needs_ground_truth() {
+        let evaluator = RagasEvaluator::new(Arc::new(ParisLlm));
+        let report = evaluator
+            .evaluate_all(&[RagasSample { ground_truth: None, ..sample() }])
+            .unwrap();
+
+        assert_eq!(report.per_sample[0].context_recall, None);
+        assert_eq!(report.mean.context_recall, None);
+    }
+}
@@ -0,0 +1,86 @@
+//! Portable index exports (`.ragpack`) with a versioned format
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::storage::StorageSnapshot;
+
+/// Marker identifying a file as a ragpack
+pub const RAGPACK_FORMAT: &str = "rag-system/ragpack";
+
+/// Version written by this release; older versions are still readable
+pub const RAGPACK_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ragpack {
+    pub format: String,
+    pub version: u32,
+    /// Crate version that wrote the file
+    pub created_by: String,
+    /// Seconds since the Unix epoch
+    pub created_at: u64,
+    pub snapshot: StorageSnapshot,
+}
+
+impl Ragpack {
+    pub fn new(snapshot: StorageSnapshot) -> Self {
+        Self {
+            format: RAGPACK_FORMAT.to_string(),
+            version: RAGPACK_VERSION,
+            created_by: env!("CARGO_PKG_VERSION").to_string(),
+            created_at: crate::answer_cache::unix_now(),
+            snapshot,
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        // Check the header first so a newer or foreign file fails with a clear message
+        #[derive(Deserialize)]
+        struct Header {
+            format: Option<String>,
+            version: Option<u32>,
+        }
+        let header: Header = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("{} is not a ragpack: {}", path.display(), e))?;
+        if header.format.as_deref() != Some(RAGPACK_FORMAT) {
+            return Err(anyhow!("{} is not a ragpack", path.display()));
+        }
+        match header.version {
+            Some(version) if version <= RAGPACK_VERSION => Ok(serde_json::from_str(&content)?),
+            Some(version) => Err(anyhow!(
+                "{} uses ragpack version {}, but this release reads up to {}; upgrade rag-system",
+                path.display(),
+                version,
+                RAGPACK_VERSION
+            )),
+            None => Err(anyhow!("{} has no ragpack version", path.display())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_newer_versions() {
+        let pack_file = "/tmp/test_rag_newer.ragpack";
+        let mut pack = serde_json::to_value(Ragpack::new(StorageSnapshot::default())).unwrap();
+        pack["version"] = serde_json::json!(RAGPACK_VERSION + 1);
+        std::fs::write(pack_file, pack.to_string()).unwrap();
+
+        let error = Ragpack::load(Path::new(pack_file)).unwrap_err();
+        assert!(error.to_string().contains("upgrade"));
+
+        std::fs::write(pack_file, "{\"documents\": []}").unwrap();
+        assert!(Ragpack::load(Path::new(pack_file)).is_err());
+
+        std::fs::remove_file(pack_file).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+//! Per-key request rate and concurrency limits for the HTTP server, so one client can't spend the
+//! whole embedding and LLM budget
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use crate::time::{Duration, Instant};
+
+/// Limits for one key; unset fields don't limit
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RateLimits {
+    /// Sustained request rate, with bursts of up to this many requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+    /// Requests the key may have in flight at once
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_concurrent: Option<usize>,
+}
+
+impl RateLimits {
+    /// These limits, with any unset field taken from `defaults`
+    pub fn or(self, defaults: RateLimits) -> RateLimits {
+        RateLimits {
+            requests_per_minute: self.requests_per_minute.or(defaults.requests_per_minute),
+            max_concurrent: self.max_concurrent.or(defaults.max_concurrent),
+        }
+    }
+}
+
+/// Why a request was refused, and when it's worth retrying
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Throttled {
+    /// The key's requests-per-minute budget is spent
+    Rate { retry_after: Duration },
+    /// The key already has `max_concurrent` requests in flight
+    Concurrency,
+}
+
+impl Throttled {
+    /// Whole seconds for a `Retry-After` header, at least one
+    pub fn retry_after_secs(&self) -> u64 {
+        match self {
+            Throttled::Rate { retry_after } => retry_after.as_secs_f64().ceil().max(1.0) as u64,
+            Throttled::Concurrency => 1,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct KeyState {
+    tokens: f64,
+    refilled: Instant,
+    in_flight: usize,
+}
+
+/// Token bucket and in-flight count per key name
+#[derive(Debug, Clone, Default)]
+pub struct RequestLimiter {
+    keys: Arc<Mutex<HashMap<String, KeyState>>>,
+}
+
+/// Held while a request runs; dropping it frees the key's concurrency slot
+#[derive(Debug)]
+pub struct Permit {
+    keys: Arc<Mutex<HashMap<String, KeyState>>>,
+    key: String,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        if let Some(state) = self.keys.lock().unwrap().get_mut(&self.key) {
+            state.in_flight = state.in_flight.saturating_sub(1);
+        }
+    }
+}
+
+impl RequestLimiter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Admit one request for `key` under `limits`, or say how long to back off
+    pub fn acquire(&self, key: &str, limits: RateLimits) -> Result<Permit, Throttled> {
+        self.acquire_at(key, limits, Instant::now())
+    }
+
+    fn acquire_at(&self, key: &str, limits: RateLimits, now: Instant) -> Result<Permit, Throttled> {
+        let mut keys = self.keys.lock().unwrap();
+        let state = keys.entry(key.to_string()).or_insert_with(|| KeyState {
+            tokens: limits.requests_per_minute.unwrap_or(0) as f64,
+            refilled: now,
+            in_flight: 0,
+        });
+
+        if let Some(max) = limits.max_concurrent {
+            if state.in_flight >= max {
+                return Err(Throttled::Concurrency);
+            }
+        }
+        if let Some(rpm) = limits.requests_per_minute.filter(|&rpm| rpm > 0) {
+            let per_second = rpm as f64 / 60.0;
+            let elapsed = now.saturating_duration_since(state.refilled).as_secs_f64();
+            state.tokens = (state.tokens + elapsed * per_second).min(rpm as f64);
+            state.refilled = now;
+            if state.tokens < 1.0 {
+                let retry_after = Duration::from_secs_f64((1.0 - state.tokens) / per_second);
+                return Err(Throttled::Rate { retry_after });
+            }
+            state.tokens -= 1.0;
+        }
+        state.in_flight += 1;
+        Ok(Permit {
+            keys: self.keys.clone(),
+            key: key.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limit_refills_over_time() {
+        let limiter = RequestLimiter::new();
+        let limits = RateLimits { requests_per_minute: Some(2), max_concurrent: None };
+        let start = Instant::now();
+
+        assert!(limiter.acquire_at("bot", limits, start).is_ok());
+        assert!(limiter.acquire_at("bot", limits, start).is_ok());
+        let throttled = limiter.acquire_at("bot", limits, start).unwrap_err();
+        assert_eq!(throttled.retry_after_secs(), 30);
+        // Other keys have their own budget
+        assert!(limiter.acquire_at("frontend", limits, start).is_ok());
+        assert!(limiter.acquire_at("bot", limits, start + Duration::from_secs(30)).is_ok());
+    }
+
+    #[test]
+    fn test_concurrency_slot_frees_when_permit_drops() {
+        let limiter = RequestLimiter::new();
+        let limits = RateLimits { requests_per_minute: None, max_concurrent: Some(1) };
+
+        let permit = limiter.acquire("bot", limits).unwrap();
+        assert_eq!(limiter.acquire("bot", limits).unwrap_err(), Throttled::Concurrency);
+        drop(permit);
+        assert!(limiter.acquire("bot", limits).is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+//! Retrieval quality regression checks against a stored baseline
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use crate::chunking::DocumentChunk;
+use crate::evaluation::{DatasetEvaluation, EvaluationDataset, EvaluationMetrics, Evaluator};
+use crate::search::SearchEngine;
+
+/// Mean metrics from a known-good run, stored as JSON next to the eval dataset
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsBaseline {
+    pub k: usize,
+    pub metrics: EvaluationMetrics,
+}
+
+impl MetricsBaseline {
+    pub fn from_evaluation(evaluation: &DatasetEvaluation) -> Self {
+        Self {
+            k: evaluation.k,
+            metrics: evaluation.mean.clone(),
+        }
+    }
+
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricCheck {
+    pub metric: String,
+    pub baseline: f32,
+    pub current: f32,
+    pub regressed: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RegressionReport {
+    pub tolerance: f32,
+    pub checks: Vec<MetricCheck>,
+    pub evaluation: DatasetEvaluation,
+}
+
+impl RegressionReport {
+    pub fn regressions(&self) -> Vec<&MetricCheck> {
+        self.checks.iter().filter(|c| c.regressed).collect()
+    }
+}
+
+/// Fails when any metric drops more than `tolerance` below the baseline
+pub struct RegressionHarness {
+    baseline: MetricsBaseline,
+    tolerance: f32,
+}
+
+impl RegressionHarness {
+    pub fn new(baseline: MetricsBaseline, tolerance: f32) -> Self {
+        Self { baseline, tolerance }
+    }
+
+    pub fn from_baseline_file(path: &Path, tolerance: f32) -> Result<Self> {
+        Ok(Self::new(MetricsBaseline::from_file(path)?, tolerance))
+    }
+
+    pub fn baseline(&self) -> &MetricsBaseline {
+        &self.baseline
+    }
+
+    /// Evaluate the dataset at the baseline's k and compare against the baseline
+    pub fn run(&self, engine: &SearchEngine, chunks: &[DocumentChunk], dataset: &EvaluationDataset) -> Result<RegressionReport> {
+        let evaluation = Evaluator::new().evaluate_dataset(engine, chunks, dataset, self.baseline.k)?;
+        self.check(evaluation)
+    }
+
+    /// Compare an existing evaluation against the baseline, returning Err on any regression
+    pub fn check(&self, evaluation: DatasetEvaluation) -> Result<RegressionReport> {
+        let checks: Vec<MetricCheck> = self
+            .baseline
+            .metrics
+            .values()
+            .into_iter()
+            .zip(evaluation.mean.values())
+            .map(|((metric, baseline), (_, current))| MetricCheck {
+                metric: metric.to_string(),
+                baseline,
+                current,
+                regressed: current < baseline - self.tolerance,
+            })
+            .collect();
+
+        let report = RegressionReport {
+            tolerance: self.tolerance,
+            checks,
+            evaluation,
+        };
+
+        let regressions = report.regressions();
+        if !regressions.is_empty() {
+            let details: Vec<String> = regressions
+                .iter()
+                .map(|c| format!("{} {:.3} -> {:.3}", c.metric, c.baseline, c.current))
+                .collect();
+            bail!("Retrieval quality regressed beyond tolerance {:.3}: {}", self.tolerance, details.join(", "));
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evaluation::EvaluationQuery;
+
+    fn setup() -> (Vec<DocumentChunk>, EvaluationDataset) {
+        let chunks = vec![DocumentChunk {
+            id: "c1".to_string(),
+            content: "Tokio is an asynchronous runtime for the Rust programming language".into(),
+            start_pos: 0,
+            end_pos: 10,
+            word_count: 10,
+            document_id: "tokio".to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        }];
+        let dataset = EvaluationDataset {
+            queries: vec![EvaluationQuery {
+                query: "asynchronous runtime".to_string(),
+                relevant_doc_ids: vec!["tokio".to_string()],
+                source_chunk_id: None,
+            }],
+        };
+        (chunks, dataset)
+    }
+
+    #[test]
+    fn test_passes_when_metrics_hold() {
+        let (chunks, dataset) = setup();
+        let engine = SearchEngine::new().unwrap();
+        let evaluation = Evaluator::new().evaluate_dataset(&engine, &chunks, &dataset, 1).unwrap();
+        let harness = RegressionHarness::new(MetricsBaseline::from_evaluation(&evaluation), 0.01);
+
+        let report = harness.run(&engine, &chunks, &dataset).unwrap();
+        assert!(report.regressions().is_empty());
+    }
+
+    #[test]
+    fn test_fails_on_regression() {
+        let (chunks, dataset) = setup();
+        let engine = SearchEngine::new().unwrap();
+        let baseline = MetricsBaseline {
+            k: 1,
+            metrics: EvaluationMetrics {
+                relevance: 1.0,
+                precision: 1.0,
+                recall: 1.0,
+                f1_score: 1.0,
+                hit_rate: 1.0,
+            },
+        };
+        let baseline_file = "/tmp/test_regression_baseline.json";
+        baseline.save(Path::new(baseline_file)).unwrap();
+        let harness = RegressionHarness::from_baseline_file(Path::new(baseline_file), 0.0).unwrap();
+
+        assert!(harness.run(&engine, &chunks, &dataset).is_ok());
+        let err = harness.run(&engine, &[], &dataset).unwrap_err();
+        assert!(err.to_string().contains("precision"));
+
+        std::fs::remove_file(baseline_file).unwrap();
+    }
+}
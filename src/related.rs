@@ -0,0 +1,127 @@
+//! Documents similar to a given one, for "see also" links
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use crate::chunking::DocumentChunk;
+use crate::embedding::cosine_similarity;
+use crate::keyphrases::STOPWORDS;
+use crate::search::{bm25_idf, tokenize};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RelatedDocument {
+    pub document_id: String,
+    pub file_path: String,
+    /// Cosine similarity of the two documents' profiles, from 0 to 1
+    pub score: f32,
+    pub method: Similarity,
+}
+
+/// What the documents were compared by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Similarity {
+    /// Mean of the chunk embeddings
+    EmbeddingCentroid,
+    /// Term frequencies over all chunks, weighted by how rare each term is across documents
+    TermOverlap,
+}
+
+/// Mean of a document's chunk embeddings; `None` when none of its chunks has one
+pub fn embedding_centroid(chunks: &[&DocumentChunk], embeddings: &HashMap<String, Vec<f32>>) -> Option<Vec<f32>> {
+    let vectors: Vec<&Vec<f32>> = chunks.iter().filter_map(|c| embeddings.get(&c.id)).collect();
+    let first = vectors.first()?;
+    let mut centroid = vec![0.0; first.len()];
+    for vector in &vectors {
+        for (sum, x) in centroid.iter_mut().zip(vector.iter()) {
+            *sum += x;
+        }
+    }
+    centroid.iter_mut().for_each(|x| *x /= vectors.len() as f32);
+    Some(centroid)
+}
+
+/// Term counts over a document's chunks, ignoring stopwords and words too short to carry meaning
+pub fn term_profile(chunks: &[&DocumentChunk]) -> HashMap<String, f32> {
+    let mut terms = HashMap::new();
+    for token in chunks.iter().flat_map(|c| tokenize(&c.content)) {
+        if token.chars().count() > 2 && !STOPWORDS.contains(&token.as_str()) {
+            *terms.entry(token).or_insert(0.0) += 1.0;
+        }
+    }
+    terms
+}
+
+/// Weight the counts in each of `profiles`, one per document, by the term's inverse document frequency
+/// across them, so words most documents use count for little next to the ones few share
+pub fn weight_by_idf<'a>(profiles: impl IntoIterator<Item = &'a mut HashMap<String, f32>>) {
+    let mut profiles: Vec<&mut HashMap<String, f32>> = profiles.into_iter().collect();
+    let mut document_frequencies: HashMap<String, usize> = HashMap::new();
+    for profile in &profiles {
+        for term in profile.keys() {
+            *document_frequencies.entry(term.clone()).or_insert(0) += 1;
+        }
+    }
+    let documents = profiles.len() as f32;
+    for profile in &mut profiles {
+        for (term, weight) in profile.iter_mut() {
+            *weight *= bm25_idf(documents, document_frequencies[term] as f32);
+        }
+    }
+}
+
+/// Cosine similarity of two term profiles
+pub fn term_similarity(a: &HashMap<String, f32>, b: &HashMap<String, f32>) -> f32 {
+    let dot: f32 = a.iter().filter_map(|(term, x)| b.get(term).map(|y| x * y)).sum();
+    let norm = |p: &HashMap<String, f32>| p.values().map(|x| x * x).sum::<f32>().sqrt();
+    let (norm_a, norm_b) = (norm(a), norm(b));
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Cosine similarity of two centroids, clamped so unrelated documents score 0
+pub fn centroid_similarity(a: &[f32], b: &[f32]) -> f32 {
+    cosine_similarity(a, b).max(0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fake_chunk;
+
+    #[test]
+    fn test_profiles_compare_documents() {
+        let (a, b, c) = (
+            fake_chunk("a", 0, "Rust ownership and borrowing"),
+            fake_chunk("b", 0, "Borrowing rules about ownership in Rust"),
+            fake_chunk("c", 0, "Sourdough bread baking"),
+        );
+        let (a, b, c) = (term_profile(&[&a]), term_profile(&[&b]), term_profile(&[&c]));
+        assert!(term_similarity(&a, &b) > 0.5);
+        assert_eq!(term_similarity(&a, &c), 0.0);
+        assert!(!b.contains_key("about"), "stopwords are left out");
+
+        // Every document mentions the policy, so sharing the rarer "ownership" matters more
+        let mut profiles: Vec<HashMap<String, f32>> = [
+            "policy policy policy ownership",
+            "policy policy policy sourdough",
+            "ownership transfers policy",
+            "policy review",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, text)| term_profile(&[&fake_chunk("d", i, text)]))
+        .collect();
+        assert!(term_similarity(&profiles[0], &profiles[1]) > term_similarity(&profiles[0], &profiles[2]));
+        weight_by_idf(&mut profiles);
+        assert!(term_similarity(&profiles[0], &profiles[2]) > term_similarity(&profiles[0], &profiles[1]));
+
+        let chunks = [fake_chunk("a", 0, "x"), fake_chunk("a", 1, "y")];
+        let embeddings = HashMap::from([("a_0".to_string(), vec![1.0, 0.0]), ("a_1".to_string(), vec![0.0, 1.0])]);
+        let refs: Vec<&DocumentChunk> = chunks.iter().collect();
+        assert_eq!(embedding_centroid(&refs, &embeddings), Some(vec![0.5, 0.5]));
+        assert_eq!(embedding_centroid(&refs, &HashMap::new()), None);
+    }
+}
@@ -0,0 +1,106 @@
+//! Searches saved under a name, such as `onboarding`, to re-run without retyping the query and
+//! its filters
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use crate::query::Query;
+use crate::tags::DocumentFilter;
+
+/// Results a saved search returns when neither it nor the caller sets a limit
+pub const DEFAULT_SAVED_SEARCH_LIMIT: usize = 5;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedSearch {
+    pub name: String,
+    /// In the query syntax, see `query::Query`
+    pub query: String,
+    #[serde(default, skip_serializing_if = "DocumentFilter::is_empty")]
+    pub filter: DocumentFilter,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limit: Option<usize>,
+    /// Unix seconds it was saved at
+    pub saved_at: u64,
+    /// Owner in a multi-tenant index, set by the storage view that stored it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+}
+
+impl SavedSearch {
+    pub fn new(name: impl Into<String>, query: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            query: query.into(),
+            filter: DocumentFilter::default(),
+            limit: None,
+            saved_at: 0,
+            tenant: None,
+        }
+    }
+
+    pub fn with_filter(mut self, filter: DocumentFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The saved query with its filter required alongside; fails on a name or query that can't be used
+    pub fn parsed(&self) -> Result<Query> {
+        validate_name(&self.name)?;
+        let query = Query::parse(&self.query).map_err(|e| anyhow!("Saved search '{}': {}", self.name, e))?;
+        Ok(match self.filter.is_empty() {
+            true => query,
+            false => query.and(Query::from(&self.filter)),
+        })
+    }
+}
+
+/// Names go in URLs and on command lines, so they are one word of letters, digits, `-`, `_` and `.`
+pub fn validate_name(name: &str) -> Result<()> {
+    let allowed = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if name.is_empty() || !name.chars().all(allowed) {
+        return Err(anyhow!("Invalid saved search name '{}': use letters, digits, '-', '_' and '.'", name));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::StorageManager;
+    use crate::SimpleRagSystem;
+
+    #[test]
+    fn test_saved_searches_rerun_by_name_and_persist() {
+        let path = std::env::temp_dir().join(format!("saved_searches_{}.json", std::process::id()));
+        let mut rag = SimpleRagSystem::builder().storage(StorageManager::open(&path).unwrap()).build().unwrap();
+        let handbook = rag.process_bytes("handbook.md", b"Employee handbook policies cover leave and pay.").unwrap();
+        rag.process_bytes("blog.md", b"Our blog on employee handbook policies elsewhere.").unwrap();
+        rag.set_tag(&handbook, "collection", "hr").unwrap();
+
+        let onboarding = SavedSearch::new("onboarding", "employee handbook policies")
+            .with_filter(DocumentFilter::default().with_tag("collection", "hr"));
+        assert!(rag.save_search(onboarding).unwrap().is_none());
+        assert!(rag.save_search(SavedSearch::new("two words", "pay")).is_err());
+        assert!(rag.save_search(SavedSearch::new("broken", "(pay")).is_err());
+        rag.persist().unwrap();
+
+        let mut reopened = SimpleRagSystem::builder().storage(StorageManager::open(&path).unwrap()).build().unwrap();
+        let results = reopened.run_saved_search("onboarding", None).unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].document_id, handbook);
+        assert_eq!(reopened.saved_searches()[0].name, "onboarding");
+
+        let replaced = reopened.save_search(SavedSearch::new("onboarding", "leave").with_limit(3)).unwrap();
+        assert_eq!(replaced.unwrap().query, "employee handbook policies");
+        assert_eq!(reopened.saved_search("onboarding").unwrap().limit, Some(3));
+        reopened.delete_saved_search("onboarding").unwrap();
+        assert!(reopened.run_saved_search("onboarding", None).is_err());
+        assert!(reopened.delete_saved_search("onboarding").is_err());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
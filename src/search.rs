@@ -2,7 +2,27 @@
 
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::analysis::{Analyzer, AnalyzerConfig};
 use crate::chunking::DocumentChunk;
+use crate::disk_index::{DiskIndex, DiskIndexConfig};
+use crate::ivf::{IvfConfig, IvfIndex};
+use crate::quantization::{hamming_prefilter, BinaryVector, EmbeddingVector};
+use crate::entities::{self, words};
+use crate::feedback::{apply_feedback, feedback_adjustments, FeedbackEntry};
+use crate::keyphrases;
+use crate::minhash::similarity;
+use crate::ltr::{self, RankingFeatures, RankingModel};
+use crate::plugins::SearchScorer;
+use crate::storage::content_hash;
+use crate::synonyms::SynonymDictionary;
+use crate::tags::DocumentBoost;
+
+/// Share of the best score a chunk without any query term needs to count as matching the query
+pub const MIN_RELATIVE_SCORE: f32 = 0.5;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
@@ -11,81 +31,953 @@ pub struct SearchResult {
     pub content: String,
     pub score: f32,
     pub rank: usize,
+    /// Ranked ahead of better scores as a match in a pinned document
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub pinned: bool,
+}
+
+/// Results of one query in a batch; a failed query keeps its error instead of aborting the batch
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryBatchResult {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub latency_ms: f64,
+}
+
+/// How chunks are scored against a query
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SearchMode {
+    /// Fraction of query terms present, with a chunk length penalty
+    #[serde(alias = "keyword")]
+    Keyword,
+    /// Okapi BM25 over the searched chunk set
+    #[serde(alias = "bm25")]
+    Bm25,
+    /// Cosine similarity between query and chunk embeddings
+    #[serde(alias = "vector")]
+    Vector,
+    /// `keyword_weight` of the keyword score blended with the rest from vector similarity
+    #[serde(alias = "hybrid")]
+    Hybrid,
+}
+
+impl SearchMode {
+    pub fn uses_embeddings(&self) -> bool {
+        matches!(self, SearchMode::Vector | SearchMode::Hybrid)
+    }
+}
+
+impl std::str::FromStr for SearchMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "keyword" => Ok(SearchMode::Keyword),
+            "bm25" => Ok(SearchMode::Bm25),
+            "vector" => Ok(SearchMode::Vector),
+            "hybrid" => Ok(SearchMode::Hybrid),
+            other => Err(anyhow::anyhow!("Unknown search mode '{}' (expected keyword, bm25, vector or hybrid)", other)),
+        }
+    }
+}
+
+/// What the vector side of a search scores chunks with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VectorIndex {
+    /// Every chunk's stored embedding
+    Exact,
+    /// Stored embeddings of the chunks in the IVF lists nearest the query, plus chunks added since training
+    Ivf,
+    /// The disk index, for the chunks it holds
+    Disk,
+}
+
+impl std::str::FromStr for VectorIndex {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "exact" => Ok(VectorIndex::Exact),
+            "ivf" => Ok(VectorIndex::Ivf),
+            "disk" => Ok(VectorIndex::Disk),
+            other => Err(anyhow::anyhow!("Unknown vector index '{}' (expected exact, ivf or disk)", other)),
+        }
+    }
+}
+
+/// How a query in one language finds documents written in another
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CrossLingual {
+    /// Queries are matched as written
+    #[default]
+    Off,
+    /// Rely on a multilingual embedding model placing translations close together; needs vector or hybrid mode
+    Embeddings,
+    /// Have the LLM translate each query into `document_language` before it is scored
+    Translate,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    pub mode: SearchMode,
+    pub keyword_weight: f32,
+    pub bm25_k1: f32,
+    pub bm25_b: f32,
+    /// Fraction added to the score of a chunk naming an entity the query mentions; 0 turns it off
+    pub entity_boost: f32,
+    /// Fraction added to the score of a chunk with a keyphrase the query contains; 0 turns it off
+    pub keyphrase_boost: f32,
+    /// Fraction added, in a search session, to the scores of documents the session's recent searches
+    /// returned; 0 turns it off
+    pub session_boost: f32,
+    /// Fraction taken, in a search session, from the scores of documents rejected in it; 0 turns it off
+    pub session_demotion: f32,
+    /// How far relevance feedback on similar queries moves a chunk, as a fraction of the best score;
+    /// 0 turns it off
+    pub feedback_weight: f32,
+    /// Score chunks with the model `train-ranker` fitted to the feedback, when there is one for this mode
+    pub learned_ranking: bool,
+    /// Chunks with binary embeddings rescored against their full vectors, picked by Hamming distance
+    /// between sign bits
+    pub binary_candidates: usize,
+    /// Top chunks of a search over truncated embeddings to rescore with the full-dimension embeddings
+    /// of their text, read from the embedding cache or embedded again
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub full_dimension_rescoring: Option<usize>,
+    /// Graph and search settings of the disk-resident vector index
+    #[serde(skip_serializing_if = "DiskIndexConfig::is_default")]
+    pub disk_index: DiskIndexConfig,
+    /// Training and probing settings of the IVF index
+    #[serde(skip_serializing_if = "IvfConfig::is_default")]
+    pub ivf: IvfConfig,
+    /// Index vector search uses; unset, the disk index for the chunks it holds and exact search for the rest
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector_index: Option<VectorIndex>,
+    /// Indexes for the chunks of particular collections in place of `vector_index`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub collection_vector_indexes: BTreeMap<String, VectorIndex>,
+    /// Most chunks one document may hold in the results, so a long document can't fill them all
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_chunks_per_document: Option<usize>,
+    /// Estimated shingle similarity from which a chunk repeating a better result, from any
+    /// document, is left out of the results
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near_duplicate_threshold: Option<f32>,
+    pub cross_lingual: CrossLingual,
+    /// Language the documents are written in, such as `English`, for `cross_lingual = "translate"`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document_language: Option<String>,
+    /// Synonyms file queries are expanded with; see `SynonymDictionary` for the format
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub synonyms: Option<PathBuf>,
+    /// How chunks and queries are split into terms for keyword and BM25 scoring
+    #[serde(skip_serializing_if = "AnalyzerConfig::is_default")]
+    pub analyzer: AnalyzerConfig,
+    /// Analyzers for the chunks of particular collections in place of `analyzer`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub collection_analyzers: BTreeMap<String, AnalyzerConfig>,
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            mode: SearchMode::Keyword,
+            keyword_weight: 0.7,
+            bm25_k1: 1.2,
+            bm25_b: 0.75,
+            entity_boost: 0.25,
+            keyphrase_boost: 0.15,
+            session_boost: 0.1,
+            session_demotion: 0.5,
+            feedback_weight: 0.5,
+            learned_ranking: false,
+            binary_candidates: 100,
+            full_dimension_rescoring: None,
+            disk_index: DiskIndexConfig::default(),
+            ivf: IvfConfig::default(),
+            vector_index: None,
+            collection_vector_indexes: BTreeMap::new(),
+            max_chunks_per_document: None,
+            near_duplicate_threshold: None,
+            cross_lingual: CrossLingual::Off,
+            document_language: None,
+            synonyms: None,
+            analyzer: AnalyzerConfig::default(),
+            collection_analyzers: BTreeMap::new(),
+        }
+    }
+}
+
+impl SearchConfig {
+    /// Whether any chunk may be searched with `index`
+    pub fn may_use(&self, index: VectorIndex) -> bool {
+        self.vector_index == Some(index) || self.collection_vector_indexes.values().any(|i| *i == index)
+    }
+
+    /// Stable short hash identifying this configuration across runs
+    pub fn fingerprint(&self) -> String {
+        content_hash(&serde_json::to_string(self).unwrap_or_default())
+    }
+}
+
+/// What a search knows besides the query and the chunks
+#[derive(Debug, Clone, Default)]
+pub struct SearchContext {
+    /// Collection of each document by id, for per-collection analyzers
+    pub collections: HashMap<String, String>,
+    /// Past relevance judgements, applied with `feedback_weight`
+    pub feedback: Vec<FeedbackEntry>,
+    /// Scores chunks in place of the mode's own score
+    pub ranking: Option<RankingModel>,
+    /// Recency signal of each document by id, for `ranking`
+    pub recency: HashMap<String, f32>,
+    /// Trained IVF index, for chunks searched with `VectorIndex::Ivf`
+    pub ivf: Option<Arc<IvfIndex>>,
+    /// Static boost of each document by id that is tagged with one
+    pub boosts: HashMap<String, DocumentBoost>,
+    /// Chunks embedded or removed since the disk index was built, searched exactly instead of from it
+    pub stale_vectors: HashSet<String>,
+    /// Counts of the whole corpus for BM25 to weigh terms by, when only part of it is being scored
+    pub term_stats: Option<TermStats>,
+    /// Leave out chunks that didn't match the query, rather than ranking them last
+    pub matched_only: bool,
+}
+
+/// Chunk count, total length and query term document frequencies of a set of chunks, which BM25
+/// weighs terms by; summed over the parts of a corpus they are those of the whole
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TermStats {
+    pub chunks: usize,
+    pub total_length: usize,
+    pub doc_freqs: HashMap<String, usize>,
+}
+
+impl TermStats {
+    pub fn merge(mut self, other: TermStats) -> Self {
+        self.chunks += other.chunks;
+        self.total_length += other.total_length;
+        for (term, count) in other.doc_freqs {
+            *self.doc_freqs.entry(term).or_insert(0) += count;
+        }
+        self
+    }
+}
+
+/// Per-chunk scores a search combines, from `SearchEngine::signals`
+#[derive(Debug, Clone, Default)]
+pub struct ChunkSignals {
+    /// Keyword score of each chunk under the mode's lexical scoring
+    pub keyword: Option<Vec<f32>>,
+    /// Similarity of each chunk's embedding to the query's
+    pub vector: Option<Vec<f32>>,
+}
+
+impl ChunkSignals {
+    /// Signals of consecutive runs of chunks, as those of the runs joined in order
+    pub fn concat(parts: Vec<ChunkSignals>) -> Self {
+        let (keyword, vector): (Vec<_>, Vec<_>) = parts.into_iter().map(|p| (p.keyword, p.vector)).unzip();
+        Self {
+            keyword: keyword.into_iter().collect::<Option<Vec<_>>>().map(|k| k.concat()),
+            vector: vector.into_iter().collect::<Option<Vec<_>>>().map(|v| v.concat()),
+        }
+    }
 }
 
 pub struct SearchEngine {
-    keyword_weight: f32,
+    config: SearchConfig,
+    analyzer: Analyzer,
+    collection_analyzers: BTreeMap<String, Analyzer>,
+    synonyms: SynonymDictionary,
+    disk_index: Option<Arc<DiskIndex>>,
+    /// Applied in order after every other adjustment
+    scorers: Vec<Arc<dyn SearchScorer>>,
 }
 
 impl SearchEngine {
     pub fn new() -> Result<Self> {
+        Self::with_config(SearchConfig::default())
+    }
+
+    pub fn with_config(config: SearchConfig) -> Result<Self> {
+        if config.feedback_weight < 0.0 {
+            return Err(anyhow::anyhow!("feedback_weight must not be negative"));
+        }
+        let disk_index = &config.disk_index;
+        if disk_index.degree == 0 || disk_index.build_list == 0 || disk_index.search_list == 0 || disk_index.alpha < 1.0 {
+            return Err(anyhow::anyhow!("disk_index needs degree and list sizes of at least 1 and alpha of at least 1"));
+        }
+        if config.ivf.nprobe == 0 || config.ivf.lists == Some(0) {
+            return Err(anyhow::anyhow!("ivf needs nprobe and lists of at least 1"));
+        }
+        if config.session_boost < 0.0 || !(0.0..=1.0).contains(&config.session_demotion) {
+            return Err(anyhow::anyhow!("session_boost must not be negative and session_demotion must be between 0 and 1"));
+        }
+        if config.binary_candidates == 0 {
+            return Err(anyhow::anyhow!("binary_candidates must be at least 1"));
+        }
+        if config.max_chunks_per_document == Some(0) {
+            return Err(anyhow::anyhow!("max_chunks_per_document must be at least 1"));
+        }
+        if config.near_duplicate_threshold.is_some_and(|t| !(t > 0.0 && t <= 1.0)) {
+            return Err(anyhow::anyhow!("near_duplicate_threshold must be above 0 and at most 1"));
+        }
+        match config.cross_lingual {
+            CrossLingual::Embeddings if !config.mode.uses_embeddings() => {
+                return Err(anyhow::anyhow!("Cross-lingual matching by embeddings needs vector or hybrid search"));
+            }
+            CrossLingual::Translate if config.document_language.is_none() => {
+                return Err(anyhow::anyhow!("Translating queries needs search.document_language"));
+            }
+            _ => {}
+        }
         Ok(Self {
-            keyword_weight: 0.7,
+            analyzer: Analyzer::new(&config.analyzer),
+            collection_analyzers: config
+                .collection_analyzers
+                .iter()
+                .map(|(collection, analyzer)| (collection.clone(), Analyzer::new(analyzer)))
+                .collect(),
+            synonyms: SynonymDictionary::default(),
+            disk_index: None,
+            scorers: Vec::new(),
+            config,
         })
     }
 
+    pub fn config(&self) -> &SearchConfig {
+        &self.config
+    }
+
+    /// Expands queries in analyzers with the synonyms filter
+    pub fn with_synonyms(mut self, synonyms: SynonymDictionary) -> Self {
+        self.synonyms = synonyms;
+        self
+    }
+
+    pub fn synonyms(&self) -> &SynonymDictionary {
+        &self.synonyms
+    }
+
+    /// Have `scorers` adjust every search's scores, in order, last of all
+    pub fn with_scorers(mut self, scorers: Vec<Arc<dyn SearchScorer>>) -> Self {
+        self.scorers = scorers;
+        self
+    }
+
+    pub fn scorers(&self) -> &[Arc<dyn SearchScorer>] {
+        &self.scorers
+    }
+
+    pub fn synonyms_mut(&mut self) -> &mut SynonymDictionary {
+        &mut self.synonyms
+    }
+
+    /// Build a disk index over `embeddings` at `path` with this configuration's `disk_index` settings
+    pub fn build_disk_index(&self, path: &Path, embeddings: &HashMap<String, Vec<f32>>) -> Result<DiskIndex> {
+        DiskIndex::build(path, embeddings, &self.config.disk_index)
+    }
+
+    /// Score the chunks `index` holds from it rather than from the embeddings passed to each search
+    pub fn with_disk_index(mut self, index: Option<Arc<DiskIndex>>) -> Self {
+        self.set_disk_index(index);
+        self
+    }
+
+    pub fn set_disk_index(&mut self, index: Option<Arc<DiskIndex>>) {
+        self.disk_index = index;
+    }
+
+    pub fn disk_index(&self) -> Option<&Arc<DiskIndex>> {
+        self.disk_index.as_ref()
+    }
+
+    /// Text to embed for `query`: expanded with synonyms when the default analyzer expands them
+    pub fn embedding_query(&self, query: &str) -> String {
+        if self.analyzer.expands_synonyms() {
+            self.synonyms.expand(query)
+        } else {
+            query.to_string()
+        }
+    }
+
+    /// Language queries are translated into before scoring, when translation is on
+    pub fn translation_language(&self) -> Option<&str> {
+        match self.config.cross_lingual {
+            CrossLingual::Translate => self.config.document_language.as_deref(),
+            _ => None,
+        }
+    }
+
+    pub fn keyword_weight(&self) -> f32 {
+        self.config.keyword_weight
+    }
+
+    /// Lexical search; vector modes fall back to keyword scoring without embeddings
     pub fn search(&self, query: &str, chunks: &[DocumentChunk], limit: usize) -> Result<Vec<SearchResult>> {
-        let mut results: Vec<SearchResult> = chunks
+        self.search_in(query, chunks, &SearchContext::default(), limit)
+    }
+
+    /// Like `search`, with per-collection analyzers and relevance feedback from `context`
+    pub fn search_in(
+        &self,
+        query: &str,
+        chunks: &[DocumentChunk],
+        context: &SearchContext,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let signals = self.signals(query, None, chunks, &HashMap::<String, Vec<f32>>::new(), context)?;
+        Ok(self.rank_signals(query, chunks, signals, context, limit))
+    }
+
+    /// Search using chunk embeddings keyed by chunk id; chunks without one score zero on the vector side
+    pub fn search_with_embeddings<E: EmbeddingVector>(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        self.search_with_embeddings_in(query, query_embedding, chunks, embeddings, &SearchContext::default(), limit)
+    }
+
+    /// `search_with_embeddings` with the `context` of `search_in`
+    pub fn search_with_embeddings_in<E: EmbeddingVector>(
+        &self,
+        query: &str,
+        query_embedding: &[f32],
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        context: &SearchContext,
+        limit: usize,
+    ) -> Result<Vec<SearchResult>> {
+        let signals = self.signals(query, Some(query_embedding), chunks, embeddings, context)?;
+        Ok(self.rank_signals(query, chunks, signals, context, limit))
+    }
+
+    /// The keyword and vector scores of each chunk that a search combines, each only when the mode, or
+    /// a ranking model, uses it. They depend on nothing but the chunk and the `context`, so parts of a
+    /// corpus can be scored apart, given its `term_stats`, and their signals joined in chunk order
+    pub fn signals<E: EmbeddingVector>(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f32]>,
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        context: &SearchContext,
+    ) -> Result<ChunkSignals> {
+        let query_embedding = query_embedding.filter(|_| self.config.mode.uses_embeddings());
+        let keyword = match (&context.ranking, self.config.mode, query_embedding) {
+            (None, SearchMode::Vector, Some(_)) => None,
+            _ => Some(self.lexical_scores(query, chunks, context)),
+        };
+        let vector = match query_embedding {
+            Some(query_embedding) => Some(self.vector_similarities(query_embedding, chunks, embeddings, context)?),
+            None => None,
+        };
+        Ok(ChunkSignals { keyword, vector })
+    }
+
+    /// Combine `signals` of `chunks` into scores, adjust them, and pick the top `limit`
+    pub fn rank_signals(
+        &self,
+        query: &str,
+        chunks: &[DocumentChunk],
+        signals: ChunkSignals,
+        context: &SearchContext,
+        limit: usize,
+    ) -> Vec<SearchResult> {
+        let keyword = signals.keyword.clone();
+        let mut scores: Vec<f32> = match (&context.ranking, signals) {
+            // The model reorders what the query matched; it isn't trusted to pull in chunks that didn't
+            (Some(model), signals) => Self::features(chunks, signals, context)
+                .iter()
+                .map(|f| if f.keyword > 0.0 || f.vector > 0.0 { model.score(f) } else { 0.0 })
+                .collect(),
+            (None, ChunkSignals { keyword: Some(keyword), vector: Some(vector) }) => {
+                let weight = self.config.keyword_weight;
+                keyword.into_iter().zip(vector).map(|(keyword, vector)| weight * keyword + (1.0 - weight) * vector).collect()
+            }
+            (None, ChunkSignals { vector: Some(scores), .. } | ChunkSignals { keyword: Some(scores), .. }) => scores,
+            (None, _) => vec![0.0; chunks.len()],
+        };
+        self.boost_annotations(query, chunks, &mut scores);
+        self.apply_feedback(query, chunks, &context.feedback, &mut scores);
+        apply_boosts(chunks, &context.boosts, &mut scores);
+        self.apply_scorers(query, chunks, &mut scores);
+        let matched = matches(keyword.as_deref(), &scores);
+        if !context.matched_only {
+            return self.select(chunks, scores, &matched, limit, &context.boosts);
+        }
+        // Unmatched chunks rank below every matched one at 0, where they are cut off
+        for (score, matched) in scores.iter_mut().zip(&matched) {
+            if !matched {
+                *score = 0.0;
+            }
+        }
+        let mut results = self.select(chunks, scores, &matched, limit, &context.boosts);
+        results.retain(|r| r.score > 0.0);
+        results
+    }
+
+    /// Signals of each chunk `train-ranker` fits a model to; vector similarity needs `query_embedding`
+    pub fn ranking_features<E: EmbeddingVector>(
+        &self,
+        query: &str,
+        query_embedding: Option<&[f32]>,
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        context: &SearchContext,
+    ) -> Result<Vec<RankingFeatures>> {
+        let signals = ChunkSignals {
+            keyword: Some(self.lexical_scores(query, chunks, context)),
+            vector: match query_embedding {
+                Some(query_embedding) => Some(self.vector_similarities(query_embedding, chunks, embeddings, context)?),
+                None => None,
+            },
+        };
+        Ok(Self::features(chunks, signals, context))
+    }
+
+    /// Keyword scores relative to the best of them, so the model sees the same scale in every search
+    fn features(chunks: &[DocumentChunk], signals: ChunkSignals, context: &SearchContext) -> Vec<RankingFeatures> {
+        let keyword = signals.keyword.unwrap_or_else(|| vec![0.0; chunks.len()]);
+        let vector = signals.vector.unwrap_or_else(|| vec![0.0; chunks.len()]);
+        let best = keyword.iter().copied().fold(0.0, f32::max);
+        chunks
             .iter()
-            .enumerate()
-            .map(|(i, chunk)| {
-                let score = self.calculate_similarity(query, &chunk.content);
-                SearchResult {
-                    chunk_id: chunk.id.clone(),
-                    document_id: chunk.document_id.clone(),
-                    content: chunk.content.clone(),
-                    score,
-                    rank: i,
-                }
+            .zip(keyword.into_iter().zip(vector))
+            .map(|(chunk, (keyword, vector))| RankingFeatures {
+                keyword: if best > 0.0 { keyword / best } else { 0.0 },
+                vector,
+                recency: context.recency.get(&chunk.document_id).copied().unwrap_or(0.0),
+                length: ltr::length(chunk.word_count),
+            })
+            .collect()
+    }
+
+    /// Vector side of each chunk's score, from the index its collection searches with: chunks in the disk
+    /// index are scored from it, and the IVF index skips chunks outside the lists it probes
+    fn vector_similarities<E: EmbeddingVector>(
+        &self,
+        query_embedding: &[f32],
+        chunks: &[DocumentChunk],
+        embeddings: &HashMap<String, E>,
+        context: &SearchContext,
+    ) -> Result<Vec<f32>> {
+        let indexes: Vec<VectorIndex> = chunks.iter().map(|c| self.vector_index(c, context)).collect();
+        // Chunks in the IVF lists nearest the query, gathered from those lists' postings
+        let probed = match &context.ivf {
+            Some(ivf) if indexes.contains(&VectorIndex::Ivf) => {
+                Some(ivf.chunks_in(&ivf.probe(query_embedding, self.config.ivf.nprobe)))
+            }
+            _ => None,
+        };
+        let stored: Vec<Option<&E>> = chunks
+            .iter()
+            .zip(&indexes)
+            .map(|(chunk, index)| match (index, &probed) {
+                (VectorIndex::Disk, _) => None,
+                (VectorIndex::Ivf, Some(probed)) if !probed.contains(chunk.id.as_str()) => None,
+                _ => embeddings.get(&chunk.id),
             })
             .collect();
+        let mut scores = vector_scores(query_embedding, &stored, self.config.binary_candidates);
+        if let Some(index) = self.disk_index.as_ref().filter(|_| indexes.contains(&VectorIndex::Disk)) {
+            let on_disk: Vec<usize> = (0..chunks.len()).filter(|&i| indexes[i] == VectorIndex::Disk).collect();
+            let ids: Vec<&str> = on_disk.iter().map(|&i| chunks[i].id.as_str()).collect();
+            let similarities = index.similarities(query_embedding, &ids, self.config.disk_index.search_list)?;
+            for (i, similarity) in on_disk.into_iter().zip(similarities) {
+                scores[i] = similarity.max(0.0);
+            }
+        }
+        Ok(scores)
+    }
+
+    /// Raise chunks naming an entity, or holding a keyphrase, that the query mentions
+    fn boost_annotations(&self, query: &str, chunks: &[DocumentChunk], scores: &mut [f32]) {
+        let (entity_boost, keyphrase_boost) = (self.config.entity_boost, self.config.keyphrase_boost);
+        if entity_boost == 0.0 && keyphrase_boost == 0.0 {
+            return;
+        }
+        let query_words = words(query);
+        for (chunk, score) in chunks.iter().zip(scores) {
+            if entity_boost != 0.0 && chunk.entities.iter().any(|e| entities::mentioned_in(e, &query_words)) {
+                *score *= 1.0 + entity_boost;
+            }
+            if keyphrase_boost != 0.0 && chunk.keyphrases.iter().any(|p| keyphrases::mentioned_in(p, &query_words)) {
+                *score *= 1.0 + keyphrase_boost;
+            }
+        }
+    }
+
+    /// Raise chunks judged relevant to similar queries and lower those judged irrelevant
+    fn apply_feedback(&self, query: &str, chunks: &[DocumentChunk], feedback: &[FeedbackEntry], scores: &mut [f32]) {
+        if feedback.is_empty() || self.config.feedback_weight == 0.0 {
+            return;
+        }
+        let adjustments = feedback_adjustments(query, feedback);
+        apply_feedback(chunks.iter().map(|c| &c.id), scores, &adjustments, self.config.feedback_weight);
+    }
+
+    fn apply_scorers(&self, query: &str, chunks: &[DocumentChunk], scores: &mut [f32]) {
+        for scorer in &self.scorers {
+            for (chunk, score) in chunks.iter().zip(scores.iter_mut()) {
+                *score = scorer.score(query, chunk, *score);
+            }
+        }
+    }
+
+    /// Term statistics of `chunks` for the terms `query` is analyzed into by any analyzer
+    pub fn term_stats(&self, query: &str, chunks: &[DocumentChunk], collections: &HashMap<String, String>) -> TermStats {
+        let query_terms: HashSet<String> = std::iter::once(&self.analyzer)
+            .chain(self.collection_analyzers.values())
+            .flat_map(|analyzer| analyzer.analyze_query(query, &self.synonyms))
+            .collect();
+        let mut stats = TermStats { chunks: chunks.len(), ..TermStats::default() };
+        for chunk in chunks {
+            let terms = self.analyzer(self.analyzer_name(collections, chunk)).analyze(&chunk.content);
+            stats.total_length += terms.len();
+            let terms: HashSet<String> = terms.into_iter().filter(|t| query_terms.contains(t)).collect();
+            for term in terms {
+                *stats.doc_freqs.entry(term).or_insert(0) += 1;
+            }
+        }
+        stats
+    }
 
-        // Sort by score (descending) and take top results
-        results.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
-        results.truncate(limit);
+    fn lexical_scores(&self, query: &str, chunks: &[DocumentChunk], context: &SearchContext) -> Vec<f32> {
+        let analyzers: Vec<Option<&str>> = chunks.iter().map(|c| self.analyzer_name(&context.collections, c)).collect();
+        // The query is analyzed once per analyzer in use rather than once per chunk
+        let mut query_terms: HashMap<Option<&str>, Vec<String>> = HashMap::new();
+        for name in &analyzers {
+            query_terms
+                .entry(*name)
+                .or_insert_with(|| self.analyzer(*name).analyze_query(query, &self.synonyms));
+        }
+        let chunk_terms: Vec<Vec<String>> = chunks
+            .iter()
+            .zip(&analyzers)
+            .map(|(chunk, name)| self.analyzer(*name).analyze(&chunk.content))
+            .collect();
+        match self.config.mode {
+            SearchMode::Bm25 => {
+                let mut index = Bm25Index::build(&chunk_terms);
+                if let Some(stats) = &context.term_stats {
+                    index.use_stats(stats);
+                }
+                (0..chunks.len())
+                    .map(|i| index.score(&query_terms[&analyzers[i]], i, self.config.bm25_k1, self.config.bm25_b))
+                    .collect()
+            }
+            _ => chunk_terms
+                .iter()
+                .zip(&analyzers)
+                .map(|(terms, name)| calculate_similarity(&query_terms[name], terms))
+                .collect(),
+        }
+    }
 
-        // Update ranks
-        for (i, result) in results.iter_mut().enumerate() {
-            result.rank = i + 1;
+    /// Index `chunk` is searched with; disk only if the disk index holds its current embedding
+    fn vector_index(&self, chunk: &DocumentChunk, context: &SearchContext) -> VectorIndex {
+        let chosen = context
+            .collections
+            .get(&chunk.document_id)
+            .and_then(|c| self.config.collection_vector_indexes.get(c))
+            .or(self.config.vector_index.as_ref());
+        let on_disk = self.disk_index.as_ref().is_some_and(|index| index.contains(&chunk.id))
+            && !context.stale_vectors.contains(&chunk.id);
+        match chosen {
+            Some(VectorIndex::Disk) | None if on_disk => VectorIndex::Disk,
+            Some(VectorIndex::Ivf) => VectorIndex::Ivf,
+            _ => VectorIndex::Exact,
         }
+    }
 
-        Ok(results)
+    /// Collection whose analyzer applies to `chunk`, if it has one of its own
+    fn analyzer_name(&self, collections: &HashMap<String, String>, chunk: &DocumentChunk) -> Option<&str> {
+        let collection = collections.get(&chunk.document_id)?;
+        self.collection_analyzers.get_key_value(collection).map(|(name, _)| name.as_str())
     }
 
-    fn calculate_similarity(&self, query: &str, content: &str) -> f32 {
-        let query_lower = query.to_lowercase();
-        let content_lower = content.to_lowercase();
+    fn analyzer(&self, collection: Option<&str>) -> &Analyzer {
+        collection.and_then(|c| self.collection_analyzers.get(c)).unwrap_or(&self.analyzer)
+    }
 
-        // Simple keyword matching score
-        let query_words: Vec<&str> = query_lower.split_whitespace().collect();
-        let content_words: Vec<&str> = content_lower.split_whitespace().collect();
+    /// The top `limit` chunks, with the `matched` ones of pinned documents first
+    fn select(
+        &self,
+        chunks: &[DocumentChunk],
+        scores: Vec<f32>,
+        matched: &[bool],
+        limit: usize,
+        boosts: &HashMap<String, DocumentBoost>,
+    ) -> Vec<SearchResult> {
+        let pinned: Vec<bool> = chunks
+            .iter()
+            .zip(matched)
+            .map(|(chunk, matched)| *matched && is_pinned(boosts, &chunk.document_id))
+            .collect();
+        match (self.config.max_chunks_per_document, self.config.near_duplicate_threshold) {
+            (None, None) if !pinned.contains(&true) => Self::rank(chunks, scores, limit),
+            (cap, threshold) => Self::rank_filtered(chunks, scores, limit, cap, threshold, &pinned),
+        }
+    }
 
-        if query_words.is_empty() || content_words.is_empty() {
-            return 0.0;
+    /// Top `limit` chunks by score, ties in chunk order; only those are copied into results
+    fn rank(chunks: &[DocumentChunk], scores: Vec<f32>, limit: usize) -> Vec<SearchResult> {
+        if limit == 0 {
+            return Vec::new();
+        }
+        let mut order: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+        if limit < order.len() {
+            order.select_nth_unstable_by(limit - 1, by_score);
+            order.truncate(limit);
         }
+        order.sort_by(by_score);
+        Self::results(chunks, order)
+    }
 
-        let mut matches = 0;
-        for query_word in &query_words {
-            for content_word in &content_words {
-                if content_word.contains(query_word) || query_word.contains(content_word) {
-                    matches += 1;
-                    break;
+    /// Like `rank`, with `pinned` chunks ahead of the rest, but once a document holds `cap` results its
+    /// further chunks give way to the next best documents' chunks, as does a chunk whose signature is
+    /// within `threshold` of a better one's
+    fn rank_filtered(
+        chunks: &[DocumentChunk],
+        scores: Vec<f32>,
+        limit: usize,
+        cap: Option<usize>,
+        threshold: Option<f32>,
+        pinned: &[bool],
+    ) -> Vec<SearchResult> {
+        let mut order: Vec<(usize, f32)> = scores.into_iter().enumerate().collect();
+        order.sort_by(|a, b| pinned[b.0].cmp(&pinned[a.0]).then_with(|| by_score(a, b)));
+        let mut per_document: HashMap<&str, usize> = HashMap::new();
+        let mut signatures: Vec<Cow<[u32]>> = Vec::new();
+        let mut selected = Vec::with_capacity(limit);
+        for (i, score) in order {
+            if selected.len() == limit {
+                break;
+            }
+            let taken = per_document.entry(chunks[i].document_id.as_str()).or_insert(0);
+            if cap.is_some_and(|cap| *taken >= cap) {
+                continue;
+            }
+            if let Some(threshold) = threshold {
+                let signature = chunks[i].signature();
+                if signatures.iter().any(|kept| similarity(kept, &signature) >= threshold as f64) {
+                    continue;
                 }
+                signatures.push(signature);
             }
+            *taken += 1;
+            selected.push((i, score));
         }
+        let mut results = Self::results(chunks, selected.iter().copied());
+        for (result, (i, _)) in results.iter_mut().zip(selected) {
+            result.pinned = pinned[i];
+        }
+        results
+    }
 
-        let keyword_score = matches as f32 / query_words.len() as f32;
+    fn results(chunks: &[DocumentChunk], order: impl IntoIterator<Item = (usize, f32)>) -> Vec<SearchResult> {
+        order
+            .into_iter()
+            .enumerate()
+            .map(|(rank, (i, score))| SearchResult {
+                chunk_id: chunks[i].id.clone(),
+                document_id: chunks[i].document_id.clone(),
+                content: chunks[i].content.to_string(),
+                score,
+                rank: rank + 1,
+                pinned: false,
+            })
+            .collect()
+    }
+}
+
+/// Cosine similarity of each embedding to the query's, zero where there is none; of the binary
+/// embeddings, only the `binary_candidates` nearest by Hamming distance are rescored at full precision,
+/// and the rest keep the estimate their sign bits give
+fn vector_scores<E: EmbeddingVector>(query_embedding: &[f32], stored: &[Option<&E>], binary_candidates: usize) -> Vec<f32> {
+    let codes: Vec<Option<&BinaryVector>> = stored.iter().map(|e| e.and_then(|e| e.binary_code())).collect();
+    let candidates = hamming_prefilter(query_embedding, &codes, binary_candidates);
+    // Product-quantized embeddings share one codebook, so one table of the query's distances serves them all
+    let pq_table = stored.iter().flatten().find_map(|e| e.pq_code()).and_then(|pq| {
+        let table = pq.codebook.distance_table(query_embedding)?;
+        Some((pq.codebook.clone(), table))
+    });
+    stored
+        .iter()
+        .zip(&codes)
+        .enumerate()
+        .map(|(i, (embedding, code))| match (embedding, &candidates) {
+            (Some(embedding), Some(candidates)) if code.is_some() && !candidates.contains(&i) => {
+                let dimensions = embedding.dimensions().min(query_embedding.len());
+                code.map_or(0.0, |code| code.cosine(&query_embedding[..dimensions]).max(0.0))
+            }
+            (Some(embedding), _) => {
+                if let (Some(pq), Some((codebook, table))) = (embedding.pq_code(), &pq_table) {
+                    if Arc::ptr_eq(&pq.codebook, codebook) {
+                        return table.cosine(pq).max(0.0);
+                    }
+                }
+                // Truncated embeddings are compared with the same leading dimensions of the query
+                let dimensions = embedding.dimensions().min(query_embedding.len());
+                embedding.cosine(&query_embedding[..dimensions]).max(0.0)
+            }
+            (None, _) => 0.0,
+        })
+        .collect()
+}
 
-        // Simple length penalty (prefer chunks of reasonable length)
-        let length_penalty = if content_words.len() < 10 {
-            content_words.len() as f32 / 10.0
-        } else if content_words.len() > 200 {
-            200.0 / content_words.len() as f32
+/// Share of query terms found in the chunk, where a term also matches inside a longer one
+fn calculate_similarity(query_terms: &[String], content_terms: &[String]) -> f32 {
+    if query_terms.is_empty() || content_terms.is_empty() {
+        return 0.0;
+    }
+
+    let mut matches = 0;
+    for query_term in query_terms {
+        for content_term in content_terms {
+            if content_term.contains(query_term.as_str()) || query_term.contains(content_term.as_str()) {
+                matches += 1;
+                break;
+            }
+        }
+    }
+
+    let keyword_score = matches as f32 / query_terms.len() as f32;
+
+    // Simple length penalty (prefer chunks of reasonable length)
+    let length_penalty = if content_terms.len() < 10 {
+        content_terms.len() as f32 / 10.0
+    } else if content_terms.len() > 200 {
+        200.0 / content_terms.len() as f32
+    } else {
+        1.0
+    };
+
+    keyword_score * length_penalty
+}
+
+/// Multiply each chunk's score by its document's boost factor
+fn apply_boosts(chunks: &[DocumentChunk], boosts: &HashMap<String, DocumentBoost>, scores: &mut [f32]) {
+    if boosts.is_empty() {
+        return;
+    }
+    for (chunk, score) in chunks.iter().zip(scores) {
+        if let Some(boost) = boosts.get(&chunk.document_id) {
+            *score *= boost.factor;
+        }
+    }
+}
+
+/// Whether the document is pinned above unpinned results
+pub fn is_pinned(boosts: &HashMap<String, DocumentBoost>, doc_id: &str) -> bool {
+    boosts.get(doc_id).is_some_and(|boost| boost.pinned)
+}
+
+/// Whether each chunk matched the query: it holds a query term, going by its `keyword` score, or
+/// scores at least `MIN_RELATIVE_SCORE` of the best. A vector score alone is above 0 for every chunk
+fn matches(keyword: Option<&[f32]>, scores: &[f32]) -> Vec<bool> {
+    let best = scores.iter().copied().fold(0.0, f32::max);
+    scores
+        .iter()
+        .enumerate()
+        .map(|(i, &score)| score > 0.0 && (keyword.is_some_and(|k| k[i] > 0.0) || score >= MIN_RELATIVE_SCORE * best))
+        .collect()
+}
+
+/// Best score first, ties in chunk order
+fn by_score(a: &(usize, f32), b: &(usize, f32)) -> std::cmp::Ordering {
+    b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0))
+}
+
+pub(crate) fn tokenize(text: &str) -> Vec<String> {
+    text.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Inverse document frequency of a term found in `df` of `n` chunks, as BM25 weighs it
+pub fn bm25_idf(n: f32, df: f32) -> f32 {
+    ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+}
+
+/// Term statistics for the chunk set being searched
+struct Bm25Index {
+    term_freqs: Vec<HashMap<String, usize>>,
+    doc_lengths: Vec<usize>,
+    chunks: usize,
+    doc_freqs: HashMap<String, usize>,
+    avg_length: f32,
+}
+
+impl Bm25Index {
+    /// From each chunk's analyzed terms
+    fn build(chunks: &[Vec<String>]) -> Self {
+        let mut term_freqs = Vec::with_capacity(chunks.len());
+        let mut doc_lengths = Vec::with_capacity(chunks.len());
+        let mut doc_freqs: HashMap<String, usize> = HashMap::new();
+
+        for tokens in chunks {
+            let mut freqs: HashMap<String, usize> = HashMap::new();
+            for token in tokens.iter() {
+                *freqs.entry(token.clone()).or_insert(0) += 1;
+            }
+            for term in freqs.keys() {
+                *doc_freqs.entry(term.clone()).or_insert(0) += 1;
+            }
+            doc_lengths.push(tokens.len());
+            term_freqs.push(freqs);
+        }
+
+        let avg_length = if doc_lengths.is_empty() {
+            0.0
         } else {
-            1.0
+            doc_lengths.iter().sum::<usize>() as f32 / doc_lengths.len() as f32
         };
 
-        keyword_score * length_penalty
+        Self {
+            term_freqs,
+            chunks: doc_lengths.len(),
+            doc_lengths,
+            doc_freqs,
+            avg_length,
+        }
+    }
+
+    /// Weigh terms by `stats` of the whole corpus rather than by these chunks alone
+    fn use_stats(&mut self, stats: &TermStats) {
+        self.chunks = stats.chunks;
+        self.doc_freqs = stats.doc_freqs.clone();
+        self.avg_length = if stats.chunks == 0 { 0.0 } else { stats.total_length as f32 / stats.chunks as f32 };
+    }
+
+    fn score(&self, terms: &[String], doc: usize, k1: f32, b: f32) -> f32 {
+        let n = self.chunks as f32;
+        let length_ratio = if self.avg_length > 0.0 {
+            self.doc_lengths[doc] as f32 / self.avg_length
+        } else {
+            0.0
+        };
+
+        terms
+            .iter()
+            .filter_map(|term| {
+                let tf = *self.term_freqs[doc].get(term)? as f32;
+                let df = *self.doc_freqs.get(term)? as f32;
+                let idf = bm25_idf(n, df);
+                Some(idf * tf * (k1 + 1.0) / (tf + k1 * (1.0 - b + b * length_ratio)))
+            })
+            .sum()
     }
 }
 
@@ -106,19 +998,29 @@ mod tests {
         let chunks = vec![
             DocumentChunk {
                 id: "chunk1".to_string(),
-                content: "Machine learning is a subset of artificial intelligence".to_string(),
+                content: "Machine learning is a subset of artificial intelligence".into(),
                 start_pos: 0,
                 end_pos: 10,
                 word_count: 10,
                 document_id: "doc1".to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
             },
             DocumentChunk {
                 id: "chunk2".to_string(),
-                content: "Natural language processing deals with text data".to_string(),
+                content: "Natural language processing deals with text data".into(),
                 start_pos: 0,
                 end_pos: 8,
                 word_count: 8,
                 document_id: "doc2".to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
             },
         ];
 
@@ -126,4 +1028,198 @@ mod tests {
         assert_eq!(results.len(), 2);
         assert!(results[0].score > results[1].score); // First result should be more relevant
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_bm25_search() {
+        let engine = SearchEngine::with_config(SearchConfig {
+            mode: SearchMode::Bm25,
+            ..SearchConfig::default()
+        })
+        .unwrap();
+        let chunks = vec![
+            DocumentChunk {
+                id: "chunk1".to_string(),
+                content: "Rust ownership and borrowing rules".into(),
+                start_pos: 0,
+                end_pos: 5,
+                word_count: 5,
+                document_id: "doc1".to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
+            },
+            DocumentChunk {
+                id: "chunk2".to_string(),
+                content: "Python is dynamically typed".into(),
+                start_pos: 0,
+                end_pos: 4,
+                word_count: 4,
+                document_id: "doc2".to_string(),
+                byte_start: 0,
+                byte_end: 0,
+                entities: Default::default(),
+                keyphrases: Default::default(),
+                minhash: Default::default(),
+            },
+        ];
+
+        let results = engine.search("rust borrowing", &chunks, 5).unwrap();
+        assert_eq!(results[0].chunk_id, "chunk1");
+        assert!(results[0].score > 0.0);
+        assert_eq!(results[1].score, 0.0);
+    }
+
+    #[test]
+    fn test_vector_and_hybrid_search() {
+        let chunk = |id: &str, content: &str| DocumentChunk {
+            id: id.to_string(),
+            content: content.into(),
+            start_pos: 0,
+            end_pos: 0,
+            word_count: 12,
+            document_id: id.to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        };
+        let chunks = vec![chunk("cats", "Felines purr and nap in the sun all afternoon long"), chunk("dogs", "Dogs bark")];
+        let embeddings = HashMap::from([
+            ("cats".to_string(), vec![1.0, 0.0]),
+            ("dogs".to_string(), vec![0.0, 1.0]),
+        ]);
+
+        let vector = SearchEngine::with_config(SearchConfig { mode: SearchMode::Vector, ..SearchConfig::default() }).unwrap();
+        let results = vector.search_with_embeddings("cat", &[0.9, 0.1], &chunks, &embeddings, 2).unwrap();
+        assert_eq!(results[0].chunk_id, "cats");
+
+        // With all weight on keywords, hybrid ranks by the lexical match instead
+        let hybrid = SearchEngine::with_config(SearchConfig {
+            mode: SearchMode::Hybrid,
+            keyword_weight: 1.0,
+            ..SearchConfig::default()
+        })
+        .unwrap();
+        let results = hybrid.search_with_embeddings("dogs", &[0.9, 0.1], &chunks, &embeddings, 2).unwrap();
+        assert_eq!(results[0].chunk_id, "dogs");
+    }
+
+    #[test]
+    fn test_binary_embeddings_only_rescore_hamming_candidates() {
+        use crate::embedding::cosine_similarity;
+        use crate::quantization::{EmbeddingQuantization, EmbeddingVector, StoredEmbedding};
+        let chunks: Vec<DocumentChunk> = ["a", "b", "c"].iter().map(|id| crate::testing::fake_chunk(id, 0, "text")).collect();
+        let binary = |vector: Vec<f32>| StoredEmbedding::new(vector, EmbeddingQuantization::Binary, None);
+        let embeddings = HashMap::from([
+            ("a_0".to_string(), binary(vec![0.9, 0.8, -0.1, 0.2])),
+            ("b_0".to_string(), binary(vec![0.9, 0.8, 0.1, -0.2])),
+            ("c_0".to_string(), binary(vec![-0.9, -0.8, 0.1, 0.2])),
+        ]);
+        let query = [0.7, 0.6, -0.3, 0.4];
+        let engine = |binary_candidates: usize| {
+            SearchEngine::with_config(SearchConfig { mode: SearchMode::Vector, binary_candidates, ..SearchConfig::default() })
+                .unwrap()
+        };
+
+        let full = |id: &str| cosine_similarity(&query, &embeddings[id].to_vec());
+        let signs = |id: &str| embeddings[id].binary_code().unwrap().cosine(&query).max(0.0);
+        let results = engine(1).search_with_embeddings("", &query, &chunks, &embeddings, 3).unwrap();
+        assert_eq!(results[0].chunk_id, "a_0");
+        assert!((results[0].score - full("a_0")).abs() < 1e-6, "the candidate is rescored at full precision");
+        assert!((results[1].score - signs(&results[1].chunk_id)).abs() < 1e-6, "the rest keep their sign estimate");
+        let results = engine(3).search_with_embeddings("", &query, &chunks, &embeddings, 3).unwrap();
+        assert!(results.iter().all(|r| (r.score - full(&r.chunk_id).max(0.0)).abs() < 1e-6));
+        assert!(SearchEngine::with_config(SearchConfig { binary_candidates: 0, ..SearchConfig::default() }).is_err());
+    }
+
+    #[test]
+    fn test_chunks_naming_query_entities_are_boosted() {
+        let chunks = vec![
+            crate::testing::fake_chunk("doc", 0, "during the war alan turing broke the enigma codes at bletchley."),
+            crate::testing::fake_chunk("doc", 1, "During the war Alan Turing broke the Enigma codes at Bletchley."),
+        ];
+        let results = SearchEngine::new().unwrap().search("alan turing enigma", &chunks, 2).unwrap();
+        assert_eq!(results[0].chunk_id, "doc_1");
+        assert!(results[0].score > results[1].score);
+
+        let unboosted = SearchEngine::with_config(SearchConfig {
+            entity_boost: 0.0,
+            keyphrase_boost: 0.0,
+            ..SearchConfig::default()
+        })
+        .unwrap();
+        let results = unboosted.search("alan turing enigma", &chunks, 2).unwrap();
+        assert_eq!(results[0].chunk_id, "doc_0");
+    }
+
+    #[test]
+    fn test_per_document_cap_leaves_room_for_other_documents() {
+        let chunks: Vec<DocumentChunk> = ["long", "long", "long", "short"]
+            .iter()
+            .enumerate()
+            .map(|(i, doc)| crate::testing::fake_chunk(doc, i, "text"))
+            .collect();
+        let scores = vec![0.9, 0.8, 0.7, 0.1];
+        let capped = SearchEngine::rank_filtered(&chunks, scores.clone(), 3, Some(2), None, &[false; 4]);
+        let ids: Vec<&str> = capped.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, ["long_0", "long_1", "short_3"]);
+        assert_eq!(capped[2].rank, 3);
+        assert!(SearchEngine::rank(&chunks, scores, 3).iter().all(|r| r.document_id == "long"));
+    }
+
+    #[test]
+    fn test_boosted_and_pinned_documents_outrank_mirrors() {
+        let chunks = vec![
+            crate::testing::fake_chunk("mirror", 0, "install the cli with cargo install, then run the cli"),
+            crate::testing::fake_chunk("official", 0, "cargo builds the cli from source"),
+            crate::testing::fake_chunk("blog", 0, "sunny skies today"),
+        ];
+        let engine = SearchEngine::new().unwrap();
+        let plain = engine.search("install the cli", &chunks, 3).unwrap();
+        assert_eq!(plain[0].document_id, "mirror");
+
+        let mut context = SearchContext::default();
+        context.boosts.insert("official".to_string(), DocumentBoost { factor: 3.0, pinned: false });
+        let boosted = engine.search_in("install the cli", &chunks, &context, 3).unwrap();
+        assert_eq!(boosted[0].document_id, "official");
+        assert!(boosted[0].score > plain.iter().find(|r| r.document_id == "official").unwrap().score);
+
+        context.boosts.insert("official".to_string(), DocumentBoost { factor: 0.1, pinned: true });
+        context.boosts.insert("blog".to_string(), DocumentBoost { factor: 1.0, pinned: true });
+        let pinned = engine.search_in("install the cli", &chunks, &context, 3).unwrap();
+        assert_eq!(pinned[0].document_id, "official");
+        // Pinning lifts what matched, not what didn't
+        assert!(pinned.iter().all(|r| r.document_id != "blog" || r.rank == 3));
+    }
+
+    #[test]
+    fn test_near_duplicate_chunks_give_way_to_distinct_ones() {
+        let policy = "Staff get twenty days of paid leave each year, booked through the portal with a week of notice.";
+        let chunks = vec![
+            crate::testing::fake_chunk("handbook", 0, policy),
+            crate::testing::fake_chunk("wiki", 0, &policy.replace("portal", "HR portal")),
+            crate::testing::fake_chunk("faq", 0, "Paid leave requests need a manager's approval before booking."),
+        ];
+        let engine = SearchEngine::with_config(SearchConfig { near_duplicate_threshold: Some(0.5), ..SearchConfig::default() }).unwrap();
+        let results = engine.search("paid leave portal", &chunks, 2).unwrap();
+        let ids: Vec<&str> = results.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, ["handbook_0", "faq_0"]);
+        assert_eq!(SearchEngine::new().unwrap().search("paid leave portal", &chunks, 2).unwrap()[1].chunk_id, "wiki_0");
+        assert!(SearchEngine::with_config(SearchConfig { near_duplicate_threshold: Some(0.0), ..SearchConfig::default() }).is_err());
+    }
+
+    #[test]
+    fn test_rank_keeps_top_scores_and_breaks_ties_by_chunk_order() {
+        let chunks: Vec<DocumentChunk> = (0..6).map(|i| crate::testing::fake_chunk("doc", i, "text")).collect();
+        let results = SearchEngine::rank(&chunks, vec![0.1, 0.5, 0.9, 0.5, 0.0, 0.5], 3);
+
+        let ids: Vec<&str> = results.iter().map(|r| r.chunk_id.as_str()).collect();
+        assert_eq!(ids, ["doc_2", "doc_1", "doc_3"]);
+        assert_eq!(results.iter().map(|r| r.rank).collect::<Vec<_>>(), [1, 2, 3]);
+        assert_eq!(SearchEngine::rank(&chunks, vec![0.0; 6], 10).len(), 6);
+        assert!(SearchEngine::rank(&chunks, vec![0.0; 6], 0).is_empty());
+    }
+}
@@ -0,0 +1,1048 @@
+//! HTTP API over one index for shared deployments: search it and add documents to it,
+//! optionally behind API keys with per-collection permissions.
+//!
+//! Query traffic scales out with read replicas: servers that load the snapshot a primary persists,
+//! reload it whenever it changes, and refuse writes, so ingestion stays on the one primary.
+
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::future::{poll_fn, ready, Future, Ready};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::pin::{pin, Pin};
+use std::sync::{Arc, Mutex, RwLock};
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+use tower::{Layer, Service};
+use crate::audit::{AuditAction, AuditFilter};
+use crate::auth::{presented_key, AuthConfig, AuthError, Permission};
+use crate::backup::BackupScheduler;
+use crate::ratelimit::{Permit, RequestLimiter, Throttled};
+use crate::storage::{StorageManager, StorageSnapshot};
+use crate::query::{Query, TypedQuery};
+use crate::saved::{SavedSearch, DEFAULT_SAVED_SEARCH_LIMIT};
+use crate::session::SessionStore;
+use crate::sort::ResultSort;
+use crate::tags::COLLECTION_TAG;
+use crate::SimpleRagSystem;
+
+/// Largest request body accepted, so one upload can't exhaust memory
+pub const MAX_BODY_BYTES: usize = 16 * 1024 * 1024;
+
+/// Most results one search may ask for
+pub const MAX_SEARCH_LIMIT: usize = 100;
+
+/// How long a client may stall while sending its request or reading the response before its
+/// connection, and the thread serving it, are let go
+pub const CONNECTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    /// Decoded query string parameters
+    pub query: BTreeMap<String, String>,
+    /// Keyed by lowercased header name
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Request for `target`, a path with an optional query string
+    pub fn new(method: &str, target: &str) -> Self {
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let query = query
+            .split('&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+                (decode_component(name), decode_component(value))
+            })
+            .collect();
+        Self {
+            method: method.to_string(),
+            path: path.to_string(),
+            query,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.insert(name.to_lowercase(), value.to_string());
+        self
+    }
+
+    pub fn with_body(mut self, body: impl Into<Vec<u8>>) -> Self {
+        self.body = body.into();
+        self
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(String::as_str)
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str).filter(|value| !value.is_empty())
+    }
+
+    /// Parse one HTTP/1.1 request; a malformed or oversized one becomes the response to send back
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Self, HttpResponse> {
+        let bad_request = |e: std::io::Error| match e.kind() {
+            std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                HttpResponse::error(408, "Timed out waiting for the request")
+            }
+            _ => HttpResponse::error(400, &e.to_string()),
+        };
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).map_err(bad_request)?;
+        let mut parts = request_line.split_whitespace();
+        let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+            return Err(HttpResponse::error(400, "Malformed request line"));
+        };
+        let mut request = Self::new(method, target);
+
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line).map_err(bad_request)? == 0 || line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                request = request.with_header(name.trim(), value.trim());
+            }
+        }
+
+        let length = match request.header("content-length") {
+            Some(length) => length.parse::<usize>().map_err(|_| HttpResponse::error(400, "Invalid Content-Length"))?,
+            None => 0,
+        };
+        if length > MAX_BODY_BYTES {
+            return Err(HttpResponse::error(413, &format!("Request body is over {} bytes", MAX_BODY_BYTES)));
+        }
+        request.body = vec![0; length];
+        reader.read_exact(&mut request.body).map_err(bad_request)?;
+        Ok(request)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub content_type: &'static str,
+    pub headers: Vec<(&'static str, String)>,
+    pub body: String,
+}
+
+impl HttpResponse {
+    pub fn json(status: u16, body: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            body: body.to_string(),
+        }
+    }
+
+    pub fn error(status: u16, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+
+    fn with_header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// The body parsed as JSON
+    pub fn body_json(&self) -> Option<Value> {
+        serde_json::from_str(&self.body).ok()
+    }
+
+    pub fn write_to(&self, mut out: impl Write) -> std::io::Result<()> {
+        write!(out, "HTTP/1.1 {} {}\r\n", self.status, reason(self.status))?;
+        write!(out, "Content-Type: {}\r\nContent-Length: {}\r\n", self.content_type, self.body.len())?;
+        for (name, value) in &self.headers {
+            write!(out, "{}: {}\r\n", name, value)?;
+        }
+        write!(out, "Connection: close\r\n\r\n{}", self.body)?;
+        out.flush()
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        201 => "Created",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        429 => "Too Many Requests",
+        _ => "Internal Server Error",
+    }
+}
+
+/// `+` and `%XX` escapes of a query string component
+fn decode_component(component: &str) -> String {
+    let bytes = component.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < bytes.len() => match component.get(i + 1..i + 3).map(|hex| u8::from_str_radix(hex, 16)) {
+                Some(Ok(byte)) => {
+                    decoded.push(byte);
+                    i += 2;
+                }
+                _ => decoded.push(b'%'),
+            },
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn internal(e: anyhow::Error) -> HttpResponse {
+    HttpResponse::error(500, &e.to_string())
+}
+
+/// Longest session id a client may choose
+const MAX_SESSION_ID_LEN: usize = 128;
+
+/// Store key of a client's session, kept apart from other keys' sessions of the same name
+fn session_id(key: &str, session: &str) -> Result<String, HttpResponse> {
+    if session.is_empty() || session.len() > MAX_SESSION_ID_LEN {
+        return Err(HttpResponse::error(400, &format!("Session ids must be 1 to {} bytes", MAX_SESSION_ID_LEN)));
+    }
+    Ok(format!("{}/{}", key, session))
+}
+
+/// Where a read replica follows its primary from: the snapshot file the primary persists to,
+/// typically on shared storage, checked for a new generation every `poll`
+#[derive(Debug)]
+pub struct Replica {
+    primary: PathBuf,
+    poll: Duration,
+    /// Generation of the snapshot last loaded
+    loaded: Mutex<Option<u64>>,
+}
+
+impl Replica {
+    pub fn new(primary: impl Into<PathBuf>, poll: Duration) -> Self {
+        Self {
+            primary: primary.into(),
+            poll,
+            loaded: Mutex::new(None),
+        }
+    }
+
+    pub fn primary(&self) -> &Path {
+        &self.primary
+    }
+
+    /// The primary's snapshot if its generation differs from the last one returned. The primary
+    /// renames each file into place whole, so a read never sees one half written
+    pub fn poll(&self) -> anyhow::Result<Option<StorageSnapshot>> {
+        let generation = StorageManager::snapshot_generation(&self.primary)
+            .map_err(|e| anyhow::anyhow!("Cannot read primary snapshot {}: {}", self.primary.display(), e))?;
+        let mut loaded = self.loaded.lock().unwrap();
+        if *loaded == Some(generation) {
+            return Ok(None);
+        }
+        let snapshot = StorageManager::read_snapshot(&self.primary)?;
+        *loaded = Some(snapshot.generation);
+        Ok(Some(snapshot))
+    }
+}
+
+/// Serves `GET /search`, `POST /documents`, `/synonyms`, `GET /audit`, `GET /metrics` and `GET /health`, a thread per connection
+pub struct RagServer {
+    rag: RwLock<SimpleRagSystem>,
+    auth: Arc<AuthConfig>,
+    limits: RateLimitLayer,
+    replica: Option<Replica>,
+    backups: Option<Mutex<BackupScheduler>>,
+    /// Search sessions by key name and client-chosen id
+    sessions: SessionStore,
+    timeout: Duration,
+}
+
+impl RagServer {
+    pub fn new(rag: SimpleRagSystem, auth: AuthConfig) -> Self {
+        let auth = Arc::new(auth);
+        Self {
+            rag: RwLock::new(rag),
+            limits: RateLimitLayer::new(auth.clone()),
+            auth,
+            replica: None,
+            backups: None,
+            sessions: SessionStore::new(),
+            timeout: CONNECTION_TIMEOUT,
+        }
+    }
+
+    /// Drop connections that stall for longer than `timeout`, instead of `CONNECTION_TIMEOUT`
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Back the index up on `scheduler`'s interval while serving
+    pub fn with_backups(mut self, scheduler: BackupScheduler) -> Self {
+        self.backups = Some(Mutex::new(scheduler));
+        self
+    }
+
+    /// Serve as a read-only replica of `replica`'s primary; `serve` keeps the index in step with it
+    pub fn with_replica(mut self, replica: Replica) -> Self {
+        self.replica = Some(replica);
+        self
+    }
+
+    /// Load the primary's snapshot if it changed, returning whether it did. Queries keep being
+    /// answered from the old index while the new one is read
+    pub fn sync_replica(&self) -> anyhow::Result<bool> {
+        let Some(snapshot) = self.replica.as_ref().map(Replica::poll).transpose()?.flatten() else {
+            return Ok(false);
+        };
+        let documents = self.rag.write().unwrap().restore_snapshot(snapshot)?;
+        tracing::info!("Replica loaded {} documents from its primary", documents);
+        Ok(true)
+    }
+
+    /// Answer connections on `listener` until it fails
+    pub fn serve(self: Arc<Self>, listener: TcpListener) -> anyhow::Result<()> {
+        if let Some(replica) = &self.replica {
+            let server = self.clone();
+            let poll = replica.poll;
+            std::thread::spawn(move || loop {
+                std::thread::sleep(poll);
+                if let Err(e) = server.sync_replica() {
+                    tracing::warn!("Replica sync failed: {}", e);
+                }
+            });
+        }
+        if self.backups.is_some() {
+            let server = self.clone();
+            std::thread::spawn(move || loop {
+                let wait = server.run_backup_if_due();
+                std::thread::sleep(Duration::from_secs(wait));
+            });
+        }
+        for stream in listener.incoming() {
+            let stream = stream?;
+            // Without these a client that never finishes its request holds its thread forever
+            stream.set_read_timeout(Some(self.timeout))?;
+            stream.set_write_timeout(Some(self.timeout))?;
+            let server = self.clone();
+            std::thread::spawn(move || {
+                if let Err(e) = server.respond(stream) {
+                    tracing::warn!("HTTP request failed: {}", e);
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Back up if a backup is due, returning the seconds to wait before checking again
+    fn run_backup_if_due(&self) -> u64 {
+        let Some(backups) = &self.backups else {
+            return 60;
+        };
+        let mut scheduler = backups.lock().unwrap();
+        // Searches go on during a backup; only writes wait for it
+        match scheduler.run_if_due(&self.rag.read().unwrap(), crate::answer_cache::unix_now()) {
+            Ok(_) => scheduler.due_in(crate::answer_cache::unix_now()).clamp(1, 60),
+            Err(e) => {
+                tracing::warn!("Backup failed: {}", e);
+                60
+            }
+        }
+    }
+
+    fn respond(&self, stream: TcpStream) -> std::io::Result<()> {
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let response = match HttpRequest::read_from(&mut reader) {
+            Ok(request) => self.handle(&request),
+            Err(response) => response,
+        };
+        response.write_to(stream)
+    }
+
+    /// Answer `request` through the server's middleware stack, `RateLimitLayer` around the routes
+    pub fn handle(&self, request: &HttpRequest) -> HttpResponse {
+        let mut service = self.limits.layer(Routes(self));
+        let Ok(response) = run_inline(async {
+            poll_fn(|cx| service.poll_ready(cx)).await?;
+            service.call(request).await
+        });
+        tracing::debug!("{} {} -> {}", request.method, request.path, response.status);
+        response
+    }
+
+    fn route(&self, request: &HttpRequest) -> HttpResponse {
+        let response = match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => Ok(self.health()),
+            ("GET", "/metrics") => self.metrics(request),
+            ("GET", "/search") => self.search(request),
+            ("POST" | "PUT" | "DELETE", "/documents" | "/synonyms" | "/saved") if self.replica.is_some() => {
+                Err(HttpResponse::error(403, "This server is a read-only replica; send writes to its primary"))
+            }
+            ("POST", "/documents") => self.add_document(request),
+            ("GET", "/synonyms") => self.list_synonyms(request),
+            ("PUT", "/synonyms") => self.set_synonyms(request),
+            ("DELETE", "/synonyms") => self.remove_synonyms(request),
+            ("GET", "/audit") => self.audit_log(request),
+            ("POST", "/reject") => self.reject(request),
+            ("GET", "/saved") => self.list_saved_searches(request),
+            ("PUT", "/saved") => self.save_search(request),
+            ("DELETE", "/saved") => self.delete_saved_search(request),
+            (_, "/health" | "/metrics" | "/search" | "/reject" | "/documents" | "/synonyms" | "/audit" | "/saved") => {
+                Err(HttpResponse::error(405, "Method not allowed"))
+            }
+            _ => Err(HttpResponse::error(404, "Not found")),
+        };
+        response.unwrap_or_else(|response| response)
+    }
+
+    /// Name of the key that grants `needed` on `collection`, "anonymous" without auth
+    fn authorize(&self, request: &HttpRequest, collection: Option<&str>, needed: Permission) -> Result<String, HttpResponse> {
+        let presented = presented_key(request.header("authorization"), request.header("x-api-key"));
+        match self.auth.authorize(presented, collection, needed) {
+            Ok(name) => Ok(name.unwrap_or("anonymous").to_string()),
+            Err(AuthError::Unauthenticated) => Err(HttpResponse::error(401, "Missing or unknown API key")
+                .with_header("WWW-Authenticate", "Bearer")),
+            Err(AuthError::Forbidden { key }) => {
+                let scope = collection.map_or("the whole index".to_string(), |c| format!("collection '{}'", c));
+                let action = if needed == Permission::Write { "write to" } else { "read" };
+                Err(HttpResponse::error(403, &format!("Key '{}' may not {} {}", key, action, scope)))
+            }
+        }
+    }
+
+    fn health(&self) -> HttpResponse {
+        let role = if self.replica.is_some() { "replica" } else { "primary" };
+        HttpResponse::json(200, json!({ "status": "ok", "role": role }))
+    }
+
+    fn metrics(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        // Any valid key may read the counters, whatever its collections
+        let presented = presented_key(request.header("authorization"), request.header("x-api-key"));
+        if self.auth.is_enabled() && presented.and_then(|key| self.auth.key(key)).is_none() {
+            return Err(HttpResponse::error(401, "Missing or unknown API key").with_header("WWW-Authenticate", "Bearer"));
+        }
+        let body = self.rag.read().unwrap().metrics().render();
+        Ok(HttpResponse {
+            status: 200,
+            content_type: "text/plain; version=0.0.4",
+            headers: Vec::new(),
+            body,
+        })
+    }
+
+    /// Searches for `q`, or runs the search saved as `saved`, within `session` when one is named
+    fn search(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let saved = request.param("saved");
+        let raw = match request.param("raw") {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(_) => return Err(HttpResponse::error(400, "raw must be true or false")),
+        };
+        let query = match (request.param("q"), saved) {
+            (Some(query), _) if raw => Some(TypedQuery::raw(query)),
+            (Some(query), _) => Some(TypedQuery::parse(query).map_err(|e| HttpResponse::error(400, &e.to_string()))?),
+            (None, Some(_)) => None,
+            (None, None) => return Err(HttpResponse::error(400, "Missing query parameter 'q'")),
+        };
+        let limit = match request.param("limit") {
+            Some(limit) => Some(limit.parse::<usize>().map_err(|_| HttpResponse::error(400, "Invalid limit"))?),
+            None => None,
+        };
+        let sort = match request.param("sort") {
+            Some(sort) => sort.parse::<ResultSort>().map_err(|e| HttpResponse::error(400, &e.to_string()))?,
+            None => ResultSort::default(),
+        };
+        if !sort.is_relevance() && request.param("session").is_some() {
+            return Err(HttpResponse::error(400, "sort can't be combined with a session, which ranks by score"));
+        }
+        let collection = request.param("collection");
+        let key = self.authorize(request, collection, Permission::Read)?;
+
+        let rag = self.rag.read().unwrap();
+        let (mut query, limit) = match (query, saved) {
+            (Some(query), _) => (query, limit.unwrap_or(5)),
+            (None, Some(name)) => {
+                let saved = rag
+                    .saved_search(name)
+                    .ok_or_else(|| HttpResponse::error(404, &format!("No saved search named '{}'", name)))?;
+                let limit = limit.or(saved.limit).unwrap_or(DEFAULT_SAVED_SEARCH_LIMIT);
+                (TypedQuery { input: saved.query.clone(), query: saved.parsed().map_err(internal)? }, limit)
+            }
+            (None, None) => unreachable!("a request without 'q' or 'saved' was turned away above"),
+        };
+        // Required alongside the whole query, so an `OR` in it can't reach past the collection
+        if let Some(collection) = collection {
+            query.query = Query::And(vec![query.query, Query::tag(COLLECTION_TAG, collection)]);
+        }
+        let limit = limit.min(MAX_SEARCH_LIMIT);
+        let faceted = match request.param("facets") {
+            Some("true") => true,
+            Some("false") | None => false,
+            Some(_) => return Err(HttpResponse::error(400, "facets must be true or false")),
+        };
+        // Searched outside the store's lock, so one session's search doesn't hold up the others',
+        // then only its results are recorded, keeping rejections made while it ran
+        let session = match request.param("session") {
+            Some(session) => {
+                let id = session_id(&key, session)?;
+                Some((self.sessions.with(&id, |session| session.clone()), id))
+            }
+            None => None,
+        };
+        let (results, facets) = match &session {
+            _ if faceted => {
+                let searched = rag.search_faceted_with(&query, limit, sort, session.as_ref().map(|(session, _)| session));
+                let faceted = searched.map_err(internal)?;
+                (faceted.results, Some(faceted.facets))
+            }
+            Some((session, _)) => (rag.search_with_session(session, &query, limit).map_err(internal)?, None),
+            None => (rag.search_sorted(&query, limit, sort).map_err(internal)?, None),
+        };
+        if let Some((_, id)) = &session {
+            self.sessions.with(id, |stored| stored.record(&results));
+        }
+        let mut hits = Vec::new();
+        for result in results.into_iter().filter(|r| r.score > 0.0) {
+            let source = rag.get_document(&result.document_id).map_err(internal)?.map(|doc| doc.metadata.file_path);
+            hits.push(json!({
+                "document_id": result.document_id,
+                "chunk_id": result.chunk_id,
+                "source": source,
+                "score": result.score,
+                "content": result.content,
+            }));
+        }
+        let mut body = json!({ "results": hits });
+        if let Some(facets) = facets {
+            body["facets"] = json!(facets);
+        }
+        Ok(HttpResponse::json(200, body))
+    }
+
+    /// The body is the document's text, `name` its source path and `collection` where it goes
+    fn add_document(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let name = request.param("name").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'name'"))?;
+        let collection = request.param("collection");
+        let key = self.authorize(request, collection, Permission::Write)?;
+        if std::str::from_utf8(&request.body).is_err() {
+            return Err(HttpResponse::error(400, "Document body must be UTF-8 text"));
+        }
+
+        let mut rag = self.rag.write().unwrap();
+        let doc_id = rag.as_actor(&key, |rag| rag.process_bytes(name, &request.body)).map_err(internal)?;
+        if let Some(collection) = collection {
+            let tags = BTreeMap::from([(COLLECTION_TAG.to_string(), collection.to_string())]);
+            rag.set_tags(&doc_id, tags).map_err(internal)?;
+        }
+        rag.persist().map_err(internal)?;
+        let chunks = rag.document_chunks(&doc_id).map_err(internal)?.len();
+        tracing::info!("{} added {} as {} ({} chunks)", key, name, doc_id, chunks);
+        Ok(HttpResponse::json(201, json!({ "document_id": doc_id, "chunks": chunks })))
+    }
+
+    /// The whole dictionary, as term to expansions
+    fn list_synonyms(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        self.authorize(request, None, Permission::Read)?;
+        let rag = self.rag.read().unwrap();
+        Ok(HttpResponse::json(200, json!({ "synonyms": rag.synonyms() })))
+    }
+
+    /// Adds the comma-separated `expansions` to `term`; like the rest of the dictionary they last
+    /// until the server stops, so lasting ones belong in the synonyms file
+    fn set_synonyms(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let term = request.param("term").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'term'"))?;
+        let expansions = request
+            .param("expansions")
+            .ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'expansions'"))?;
+        let expansions: Vec<&str> = expansions.split(',').collect();
+        let key = self.authorize(request, None, Permission::Write)?;
+
+        let mut rag = self.rag.write().unwrap();
+        rag.add_synonyms(term, &expansions);
+        let Some(expansions) = rag.synonyms().get(term) else {
+            return Err(HttpResponse::error(400, "No usable terms in 'expansions'"));
+        };
+        tracing::info!("{} set synonyms of '{}' to {:?}", key, term, expansions);
+        Ok(HttpResponse::json(200, json!({ "term": term, "expansions": expansions })))
+    }
+
+    fn remove_synonyms(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let term = request.param("term").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'term'"))?;
+        let key = self.authorize(request, None, Permission::Write)?;
+        if !self.rag.write().unwrap().remove_synonyms(term) {
+            return Err(HttpResponse::error(404, &format!("No synonyms for '{}'", term)));
+        }
+        tracing::info!("{} removed synonyms of '{}'", key, term);
+        Ok(HttpResponse::json(200, json!({ "term": term })))
+    }
+
+    /// Demotes `document_id` in the caller's later searches in `session`
+    fn reject(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let session = request.param("session").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'session'"))?;
+        let document_id = request
+            .param("document_id")
+            .ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'document_id'"))?;
+        let key = self.authorize(request, request.param("collection"), Permission::Read)?;
+        self.sessions.with(&session_id(&key, session)?, |session| session.reject(document_id));
+        Ok(HttpResponse::json(200, json!({ "session": session, "rejected": document_id })))
+    }
+
+    /// Saved searches span the index like synonyms, so they need a key over all of it
+    fn list_saved_searches(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        self.authorize(request, None, Permission::Read)?;
+        let rag = self.rag.read().unwrap();
+        Ok(HttpResponse::json(200, json!({ "saved": rag.saved_searches() })))
+    }
+
+    /// Saves `q` as `name`, with an optional default `limit`, replacing any search saved under it
+    fn save_search(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let name = request.param("name").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'name'"))?;
+        let query = request.param("q").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'q'"))?;
+        let mut search = SavedSearch::new(name, query);
+        if let Some(limit) = request.param("limit") {
+            search = search.with_limit(limit.parse::<usize>().map_err(|_| HttpResponse::error(400, "Invalid limit"))?);
+        }
+        let key = self.authorize(request, None, Permission::Write)?;
+
+        let mut rag = self.rag.write().unwrap();
+        let replaced = rag.save_search(search).map_err(|e| HttpResponse::error(400, &e.to_string()))?;
+        rag.persist().map_err(internal)?;
+        tracing::info!("{} saved search '{}' as {}", key, query, name);
+        let saved = rag.saved_search(name);
+        Ok(HttpResponse::json(if replaced.is_some() { 200 } else { 201 }, json!(saved)))
+    }
+
+    fn delete_saved_search(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let name = request.param("name").ok_or_else(|| HttpResponse::error(400, "Missing query parameter 'name'"))?;
+        let key = self.authorize(request, None, Permission::Write)?;
+        let mut rag = self.rag.write().unwrap();
+        rag.delete_saved_search(name).map_err(|e| HttpResponse::error(404, &e.to_string()))?;
+        rag.persist().map_err(internal)?;
+        tracing::info!("{} deleted saved search {}", key, name);
+        Ok(HttpResponse::json(200, json!({ "name": name })))
+    }
+
+    /// Audited operations, oldest first, narrowed by `actor`, `action`, `document_id` and `since`
+    /// (Unix seconds); `limit` keeps only the newest
+    fn audit_log(&self, request: &HttpRequest) -> Result<HttpResponse, HttpResponse> {
+        let action = match request.param("action") {
+            Some(action) => Some(action.parse::<AuditAction>().map_err(|e| HttpResponse::error(400, &e.to_string()))?),
+            None => None,
+        };
+        let since = match request.param("since") {
+            Some(since) => Some(since.parse::<u64>().map_err(|_| HttpResponse::error(400, "Invalid since"))?),
+            None => None,
+        };
+        let limit = match request.param("limit") {
+            Some(limit) => limit.parse::<usize>().map_err(|_| HttpResponse::error(400, "Invalid limit"))?,
+            None => 100,
+        };
+        // Operations span every collection, so only keys over the whole index may read them
+        self.authorize(request, None, Permission::Read)?;
+
+        let filter = AuditFilter {
+            actor: request.param("actor").map(str::to_string),
+            action,
+            document_id: request.param("document_id").map(str::to_string),
+            since,
+        };
+        let entries = self.rag.read().unwrap().audit_log(&filter);
+        let shown = &entries[entries.len().saturating_sub(limit)..];
+        Ok(HttpResponse::json(200, json!({ "entries": shown })))
+    }
+}
+
+/// The routes behind `RagServer::handle`, as the innermost service of its stack
+struct Routes<'a>(&'a RagServer);
+
+impl<'r> Service<&'r HttpRequest> for Routes<'_> {
+    type Response = HttpResponse;
+    type Error = Infallible;
+    type Future = Ready<Result<HttpResponse, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: &'r HttpRequest) -> Self::Future {
+        ready(Ok(self.0.route(request)))
+    }
+}
+
+/// Tower middleware holding each key to its `RateLimits`: over a limit the request is answered
+/// `429` with a `Retry-After` header instead of reaching the service it wraps. `/health` and
+/// `/metrics` pass through uncounted, as do unknown keys, which the routes refuse with `401`
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    auth: Arc<AuthConfig>,
+    limiter: RequestLimiter,
+}
+
+impl RateLimitLayer {
+    pub fn new(auth: Arc<AuthConfig>) -> Self {
+        Self { auth, limiter: RequestLimiter::new() }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> RateLimit<S> {
+        RateLimit { inner, layer: self.clone() }
+    }
+}
+
+/// A service wrapped by `RateLimitLayer`
+#[derive(Debug, Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    layer: RateLimitLayer,
+}
+
+impl<S> RateLimit<S> {
+    /// One of the request's key's slots, `None` for requests that aren't counted
+    fn admit(&self, request: &HttpRequest) -> Result<Option<Permit>, HttpResponse> {
+        if matches!(request.path.as_str(), "/health" | "/metrics") {
+            return Ok(None);
+        }
+        let auth = &self.layer.auth;
+        let key = if auth.is_enabled() {
+            let presented = presented_key(request.header("authorization"), request.header("x-api-key"));
+            match presented.and_then(|presented| auth.key(presented)) {
+                Some(key) => key.name.as_str(),
+                None => return Ok(None),
+            }
+        } else {
+            "anonymous"
+        };
+        let permit = self.layer.limiter.acquire(key, auth.limits_for(key)).map_err(|throttled| {
+            let message = match throttled {
+                Throttled::Rate { .. } => format!("Key '{}' is over its request rate", key),
+                Throttled::Concurrency => format!("Key '{}' has too many requests in flight", key),
+            };
+            HttpResponse::error(429, &message).with_header("Retry-After", throttled.retry_after_secs().to_string())
+        })?;
+        Ok(Some(permit))
+    }
+}
+
+impl<'r, S> Service<&'r HttpRequest> for RateLimit<S>
+where
+    S: Service<&'r HttpRequest, Response = HttpResponse>,
+    S::Future: Unpin,
+{
+    type Response = HttpResponse;
+    type Error = S::Error;
+    type Future = Limited<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), S::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: &'r HttpRequest) -> Self::Future {
+        match self.admit(request) {
+            Ok(permit) => Limited::Admitted { response: self.inner.call(request), _permit: permit },
+            Err(throttled) => Limited::Throttled(Some(throttled)),
+        }
+    }
+}
+
+/// Response of a `RateLimit` service; an admitted request keeps its key's slot until this is dropped
+#[derive(Debug)]
+pub enum Limited<F> {
+    Admitted { response: F, _permit: Option<Permit> },
+    Throttled(Option<HttpResponse>),
+}
+
+impl<F, E> Future for Limited<F>
+where
+    F: Future<Output = Result<HttpResponse, E>> + Unpin,
+{
+    type Output = Result<HttpResponse, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        match self.get_mut() {
+            Limited::Admitted { response, .. } => Pin::new(response).poll(cx),
+            Limited::Throttled(response) => Poll::Ready(Ok(response.take().expect("polled after completion"))),
+        }
+    }
+}
+
+/// Every service in the server's stack answers without waiting, so this polls once on the
+/// connection's own thread rather than needing an async runtime
+fn run_inline<F: Future>(future: F) -> F::Output {
+    match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+        Poll::Ready(output) => output,
+        Poll::Pending => unreachable!("the server's services never wait"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AccessKey;
+    use crate::ratelimit::RateLimits;
+
+    fn server() -> RagServer {
+        let auth = AuthConfig {
+            keys: vec![
+                AccessKey {
+                    name: "writer".to_string(),
+                    key: "w-secret".to_string(),
+                    collections: BTreeMap::from([("handbook".to_string(), Permission::Write)]),
+                    limits: RateLimits::default(),
+                },
+                AccessKey {
+                    name: "reader".to_string(),
+                    key: "r-secret".to_string(),
+                    collections: BTreeMap::from([("handbook".to_string(), Permission::Read)]),
+                    limits: RateLimits::default(),
+                },
+            ],
+            limits: RateLimits::default(),
+        };
+        RagServer::new(SimpleRagSystem::new().unwrap(), auth)
+    }
+
+    #[test]
+    fn test_keys_gate_ingest_and_search_per_collection() {
+        let server = server();
+        let upload = |key: &str| {
+            HttpRequest::new("POST", "/documents?name=leave.md&collection=handbook")
+                .with_header("Authorization", &format!("Bearer {}", key))
+                .with_body("Staff get twenty days of paid leave.")
+        };
+
+        assert_eq!(server.handle(&upload("nope")).status, 401);
+        assert_eq!(server.handle(&upload("r-secret")).status, 403);
+        assert_eq!(server.handle(&upload("w-secret")).status, 201);
+
+        let search = |target: &str| HttpRequest::new("GET", target).with_header("X-Api-Key", "r-secret");
+        let found = server.handle(&search("/search?q=paid+leave&collection=handbook"));
+        assert_eq!(found.status, 200);
+        assert_eq!(found.body_json().unwrap()["results"][0]["source"], "leave.md");
+        // Without a collection the search spans the whole index, which needs `*`
+        assert_eq!(server.handle(&search("/search?q=leave")).status, 403);
+        assert_eq!(server.handle(&search("/search?q=leave&collection=payroll")).status, 403);
+
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/health")).status, 200);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/metrics")).status, 401);
+        assert_eq!(server.handle(&HttpRequest::new("DELETE", "/search")).status, 405);
+    }
+
+    #[test]
+    fn test_search_accepts_query_syntax_within_collection() {
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default());
+        let upload = |target: &str, body: &str| server.handle(&HttpRequest::new("POST", target).with_body(body)).status;
+        assert_eq!(upload("/documents?name=leave.md&collection=handbook", "Staff get twenty days of paid leave."), 201);
+        assert_eq!(upload("/documents?name=pay.txt&collection=payroll", "Pay day is the 25th, leave is unpaid."), 201);
+        let sources = |target: &str| {
+            let found = server.handle(&HttpRequest::new("GET", target)).body_json().unwrap();
+            found["results"].as_array().unwrap().iter().map(|r| r["source"].as_str().unwrap().to_string()).collect::<Vec<_>>()
+        };
+
+        assert_eq!(sources("/search?q=leave+type:txt"), ["pay.txt"]);
+        assert_eq!(sources("/search?q=leave+-%22paid+leave%22"), ["pay.txt"]);
+        // The collection holds for the whole query, OR included
+        assert_eq!(sources("/search?q=leave+OR+collection:payroll&collection=handbook"), ["leave.md"]);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/search?q=(leave")).status, 400);
+        // Raw queries are plain text, as searches were before the query syntax
+        assert_eq!(sources("/search?q=(leave&raw=true").len(), 2);
+        assert_eq!(sources("/search?q=leave+type:txt&raw=true").len(), 2);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/search?q=leave&raw=1")).status, 400);
+        let faceted = server.handle(&HttpRequest::new("GET", "/search?q=leave&limit=1&facets=true")).body_json().unwrap();
+        assert_eq!(faceted["results"].as_array().unwrap().len(), 1);
+        assert_eq!(faceted["facets"]["collection"], json!({ "handbook": 1, "payroll": 1 }));
+        assert_eq!(faceted["facets"]["file_type"], json!({ "md": 1, "txt": 1 }));
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/search?q=leave&facets=yes")).status, 400);
+        assert_eq!(sources("/search?q=type:txt"), ["pay.txt"]);
+
+        assert_eq!(sources("/search?q=leave&sort=path:desc&limit=1"), ["pay.txt"]);
+        assert_eq!(sources("/search?q=leave&sort=size"), ["pay.txt", "leave.md"]);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/search?q=leave&sort=newest")).status, 400);
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/search?q=leave&sort=date&session=s1")).status, 400);
+    }
+
+    #[test]
+    fn test_saved_searches_run_by_name() {
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default());
+        let request = |method: &str, target: &str| server.handle(&HttpRequest::new(method, target));
+        let upload = HttpRequest::new("POST", "/documents?name=leave.md&collection=handbook").with_body("Staff get twenty days of paid leave.");
+        assert_eq!(server.handle(&upload).status, 201);
+
+        assert_eq!(request("PUT", "/saved?name=leave&q=%22paid+leave%22&limit=1").status, 201);
+        assert_eq!(request("PUT", "/saved?name=leave&q=(paid").status, 400);
+        assert_eq!(request("PUT", "/saved?name=pay+day&q=pay").status, 400);
+        assert_eq!(request("GET", "/saved").body_json().unwrap()["saved"][0]["limit"], 1);
+
+        let found = request("GET", "/search?saved=leave&collection=handbook").body_json().unwrap();
+        assert_eq!(found["results"][0]["source"], "leave.md");
+        assert_eq!(request("GET", "/search?saved=leave&collection=payroll").body_json().unwrap()["results"], json!([]));
+        assert_eq!(request("DELETE", "/saved?name=leave").status, 200);
+        assert_eq!(request("GET", "/search?saved=leave").status, 404);
+        assert_eq!(request("DELETE", "/saved?name=leave").status, 404);
+
+        // Saved searches span every collection, so collection-scoped keys can't change them
+        let scoped = HttpRequest::new("PUT", "/saved?name=leave&q=leave").with_header("X-Api-Key", "w-secret");
+        assert_eq!(self::server().handle(&scoped).status, 403);
+    }
+
+    #[test]
+    fn test_rejections_demote_within_a_session() {
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default());
+        let request = |method: &str, target: &str| server.handle(&HttpRequest::new(method, target));
+        for (name, body) in [("leave.md", "Paid leave: twenty days of leave."), ("form.md", "The leave request form.")] {
+            assert_eq!(server.handle(&HttpRequest::new("POST", &format!("/documents?name={}", name)).with_body(body)).status, 201);
+        }
+        let top = |target: &str| request("GET", target).body_json().unwrap()["results"][0]["source"].clone();
+
+        assert_eq!(top("/search?q=leave&session=s1"), "leave.md");
+        let found = request("GET", "/search?q=leave&session=s1").body_json().unwrap();
+        let leave = found["results"][0]["document_id"].as_str().unwrap().to_string();
+        assert_eq!(request("POST", &format!("/reject?session=s1&document_id={}", leave)).status, 200);
+        assert_eq!(top("/search?q=leave&session=s1"), "form.md");
+        // Other sessions, and searches outside any, are unaffected
+        assert_eq!(top("/search?q=leave&session=s2"), "leave.md");
+        assert_eq!(top("/search?q=leave"), "leave.md");
+        assert_eq!(request("POST", "/reject?session=s1").status, 400);
+        assert_eq!(request("GET", &format!("/search?q=leave&session={}", "s".repeat(200))).status, 400);
+    }
+
+    #[test]
+    fn test_synonyms_change_at_runtime() {
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default());
+        let request = |method: &str, target: &str| server.handle(&HttpRequest::new(method, target));
+
+        assert_eq!(request("PUT", "/synonyms?term=k8s&expansions=kubernetes,kube").status, 200);
+        let listed = request("GET", "/synonyms").body_json().unwrap();
+        assert_eq!(listed["synonyms"]["k8s"], json!(["kubernetes", "kube"]));
+        assert_eq!(request("PUT", "/synonyms?term=k8s").status, 400);
+        assert_eq!(request("PUT", "/synonyms?term=pg&expansions=,").status, 400);
+        assert_eq!(request("DELETE", "/synonyms?term=K8s").status, 200);
+        assert_eq!(request("DELETE", "/synonyms?term=k8s").status, 404);
+    }
+
+    #[test]
+    fn test_audit_log_records_uploads_by_key() {
+        let server = server();
+        let upload = HttpRequest::new("POST", "/documents?name=leave.md&collection=handbook")
+            .with_header("X-Api-Key", "w-secret")
+            .with_body("Staff get twenty days of paid leave.");
+        assert_eq!(server.handle(&upload).status, 201);
+        // Collection-scoped keys can't see operations on the rest of the index
+        let audit = HttpRequest::new("GET", "/audit").with_header("X-Api-Key", "r-secret");
+        assert_eq!(server.handle(&audit).status, 403);
+
+        let entries = server.rag.read().unwrap().audit_log(&AuditFilter::default());
+        assert_eq!(entries.len(), 1);
+        assert_eq!((entries[0].actor.as_str(), entries[0].action), ("writer", AuditAction::Ingest));
+        assert_eq!(entries[0].source.as_deref(), Some("leave.md"));
+
+        let open = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default());
+        open.handle(&HttpRequest::new("POST", "/documents?name=pay.md").with_body("Pay day is the 25th."));
+        let listed = open.handle(&HttpRequest::new("GET", "/audit?action=ingest")).body_json().unwrap();
+        assert_eq!(listed["entries"][0]["actor"], "anonymous");
+        assert_eq!(open.handle(&HttpRequest::new("GET", "/audit?action=rename")).status, 400);
+        assert_eq!(open.handle(&HttpRequest::new("GET", "/audit?action=purge")).body_json().unwrap()["entries"], json!([]));
+    }
+
+    #[test]
+    fn test_spent_rate_limit_answers_429_with_retry_after() {
+        let limits = RateLimits { requests_per_minute: Some(1), max_concurrent: None };
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig { keys: Vec::new(), limits });
+        let search = HttpRequest::new("GET", "/search?q=anything");
+
+        assert_eq!(server.handle(&search).status, 200);
+        let throttled = server.handle(&search);
+        assert_eq!(throttled.status, 429);
+        assert_eq!(throttled.headers, vec![("Retry-After", "60".to_string())]);
+        // Health checks aren't counted against the budget
+        assert_eq!(server.handle(&HttpRequest::new("GET", "/health")).status, 200);
+    }
+
+    #[test]
+    fn test_rate_limit_layer_holds_a_slot_until_the_response_is_dropped() {
+        let limits = RateLimits { requests_per_minute: None, max_concurrent: Some(1) };
+        let server = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig { keys: Vec::new(), limits });
+        let search = HttpRequest::new("GET", "/search?q=anything");
+
+        let in_flight = server.limits.layer(Routes(&server)).call(&search);
+        let throttled = server.handle(&search);
+        assert_eq!(throttled.status, 429);
+        assert_eq!(throttled.headers, vec![("Retry-After", "1".to_string())]);
+        assert_eq!(run_inline(in_flight).unwrap().status, 200);
+        assert_eq!(server.handle(&search).status, 200);
+    }
+
+    #[test]
+    fn test_replica_follows_primary_and_refuses_writes() {
+        let path = std::env::temp_dir().join(format!("rag_replica_{}.json", std::process::id()));
+        let mut primary = SimpleRagSystem::open(&path).unwrap();
+        primary.process_bytes("leave.md", b"Staff get twenty days of paid leave.").unwrap();
+        primary.persist().unwrap();
+
+        let replica = RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default())
+            .with_replica(Replica::new(&path, Duration::from_secs(1)));
+        assert!(replica.sync_replica().unwrap());
+        assert!(!replica.sync_replica().unwrap());
+        let found = replica.handle(&HttpRequest::new("GET", "/search?q=paid+leave"));
+        assert_eq!(found.body_json().unwrap()["results"][0]["source"], "leave.md");
+        assert_eq!(replica.handle(&HttpRequest::new("GET", "/health")).body_json().unwrap()["role"], "replica");
+        let upload = HttpRequest::new("POST", "/documents?name=pay.md").with_body("Pay day is the 25th.");
+        assert_eq!(replica.handle(&upload).status, 403);
+        assert_eq!(replica.handle(&HttpRequest::new("PUT", "/synonyms?term=pto&expansions=leave")).status, 403);
+
+        primary.process_bytes("pay.md", b"Pay day is the 25th.").unwrap();
+        primary.persist().unwrap();
+        assert_eq!(StorageManager::snapshot_generation(&path).unwrap(), 2);
+        assert!(replica.sync_replica().unwrap());
+        assert!(!replica.sync_replica().unwrap());
+        let found = replica.handle(&HttpRequest::new("GET", "/search?q=pay+day"));
+        assert_eq!(found.body_json().unwrap()["results"][0]["source"], "pay.md");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_requests_parse_from_the_wire() {
+        let raw = "POST /documents?name=a%20b.txt HTTP/1.1\r\nContent-Length: 5\r\nX-Api-Key: k\r\n\r\nhello";
+        let request = HttpRequest::read_from(&mut raw.as_bytes()).unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/documents"));
+        assert_eq!(request.query["name"], "a b.txt");
+        assert_eq!(request.header("x-api-key"), Some("k"));
+        assert_eq!(request.body, b"hello");
+
+        let oversized = format!("POST /documents HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_BYTES + 1);
+        assert_eq!(HttpRequest::read_from(&mut oversized.as_bytes()).unwrap_err().status, 413);
+    }
+
+    #[test]
+    fn test_stalled_connections_time_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Arc::new(RagServer::new(SimpleRagSystem::new().unwrap(), AuthConfig::default()).with_timeout(Duration::from_millis(100)));
+        std::thread::spawn(move || server.serve(listener));
+
+        // A client that sends half a request and then nothing is answered and hung up on
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        client.write_all(b"GET /health HTTP/1.1\r\n").unwrap();
+        let mut response = String::new();
+        std::io::Read::read_to_string(&mut client, &mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408"), "{}", response);
+    }
+}
@@ -0,0 +1,197 @@
+//! Search sessions: consecutive searches by one user, where what the last few searches returned
+//! ranks a little higher and what the user turned down ranks lower
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Mutex;
+use crate::time::{Duration, Instant};
+use crate::search::{SearchConfig, SearchResult};
+
+/// Searches whose result documents a session boosts
+pub const SESSION_MEMORY: usize = 3;
+/// A session unused this long is forgotten
+pub const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+/// Sessions a store keeps; starting another forgets the one idle longest
+pub const MAX_SESSIONS: usize = 10_000;
+/// Rejections a session keeps; another forgets the oldest
+pub const MAX_REJECTED: usize = 1_000;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct SearchSession {
+    /// Documents each of the last `SESSION_MEMORY` searches returned, oldest first
+    recent: VecDeque<Vec<String>>,
+    /// Rejected documents, oldest first
+    rejected: VecDeque<String>,
+}
+
+impl SearchSession {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Demote `document_id` for the rest of the session, however often later searches return it,
+    /// or until `MAX_REJECTED` later rejections push it out
+    pub fn reject(&mut self, document_id: &str) {
+        self.rejected.retain(|doc| doc != document_id);
+        self.rejected.push_back(document_id.to_string());
+        while self.rejected.len() > MAX_REJECTED {
+            self.rejected.pop_front();
+        }
+    }
+
+    fn is_rejected(&self, document_id: &str) -> bool {
+        self.rejected.iter().any(|doc| doc == document_id)
+    }
+
+    pub fn rejected(&self) -> impl Iterator<Item = &str> {
+        self.rejected.iter().map(String::as_str)
+    }
+
+    /// Documents the recent searches returned, besides rejected ones
+    pub fn recent_documents(&self) -> HashSet<&str> {
+        let recent = self.recent.iter().flatten().map(String::as_str);
+        recent.filter(|doc| !self.is_rejected(doc)).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recent.is_empty() && self.rejected.is_empty()
+    }
+
+    /// What a document's scores are multiplied by in this session
+    pub fn factor(&self, document_id: &str, config: &SearchConfig) -> f32 {
+        if self.is_rejected(document_id) {
+            1.0 - config.session_demotion
+        } else if self.recent.iter().flatten().any(|doc| doc == document_id) {
+            1.0 + config.session_boost
+        } else {
+            1.0
+        }
+    }
+
+    /// Remember the documents a search returned, forgetting those of searches past `SESSION_MEMORY`
+    pub fn record(&mut self, results: &[SearchResult]) {
+        let mut documents: Vec<String> = Vec::new();
+        for result in results {
+            if !documents.contains(&result.document_id) {
+                documents.push(result.document_id.clone());
+            }
+        }
+        self.recent.push_back(documents);
+        while self.recent.len() > SESSION_MEMORY {
+            self.recent.pop_front();
+        }
+    }
+}
+
+/// Sessions by id, for a server whose clients name the session each search belongs to
+#[derive(Debug, Default)]
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, (SearchSession, Instant)>>,
+}
+
+impl SessionStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run `f` on the session `id`, started afresh if it is new or was idle too long
+    pub fn with<T>(&self, id: &str, f: impl FnOnce(&mut SearchSession) -> T) -> T {
+        let mut sessions = self.sessions.lock().unwrap();
+        let now = Instant::now();
+        sessions.retain(|_, (_, used)| now.duration_since(*used) < SESSION_IDLE_TIMEOUT);
+        if !sessions.contains_key(id) && sessions.len() >= MAX_SESSIONS {
+            let idlest = sessions.iter().min_by_key(|(_, (_, used))| *used).map(|(id, _)| id.clone());
+            if let Some(idlest) = idlest {
+                sessions.remove(&idlest);
+            }
+        }
+        let (session, used) = sessions.entry(id.to_string()).or_insert_with(|| (SearchSession::new(), now));
+        *used = now;
+        f(session)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::Query;
+    use crate::SimpleRagSystem;
+
+    #[test]
+    fn test_sessions_carry_results_over_and_demote_rejections() {
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let policy = rag.process_bytes("policy.md", b"Annual leave policy: staff get twenty days of leave.").unwrap();
+        let form = rag.process_bytes("form.md", b"Leave request form, filed with HR for annual leave.").unwrap();
+        rag.process_bytes("pay.md", b"Payroll runs monthly and holiday pay follows the leave policy.").unwrap();
+
+        let query = Query::parse("leave").unwrap();
+        let plain = rag.search_query(&query, 3).unwrap();
+        let mut session = SearchSession::new();
+        let first = rag.search_in_session(&mut session, &query, 3).unwrap();
+        assert!(first.iter().map(|r| &r.chunk_id).eq(plain.iter().map(|r| &r.chunk_id)));
+
+        // The second search ranks the policy, which the first one returned, above the new documents
+        let mut session = SearchSession::new();
+        rag.search_in_session(&mut session, &Query::parse("twenty days").unwrap(), 1).unwrap();
+        let carried = rag.search_in_session(&mut session, &query, 3).unwrap();
+        assert_eq!(carried[0].document_id, policy);
+        let boosted = plain.iter().find(|r| r.document_id == policy).unwrap().score * 1.1;
+        assert!((carried[0].score - boosted).abs() < 1e-5);
+
+        session.reject(&policy);
+        let demoted = rag.search_in_session(&mut session, &query, 3).unwrap();
+        assert_eq!(demoted.last().unwrap().document_id, policy);
+        assert!(demoted.iter().map(|r| r.rank).eq(1..=3));
+        assert!(session.recent_documents().contains(form.as_str()));
+        assert!(!session.recent_documents().contains(policy.as_str()));
+
+        // A rejection made while a stored session's search runs survives recording its results
+        let store = SessionStore::new();
+        let before = store.with("s1", |session| session.clone());
+        store.with("s1", |session| session.reject(&policy));
+        let results = rag.search_with_session(&before, &query, 3).unwrap();
+        assert!(before.is_empty());
+        store.with("s1", |session| session.record(&results));
+        store.with("s1", |session| {
+            assert!(session.rejected().eq([policy.as_str()]));
+            assert!(session.recent_documents().contains(form.as_str()));
+        });
+    }
+
+    #[test]
+    fn test_sessions_remember_only_recent_searches() {
+        let result = |doc: &str| SearchResult {
+            chunk_id: format!("{}_0", doc),
+            document_id: doc.to_string(),
+            content: String::new(),
+            score: 1.0,
+            rank: 1,
+            pinned: false,
+        };
+        let mut session = SearchSession::new();
+        for doc in ["a", "b", "c", "d"] {
+            session.record(&[result(doc), result(doc)]);
+        }
+        assert_eq!(session.recent_documents(), HashSet::from(["b", "c", "d"]));
+        let config = SearchConfig::default();
+        assert_eq!(session.factor("a", &config), 1.0);
+        session.reject("d");
+        assert_eq!(session.factor("d", &config), 0.5);
+
+        // Rejections are bounded too, forgetting the oldest first
+        for n in 0..MAX_REJECTED {
+            session.reject(&format!("r{}", n));
+        }
+        assert_eq!(session.rejected().count(), MAX_REJECTED);
+        assert_eq!(session.factor("d", &config), 1.0 + config.session_boost);
+        assert_eq!(session.factor("r0", &config), 0.5);
+    }
+}
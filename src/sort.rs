@@ -0,0 +1,171 @@
+//! Orders for search results besides relevance: newest, largest or by path, with the better score
+//! first among equals
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use crate::processor::DocumentMetadata;
+use crate::search::SearchResult;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    /// Relevance, the usual order
+    #[default]
+    Score,
+    /// When the document was ingested
+    Date,
+    /// Size of the document's source in bytes
+    Size,
+    /// The document's file path or upload name
+    Path,
+}
+
+/// A field and direction, written `date`, `size:asc` or `path:desc`. Without a direction dates and
+/// sizes put the newest and largest first, paths sort A to Z and scores best first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResultSort {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+impl ResultSort {
+    pub fn by(field: SortField) -> Self {
+        Self { field, descending: field != SortField::Path }
+    }
+
+    /// Whether this is the usual order, best score first
+    pub fn is_relevance(&self) -> bool {
+        self.field == SortField::Score && self.descending
+    }
+
+    /// Reorder `results` of documents `documents` describes, keeping their relative order among ties
+    /// of both the field and the score. A document field keeps only each document's first result,
+    /// the best one for results in score order, since the others would tie with it. Results without a
+    /// value for the field, such as documents indexed before ingest dates were kept, go last
+    pub fn sort(&self, results: &mut Vec<SearchResult>, documents: &HashMap<String, DocumentMetadata>) {
+        if self.field != SortField::Score {
+            let mut seen = HashSet::new();
+            results.retain(|r| seen.insert(r.document_id.clone()));
+        }
+        let key = |result: &SearchResult| -> Option<SortKey<'_>> {
+            let metadata = documents.get(&result.document_id);
+            match self.field {
+                SortField::Score => Some(SortKey::Number(result.score as f64)),
+                SortField::Date => metadata?.ingested_at.map(|at| SortKey::Number(at as f64)),
+                SortField::Size => metadata.map(|m| SortKey::Number(m.file_size as f64)),
+                SortField::Path => metadata.map(|m| SortKey::Text(m.file_path.as_str())),
+            }
+        };
+        results.sort_by(|a, b| {
+            let by_field = match (key(a), key(b)) {
+                (Some(a), Some(b)) if self.descending => b.cmp(&a),
+                (Some(a), Some(b)) => a.cmp(&b),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            };
+            by_field.then(b.score.total_cmp(&a.score))
+        });
+        for (rank, result) in results.iter_mut().enumerate() {
+            result.rank = rank + 1;
+        }
+    }
+}
+
+#[derive(PartialEq, PartialOrd)]
+enum SortKey<'a> {
+    Number(f64),
+    Text(&'a str),
+}
+
+impl SortKey<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl Default for ResultSort {
+    fn default() -> Self {
+        Self::by(SortField::Score)
+    }
+}
+
+impl std::str::FromStr for ResultSort {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (field, direction) = match s.split_once(':') {
+            Some((field, direction)) => (field, Some(direction)),
+            None => (s, None),
+        };
+        let field = match field.trim().to_lowercase().as_str() {
+            "score" => SortField::Score,
+            "date" => SortField::Date,
+            "size" => SortField::Size,
+            "path" => SortField::Path,
+            other => return Err(anyhow!("Unknown sort field '{}' (expected score, date, size or path)", other)),
+        };
+        let sort = ResultSort::by(field);
+        match direction.map(|d| d.trim().to_lowercase()).as_deref() {
+            None => Ok(sort),
+            Some("asc") => Ok(ResultSort { descending: false, ..sort }),
+            Some("desc") => Ok(ResultSort { descending: true, ..sort }),
+            Some(other) => Err(anyhow!("Unknown sort direction '{}' (expected asc or desc)", other)),
+        }
+    }
+}
+
+impl fmt::Display for ResultSort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let field = match self.field {
+            SortField::Score => "score",
+            SortField::Date => "date",
+            SortField::Size => "size",
+            SortField::Path => "path",
+        };
+        write!(f, "{}:{}", field, if self.descending { "desc" } else { "asc" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunking::ChunkingStrategy;
+    use crate::query::Query;
+    use crate::SimpleRagSystem;
+
+    #[test]
+    fn test_sorts_every_match_by_field_then_score() {
+        let mut rag = SimpleRagSystem::new().unwrap().with_chunking_strategy(ChunkingStrategy::Paragraph);
+        let guide = rag
+            .process_bytes("b/guide.md", b"Leave: a long guide to leave and how leave is requested.\n\nLeave is approved by HR.")
+            .unwrap();
+        let note = rag.process_bytes("a/note.md", b"Leave note.").unwrap();
+        let form = rag.process_bytes("c/form.md", b"The leave form.").unwrap();
+        rag.process_bytes("pay.md", b"Payroll runs monthly.").unwrap();
+        // Stand-ins for documents ingested on different days, and one from before dates were kept
+        for (doc_id, ingested_at) in [(&guide, Some(100)), (&note, Some(300)), (&form, None)] {
+            let mut document = rag.storage.get_document(doc_id).unwrap().unwrap();
+            document.metadata.ingested_at = ingested_at;
+            rag.storage.store_document(document).unwrap();
+        }
+
+        let leave = Query::parse("leave").unwrap();
+        let ids = |sort: &str, limit: usize| -> Vec<String> {
+            let results = rag.search_sorted(&leave, limit, sort.parse().unwrap()).unwrap();
+            results.into_iter().map(|r| r.document_id).collect()
+        };
+        assert_eq!(ids("date", 3), [note.clone(), guide.clone(), form.clone()]);
+        assert_eq!(ids("date:asc", 3), [guide.clone(), note.clone(), form.clone()]);
+        assert_eq!(ids("path", 5), [note.clone(), guide.clone(), form.clone()], "one result per document");
+        assert_eq!(ids("path", 1), [note], "sorted before the top results are picked");
+        assert_eq!(ids("size", 3)[0], guide);
+        assert_eq!(ids("score", 3), rag.search_query(&leave, 3).unwrap().into_iter().map(|r| r.document_id).collect::<Vec<_>>());
+
+        assert_eq!("size:asc".parse::<ResultSort>().unwrap().to_string(), "size:asc");
+        assert!("newest".parse::<ResultSort>().is_err() && "date:up".parse::<ResultSort>().is_err());
+    }
+}
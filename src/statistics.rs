@@ -0,0 +1,224 @@
+//! Paired significance tests for comparing evaluation runs
+
+use serde::{Deserialize, Serialize};
+
+/// Differences smaller than this are treated as ties
+const TIE_EPSILON: f64 = 1e-9;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TestResult {
+    pub statistic: f64,
+    /// Two-sided p-value
+    pub p_value: f64,
+}
+
+/// Paired Student's t-test on `b - a`; None with fewer than two pairs
+pub fn paired_t_test(a: &[f64], b: &[f64]) -> Option<TestResult> {
+    let diffs: Vec<f64> = b.iter().zip(a).map(|(b, a)| b - a).collect();
+    let n = diffs.len();
+    if n < 2 {
+        return None;
+    }
+
+    let mean = diffs.iter().sum::<f64>() / n as f64;
+    let variance = diffs.iter().map(|d| (d - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+    if variance < TIE_EPSILON * TIE_EPSILON {
+        // Identical differences: either no effect at all or a perfectly consistent one
+        let p_value = if mean.abs() < TIE_EPSILON { 1.0 } else { 0.0 };
+        return Some(TestResult { statistic: 0.0, p_value });
+    }
+
+    let t = mean / (variance / n as f64).sqrt();
+    let df = (n - 1) as f64;
+    let p_value = regularized_incomplete_beta(df / (df + t * t), df / 2.0, 0.5);
+    Some(TestResult { statistic: t, p_value })
+}
+
+/// Wilcoxon signed-rank test on `b - a` using the normal approximation with tie and continuity corrections
+pub fn wilcoxon_signed_rank(a: &[f64], b: &[f64]) -> Option<TestResult> {
+    if a.len() < 2 || a.len() != b.len() {
+        return None;
+    }
+
+    let mut diffs: Vec<f64> = b
+        .iter()
+        .zip(a)
+        .map(|(b, a)| b - a)
+        .filter(|d| d.abs() > TIE_EPSILON)
+        .collect();
+    let n = diffs.len();
+    if n == 0 {
+        return Some(TestResult { statistic: 0.0, p_value: 1.0 });
+    }
+
+    diffs.sort_by(|x, y| x.abs().partial_cmp(&y.abs()).unwrap_or(std::cmp::Ordering::Equal));
+
+    let mut w_plus = 0.0;
+    let mut tie_correction = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && (diffs[j + 1].abs() - diffs[i].abs()).abs() < TIE_EPSILON {
+            j += 1;
+        }
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let group = (j - i + 1) as f64;
+        tie_correction += group.powi(3) - group;
+        w_plus += diffs[i..=j].iter().filter(|d| **d > 0.0).count() as f64 * rank;
+        i = j + 1;
+    }
+
+    let n = n as f64;
+    let mean = n * (n + 1.0) / 4.0;
+    let variance = n * (n + 1.0) * (2.0 * n + 1.0) / 24.0 - tie_correction / 48.0;
+    if variance <= 0.0 {
+        return Some(TestResult { statistic: w_plus, p_value: 1.0 });
+    }
+
+    let z = ((w_plus - mean).abs() - 0.5).max(0.0) / variance.sqrt();
+    Some(TestResult {
+        statistic: w_plus,
+        p_value: erfc(z / std::f64::consts::SQRT_2).min(1.0),
+    })
+}
+
+fn ln_gamma(x: f64) -> f64 {
+    // Lanczos approximation (g = 7, n = 9)
+    const COEFFS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let sum = COEFFS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFS[0], |acc, (i, c)| acc + c / (x + i as f64 + 1.0));
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + sum.ln()
+}
+
+/// I_x(a, b) via the continued fraction from Numerical Recipes
+fn regularized_incomplete_beta(x: f64, a: f64, b: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x >= 1.0 {
+        return 1.0;
+    }
+
+    let front = (ln_gamma(a + b) - ln_gamma(a) - ln_gamma(b) + a * x.ln() + b * (1.0 - x).ln()).exp();
+    if x < (a + 1.0) / (a + b + 2.0) {
+        front * beta_continued_fraction(x, a, b) / a
+    } else {
+        1.0 - front * beta_continued_fraction(1.0 - x, b, a) / b
+    }
+}
+
+fn beta_continued_fraction(x: f64, a: f64, b: f64) -> f64 {
+    const MAX_ITERATIONS: usize = 200;
+    const EPS: f64 = 1e-14;
+    const TINY: f64 = 1e-300;
+
+    let mut c = 1.0;
+    let mut d = 1.0 - (a + b) * x / (a + 1.0);
+    if d.abs() < TINY {
+        d = TINY;
+    }
+    d = 1.0 / d;
+    let mut h = d;
+
+    for m in 1..=MAX_ITERATIONS {
+        let m = m as f64;
+        let even = m * (b - m) * x / ((a + 2.0 * m - 1.0) * (a + 2.0 * m));
+        d = 1.0 + even * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + even / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        h *= d * c;
+
+        let odd = -(a + m) * (a + b + m) * x / ((a + 2.0 * m) * (a + 2.0 * m + 1.0));
+        d = 1.0 + odd * d;
+        if d.abs() < TINY {
+            d = TINY;
+        }
+        c = 1.0 + odd / c;
+        if c.abs() < TINY {
+            c = TINY;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    h
+}
+
+/// Complementary error function (Numerical Recipes erfcc, fractional error < 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let poly = -z * z - 1.265_512_23
+        + t * (1.000_023_68
+            + t * (0.374_091_96
+                + t * (0.096_784_18
+                    + t * (-0.186_288_06
+                        + t * (0.278_868_07
+                            + t * (-1.135_203_98 + t * (1.488_515_87 + t * (-0.822_152_23 + t * 0.170_872_77))))))));
+    let result = t * poly.exp();
+    if x >= 0.0 {
+        result
+    } else {
+        2.0 - result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [f64; 10] = [0.2, 0.5, 0.1, 0.8, 0.4, 0.9, 0.3, 0.6, 0.7, 0.35];
+    const B: [f64; 10] = [0.3, 0.6, 0.15, 0.7, 0.6, 0.95, 0.5, 0.65, 0.9, 0.5];
+
+    #[test]
+    fn test_paired_t_test() {
+        let result = paired_t_test(&[1.0, 2.0, 3.0, 4.0, 5.0], &[2.0, 3.0, 4.0, 5.0, 7.0]).unwrap();
+        assert!((result.statistic - 6.0).abs() < 1e-9);
+        assert!((result.p_value - 0.003_882_5).abs() < 1e-6);
+
+        let result = paired_t_test(&A, &B).unwrap();
+        assert!((result.statistic - 3.354_102).abs() < 1e-5);
+        assert!((result.p_value - 0.008_468_2).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_wilcoxon_signed_rank() {
+        let result = wilcoxon_signed_rank(&A, &B).unwrap();
+        assert_eq!(result.statistic, 50.0);
+        assert!((result.p_value - 0.023_813).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_identical_runs_are_not_significant() {
+        assert_eq!(paired_t_test(&A, &A).unwrap().p_value, 1.0);
+        assert_eq!(wilcoxon_signed_rank(&A, &A).unwrap().p_value, 1.0);
+        assert!(paired_t_test(&[1.0], &[2.0]).is_none());
+    }
+}
@@ -1,22 +1,499 @@
 //! Simple in-memory storage for MVP
 
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
+use crate::time::Instant;
+use tracing::field::Empty;
+use crate::audit::{AuditEntry, AuditFilter};
+use crate::answer_cache::{find_cached_answer, unix_now, AnswerCacheConfig, CachedAnswer};
+use crate::bench::elapsed_ms;
 use crate::chunking::DocumentChunk;
+use crate::evaluation::EvaluationMetrics;
+use crate::generation::Answer;
+use crate::graph::{EntityLinks, KnowledgeGraph};
+use crate::info::modified_secs;
+use crate::ivf::IvfIndex;
+use crate::pq::{self, PqCodebook};
 use crate::processor::ProcessedDocument;
+use crate::feedback::{FeedbackEntry, MAX_FEEDBACK_ENTRIES};
+use crate::ltr::RankingModel;
+use crate::quantization::{EmbeddingQuantization, EmbeddingVector, StoredEmbedding};
+use crate::query_log::QueryLogEntry;
+use crate::saved::SavedSearch;
+use crate::search::{bm25_idf, tokenize};
+use crate::tags::{DocumentBoost, COLLECTION_TAG};
+use crate::usage::Usage;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StorageStats {
     pub total_documents: usize,
     pub total_chunks: usize,
     pub total_size_bytes: usize,
+    /// Documents whose content was dropped from memory to stay under the budget
+    #[serde(default)]
+    pub evicted_documents: usize,
+    pub embedding_cache_entries: usize,
+    pub embedding_cache_hits: u64,
+    pub embedding_cache_misses: u64,
+    /// Memory taken by the chunk embeddings' components
+    #[serde(default)]
+    pub embedding_bytes: usize,
+    pub answer_cache_entries: usize,
+    pub answer_cache_hits: u64,
+    pub answer_cache_misses: u64,
+    /// Tokens and estimated cost by operation: ingest, search, ask and chat
+    pub usage: BTreeMap<String, Usage>,
+}
+
+/// Term and length statistics of the chunks, as the lexical search modes tokenize them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CorpusStats {
+    pub chunks: usize,
+    /// Distinct terms
+    pub vocabulary_size: usize,
+    /// Terms across all chunks, counting repeats
+    pub total_terms: usize,
+    /// Chunks each term appears in
+    pub document_frequencies: BTreeMap<String, usize>,
+    /// Chunk lengths in terms
+    pub chunk_lengths: LengthDistribution,
+}
+
+impl CorpusStats {
+    /// BM25 inverse document frequency of `term`
+    pub fn idf(&self, term: &str) -> f32 {
+        let df = self.document_frequencies.get(term).copied().unwrap_or(0);
+        bm25_idf(self.chunks as f32, df as f32)
+    }
+
+    /// The `n` terms found in the most chunks, ties alphabetical
+    pub fn most_common(&self, n: usize) -> Vec<(&str, usize)> {
+        let mut terms: Vec<(&str, usize)> = self.document_frequencies.iter().map(|(t, df)| (t.as_str(), *df)).collect();
+        terms.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        terms.truncate(n);
+        terms
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LengthDistribution {
+    pub min: usize,
+    pub mean: f64,
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize,
+}
+
+impl LengthDistribution {
+    pub fn from_lengths(mut lengths: Vec<usize>) -> Self {
+        if lengths.is_empty() {
+            return Self::default();
+        }
+        lengths.sort_unstable();
+        // Nearest rank, as the latency percentiles are taken
+        let percentile = |p: f64| lengths[((p / 100.0 * lengths.len() as f64).ceil() as usize).clamp(1, lengths.len()) - 1];
+        Self {
+            min: lengths[0],
+            mean: lengths.iter().sum::<usize>() as f64 / lengths.len() as f64,
+            p50: percentile(50.0),
+            p90: percentile(90.0),
+            p99: percentile(99.0),
+            max: lengths[lengths.len() - 1],
+        }
+    }
+}
+
+/// Lookups against a cache since the index was created
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// Stable short hash of `text`, used for cache keys and config fingerprints
+pub fn content_hash(text: &str) -> String {
+    format!("{:016x}", fnv1a(text))
+}
+
+/// Shard, out of `shards`, that holds the document `doc_id` with its chunks and their embeddings
+pub fn shard_of(doc_id: &str, shards: usize) -> usize {
+    (fnv1a(doc_id) % shards.max(1) as u64) as usize
+}
+
+/// FNV-1a, since std's hasher is not guaranteed stable between releases
+pub(crate) fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+/// File of shard `shard` next to the snapshot file at `path`: `index.shard0.json` for `index.json`
+pub fn shard_path(path: &Path, shard: usize) -> PathBuf {
+    path.with_extension(format!("shard{}.json", shard))
+}
+
+/// File beside the index at `path` that searches are logged to, one JSON entry per line, so logging a
+/// search never rewrites the index
+pub fn query_log_path(path: &Path) -> PathBuf {
+    path.with_extension("queries.jsonl")
+}
+
+/// Exchange the values behind two locks, such as a live map and a freshly loaded one
+fn swap<T>(a: &Mutex<T>, b: &Mutex<T>) {
+    std::mem::swap(&mut *a.lock().unwrap(), &mut *b.lock().unwrap());
+}
+
+/// Write `contents` aside and rename it over `path`, so a crash mid-write leaves the old file whole
+fn write_atomic(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+    let mut file = std::fs::File::create(&temporary)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Entries of a query log file; a line torn by a crash mid-append is skipped
+fn read_query_log(path: &Path) -> Result<Vec<QueryLogEntry>> {
+    let content = std::fs::read_to_string(path)?;
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(entry) => Some(entry),
+            Err(e) => {
+                tracing::warn!("Skipping unreadable query log line in {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+/// Move `snapshot`'s documents, chunks and embeddings into one snapshot per shard
+fn split_shards(snapshot: &mut StorageSnapshot, shards: usize) -> Vec<StorageSnapshot> {
+    let mut parts = vec![StorageSnapshot::default(); shards];
+    let mut chunk_shards = HashMap::new();
+    for chunk in std::mem::take(&mut snapshot.chunks) {
+        let shard = shard_of(&chunk.document_id, shards);
+        chunk_shards.insert(chunk.id.clone(), shard);
+        parts[shard].chunks.push(chunk);
+    }
+    for document in std::mem::take(&mut snapshot.documents) {
+        parts[shard_of(&document.id, shards)].documents.push(document);
+    }
+    // Embeddings of chunks that are gone still go somewhere, so integrity checks can find them
+    for (id, embedding) in std::mem::take(&mut snapshot.embeddings) {
+        let shard = chunk_shards.get(&id).copied().unwrap_or(0);
+        parts[shard].embeddings.insert(id, embedding);
+    }
+    parts
+}
+
+/// Shard files of the snapshot at `path`, read in parallel
+fn read_shards(path: &Path, shards: usize) -> Result<Vec<StorageSnapshot>> {
+    std::thread::scope(|scope| {
+        let readers: Vec<_> = (0..shards)
+            .map(|i| {
+                scope.spawn(move || -> Result<StorageSnapshot> {
+                    let shard = shard_path(path, i);
+                    let content = std::fs::read_to_string(&shard)
+                        .map_err(|e| anyhow!("Cannot read shard {}: {}", shard.display(), e))?;
+                    Ok(serde_json::from_str(&content)?)
+                })
+            })
+            .collect();
+        readers.into_iter().map(|reader| reader.join().map_err(|_| anyhow!("Shard reader panicked"))?).collect()
+    })
+}
+
+fn embedding_cache_key(model: &str, text: &str) -> String {
+    format!("{}:{}", model, content_hash(text))
+}
+
+/// One recorded evaluation, kept so retrieval quality can be tracked over time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluationRun {
+    pub id: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub config_fingerprint: String,
+    pub query_count: usize,
+    pub k: usize,
+    pub metrics: EvaluationMetrics,
+}
+
+/// Everything the storage holds, in a serializable form
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StorageSnapshot {
+    /// Counts the persists of the index, so a reader can tell a rewrite from the file it loaded
+    #[serde(default)]
+    pub generation: u64,
+    pub documents: Vec<ProcessedDocument>,
+    pub chunks: Vec<DocumentChunk>,
+    #[serde(default)]
+    pub evaluation_runs: Vec<EvaluationRun>,
+    /// Chunk embeddings keyed by chunk id, full or quantized
+    #[serde(default)]
+    pub embeddings: HashMap<String, StoredEmbedding>,
+    /// Model that produced `embeddings`; queries must be embedded with the same one
+    #[serde(default)]
+    pub embedding_model: Option<String>,
+    /// Embeddings keyed by model and content hash, shared by chunks and queries
+    #[serde(default)]
+    pub embedding_cache: HashMap<String, Vec<f32>>,
+    #[serde(default)]
+    pub embedding_cache_stats: CacheStats,
+    /// Answers reused for semantically similar questions
+    #[serde(default)]
+    pub answer_cache: Vec<CachedAnswer>,
+    #[serde(default)]
+    pub answer_cache_stats: CacheStats,
+    #[serde(default)]
+    pub usage: BTreeMap<String, Usage>,
+    /// Searches recorded while the query log was enabled, oldest first
+    #[serde(default)]
+    pub query_log: Vec<QueryLogEntry>,
+    /// Relevance judgements on search results, oldest first
+    #[serde(default)]
+    pub feedback: Vec<FeedbackEntry>,
+    /// Learned ranking models, at most one per tenant
+    #[serde(default)]
+    pub ranking_models: Vec<RankingModel>,
+    /// Trained IVF indexes, at most one per tenant
+    #[serde(default)]
+    pub ivf_indexes: Vec<Arc<IvfIndex>>,
+    /// Codebook of the product-quantized embeddings
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pq_codebook: Option<Arc<PqCodebook>>,
+    /// Shard files holding the documents, chunks and embeddings, which this file then leaves out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shards: Option<usize>,
+    /// Unix seconds each document in the trash was deleted at, by id
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub tombstones: HashMap<String, u64>,
+    /// Every ingest, update and delete, oldest first
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub audit_log: Vec<AuditEntry>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub saved_searches: Vec<SavedSearch>,
+    /// Relations of each document and the graph entities each chunk names; rebuilt from the
+    /// documents' relations when missing
+    #[serde(default, skip_serializing_if = "EntityLinks::is_empty")]
+    pub entity_links: EntityLinks,
+    /// Chunks embedded or removed since the disk index was built; `None` until there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stale_vectors: Option<BTreeSet<String>>,
+}
+
+/// Inconsistencies between stored documents, chunks and embeddings
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntegrityReport {
+    /// Chunks whose document is not stored
+    pub orphan_chunks: Vec<String>,
+    /// Documents with no chunks, which no search can return
+    pub documents_without_chunks: Vec<String>,
+    /// Embeddings of chunks that no longer exist
+    pub orphan_embeddings: Vec<String>,
+    /// Chunks without an embedding in an embedded index, invisible to vector search
+    pub unembedded_chunks: Vec<String>,
+}
+
+impl IntegrityReport {
+    pub fn is_clean(&self) -> bool {
+        self.orphan_chunks.is_empty()
+            && self.documents_without_chunks.is_empty()
+            && self.orphan_embeddings.is_empty()
+            && self.unembedded_chunks.is_empty()
+    }
+}
+
+/// What `gc` removed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GcReport {
+    pub chunks: usize,
+    pub embeddings: usize,
+    /// Chunk text and embedding memory freed
+    pub bytes: usize,
+}
+
+impl GcReport {
+    pub fn is_empty(&self) -> bool {
+        self.chunks == 0 && self.embeddings == 0
+    }
+}
+
+/// A deleted document kept, unsearchable, until it is purged
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrashedDocument {
+    pub id: String,
+    pub file_path: String,
+    /// Unix seconds
+    pub deleted_at: u64,
+    pub chunks: usize,
+}
+
+/// Least-recently-used bookkeeping for document content kept in memory under a byte budget
+#[derive(Debug, Default)]
+struct ContentBudget {
+    max_bytes: Option<usize>,
+    resident_bytes: usize,
+    clock: u64,
+    /// Last use and content size of each document whose content is in memory
+    resident: HashMap<String, (u64, usize)>,
+    /// Where each document whose content was dropped had it written
+    evicted: HashMap<String, SpilledContent>,
+    /// Directory dropped content is written to, made on first use and removed with the budget
+    spill_dir: Option<PathBuf>,
+}
+
+/// Document content written verbatim to a file when dropped from memory, with what it must read back as
+#[derive(Debug, Clone)]
+struct SpilledContent {
+    path: PathBuf,
+    size: usize,
+    hash: String,
+}
+
+impl ContentBudget {
+    fn touch(&mut self, doc_id: &str, size: usize) {
+        self.clock += 1;
+        self.unspill(doc_id);
+        if let Some((_, old)) = self.resident.insert(doc_id.to_string(), (self.clock, size)) {
+            self.resident_bytes -= old;
+        }
+        self.resident_bytes += size;
+    }
+
+    fn forget(&mut self, doc_id: &str) {
+        self.unspill(doc_id);
+        if let Some((_, size)) = self.resident.remove(doc_id) {
+            self.resident_bytes -= size;
+        }
+    }
+
+    /// Documents to drop content from, least recently used first, until the rest fit; `keep` is never chosen
+    fn over_budget(&mut self, keep: Option<&str>) -> Vec<String> {
+        let Some(max_bytes) = self.max_bytes else {
+            return Vec::new();
+        };
+        let mut by_age: Vec<(u64, String)> = self
+            .resident
+            .iter()
+            .filter(|(id, _)| Some(id.as_str()) != keep)
+            .map(|(id, (used, _))| (*used, id.clone()))
+            .collect();
+        by_age.sort();
+        let mut victims = Vec::new();
+        for (_, doc_id) in by_age {
+            if self.resident_bytes <= max_bytes {
+                break;
+            }
+            let (_, size) = self.resident.remove(&doc_id).unwrap();
+            self.resident_bytes -= size;
+            victims.push(doc_id);
+        }
+        victims
+    }
+
+    /// Write each victim's content to a spill file and drop it from memory. Content that can't be
+    /// written stays in memory, over budget, rather than being lost
+    fn spill(&mut self, docs: &mut HashMap<String, ProcessedDocument>, victims: Vec<String>) {
+        for doc_id in victims {
+            let Some(doc) = docs.get_mut(&doc_id) else {
+                continue;
+            };
+            match self.write_spill(&doc.content) {
+                Ok(spilled) => {
+                    tracing::debug!("Dropping content of {} from memory to {}", doc.metadata.file_path, spilled.path.display());
+                    doc.content = String::new();
+                    self.evicted.insert(doc_id, spilled);
+                }
+                Err(e) => {
+                    tracing::warn!("Keeping content of {} in memory, as spilling it failed: {}", doc.metadata.file_path, e);
+                    self.touch(&doc_id, doc.content.len());
+                }
+            }
+        }
+    }
+
+    fn write_spill(&mut self, content: &str) -> Result<SpilledContent> {
+        if cfg!(all(target_arch = "wasm32", target_os = "unknown")) {
+            return Err(anyhow!("No filesystem to spill content to"));
+        }
+        let dir = self
+            .spill_dir
+            .get_or_insert_with(|| std::env::temp_dir().join(format!("rag-spill-{}", uuid::Uuid::new_v4())));
+        std::fs::create_dir_all(&*dir)?;
+        let path = dir.join(uuid::Uuid::new_v4().to_string());
+        std::fs::write(&path, content)?;
+        Ok(SpilledContent { path, size: content.len(), hash: content_hash(content) })
+    }
+
+    /// Forget where the document's content was spilled, deleting the file
+    fn unspill(&mut self, doc_id: &str) {
+        if let Some(spilled) = self.evicted.remove(doc_id) {
+            let _ = std::fs::remove_file(spilled.path);
+        }
+    }
+}
+
+impl Drop for ContentBudget {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.spill_dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
 }
 
 pub struct StorageManager {
     documents: Arc<Mutex<HashMap<String, ProcessedDocument>>>,
     chunks: Arc<Mutex<HashMap<String, DocumentChunk>>>,
+    evaluation_runs: Arc<Mutex<Vec<EvaluationRun>>>,
+    embeddings: Arc<Mutex<HashMap<String, StoredEmbedding>>>,
+    /// Form new embeddings are stored in, shared by every view
+    quantization: Arc<Mutex<EmbeddingQuantization>>,
+    pq_codebook: Arc<Mutex<Option<Arc<PqCodebook>>>>,
+    /// Leading dimensions of each embedding kept, when truncating
+    embedding_dimension_limit: Arc<Mutex<Option<usize>>>,
+    /// Shared by every tenant view, since vectors from different models can't be compared
+    embedding_model: Arc<Mutex<Option<String>>>,
+    embedding_cache: Arc<Mutex<HashMap<String, Vec<f32>>>>,
+    embedding_cache_stats: Arc<Mutex<CacheStats>>,
+    answer_cache: Arc<Mutex<Vec<CachedAnswer>>>,
+    answer_cache_stats: Arc<Mutex<CacheStats>>,
+    usage: Arc<Mutex<BTreeMap<String, Usage>>>,
+    query_log: Arc<Mutex<Vec<QueryLogEntry>>>,
+    /// Lines in the query log file, which is rewritten with just the kept entries once it has twice as many
+    query_log_lines: Arc<Mutex<usize>>,
+    /// Held while persisting, so concurrent writers never interleave their files
+    persist_lock: Arc<Mutex<()>>,
+    /// Generation of the snapshot last persisted or loaded
+    generation: Arc<Mutex<u64>>,
+    /// Only ever appended to
+    audit_log: Arc<Mutex<Vec<AuditEntry>>>,
+    saved_searches: Arc<Mutex<Vec<SavedSearch>>>,
+    feedback: Arc<Mutex<Vec<FeedbackEntry>>>,
+    ranking_models: Arc<Mutex<Vec<RankingModel>>>,
+    ivf_indexes: Arc<Mutex<Vec<Arc<IvfIndex>>>>,
+    entity_links: Arc<Mutex<EntityLinks>>,
+    stale_vectors: Arc<Mutex<Option<BTreeSet<String>>>>,
+    /// Source file modification times of documents indexed before they were kept, checked once
+    checked_modified_times: Arc<Mutex<HashMap<String, Option<u64>>>>,
+    /// Shared by every view, since they share the memory
+    content_budget: Arc<Mutex<ContentBudget>>,
+    /// Documents are partitioned across this many shards, by hash of their id
+    shards: Arc<Mutex<usize>>,
+    /// Deleted documents, hidden from every view until purged or undeleted, with when they were deleted
+    tombstones: Arc<Mutex<HashMap<String, u64>>>,
+    path: Option<PathBuf>,
+    /// Documents, chunks, embeddings, cached answers and usage this view reads and writes;
+    /// `None` is the default tenant
+    tenant: Option<String>,
 }
 
 impl StorageManager {
@@ -24,59 +501,1234 @@ impl StorageManager {
         Ok(Self {
             documents: Arc::new(Mutex::new(HashMap::new())),
             chunks: Arc::new(Mutex::new(HashMap::new())),
+            evaluation_runs: Arc::new(Mutex::new(Vec::new())),
+            embeddings: Arc::new(Mutex::new(HashMap::new())),
+            quantization: Arc::new(Mutex::new(EmbeddingQuantization::None)),
+            pq_codebook: Arc::new(Mutex::new(None)),
+            embedding_dimension_limit: Arc::new(Mutex::new(None)),
+            embedding_model: Arc::new(Mutex::new(None)),
+            embedding_cache: Arc::new(Mutex::new(HashMap::new())),
+            embedding_cache_stats: Arc::new(Mutex::new(CacheStats::default())),
+            answer_cache: Arc::new(Mutex::new(Vec::new())),
+            answer_cache_stats: Arc::new(Mutex::new(CacheStats::default())),
+            usage: Arc::new(Mutex::new(BTreeMap::new())),
+            query_log: Arc::new(Mutex::new(Vec::new())),
+            query_log_lines: Arc::new(Mutex::new(0)),
+            persist_lock: Arc::new(Mutex::new(())),
+            generation: Arc::new(Mutex::new(0)),
+            audit_log: Arc::new(Mutex::new(Vec::new())),
+            saved_searches: Arc::new(Mutex::new(Vec::new())),
+            feedback: Arc::new(Mutex::new(Vec::new())),
+            ranking_models: Arc::new(Mutex::new(Vec::new())),
+            ivf_indexes: Arc::new(Mutex::new(Vec::new())),
+            entity_links: Arc::new(Mutex::new(EntityLinks::default())),
+            stale_vectors: Arc::new(Mutex::new(None)),
+            checked_modified_times: Arc::new(Mutex::new(HashMap::new())),
+            content_budget: Arc::new(Mutex::new(ContentBudget::default())),
+            shards: Arc::new(Mutex::new(1)),
+            tombstones: Arc::new(Mutex::new(HashMap::new())),
+            path: None,
+            tenant: None,
+        })
+    }
+
+    /// View of the same index that only sees `tenant`'s data and stamps what it stores with it.
+    /// Views share everything else, so one process can serve isolated tenants from one index file
+    pub fn tenant(&self, tenant: &str) -> Self {
+        self.view(Some(tenant.to_string()))
+    }
+
+    /// Another handle on this view, for writing from a stage that only borrows the system
+    pub(crate) fn handle(&self) -> Self {
+        self.view(self.tenant.clone())
+    }
+
+    fn view(&self, tenant: Option<String>) -> Self {
+        Self {
+            documents: self.documents.clone(),
+            chunks: self.chunks.clone(),
+            evaluation_runs: self.evaluation_runs.clone(),
+            embeddings: self.embeddings.clone(),
+            quantization: self.quantization.clone(),
+            pq_codebook: self.pq_codebook.clone(),
+            embedding_dimension_limit: self.embedding_dimension_limit.clone(),
+            embedding_model: self.embedding_model.clone(),
+            embedding_cache: self.embedding_cache.clone(),
+            embedding_cache_stats: self.embedding_cache_stats.clone(),
+            answer_cache: self.answer_cache.clone(),
+            answer_cache_stats: self.answer_cache_stats.clone(),
+            usage: self.usage.clone(),
+            query_log: self.query_log.clone(),
+            query_log_lines: self.query_log_lines.clone(),
+            persist_lock: self.persist_lock.clone(),
+            generation: self.generation.clone(),
+            audit_log: self.audit_log.clone(),
+            saved_searches: self.saved_searches.clone(),
+            feedback: self.feedback.clone(),
+            ranking_models: self.ranking_models.clone(),
+            ivf_indexes: self.ivf_indexes.clone(),
+            entity_links: self.entity_links.clone(),
+            stale_vectors: self.stale_vectors.clone(),
+            checked_modified_times: self.checked_modified_times.clone(),
+            content_budget: self.content_budget.clone(),
+            shards: self.shards.clone(),
+            tombstones: self.tombstones.clone(),
+            path: self.path.clone(),
+            tenant,
+        }
+    }
+
+    /// Tenant this view is scoped to, `None` for the default tenant
+    pub fn tenant_id(&self) -> Option<&str> {
+        self.tenant.as_deref()
+    }
+
+    /// Every tenant with at least one document
+    pub fn tenants(&self) -> Vec<String> {
+        let docs = self.documents.lock().unwrap();
+        let tenants: std::collections::BTreeSet<String> =
+            docs.values().filter_map(|doc| doc.metadata.tenant.clone()).collect();
+        tenants.into_iter().collect()
+    }
+
+    /// Whether the document is this view's and not in the trash
+    fn owns(&self, document: &ProcessedDocument) -> bool {
+        self.in_tenant(document) && !self.tombstones.lock().unwrap().contains_key(&document.id)
+    }
+
+    /// Whether the document is this view's, trashed or not
+    fn in_tenant(&self, document: &ProcessedDocument) -> bool {
+        document.metadata.tenant == self.tenant
+    }
+
+    /// Chunks whose document is missing belong to the default tenant, so integrity checks still see them
+    fn owns_chunk(&self, docs: &HashMap<String, ProcessedDocument>, chunk: &DocumentChunk) -> bool {
+        docs.get(&chunk.document_id).map_or(self.tenant.is_none(), |doc| self.owns(doc))
+    }
+
+    /// Ids of the chunks this view owns
+    fn owned_chunk_ids(&self) -> HashSet<String> {
+        let docs = self.documents.lock().unwrap();
+        let chunks = self.chunks.lock().unwrap();
+        chunks.values().filter(|c| self.owns_chunk(&docs, c)).map(|c| c.id.clone()).collect()
+    }
+
+    /// Usage totals are kept per tenant under `tenant/operation`
+    fn usage_key(&self, operation: &str) -> String {
+        match &self.tenant {
+            Some(tenant) => format!("{}/{}", tenant, operation),
+            None => operation.to_string(),
+        }
+    }
+
+    /// Storage backed by a snapshot file, loaded now if it exists and written by `persist`
+    pub fn open(path: &Path) -> Result<Self> {
+        let mut storage = if path.exists() {
+            let mut snapshot = Self::read_snapshot(path)?;
+            let shards = snapshot.shards.take().unwrap_or(1);
+            Self::from_snapshot(snapshot)?.with_shards(shards)
+        } else {
+            Self::new()?
+        };
+        // Once the log file exists it holds the whole log; before that, any log is the snapshot's own
+        let log_path = query_log_path(path);
+        if log_path.exists() {
+            let log = read_query_log(&log_path)?;
+            *storage.query_log_lines.lock().unwrap() = log.len();
+            *storage.query_log.lock().unwrap() = log;
+        }
+        storage.path = Some(path.to_path_buf());
+        tracing::debug!("Opened index {} with {} documents", path.display(), storage.documents.lock().unwrap().len());
+        Ok(storage)
+    }
+
+    /// The snapshot persisted at `path`, with the contents of any shard files merged in
+    pub fn read_snapshot(path: &Path) -> Result<StorageSnapshot> {
+        let content = std::fs::read_to_string(path)?;
+        let mut snapshot: StorageSnapshot = serde_json::from_str(&content)?;
+        if let Some(shards) = snapshot.shards.filter(|shards| *shards > 1) {
+            for shard in read_shards(path, shards)? {
+                snapshot.documents.extend(shard.documents);
+                snapshot.chunks.extend(shard.chunks);
+                snapshot.embeddings.extend(shard.embeddings);
+            }
+        }
+        Ok(snapshot)
+    }
+
+    /// Generation of the snapshot persisted at `path`, without loading its shard files
+    pub fn snapshot_generation(path: &Path) -> Result<u64> {
+        #[derive(Deserialize)]
+        struct Generation {
+            #[serde(default)]
+            generation: u64,
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str::<Generation>(&content)?.generation)
+    }
+
+    pub fn from_snapshot(snapshot: StorageSnapshot) -> Result<Self> {
+        let storage = Self::new()?;
+        *storage.generation.lock().unwrap() = snapshot.generation;
+        *storage.embedding_model.lock().unwrap() = snapshot.embedding_model;
+        {
+            let mut docs = storage.documents.lock().unwrap();
+            let mut budget = storage.content_budget.lock().unwrap();
+            for document in snapshot.documents {
+                budget.touch(&document.id, document.content.len());
+                docs.insert(document.id.clone(), document);
+            }
+            drop(budget);
+            let mut chunks = storage.chunks.lock().unwrap();
+            for chunk in snapshot.chunks {
+                chunks.insert(chunk.id.clone(), chunk);
+            }
+            *storage.evaluation_runs.lock().unwrap() = snapshot.evaluation_runs;
+            let mut embeddings = snapshot.embeddings;
+            if let Some(codebook) = &snapshot.pq_codebook {
+                for embedding in embeddings.values_mut() {
+                    if let StoredEmbedding::Pq(pq) = embedding {
+                        pq.codebook = codebook.clone();
+                    }
+                }
+            }
+            *storage.embeddings.lock().unwrap() = embeddings;
+            *storage.pq_codebook.lock().unwrap() = snapshot.pq_codebook;
+            *storage.embedding_cache.lock().unwrap() = snapshot.embedding_cache;
+            *storage.embedding_cache_stats.lock().unwrap() = snapshot.embedding_cache_stats;
+            *storage.answer_cache.lock().unwrap() = snapshot.answer_cache;
+            *storage.answer_cache_stats.lock().unwrap() = snapshot.answer_cache_stats;
+            *storage.usage.lock().unwrap() = snapshot.usage;
+            *storage.query_log.lock().unwrap() = snapshot.query_log;
+            *storage.audit_log.lock().unwrap() = snapshot.audit_log;
+            *storage.saved_searches.lock().unwrap() = snapshot.saved_searches;
+            *storage.feedback.lock().unwrap() = snapshot.feedback;
+            *storage.ranking_models.lock().unwrap() = snapshot.ranking_models;
+            *storage.ivf_indexes.lock().unwrap() = snapshot.ivf_indexes;
+            *storage.tombstones.lock().unwrap() = snapshot.tombstones;
+            let mut links = snapshot.entity_links;
+            // Indexes written before links were kept, and snapshots of one tenant, are linked from scratch
+            if links.is_empty() {
+                let relations = docs.values().map(|doc| (doc.id.as_str(), doc.metadata.relations.as_slice()));
+                links = EntityLinks::build(relations, &chunks);
+            }
+            *storage.entity_links.lock().unwrap() = links;
+            *storage.stale_vectors.lock().unwrap() = snapshot.stale_vectors;
+        }
+        Ok(storage)
+    }
+
+    /// Replace the contents with `snapshot`, keeping the snapshot file path, shard count, memory budget
+    /// and embedding form. Contents are swapped into the maps every view shares, so other views see them
+    pub fn restore(&mut self, snapshot: StorageSnapshot) -> Result<()> {
+        if let Some(tenant) = &self.tenant {
+            return Err(anyhow!("Restoring replaces the whole index, which tenant {} cannot do", tenant));
+        }
+        // An older snapshot mustn't take the generation back to one readers have already loaded
+        let generation = snapshot.generation.max(*self.generation.lock().unwrap());
+        let restored = Self::from_snapshot(snapshot)?;
+        swap(&self.documents, &restored.documents);
+        swap(&self.chunks, &restored.chunks);
+        swap(&self.evaluation_runs, &restored.evaluation_runs);
+        swap(&self.embeddings, &restored.embeddings);
+        swap(&self.pq_codebook, &restored.pq_codebook);
+        swap(&self.embedding_model, &restored.embedding_model);
+        swap(&self.embedding_cache, &restored.embedding_cache);
+        swap(&self.embedding_cache_stats, &restored.embedding_cache_stats);
+        swap(&self.answer_cache, &restored.answer_cache);
+        swap(&self.answer_cache_stats, &restored.answer_cache_stats);
+        swap(&self.usage, &restored.usage);
+        swap(&self.query_log, &restored.query_log);
+        swap(&self.query_log_lines, &restored.query_log_lines);
+        swap(&self.saved_searches, &restored.saved_searches);
+        swap(&self.feedback, &restored.feedback);
+        swap(&self.ranking_models, &restored.ranking_models);
+        swap(&self.ivf_indexes, &restored.ivf_indexes);
+        swap(&self.entity_links, &restored.entity_links);
+        swap(&self.stale_vectors, &restored.stale_vectors);
+        swap(&self.checked_modified_times, &restored.checked_modified_times);
+        swap(&self.tombstones, &restored.tombstones);
+        // The restored contents are all in memory; the old spill files go with `restored`
+        swap(&self.content_budget, &restored.content_budget);
+        *self.generation.lock().unwrap() = generation;
+
+        let (budget, dimensions) = (restored.memory_budget(), self.embedding_dimension_limit());
+        if let Some(max_bytes) = budget {
+            self.handle().with_memory_budget(max_bytes);
+        }
+        if let Some(dimensions) = dimensions {
+            self.handle().with_embedding_dimensions(dimensions);
+        }
+        let quantization = self.embedding_quantization();
+        self.handle().with_embedding_quantization(quantization);
+
+        // The log outlives the index it describes: the snapshot's entries join the ones already kept
+        let mut audit_log = self.audit_log.lock().unwrap();
+        let kept: HashSet<&AuditEntry> = audit_log.iter().collect();
+        let snapshot_log = std::mem::take(&mut *restored.audit_log.lock().unwrap());
+        let joined: Vec<AuditEntry> = snapshot_log.into_iter().filter(|entry| !kept.contains(entry)).collect();
+        audit_log.extend(joined);
+        audit_log.sort_by_key(|entry| entry.timestamp);
+        Ok(())
+    }
+
+    /// This view's data; the default tenant's snapshot leaves out other tenants' documents
+    pub fn snapshot(&self) -> Result<StorageSnapshot> {
+        let chunks = self.get_all_chunks()?;
+        let embeddings = self.get_stored_embeddings()?;
+        let docs = self.documents.lock().unwrap();
+        let runs = self.evaluation_runs.lock().unwrap();
+        Ok(StorageSnapshot {
+            generation: *self.generation.lock().unwrap(),
+            documents: docs.values().filter(|doc| self.owns(doc)).map(|doc| self.with_content(doc)).collect::<Result<_>>()?,
+            chunks,
+            evaluation_runs: runs.clone(),
+            embeddings,
+            embedding_model: self.embedding_model(),
+            embedding_cache: self.embedding_cache.lock().unwrap().clone(),
+            embedding_cache_stats: *self.embedding_cache_stats.lock().unwrap(),
+            answer_cache: self.cached_answers(),
+            answer_cache_stats: *self.answer_cache_stats.lock().unwrap(),
+            usage: self.usage.lock().unwrap().clone(),
+            query_log: self.query_log()?,
+            feedback: self.feedback(),
+            ranking_models: self.ranking_model().into_iter().collect(),
+            ivf_indexes: self.ivf_index().into_iter().collect(),
+            pq_codebook: self.pq_codebook(),
+            shards: None,
+            // Trashed documents are left out above, so their tombstones are too
+            tombstones: HashMap::new(),
+            audit_log: self.audit_log(&AuditFilter::default()),
+            saved_searches: self.saved_searches(),
+            // Rebuilt on load from the documents' relations, as other tenants' links are mixed in
+            entity_links: EntityLinks::default(),
+            stale_vectors: None,
         })
     }
 
-    pub fn store_document(&mut self, document: ProcessedDocument) -> Result<String> {
+    /// Every tenant's data, as written to the snapshot file
+    pub fn full_snapshot(&self) -> Result<StorageSnapshot> {
+        let mut snapshot = self.view(None).snapshot()?;
+        let docs = self.documents.lock().unwrap();
+        snapshot.documents = docs.values().map(|doc| self.with_content(doc)).collect::<Result<_>>()?;
+        snapshot.chunks = self.chunks.lock().unwrap().values().cloned().collect();
+        snapshot.embeddings = self.embeddings.lock().unwrap().clone();
+        snapshot.answer_cache = self.answer_cache.lock().unwrap().clone();
+        snapshot.query_log = self.query_log.lock().unwrap().clone();
+        snapshot.audit_log = self.audit_log.lock().unwrap().clone();
+        snapshot.saved_searches = self.saved_searches.lock().unwrap().clone();
+        snapshot.feedback = self.feedback.lock().unwrap().clone();
+        snapshot.ranking_models = self.ranking_models.lock().unwrap().clone();
+        snapshot.ivf_indexes = self.ivf_indexes.lock().unwrap().clone();
+        snapshot.tombstones = self.tombstones.lock().unwrap().clone();
+        snapshot.entity_links = self.entity_links.lock().unwrap().clone();
+        snapshot.stale_vectors = self.stale_vectors.lock().unwrap().clone();
+        Ok(snapshot)
+    }
+
+    /// Write the snapshot file if this storage was opened from a path. Each file is written aside
+    /// and renamed into place, one persist at a time, and the query log goes to its own file
+    pub fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let _writer = self.persist_lock.lock().unwrap();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut snapshot = self.full_snapshot()?;
+        snapshot.query_log.clear();
+        snapshot.generation += 1;
+        let shards = self.shard_count();
+        if shards > 1 {
+            let parts = split_shards(&mut snapshot, shards);
+            std::thread::scope(|scope| {
+                let writers: Vec<_> = parts
+                    .iter()
+                    .enumerate()
+                    .map(|(i, part)| {
+                        scope.spawn(move || -> Result<()> {
+                            write_atomic(&shard_path(path, i), serde_json::to_string(part)?.as_bytes())?;
+                            Ok(())
+                        })
+                    })
+                    .collect();
+                writers.into_iter().try_for_each(|writer| writer.join().map_err(|_| anyhow!("Shard writer panicked"))?)
+            })?;
+            snapshot.shards = Some(shards);
+        }
+        write_atomic(path, serde_json::to_string(&snapshot)?.as_bytes())?;
+        *self.generation.lock().unwrap() = snapshot.generation;
+        let log = self.query_log.lock().unwrap();
+        self.write_query_log(&log)?;
+        tracing::debug!("Persisted index to {} in {} shard(s)", path.display(), shards);
+        Ok(())
+    }
+
+    /// Partition documents, chunks and embeddings across `shards` files when persisting, and give
+    /// each shard its own search thread. Takes effect at the next `persist`
+    pub fn with_shards(self, shards: usize) -> Self {
+        *self.shards.lock().unwrap() = shards.max(1);
+        self
+    }
+
+    pub fn shard_count(&self) -> usize {
+        *self.shards.lock().unwrap()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Store `document` under this view's tenant
+    pub fn store_document(&mut self, mut document: ProcessedDocument) -> Result<String> {
+        document.metadata.tenant = self.tenant.clone();
         let doc_id = document.id.clone();
         let mut docs = self.documents.lock().unwrap();
+        let mut budget = self.content_budget.lock().unwrap();
+        budget.touch(&doc_id, document.content.len());
         docs.insert(doc_id.clone(), document);
+        let victims = budget.over_budget(Some(&doc_id));
+        budget.spill(&mut docs, victims);
         Ok(doc_id)
     }
 
-    pub fn store_chunks(&mut self, _doc_id: String, chunks: Vec<DocumentChunk>) -> Result<()> {
+    /// Keep at most `max_bytes` of document content in memory, dropping the least recently used first.
+    /// Chunks stay, so search is unaffected; a dropped document's content is written verbatim to a
+    /// temporary spill file and read back, checked against its hash, when it's next fetched
+    pub fn with_memory_budget(self, max_bytes: usize) -> Self {
+        {
+            let mut docs = self.documents.lock().unwrap();
+            let mut budget = self.content_budget.lock().unwrap();
+            budget.max_bytes = Some(max_bytes);
+            let victims = budget.over_budget(None);
+            budget.spill(&mut docs, victims);
+        }
+        self
+    }
+
+    pub fn memory_budget(&self) -> Option<usize> {
+        self.content_budget.lock().unwrap().max_bytes
+    }
+
+    /// Store embeddings in `quantization`'s form from now on. Switching to a quantized form also
+    /// quantizes the embeddings already stored, which can't be undone short of embedding the chunks again.
+    /// Product quantization starts once there are `PQ_MIN_TRAINING_VECTORS` embeddings to train on
+    pub fn with_embedding_quantization(self, quantization: EmbeddingQuantization) -> Self {
+        *self.quantization.lock().unwrap() = quantization;
+        if let Err(e) = self.train_pq_codebook() {
+            tracing::warn!("Keeping embeddings unquantized: {}", e);
+        }
+        let codebook = self.pq_codebook();
+        let mut embeddings = self.embeddings.lock().unwrap();
+        if quantization != EmbeddingQuantization::None {
+            let current = std::mem::take(&mut *embeddings);
+            *embeddings =
+                current.into_iter().map(|(id, e)| (id, e.quantized(quantization, codebook.as_ref()))).collect();
+        }
+        drop(embeddings);
+        self
+    }
+
+    /// Codebook the embeddings are product-quantized with, once trained
+    pub fn pq_codebook(&self) -> Option<Arc<PqCodebook>> {
+        self.pq_codebook.lock().unwrap().clone()
+    }
+
+    /// Train the codebook from the stored vectors if product quantization is on and there is none yet
+    fn train_pq_codebook(&self) -> Result<()> {
+        if self.embedding_quantization() != EmbeddingQuantization::Pq || self.pq_codebook().is_some() {
+            return Ok(());
+        }
+        let vectors: HashMap<String, Vec<f32>> = self
+            .embeddings
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, e)| matches!(e, StoredEmbedding::Full(_) | StoredEmbedding::Int8(_)))
+            .map(|(id, e)| (id.clone(), e.to_vec()))
+            .collect();
+        if let Some(codebook) = pq::train_if_enough(&vectors)? {
+            tracing::info!("Trained a product quantizer on {} embeddings", vectors.len());
+            *self.pq_codebook.lock().unwrap() = Some(codebook);
+        }
+        Ok(())
+    }
+
+    pub fn embedding_quantization(&self) -> EmbeddingQuantization {
+        *self.quantization.lock().unwrap()
+    }
+
+    /// Keep only the first `dimensions` (at least 1) of each embedding, for models trained so that a
+    /// prefix of the vector is itself a usable embedding. Longer embeddings already stored are cut too
+    pub fn with_embedding_dimensions(self, dimensions: usize) -> Self {
+        let dimensions = dimensions.max(1);
+        *self.embedding_dimension_limit.lock().unwrap() = Some(dimensions);
+        let mut embeddings = self.embeddings.lock().unwrap();
+        if embeddings.values().any(|e| e.dimensions() > dimensions) {
+            let current = std::mem::take(&mut *embeddings);
+            *embeddings = current.into_iter().map(|(id, e)| (id, e.truncated(dimensions))).collect();
+        }
+        drop(embeddings);
+        self
+    }
+
+    pub fn embedding_dimension_limit(&self) -> Option<usize> {
+        *self.embedding_dimension_limit.lock().unwrap()
+    }
+
+    /// `doc` with its content, read back from its spill file if it was dropped from memory. Fails
+    /// if the file is gone or no longer holds exactly what was written
+    fn with_content(&self, doc: &ProcessedDocument) -> Result<ProcessedDocument> {
+        let spilled = self.content_budget.lock().unwrap().evicted.get(&doc.id).cloned();
+        let Some(spilled) = spilled else {
+            return Ok(doc.clone());
+        };
+        let content = std::fs::read_to_string(&spilled.path)
+            .ok()
+            .filter(|content| content.len() == spilled.size && content_hash(content) == spilled.hash)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Content of {} spilled to {} is missing or corrupt",
+                    doc.metadata.file_path,
+                    spilled.path.display()
+                )
+            })?;
+        Ok(ProcessedDocument { content, ..doc.clone() })
+    }
+
+    #[tracing::instrument(skip_all, fields(doc_id = %doc_id, chunks = chunks.len(), elapsed_ms = Empty))]
+    pub fn store_chunks(&mut self, doc_id: String, chunks: Vec<DocumentChunk>) -> Result<()> {
+        let started = Instant::now();
+        let relations = self.documents.lock().unwrap().get(&doc_id).map(|doc| doc.metadata.relations.clone());
+        let own: Vec<String> = chunks.iter().map(|chunk| chunk.id.clone()).collect();
         let mut chunk_map = self.chunks.lock().unwrap();
         for chunk in chunks {
             chunk_map.insert(chunk.id.clone(), chunk);
         }
+        self.entity_links.lock().unwrap().add_document(&doc_id, relations.unwrap_or_default(), &own, &chunk_map);
+        tracing::Span::current().record("elapsed_ms", elapsed_ms(started));
+        Ok(())
+    }
+
+    /// Graph of the relations stated by this view's live documents
+    pub fn knowledge_graph(&self) -> KnowledgeGraph {
+        let docs = self.documents.lock().unwrap();
+        let links = self.entity_links.lock().unwrap();
+        links.graph(|doc_id| docs.get(doc_id).is_some_and(|doc| self.owns(doc)))
+    }
+
+    /// Graph entities each linked chunk names; the caller keeps the chunks it can see
+    pub fn entity_links(&self) -> std::sync::MutexGuard<'_, EntityLinks> {
+        self.entity_links.lock().unwrap()
+    }
+
+    /// Store chunk embeddings produced by `model`, refusing to mix vectors from different models
+    pub fn store_embeddings(&mut self, model: &str, mut embeddings: HashMap<String, Vec<f32>>) -> Result<()> {
+        let mut embedding_model = self.embedding_model.lock().unwrap();
+        match embedding_model.as_deref() {
+            Some(existing) if existing != model => {
+                return Err(anyhow!(
+                    "Index is embedded with {}, cannot add embeddings from {}",
+                    existing,
+                    model
+                ))
+            }
+            _ => *embedding_model = Some(model.to_string()),
+        }
+        let (quantization, limit) = (self.embedding_quantization(), self.embedding_dimension_limit());
+        let codebook = self.pq_codebook();
+        if let Some(limit) = limit {
+            embeddings.values_mut().for_each(|vector| vector.truncate(limit));
+        }
+        self.mark_stale_vectors(embeddings.keys());
+        if let Some(index) = self.ivf_indexes.lock().unwrap().iter_mut().find(|i| i.tenant == self.tenant) {
+            Arc::make_mut(index).add(&embeddings);
+        }
+        self.embeddings.lock().unwrap().extend(
+            embeddings.into_iter().map(|(id, vector)| (id, StoredEmbedding::new(vector, quantization, codebook.as_ref()))),
+        );
+        if quantization == EmbeddingQuantization::Pq && codebook.is_none() {
+            self.train_pq_codebook()?;
+            if let Some(codebook) = self.pq_codebook() {
+                let mut embeddings = self.embeddings.lock().unwrap();
+                let current = std::mem::take(&mut *embeddings);
+                *embeddings = current.into_iter().map(|(id, e)| (id, e.quantized(quantization, Some(&codebook)))).collect();
+            }
+        }
         Ok(())
     }
 
+    /// Start recording which chunks' embeddings change, for a disk index built or opened now
+    pub fn track_stale_vectors(&self) {
+        self.stale_vectors.lock().unwrap().get_or_insert_with(BTreeSet::new);
+    }
+
+    /// Forget the recorded changes, once a disk index holds every current embedding
+    pub fn reset_stale_vectors(&self) {
+        *self.stale_vectors.lock().unwrap() = Some(BTreeSet::new());
+    }
+
+    /// Chunks embedded or removed since the disk index was built or first opened
+    pub fn stale_vectors(&self) -> HashSet<String> {
+        self.stale_vectors.lock().unwrap().iter().flatten().cloned().collect()
+    }
+
+    fn mark_stale_vectors<'a>(&self, chunk_ids: impl IntoIterator<Item = &'a String>) {
+        if let Some(stale) = self.stale_vectors.lock().unwrap().as_mut() {
+            stale.extend(chunk_ids.into_iter().cloned());
+        }
+    }
+
+    /// Take removed chunks out of the vector indexes' bookkeeping
+    fn forget_vectors(&self, chunk_ids: &[String]) {
+        if chunk_ids.is_empty() {
+            return;
+        }
+        self.mark_stale_vectors(chunk_ids);
+        let removed: HashSet<&str> = chunk_ids.iter().map(String::as_str).collect();
+        for index in self.ivf_indexes.lock().unwrap().iter_mut() {
+            Arc::make_mut(index).remove(|id| removed.contains(id));
+        }
+    }
+
+    pub fn embedding_model(&self) -> Option<String> {
+        self.embedding_model.lock().unwrap().clone()
+    }
+
+    /// Length of the stored embedding vectors, `None` before anything is embedded
+    pub fn embedding_dimensions(&self) -> Option<usize> {
+        self.embeddings.lock().unwrap().values().next().map(EmbeddingVector::dimensions)
+    }
+
+    /// This view's embeddings at full precision, dequantizing any stored as int8
+    pub fn get_all_embeddings(&self) -> Result<HashMap<String, Vec<f32>>> {
+        Ok(self.get_stored_embeddings()?.into_iter().map(|(id, e)| (id, e.to_vec())).collect())
+    }
+
+    /// This view's embeddings in the form they're stored, for scoring without dequantizing
+    pub fn get_stored_embeddings(&self) -> Result<HashMap<String, StoredEmbedding>> {
+        let docs = self.documents.lock().unwrap();
+        let chunks = self.chunks.lock().unwrap();
+        let embeddings = self.embeddings.lock().unwrap();
+        Ok(embeddings
+            .iter()
+            .filter(|(id, _)| chunks.get(*id).map_or(self.tenant.is_none(), |chunk| self.owns_chunk(&docs, chunk)))
+            .map(|(id, embedding)| (id.clone(), embedding.clone()))
+            .collect())
+    }
+
+    /// Cached embedding of `text` under `model`, counting the lookup as a hit or miss
+    pub fn cached_embedding(&self, model: &str, text: &str) -> Option<Vec<f32>> {
+        let cached = self.embedding_cache.lock().unwrap().get(&embedding_cache_key(model, text)).cloned();
+        let mut stats = self.embedding_cache_stats.lock().unwrap();
+        match cached {
+            Some(_) => stats.hits += 1,
+            None => stats.misses += 1,
+        }
+        cached
+    }
+
+    pub fn cache_embedding(&self, model: &str, text: &str, embedding: Vec<f32>) {
+        self.embedding_cache.lock().unwrap().insert(embedding_cache_key(model, text), embedding);
+    }
+
+    /// Answer to the closest cached question within the config's similarity and age limits
+    pub fn cached_answer(&self, config: &AnswerCacheConfig, model: &str, embedding: &[f32]) -> Option<Answer> {
+        let entries = self.answer_cache.lock().unwrap();
+        let cached = find_cached_answer(&entries, config, self.tenant.as_deref(), model, embedding, unix_now())
+            .map(|i| entries[i].answer.clone());
+        let mut stats = self.answer_cache_stats.lock().unwrap();
+        match cached {
+            Some(_) => stats.hits += 1,
+            None => stats.misses += 1,
+        }
+        cached
+    }
+
+    pub fn cache_answer(&self, config: &AnswerCacheConfig, mut entry: CachedAnswer) {
+        entry.tenant = self.tenant.clone();
+        let mut entries = self.answer_cache.lock().unwrap();
+        entries.push(entry);
+        let overflow = entries.len().saturating_sub(config.max_entries);
+        entries.drain(..overflow);
+    }
+
+    pub fn cached_answers(&self) -> Vec<CachedAnswer> {
+        let entries = self.answer_cache.lock().unwrap();
+        entries.iter().filter(|entry| entry.tenant == self.tenant).cloned().collect()
+    }
+
+    /// Drop answers older than `ttl_secs`, returning how many were removed
+    pub fn evict_expired_answers(&self, ttl_secs: u64) -> usize {
+        let mut entries = self.answer_cache.lock().unwrap();
+        let before = entries.len();
+        let now = unix_now();
+        entries.retain(|entry| entry.tenant != self.tenant || !entry.is_expired(Some(ttl_secs), now));
+        before - entries.len()
+    }
+
+    /// Drop every cached answer, returning how many there were
+    pub fn clear_answer_cache(&self) -> usize {
+        let mut entries = self.answer_cache.lock().unwrap();
+        let before = entries.len();
+        entries.retain(|entry| entry.tenant != self.tenant);
+        before - entries.len()
+    }
+
+    /// Fetching a document counts as using it, bringing dropped content back into memory
     pub fn get_document(&self, doc_id: &str) -> Result<Option<ProcessedDocument>> {
+        let mut docs = self.documents.lock().unwrap();
+        let Some(document) = docs.get(doc_id).filter(|doc| self.owns(doc)).map(|doc| self.with_content(doc)).transpose()? else {
+            return Ok(None);
+        };
+        let mut budget = self.content_budget.lock().unwrap();
+        if budget.max_bytes.is_some() {
+            budget.touch(doc_id, document.content.len());
+            docs.insert(doc_id.to_string(), document.clone());
+            let victims = budget.over_budget(Some(doc_id));
+            budget.spill(&mut docs, victims);
+        }
+        Ok(Some(document))
+    }
+
+    pub fn get_chunk(&self, chunk_id: &str) -> Result<Option<DocumentChunk>> {
         let docs = self.documents.lock().unwrap();
-        Ok(docs.get(doc_id).cloned())
+        let chunks = self.chunks.lock().unwrap();
+        Ok(chunks.get(chunk_id).filter(|chunk| self.owns_chunk(&docs, chunk)).cloned())
     }
 
     pub fn get_all_chunks(&self) -> Result<Vec<DocumentChunk>> {
+        let docs = self.documents.lock().unwrap();
         let chunks = self.chunks.lock().unwrap();
-        Ok(chunks.values().cloned().collect())
+        Ok(chunks.values().filter(|chunk| self.owns_chunk(&docs, chunk)).cloned().collect())
+    }
+
+    /// Replace a document's tags; `false` if there is no such document
+    pub fn set_document_tags(&mut self, doc_id: &str, tags: BTreeMap<String, String>) -> Result<bool> {
+        let mut docs = self.documents.lock().unwrap();
+        match docs.get_mut(doc_id).filter(|doc| doc.metadata.tenant == self.tenant) {
+            Some(doc) => {
+                doc.metadata.tags = tags;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Chunks of one document in document order
+    pub fn get_document_chunks(&self, doc_id: &str) -> Result<Vec<DocumentChunk>> {
+        let docs = self.documents.lock().unwrap();
+        let chunks = self.chunks.lock().unwrap();
+        let mut document_chunks: Vec<DocumentChunk> =
+            chunks.values().filter(|c| c.document_id == doc_id && self.owns_chunk(&docs, c)).cloned().collect();
+        document_chunks.sort_by_key(|c| (c.start_pos, c.byte_start));
+        Ok(document_chunks)
+    }
+
+    /// Boost of each document tagged with one; unusable values are skipped, as scoring can't apply them
+    pub fn document_boosts(&self) -> HashMap<String, DocumentBoost> {
+        let docs = self.documents.lock().unwrap();
+        docs.values()
+            .filter(|doc| self.owns(doc))
+            .filter_map(|doc| match DocumentBoost::from_tags(&doc.metadata.tags) {
+                Ok(boost) => boost.map(|boost| (doc.id.clone(), boost)),
+                Err(e) => {
+                    tracing::warn!("Ignoring boost of document {}: {}", doc.id, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Collection of each of this view's documents tagged with one
+    pub fn document_collections(&self) -> HashMap<String, String> {
+        let docs = self.documents.lock().unwrap();
+        docs.values()
+            .filter(|doc| self.owns(doc))
+            .filter_map(|doc| Some((doc.id.clone(), doc.metadata.tags.get(COLLECTION_TAG)?.clone())))
+            .collect()
+    }
+
+    /// When each of this view's documents' source file was last modified, as of indexing. Documents
+    /// indexed before that was kept have their file checked the first time they are asked about
+    pub fn source_modified_times(&self) -> HashMap<String, Option<u64>> {
+        let docs = self.documents.lock().unwrap();
+        let mut checked = self.checked_modified_times.lock().unwrap();
+        docs.values()
+            .filter(|doc| self.owns(doc))
+            .map(|doc| {
+                let modified = doc.metadata.modified_at.or_else(|| {
+                    *checked
+                        .entry(doc.id.clone())
+                        .or_insert_with(|| modified_secs(Path::new(&doc.metadata.file_path)))
+                });
+                (doc.id.clone(), modified)
+            })
+            .collect()
     }
 
     pub fn list_documents(&self) -> Result<Vec<String>> {
         let docs = self.documents.lock().unwrap();
-        Ok(docs.keys().cloned().collect())
+        Ok(docs.values().filter(|doc| self.owns(doc)).map(|doc| doc.id.clone()).collect())
     }
 
+    /// Counts, cache hit rates and usage for this view's tenant; the caches' counters are index-wide
     pub fn get_stats(&self) -> Result<StorageStats> {
+        let total_chunks = self.get_all_chunks()?.len();
+        let answer_cache_entries = self.cached_answers().len();
+        let embedding_bytes = self.get_stored_embeddings()?.values().map(StoredEmbedding::memory_bytes).sum();
         let docs = self.documents.lock().unwrap();
-        let chunks = self.chunks.lock().unwrap();
+        let docs: Vec<&ProcessedDocument> = docs.values().filter(|doc| self.owns(doc)).collect();
 
-        let total_size_bytes = docs.values()
-            .map(|doc| doc.content.len())
+        let budget = self.content_budget.lock().unwrap();
+        let total_size_bytes = docs.iter()
+            .map(|doc| budget.evicted.get(&doc.id).map_or(doc.content.len(), |spilled| spilled.size))
             .sum::<usize>();
+        let evicted_documents = docs.iter().filter(|doc| budget.evicted.contains_key(&doc.id)).count();
+        drop(budget);
 
+        let cache_stats = *self.embedding_cache_stats.lock().unwrap();
+        let answer_stats = *self.answer_cache_stats.lock().unwrap();
         Ok(StorageStats {
             total_documents: docs.len(),
-            total_chunks: chunks.len(),
+            total_chunks,
             total_size_bytes,
+            evicted_documents,
+            embedding_cache_entries: self.embedding_cache.lock().unwrap().len(),
+            embedding_cache_hits: cache_stats.hits,
+            embedding_cache_misses: cache_stats.misses,
+            embedding_bytes,
+            answer_cache_entries,
+            answer_cache_hits: answer_stats.hits,
+            answer_cache_misses: answer_stats.misses,
+            usage: self.tenant_usage(),
         })
     }
 
+    /// Vocabulary, term document frequencies and chunk lengths of this view's chunks
+    pub fn corpus_stats(&self) -> Result<CorpusStats> {
+        let chunks = self.get_all_chunks()?;
+        let mut document_frequencies: BTreeMap<String, usize> = BTreeMap::new();
+        let mut lengths = Vec::with_capacity(chunks.len());
+        for chunk in &chunks {
+            let terms = tokenize(&chunk.content);
+            lengths.push(terms.len());
+            for term in terms.into_iter().collect::<HashSet<_>>() {
+                *document_frequencies.entry(term).or_insert(0) += 1;
+            }
+        }
+        Ok(CorpusStats {
+            chunks: chunks.len(),
+            vocabulary_size: document_frequencies.len(),
+            total_terms: lengths.iter().sum(),
+            document_frequencies,
+            chunk_lengths: LengthDistribution::from_lengths(lengths),
+        })
+    }
+
+    fn tenant_usage(&self) -> BTreeMap<String, Usage> {
+        let usage = self.usage.lock().unwrap();
+        usage
+            .iter()
+            .filter_map(|(key, usage)| {
+                let operation = match (&self.tenant, key.split_once('/')) {
+                    (Some(tenant), Some((owner, operation))) if owner == tenant => operation,
+                    (None, None) => key.as_str(),
+                    _ => return None,
+                };
+                Some((operation.to_string(), *usage))
+            })
+            .collect()
+    }
+
+    /// Add to the running usage total of `operation`
+    pub fn record_usage(&self, operation: &str, usage: &Usage) {
+        if usage.is_empty() {
+            return;
+        }
+        self.usage.lock().unwrap().entry(self.usage_key(operation)).or_default().add(usage);
+    }
+
+    pub fn record_evaluation_run(&mut self, run: EvaluationRun) -> Result<()> {
+        let mut runs = self.evaluation_runs.lock().unwrap();
+        runs.push(run);
+        Ok(())
+    }
+
+    /// Recorded evaluation runs, oldest first
+    pub fn list_evaluation_runs(&self) -> Result<Vec<EvaluationRun>> {
+        let mut runs = self.evaluation_runs.lock().unwrap().clone();
+        runs.sort_by_key(|r| r.timestamp);
+        Ok(runs)
+    }
+
+    /// Append a search to the log under this view's tenant, dropping the oldest entries past `max_entries`
+    pub fn record_query(&self, mut entry: QueryLogEntry, max_entries: usize) {
+        entry.tenant = self.tenant.clone();
+        let mut log = self.query_log.lock().unwrap();
+        log.push(entry);
+        let excess = log.len().saturating_sub(max_entries);
+        log.drain(..excess);
+        if let Err(e) = self.append_query_log(&log) {
+            tracing::warn!("Failed to write the query log: {}", e);
+        }
+    }
+
+    /// Drop the oldest logged searches past `max_entries`, as the log file may keep up to twice as many
+    pub fn trim_query_log(&self, max_entries: usize) {
+        let mut log = self.query_log.lock().unwrap();
+        let excess = log.len().saturating_sub(max_entries);
+        log.drain(..excess);
+    }
+
+    /// Append the newest entry of `log` to the log file, rewriting the file with all of `log` instead
+    /// when it doesn't exist yet or has grown to twice as many lines as are kept
+    fn append_query_log(&self, log: &[QueryLogEntry]) -> Result<()> {
+        let (Some(path), Some(entry)) = (&self.path, log.last()) else {
+            return Ok(());
+        };
+        let log_path = query_log_path(path);
+        let lines = *self.query_log_lines.lock().unwrap();
+        if !log_path.exists() || lines >= 2 * log.len() {
+            return self.write_query_log(log);
+        }
+        let mut file = std::fs::OpenOptions::new().append(true).open(&log_path)?;
+        file.write_all(format!("{}\n", serde_json::to_string(entry)?).as_bytes())?;
+        *self.query_log_lines.lock().unwrap() += 1;
+        Ok(())
+    }
+
+    /// Replace the query log file with `log`, the whole in-memory log, whose lock the caller holds
+    fn write_query_log(&self, log: &[QueryLogEntry]) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let log_path = query_log_path(path);
+        if log.is_empty() && !log_path.exists() {
+            return Ok(());
+        }
+        let mut content = String::new();
+        for entry in log {
+            content.push_str(&serde_json::to_string(entry)?);
+            content.push('\n');
+        }
+        if let Some(parent) = log_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(&log_path, content.as_bytes())?;
+        *self.query_log_lines.lock().unwrap() = log.len();
+        Ok(())
+    }
+
+    /// This tenant's logged searches, oldest first
+    pub fn query_log(&self) -> Result<Vec<QueryLogEntry>> {
+        let log = self.query_log.lock().unwrap();
+        Ok(log.iter().filter(|entry| entry.tenant == self.tenant).cloned().collect())
+    }
+
+    /// Forget this tenant's logged searches, returning how many there were
+    pub fn clear_query_log(&self) -> Result<usize> {
+        let mut log = self.query_log.lock().unwrap();
+        let before = log.len();
+        log.retain(|entry| entry.tenant != self.tenant);
+        self.write_query_log(&log)?;
+        Ok(before - log.len())
+    }
+
+    /// Append an operation to the audit log under this view's tenant
+    pub fn record_audit(&self, mut entry: AuditEntry) {
+        entry.tenant = self.tenant.clone();
+        self.audit_log.lock().unwrap().push(entry);
+    }
+
+    /// This tenant's audited operations that pass `filter`, oldest first
+    pub fn audit_log(&self, filter: &AuditFilter) -> Vec<AuditEntry> {
+        let log = self.audit_log.lock().unwrap();
+        log.iter().filter(|entry| entry.tenant == self.tenant && filter.matches(entry)).cloned().collect()
+    }
+
+    /// Save `search` under this view's tenant, replacing and returning any saved under its name
+    pub fn save_search(&self, mut search: SavedSearch) -> Option<SavedSearch> {
+        search.tenant = self.tenant.clone();
+        let mut saved = self.saved_searches.lock().unwrap();
+        let existing = saved.iter().position(|s| s.tenant == self.tenant && s.name == search.name);
+        match existing {
+            Some(i) => Some(std::mem::replace(&mut saved[i], search)),
+            None => {
+                saved.push(search);
+                None
+            }
+        }
+    }
+
+    pub fn saved_search(&self, name: &str) -> Option<SavedSearch> {
+        let saved = self.saved_searches.lock().unwrap();
+        saved.iter().find(|s| s.tenant == self.tenant && s.name == name).cloned()
+    }
+
+    /// This tenant's saved searches, by name
+    pub fn saved_searches(&self) -> Vec<SavedSearch> {
+        let saved = self.saved_searches.lock().unwrap();
+        let mut searches: Vec<SavedSearch> = saved.iter().filter(|s| s.tenant == self.tenant).cloned().collect();
+        searches.sort_by(|a, b| a.name.cmp(&b.name));
+        searches
+    }
+
+    pub fn delete_saved_search(&self, name: &str) -> Option<SavedSearch> {
+        let mut saved = self.saved_searches.lock().unwrap();
+        let i = saved.iter().position(|s| s.tenant == self.tenant && s.name == name)?;
+        Some(saved.remove(i))
+    }
+
+    /// Add a relevance judgement under this view's tenant, replacing any earlier one on the same chunk for
+    /// the same query and dropping this tenant's oldest past `MAX_FEEDBACK_ENTRIES`
+    pub fn record_feedback(&self, mut entry: FeedbackEntry) {
+        entry.tenant = self.tenant.clone();
+        let mut feedback = self.feedback.lock().unwrap();
+        feedback.retain(|earlier| !entry.supersedes(earlier));
+        feedback.push(entry);
+        let mut excess = feedback.iter().filter(|e| e.tenant == self.tenant).count().saturating_sub(MAX_FEEDBACK_ENTRIES);
+        feedback.retain(|e| {
+            let drop = excess > 0 && e.tenant == self.tenant;
+            excess -= drop as usize;
+            !drop
+        });
+    }
+
+    /// This tenant's relevance judgements, oldest first
+    pub fn feedback(&self) -> Vec<FeedbackEntry> {
+        let feedback = self.feedback.lock().unwrap();
+        feedback.iter().filter(|entry| entry.tenant == self.tenant).cloned().collect()
+    }
+
+    /// Forget this tenant's relevance judgements, returning how many there were
+    pub fn clear_feedback(&self) -> usize {
+        let mut feedback = self.feedback.lock().unwrap();
+        let before = feedback.len();
+        feedback.retain(|entry| entry.tenant != self.tenant);
+        before - feedback.len()
+    }
+
+    /// Replace this tenant's learned ranking model
+    pub fn set_ranking_model(&self, mut model: RankingModel) {
+        model.tenant = self.tenant.clone();
+        let mut models = self.ranking_models.lock().unwrap();
+        models.retain(|m| m.tenant != self.tenant);
+        models.push(model);
+    }
+
+    pub fn ranking_model(&self) -> Option<RankingModel> {
+        let models = self.ranking_models.lock().unwrap();
+        models.iter().find(|m| m.tenant == self.tenant).cloned()
+    }
+
+    /// Replace this tenant's IVF index
+    pub fn set_ivf_index(&self, mut index: IvfIndex) -> Arc<IvfIndex> {
+        index.tenant = self.tenant.clone();
+        let index = Arc::new(index);
+        let mut indexes = self.ivf_indexes.lock().unwrap();
+        indexes.retain(|i| i.tenant != self.tenant);
+        indexes.push(index.clone());
+        index
+    }
+
+    pub fn ivf_index(&self) -> Option<Arc<IvfIndex>> {
+        let indexes = self.ivf_indexes.lock().unwrap();
+        indexes.iter().find(|i| i.tenant == self.tenant).cloned()
+    }
+
+    /// Remove a document with its chunks and their embeddings for good, returning how many chunks went
+    /// with it. A trashed document is removed just the same; `None` if this view has no such document
+    pub fn delete_document(&mut self, doc_id: &str) -> Result<Option<usize>> {
+        {
+            let mut docs = self.documents.lock().unwrap();
+            if !docs.get(doc_id).is_some_and(|doc| self.in_tenant(doc)) {
+                return Ok(None);
+            }
+            docs.remove(doc_id);
+            self.tombstones.lock().unwrap().remove(doc_id);
+            self.content_budget.lock().unwrap().forget(doc_id);
+        }
+        let mut chunks = self.chunks.lock().unwrap();
+        let mut embeddings = self.embeddings.lock().unwrap();
+        let before = chunks.len();
+        let mut removed = Vec::new();
+        chunks.retain(|chunk_id, chunk| {
+            let keep = chunk.document_id != doc_id;
+            if !keep {
+                embeddings.remove(chunk_id);
+                removed.push(chunk_id.clone());
+            }
+            keep
+        });
+        self.entity_links.lock().unwrap().remove_document(doc_id, removed.iter().map(String::as_str));
+        self.forget_vectors(&removed);
+        // Cached answers may cite the removed chunks
+        self.answer_cache.lock().unwrap().clear();
+        self.feedback.lock().unwrap().retain(|entry| chunks.contains_key(&entry.chunk_id));
+        Ok(Some(before - chunks.len()))
+    }
+
+    /// Move a document to the trash, hiding it and its chunks until `undelete_document` or `delete_document`.
+    /// Returns how many chunks it hid, or `None` if there is no such live document
+    pub fn trash_document(&mut self, doc_id: &str, now: u64) -> Result<Option<usize>> {
+        let docs = self.documents.lock().unwrap();
+        if !docs.get(doc_id).is_some_and(|doc| self.owns(doc)) {
+            return Ok(None);
+        }
+        self.tombstones.lock().unwrap().insert(doc_id.to_string(), now);
+        drop(docs);
+        let chunks = self.chunks.lock().unwrap().values().filter(|c| c.document_id == doc_id).count();
+        // Cached answers may cite the hidden chunks
+        self.answer_cache.lock().unwrap().clear();
+        Ok(Some(chunks))
+    }
+
+    /// File path or upload name of a document of this view, trashed or not
+    pub fn document_source(&self, doc_id: &str) -> Option<String> {
+        let docs = self.documents.lock().unwrap();
+        docs.get(doc_id).filter(|doc| self.in_tenant(doc)).map(|doc| doc.metadata.file_path.clone())
+    }
+
+    /// Take a document back out of the trash; `false` if this view has no such trashed document
+    pub fn undelete_document(&mut self, doc_id: &str) -> Result<bool> {
+        let docs = self.documents.lock().unwrap();
+        if !docs.get(doc_id).is_some_and(|doc| self.in_tenant(doc)) {
+            return Ok(false);
+        }
+        Ok(self.tombstones.lock().unwrap().remove(doc_id).is_some())
+    }
+
+    /// This view's trashed documents, oldest deletion first
+    pub fn trashed_documents(&self) -> Vec<TrashedDocument> {
+        let docs = self.documents.lock().unwrap();
+        let tombstones = self.tombstones.lock().unwrap().clone();
+        let mut trashed: Vec<TrashedDocument> = tombstones
+            .into_iter()
+            .filter_map(|(id, deleted_at)| {
+                let doc = docs.get(&id).filter(|doc| self.in_tenant(doc))?;
+                Some(TrashedDocument { file_path: doc.metadata.file_path.clone(), id, deleted_at, chunks: 0 })
+            })
+            .collect();
+        drop(docs);
+        let chunks = self.chunks.lock().unwrap();
+        let mut counts: HashMap<&str, usize> = HashMap::new();
+        for chunk in chunks.values() {
+            *counts.entry(chunk.document_id.as_str()).or_insert(0) += 1;
+        }
+        for doc in &mut trashed {
+            doc.chunks = counts.get(doc.id.as_str()).copied().unwrap_or(0);
+        }
+        trashed.sort_by(|a, b| a.deleted_at.cmp(&b.deleted_at).then_with(|| a.id.cmp(&b.id)));
+        trashed
+    }
+
+    /// Remove chunks whose document is gone and embeddings whose chunk is gone. Orphans belong to no
+    /// tenant, so any view collects all of them
+    pub fn gc(&mut self) -> Result<GcReport> {
+        let docs = self.documents.lock().unwrap();
+        let mut chunks = self.chunks.lock().unwrap();
+        let mut embeddings = self.embeddings.lock().unwrap();
+        let mut report = GcReport::default();
+        chunks.retain(|_, chunk| {
+            let keep = docs.contains_key(&chunk.document_id);
+            if !keep {
+                report.chunks += 1;
+                report.bytes += chunk.content.len();
+            }
+            keep
+        });
+        let mut removed = Vec::new();
+        embeddings.retain(|chunk_id, embedding| {
+            let keep = chunks.contains_key(chunk_id);
+            if !keep {
+                report.embeddings += 1;
+                report.bytes += embedding.memory_bytes();
+                removed.push(chunk_id.clone());
+            }
+            keep
+        });
+        self.forget_vectors(&removed);
+        if report.chunks > 0 {
+            self.entity_links.lock().unwrap().retain_chunks(|chunk_id| chunks.contains_key(chunk_id));
+            // Cached answers may cite the removed chunks
+            self.answer_cache.lock().unwrap().clear();
+            self.feedback.lock().unwrap().retain(|entry| chunks.contains_key(&entry.chunk_id));
+        }
+        drop((docs, chunks, embeddings));
+        if report.chunks > 0 || report.embeddings > 0 {
+            tracing::info!("Collected {} orphan chunks and {} orphan embeddings", report.chunks, report.embeddings);
+        }
+        Ok(report)
+    }
+
+    pub fn check_integrity(&self) -> IntegrityReport {
+        let chunks = self.get_all_chunks().unwrap_or_default();
+        let embeddings = self.get_stored_embeddings().unwrap_or_default();
+        let docs = self.documents.lock().unwrap();
+        let docs: HashMap<&String, &ProcessedDocument> = docs.iter().filter(|(_, doc)| self.owns(doc)).collect();
+        let chunk_ids: HashSet<&String> = chunks.iter().map(|c| &c.id).collect();
+
+        let sorted = |mut ids: Vec<String>| {
+            ids.sort();
+            ids
+        };
+        IntegrityReport {
+            orphan_chunks: sorted(
+                chunks.iter().filter(|c| !docs.contains_key(&c.document_id)).map(|c| c.id.clone()).collect(),
+            ),
+            documents_without_chunks: sorted(
+                docs.keys()
+                    .filter(|id| !chunks.iter().any(|c| &c.document_id == **id))
+                    .map(|id| id.to_string())
+                    .collect(),
+            ),
+            orphan_embeddings: sorted(embeddings.keys().filter(|id| !chunk_ids.contains(id)).cloned().collect()),
+            unembedded_chunks: match self.embedding_model() {
+                Some(_) => sorted(
+                    chunk_ids.iter().filter(|id| !embeddings.contains_key(**id)).map(|id| id.to_string()).collect(),
+                ),
+                None => Vec::new(),
+            },
+        }
+    }
+
+    /// Drop this view's chunks and their embeddings; the model is forgotten once no embeddings are left
+    fn remove_owned_chunks(&self) {
+        let owned_embeddings = self.get_stored_embeddings().unwrap_or_default();
+        let owned_chunks = self.owned_chunk_ids();
+        self.chunks.lock().unwrap().retain(|id, _| !owned_chunks.contains(id));
+        let mut embeddings = self.embeddings.lock().unwrap();
+        embeddings.retain(|id, _| !owned_embeddings.contains_key(id));
+        if embeddings.is_empty() {
+            *self.embedding_model.lock().unwrap() = None;
+        }
+        drop(embeddings);
+        self.forget_vectors(&owned_embeddings.into_keys().collect::<Vec<_>>());
+        self.clear_answer_cache();
+    }
+
+    /// Drop every chunk and embedding but keep the documents, ahead of rebuilding the index
+    pub fn clear_chunks(&mut self) -> Result<()> {
+        self.remove_owned_chunks();
+        Ok(())
+    }
+
     pub fn clear(&mut self) -> Result<()> {
+        for doc in self.trashed_documents() {
+            self.delete_document(&doc.id)?;
+        }
+        self.remove_owned_chunks();
         let mut docs = self.documents.lock().unwrap();
-        let mut chunks = self.chunks.lock().unwrap();
-        docs.clear();
-        chunks.clear();
+        let mut budget = self.content_budget.lock().unwrap();
+        docs.retain(|doc_id, doc| {
+            let keep = doc.metadata.tenant != self.tenant;
+            if !keep {
+                budget.forget(doc_id);
+            }
+            keep
+        });
         Ok(())
     }
 }
@@ -84,7 +1736,6 @@ impl StorageManager {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::processor::DocumentProcessor;
 
     #[test]
     fn test_storage_creation() {
@@ -104,6 +1755,13 @@ mod tests {
                 file_type: "txt".to_string(),
                 file_size: 12,
                 word_count: 2,
+                summary: None,
+                relations: Vec::new(),
+                tags: Default::default(),
+                tenant: None,
+                language: None,
+                ingested_at: None,
+                modified_at: None,
             },
         };
 
@@ -113,5 +1771,312 @@ mod tests {
         let retrieved = storage.get_document("test_doc").unwrap();
         assert!(retrieved.is_some());
         assert_eq!(retrieved.unwrap().content, "Test content");
+
+        let chunk = |i: usize, doc: &str| DocumentChunk {
+            id: format!("{}_{}", doc, i),
+            content: "Test".into(),
+            start_pos: i,
+            end_pos: i + 1,
+            word_count: 1,
+            document_id: doc.to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        };
+        storage.store_chunks("test_doc".to_string(), vec![chunk(1, "test_doc"), chunk(0, "test_doc")]).unwrap();
+        storage.store_chunks("other".to_string(), vec![chunk(0, "other")]).unwrap();
+        let ids: Vec<String> = storage.get_document_chunks("test_doc").unwrap().into_iter().map(|c| c.id).collect();
+        assert_eq!(ids, vec!["test_doc_0", "test_doc_1"]);
+    }
+
+    #[test]
+    fn test_integrity_finds_orphans() {
+        let mut storage = StorageManager::new().unwrap();
+        let chunk = |id: &str, doc: &str| DocumentChunk {
+            id: id.to_string(),
+            content: "Text".into(),
+            start_pos: 0,
+            end_pos: 1,
+            word_count: 1,
+            document_id: doc.to_string(),
+            byte_start: 0,
+            byte_end: 4,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        };
+        storage.store_chunks("gone".to_string(), vec![chunk("gone_0", "gone")]).unwrap();
+        storage
+            .store_embeddings("model", HashMap::from([("deleted_0".to_string(), vec![1.0])]))
+            .unwrap();
+        assert_eq!(
+            storage.check_integrity(),
+            IntegrityReport {
+                orphan_chunks: vec!["gone_0".to_string()],
+                documents_without_chunks: Vec::new(),
+                orphan_embeddings: vec!["deleted_0".to_string()],
+                unembedded_chunks: vec!["gone_0".to_string()],
+            }
+        );
+
+        let collected = storage.gc().unwrap();
+        assert_eq!((collected.chunks, collected.embeddings), (1, 1));
+        assert_eq!(collected.bytes, 4 + StoredEmbedding::Full(vec![1.0]).memory_bytes());
+        assert_eq!(storage.check_integrity(), IntegrityReport::default());
+        assert!(storage.gc().unwrap().is_empty());
+
+        storage.clear().unwrap();
+        assert!(storage.check_integrity().is_clean());
+    }
+
+    #[test]
+    fn test_persist_and_reopen() {
+        let path = Path::new("/tmp/test_storage_snapshot.json");
+        let _ = std::fs::remove_file(path);
+
+        let mut storage = StorageManager::open(path).unwrap();
+        storage
+            .store_chunks(
+                "doc".to_string(),
+                vec![DocumentChunk {
+                    id: "doc_0".to_string(),
+                    content: "Persisted chunk".into(),
+                    start_pos: 0,
+                    end_pos: 2,
+                    word_count: 2,
+                    document_id: "doc".to_string(),
+                    byte_start: 0,
+                    byte_end: 0,
+                    entities: Default::default(),
+                    keyphrases: Default::default(),
+                    minhash: Default::default(),
+                }],
+            )
+            .unwrap();
+        storage
+            .record_evaluation_run(EvaluationRun {
+                id: "run1".to_string(),
+                timestamp: 1,
+                config_fingerprint: "abc".to_string(),
+                query_count: 1,
+                k: 5,
+                metrics: EvaluationMetrics {
+                    relevance: 1.0,
+                    precision: 1.0,
+                    recall: 1.0,
+                    f1_score: 1.0,
+                    hit_rate: 1.0,
+                },
+            })
+            .unwrap();
+        storage
+            .store_embeddings("test-model", HashMap::from([("doc_0".to_string(), vec![0.5, 0.5])]))
+            .unwrap();
+        storage.persist().unwrap();
+
+        let mut reopened = StorageManager::open(path).unwrap();
+        assert_eq!(reopened.get_all_chunks().unwrap().len(), 1);
+        assert_eq!(reopened.list_evaluation_runs().unwrap()[0].id, "run1");
+        assert_eq!(reopened.get_all_embeddings().unwrap()["doc_0"], vec![0.5, 0.5]);
+        assert!(reopened.store_embeddings("other-model", HashMap::new()).is_err());
+
+        // Concurrent persists take turns and each replaces the file whole
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| (0..5).for_each(|_| storage.persist().unwrap()));
+            }
+        });
+        assert_eq!(StorageManager::open(path).unwrap().get_all_chunks().unwrap().len(), 1);
+        assert!(!Path::new("/tmp/test_storage_snapshot.json.tmp").exists());
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_document_signals_come_from_metadata() {
+        use crate::testing::DocumentFixture;
+        let legacy = std::env::temp_dir().join(format!("rag_signals_legacy_{}.md", std::process::id()));
+        std::fs::write(&legacy, "Indexed before modification times were kept.").unwrap();
+        let mut storage = StorageManager::new().unwrap();
+        let mut recorded = DocumentFixture::new("gone.md", "Recorded at ingest.").tag(COLLECTION_TAG, "docs").build();
+        recorded.metadata.modified_at = Some(1_000);
+        storage.store_document(recorded).unwrap();
+        storage.store_document(DocumentFixture::new(legacy.to_str().unwrap(), "Old.").id("legacy").build()).unwrap();
+
+        let modified = storage.source_modified_times();
+        assert_eq!(modified["gone.md"], Some(1_000), "no file to check, but recorded at ingest");
+        assert!(modified["legacy"].is_some());
+        // A legacy document's file is checked once, then remembered
+        std::fs::remove_file(&legacy).unwrap();
+        assert_eq!(storage.source_modified_times()["legacy"], modified["legacy"]);
+        assert_eq!(storage.document_collections(), HashMap::from([("gone.md".to_string(), "docs".to_string())]));
+    }
+
+    #[test]
+    fn test_feedback_is_compacted_and_capped() {
+        let storage = StorageManager::new().unwrap();
+        let judge = |query: &str, chunk_id: String, relevant: bool| {
+            storage.record_feedback(FeedbackEntry { timestamp: 0, query: query.into(), chunk_id, relevant, tenant: None })
+        };
+        judge("Paid leave", "a_0".into(), true);
+        judge("paid leave?", "a_0".into(), false);
+        assert_eq!(storage.feedback().len(), 1, "the later judgement replaces the earlier");
+        assert!(!storage.feedback()[0].relevant);
+
+        storage.tenant("acme").record_feedback(FeedbackEntry {
+            timestamp: 0,
+            query: "leave".into(),
+            chunk_id: "a_0".into(),
+            relevant: true,
+            tenant: None,
+        });
+        for i in 0..MAX_FEEDBACK_ENTRIES {
+            judge("leave", format!("c_{}", i), true);
+        }
+        let feedback = storage.feedback();
+        assert_eq!(feedback.len(), MAX_FEEDBACK_ENTRIES);
+        assert_eq!(feedback[0].chunk_id, "c_0", "the oldest was dropped");
+        assert_eq!(storage.tenant("acme").feedback().len(), 1, "other tenants keep theirs");
+    }
+
+    #[test]
+    fn test_embedding_cache_counts_hits() {
+        let storage = StorageManager::new().unwrap();
+        assert!(storage.cached_embedding("model-a", "hello").is_none());
+
+        storage.cache_embedding("model-a", "hello", vec![1.0, 2.0]);
+        assert_eq!(storage.cached_embedding("model-a", "hello"), Some(vec![1.0, 2.0]));
+        // Same text under another model is a different entry
+        assert!(storage.cached_embedding("model-b", "hello").is_none());
+
+        let stats = storage.get_stats().unwrap();
+        assert_eq!((stats.embedding_cache_entries, stats.embedding_cache_hits, stats.embedding_cache_misses), (1, 1, 2));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_tenant_views_are_isolated() {
+        let path = Path::new("/tmp/test_storage_tenants.json");
+        let _ = std::fs::remove_file(path);
+        let shared = StorageManager::open(path).unwrap();
+        let document = |id: &str| ProcessedDocument {
+            id: id.to_string(),
+            content: format!("{} content", id),
+            metadata: crate::processor::DocumentMetadata {
+                file_path: format!("/{}.txt", id),
+                file_type: "txt".to_string(),
+                file_size: 0,
+                word_count: 2,
+                summary: None,
+                relations: Vec::new(),
+                tags: Default::default(),
+                tenant: None,
+                language: None,
+                ingested_at: None,
+                modified_at: None,
+            },
+        };
+        let chunk = |doc: &str| DocumentChunk {
+            id: format!("{}_0", doc),
+            content: format!("{} content", doc).into(),
+            start_pos: 0,
+            end_pos: 2,
+            word_count: 2,
+            document_id: doc.to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        };
+        let mut acme = shared.tenant("acme");
+        let mut globex = shared.tenant("globex");
+        for (storage, doc) in [(&mut acme, "acme_doc"), (&mut globex, "globex_doc")] {
+            storage.store_document(document(doc)).unwrap();
+            storage.store_chunks(doc.to_string(), vec![chunk(doc)]).unwrap();
+        }
+        acme.store_embeddings("model", HashMap::from([("acme_doc_0".to_string(), vec![1.0])])).unwrap();
+        acme.record_usage("ingest", &Usage { embedding_calls: 1, ..Default::default() });
+
+        assert_eq!(acme.list_documents().unwrap(), vec!["acme_doc"]);
+        assert!(acme.get_document("globex_doc").unwrap().is_none());
+        assert!(acme.delete_document("globex_doc").unwrap().is_none());
+        assert_eq!(globex.get_all_chunks().unwrap()[0].id, "globex_doc_0");
+        assert!(globex.get_all_embeddings().unwrap().is_empty());
+        assert_eq!(acme.get_stats().unwrap().usage["ingest"].embedding_calls, 1);
+        assert!(globex.get_stats().unwrap().usage.is_empty());
+        assert!(shared.list_documents().unwrap().is_empty());
+        assert_eq!(shared.tenants(), vec!["acme", "globex"]);
+
+        // Clearing one tenant leaves the other, and the file still holds both
+        globex.clear().unwrap();
+        assert_eq!(acme.get_all_chunks().unwrap().len(), 1);
+        globex.store_document(document("globex_new")).unwrap();
+        globex.persist().unwrap();
+        let reopened = StorageManager::open(path).unwrap();
+        assert_eq!(reopened.tenant("acme").list_documents().unwrap(), vec!["acme_doc"]);
+        assert_eq!(reopened.tenant("globex").list_documents().unwrap(), vec!["globex_new"]);
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_memory_budget_evicts_least_recently_used_content() {
+        use crate::testing::DocumentFixture;
+        let mut storage = StorageManager::new().unwrap().with_memory_budget(40);
+        storage.store_document(DocumentFixture::new("a.txt", "alpha text in memory").id("a").build()).unwrap();
+        // Content that no chunking could piece back together, so it must come back verbatim
+        let beta = "beta  first part\r\n\tbeta second part";
+        storage.store_document(DocumentFixture::new("gone/b.txt", beta).id("b").build()).unwrap();
+
+        // "a" was least recently used, so it made room for "b"
+        assert!(storage.documents.lock().unwrap()["a"].content.is_empty());
+        assert_eq!(storage.get_stats().unwrap().evicted_documents, 1);
+        assert_eq!(storage.get_stats().unwrap().total_size_bytes, 20 + beta.len());
+
+        // Fetching "a" reads its spill file back and pushes "b" out to one
+        assert_eq!(storage.get_document("a").unwrap().unwrap().content, "alpha text in memory");
+        assert!(storage.documents.lock().unwrap()["b"].content.is_empty());
+        assert_eq!(storage.snapshot().unwrap().documents.iter().find(|d| d.id == "b").unwrap().content, beta);
+
+        // A spill file that changed is an error, not a guess
+        let spilled = storage.content_budget.lock().unwrap().evicted["b"].path.clone();
+        std::fs::write(&spilled, "beta tampered").unwrap();
+        assert!(storage.get_document("b").is_err());
+        assert!(storage.snapshot().is_err());
+
+        let spill_dir = spilled.parent().unwrap().to_path_buf();
+        drop(storage);
+        assert!(!spill_dir.exists(), "spill files go with the storage");
+    }
+
+    #[test]
+    fn test_restore_keeps_embedding_form_and_memory_budget() {
+        use crate::quantization::{EmbeddingQuantization, EmbeddingVector, StoredEmbedding};
+        use crate::testing::DocumentFixture;
+        let mut source = StorageManager::new().unwrap();
+        source.store_document(DocumentFixture::new("a.txt", "alpha text kept in the snapshot").id("a").build()).unwrap();
+        source.store_document(DocumentFixture::new("b.txt", "beta text kept in the snapshot").id("b").build()).unwrap();
+        source.store_embeddings("model", HashMap::from([("a_0".to_string(), vec![0.5, 0.25, 0.75, 1.0])])).unwrap();
+        let snapshot = source.snapshot().unwrap();
+
+        let mut storage = StorageManager::new()
+            .unwrap()
+            .with_memory_budget(40)
+            .with_embedding_dimensions(2)
+            .with_embedding_quantization(EmbeddingQuantization::Int8);
+        let view = storage.handle();
+        storage.restore(snapshot).unwrap();
+
+        assert_eq!(storage.memory_budget(), Some(40));
+        assert_eq!(storage.get_stats().unwrap().evicted_documents, 1);
+        assert_eq!(storage.embedding_dimension_limit(), Some(2));
+        assert_eq!(storage.embedding_quantization(), EmbeddingQuantization::Int8);
+        let embedding = storage.get_stored_embeddings().unwrap().remove("a_0").unwrap();
+        assert!(matches!(embedding, StoredEmbedding::Int8(_)));
+        assert_eq!(embedding.dimensions(), 2);
+        // Views taken before the restore see the restored documents
+        assert_eq!(view.list_documents().unwrap().len(), 2);
+    }
+}
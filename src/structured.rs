@@ -0,0 +1,170 @@
+//! Answers as JSON matching a caller-supplied schema
+
+use anyhow::{anyhow, Result};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::chunking::DocumentChunk;
+use crate::generation::{Answer, Citation};
+use crate::llm::{CompletionProvider, CompletionRequest};
+use crate::usage::Usage;
+
+const STRUCTURED_INSTRUCTIONS: &str = "Respond with a single JSON value that conforms to the JSON schema below. \
+Do not wrap it in markdown or add any other text. Where a string field relies on a passage, \
+cite it with its bracketed number, for example [1].";
+
+/// A parsed structured answer along with the raw JSON it came from
+#[derive(Debug, Clone)]
+pub struct StructuredAnswer<T> {
+    pub value: T,
+    /// Passages cited inside the JSON string values
+    pub citations: Vec<Citation>,
+    pub raw: String,
+    pub usage: Usage,
+}
+
+/// Rewrite a prose answer request so the model replies with JSON conforming to `schema`
+pub fn structured_request(mut request: CompletionRequest, schema: &Value) -> Result<CompletionRequest> {
+    let schema = serde_json::to_string_pretty(schema)?;
+    request.prompt = format!("{}\n\n{}\n\nSchema:\n{}", request.prompt, STRUCTURED_INSTRUCTIONS, schema);
+    request.temperature.get_or_insert(0.0);
+    Ok(request)
+}
+
+/// Complete `request` and parse the reply, giving the model one chance to repair invalid output
+pub fn complete_structured<T: DeserializeOwned>(
+    llm: &dyn CompletionProvider,
+    request: &CompletionRequest,
+    schema: &Value,
+    context: &[DocumentChunk],
+) -> Result<StructuredAnswer<T>> {
+    let text = llm.complete(request)?;
+    let error = match parse_structured(&text, schema, context) {
+        Ok(answer) => return Ok(answer),
+        Err(error) => error,
+    };
+
+    let mut retry = request.clone();
+    retry.prompt = format!(
+        "{}\n\nYour previous reply was:\n{}\n\nIt was rejected: {}. Reply again with corrected JSON only.",
+        request.prompt, text, error
+    );
+    let text = llm.complete(&retry)?;
+    parse_structured(&text, schema, context).map_err(|e| anyhow!("LLM did not return valid structured output: {}", e))
+}
+
+/// Parse model output into `T`, checking the schema's top-level required fields first
+pub fn parse_structured<T: DeserializeOwned>(
+    text: &str,
+    schema: &Value,
+    context: &[DocumentChunk],
+) -> Result<StructuredAnswer<T>> {
+    let json = extract_json(text);
+    let value: Value = serde_json::from_str(json).map_err(|e| anyhow!("not valid JSON ({})", e))?;
+
+    if let Some(required) = schema.get("required").and_then(Value::as_array) {
+        let missing: Vec<&str> = required
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|field| value.get(field).is_none())
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!("missing required fields: {}", missing.join(", ")));
+        }
+    }
+
+    let citations = Answer::from_response(json.to_string(), context).citations;
+    Ok(StructuredAnswer {
+        value: serde_json::from_value(value).map_err(|e| anyhow!("does not match the expected shape ({})", e))?,
+        citations,
+        raw: json.to_string(),
+        usage: Usage::default(),
+    })
+}
+
+/// The JSON part of a reply, tolerating markdown fences and surrounding chatter
+fn extract_json(text: &str) -> &str {
+    let trimmed = text.trim();
+    let start = trimmed.find(['{', '[']);
+    let end = trimmed.rfind(['}', ']']);
+    match (start, end) {
+        (Some(start), Some(end)) if start < end => &trimmed[start..=end],
+        _ => trimmed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+    use std::sync::Mutex;
+
+    #[derive(Debug, Deserialize)]
+    struct Release {
+        version: String,
+        notes: String,
+    }
+
+    /// Replies with each canned output in turn
+    struct ScriptedLlm {
+        replies: Mutex<Vec<&'static str>>,
+    }
+
+    impl CompletionProvider for ScriptedLlm {
+        fn model_name(&self) -> &str {
+            "scripted"
+        }
+
+        fn complete(&self, _request: &CompletionRequest) -> Result<String> {
+            Ok(self.replies.lock().unwrap().remove(0).to_string())
+        }
+    }
+
+    fn schema() -> Value {
+        serde_json::json!({
+            "type": "object",
+            "properties": { "version": { "type": "string" }, "notes": { "type": "string" } },
+            "required": ["version", "notes"]
+        })
+    }
+
+    #[test]
+    fn test_parses_fenced_json() {
+        let reply = "Here you go:\n```json\n{\"version\": \"1.2\", \"notes\": \"Faster [1]\"}\n```";
+        let context = vec![DocumentChunk {
+            id: "c1".to_string(),
+            content: "".into(),
+            start_pos: 0,
+            end_pos: 0,
+            word_count: 0,
+            document_id: "d1".to_string(),
+            byte_start: 0,
+            byte_end: 0,
+            entities: Default::default(),
+            keyphrases: Default::default(),
+            minhash: Default::default(),
+        }];
+
+        let answer: StructuredAnswer<Release> = parse_structured(reply, &schema(), &context).unwrap();
+
+        assert_eq!(answer.value.version, "1.2");
+        assert_eq!(answer.value.notes, "Faster [1]");
+        assert_eq!(answer.citations[0].chunk_id, "c1");
+    }
+
+    #[test]
+    fn test_invalid_output_is_retried_once() {
+        let llm = ScriptedLlm {
+            replies: Mutex::new(vec![r#"{"version": "1.2"}"#, r#"{"version": "1.2", "notes": "ok"}"#]),
+        };
+        let request = structured_request(CompletionRequest::new("Question"), &schema()).unwrap();
+
+        let answer: StructuredAnswer<Release> = complete_structured(&llm, &request, &schema(), &[]).unwrap();
+        assert_eq!(answer.value.notes, "ok");
+
+        let llm = ScriptedLlm {
+            replies: Mutex::new(vec!["no idea", "still no idea"]),
+        };
+        let error = complete_structured::<Release>(&llm, &request, &schema(), &[]).unwrap_err();
+        assert!(error.to_string().contains("not valid JSON"));
+    }
+}
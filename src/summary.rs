@@ -0,0 +1,83 @@
+//! Per-document summaries generated at ingest
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use crate::chunking::DocumentChunk;
+use crate::entities::extract_entities;
+use crate::keyphrases::{extract_keyphrases, KEYPHRASES_PER_CHUNK};
+use crate::minhash::minhash;
+use crate::llm::{CompletionProvider, CompletionRequest};
+use crate::processor::ProcessedDocument;
+
+/// Summaries are indexed as an extra chunk with this id suffix
+pub const SUMMARY_CHUNK_SUFFIX: &str = "_summary";
+
+/// Only the start of very long documents is sent, to keep the request inside small context windows
+const MAX_SUMMARY_INPUT_CHARS: usize = 24_000;
+
+const SUMMARY_PREAMBLE: &str = "Summarize the document in two to four sentences: what it is about \
+and the main points it makes. Respond with the summary only.";
+
+pub fn summarize_document(llm: &dyn CompletionProvider, document: &ProcessedDocument) -> Result<String> {
+    Ok(llm.complete(&summary_request(document))?.trim().to_string())
+}
+
+pub async fn summarize_document_async(llm: &dyn CompletionProvider, document: &ProcessedDocument) -> Result<String> {
+    let (summary, _) = llm.complete_async(&summary_request(document)).await?;
+    Ok(summary.trim().to_string())
+}
+
+fn summary_request(document: &ProcessedDocument) -> CompletionRequest {
+    let content: String = document.content.chars().take(MAX_SUMMARY_INPUT_CHARS).collect();
+    CompletionRequest::new(format!("Document ({}):\n{}\n\nSummary:", document.metadata.file_path, content))
+        .with_preamble(SUMMARY_PREAMBLE)
+        .with_temperature(0.0)
+}
+
+/// The summary as a searchable chunk spanning the whole document
+pub fn summary_chunk(document: &ProcessedDocument, summary: &str) -> DocumentChunk {
+    DocumentChunk {
+        id: format!("{}{}", document.id, SUMMARY_CHUNK_SUFFIX),
+        content: summary.into(),
+        start_pos: 0,
+        end_pos: document.metadata.word_count,
+        word_count: summary.split_whitespace().count(),
+        document_id: document.id.clone(),
+        byte_start: 0,
+        byte_end: document.content.len(),
+        entities: extract_entities(summary).into(),
+        keyphrases: extract_keyphrases(summary, KEYPHRASES_PER_CHUNK).into(),
+        minhash: minhash(summary).into(),
+    }
+}
+
+impl DocumentChunk {
+    pub fn is_summary(&self) -> bool {
+        self.id.ends_with(SUMMARY_CHUNK_SUFFIX)
+    }
+}
+
+/// One chunk standing in for each document: its summary when one was generated, otherwise its
+/// opening chunk, which for most documents says what they are about
+pub fn document_representatives(chunks: &[DocumentChunk]) -> Vec<DocumentChunk> {
+    let mut chosen: HashMap<&str, &DocumentChunk> = HashMap::new();
+    for chunk in chunks {
+        let better = match chosen.get(chunk.document_id.as_str()) {
+            None => true,
+            Some(current) => {
+                !current.is_summary() && (chunk.is_summary() || chunk.start_pos < current.start_pos)
+            }
+        };
+        if better {
+            chosen.insert(&chunk.document_id, chunk);
+        }
+    }
+    // Keep storage order so ties rank the same way as in a normal search
+    chunks
+        .iter()
+        .filter(|c| chosen.get(c.document_id.as_str()).is_some_and(|r| r.id == c.id))
+        .cloned()
+        .collect()
+}
+
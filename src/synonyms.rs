@@ -0,0 +1,158 @@
+//! User-defined synonyms, such as domain acronyms, that queries are expanded with before scoring.
+//!
+//! A synonyms file has one entry per line. `k8s => kubernetes, kube` adds the terms on the right to any
+//! query mentioning the one on the left; `car, automobile, auto` makes every term of the group expand to
+//! the others. Terms may be phrases, and match queries as whole words regardless of case. Blank lines
+//! and lines starting with `#` are ignored.
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::Path;
+use crate::entities::words;
+use crate::keyphrases::mentioned_in;
+
+/// Terms and what each expands to, kept lowercased
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SynonymDictionary {
+    entries: BTreeMap<String, Vec<String>>,
+}
+
+impl SynonymDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read synonyms file {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Invalid synonyms file {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut dictionary = Self::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let terms = |list: &str| list.split(',').map(normalize).filter(|t| !t.is_empty()).collect::<Vec<_>>();
+            match line.split_once("=>") {
+                Some((term, expansions)) => {
+                    let (term, expansions) = (normalize(term), terms(expansions));
+                    if term.is_empty() || expansions.is_empty() {
+                        return Err(anyhow!("Line {}: expected `term => expansion, ...`", number + 1));
+                    }
+                    dictionary.add(&term, &expansions);
+                }
+                None => {
+                    let group = terms(line);
+                    if group.len() < 2 {
+                        return Err(anyhow!("Line {}: a group of synonyms needs at least two terms", number + 1));
+                    }
+                    for term in &group {
+                        dictionary.add(term, &group);
+                    }
+                }
+            }
+        }
+        Ok(dictionary)
+    }
+
+    /// Expand `term` to `expansions` as well as whatever it already expanded to
+    pub fn add<S: AsRef<str>>(&mut self, term: &str, expansions: &[S]) {
+        let term = normalize(term);
+        if term.is_empty() {
+            return;
+        }
+        let entry = self.entries.entry(term.clone()).or_default();
+        for expansion in expansions.iter().map(|e| normalize(e.as_ref())) {
+            if !expansion.is_empty() && expansion != term && !entry.contains(&expansion) {
+                entry.push(expansion);
+            }
+        }
+        if entry.is_empty() {
+            self.entries.remove(&term);
+        }
+    }
+
+    /// Stop expanding `term`, returning what it expanded to
+    pub fn remove(&mut self, term: &str) -> Option<Vec<String>> {
+        self.entries.remove(&normalize(term))
+    }
+
+    pub fn get(&self, term: &str) -> Option<&[String]> {
+        self.entries.get(&normalize(term)).map(Vec::as_slice)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[String])> {
+        self.entries.iter().map(|(term, expansions)| (term.as_str(), expansions.as_slice()))
+    }
+
+    /// `query` followed by the expansions of the terms it mentions that it doesn't already contain
+    pub fn expand(&self, query: &str) -> String {
+        let added = self.expansions(&words(query));
+        if added.is_empty() {
+            query.to_string()
+        } else {
+            format!("{} {}", query, added.join(" "))
+        }
+    }
+
+    /// Expansions of the terms in `query_words`, lowercased words, that aren't already among them
+    pub(crate) fn expansions(&self, query_words: &[String]) -> Vec<&str> {
+        let mut added: Vec<&str> = Vec::new();
+        for (term, expansions) in &self.entries {
+            if !mentioned_in(term, query_words) {
+                continue;
+            }
+            for expansion in expansions {
+                if !mentioned_in(expansion, query_words) && !added.contains(&expansion.as_str()) {
+                    added.push(expansion);
+                }
+            }
+        }
+        added
+    }
+}
+
+/// Lowercase words separated by single spaces, the form terms are stored and matched in
+fn normalize(term: &str) -> String {
+    words(term).join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_expand_queries() {
+        let mut dictionary = SynonymDictionary::parse(
+            "# Infrastructure\nK8s => Kubernetes, kube\n\ncar, automobile\nml ops => machine learning operations\n",
+        )
+        .unwrap();
+        assert_eq!(dictionary.get("k8s"), Some(&["kubernetes".to_string(), "kube".to_string()][..]));
+        assert_eq!(dictionary.get("automobile"), Some(&["car".to_string()][..]));
+        assert_eq!(dictionary.expand("Deploying to K8s?"), "Deploying to K8s? kubernetes kube");
+        assert_eq!(dictionary.expand("k8s vs kubernetes"), "k8s vs kubernetes kube");
+        assert_eq!(dictionary.expand("ML Ops tooling"), "ML Ops tooling machine learning operations");
+        assert_eq!(dictionary.expand("k8sx"), "k8sx");
+
+        dictionary.add("pg", &["postgres"]);
+        assert_eq!(dictionary.expand("pg tuning"), "pg tuning postgres");
+        assert_eq!(dictionary.remove("PG"), Some(vec!["postgres".to_string()]));
+        assert_eq!(dictionary.expand("pg tuning"), "pg tuning");
+
+        assert!(SynonymDictionary::parse("k8s =>").is_err());
+        assert!(SynonymDictionary::parse("lonely").is_err());
+    }
+}
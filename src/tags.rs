@@ -0,0 +1,172 @@
+//! Key/value tags on documents and the filters that select documents, and chunks, by them
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use crate::chunking::DocumentChunk;
+use crate::entities::EntityKind;
+use crate::processor::DocumentMetadata;
+
+/// Tag naming the collection a document belongs to
+pub const COLLECTION_TAG: &str = "collection";
+/// Tag multiplying a document's scores, such as `boost=2` for official docs or `boost=0.5` for a mirror
+pub const BOOST_TAG: &str = "boost";
+/// Tag that, set to `true`, ranks a document's matching chunks above every unpinned result
+pub const PINNED_TAG: &str = "pinned";
+
+/// A document's static ranking adjustment, from its `boost` and `pinned` tags
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DocumentBoost {
+    pub factor: f32,
+    pub pinned: bool,
+}
+
+impl DocumentBoost {
+    /// `None` for a document with neither tag, which ranks on its scores alone
+    pub fn from_tags(tags: &BTreeMap<String, String>) -> Result<Option<Self>> {
+        let factor = match tags.get(BOOST_TAG) {
+            Some(value) => match value.parse::<f32>() {
+                Ok(factor) if factor.is_finite() && factor > 0.0 => Some(factor),
+                _ => return Err(anyhow!("Tag {}={} must be a positive number", BOOST_TAG, value)),
+            },
+            None => None,
+        };
+        let pinned = match tags.get(PINNED_TAG).map(String::as_str) {
+            Some("true") => Some(true),
+            Some("false") => Some(false),
+            Some(value) => return Err(anyhow!("Tag {}={} must be true or false", PINNED_TAG, value)),
+            None => None,
+        };
+        if factor.is_none() && pinned.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(Self { factor: factor.unwrap_or(1.0), pinned: pinned.unwrap_or(false) }))
+    }
+}
+
+/// Split `key=value`, trimming both sides
+pub fn parse_tag(tag: &str) -> Result<(String, String)> {
+    let (key, value) = tag
+        .split_once('=')
+        .ok_or_else(|| anyhow!("Expected a tag like key=value, got '{}'", tag))?;
+    let (key, value) = (key.trim(), value.trim());
+    if key.is_empty() {
+        return Err(anyhow!("Tag '{}' has an empty key", tag));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+/// Documents a search is restricted to; every condition must hold
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentFilter {
+    pub tags: Vec<(String, String)>,
+    /// Entities a chunk must name, such as a `Person` called `Turing`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub entities: Vec<(EntityKind, String)>,
+}
+
+impl DocumentFilter {
+    /// Parse filters such as `tag:project=alpha` or `entity:Person=Turing`
+    pub fn parse(filters: &[String]) -> Result<Self> {
+        let mut filter = Self::default();
+        for expression in filters {
+            match expression.split_once(':') {
+                Some(("tag", tag)) => filter.tags.push(parse_tag(tag)?),
+                Some(("entity", entity)) => {
+                    let (kind, name) = parse_tag(entity)?;
+                    filter.entities.push((kind.parse()?, name));
+                }
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown filter '{}' (expected tag:key=value or entity:Type=name)",
+                        expression
+                    ))
+                }
+            }
+        }
+        Ok(filter)
+    }
+
+    /// Documents in the named collection
+    pub fn collection(name: impl Into<String>) -> Self {
+        Self::default().with_tag(COLLECTION_TAG, name)
+    }
+
+    pub fn with_tag(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.tags.push((key.into(), value.into()));
+        self
+    }
+
+    pub fn with_entity(mut self, kind: EntityKind, name: impl Into<String>) -> Self {
+        self.entities.push((kind, name.into()));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty() && self.entities.is_empty()
+    }
+
+    /// Whether the document's tags pass; entity conditions are checked per chunk
+    pub fn matches(&self, metadata: &DocumentMetadata) -> bool {
+        self.tags.iter().all(|(key, value)| metadata.tags.get(key) == Some(value))
+    }
+
+    /// Whether the chunk names every entity the filter asks for
+    pub fn matches_chunk(&self, chunk: &DocumentChunk) -> bool {
+        self.entities.iter().all(|(kind, name)| {
+            chunk.entities.iter().any(|entity| entity.kind == *kind && entity.matches_name(name))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_parsing_and_matching() {
+        let filter = DocumentFilter::parse(&["tag:project=alpha".to_string(), "tag: team = search ".to_string()]).unwrap();
+        assert_eq!(filter, DocumentFilter::default().with_tag("project", "alpha").with_tag("team", "search"));
+
+        let mut metadata = DocumentMetadata {
+            file_path: "notes.md".to_string(),
+            file_type: "md".to_string(),
+            file_size: 0,
+            word_count: 0,
+            summary: None,
+            relations: Vec::new(),
+            tags: Default::default(),
+            tenant: None,
+            language: None,
+            ingested_at: None,
+            modified_at: None,
+        };
+        metadata.tags.insert("project".to_string(), "alpha".to_string());
+        assert!(!filter.matches(&metadata));
+        metadata.tags.insert("team".to_string(), "search".to_string());
+        assert!(filter.matches(&metadata));
+
+        assert!(DocumentFilter::parse(&["project=alpha".to_string()]).is_err());
+        let filter = DocumentFilter::parse(&["entity:person=Turing".to_string()]).unwrap();
+        assert_eq!(filter, DocumentFilter::default().with_entity(EntityKind::Person, "Turing"));
+        assert!(DocumentFilter::parse(&["entity:animal=cat".to_string()]).is_err());
+        assert!(parse_tag("=alpha").is_err());
+    }
+
+    #[test]
+    fn test_boost_tags() {
+        let tags = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(DocumentBoost::from_tags(&tags(&[("team", "search")])).unwrap(), None);
+        assert_eq!(
+            DocumentBoost::from_tags(&tags(&[(BOOST_TAG, "2.5")])).unwrap(),
+            Some(DocumentBoost { factor: 2.5, pinned: false })
+        );
+        assert_eq!(
+            DocumentBoost::from_tags(&tags(&[(PINNED_TAG, "true")])).unwrap(),
+            Some(DocumentBoost { factor: 1.0, pinned: true })
+        );
+        assert!(DocumentBoost::from_tags(&tags(&[(BOOST_TAG, "0")])).is_err());
+        assert!(DocumentBoost::from_tags(&tags(&[(BOOST_TAG, "lots")])).is_err());
+        assert!(DocumentBoost::from_tags(&tags(&[(PINNED_TAG, "yes")])).is_err());
+    }
+}
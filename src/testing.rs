@@ -0,0 +1,203 @@
+//! Fixtures for applications that embed this crate: fake documents and chunks, a deterministic
+//! embedding stub and a ready in-memory system, so their tests run fast and offline
+
+use anyhow::{anyhow, Result};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::builder::SimpleRagSystemBuilder;
+use crate::chunking::DocumentChunk;
+use crate::embedding::EmbeddingProvider;
+use crate::entities::extract_entities;
+use crate::keyphrases::{extract_keyphrases, KEYPHRASES_PER_CHUNK};
+use crate::language::detect_language;
+use crate::minhash::minhash;
+use crate::processor::{DocumentMetadata, ProcessedDocument};
+use crate::search::SearchMode;
+use crate::SimpleRagSystem;
+
+/// Builds a `ProcessedDocument` without reading a file; its id defaults to its path
+#[derive(Debug, Clone)]
+pub struct DocumentFixture {
+    id: Option<String>,
+    path: String,
+    content: String,
+    tags: BTreeMap<String, String>,
+}
+
+impl DocumentFixture {
+    pub fn new(path: &str, content: &str) -> Self {
+        Self {
+            id: None,
+            path: path.to_string(),
+            content: content.to_string(),
+            tags: BTreeMap::new(),
+        }
+    }
+
+    pub fn id(mut self, id: &str) -> Self {
+        self.id = Some(id.to_string());
+        self
+    }
+
+    pub fn tag(mut self, key: &str, value: &str) -> Self {
+        self.tags.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn build(self) -> ProcessedDocument {
+        let file_type = self.path.rsplit_once('.').map_or("txt", |(_, ext)| ext).to_string();
+        ProcessedDocument {
+            id: self.id.unwrap_or_else(|| self.path.clone()),
+            metadata: DocumentMetadata {
+                file_path: self.path,
+                file_type,
+                file_size: self.content.len(),
+                word_count: self.content.split_whitespace().count(),
+                summary: None,
+                relations: Vec::new(),
+                tags: self.tags,
+                tenant: None,
+                language: detect_language(&self.content).map(str::to_string),
+                ingested_at: None,
+                modified_at: None,
+            },
+            content: self.content,
+        }
+    }
+}
+
+/// Chunk `index` of `document_id` holding `content`, with the id and entities the chunkers would give it
+pub fn fake_chunk(document_id: &str, index: usize, content: &str) -> DocumentChunk {
+    let word_count = content.split_whitespace().count();
+    DocumentChunk {
+        id: format!("{}_{}", document_id, index),
+        content: content.into(),
+        start_pos: 0,
+        end_pos: word_count,
+        word_count,
+        document_id: document_id.to_string(),
+        byte_start: 0,
+        byte_end: content.len(),
+        entities: extract_entities(content).into(),
+        keyphrases: extract_keyphrases(content, KEYPHRASES_PER_CHUNK).into(),
+        minhash: minhash(content).into(),
+    }
+}
+
+/// Embeds by hashing each lowercased word into one of `dimensions` buckets, so texts sharing words
+/// come out similar. Deterministic and free, but knows nothing of meaning
+#[derive(Debug)]
+pub struct HashEmbedder {
+    dimensions: usize,
+    model_name: String,
+    texts_embedded: AtomicUsize,
+}
+
+impl HashEmbedder {
+    /// Fails for zero dimensions, which leave no bucket to hash words into
+    pub fn new(dimensions: usize) -> Result<Self> {
+        if dimensions == 0 {
+            return Err(anyhow!("A hash embedder needs at least one dimension"));
+        }
+        Ok(Self {
+            dimensions,
+            model_name: format!("hash-embedder-{}", dimensions),
+            texts_embedded: AtomicUsize::new(0),
+        })
+    }
+
+    /// Texts embedded so far, for asserting that caches were hit
+    pub fn texts_embedded(&self) -> usize {
+        self.texts_embedded.load(Ordering::SeqCst)
+    }
+
+    fn embed_one(&self, text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0; self.dimensions];
+        for word in text.split(|c: char| !c.is_alphanumeric()).filter(|w| !w.is_empty()) {
+            // FNV-1a, stable across runs and platforms unlike the std hasher
+            let hash = word
+                .to_lowercase()
+                .bytes()
+                .fold(0xcbf2_9ce4_8422_2325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3));
+            vector[(hash % self.dimensions as u64) as usize] += 1.0;
+        }
+        let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm > 0.0 {
+            vector.iter_mut().for_each(|x| *x /= norm);
+        }
+        vector
+    }
+}
+
+impl Default for HashEmbedder {
+    fn default() -> Self {
+        Self::new(64).unwrap()
+    }
+}
+
+impl EmbeddingProvider for HashEmbedder {
+    fn model_name(&self) -> &str {
+        &self.model_name
+    }
+
+    fn dimensions(&self) -> usize {
+        self.dimensions
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        self.texts_embedded.fetch_add(texts.len(), Ordering::SeqCst);
+        Ok(texts.iter().map(|text| self.embed_one(text)).collect())
+    }
+}
+
+/// Builder for an in-memory system using a `HashEmbedder` and hybrid search; add a completion
+/// provider or change anything else before building
+pub fn in_memory_builder() -> SimpleRagSystemBuilder {
+    SimpleRagSystem::builder()
+        .embedding_provider(HashEmbedder::default())
+        .search_mode(SearchMode::Hybrid)
+}
+
+/// In-memory system from `in_memory_builder` with `documents` already chunked and indexed
+pub fn in_memory_system(documents: impl IntoIterator<Item = ProcessedDocument>) -> Result<SimpleRagSystem> {
+    let rag = in_memory_builder().build()?;
+    for document in documents {
+        rag.ingest(document)?;
+    }
+    Ok(rag)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_embedder_is_deterministic_and_word_based() {
+        let embedder = HashEmbedder::new(32).unwrap();
+        let texts = ["Rust borrow checker", "the BORROW checker in rust", "Paid leave"].map(String::from);
+        let vectors = embedder.embed(&texts).unwrap();
+        let again = embedder.embed(&texts[..1]).unwrap();
+
+        assert_eq!(vectors[0], again[0]);
+        assert_eq!(embedder.texts_embedded(), 4);
+        let dot = |a: &[f32], b: &[f32]| a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+        assert!(dot(&vectors[0], &vectors[1]) > dot(&vectors[0], &vectors[2]));
+        assert!((dot(&vectors[2], &vectors[2]) - 1.0).abs() < 1e-5);
+        assert!(HashEmbedder::new(0).is_err());
+    }
+
+    #[test]
+    fn test_in_memory_system_indexes_fixtures() {
+        let rag = in_memory_system([
+            DocumentFixture::new("handbook/leave.md", "Staff get twenty days of paid leave.").tag("team", "hr").build(),
+            DocumentFixture::new("eng/rust.md", "The borrow checker enforces ownership.").id("rust").build(),
+        ])
+        .unwrap();
+
+        let results = rag.search("paid leave", 1).unwrap();
+        assert_eq!(results[0].document_id, "handbook/leave.md");
+        assert_eq!(rag.tags("handbook/leave.md").unwrap()["team"], "hr");
+        assert_eq!(rag.document_chunks("rust").unwrap()[0].id, fake_chunk("rust", 0, "").id);
+        assert_eq!(rag.embedding_model().as_deref(), Some("hash-embedder-64"));
+    }
+}
@@ -0,0 +1,134 @@
+//! Retrieval as a rig agent tool
+
+use rig::completion::ToolDefinition;
+use rig::tool::Tool;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use crate::SimpleRagSystem;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RagSearchArgs {
+    pub query: String,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// A search hit as the agent sees it
+#[derive(Debug, Clone, Serialize)]
+pub struct RagSearchHit {
+    pub chunk_id: String,
+    pub document_id: String,
+    /// File the passage came from
+    pub source: Option<String>,
+    pub content: String,
+    pub score: f32,
+}
+
+#[derive(Debug)]
+pub struct RagToolError(String);
+
+impl std::fmt::Display for RagToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "knowledge base search failed: {}", self.0)
+    }
+}
+
+impl std::error::Error for RagToolError {}
+
+/// Knowledge-base search for rig agents: `agent_builder.tool(RagSearchTool::new(rag))`
+#[derive(Clone)]
+pub struct RagSearchTool {
+    rag: Arc<SimpleRagSystem>,
+    default_limit: usize,
+}
+
+impl RagSearchTool {
+    pub fn new(rag: impl Into<Arc<SimpleRagSystem>>) -> Self {
+        Self {
+            rag: rag.into(),
+            default_limit: 5,
+        }
+    }
+
+    /// Results returned when the model doesn't ask for a specific number
+    pub fn with_default_limit(mut self, limit: usize) -> Self {
+        self.default_limit = limit;
+        self
+    }
+}
+
+impl Tool for RagSearchTool {
+    const NAME: &'static str = "search_knowledge_base";
+
+    type Error = RagToolError;
+    type Args = RagSearchArgs;
+    type Output = Vec<RagSearchHit>;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Search the indexed documents for passages relevant to a query. \
+                Returns the best matching passages with their source files."
+                .to_string(),
+            parameters: serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string", "description": "What to look for" },
+                    "limit": { "type": "integer", "description": "Maximum number of passages to return" }
+                },
+                "required": ["query"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let rag = self.rag.clone();
+        let limit = args.limit.unwrap_or(self.default_limit);
+        // Search is synchronous and may block on embedding requests, so keep it off the async workers
+        tokio::task::spawn_blocking(move || {
+            let results = rag.search(&args.query, limit)?;
+            results
+                .into_iter()
+                .filter(|r| r.score > 0.0)
+                .map(|r| {
+                    let source = rag.get_document(&r.document_id)?.map(|doc| doc.metadata.file_path);
+                    Ok(RagSearchHit {
+                        chunk_id: r.chunk_id,
+                        document_id: r.document_id,
+                        source,
+                        content: r.content,
+                        score: r.score,
+                    })
+                })
+                .collect::<anyhow::Result<Vec<_>>>()
+        })
+        .await
+        .map_err(|e| RagToolError(e.to_string()))?
+        .map_err(|e| RagToolError(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[tokio::test]
+    async fn test_tool_searches_the_index() {
+        let test_file = "/tmp/test_rag_tool.txt";
+        std::fs::write(test_file, "The borrow checker enforces Rust's ownership rules.").unwrap();
+        let mut rag = SimpleRagSystem::new().unwrap();
+        rag.process_document(Path::new(test_file)).unwrap();
+        let tool = RagSearchTool::new(rag);
+
+        let definition = tool.definition(String::new()).await;
+        assert_eq!(definition.name, "search_knowledge_base");
+
+        let args: RagSearchArgs = serde_json::from_str(r#"{"query": "borrow checker"}"#).unwrap();
+        let hits = tool.call(args).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert_eq!(hits[0].source.as_deref(), Some(test_file));
+
+        std::fs::remove_file(test_file).unwrap();
+    }
+}
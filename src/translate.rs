@@ -0,0 +1,29 @@
+//! Query translation, for searching documents written in another language than the query
+
+use anyhow::Result;
+use crate::llm::{CompletionProvider, CompletionRequest};
+
+/// `query` in `language`, as the documents would phrase it
+pub fn translate_query(llm: &dyn CompletionProvider, query: &str, language: &str) -> Result<String> {
+    let translation = llm.complete(&translate_request(query, language))?.trim().to_string();
+    tracing::debug!("Translated '{}' into {}: {}", query, language, translation);
+    Ok(translation)
+}
+
+pub async fn translate_query_async(llm: &dyn CompletionProvider, query: &str, language: &str) -> Result<String> {
+    let (translation, _) = llm.complete_async(&translate_request(query, language)).await?;
+    let translation = translation.trim().to_string();
+    tracing::debug!("Translated '{}' into {}: {}", query, language, translation);
+    Ok(translation)
+}
+
+fn translate_request(query: &str, language: &str) -> CompletionRequest {
+    CompletionRequest::new(format!("Query: {}\n\nTranslation:", query))
+        .with_preamble(format!(
+            "Translate the search query into {}. Keep names, code and numbers as they are; if it is already \
+             in {}, repeat it unchanged. Respond with the translation only.",
+            language, language
+        ))
+        .with_temperature(0.0)
+        .with_max_tokens(256)
+}
@@ -0,0 +1,229 @@
+//! Token and cost accounting for LLM and embedding calls
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use crate::embedding::EmbeddingProvider;
+use crate::llm::{BoxFuture, CompletionProvider, CompletionRequest};
+
+/// Tokens reported for a single completion
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl TokenUsage {
+    /// Count with the cl100k tokenizer, for providers that don't report usage
+    pub fn estimate(request: &CompletionRequest, response: &str) -> Self {
+        let preamble = request.preamble.as_deref().map(count_tokens).unwrap_or(0);
+        Self {
+            input_tokens: preamble + count_tokens(&request.prompt),
+            output_tokens: count_tokens(response),
+        }
+    }
+}
+
+fn count_tokens(text: &str) -> u64 {
+    tiktoken_rs::cl100k_base_singleton().encode_with_special_tokens(text).len() as u64
+}
+
+/// Accumulated calls, tokens and estimated spend
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Usage {
+    pub completion_calls: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub embedding_calls: u64,
+    pub embedding_tokens: u64,
+    /// Estimated from list prices; zero for local and unknown models
+    pub cost_usd: f64,
+}
+
+impl Usage {
+    pub fn add(&mut self, other: &Usage) {
+        self.completion_calls += other.completion_calls;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.embedding_calls += other.embedding_calls;
+        self.embedding_tokens += other.embedding_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+
+    /// What was used after `earlier` was taken from the same running total
+    pub fn since(&self, earlier: &Usage) -> Usage {
+        Usage {
+            completion_calls: self.completion_calls.saturating_sub(earlier.completion_calls),
+            input_tokens: self.input_tokens.saturating_sub(earlier.input_tokens),
+            output_tokens: self.output_tokens.saturating_sub(earlier.output_tokens),
+            embedding_calls: self.embedding_calls.saturating_sub(earlier.embedding_calls),
+            embedding_tokens: self.embedding_tokens.saturating_sub(earlier.embedding_tokens),
+            cost_usd: (self.cost_usd - earlier.cost_usd).max(0.0),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.completion_calls == 0 && self.embedding_calls == 0
+    }
+}
+
+/// USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// List prices by model name prefix; more specific prefixes come first
+const MODEL_PRICES: &[(&str, ModelPrice)] = &[
+    ("gpt-4o-mini", ModelPrice { input: 0.15, output: 0.60 }),
+    ("gpt-4o", ModelPrice { input: 2.50, output: 10.00 }),
+    ("gpt-4.1-nano", ModelPrice { input: 0.10, output: 0.40 }),
+    ("gpt-4.1-mini", ModelPrice { input: 0.40, output: 1.60 }),
+    ("gpt-4.1", ModelPrice { input: 2.00, output: 8.00 }),
+    ("text-embedding-3-small", ModelPrice { input: 0.02, output: 0.0 }),
+    ("text-embedding-3-large", ModelPrice { input: 0.13, output: 0.0 }),
+    ("text-embedding-ada-002", ModelPrice { input: 0.10, output: 0.0 }),
+    ("claude-opus-4", ModelPrice { input: 15.00, output: 75.00 }),
+    ("claude-sonnet-4", ModelPrice { input: 3.00, output: 15.00 }),
+    ("claude-3-7-sonnet", ModelPrice { input: 3.00, output: 15.00 }),
+    ("claude-3-5-haiku", ModelPrice { input: 0.80, output: 4.00 }),
+];
+
+pub fn model_price(model: &str) -> Option<ModelPrice> {
+    MODEL_PRICES
+        .iter()
+        .find(|(prefix, _)| model.starts_with(prefix))
+        .map(|(_, price)| *price)
+}
+
+fn estimate_cost(model: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+    model_price(model)
+        .map(|price| (input_tokens as f64 * price.input + output_tokens as f64 * price.output) / 1_000_000.0)
+        .unwrap_or(0.0)
+}
+
+/// Running total shared by the metered providers of one system
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    total: Mutex<Usage>,
+}
+
+impl UsageMeter {
+    pub fn total(&self) -> Usage {
+        *self.total.lock().unwrap()
+    }
+
+    pub fn record_completion(&self, model: &str, tokens: TokenUsage) {
+        let mut total = self.total.lock().unwrap();
+        total.completion_calls += 1;
+        total.input_tokens += tokens.input_tokens;
+        total.output_tokens += tokens.output_tokens;
+        total.cost_usd += estimate_cost(model, tokens.input_tokens, tokens.output_tokens);
+    }
+
+    pub fn record_embedding(&self, model: &str, tokens: u64) {
+        let mut total = self.total.lock().unwrap();
+        total.embedding_calls += 1;
+        total.embedding_tokens += tokens;
+        total.cost_usd += estimate_cost(model, tokens, 0);
+    }
+}
+
+/// Records every completion on a meter
+pub struct MeteredCompletionProvider {
+    inner: Arc<dyn CompletionProvider>,
+    meter: Arc<UsageMeter>,
+}
+
+impl MeteredCompletionProvider {
+    pub fn new(inner: Arc<dyn CompletionProvider>, meter: Arc<UsageMeter>) -> Self {
+        Self { inner, meter }
+    }
+}
+
+impl CompletionProvider for MeteredCompletionProvider {
+    fn model_name(&self) -> &str {
+        self.inner.model_name()
+    }
+
+    fn context_window(&self) -> usize {
+        self.inner.context_window()
+    }
+
+    fn complete(&self, request: &CompletionRequest) -> Result<String> {
+        self.complete_with_usage(request).map(|(text, _)| text)
+    }
+
+    fn complete_with_usage(&self, request: &CompletionRequest) -> Result<(String, TokenUsage)> {
+        let (text, tokens) = self.inner.complete_with_usage(request)?;
+        self.meter.record_completion(self.inner.model_name(), tokens);
+        Ok((text, tokens))
+    }
+
+    fn complete_async<'a>(&'a self, request: &'a CompletionRequest) -> BoxFuture<'a, Result<(String, TokenUsage)>> {
+        Box::pin(async move {
+            let (text, tokens) = self.inner.complete_async(request).await?;
+            self.meter.record_completion(self.inner.model_name(), tokens);
+            Ok((text, tokens))
+        })
+    }
+}
+
+/// Records every embedding request on a meter, counting input tokens with cl100k
+pub struct MeteredEmbeddingProvider {
+    inner: Arc<dyn EmbeddingProvider>,
+    meter: Arc<UsageMeter>,
+}
+
+impl MeteredEmbeddingProvider {
+    pub fn new(inner: Arc<dyn EmbeddingProvider>, meter: Arc<UsageMeter>) -> Self {
+        Self { inner, meter }
+    }
+}
+
+impl EmbeddingProvider for MeteredEmbeddingProvider {
+    fn model_name(&self) -> &str {
+        self.inner.model_name()
+    }
+
+    fn dimensions(&self) -> usize {
+        self.inner.dimensions()
+    }
+
+    fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>> {
+        let vectors = self.inner.embed(texts)?;
+        let tokens = texts.iter().map(|t| count_tokens(t)).sum();
+        self.meter.record_embedding(self.inner.model_name(), tokens);
+        Ok(vectors)
+    }
+
+    fn embed_async<'a>(&'a self, texts: &'a [String]) -> BoxFuture<'a, Result<Vec<Vec<f32>>>> {
+        Box::pin(async move {
+            let vectors = self.inner.embed_async(texts).await?;
+            let tokens = texts.iter().map(|t| count_tokens(t)).sum();
+            self.meter.record_embedding(self.inner.model_name(), tokens);
+            Ok(vectors)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_meter_prices_known_models() {
+        let meter = UsageMeter::default();
+        meter.record_completion("gpt-4o-mini", TokenUsage { input_tokens: 1_000_000, output_tokens: 1_000_000 });
+        let before = meter.total();
+        meter.record_embedding("nomic-embed-text", 500);
+
+        let total = meter.total();
+        assert_eq!((total.completion_calls, total.embedding_calls, total.embedding_tokens), (1, 1, 500));
+        assert!((total.cost_usd - 0.75).abs() < 1e-9);
+        // Local models are free
+        assert_eq!(total.since(&before).cost_usd, 0.0);
+        assert_eq!(model_price("gpt-4o-2024-08-06"), model_price("gpt-4o"));
+    }
+}
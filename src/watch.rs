@@ -0,0 +1,153 @@
+//! Keeping the index in step with a directory as files are added, changed and removed
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::ingest::{discover_files, IngestOptions};
+use crate::SimpleRagSystem;
+
+/// A change the watcher applied to the index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum WatchEvent {
+    Added { path: PathBuf, document_id: String, chunks: usize },
+    /// The file changed, so its old document was replaced by `document_id`
+    Updated { path: PathBuf, document_id: String, chunks: usize },
+    Removed { path: PathBuf, document_id: String },
+    Failed { path: PathBuf, error: String },
+}
+
+/// Polls a directory and mirrors it into the index.
+///
+/// Documents are matched to files by their stored file path, so the first poll also picks up
+/// edits and deletions made while nothing was watching. The root is canonicalized, which means
+/// documents processed earlier through a relative path are not recognised as the same file.
+pub struct DirectoryWatcher {
+    root: PathBuf,
+    options: IngestOptions,
+    /// Modification time and size at the last poll, to skip reading unchanged files
+    seen: HashMap<PathBuf, (Option<SystemTime>, u64)>,
+}
+
+impl DirectoryWatcher {
+    pub fn new(root: &Path, options: IngestOptions) -> Result<Self> {
+        Ok(Self {
+            root: root.canonicalize()?,
+            options,
+            seen: HashMap::new(),
+        })
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Compare the directory with the index and apply the differences
+    pub fn poll(&mut self, rag: &mut SimpleRagSystem) -> Result<Vec<WatchEvent>> {
+        let (files, _) = discover_files(&self.root, &self.options)?;
+        let root = self.root.to_string_lossy().to_string();
+        let mut indexed: HashMap<PathBuf, (String, String)> = HashMap::new();
+        for doc_id in rag.list_documents()? {
+            if let Some(doc) = rag.get_document(&doc_id)? {
+                if Path::new(&doc.metadata.file_path).starts_with(&root) {
+                    indexed.insert(PathBuf::from(doc.metadata.file_path), (doc.id, doc.content));
+                }
+            }
+        }
+
+        let mut events = Vec::new();
+        let present: HashSet<&PathBuf> = files.iter().collect();
+        for file in &files {
+            let stamp = match std::fs::metadata(file) {
+                Ok(metadata) => (metadata.modified().ok(), metadata.len()),
+                Err(_) => continue,
+            };
+            // Unchanged since the last poll; this also keeps failing files from being retried until edited
+            if self.seen.get(file) == Some(&stamp) {
+                continue;
+            }
+            self.seen.insert(file.clone(), stamp);
+
+            let previous = indexed.get(file);
+            if let Some((_, content)) = previous {
+                if std::fs::read_to_string(file).is_ok_and(|current| &current == content) {
+                    continue;
+                }
+            }
+            // Tags belong to the file rather than a version of it, so updating carries them over
+            let processed = match previous {
+                Some((old_id, _)) => rag.update_document(old_id, file),
+                None => rag.process_document(file),
+            };
+            let event = match processed {
+                Ok(document_id) => {
+                    let chunks = rag.document_chunks(&document_id)?.len();
+                    let path = file.clone();
+                    match previous {
+                        Some(_) => WatchEvent::Updated { path, document_id, chunks },
+                        None => WatchEvent::Added { path, document_id, chunks },
+                    }
+                }
+                Err(e) => WatchEvent::Failed {
+                    path: file.clone(),
+                    error: e.to_string(),
+                },
+            };
+            events.push(event);
+        }
+
+        let mut removed: Vec<(PathBuf, String)> = indexed
+            .into_iter()
+            .filter(|(path, _)| !present.contains(path))
+            .map(|(path, (document_id, _))| (path, document_id))
+            .collect();
+        removed.sort();
+        for (path, document_id) in removed {
+            rag.delete_document(&document_id)?;
+            self.seen.remove(&path);
+            events.push(WatchEvent::Removed { path, document_id });
+        }
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_mirrors_directory_changes() {
+        let root = Path::new("/tmp/test_rag_watch");
+        let _ = std::fs::remove_dir_all(root);
+        std::fs::create_dir_all(root).unwrap();
+        let file = root.canonicalize().unwrap().join("notes.md");
+        std::fs::write(&file, "Cats purr.").unwrap();
+
+        let mut rag = SimpleRagSystem::new().unwrap();
+        let mut watcher = DirectoryWatcher::new(root, IngestOptions::default()).unwrap();
+
+        let events = watcher.poll(&mut rag).unwrap();
+        assert!(matches!(&events[..], [WatchEvent::Added { path, chunks: 1, .. }] if path == &file));
+        assert!(watcher.poll(&mut rag).unwrap().is_empty());
+
+        let WatchEvent::Added { document_id, .. } = &events[0] else { unreachable!() };
+        rag.set_tag(document_id, "topic", "cats").unwrap();
+        std::fs::write(&file, "Cats purr and sleep all day.").unwrap();
+        let events = watcher.poll(&mut rag).unwrap();
+        let [WatchEvent::Updated { document_id, .. }] = &events[..] else {
+            panic!("expected an update, got {:?}", events);
+        };
+        assert_eq!(rag.tags(document_id).unwrap()["topic"], "cats");
+        assert_eq!(rag.list_documents().unwrap().len(), 1);
+        assert!(rag.search("sleep", 1).unwrap()[0].content.contains("sleep all day"));
+
+        std::fs::remove_file(&file).unwrap();
+        let events = watcher.poll(&mut rag).unwrap();
+        assert!(matches!(&events[..], [WatchEvent::Removed { .. }]));
+        assert!(rag.list_documents().unwrap().is_empty());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,254 @@
+//! Webhooks telling downstream systems, such as caches to invalidate, when documents enter,
+//! change in or leave the index
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{channel, Sender};
+use std::time::Duration;
+
+pub const DEFAULT_WEBHOOK_TIMEOUT_SECS: u64 = 5;
+pub const DEFAULT_WEBHOOK_RETRIES: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IndexEvent {
+    /// A new document, or one taken back out of the trash
+    Ingested,
+    /// A changed source file reingested in place of its previous version
+    Updated,
+    /// Moved to the trash or removed for good; a trashed document is announced again when purged
+    Deleted,
+}
+
+/// One `[[webhooks]]` entry in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    /// Plain `http://` endpoint the payload is POSTed to
+    pub url: String,
+    /// Events sent to it; every event when empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<IndexEvent>,
+    /// Extra request headers, such as an `Authorization` token the receiver checks
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Further attempts after a failed delivery, a second apart
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_timeout_secs() -> u64 {
+    DEFAULT_WEBHOOK_TIMEOUT_SECS
+}
+
+fn default_retries() -> u32 {
+    DEFAULT_WEBHOOK_RETRIES
+}
+
+impl WebhookConfig {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            events: Vec::new(),
+            headers: BTreeMap::new(),
+            timeout_secs: DEFAULT_WEBHOOK_TIMEOUT_SECS,
+            retries: DEFAULT_WEBHOOK_RETRIES,
+        }
+    }
+
+    pub fn wants(&self, event: IndexEvent) -> bool {
+        self.events.is_empty() || self.events.contains(&event)
+    }
+
+    /// Host with port, and path, of `url`
+    fn endpoint(&self) -> Result<(String, String)> {
+        let rest = self
+            .url
+            .strip_prefix("http://")
+            .ok_or_else(|| anyhow!("Webhook URL '{}' must start with http://", self.url))?;
+        let (host, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        if host.is_empty() {
+            return Err(anyhow!("Webhook URL '{}' has no host", self.url));
+        }
+        let host = if host.contains(':') { host.to_string() } else { format!("{}:80", host) };
+        Ok((host, path.to_string()))
+    }
+}
+
+/// The JSON body each webhook receives
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WebhookPayload {
+    pub event: IndexEvent,
+    pub document_id: String,
+    /// File path or upload name the document came from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Chunks the document has now; unset for deletions
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chunks: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    /// Unix seconds of the change
+    pub timestamp: u64,
+}
+
+/// Delivers payloads to the configured webhooks from a background thread, in the order the
+/// changes happened, so the index never waits on a slow receiver
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    queue: Sender<WebhookPayload>,
+}
+
+impl WebhookNotifier {
+    pub fn new(hooks: Vec<WebhookConfig>) -> Result<Self> {
+        for hook in &hooks {
+            hook.endpoint()?;
+        }
+        let (queue, payloads) = channel::<WebhookPayload>();
+        std::thread::spawn(move || {
+            for payload in payloads {
+                for hook in hooks.iter().filter(|hook| hook.wants(payload.event)) {
+                    if let Err(e) = deliver_with_retries(hook, &payload) {
+                        tracing::warn!("Webhook {} missed {:?} of {}: {}", hook.url, payload.event, payload.document_id, e);
+                    }
+                }
+            }
+        });
+        Ok(Self { queue })
+    }
+
+    pub fn notify(&self, payload: WebhookPayload) {
+        // The thread only stops with the notifier, so sending can't fail while it lives
+        let _ = self.queue.send(payload);
+    }
+}
+
+fn deliver_with_retries(hook: &WebhookConfig, payload: &WebhookPayload) -> Result<()> {
+    let mut attempt = 0;
+    loop {
+        match deliver(hook, payload) {
+            Ok(()) => return Ok(()),
+            Err(e) if attempt >= hook.retries => return Err(e),
+            Err(e) => tracing::debug!("Webhook {} failed, retrying: {}", hook.url, e),
+        }
+        attempt += 1;
+        std::thread::sleep(Duration::from_secs(1));
+    }
+}
+
+/// POST `payload` to `hook` once, succeeding on any 2xx response
+pub fn deliver(hook: &WebhookConfig, payload: &WebhookPayload) -> Result<()> {
+    let (host, path) = hook.endpoint()?;
+    let timeout = Duration::from_secs(hook.timeout_secs.max(1));
+    let addr = host.to_socket_addrs()?.next().ok_or_else(|| anyhow!("Cannot resolve {}", host))?;
+    let mut stream = TcpStream::connect_timeout(&addr, timeout)?;
+    stream.set_read_timeout(Some(timeout))?;
+    stream.set_write_timeout(Some(timeout))?;
+
+    let body = serde_json::to_string(payload)?;
+    let mut request = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        host,
+        body.len()
+    );
+    for (name, value) in &hook.headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    request.push_str(&body);
+    stream.write_all(request.as_bytes())?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1).and_then(|status| status.parse::<u16>().ok()) {
+        Some(status) if (200..300).contains(&status) => Ok(()),
+        Some(status) => Err(anyhow!("Webhook answered {}", status)),
+        None => Err(anyhow!("Webhook sent no HTTP response")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::net::TcpListener;
+
+    /// Answer one request on `listener` with 204, returning its head and body
+    fn receive(listener: &TcpListener) -> (String, Vec<u8>) {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut head = String::new();
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                length = value.trim().parse().unwrap();
+            }
+            if line == "\r\n" {
+                break;
+            }
+            head.push_str(&line);
+        }
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        stream.write_all(b"HTTP/1.1 204 No Content\r\nContent-Length: 0\r\n\r\n").unwrap();
+        (head, body)
+    }
+
+    #[test]
+    fn test_deliver_posts_json_payload() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut hook = WebhookConfig::new(format!("http://{}/hooks/index", listener.local_addr().unwrap()));
+        hook.headers.insert("Authorization".to_string(), "Bearer hook-secret".to_string());
+        let receiver = std::thread::spawn(move || receive(&listener));
+
+        let payload = WebhookPayload {
+            event: IndexEvent::Updated,
+            document_id: "doc".to_string(),
+            source: Some("docs/setup.md".to_string()),
+            chunks: Some(3),
+            tenant: None,
+            timestamp: 1_700_000_000,
+        };
+        deliver(&hook, &payload).unwrap();
+        let (head, body) = receiver.join().unwrap();
+        assert!(head.starts_with("POST /hooks/index HTTP/1.1"));
+        assert!(head.contains("Authorization: Bearer hook-secret"));
+        let sent: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!((sent["event"].as_str(), sent["chunks"].as_u64()), (Some("updated"), Some(3)));
+    }
+
+    #[test]
+    fn test_index_changes_reach_webhooks_in_order() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let hook = WebhookConfig::new(format!("http://{}/", listener.local_addr().unwrap()));
+        let mut rag = crate::SimpleRagSystem::new().unwrap().with_webhooks(WebhookNotifier::new(vec![hook]).unwrap());
+        let doc_id = rag.process_bytes("leave.md", b"Staff get twenty days of paid leave.").unwrap();
+        rag.delete_document(&doc_id).unwrap();
+        assert!(rag.delete_document(&doc_id).is_err());
+
+        let sent: Vec<serde_json::Value> =
+            (0..2).map(|_| serde_json::from_slice(&receive(&listener).1).unwrap()).collect();
+        assert_eq!((sent[0]["event"].as_str(), sent[0]["chunks"].as_u64()), (Some("ingested"), Some(1)));
+        assert_eq!((sent[1]["event"].as_str(), sent[1]["source"].as_str()), (Some("deleted"), Some("leave.md")));
+        assert!(sent.iter().all(|payload| payload["document_id"] == doc_id.as_str()));
+    }
+
+    #[test]
+    fn test_only_plain_http_urls_and_wanted_events() {
+        assert!(WebhookNotifier::new(vec![WebhookConfig::new("https://hooks.example.com")]).is_err());
+        let (host, path) = WebhookConfig::new("http://cache.internal").endpoint().unwrap();
+        assert_eq!((host.as_str(), path.as_str()), ("cache.internal:80", "/"));
+        let hook = WebhookConfig { events: vec![IndexEvent::Deleted], ..WebhookConfig::new("http://cache.internal") };
+        assert!(hook.wants(IndexEvent::Deleted) && !hook.wants(IndexEvent::Ingested));
+    }
+}